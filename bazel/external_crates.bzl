@@ -97,6 +97,13 @@ def external_crates_repository(name, static_openssl, cargo_lockfile, lockfile):
             "arrayvec": crate.spec(
                 version = "^0.5.1",
             ),
+            "arrow": crate.spec(
+                version = "^27.0.0",
+                default_features = False,
+                features = [
+                    "ipc",
+                ],
+            ),
             "askama": crate.spec(
                 version = "^0.11.1",
                 features = [
@@ -729,6 +736,14 @@ def external_crates_repository(name, static_openssl, cargo_lockfile, lockfile):
             "parking_lot": crate.spec(
                 version = "^0.12.1",
             ),
+            "parquet": crate.spec(
+                version = "^27.0.0",
+                default_features = False,
+                features = [
+                    "arrow",
+                    "zstd",
+                ],
+            ),
             "parse_int": crate.spec(
                 version = "^0.4.0",
             ),
@@ -1303,6 +1318,9 @@ def external_crates_repository(name, static_openssl, cargo_lockfile, lockfile):
                     "zeroize_derive",
                 ],
             ),
+            "zstd": crate.spec(
+                version = "^0.12.3",
+            ),
         },
         splicing_config = splicing_config(
             resolver_version = "2",
@@ -112,6 +112,11 @@ Usage:
       has been confirmed previously already (safe to call as many times
       as you like, will not iniate I/O if nothing to be written).
 
+    revert
+      Reboot into the previous system without confirming the current one.
+      Only valid while a newly installed upgrade is running unconfirmed, as
+      the bootloader then falls back to the previous installation.
+
     current
       Output currently booted system (A or B) on stdout and exit.
 
@@ -180,7 +185,9 @@ case "${ACTION}" in
 
     upgrade-commit) ;&
 
-    confirm)
+    confirm) ;&
+
+    revert)
         # Re-execute script as root (unless root already) for operations that
         # require privilege.
         if [ $(id -u) != 0 ]; then
@@ -242,6 +249,16 @@ case "${ACTION}" in
             write_grubenv "${GRUBENV_FILE}"
         fi
         ;;
+    revert)
+        if [ "${NEXT_BOOT}" == "${CURRENT_ALTERNATIVE}" ]; then
+            echo "Cannot revert: the present system is committed as stable." >&2
+            exit 1
+        fi
+        # Ignore termination signals from the following reboot, so that
+        # the script exits without error.
+        trap -- '' SIGTERM
+        reboot
+        ;;
     current)
         echo "${CURRENT_ALTERNATIVE}"
         ;;
@@ -1,3 +1,6 @@
+#[cfg(feature = "export_tables")]
+mod tables;
+
 use clap::{arg, Arg, Command};
 use ic_artifact_pool::{
     certification_pool::CertificationPoolImpl,
@@ -21,7 +24,7 @@ use std::io::Write;
 use std::path::PathBuf;
 
 fn main() {
-    let mut app = Command::new("ic-consensus-pool-util")
+    let app = Command::new("ic-consensus-pool-util")
         .version("0.1")
         .about("IC Consensus Pool Unitity")
         .subcommand(
@@ -49,8 +52,35 @@ fn main() {
                         .required(true)
                         .takes_value(true),
                 ),
-        )
-        .arg(arg!(<PATH>       "PATH to the consensus pool directory"));
+        );
+    #[cfg(feature = "export_tables")]
+    let app = app.subcommand(
+        Command::new("export-tables")
+            .about(
+                "Export blocks, notarizations, finalizations, random beacons and \
+                 certification shares as typed tables for offline analytics",
+            )
+            .arg(
+                Arg::new("output")
+                    .short('o')
+                    .long("output")
+                    .value_name("DIR")
+                    .help("Output directory, one file is written per table")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("format")
+                    .short('f')
+                    .long("format")
+                    .value_name("FORMAT")
+                    .help("Table format")
+                    .possible_values(["parquet", "arrow"])
+                    .default_value("parquet")
+                    .takes_value(true),
+            ),
+    );
+    let mut app = app.arg(arg!(<PATH>       "PATH to the consensus pool directory"));
     let mut help = Vec::new();
     app.write_help(&mut help)
         .expect("Unable to output help message");
@@ -58,6 +88,10 @@ fn main() {
     let path = matches
         .value_of("PATH")
         .expect("Missing PATH to consensus pool directory");
+    #[cfg(feature = "export_tables")]
+    if let Some(matches) = matches.subcommand_matches("export-tables") {
+        return export_tables(path, matches);
+    }
     if let Some(matches) = matches.subcommand_matches("export") {
         export(path, matches)
     } else if let Some(_matches) = matches.subcommand_matches("import") {
@@ -272,3 +306,26 @@ fn export_cup_proto(path: &str, matches: &clap::ArgMatches) {
     file.write_all(&buf)
         .unwrap_or_else(|err| panic!("Cannot write to file {}: {:?}", filename, err));
 }
+
+#[cfg(feature = "export_tables")]
+fn export_tables(path: &str, matches: &clap::ArgMatches) {
+    let dir = matches
+        .value_of("output")
+        .expect("Expect an output directory");
+    let format = matches
+        .value_of("format")
+        .map(|format| {
+            format
+                .parse::<tables::Format>()
+                .unwrap_or_else(|err| panic!("{}", err))
+        })
+        .expect("Expect a table format");
+    let consensus_pool = open_consensus_pool(path, true);
+    let certification_pool = open_certification_pool(path, true);
+    tables::export_tables(
+        std::path::Path::new(dir),
+        format,
+        consensus_pool.validated(),
+        certification_pool.persistent_pool.certification_shares(),
+    );
+}
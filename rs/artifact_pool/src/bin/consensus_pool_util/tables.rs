@@ -0,0 +1,673 @@
+//! Export of consensus artifacts as typed tables, for offline analytics.
+//!
+//! Each artifact type is written to its own file in the output directory,
+//! either as Parquet or as an Arrow IPC file, with one row per artifact.
+//! Hashes are hex encoded and all times are nanoseconds since the Unix epoch.
+//! The `pool_time_ns` column holds the time at which the artifact was added
+//! to the validated pool.
+//!
+//! The pool is read and written in batches of `HEIGHTS_PER_BATCH` heights,
+//! so that exporting a large pool does not load it into memory at once.
+
+use arrow::array::{ArrayRef, BooleanArray, ListBuilder, StringArray, StringBuilder, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use ic_interfaces::consensus_pool::{
+    HeightIndexedPool, HeightRange, PoolSection, ValidatedConsensusArtifact,
+};
+use ic_types::{
+    batch::BatchPayload,
+    consensus::{
+        certification::CertificationShare, BlockProposal, ConsensusMessageHashable, Finalization,
+        Notarization, RandomBeacon,
+    },
+    CountBytes, Height, NodeId,
+};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The number of heights whose artifacts are read and written at once.
+const HEIGHTS_PER_BATCH: u64 = 1000;
+
+/// The file format the tables are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    Parquet,
+    Arrow,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Parquet => "parquet",
+            Format::Arrow => "arrow",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "parquet" => Ok(Format::Parquet),
+            "arrow" => Ok(Format::Arrow),
+            _ => Err(format!("Unknown table format '{}'", s)),
+        }
+    }
+}
+
+/// Writes the blocks, notarizations, finalizations and random beacons in
+/// the validated section of the consensus pool, and the given certification
+/// shares, as one table each to `dir`.
+pub(crate) fn export_tables(
+    dir: &Path,
+    format: Format,
+    validated: &dyn PoolSection<ValidatedConsensusArtifact>,
+    certification_shares: &dyn HeightIndexedPool<CertificationShare>,
+) {
+    export_tables_in_batches(
+        dir,
+        format,
+        validated,
+        certification_shares,
+        HEIGHTS_PER_BATCH,
+    )
+}
+
+fn export_tables_in_batches(
+    dir: &Path,
+    format: Format,
+    validated: &dyn PoolSection<ValidatedConsensusArtifact>,
+    certification_shares: &dyn HeightIndexedPool<CertificationShare>,
+    heights_per_batch: u64,
+) {
+    std::fs::create_dir_all(dir)
+        .unwrap_or_else(|err| panic!("Cannot create directory {}: {:?}", dir.display(), err));
+    let path = |name: &str| dir.join(format!("{}.{}", name, format.extension()));
+    export_table(
+        &path("blocks"),
+        format,
+        validated.block_proposal(),
+        heights_per_batch,
+        |proposals| blocks_table(validated, proposals),
+    );
+    export_table(
+        &path("notarizations"),
+        format,
+        validated.notarization(),
+        heights_per_batch,
+        |notarizations| notarizations_table(validated, notarizations),
+    );
+    export_table(
+        &path("finalizations"),
+        format,
+        validated.finalization(),
+        heights_per_batch,
+        |finalizations| finalizations_table(validated, finalizations),
+    );
+    export_table(
+        &path("random_beacons"),
+        format,
+        validated.random_beacon(),
+        heights_per_batch,
+        |beacons| random_beacons_table(validated, beacons),
+    );
+    export_table(
+        &path("certification_shares"),
+        format,
+        certification_shares,
+        heights_per_batch,
+        certification_shares_table,
+    );
+}
+
+/// Writes the artifacts of `pool` to the table at `path`, one record batch
+/// per `heights_per_batch` heights.
+fn export_table<T>(
+    path: &Path,
+    format: Format,
+    pool: &dyn HeightIndexedPool<T>,
+    heights_per_batch: u64,
+    to_record_batch: impl Fn(Vec<T>) -> RecordBatch,
+) {
+    // The schema does not depend on the rows, so take it from an empty batch.
+    let mut writer = TableWriter::new(path, format, to_record_batch(vec![]).schema());
+    let mut rows = 0;
+    if let Some(range) = pool.height_range() {
+        let mut min = range.min;
+        loop {
+            let max = Height::from(min.get().saturating_add(heights_per_batch - 1)).min(range.max);
+            let batch = to_record_batch(
+                pool.get_by_height_range(HeightRange::new(min, max))
+                    .collect(),
+            );
+            if batch.num_rows() > 0 {
+                writer.write(&batch);
+                rows += batch.num_rows();
+            }
+            if max >= range.max {
+                break;
+            }
+            min = max.increment();
+        }
+    }
+    writer.close();
+    println!("Wrote {} rows to {}", rows, path.display());
+}
+
+enum TableWriter<'a> {
+    Parquet(&'a Path, ArrowWriter<File>),
+    Arrow(&'a Path, FileWriter<File>),
+}
+
+impl<'a> TableWriter<'a> {
+    fn new(path: &'a Path, format: Format, schema: SchemaRef) -> Self {
+        let file = File::create(path).unwrap_or_else(|err| {
+            panic!("Cannot open file {} for write: {:?}", path.display(), err)
+        });
+        let writer = match format {
+            Format::Parquet => {
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::ZSTD)
+                    .build();
+                ArrowWriter::try_new(file, schema, Some(props))
+                    .map(|writer| TableWriter::Parquet(path, writer))
+                    .map_err(|err| format!("{:?}", err))
+            }
+            Format::Arrow => FileWriter::try_new(file, &schema)
+                .map(|writer| TableWriter::Arrow(path, writer))
+                .map_err(|err| format!("{:?}", err)),
+        };
+        writer.unwrap_or_else(|err| panic!("Cannot write table {}: {}", path.display(), err))
+    }
+
+    fn write(&mut self, batch: &RecordBatch) {
+        let (path, result) = match self {
+            TableWriter::Parquet(path, writer) => (
+                path,
+                writer.write(batch).map_err(|err| format!("{:?}", err)),
+            ),
+            TableWriter::Arrow(path, writer) => (
+                path,
+                writer.write(batch).map_err(|err| format!("{:?}", err)),
+            ),
+        };
+        result.unwrap_or_else(|err| panic!("Cannot write table {}: {}", path.display(), err));
+    }
+
+    fn close(self) {
+        let (path, result) = match self {
+            TableWriter::Parquet(path, writer) => (
+                path,
+                writer
+                    .close()
+                    .map(|_| ())
+                    .map_err(|err| format!("{:?}", err)),
+            ),
+            TableWriter::Arrow(path, mut writer) => {
+                (path, writer.finish().map_err(|err| format!("{:?}", err)))
+            }
+        };
+        result.unwrap_or_else(|err| panic!("Cannot write table {}: {}", path.display(), err));
+    }
+}
+
+/// Returns the time at which `msg` was added to the validated pool.
+fn pool_time_ns<T: ConsensusMessageHashable>(
+    validated: &dyn PoolSection<ValidatedConsensusArtifact>,
+    msg: &T,
+) -> Option<u64> {
+    validated
+        .get_timestamp(&msg.get_id())
+        .map(|time| time.as_nanos_since_unix_epoch())
+}
+
+fn u64_column(name: &str, values: Vec<u64>) -> (Field, ArrayRef) {
+    (
+        Field::new(name, DataType::UInt64, false),
+        Arc::new(UInt64Array::from(values)),
+    )
+}
+
+fn nullable_u64_column(name: &str, values: Vec<Option<u64>>) -> (Field, ArrayRef) {
+    (
+        Field::new(name, DataType::UInt64, true),
+        Arc::new(UInt64Array::from(values)),
+    )
+}
+
+fn string_column(name: &str, values: Vec<String>) -> (Field, ArrayRef) {
+    (
+        Field::new(name, DataType::Utf8, false),
+        Arc::new(StringArray::from(values)),
+    )
+}
+
+fn node_ids_column(name: &str, values: Vec<&[NodeId]>) -> (Field, ArrayRef) {
+    let mut builder = ListBuilder::new(StringBuilder::new());
+    for node_ids in values {
+        for node_id in node_ids {
+            builder.values().append_value(node_id.to_string());
+        }
+        builder.append(true);
+    }
+    (
+        Field::new(
+            name,
+            DataType::List(Box::new(Field::new("item", DataType::Utf8, true))),
+            false,
+        ),
+        Arc::new(builder.finish()),
+    )
+}
+
+fn bool_column(name: &str, values: Vec<bool>) -> (Field, ArrayRef) {
+    (
+        Field::new(name, DataType::Boolean, false),
+        Arc::new(BooleanArray::from(values)),
+    )
+}
+
+fn record_batch(columns: Vec<(Field, ArrayRef)>) -> RecordBatch {
+    let (fields, arrays): (Vec<_>, Vec<_>) = columns.into_iter().unzip();
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+        .expect("Columns must match the schema")
+}
+
+fn blocks_table(
+    validated: &dyn PoolSection<ValidatedConsensusArtifact>,
+    proposals: Vec<BlockProposal>,
+) -> RecordBatch {
+    let blocks = || proposals.iter().map(|proposal| proposal.as_ref());
+    // Summary blocks carry no batch, so their batch columns are zero.
+    let batch_column = |name: &str, f: &dyn Fn(&BatchPayload) -> usize| {
+        u64_column(
+            name,
+            blocks()
+                .map(|block| {
+                    let payload = block.payload.as_ref();
+                    if payload.is_summary() {
+                        0
+                    } else {
+                        f(&payload.as_data().batch) as u64
+                    }
+                })
+                .collect(),
+        )
+    };
+    record_batch(vec![
+        u64_column("height", blocks().map(|b| b.height.get()).collect()),
+        u64_column("rank", blocks().map(|b| b.rank.0).collect()),
+        string_column(
+            "proposer",
+            proposals
+                .iter()
+                .map(|p| p.signature.signer.to_string())
+                .collect(),
+        ),
+        string_column(
+            "hash",
+            proposals
+                .iter()
+                .map(|p| hex::encode(&p.content.get_hash().get_ref().0))
+                .collect(),
+        ),
+        string_column(
+            "parent_hash",
+            blocks()
+                .map(|b| hex::encode(&b.parent.get_ref().0))
+                .collect(),
+        ),
+        u64_column(
+            "registry_version",
+            blocks().map(|b| b.context.registry_version.get()).collect(),
+        ),
+        u64_column(
+            "certified_height",
+            blocks().map(|b| b.context.certified_height.get()).collect(),
+        ),
+        u64_column(
+            "time_ns",
+            blocks()
+                .map(|b| b.context.time.as_nanos_since_unix_epoch())
+                .collect(),
+        ),
+        nullable_u64_column(
+            "pool_time_ns",
+            proposals
+                .iter()
+                .map(|p| pool_time_ns(validated, p))
+                .collect(),
+        ),
+        bool_column(
+            "is_summary",
+            blocks().map(|b| b.payload.as_ref().is_summary()).collect(),
+        ),
+        batch_column("ingress_count", &|batch| batch.ingress.message_count()),
+        batch_column("ingress_bytes", &|batch| batch.ingress.count_bytes()),
+        batch_column("xnet_slice_count", &|batch| batch.xnet.stream_slices.len()),
+        batch_column("xnet_bytes", &|batch| batch.xnet.size_bytes()),
+        batch_column("canister_http_bytes", &|batch| batch.canister_http.len()),
+    ])
+}
+
+fn notarizations_table(
+    validated: &dyn PoolSection<ValidatedConsensusArtifact>,
+    notarizations: Vec<Notarization>,
+) -> RecordBatch {
+    record_batch(vec![
+        u64_column(
+            "height",
+            notarizations
+                .iter()
+                .map(|n| n.content.height.get())
+                .collect(),
+        ),
+        string_column(
+            "block_hash",
+            notarizations
+                .iter()
+                .map(|n| hex::encode(&n.content.block.get_ref().0))
+                .collect(),
+        ),
+        u64_column(
+            "signer_count",
+            notarizations
+                .iter()
+                .map(|n| n.signature.signers.len() as u64)
+                .collect(),
+        ),
+        node_ids_column(
+            "signers",
+            notarizations
+                .iter()
+                .map(|n| n.signature.signers.as_slice())
+                .collect(),
+        ),
+        nullable_u64_column(
+            "pool_time_ns",
+            notarizations
+                .iter()
+                .map(|n| pool_time_ns(validated, n))
+                .collect(),
+        ),
+    ])
+}
+
+fn finalizations_table(
+    validated: &dyn PoolSection<ValidatedConsensusArtifact>,
+    finalizations: Vec<Finalization>,
+) -> RecordBatch {
+    record_batch(vec![
+        u64_column(
+            "height",
+            finalizations
+                .iter()
+                .map(|f| f.content.height.get())
+                .collect(),
+        ),
+        string_column(
+            "block_hash",
+            finalizations
+                .iter()
+                .map(|f| hex::encode(&f.content.block.get_ref().0))
+                .collect(),
+        ),
+        u64_column(
+            "signer_count",
+            finalizations
+                .iter()
+                .map(|f| f.signature.signers.len() as u64)
+                .collect(),
+        ),
+        node_ids_column(
+            "signers",
+            finalizations
+                .iter()
+                .map(|f| f.signature.signers.as_slice())
+                .collect(),
+        ),
+        nullable_u64_column(
+            "pool_time_ns",
+            finalizations
+                .iter()
+                .map(|f| pool_time_ns(validated, f))
+                .collect(),
+        ),
+    ])
+}
+
+fn random_beacons_table(
+    validated: &dyn PoolSection<ValidatedConsensusArtifact>,
+    beacons: Vec<RandomBeacon>,
+) -> RecordBatch {
+    record_batch(vec![
+        u64_column(
+            "height",
+            beacons.iter().map(|b| b.content.height.get()).collect(),
+        ),
+        string_column(
+            "parent_hash",
+            beacons
+                .iter()
+                .map(|b| hex::encode(&b.content.parent.get_ref().0))
+                .collect(),
+        ),
+        nullable_u64_column(
+            "pool_time_ns",
+            beacons.iter().map(|b| pool_time_ns(validated, b)).collect(),
+        ),
+    ])
+}
+
+fn certification_shares_table(shares: Vec<CertificationShare>) -> RecordBatch {
+    record_batch(vec![
+        u64_column("height", shares.iter().map(|s| s.height.get()).collect()),
+        string_column(
+            "signer",
+            shares
+                .iter()
+                .map(|s| s.signed.signature.signer.to_string())
+                .collect(),
+        ),
+        string_column(
+            "state_hash",
+            shares
+                .iter()
+                .map(|s| hex::encode(&s.signed.content.hash.get_ref().0))
+                .collect(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, ListArray};
+    use arrow::ipc::reader::FileReader;
+    use ic_artifact_pool::{
+        certification_pool::{CertificationPoolImpl, MutablePoolSection as _},
+        consensus_pool::{MutablePoolSection as _, PoolSectionOps, UncachedConsensusPoolImpl},
+    };
+    use ic_logger::replica_logger::no_op_logger;
+    use ic_metrics::MetricsRegistry;
+    use ic_test_utilities::{
+        artifact_pool_config::with_test_pool_config, consensus::fake::*, mock_time,
+        types::ids::node_test_id,
+    };
+    use ic_types::{
+        batch::ValidationContext,
+        consensus::{
+            certification::{CertificationContent, CertificationMessage},
+            Block, FinalizationContent, NotarizationContent, Payload, RandomBeaconContent, Rank,
+        },
+        crypto::{crypto_hash, CryptoHash, CryptoHashOf, Signed},
+        signature::ThresholdSignatureShare,
+        time::current_time,
+        RegistryVersion,
+    };
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    const MAX_HEIGHT: u64 = 5;
+
+    fn fake_block(height: u64) -> Block {
+        Block::new(
+            CryptoHashOf::from(CryptoHash(vec![])),
+            Payload::new(
+                crypto_hash,
+                (ic_types::consensus::dkg::Summary::fake(), None).into(),
+            ),
+            Height::from(height),
+            Rank(0),
+            ValidationContext {
+                registry_version: RegistryVersion::from(99),
+                certified_height: Height::from(0),
+                time: mock_time(),
+            },
+        )
+    }
+
+    fn signers(height: u64) -> Vec<NodeId> {
+        (0..height).map(node_test_id).collect()
+    }
+
+    fn read_table(path: &Path, format: Format) -> Vec<RecordBatch> {
+        let file = File::open(path).unwrap();
+        match format {
+            Format::Parquet => ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .build()
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap(),
+            Format::Arrow => FileReader::try_new(file, None)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap(),
+        }
+    }
+
+    fn u64_values(batches: &[RecordBatch], name: &str) -> Vec<u64> {
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let column = batch.column(batch.schema().index_of(name).unwrap());
+                let column = column.as_any().downcast_ref::<UInt64Array>().unwrap();
+                column.values().to_vec()
+            })
+            .collect()
+    }
+
+    fn string_list_values(batches: &[RecordBatch], name: &str) -> Vec<Vec<String>> {
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let column = batch.column(batch.schema().index_of(name).unwrap());
+                let column = column.as_any().downcast_ref::<ListArray>().unwrap();
+                (0..column.len())
+                    .map(|row| {
+                        let values = column.value(row);
+                        let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+                        (0..values.len())
+                            .map(|i| values.value(i).to_string())
+                            .collect()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_export_tables_round_trip() {
+        with_test_pool_config(|config| {
+            let mut consensus_pool = UncachedConsensusPoolImpl::new(config.clone(), no_op_logger());
+            let certification_pool =
+                CertificationPoolImpl::new(config, no_op_logger(), MetricsRegistry::new());
+
+            let mut ops = PoolSectionOps::new();
+            let mut insert = |msg| {
+                ops.insert(ValidatedConsensusArtifact {
+                    msg,
+                    timestamp: current_time(),
+                })
+            };
+            for h in 1..=MAX_HEIGHT {
+                let block = fake_block(h);
+                let height = Height::from(h);
+                let mut notarization =
+                    Notarization::fake(NotarizationContent::new(height, crypto_hash(&block)));
+                notarization.signature.signers = signers(h);
+                let mut finalization =
+                    Finalization::fake(FinalizationContent::new(height, crypto_hash(&block)));
+                finalization.signature.signers = signers(h);
+                insert(BlockProposal::fake(block, node_test_id(333)).into_message());
+                insert(notarization.into_message());
+                insert(finalization.into_message());
+                insert(
+                    RandomBeacon::fake(RandomBeaconContent::new(
+                        height,
+                        CryptoHashOf::from(CryptoHash(vec![])),
+                    ))
+                    .into_message(),
+                );
+                certification_pool.persistent_pool.insert(
+                    CertificationMessage::CertificationShare(CertificationShare {
+                        height,
+                        signed: Signed {
+                            content: CertificationContent::new(CryptoHashOf::from(CryptoHash(
+                                vec![h as u8],
+                            ))),
+                            signature: ThresholdSignatureShare::fake(node_test_id(h)),
+                        },
+                    }),
+                );
+            }
+            consensus_pool.validated.mutate(ops);
+
+            let dir = tempfile::tempdir().unwrap();
+            for format in [Format::Parquet, Format::Arrow] {
+                // Use a batch size that does not divide the height range, so
+                // that the last batch is partial.
+                export_tables_in_batches(
+                    dir.path(),
+                    format,
+                    consensus_pool.validated.pool_section(),
+                    certification_pool.persistent_pool.certification_shares(),
+                    2,
+                );
+                let path = |name: &str| dir.path().join(format!("{}.{}", name, format.extension()));
+                let heights: Vec<u64> = (1..=MAX_HEIGHT).collect();
+
+                let blocks = read_table(&path("blocks"), format);
+                assert_eq!(blocks.len(), 3);
+                assert_eq!(u64_values(&blocks, "height"), heights);
+
+                for name in ["notarizations", "finalizations"] {
+                    let table = read_table(&path(name), format);
+                    assert_eq!(u64_values(&table, "height"), heights);
+                    assert_eq!(u64_values(&table, "signer_count"), heights);
+                    assert_eq!(
+                        string_list_values(&table, "signers"),
+                        heights
+                            .iter()
+                            .map(|h| signers(*h).iter().map(|id| id.to_string()).collect())
+                            .collect::<Vec<Vec<String>>>()
+                    );
+                }
+
+                let beacons = read_table(&path("random_beacons"), format);
+                assert_eq!(u64_values(&beacons, "height"), heights);
+
+                let shares = read_table(&path("certification_shares"), format);
+                assert_eq!(u64_values(&shares, "height"), heights);
+            }
+        })
+    }
+}
@@ -1,6 +1,6 @@
 use crate::height_index::HeightIndex;
 use crate::metrics::{PoolMetrics, POOL_TYPE_UNVALIDATED, POOL_TYPE_VALIDATED};
-use ic_config::artifact_pool::{ArtifactPoolConfig, PersistentPoolBackend};
+use ic_config::artifact_pool::ArtifactPoolConfig;
 use ic_interfaces::{
     artifact_pool::{ChangeResult, MutablePool, UnvalidatedArtifact, ValidatedPoolReader},
     certification::{CertificationPool, ChangeAction, ChangeSet},
@@ -51,27 +51,17 @@ impl CertificationPoolImpl {
         log: ReplicaLogger,
         metrics_registry: MetricsRegistry,
     ) -> Self {
-        let persistent_pool = match config.persistent_pool_backend {
-            PersistentPoolBackend::Lmdb(lmdb_config) => Box::new(
-                crate::lmdb_pool::PersistentHeightIndexedPool::new_certification_pool(
-                    lmdb_config,
-                    config.persistent_pool_read_only,
-                    log.clone(),
-                ),
-            ) as Box<_>,
-            #[cfg(feature = "rocksdb_backend")]
-            PersistentPoolBackend::RocksDB(config) => Box::new(
-                crate::rocksdb_pool::PersistentHeightIndexedPool::new_certification_pool(
-                    config,
-                    log.clone(),
-                ),
-            ) as Box<_>,
-            #[allow(unreachable_patterns)]
-            cfg => {
-                unimplemented!("Configuration {:?} is not supported", cfg)
-            }
-        };
+        let persistent_pool = crate::storage::new_certification_pool_section(&config, log.clone());
+        Self::new_with_storage(persistent_pool, log, metrics_registry)
+    }
 
+    /// Create a certification pool whose validated artifacts are kept in the
+    /// given storage backend.
+    pub fn new_with_storage(
+        persistent_pool: Box<dyn MutablePoolSection + Send + Sync>,
+        log: ReplicaLogger,
+        metrics_registry: MetricsRegistry,
+    ) -> Self {
         CertificationPoolImpl {
             unvalidated_shares: HeightIndex::default(),
             unvalidated_certifications: HeightIndex::default(),
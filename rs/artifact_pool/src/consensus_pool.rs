@@ -7,7 +7,7 @@ use crate::{
     inmemory_pool::InMemoryPoolSection,
     metrics::{LABEL_POOL_TYPE, POOL_TYPE_UNVALIDATED, POOL_TYPE_VALIDATED},
 };
-use ic_config::artifact_pool::{ArtifactPoolConfig, PersistentPoolBackend};
+use ic_config::artifact_pool::ArtifactPoolConfig;
 use ic_interfaces::{
     artifact_pool::{ChangeResult, MutablePool, ValidatedPoolReader},
     consensus_pool::{
@@ -59,7 +59,7 @@ impl<T> PoolSectionOps<T> {
 }
 
 pub trait InitializablePoolSection: MutablePoolSection<ValidatedConsensusArtifact> {
-    fn insert_cup_with_proto(&self, cup_proto: pb::CatchUpPackage);
+    fn insert_cup_with_proto(&mut self, cup_proto: pb::CatchUpPackage);
 }
 
 pub trait MutablePoolSection<T>: PoolSection<T> {
@@ -266,27 +266,16 @@ pub struct UncachedConsensusPoolImpl {
 
 impl UncachedConsensusPoolImpl {
     pub fn new(config: ArtifactPoolConfig, log: ReplicaLogger) -> UncachedConsensusPoolImpl {
-        let validated = match config.persistent_pool_backend {
-            PersistentPoolBackend::Lmdb(lmdb_config) => Box::new(
-                crate::lmdb_pool::PersistentHeightIndexedPool::new_consensus_pool(
-                    lmdb_config,
-                    config.persistent_pool_read_only,
-                    log.clone(),
-                ),
-            ) as Box<_>,
-            #[cfg(feature = "rocksdb_backend")]
-            PersistentPoolBackend::RocksDB(config) => Box::new(
-                crate::rocksdb_pool::PersistentHeightIndexedPool::new_consensus_pool(
-                    config,
-                    log.clone(),
-                ),
-            ) as Box<_>,
-            #[allow(unreachable_patterns)]
-            cfg => {
-                unimplemented!("Configuration {:?} is not supported", cfg)
-            }
-        };
+        let validated = crate::storage::new_consensus_pool_section(&config, log.clone());
+        Self::new_with_storage(validated, log)
+    }
 
+    /// Create a pool whose validated section is kept in the given storage
+    /// backend.
+    pub fn new_with_storage(
+        validated: Box<dyn InitializablePoolSection + Send + Sync>,
+        log: ReplicaLogger,
+    ) -> UncachedConsensusPoolImpl {
         UncachedConsensusPoolImpl {
             validated,
             unvalidated: Box::new(InMemoryPoolSection::new(log)),
@@ -366,7 +355,21 @@ impl ConsensusPoolImpl {
         registry: ic_metrics::MetricsRegistry,
         log: ReplicaLogger,
     ) -> ConsensusPoolImpl {
-        let mut pool = UncachedConsensusPoolImpl::new(config.clone(), log.clone());
+        let validated = crate::storage::new_consensus_pool_section(&config, log.clone());
+        Self::new_with_storage(subnet_id, cup_proto, config, validated, registry, log)
+    }
+
+    /// Like [`ConsensusPoolImpl::new`], but keeps the validated artifacts in
+    /// the given storage backend instead of the one chosen by `config`.
+    pub fn new_with_storage(
+        subnet_id: SubnetId,
+        cup_proto: pb::CatchUpPackage,
+        config: ArtifactPoolConfig,
+        validated: Box<dyn InitializablePoolSection + Send + Sync>,
+        registry: ic_metrics::MetricsRegistry,
+        log: ReplicaLogger,
+    ) -> ConsensusPoolImpl {
+        let mut pool = UncachedConsensusPoolImpl::new_with_storage(validated, log.clone());
         Self::init_genesis(cup_proto, pool.validated.as_mut());
         let mut pool = Self::from_uncached(pool, registry.clone(), log.clone());
         // If the back up directory is set, instantiate the backup component
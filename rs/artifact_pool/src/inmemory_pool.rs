@@ -1,23 +1,41 @@
 use crate::{
-    consensus_pool::{MutablePoolSection, PoolSectionOp, PoolSectionOps},
+    consensus_pool::{InitializablePoolSection, MutablePoolSection, PoolSectionOp, PoolSectionOps},
     height_index::{HeightIndex, Indexes, SelectIndex},
 };
 use ic_interfaces::{
     artifact_pool::{HasTimestamp, IntoInner},
-    consensus_pool::{HeightIndexedPool, HeightRange, OnlyError, PoolSection},
+    consensus_pool::{
+        HeightIndexedPool, HeightRange, OnlyError, PoolSection, ValidatedConsensusArtifact,
+    },
 };
 use ic_logger::{warn, ReplicaLogger};
+use ic_protobuf::types::v1 as pb;
 use ic_types::{
-    artifact::ConsensusMessageId,
-    consensus::*,
-    crypto::{CryptoHash, CryptoHashOf},
+    artifact::{CertificationMessageId, ConsensusMessageId},
+    consensus::{
+        certification::{
+            Certification, CertificationMessage, CertificationMessageHash, CertificationShare,
+        },
+        *,
+    },
+    crypto::{crypto_hash, CryptoHash, CryptoHashOf},
     Height, Time,
 };
 use std::collections::BTreeMap;
+use std::sync::RwLock;
 
+/// A pool section that keeps all artifacts in memory.
+///
+/// It backs the unvalidated section of the consensus pool. Tests can also use
+/// it for the validated section, by passing it to
+/// [`ConsensusPoolImpl::new_with_storage`](crate::consensus_pool::ConsensusPoolImpl::new_with_storage).
 pub struct InMemoryPoolSection<T: IntoInner<ConsensusMessage>> {
     indexes: Indexes,
     artifacts: BTreeMap<CryptoHash, T>,
+    /// The protobufs of CUPs inserted with
+    /// [`InitializablePoolSection::insert_cup_with_proto`], by hash, so that
+    /// they can be handed out with their original signed bytes.
+    cup_protos: BTreeMap<CryptoHash, pb::CatchUpPackage>,
     log: ReplicaLogger,
 }
 
@@ -26,6 +44,7 @@ impl<T: IntoInner<ConsensusMessage> + HasTimestamp + Clone> InMemoryPoolSection<
         InMemoryPoolSection {
             artifacts: BTreeMap::new(),
             indexes: Indexes::new(),
+            cup_protos: BTreeMap::new(),
             log,
         }
     }
@@ -78,6 +97,9 @@ impl<T: IntoInner<ConsensusMessage> + HasTimestamp + Clone> InMemoryPoolSection<
             purge!(catch_up_package_share, CatchUpPackageShare);
         }
 
+        let artifacts = &self.artifacts;
+        self.cup_protos
+            .retain(|hash, _| artifacts.contains_key(hash));
         purged
     }
 
@@ -106,6 +128,7 @@ impl<T: IntoInner<ConsensusMessage> + HasTimestamp + Clone> InMemoryPoolSection<
     pub fn remove_by_hash(&mut self, hash: &CryptoHash) -> Option<T> {
         self.artifacts.remove(hash).map(|artifact| {
             self.indexes.remove(artifact.as_ref(), hash);
+            self.cup_protos.remove(hash);
             artifact
         })
     }
@@ -248,6 +271,20 @@ impl<T: IntoInner<ConsensusMessage> + HasTimestamp + Clone> PoolSection<T>
     fn catch_up_package_share(&self) -> &dyn HeightIndexedPool<CatchUpPackageShare> {
         self
     }
+
+    fn highest_catch_up_package_proto(&self) -> pb::CatchUpPackage {
+        let cup = self.catch_up_package().get_highest().unwrap_or_else(|err| {
+            panic!(
+                "Error getting highest CatchUpPackage in the validated pool: {:?}",
+                err
+            )
+        });
+        let hash = cup.get_cm_hash();
+        self.cup_protos
+            .get(hash.digest())
+            .cloned()
+            .unwrap_or_else(|| pb::CatchUpPackage::from(&cup))
+    }
 }
 
 impl<T: IntoInner<ConsensusMessage> + HasTimestamp + Clone> MutablePoolSection<T>
@@ -281,13 +318,182 @@ impl<T: IntoInner<ConsensusMessage> + HasTimestamp + Clone> MutablePoolSection<T
     }
 }
 
+impl InitializablePoolSection for InMemoryPoolSection<ValidatedConsensusArtifact> {
+    fn insert_cup_with_proto(&mut self, cup_proto: pb::CatchUpPackage) {
+        let cup = CatchUpPackage::try_from(&cup_proto).expect("deserializing CUP failed");
+        let hash = cup.get_cm_hash().digest().clone();
+        let timestamp = cup.content.block.as_ref().context.time;
+        self.insert(ValidatedConsensusArtifact {
+            msg: cup.into_message(),
+            timestamp,
+        });
+        self.cup_protos.insert(hash, cup_proto);
+    }
+}
+
+/// A certification pool section that keeps all artifacts in memory.
+///
+/// Tests can use it for the validated section of the certification pool, by
+/// passing it to
+/// [`CertificationPoolImpl::new_with_storage`](crate::certification_pool::CertificationPoolImpl::new_with_storage).
+#[derive(Default)]
+pub struct InMemoryCertificationPoolSection {
+    certifications: InMemoryHeightIndex<Certification>,
+    certification_shares: InMemoryHeightIndex<CertificationShare>,
+}
+
+impl InMemoryCertificationPoolSection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl crate::certification_pool::MutablePoolSection for InMemoryCertificationPoolSection {
+    fn insert(&self, message: CertificationMessage) {
+        match message {
+            CertificationMessage::Certification(value) => {
+                self.certifications.insert(value.height, value)
+            }
+            CertificationMessage::CertificationShare(value) => {
+                self.certification_shares.insert(value.height, value)
+            }
+        }
+    }
+
+    fn certifications(&self) -> &dyn HeightIndexedPool<Certification> {
+        &self.certifications
+    }
+
+    fn certification_shares(&self) -> &dyn HeightIndexedPool<CertificationShare> {
+        &self.certification_shares
+    }
+
+    fn purge_below(&self, height: Height) -> Vec<CertificationMessageId> {
+        let certifications = self
+            .certifications
+            .purge_below(height)
+            .into_iter()
+            .map(|value| CertificationMessageId {
+                height: value.height,
+                hash: CertificationMessageHash::Certification(crypto_hash(&value)),
+            });
+        let shares = self
+            .certification_shares
+            .purge_below(height)
+            .into_iter()
+            .map(|value| CertificationMessageId {
+                height: value.height,
+                hash: CertificationMessageHash::CertificationShare(crypto_hash(&value)),
+            });
+        certifications.chain(shares).collect()
+    }
+}
+
+/// A [`HeightIndex`] of artifacts behind a lock, so that it can be mutated
+/// through a shared reference like the persistent pools.
+struct InMemoryHeightIndex<T: Eq> {
+    index: RwLock<HeightIndex<T>>,
+}
+
+impl<T: Eq> Default for InMemoryHeightIndex<T> {
+    fn default() -> Self {
+        Self {
+            index: RwLock::new(HeightIndex::default()),
+        }
+    }
+}
+
+impl<T: Eq + Clone> InMemoryHeightIndex<T> {
+    fn insert(&self, height: Height, value: T) {
+        self.index.write().unwrap().insert(height, &value);
+    }
+
+    /// Removes and returns all values below the given [`Height`].
+    fn purge_below(&self, height: Height) -> Vec<T> {
+        let mut index = self.index.write().unwrap();
+        let heights: Vec<Height> = index
+            .heights()
+            .take_while(|h| **h < height)
+            .cloned()
+            .collect();
+        heights
+            .into_iter()
+            .flat_map(|h| index.remove_all(h))
+            .collect()
+    }
+}
+
+impl<T: Eq + Clone + 'static> HeightIndexedPool<T> for InMemoryHeightIndex<T> {
+    fn height_range(&self) -> Option<HeightRange> {
+        let index = self.index.read().unwrap();
+        let mut heights = index.heights();
+        let min = *heights.next()?;
+        let max = heights.last().cloned().unwrap_or(min);
+        Some(HeightRange::new(min, max))
+    }
+
+    fn max_height(&self) -> Option<Height> {
+        self.height_range().map(|range| range.max)
+    }
+
+    fn get_all(&self) -> Box<dyn Iterator<Item = T>> {
+        let values: Vec<T> = self.index.read().unwrap().get_all().cloned().collect();
+        Box::new(values.into_iter())
+    }
+
+    fn get_by_height(&self, h: Height) -> Box<dyn Iterator<Item = T>> {
+        let values: Vec<T> = self.index.read().unwrap().lookup(h).cloned().collect();
+        Box::new(values.into_iter())
+    }
+
+    fn get_by_height_range(&self, range: HeightRange) -> Box<dyn Iterator<Item = T>> {
+        if range.min > range.max {
+            return Box::new(std::iter::empty());
+        }
+        let values: Vec<T> = self
+            .index
+            .read()
+            .unwrap()
+            .range(range.min..=range.max)
+            .flat_map(|(_, bucket)| bucket.iter().cloned())
+            .collect();
+        Box::new(values.into_iter())
+    }
+
+    fn get_only_by_height(&self, h: Height) -> Result<T, OnlyError> {
+        let mut to_vec: Vec<T> = self.get_by_height(h).collect();
+        match to_vec.len() {
+            0 => Err(OnlyError::NoneAvailable),
+            1 => Ok(to_vec.remove(0)),
+            _ => Err(OnlyError::MultipleValues),
+        }
+    }
+
+    fn get_highest(&self) -> Result<T, OnlyError> {
+        match self.max_height() {
+            Some(h) => self.get_only_by_height(h),
+            None => Err(OnlyError::NoneAvailable),
+        }
+    }
+
+    fn get_highest_iter(&self) -> Box<dyn Iterator<Item = T>> {
+        match self.max_height() {
+            Some(h) => self.get_by_height(h),
+            None => Box::new(std::iter::empty()),
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use std::collections::HashSet;
+    use std::{panic, path::PathBuf};
 
     use super::*;
+    use crate::test_utils::PoolTestHelper;
     use ic_interfaces::artifact_pool::ValidatedArtifact;
     use ic_test_utilities::consensus::{fake::*, make_genesis};
+    use ic_test_utilities_logger::with_test_replica_logger;
 
     fn make_summary(genesis_height: Height) -> ic_types::consensus::dkg::Summary {
         let mut summary = ic_types::consensus::dkg::Summary::fake();
@@ -364,4 +570,73 @@ pub mod test {
             }
         ));
     }
+
+    /// The in-memory backend has no configuration. The test suite still
+    /// expects a pool directory, so this holds a temporary one.
+    #[derive(Clone)]
+    struct InMemoryConfig {
+        persistent_pool_path: PathBuf,
+    }
+
+    impl PoolTestHelper for InMemoryConfig {
+        type PersistentHeightIndexedPool = InMemoryPoolSection<ValidatedConsensusArtifact>;
+
+        const PERSISTENT: bool = false;
+
+        fn run_persistent_pool_test<T, R>(_test_name: &str, test: T) -> R
+        where
+            T: FnOnce(InMemoryConfig, ReplicaLogger) -> R + panic::UnwindSafe,
+        {
+            with_test_replica_logger(|log| {
+                let tempdir = tempfile::Builder::new()
+                    .prefix("persistent-pool")
+                    .tempdir()
+                    .unwrap();
+                let config = InMemoryConfig {
+                    persistent_pool_path: tempdir.path().to_path_buf(),
+                };
+                let result = panic::catch_unwind(|| test(config, log));
+                assert!(result.is_ok());
+                result.unwrap()
+            })
+        }
+
+        fn new_consensus_pool(self, log: ReplicaLogger) -> Self::PersistentHeightIndexedPool {
+            InMemoryPoolSection::new(log)
+        }
+
+        fn persistent_pool_validated_persistent_db_path(&self) -> &PathBuf {
+            &self.persistent_pool_path
+        }
+    }
+
+    #[test]
+    fn test_as_height_indexed_pool() {
+        crate::test_utils::test_as_height_indexed_pool::<InMemoryConfig>()
+    }
+
+    #[test]
+    fn test_block_proposal_and_payload_correspondence() {
+        crate::test_utils::test_block_proposal_and_payload_correspondence::<InMemoryConfig>()
+    }
+
+    #[test]
+    fn test_iterating_while_inserting_doesnt_see_new_updates() {
+        crate::test_utils::test_iterating_while_inserting_doesnt_see_new_updates::<InMemoryConfig>()
+    }
+
+    #[test]
+    fn test_iterator_can_outlive_the_pool() {
+        crate::test_utils::test_iterator_can_outlive_the_pool::<InMemoryConfig>()
+    }
+
+    #[test]
+    fn test_highest_catch_up_package_proto() {
+        crate::test_utils::test_highest_catch_up_package_proto::<InMemoryConfig>()
+    }
+
+    #[test]
+    fn test_certification_pool_section() {
+        crate::test_utils::test_certification_pool_section(&InMemoryCertificationPoolSection::new())
+    }
 }
@@ -641,7 +641,7 @@ impl<Artifact: PoolArtifact> PersistentHeightIndexedPool<Artifact> {
 
 impl InitializablePoolSection for PersistentHeightIndexedPool<ConsensusMessage> {
     /// Insert a cup with the original bytes from which that cup was received.
-    fn insert_cup_with_proto(&self, cup_proto: pb::CatchUpPackage) {
+    fn insert_cup_with_proto(&mut self, cup_proto: pb::CatchUpPackage) {
         let cup = CatchUpPackage::try_from(&cup_proto).expect("deserializing CUP failed");
         let mut tx = self
             .db_env
@@ -1979,6 +1979,19 @@ mod tests {
         crate::test_utils::test_persistent_pool_path_is_cleanedup_after_tests::<LMDBConfig>()
     }
 
+    #[test]
+    fn test_highest_catch_up_package_proto() {
+        crate::test_utils::test_highest_catch_up_package_proto::<LMDBConfig>()
+    }
+
+    #[test]
+    fn test_certification_pool_section() {
+        run_persistent_pool_test("test_certification_pool_section", |config, log| {
+            let pool = PersistentHeightIndexedPool::new_certification_pool(config, false, log);
+            crate::test_utils::test_certification_pool_section(&pool)
+        })
+    }
+
     #[test]
     fn test_purge_survives_reboot() {
         run_persistent_pool_test("test_purge_survives_reboot", |config, log| {
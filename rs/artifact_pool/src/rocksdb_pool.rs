@@ -386,7 +386,7 @@ impl PersistentHeightIndexedPool<ConsensusMessage> {
 
 impl InitializablePoolSection for PersistentHeightIndexedPool<ConsensusMessage> {
     /// Insert a cup with the original bytes from which that cup was received.
-    fn insert_cup_with_proto(&self, cup_with_proto: pb::CatchUpPackage) {
+    fn insert_cup_with_proto(&mut self, cup_with_proto: pb::CatchUpPackage) {
         let cup = CatchUpPackage::try_from(&cup_with_proto).expect("deserializing CUP failed");
         let height = cup.height();
         let info = &CATCH_UP_PACKAGE_CF_INFO;
@@ -1264,6 +1264,19 @@ mod tests {
         crate::test_utils::test_persistent_pool_path_is_cleanedup_after_tests::<RocksDBConfig>()
     }
 
+    #[test]
+    fn test_highest_catch_up_package_proto() {
+        crate::test_utils::test_highest_catch_up_package_proto::<RocksDBConfig>()
+    }
+
+    #[test]
+    fn test_certification_pool_section() {
+        run_persistent_pool_test("test_certification_pool_section", |config, log| {
+            let pool = PersistentHeightIndexedPool::new_certification_pool(config, log);
+            crate::test_utils::test_certification_pool_section(&pool)
+        })
+    }
+
     // Test if purge survives reboot.
     #[test]
     fn test_purge_survives_reboot() {
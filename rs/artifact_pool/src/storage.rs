@@ -0,0 +1,83 @@
+//! Storage backends for the validated sections of the consensus and
+//! certification pools.
+//!
+//! A backend is an implementation of [`InitializablePoolSection`] for the
+//! consensus pool and of [`MutableCertificationPoolSection`] for the
+//! certification pool. The LMDB and RocksDB (behind the `rocksdb_backend`
+//! feature) backends are chosen by [`PersistentPoolBackend`]. Other backends,
+//! such as the in-memory ones meant for tests, can be plugged in with
+//! [`ConsensusPoolImpl::new_with_storage`](crate::consensus_pool::ConsensusPoolImpl::new_with_storage)
+//! and
+//! [`CertificationPoolImpl::new_with_storage`](crate::certification_pool::CertificationPoolImpl::new_with_storage).
+//!
+//! Every backend must provide the same semantics: artifacts are indexed by
+//! height, inserting an artifact twice stores it once, and purging below a
+//! height removes all artifacts (or all shares) strictly below it and
+//! returns their ids. Persistent backends additionally keep artifacts and
+//! their timestamps across restarts.
+
+use ic_config::artifact_pool::{ArtifactPoolConfig, PersistentPoolBackend};
+use ic_logger::ReplicaLogger;
+
+pub use crate::certification_pool::MutablePoolSection as MutableCertificationPoolSection;
+pub use crate::consensus_pool::{
+    InitializablePoolSection, MutablePoolSection as MutableConsensusPoolSection, PoolSectionOp,
+    PoolSectionOps,
+};
+pub use crate::inmemory_pool::{InMemoryCertificationPoolSection, InMemoryPoolSection};
+
+/// Creates the validated section of the consensus pool in the backend
+/// chosen by `config`.
+pub fn new_consensus_pool_section(
+    config: &ArtifactPoolConfig,
+    log: ReplicaLogger,
+) -> Box<dyn InitializablePoolSection + Send + Sync> {
+    match &config.persistent_pool_backend {
+        PersistentPoolBackend::Lmdb(lmdb_config) => Box::new(
+            crate::lmdb_pool::PersistentHeightIndexedPool::new_consensus_pool(
+                lmdb_config.clone(),
+                config.persistent_pool_read_only,
+                log,
+            ),
+        ),
+        #[cfg(feature = "rocksdb_backend")]
+        PersistentPoolBackend::RocksDB(rocksdb_config) => Box::new(
+            crate::rocksdb_pool::PersistentHeightIndexedPool::new_consensus_pool(
+                rocksdb_config.clone(),
+                log,
+            ),
+        ),
+        #[cfg(not(feature = "rocksdb_backend"))]
+        PersistentPoolBackend::RocksDB(_) => {
+            panic!("The RocksDB backend requires the rocksdb_backend feature")
+        }
+    }
+}
+
+/// Creates the validated section of the certification pool in the backend
+/// chosen by `config`.
+pub fn new_certification_pool_section(
+    config: &ArtifactPoolConfig,
+    log: ReplicaLogger,
+) -> Box<dyn MutableCertificationPoolSection + Send + Sync> {
+    match &config.persistent_pool_backend {
+        PersistentPoolBackend::Lmdb(lmdb_config) => Box::new(
+            crate::lmdb_pool::PersistentHeightIndexedPool::new_certification_pool(
+                lmdb_config.clone(),
+                config.persistent_pool_read_only,
+                log,
+            ),
+        ),
+        #[cfg(feature = "rocksdb_backend")]
+        PersistentPoolBackend::RocksDB(rocksdb_config) => Box::new(
+            crate::rocksdb_pool::PersistentHeightIndexedPool::new_certification_pool(
+                rocksdb_config.clone(),
+                log,
+            ),
+        ),
+        #[cfg(not(feature = "rocksdb_backend"))]
+        PersistentPoolBackend::RocksDB(_) => {
+            panic!("The RocksDB backend requires the rocksdb_backend feature")
+        }
+    }
+}
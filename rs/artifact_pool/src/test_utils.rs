@@ -7,27 +7,36 @@
 //! By implementing this trait on a Pool implementation (in a test submodule),
 //! the tests in this module can be used to test the Pool implementation.
 
-use crate::consensus_pool::{MutablePoolSection, PoolSectionOp, PoolSectionOps};
+use crate::certification_pool::MutablePoolSection as MutableCertificationPoolSection;
+use crate::consensus_pool::{
+    InitializablePoolSection, MutablePoolSection, PoolSectionOp, PoolSectionOps,
+};
 use ic_interfaces::consensus_pool::{
     HeightIndexedPool, HeightRange, PoolSection, ValidatedConsensusArtifact,
 };
 use ic_logger::ReplicaLogger;
+use ic_protobuf::types::v1 as pb;
 use ic_test_utilities::{
     consensus::{fake::*, make_genesis},
     mock_time,
     types::ids::node_test_id,
 };
 use ic_types::{
-    artifact::{ConsensusMessage, ConsensusMessageId},
+    artifact::{CertificationMessageId, ConsensusMessage, ConsensusMessageId},
     consensus::{
-        dkg::Summary, Block, BlockPayload, BlockProposal, ConsensusMessageHashable, Finalization,
+        certification::{
+            Certification, CertificationContent, CertificationMessage, CertificationMessageHash,
+            CertificationShare,
+        },
+        dkg::Summary,
+        Block, BlockPayload, BlockProposal, ConsensusMessageHashable, Finalization,
         FinalizationContent, FinalizationShare, Notarization, NotarizationContent,
         NotarizationShare, RandomBeacon, RandomBeaconContent, RandomBeaconShare, RandomTape,
         RandomTapeContent, RandomTapeShare,
     },
-    crypto::{ThresholdSigShare, ThresholdSigShareOf},
+    crypto::{crypto_hash, CryptoHash, Signed, ThresholdSigShare, ThresholdSigShareOf},
     signature::*,
-    Height,
+    CryptoHashOfPartialState, Height,
 };
 use std::{
     collections::HashSet,
@@ -37,7 +46,11 @@ use std::{
 };
 
 pub(crate) trait PoolTestHelper: Clone {
-    type PersistentHeightIndexedPool: MutablePoolSection<ValidatedConsensusArtifact>;
+    type PersistentHeightIndexedPool: InitializablePoolSection;
+
+    /// Whether the pool keeps its artifacts across restarts. Tests skip
+    /// their reboot checks for pools that don't.
+    const PERSISTENT: bool = true;
 
     fn run_persistent_pool_test<T, R>(_test_name: &str, test: T) -> R
     where
@@ -139,10 +152,10 @@ where
         let rt_ops = random_tape_ops();
         let rts_ops = random_tape_share_ops();
 
+        let mut pool = T::new_consensus_pool(config.clone(), log.clone());
+
         // Insert a bunch of items and test that the pool returns them
         {
-            let mut pool = T::new_consensus_pool(config.clone(), log.clone());
-
             pool.mutate(rb_ops.clone());
             match_ops_to_results(&rb_ops, pool.random_beacon(), false);
 
@@ -172,8 +185,11 @@ where
         }
 
         // Test the matching after a reboot.
+        if T::PERSISTENT {
+            drop(pool);
+            pool = T::new_consensus_pool(config, log);
+        }
         {
-            let pool = T::new_consensus_pool(config.clone(), log.clone());
             match_ops_to_results(&rb_ops, pool.random_beacon(), false);
             match_ops_to_results(&fz_ops, pool.finalization(), false);
             match_ops_to_results(&nz_ops, pool.notarization(), false);
@@ -195,7 +211,6 @@ where
                 pool.get_by_height_range(range.clone()).count()
             }
 
-            let mut pool = T::new_consensus_pool(config, log);
             let finalized_height = pool.finalization().max_height().unwrap();
             let range_to_delete = HeightRange::new(Height::from(0), finalized_height.decrement());
 
@@ -462,6 +477,105 @@ where
     });
 }
 
+// Tests that a CUP inserted with its protobuf is returned as the highest
+// CUP, with the original protobuf.
+pub(crate) fn test_highest_catch_up_package_proto<T>()
+where
+    T: PoolTestHelper,
+{
+    T::run_persistent_pool_test("test_highest_catch_up_package_proto", |config, log| {
+        let cup = make_genesis(make_summary(Height::from(10)));
+        let cup_proto = pb::CatchUpPackage::from(&cup);
+        let mut pool = T::new_consensus_pool(config, log);
+        pool.insert_cup_with_proto(cup_proto.clone());
+
+        assert_eq!(pool.catch_up_package().get_highest().unwrap(), cup);
+        assert_eq!(pool.highest_catch_up_package_proto(), cup_proto);
+        assert_eq!(
+            pool.get_timestamp(&cup.get_id()),
+            Some(cup.content.block.as_ref().context.time)
+        );
+    });
+}
+
+// Tests the validated section of the certification pool: insertion,
+// deduplication, height-indexed lookups and purging.
+pub(crate) fn test_certification_pool_section(pool: &dyn MutableCertificationPoolSection) {
+    let content = CertificationContent::new(CryptoHashOfPartialState::from(CryptoHash(vec![])));
+    let share = |height: u64, node: u64| CertificationShare {
+        height: Height::from(height),
+        signed: Signed {
+            signature: ThresholdSignatureShare::fake(node_test_id(node)),
+            content: content.clone(),
+        },
+    };
+    let certification = |height: u64| Certification {
+        height: Height::from(height),
+        signed: Signed {
+            signature: ThresholdSignature::fake(),
+            content: content.clone(),
+        },
+    };
+
+    for height in 1..=5 {
+        pool.insert(CertificationMessage::Certification(certification(height)));
+        for node in 0..3 {
+            pool.insert(CertificationMessage::CertificationShare(share(
+                height, node,
+            )));
+        }
+    }
+    // Inserting an artifact that is already in the pool is a no-op.
+    pool.insert(CertificationMessage::Certification(certification(3)));
+    pool.insert(CertificationMessage::CertificationShare(share(3, 0)));
+
+    assert_eq!(pool.certifications().get_all().count(), 5);
+    assert_eq!(pool.certification_shares().get_all().count(), 15);
+    assert_eq!(
+        pool.certifications().height_range(),
+        Some(HeightRange::new(Height::from(1), Height::from(5)))
+    );
+    assert_eq!(
+        pool.certifications().get_highest().unwrap(),
+        certification(5)
+    );
+    assert_eq!(
+        pool.certification_shares()
+            .get_by_height(Height::from(2))
+            .count(),
+        3
+    );
+    assert_eq!(
+        pool.certification_shares()
+            .get_by_height_range(HeightRange::new(Height::from(2), Height::from(3)))
+            .count(),
+        6
+    );
+
+    let purged = pool.purge_below(Height::from(3));
+    let expected = (1..3)
+        .map(|height| CertificationMessageId {
+            hash: CertificationMessageHash::Certification(crypto_hash(&certification(height))),
+            height: Height::from(height),
+        })
+        .chain((1..3).flat_map(|height| {
+            (0..3).map(move |node| CertificationMessageId {
+                hash: CertificationMessageHash::CertificationShare(crypto_hash(&share(
+                    height, node,
+                ))),
+                height: Height::from(height),
+            })
+        }))
+        .collect::<HashSet<_>>();
+    assert_eq!(purged.into_iter().collect::<HashSet<_>>(), expected);
+    assert_eq!(
+        pool.certifications().height_range(),
+        Some(HeightRange::new(Height::from(3), Height::from(5)))
+    );
+    assert_eq!(pool.certification_shares().get_all().count(), 9);
+    assert!(pool.purge_below(Height::from(3)).is_empty());
+}
+
 // Support functions for the tests
 pub(crate) fn random_beacon_ops() -> PoolSectionOps<ValidatedConsensusArtifact> {
     let mut ops = PoolSectionOps::new();
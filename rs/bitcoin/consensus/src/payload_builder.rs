@@ -10,7 +10,7 @@ use ic_btc_types_internal::{
     BitcoinAdapterRequestWrapper, BitcoinAdapterResponse, BitcoinAdapterResponseWrapper,
 };
 use ic_interfaces::{
-    batch_payload::{BatchPayloadBuilder, PastPayload},
+    batch_payload::{BatchPayloadBuilder, PastPayload, ProposalContext},
     consensus::{PayloadPermanentError, PayloadValidationError},
     self_validating_payload::{
         InvalidSelfValidatingPayload, SelfValidatingPayloadBuilder,
@@ -337,7 +337,7 @@ impl BatchPayloadBuilder for BitcoinPayloadBuilder {
         height: Height,
         payload: &[u8],
         past_payloads: &[PastPayload],
-        context: &ValidationContext,
+        proposal_context: &ProposalContext,
     ) -> Result<(), PayloadValidationError> {
         if payload.is_empty() {
             return Ok(());
@@ -350,7 +350,8 @@ impl BatchPayloadBuilder for BitcoinPayloadBuilder {
             ))
         })?;
 
-        let _ = self.validate_self_validating_payload_impl(&payload, context)?;
+        let _ = self
+            .validate_self_validating_payload_impl(&payload, proposal_context.validation_context)?;
         Ok(())
     }
 }
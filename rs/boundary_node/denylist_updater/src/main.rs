@@ -17,6 +17,7 @@ use axum::{
     Extension, Router,
 };
 use clap::{ArgEnum, Parser};
+use denylist_updater::decode::{Decode, Decoder, NopDecoder};
 use futures::future::TryFutureExt;
 use mockall::automock;
 use nix::{
@@ -39,9 +40,6 @@ use tracing::info;
 mod metrics;
 use metrics::{MetricParams, WithMetrics};
 
-mod decode;
-use decode::{Decode, Decoder, NopDecoder};
-
 const SERVICE_NAME: &str = "denylist-updater";
 
 const MINUTE: Duration = Duration::from_secs(60);
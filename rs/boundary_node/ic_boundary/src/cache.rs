@@ -0,0 +1,234 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::{boxed, Body},
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use bytes::Bytes;
+use candid::Principal;
+use http::{HeaderMap, HeaderValue};
+use ic_types::{messages::HttpQueryResponse, CountBytes, NumBytes};
+use ic_utils_lru_cache::LruCache;
+
+use crate::routes::{ErrorCause, RequestContext, RequestType};
+
+// Clients can set this header (to any value) to skip the cache
+pub const HEADER_CACHE_BYPASS: &str = "x-ic-cache-bypass";
+// Tells clients if the response was served from the cache
+pub const HEADER_CACHE_STATUS: &str = "x-ic-cache-status";
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    #[default]
+    Disabled,
+    Bypass,
+    Hit,
+    Miss,
+}
+
+impl fmt::Display for CacheStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Disabled => write!(f, "DISABLED"),
+            Self::Bypass => write!(f, "BYPASS"),
+            Self::Hit => write!(f, "HIT"),
+            Self::Miss => write!(f, "MISS"),
+        }
+    }
+}
+
+// Only the fields that affect the response are part of the key.
+// `ingress_expiry` and `nonce` are left out on purpose since they differ
+// between otherwise identical queries made by different clients.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    canister_id: Principal,
+    method_name: String,
+    arg: Vec<u8>,
+}
+
+impl CountBytes for CacheKey {
+    fn count_bytes(&self) -> usize {
+        self.canister_id.as_slice().len() + self.method_name.len() + self.arg.len()
+    }
+}
+
+struct CacheEntry {
+    headers: HeaderMap,
+    body: Bytes,
+    expires_at: Instant,
+}
+
+impl CountBytes for CacheEntry {
+    fn count_bytes(&self) -> usize {
+        let headers_size: usize = self
+            .headers
+            .iter()
+            .map(|(k, v)| k.as_str().len() + v.len())
+            .sum();
+
+        headers_size + self.body.len()
+    }
+}
+
+// Builds a cache key if the request is eligible for caching:
+// an anonymous, unexpired query that doesn't ask to bypass the cache
+pub fn cache_key(ctx: &RequestContext, headers: &HeaderMap, now: SystemTime) -> Option<CacheKey> {
+    if !matches!(ctx.request_type, RequestType::Query) {
+        return None;
+    }
+
+    if headers.contains_key(HEADER_CACHE_BYPASS) {
+        return None;
+    }
+
+    if ctx.sender != Some(Principal::anonymous()) {
+        return None;
+    }
+
+    let now = now.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    if u128::from(ctx.ingress_expiry?) < now {
+        return None;
+    }
+
+    Some(CacheKey {
+        canister_id: ctx.canister_id_cbor.or(ctx.canister_id)?,
+        method_name: ctx.method_name.clone()?,
+        arg: ctx.arg.clone()?,
+    })
+}
+
+pub struct Cache {
+    cache: Mutex<LruCache<CacheKey, CacheEntry>>,
+    max_item_size: usize,
+    ttl: Duration,
+}
+
+impl Cache {
+    pub fn new(cache_size: u64, max_item_size: usize, ttl: Duration) -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(NumBytes::new(cache_size))),
+            max_item_size,
+            ttl,
+        }
+    }
+
+    fn get(&self, key: &CacheKey, now: Instant) -> Option<(HeaderMap, Bytes)> {
+        let mut cache = self.cache.lock().unwrap();
+
+        let entry = cache.get(key)?;
+        if entry.expires_at > now {
+            return Some((entry.headers.clone(), entry.body.clone()));
+        }
+
+        // Drop the stale entry right away to free up space
+        cache.pop(key);
+        None
+    }
+
+    fn insert(&self, key: CacheKey, headers: HeaderMap, body: Bytes, now: Instant) {
+        if body.len() > self.max_item_size {
+            return;
+        }
+
+        self.cache.lock().unwrap().push(
+            key,
+            CacheEntry {
+                headers,
+                body,
+                expires_at: now + self.ttl,
+            },
+        );
+    }
+}
+
+// Only successful replies are cached, rejects must always reach the replica
+fn is_cacheable(body: &[u8]) -> bool {
+    matches!(
+        serde_cbor::from_slice::<HttpQueryResponse>(body),
+        Ok(HttpQueryResponse::Replied { .. })
+    )
+}
+
+fn with_cache_status(mut response: Response, status: CacheStatus) -> Response {
+    response.headers_mut().insert(
+        HEADER_CACHE_STATUS,
+        HeaderValue::from_str(&status.to_string()).unwrap(),
+    );
+
+    response
+}
+
+// Serves anonymous queries from the cache and populates it with replica responses.
+// Must run after `preprocess_request` since it relies on the request context.
+pub async fn cache_middleware(
+    State(cache): State<Option<Arc<Cache>>>,
+    Extension(mut ctx): Extension<RequestContext>,
+    mut request: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, Response> {
+    let cache = match cache {
+        Some(v) => v,
+        None => return Ok(next.run(request).await),
+    };
+
+    // Only queries can ever be cached, don't report a cache status for other requests
+    if ctx.request_type != RequestType::Query {
+        return Ok(next.run(request).await);
+    }
+
+    let key = match cache_key(&ctx, request.headers(), SystemTime::now()) {
+        Some(v) => v,
+        None => {
+            ctx.cache_status = CacheStatus::Bypass;
+            request.extensions_mut().insert(ctx);
+
+            let response = next.run(request).await;
+            return Ok(with_cache_status(response, CacheStatus::Bypass));
+        }
+    };
+
+    if let Some((headers, body)) = cache.get(&key, Instant::now()) {
+        ctx.cache_status = CacheStatus::Hit;
+
+        let mut response = Response::new(boxed(Body::from(body)));
+        *response.headers_mut() = headers;
+        response.extensions_mut().insert(ctx);
+
+        return Ok(with_cache_status(response, CacheStatus::Hit));
+    }
+
+    ctx.cache_status = CacheStatus::Miss;
+    request.extensions_mut().insert(ctx.clone());
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return Ok(with_cache_status(response, CacheStatus::Miss));
+    }
+
+    // Buffer the body to be able to store it
+    let (parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body).await.map_err(|e| {
+        ctx.respond(ErrorCause::ReplicaUnreachable(format!(
+            "unable to read response body: {e}"
+        )))
+    })?;
+
+    if is_cacheable(&body) {
+        cache.insert(key, parts.headers.clone(), body.clone(), Instant::now());
+    }
+
+    let response = Response::from_parts(parts, boxed(Body::from(body)));
+    Ok(with_cache_status(response, CacheStatus::Miss))
+}
+
+#[cfg(test)]
+pub mod test;
@@ -0,0 +1,210 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Error;
+use axum::{middleware, routing::post, Router};
+use ic_types::messages::{Blob, HttpQueryResponseReply};
+use tower::ServiceExt;
+
+use super::*;
+
+const CANISTER_1: &str = "sxiki-5ygae-aq";
+const SENDER: &str = "sqjm4-qahae-aq";
+
+fn context(sender: Principal, arg: &[u8]) -> RequestContext {
+    let canister_id = Principal::from_text(CANISTER_1).unwrap();
+
+    RequestContext {
+        request_type: RequestType::Query,
+        canister_id: Some(canister_id),
+        canister_id_cbor: Some(canister_id),
+        sender: Some(sender),
+        method_name: Some("http_request".into()),
+        arg: Some(arg.to_vec()),
+        ingress_expiry: Some(u64::MAX),
+        ..Default::default()
+    }
+}
+
+fn key(arg: &[u8]) -> CacheKey {
+    cache_key(
+        &context(Principal::anonymous(), arg),
+        &HeaderMap::new(),
+        SystemTime::now(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_cache_key() {
+    let now = SystemTime::now();
+    let ctx = context(Principal::anonymous(), b"foo");
+
+    assert!(cache_key(&ctx, &HeaderMap::new(), now).is_some());
+
+    // Same request with a different expiry maps to the same key
+    let mut ctx2 = ctx.clone();
+    ctx2.ingress_expiry = Some(u64::MAX - 1);
+    assert_eq!(
+        cache_key(&ctx, &HeaderMap::new(), now),
+        cache_key(&ctx2, &HeaderMap::new(), now)
+    );
+
+    // Not anonymous
+    let ctx2 = context(Principal::from_text(SENDER).unwrap(), b"foo");
+    assert!(cache_key(&ctx2, &HeaderMap::new(), now).is_none());
+
+    // Not a query
+    let mut ctx2 = ctx.clone();
+    ctx2.request_type = RequestType::Call;
+    assert!(cache_key(&ctx2, &HeaderMap::new(), now).is_none());
+
+    // Expired
+    let mut ctx2 = ctx.clone();
+    ctx2.ingress_expiry = Some(1);
+    assert!(cache_key(&ctx2, &HeaderMap::new(), now).is_none());
+
+    // Bypass requested
+    let mut headers = HeaderMap::new();
+    headers.insert(HEADER_CACHE_BYPASS, HeaderValue::from_static("1"));
+    assert!(cache_key(&ctx, &headers, now).is_none());
+}
+
+#[test]
+fn test_cache_ttl_and_limits() {
+    let now = Instant::now();
+    let cache = Cache::new(1024, 100, Duration::from_secs(1));
+
+    cache.insert(key(b"1"), HeaderMap::new(), Bytes::from(vec![0; 10]), now);
+    assert!(cache.get(&key(b"1"), now).is_some());
+    assert!(cache.get(&key(b"2"), now).is_none());
+
+    // Expired
+    assert!(cache
+        .get(&key(b"1"), now + Duration::from_secs(1))
+        .is_none());
+
+    // Item is too big
+    cache.insert(key(b"3"), HeaderMap::new(), Bytes::from(vec![0; 101]), now);
+    assert!(cache.get(&key(b"3"), now).is_none());
+
+    // Least recently used items are evicted to fit into the cache size
+    for i in 0..20u8 {
+        cache.insert(key(&[i]), HeaderMap::new(), Bytes::from(vec![0; 100]), now);
+    }
+    assert!(cache.get(&key(&[0]), now).is_none());
+    assert!(cache.get(&key(&[19]), now).is_some());
+}
+
+fn router(cache: Arc<Cache>, ctx: RequestContext, reply: Vec<u8>) -> (Router, Arc<AtomicUsize>) {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let c = Arc::clone(&counter);
+
+    let router = Router::new()
+        .route(
+            "/api/v2/canister/:canister_id/query",
+            post(move || {
+                c.fetch_add(1, Ordering::SeqCst);
+                let reply = reply.clone();
+                async move { reply }
+            }),
+        )
+        .layer(middleware::from_fn_with_state(
+            Some(cache),
+            cache_middleware,
+        ))
+        .layer(Extension(ctx));
+
+    (router, counter)
+}
+
+fn request() -> Request<Body> {
+    Request::post(format!("/api/v2/canister/{CANISTER_1}/query"))
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_cache_middleware() -> Result<(), Error> {
+    let cache = Arc::new(Cache::new(1024, 1024, Duration::from_secs(60)));
+
+    let reply = serde_cbor::to_vec(&HttpQueryResponse::Replied {
+        reply: HttpQueryResponseReply {
+            arg: Blob(b"hello".to_vec()),
+        },
+    })?;
+
+    let (router, counter) = router(
+        Arc::clone(&cache),
+        context(Principal::anonymous(), b"foo"),
+        reply.clone(),
+    );
+
+    let resp = router.clone().oneshot(request()).await?;
+    assert_eq!(resp.headers().get(HEADER_CACHE_STATUS).unwrap(), "MISS");
+
+    let resp = router.clone().oneshot(request()).await?;
+    assert_eq!(resp.headers().get(HEADER_CACHE_STATUS).unwrap(), "HIT");
+    assert_eq!(
+        resp.extensions()
+            .get::<RequestContext>()
+            .unwrap()
+            .cache_status,
+        CacheStatus::Hit
+    );
+
+    let body = hyper::body::to_bytes(resp.into_body()).await?;
+    assert_eq!(body.to_vec(), reply);
+
+    // Replica was called only once
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+    // Bypass header skips the cache
+    let mut req = request();
+    req.headers_mut()
+        .insert(HEADER_CACHE_BYPASS, HeaderValue::from_static("1"));
+    let resp = router.clone().oneshot(req).await?;
+    assert_eq!(resp.headers().get(HEADER_CACHE_STATUS).unwrap(), "BYPASS");
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cache_middleware_reject() -> Result<(), Error> {
+    let cache = Arc::new(Cache::new(1024, 1024, Duration::from_secs(60)));
+
+    let reply = serde_cbor::to_vec(&HttpQueryResponse::Rejected {
+        error_code: "IC0301".into(),
+        reject_code: 3,
+        reject_message: "Canister not found".into(),
+    })?;
+
+    let (router, counter) = router(cache, context(Principal::anonymous(), b"foo"), reply);
+
+    for _ in 0..2 {
+        let resp = router.clone().oneshot(request()).await?;
+        assert_eq!(resp.headers().get(HEADER_CACHE_STATUS).unwrap(), "MISS");
+    }
+
+    // Rejects are never cached
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cache_middleware_not_query() -> Result<(), Error> {
+    let cache = Arc::new(Cache::new(1024, 1024, Duration::from_secs(60)));
+
+    let mut ctx = context(Principal::anonymous(), b"foo");
+    ctx.request_type = RequestType::Call;
+    let (router, counter) = router(cache, ctx, vec![]);
+
+    for _ in 0..2 {
+        let resp = router.clone().oneshot(request()).await?;
+        assert!(resp.headers().get(HEADER_CACHE_STATUS).is_none());
+    }
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
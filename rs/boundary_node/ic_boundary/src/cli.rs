@@ -18,6 +18,9 @@ pub struct Cli {
     #[command(flatten, next_help_heading = "health")]
     pub health: HealthChecksConfig,
 
+    #[command(flatten, next_help_heading = "load_balancing")]
+    pub load_balancing: LoadBalancingConfig,
+
     #[command(flatten, next_help_heading = "firewall")]
     pub firewall: FirewallConfig,
 
@@ -26,6 +29,15 @@ pub struct Cli {
 
     #[command(flatten, next_help_heading = "monitoring")]
     pub monitoring: MonitoringConfig,
+
+    #[command(flatten, next_help_heading = "rate_limiting")]
+    pub rate_limiting: RateLimitingConfig,
+
+    #[command(flatten, next_help_heading = "cache")]
+    pub cache: CacheConfig,
+
+    #[command(flatten, next_help_heading = "denylist")]
+    pub denylist: DenylistConfig,
 }
 
 #[derive(Args)]
@@ -95,6 +107,17 @@ pub struct HealthChecksConfig {
     pub max_height_lag: u64,
 }
 
+#[derive(Args)]
+pub struct LoadBalancingConfig {
+    /// Weight of the newest latency sample in the per-node moving average, in 0..1 range
+    #[clap(long, default_value = "0.3")]
+    pub lb_latency_decay: f64,
+
+    /// How many times to retry a failed query or read_state request on other nodes of the subnet
+    #[clap(long, default_value = "1")]
+    pub lb_max_retries: u32,
+}
+
 #[derive(Args)]
 pub struct FirewallConfig {
     /// The path to the nftables replica ruleset file to update
@@ -130,3 +153,63 @@ pub struct MonitoringConfig {
     #[clap(long, default_value = "127.0.0.1:9090")]
     pub metrics_addr: SocketAddr,
 }
+
+#[derive(Args)]
+pub struct RateLimitingConfig {
+    /// The path to a JSON file with rate limiting rules.
+    /// If not specified, requests are not rate limited
+    #[clap(long)]
+    pub rate_limiting_rules_path: Option<PathBuf>,
+
+    /// Load the rate limiting rules from the registry instead of a file
+    #[clap(long, conflicts_with = "rate_limiting_rules_path")]
+    pub rate_limiting_rules_from_registry: bool,
+
+    /// How frequently to reload the rate limiting rules in seconds
+    #[clap(long, default_value = "30")]
+    pub rate_limiting_reload_interval: u64,
+}
+
+#[derive(Args)]
+pub struct CacheConfig {
+    /// Maximum size of the anonymous query response cache in bytes.
+    /// If not specified, the cache is disabled
+    #[clap(long)]
+    pub cache_size_bytes: Option<u64>,
+
+    /// Maximum size of a single cached response in bytes
+    #[clap(long, default_value = "131072")]
+    pub cache_max_item_size_bytes: usize,
+
+    /// Time-to-live of the cached responses in seconds
+    #[clap(long, default_value = "1")]
+    pub cache_ttl_seconds: u64,
+}
+
+#[derive(Args)]
+pub struct DenylistConfig {
+    /// The URL to fetch the canister denylist from.
+    /// If not specified, no canisters are blocked
+    #[clap(long, requires = "denylist_verification_key_path")]
+    pub denylist_url: Option<Url>,
+
+    /// The path to the PEM encoded RSA public key used to verify the denylist signature.
+    /// The signature is fetched from the denylist URL with `.sig` appended to the path
+    #[clap(long)]
+    pub denylist_verification_key_path: Option<PathBuf>,
+
+    /// The path to the private key used to decrypt the denylist.
+    /// If not specified, the denylist is expected to be plain JSON
+    #[clap(long)]
+    pub denylist_decryption_key_path: Option<PathBuf>,
+
+    /// How frequently to refresh the denylist in seconds
+    #[clap(long, default_value = "60")]
+    pub denylist_poll_interval: u64,
+
+    /// The path to a local denylist in plain JSON that is applied on startup.
+    /// If not specified and a denylist URL is given, startup fails unless the
+    /// denylist can be fetched, so that no request is served without a denylist
+    #[clap(long)]
+    pub denylist_seed_path: Option<PathBuf>,
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Error};
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use axum::{
+    body::Body, extract::State, http::Request, middleware::Next, response::Response, Extension,
+};
+use candid::Principal;
+use denylist_updater::decode::Decode;
+use rsa::{Hash, PaddingScheme, PublicKey, RsaPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    routes::{ErrorCause, RequestContext, RequestType},
+    Run,
+};
+
+// Set of canisters that must not be served
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Denylist {
+    canisters: HashSet<Principal>,
+}
+
+impl Denylist {
+    pub fn new(canisters: HashSet<Principal>) -> Self {
+        Self { canisters }
+    }
+
+    pub fn len(&self) -> usize {
+        self.canisters.len()
+    }
+
+    pub fn is_blocked(&self, canister_id: &Principal) -> bool {
+        self.canisters.contains(canister_id)
+    }
+}
+
+// Parses the denylist in the same JSON format as consumed by the `denylist-updater`.
+// Entries restricted to specific localities are skipped since
+// geo-blocking is not supported by the API boundary node.
+pub fn parse_denylist(data: &[u8]) -> Result<Denylist, Error> {
+    #[derive(Deserialize)]
+    struct Canister {
+        localities: Option<Vec<String>>,
+    }
+
+    #[derive(Deserialize)]
+    struct Response {
+        canisters: HashMap<String, Canister>,
+    }
+
+    let response =
+        serde_json::from_slice::<Response>(data).context("failed to deserialize denylist")?;
+
+    let mut canisters = HashSet::new();
+    for (id, canister) in response.canisters {
+        if !canister.localities.unwrap_or_default().is_empty() {
+            continue;
+        }
+
+        let id = Principal::from_text(&id).with_context(|| format!("invalid canister id {id}"))?;
+        canisters.insert(id);
+    }
+
+    Ok(Denylist::new(canisters))
+}
+
+// Verifies the detached signature of the denylist, which is an RSASSA-PKCS1-v1_5
+// signature with SHA-256 over the denylist exactly as it is served, i.e. before decoding
+pub struct Verifier {
+    key: RsaPublicKey,
+}
+
+impl Verifier {
+    pub fn new(key: RsaPublicKey) -> Self {
+        Self { key }
+    }
+
+    pub fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let hashed = Sha256::digest(data);
+
+        self.key
+            .verify(
+                PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256)),
+                &hashed,
+                signature,
+            )
+            .map_err(|e| anyhow!("invalid denylist signature: {e}"))
+    }
+}
+
+#[async_trait]
+pub trait Fetch: Send + Sync {
+    async fn fetch(&self) -> Result<Denylist, Error>;
+}
+
+// Fetches the denylist from the given URL and its signature from the same URL with
+// a `.sig` suffix appended to the path. Nothing is published unless the signature is valid.
+pub struct Fetcher {
+    http_client: reqwest::Client,
+    decoder: Arc<dyn Decode>,
+    verifier: Verifier,
+    url: Url,
+    signature_url: Url,
+}
+
+impl Fetcher {
+    pub fn new(
+        http_client: reqwest::Client,
+        decoder: Arc<dyn Decode>,
+        verifier: Verifier,
+        url: Url,
+    ) -> Self {
+        let mut signature_url = url.clone();
+        signature_url.set_path(&format!("{}.sig", url.path()));
+
+        Self {
+            http_client,
+            decoder,
+            verifier,
+            url,
+            signature_url,
+        }
+    }
+
+    async fn get(&self, url: &Url) -> Result<Vec<u8>, Error> {
+        let response = self
+            .http_client
+            .get(url.clone())
+            .send()
+            .await
+            .context("request failed")?;
+
+        if response.status() != reqwest::StatusCode::OK {
+            return Err(anyhow!("request failed with status {}", response.status()));
+        }
+
+        Ok(response
+            .bytes()
+            .await
+            .context("failed to get response bytes")?
+            .to_vec())
+    }
+}
+
+#[async_trait]
+impl Fetch for Fetcher {
+    async fn fetch(&self) -> Result<Denylist, Error> {
+        let data = self
+            .get(&self.url)
+            .await
+            .context("failed to fetch denylist")?;
+
+        let signature = self
+            .get(&self.signature_url)
+            .await
+            .context("failed to fetch denylist signature")?;
+
+        self.verifier.verify(&data, &signature)?;
+
+        let data = self
+            .decoder
+            .decode(data)
+            .await
+            .context("failed to decode denylist")?;
+
+        parse_denylist(&data)
+    }
+}
+
+// Periodically fetches the denylist and publishes it for the middleware
+pub struct Runner<F: Fetch> {
+    fetcher: F,
+    published_denylist: Arc<ArcSwap<Denylist>>,
+}
+
+impl<F: Fetch> Runner<F> {
+    pub fn new(fetcher: F, published_denylist: Arc<ArcSwap<Denylist>>) -> Self {
+        Self {
+            fetcher,
+            published_denylist,
+        }
+    }
+}
+
+#[async_trait]
+impl<F: Fetch> Run for Runner<F> {
+    async fn run(&mut self) -> Result<(), Error> {
+        let denylist = self
+            .fetcher
+            .fetch()
+            .await
+            .context("failed to fetch denylist")?;
+
+        if *self.published_denylist.load_full() != denylist {
+            self.published_denylist.store(Arc::new(denylist));
+        }
+
+        Ok(())
+    }
+}
+
+// Rejects calls and queries to the denylisted canisters.
+// Must run after `preprocess_request` since it relies on the request context.
+pub async fn denylist_middleware(
+    State(denylist): State<Arc<ArcSwap<Denylist>>>,
+    Extension(mut ctx): Extension<RequestContext>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, Response> {
+    if matches!(ctx.request_type, RequestType::Query | RequestType::Call) {
+        let denylist = denylist.load();
+
+        // Check both the canister from the URL and the one from the content
+        // since they can differ, e.g. for calls to the management canister
+        let blocked = [ctx.canister_id, ctx.canister_id_cbor]
+            .iter()
+            .flatten()
+            .find(|x| denylist.is_blocked(x))
+            .copied();
+
+        if let Some(canister_id) = blocked {
+            // Audit log entry
+            warn!(
+                action = "denylist_block",
+                canister_id = canister_id.to_string(),
+                request_type = ctx.request_type.to_string(),
+                sender = ctx.sender.map(|x| x.to_string()),
+                method_name = ctx.method_name.clone(),
+            );
+
+            return Err(ctx.respond(ErrorCause::Denylisted));
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+pub mod test;
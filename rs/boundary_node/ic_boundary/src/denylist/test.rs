@@ -0,0 +1,181 @@
+use axum::{http::StatusCode, middleware, routing::post, Router};
+use denylist_updater::decode::NopDecoder;
+use rsa::RsaPrivateKey;
+use tower::ServiceExt;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use super::*;
+
+const CANISTER_1: &str = "sxiki-5ygae-aq";
+const CANISTER_2: &str = "f7crg-kabae";
+
+fn principal(p: &str) -> Principal {
+    Principal::from_text(p).unwrap()
+}
+
+#[test]
+fn test_parse_denylist() -> Result<(), Error> {
+    let denylist = parse_denylist(
+        format!(
+            r#"{{
+                "$schema": "./schema.json",
+                "version": "1",
+                "canisters": {{
+                    "{CANISTER_1}": {{}},
+                    "{CANISTER_2}": {{"localities": ["CH", "US"]}}
+                }}
+            }}"#
+        )
+        .as_bytes(),
+    )?;
+
+    // Geo-blocked canisters are skipped
+    assert_eq!(denylist.len(), 1);
+    assert!(denylist.is_blocked(&principal(CANISTER_1)));
+    assert!(!denylist.is_blocked(&principal(CANISTER_2)));
+
+    assert!(parse_denylist(br#"{"canisters": {"foobar": {}}}"#).is_err());
+
+    Ok(())
+}
+
+fn sign(key: &RsaPrivateKey, data: &[u8]) -> Vec<u8> {
+    key.sign(
+        PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256)),
+        &Sha256::digest(data),
+    )
+    .unwrap()
+}
+
+async fn fetch(data: &[u8], signature: Vec<u8>, key: &RsaPrivateKey) -> Result<Denylist, Error> {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/denylist.json"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(data))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/denylist.json.sig"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(signature))
+        .mount(&mock_server)
+        .await;
+
+    let fetcher = Fetcher::new(
+        reqwest::Client::new(),
+        Arc::new(NopDecoder),
+        Verifier::new(key.to_public_key()),
+        Url::parse(&format!("{}/denylist.json", mock_server.uri()))?,
+    );
+
+    fetcher.fetch().await
+}
+
+#[tokio::test]
+async fn test_fetcher_verifies_signature() -> Result<(), Error> {
+    let key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+    let data = format!(r#"{{"canisters": {{"{CANISTER_1}": {{}}}}}}"#).into_bytes();
+
+    let denylist = fetch(&data, sign(&key, &data), &key).await?;
+    assert!(denylist.is_blocked(&principal(CANISTER_1)));
+
+    // Signature over different data
+    let forged = format!(r#"{{"canisters": {{"{CANISTER_2}": {{}}}}}}"#).into_bytes();
+    assert!(fetch(&forged, sign(&key, &data), &key).await.is_err());
+
+    // Signature made with a different key
+    let other_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+    assert!(fetch(&data, sign(&other_key, &data), &key).await.is_err());
+
+    Ok(())
+}
+
+struct StaticFetcher(Denylist);
+
+#[async_trait]
+impl Fetch for StaticFetcher {
+    async fn fetch(&self) -> Result<Denylist, Error> {
+        Ok(self.0.clone())
+    }
+}
+
+#[tokio::test]
+async fn test_runner() -> Result<(), Error> {
+    let denylist = Denylist::new(HashSet::from([principal(CANISTER_1)]));
+    let published_denylist = Arc::new(ArcSwap::from_pointee(Denylist::default()));
+
+    let mut runner = Runner::new(
+        StaticFetcher(denylist.clone()),
+        Arc::clone(&published_denylist),
+    );
+    runner.run().await?;
+
+    assert_eq!(*published_denylist.load_full(), denylist);
+
+    Ok(())
+}
+
+async fn call(ctx: RequestContext) -> Result<Response, Error> {
+    let denylist = Denylist::new(HashSet::from([principal(CANISTER_1)]));
+
+    let router = Router::new()
+        .route(
+            "/api/v2/canister/:canister_id/:request_type",
+            post(|| async { "foobar" }),
+        )
+        .layer(middleware::from_fn_with_state(
+            Arc::new(ArcSwap::from_pointee(denylist)),
+            denylist_middleware,
+        ))
+        .layer(Extension(ctx));
+
+    let request = Request::post(format!("/api/v2/canister/{CANISTER_1}/query"))
+        .body(Body::empty())
+        .unwrap();
+
+    Ok(router.oneshot(request).await?)
+}
+
+#[tokio::test]
+async fn test_denylist_middleware() -> Result<(), Error> {
+    for (request_type, canister_id, status) in [
+        (
+            RequestType::Query,
+            CANISTER_1,
+            StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+        ),
+        (
+            RequestType::Call,
+            CANISTER_1,
+            StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+        ),
+        (RequestType::ReadState, CANISTER_1, StatusCode::OK),
+        (RequestType::Query, CANISTER_2, StatusCode::OK),
+    ] {
+        let ctx = RequestContext {
+            request_type,
+            canister_id: Some(principal(canister_id)),
+            ..Default::default()
+        };
+
+        let resp = call(ctx).await?;
+        assert_eq!(resp.status(), status);
+    }
+
+    // Canister id from the content is checked as well
+    let ctx = RequestContext {
+        request_type: RequestType::Call,
+        canister_id: Some(principal(CANISTER_2)),
+        canister_id_cbor: Some(principal(CANISTER_1)),
+        ..Default::default()
+    };
+
+    let resp = call(ctx).await?;
+    assert_eq!(resp.status(), StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+
+    Ok(())
+}
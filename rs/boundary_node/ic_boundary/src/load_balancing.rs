@@ -0,0 +1,175 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use candid::Principal;
+use dashmap::DashMap;
+use rand::{seq::index::sample, Rng};
+
+use crate::snapshot::Node;
+
+#[derive(Default)]
+struct NodeStats {
+    // Exponentially weighted moving average of the latency in seconds
+    latency: f64,
+    // Whether at least one sample was recorded
+    initialized: bool,
+}
+
+// Latency assumed for nodes when none of the candidates has been measured yet
+const DEFAULT_LATENCY: f64 = 1.0;
+
+// Adaptive load balancer that picks nodes based on the live traffic.
+// Each node is scored by its latency EWMA multiplied by the number of requests in flight,
+// and the better one out of two randomly chosen nodes is picked (power of two choices).
+pub struct LoadBalancer {
+    stats: DashMap<Principal, NodeStats>,
+    in_flight: DashMap<Principal, AtomicU64>,
+    // Weight of the newest sample in the EWMA, in 0..1 range
+    decay: f64,
+    // Latency recorded for a failed request
+    failure_penalty: Duration,
+}
+
+impl LoadBalancer {
+    pub fn new(decay: f64, failure_penalty: Duration) -> Self {
+        Self {
+            stats: DashMap::new(),
+            in_flight: DashMap::new(),
+            decay: decay.clamp(0.0, 1.0),
+            failure_penalty,
+        }
+    }
+
+    // Median latency of the measured nodes out of the given ones
+    fn median_latency(&self, nodes: &[&Node]) -> Option<f64> {
+        let mut latencies = nodes
+            .iter()
+            .filter_map(|x| self.stats.get(&x.id).map(|x| x.latency))
+            .collect::<Vec<_>>();
+
+        if latencies.is_empty() {
+            return None;
+        }
+
+        latencies.sort_by(f64::total_cmp);
+        Some(latencies[latencies.len() / 2])
+    }
+
+    fn score(&self, node_id: &Principal, prior: f64) -> f64 {
+        // Nodes without samples are assumed to be as fast as the typical node,
+        // so that the requests in flight to them still count
+        let latency = self.stats.get(node_id).map_or(prior, |x| x.latency);
+        let in_flight = self
+            .in_flight
+            .get(node_id)
+            .map_or(0, |x| x.load(Ordering::Relaxed));
+
+        latency * (in_flight + 1) as f64
+    }
+
+    // Picks a node out of the given ones skipping the excluded ones
+    pub fn pick<'a>(
+        &self,
+        nodes: &'a [Node],
+        exclude: &[Principal],
+        rng: &mut impl Rng,
+    ) -> Option<&'a Node> {
+        let candidates = nodes
+            .iter()
+            .filter(|x| !exclude.contains(&x.id))
+            .collect::<Vec<_>>();
+
+        match candidates.len() {
+            0 => None,
+            1 => Some(candidates[0]),
+            n => {
+                let idx = sample(rng, n, 2);
+                let (a, b) = (candidates[idx.index(0)], candidates[idx.index(1)]);
+
+                // Only needed if one of them has no samples yet
+                let prior = if self.stats.contains_key(&a.id) && self.stats.contains_key(&b.id) {
+                    0.0
+                } else {
+                    self.median_latency(&candidates).unwrap_or(DEFAULT_LATENCY)
+                };
+
+                if self.score(&b.id, prior) < self.score(&a.id, prior) {
+                    Some(b)
+                } else {
+                    Some(a)
+                }
+            }
+        }
+    }
+
+    // Marks the start of a request to the given node.
+    // The returned guard must be finished with the outcome of the request.
+    pub fn start(&self, node_id: Principal) -> InFlight<'_> {
+        self.in_flight
+            .entry(node_id)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+
+        InFlight {
+            balancer: self,
+            node_id,
+        }
+    }
+
+    fn record(&self, node_id: Principal, latency: Duration) {
+        let sample = latency.as_secs_f64();
+        let mut stats = self.stats.entry(node_id).or_default();
+
+        stats.latency = if stats.initialized {
+            self.decay * sample + (1.0 - self.decay) * stats.latency
+        } else {
+            sample
+        };
+        stats.initialized = true;
+    }
+
+    // Drops the state of the nodes that are no longer in the routing table.
+    // Counters of requests still in flight are kept until they finish.
+    pub fn retain(&self, node_ids: &HashSet<Principal>) {
+        self.stats.retain(|id, _| node_ids.contains(id));
+        self.in_flight
+            .retain(|id, x| node_ids.contains(id) || x.load(Ordering::Relaxed) > 0);
+    }
+
+    pub fn latency(&self, node_id: &Principal) -> Option<Duration> {
+        self.stats
+            .get(node_id)
+            .map(|x| Duration::from_secs_f64(x.latency))
+    }
+}
+
+// Tracks a single in-flight request
+pub struct InFlight<'a> {
+    balancer: &'a LoadBalancer,
+    node_id: Principal,
+}
+
+impl InFlight<'_> {
+    pub fn success(self, latency: Duration) {
+        self.balancer.record(self.node_id, latency);
+    }
+
+    pub fn failure(self) {
+        self.balancer
+            .record(self.node_id, self.balancer.failure_penalty);
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if let Some(x) = self.balancer.in_flight.get(&self.node_id) {
+            x.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test;
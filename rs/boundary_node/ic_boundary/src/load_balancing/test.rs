@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::*;
+use crate::persist::test::node;
+
+const SUBNET_1: &str = "tdb26-jop6k-aogll-7ltgs-eruif-6kk7m-qpktf-gdiqx-mxtrf-vb5e6-eqe";
+
+fn nodes(n: u64) -> Vec<Node> {
+    let subnet_id = Principal::from_text(SUBNET_1).unwrap();
+    (0..n).map(|i| node(i, subnet_id)).collect()
+}
+
+#[test]
+fn test_ewma() {
+    let lb = LoadBalancer::new(0.5, Duration::from_secs(10));
+    let nodes = nodes(1);
+    let id = nodes[0].id;
+
+    assert_eq!(lb.latency(&id), None);
+
+    // First sample is taken as is
+    lb.start(id).success(Duration::from_millis(250));
+    assert_eq!(lb.latency(&id), Some(Duration::from_millis(250)));
+
+    lb.start(id).success(Duration::from_millis(750));
+    assert_eq!(lb.latency(&id), Some(Duration::from_millis(500)));
+
+    // Failures are recorded with a penalty
+    lb.start(id).failure();
+    assert_eq!(lb.latency(&id), Some(Duration::from_millis(5250)));
+}
+
+#[test]
+fn test_pick_prefers_faster_nodes() {
+    let lb = LoadBalancer::new(1.0, Duration::from_secs(10));
+    let nodes = nodes(2);
+    let mut rng = StdRng::seed_from_u64(0);
+
+    lb.start(nodes[0].id).success(Duration::from_millis(500));
+    lb.start(nodes[1].id).success(Duration::from_millis(10));
+
+    // With two nodes both are always sampled, so the faster one wins
+    for _ in 0..100 {
+        assert_eq!(lb.pick(&nodes, &[], &mut rng), Some(&nodes[1]));
+    }
+}
+
+#[test]
+fn test_pick_accounts_for_in_flight() {
+    let lb = LoadBalancer::new(1.0, Duration::from_secs(10));
+    let nodes = nodes(2);
+    let mut rng = StdRng::seed_from_u64(0);
+
+    lb.start(nodes[0].id).success(Duration::from_millis(100));
+    lb.start(nodes[1].id).success(Duration::from_millis(40));
+
+    // Three requests in flight make the faster node more expensive
+    let in_flight = (0..3).map(|_| lb.start(nodes[1].id)).collect::<Vec<_>>();
+    assert_eq!(lb.pick(&nodes, &[], &mut rng), Some(&nodes[0]));
+
+    // Once they're done it's preferred again
+    drop(in_flight);
+    assert_eq!(lb.pick(&nodes, &[], &mut rng), Some(&nodes[1]));
+}
+
+#[test]
+fn test_pick_excluded() {
+    let lb = LoadBalancer::new(0.5, Duration::from_secs(10));
+    let nodes = nodes(3);
+    let mut rng = StdRng::seed_from_u64(0);
+
+    for _ in 0..100 {
+        let n = lb.pick(&nodes, &[nodes[0].id, nodes[1].id], &mut rng);
+        assert_eq!(n, Some(&nodes[2]));
+    }
+
+    let all = nodes.iter().map(|x| x.id).collect::<Vec<_>>();
+    assert_eq!(lb.pick(&nodes, &all, &mut rng), None);
+    assert_eq!(lb.pick(&[], &[], &mut rng), None);
+}
+
+#[test]
+fn test_pick_spreads_load() {
+    let lb = LoadBalancer::new(0.5, Duration::from_secs(10));
+    let nodes = nodes(5);
+    let mut rng = StdRng::seed_from_u64(0);
+
+    // Without any samples all nodes have equal chances
+    let mut picked = HashSet::new();
+    for _ in 0..100 {
+        picked.insert(lb.pick(&nodes, &[], &mut rng).unwrap().id);
+    }
+
+    assert_eq!(picked.len(), nodes.len());
+}
+
+#[test]
+fn test_pick_accounts_for_in_flight_to_unmeasured_nodes() {
+    let lb = LoadBalancer::new(1.0, Duration::from_secs(10));
+    let nodes = nodes(2);
+    let mut rng = StdRng::seed_from_u64(0);
+
+    // The second node has no samples, so it's assumed to be as fast as the first one
+    lb.start(nodes[0].id).success(Duration::from_millis(100));
+
+    let _in_flight = (0..3).map(|_| lb.start(nodes[1].id)).collect::<Vec<_>>();
+    for _ in 0..100 {
+        assert_eq!(lb.pick(&nodes, &[], &mut rng), Some(&nodes[0]));
+    }
+}
+
+#[test]
+fn test_retain() {
+    let lb = LoadBalancer::new(1.0, Duration::from_secs(10));
+    let nodes = nodes(3);
+
+    for n in nodes.iter() {
+        lb.start(n.id).success(Duration::from_millis(100));
+    }
+    let in_flight = lb.start(nodes[2].id);
+
+    lb.retain(&HashSet::from([nodes[0].id]));
+
+    assert!(lb.latency(&nodes[0].id).is_some());
+    assert!(lb.latency(&nodes[1].id).is_none());
+    assert!(lb.latency(&nodes[2].id).is_none());
+
+    // The counter of a request in flight survives until it's finished
+    assert!(!lb.in_flight.contains_key(&nodes[1].id));
+    assert!(lb.in_flight.contains_key(&nodes[2].id));
+    drop(in_flight);
+
+    lb.retain(&HashSet::from([nodes[0].id]));
+    assert!(!lb.in_flight.contains_key(&nodes[2].id));
+}
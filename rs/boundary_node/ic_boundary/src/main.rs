@@ -11,7 +11,7 @@ use std::{
 };
 
 use anyhow::{anyhow, Context, Error};
-use arc_swap::ArcSwapOption;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use async_scoped::TokioScope;
 use async_trait::async_trait;
 use axum::{
@@ -24,6 +24,7 @@ use axum::{
 use axum_server::{accept::DefaultAcceptor, Server};
 use clap::{Args, Parser};
 use configuration::{Configure, ServiceConfiguration};
+use denylist_updater::decode::{Decode, Decoder, NopDecoder};
 use futures::TryFutureExt;
 use http::{header::HeaderName, Request, Response};
 use hyper_rustls::ConfigBuilderExt;
@@ -45,6 +46,10 @@ use opentelemetry::{
 };
 use opentelemetry_prometheus::{ExporterBuilder, PrometheusExporter};
 use prometheus::{labels, Encoder as PrometheusEncoder, TextEncoder};
+use rsa::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    RsaPrivateKey, RsaPublicKey,
+};
 use tokio::sync::{Mutex, RwLock};
 use tower::ServiceBuilder;
 use tower_http::{
@@ -59,11 +64,18 @@ use url::Url;
 
 use crate::{
     acme::Acme,
+    cache::Cache,
     check::{Checker, Runner as CheckRunner},
     cli::Cli,
     configuration::{Configurator, FirewallConfigurator, TlsConfigurator, WithDeduplication},
+    denylist::{Denylist, Fetcher as DenylistFetcher, Verifier as DenylistVerifier},
+    load_balancing::LoadBalancer,
     metrics::{MetricParams, WithMetrics},
     nns::Loader,
+    rate_limiting::{
+        FileLoader as FileRulesLoader, LoadRules, RateLimiter,
+        RegistryLoader as RegistryRulesLoader,
+    },
     routes::{MiddlewareState, ProxyRouter},
     snapshot::{DnsResolver, Runner as SnapshotRunner, TlsVerifier},
 };
@@ -72,13 +84,17 @@ use crate::{
 use crate::tls::{CustomAcceptor, Provisioner, TokenSetter, WithLoad, WithStore};
 
 mod acme;
+mod cache;
 mod check;
 mod cli;
 mod configuration;
+mod denylist;
 mod firewall;
+mod load_balancing;
 mod metrics;
 mod nns;
 mod persist;
+mod rate_limiting;
 mod routes;
 mod snapshot;
 #[cfg(feature = "tls")]
@@ -221,12 +237,132 @@ async fn main() -> Result<(), Error> {
     let mut configuration_runner = configuration_runner;
 
     // Server / API
+    let load_balancer = Arc::new(LoadBalancer::new(
+        cli.load_balancing.lb_latency_decay,          // decay
+        Duration::from_secs(cli.listen.http_timeout), // failure_penalty
+    ));
+
     let proxy_router = Arc::new(ProxyRouter::new(
         Arc::clone(&http_client),
         Arc::clone(&lookup_table),
         nns_pub_key.into_bytes().into(),
+        Arc::clone(&load_balancer),
+        cli.load_balancing.lb_max_retries,
     ));
 
+    // Rate Limiting
+    let rate_limiter = Arc::new(RateLimiter::new(vec![]));
+
+    let rate_limiting_loader: Option<Box<dyn LoadRules>> =
+        if cli.rate_limiting.rate_limiting_rules_from_registry {
+            Some(Box::new(RegistryRulesLoader::new(registry_client.clone())))
+        } else {
+            cli.rate_limiting
+                .rate_limiting_rules_path
+                .clone()
+                .map(|path| Box::new(FileRulesLoader::new(path)) as Box<dyn LoadRules>)
+        };
+
+    let rate_limiting_runner = rate_limiting_loader.map(|loader| {
+        let runner = rate_limiting::Runner::new(
+            loader,                    // loader
+            Arc::clone(&rate_limiter), // limiter
+        );
+        let runner = WithMetrics(
+            runner,
+            MetricParams::new(&meter, SERVICE_NAME, "run_rate_limiting"),
+        );
+
+        WithThrottle(
+            runner,
+            ThrottleParams::new(Duration::from_secs(
+                cli.rate_limiting.rate_limiting_reload_interval,
+            )),
+        )
+    });
+
+    let rate_limiter = Arc::new(WithMetrics(
+        rate_limiter,
+        MetricParams::new(&meter, SERVICE_NAME, "rate_limit"),
+    ));
+
+    // Query Cache
+    let cache = cli.cache.cache_size_bytes.map(|cache_size| {
+        Arc::new(Cache::new(
+            cache_size,                                       // cache_size
+            cli.cache.cache_max_item_size_bytes,              // max_item_size
+            Duration::from_secs(cli.cache.cache_ttl_seconds), // ttl
+        ))
+    });
+
+    // Denylist
+    let denylist = match &cli.denylist.denylist_seed_path {
+        Some(path) => {
+            let data = std::fs::read(path).context("failed to read denylist seed")?;
+            denylist::parse_denylist(&data).context("failed to parse denylist seed")?
+        }
+        None => Denylist::default(),
+    };
+    let denylist = Arc::new(ArcSwap::from_pointee(denylist));
+
+    let denylist_runner = match &cli.denylist.denylist_url {
+        Some(url) => {
+            let decoder: Arc<dyn Decode> = match &cli.denylist.denylist_decryption_key_path {
+                Some(path) => {
+                    let pem = std::fs::read_to_string(path)
+                        .context("failed to read denylist decryption key")?;
+                    let key = RsaPrivateKey::from_pkcs8_pem(&pem)
+                        .context("failed to parse denylist decryption key")?;
+
+                    Arc::new(Decoder::new(key))
+                }
+                None => Arc::new(NopDecoder),
+            };
+
+            // Enforced by clap
+            let path = cli
+                .denylist
+                .denylist_verification_key_path
+                .as_ref()
+                .unwrap();
+            let pem = std::fs::read_to_string(path)
+                .context("failed to read denylist verification key")?;
+            let key = RsaPublicKey::from_public_key_pem(&pem)
+                .context("failed to parse denylist verification key")?;
+
+            let fetcher = DenylistFetcher::new(
+                reqwest::Client::builder()
+                    .timeout(Duration::from_secs(cli.listen.http_timeout))
+                    .build()
+                    .context("unable to build denylist HTTP client")?, // http_client
+                decoder,                    // decoder
+                DenylistVerifier::new(key), // verifier
+                url.clone(),                // url
+            );
+
+            let mut runner = denylist::Runner::new(fetcher, Arc::clone(&denylist));
+
+            // Without a seed, don't serve anything until the denylist is in place
+            if cli.denylist.denylist_seed_path.is_none() {
+                runner
+                    .run()
+                    .await
+                    .context("failed to load the initial denylist")?;
+            }
+
+            let runner = WithMetrics(
+                runner,
+                MetricParams::new(&meter, SERVICE_NAME, "run_denylist"),
+            );
+
+            Some(WithThrottle(
+                runner,
+                ThrottleParams::new(Duration::from_secs(cli.denylist.denylist_poll_interval)),
+            ))
+        }
+        None => None,
+    };
+
     let state = MiddlewareState {
         proxier: proxy_router,
         metric_params: metrics::HttpMetricParams::new(&meter, SERVICE_NAME, "http_request"),
@@ -251,6 +387,19 @@ async fn main() -> Result<(), Error> {
                 .layer(middleware::from_fn_with_state(
                     state.proxier.clone(),
                     routes::preprocess_request,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    denylist,
+                    denylist::denylist_middleware,
+                ))
+                // Rate limiting has to come before the cache, cache hits count against the limits too
+                .layer(middleware::from_fn_with_state(
+                    rate_limiter,
+                    routes::rate_limit,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    cache,
+                    cache::cache_middleware,
                 )),
         )
         .with_state(state);
@@ -287,7 +436,11 @@ async fn main() -> Result<(), Error> {
         });
 
     // Snapshots
-    let snapshot_runner = SnapshotRunner::new(Arc::clone(&routing_table), registry_client);
+    let snapshot_runner = SnapshotRunner::new(
+        Arc::clone(&routing_table),
+        registry_client,
+        Arc::clone(&load_balancer),
+    );
     let snapshot_runner = WithMetrics(
         snapshot_runner,
         MetricParams::new(&meter, SERVICE_NAME, "run_snapshot"),
@@ -327,12 +480,20 @@ async fn main() -> Result<(), Error> {
     let mut check_runner = check_runner;
 
     // Runners
-    let runners: Vec<Box<dyn Run>> = vec![
+    let mut runners: Vec<Box<dyn Run>> = vec![
         Box::new(configuration_runner),
         Box::new(snapshot_runner),
         Box::new(check_runner),
     ];
 
+    if let Some(r) = rate_limiting_runner {
+        runners.push(Box::new(r));
+    }
+
+    if let Some(r) = denylist_runner {
+        runners.push(Box::new(r));
+    }
+
     TokioScope::scope_and_block(|s| {
         s.spawn(
             axum::Server::bind(&cli.monitoring.metrics_addr)
@@ -10,6 +10,7 @@ use axum::{
     response::Response,
     Extension,
 };
+use candid::Principal;
 use http::{header, HeaderValue};
 use opentelemetry::{
     baggage::BaggageExt,
@@ -25,7 +26,8 @@ use tracing::{error, info, warn};
 use crate::{
     check::{Check, CheckError, CheckResult},
     persist::{Persist, PersistResults, PersistStatus},
-    routes::{MiddlewareState, RequestContext},
+    rate_limiting::{RateLimit, RateLimited},
+    routes::{MiddlewareState, RequestContext, RequestType},
     snapshot::{Node, RoutingTable},
 };
 
@@ -145,6 +147,31 @@ impl<T: Check> Check for WithMetrics<T> {
     }
 }
 
+impl<T: RateLimit> RateLimit for WithMetrics<T> {
+    fn acquire(
+        &self,
+        canister_id: Principal,
+        subnet_id: Principal,
+        request_type: RequestType,
+    ) -> Result<(), RateLimited> {
+        let out = self.0.acquire(canister_id, subnet_id, request_type);
+
+        let status = if out.is_ok() { "ok" } else { "rate_limited" };
+
+        let MetricParams { counter, .. } = &self.1;
+
+        let labels = &[
+            KeyValue::new("status", status),
+            KeyValue::new("request_type", request_type.to_string()),
+            KeyValue::new("subnet_id", subnet_id.to_string()),
+        ];
+
+        counter.add(&Context::current(), 1, labels);
+
+        out
+    }
+}
+
 // for http calls through axum we do axum middleware instead of WithMetrics
 pub async fn with_metrics_middleware(
     State(metric_params): State<HttpMetricParams>,
@@ -187,6 +214,7 @@ pub async fn with_metrics_middleware(
         KeyValue::new("subnet_id", subnet_id.clone().unwrap_or(String::from("-"))),
         KeyValue::new("node_id", node_id.clone().unwrap_or(String::from("-"))),
         KeyValue::new("error_cause", error_cause.clone()),
+        KeyValue::new("cache_status", routes_ctx.cache_status.to_string()),
         KeyValue::new(
             "is_anonymous",
             routes_ctx
@@ -235,6 +263,7 @@ pub async fn with_metrics_middleware(
         canister_id_cbor = routes_ctx.canister_id_cbor.map(|x| x.to_string()),
         sender,
         method_name = routes_ctx.method_name,
+        cache_status = routes_ctx.cache_status.to_string(),
         duration,
         request_size = routes_ctx.request_size,
         response_size,
@@ -0,0 +1,417 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Error};
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use candid::Principal;
+use ic_protobuf::{
+    registry::boundary_node::v1::{
+        RateLimitingRequestType, RateLimitingRule, RateLimitingRulesRecord,
+    },
+    types::v1::PrincipalId as PrincipalIdProto,
+};
+use ic_registry_client::client::RegistryClient;
+use ic_registry_client_helpers::deserialize_registry_value;
+use ic_registry_keys::make_boundary_node_rate_limiting_rules_key;
+use serde::Deserialize;
+
+use crate::{routes::RequestType, Run};
+
+// Number of buckets a rule keeps before the full ones are dropped.
+// A full bucket is identical to a freshly created one, so dropping it doesn't affect the limits.
+const MAX_BUCKETS_PER_RULE: usize = 100_000;
+
+// A single rate limiting rule.
+// Every field except the limit is an optional filter, a rule applies to
+// a request only if all of the specified filters match it.
+// Each rule keeps a separate token bucket for every (canister, subnet, request type)
+// it matches, so a noisy canister doesn't throttle the others.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Rule {
+    pub canister_id: Option<Principal>,
+    pub subnet_id: Option<Principal>,
+    pub request_type: Option<RequestType>,
+    // Sustained number of requests per second
+    pub limit_per_second: f64,
+    // Maximum number of requests that can be served in a burst, defaults to `limit_per_second`
+    pub burst: Option<f64>,
+}
+
+impl Rule {
+    fn matches(&self, canister_id: Principal, subnet_id: Principal, rt: RequestType) -> bool {
+        self.canister_id.map_or(true, |x| x == canister_id)
+            && self.subnet_id.map_or(true, |x| x == subnet_id)
+            && self.request_type.map_or(true, |x| x == rt)
+    }
+
+    fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.limit_per_second).max(1.0)
+    }
+}
+
+fn principal_from_proto(p: Option<PrincipalIdProto>) -> Result<Principal, Error> {
+    let p = p.ok_or_else(|| anyhow!("principal_id not set"))?;
+    Principal::try_from_slice(&p.raw).map_err(|e| anyhow!("invalid principal: {e}"))
+}
+
+impl TryFrom<RateLimitingRule> for Rule {
+    type Error = Error;
+
+    fn try_from(rule: RateLimitingRule) -> Result<Self, Self::Error> {
+        let request_type = match rule.request_type {
+            None => None,
+            Some(x) => Some(
+                match RateLimitingRequestType::from_i32(x)
+                    .ok_or_else(|| anyhow!("unknown request_type {x}"))?
+                {
+                    RateLimitingRequestType::Query => RequestType::Query,
+                    RateLimitingRequestType::Call => RequestType::Call,
+                    RateLimitingRequestType::ReadState => RequestType::ReadState,
+                    RateLimitingRequestType::Unspecified => {
+                        return Err(anyhow!("request_type unspecified"))
+                    }
+                },
+            ),
+        };
+
+        Ok(Self {
+            canister_id: rule
+                .canister_id
+                .map(|x| principal_from_proto(x.principal_id))
+                .transpose()
+                .context("invalid canister_id")?,
+            subnet_id: rule
+                .subnet_id
+                .map(|x| principal_from_proto(x.principal_id))
+                .transpose()
+                .context("invalid subnet_id")?,
+            request_type,
+            limit_per_second: rule.limit_per_second,
+            burst: rule.burst,
+        })
+    }
+}
+
+// Classic token bucket which is refilled continuously at `rate` tokens per second
+// and holds at most `capacity` tokens
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64, now: Instant) -> Self {
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    // Checks that there's a token in the bucket without taking it.
+    // If the bucket is empty returns the time after which a token becomes available,
+    // or None if the bucket is never refilled.
+    pub fn check(&mut self, now: Instant) -> Result<(), Option<Duration>> {
+        self.refill(now);
+
+        if self.tokens >= 1.0 {
+            return Ok(());
+        }
+
+        if self.rate <= 0.0 {
+            return Err(None);
+        }
+
+        Err(Some(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.rate,
+        )))
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity
+    }
+
+    // Takes one token out of the bucket, must be preceded by a successful `check`
+    fn consume(&mut self) {
+        self.tokens -= 1.0;
+    }
+
+    // Takes one token out of the bucket.
+    // If the bucket is empty returns the time after which a token becomes available,
+    // or None if the bucket is never refilled.
+    pub fn try_acquire(&mut self, now: Instant) -> Result<(), Option<Duration>> {
+        self.check(now)?;
+        self.consume();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimited {
+    // Index of the rule that rejected the request
+    pub rule: usize,
+    // None if the request will never be let through, i.e. the rule's limit is zero
+    pub retry_after: Option<Duration>,
+}
+
+// Trait that rate limiter should implement
+pub trait RateLimit: Send + Sync {
+    fn acquire(
+        &self,
+        canister_id: Principal,
+        subnet_id: Principal,
+        request_type: RequestType,
+    ) -> Result<(), RateLimited>;
+}
+
+impl<T: RateLimit> RateLimit for Arc<T> {
+    fn acquire(
+        &self,
+        canister_id: Principal,
+        subnet_id: Principal,
+        request_type: RequestType,
+    ) -> Result<(), RateLimited> {
+        self.as_ref().acquire(canister_id, subnet_id, request_type)
+    }
+}
+
+type BucketKey = (Principal, Principal, RequestType);
+
+struct Limits {
+    rules: Vec<Rule>,
+    buckets: Vec<Mutex<HashMap<BucketKey, TokenBucket>>>,
+}
+
+impl Limits {
+    fn new(rules: Vec<Rule>) -> Self {
+        let buckets = rules.iter().map(|_| Mutex::new(HashMap::new())).collect();
+
+        Self { rules, buckets }
+    }
+}
+
+// Rate limiter that checks requests against the currently applied set of rules.
+// Rules can be swapped at runtime, which resets all the buckets.
+pub struct RateLimiter {
+    limits: ArcSwap<Limits>,
+}
+
+impl RateLimiter {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            limits: ArcSwap::from_pointee(Limits::new(rules)),
+        }
+    }
+
+    pub fn rules(&self) -> Vec<Rule> {
+        self.limits.load().rules.clone()
+    }
+
+    pub fn apply(&self, rules: Vec<Rule>) {
+        self.limits.store(Arc::new(Limits::new(rules)));
+    }
+}
+
+impl RateLimiter {
+    fn acquire_at(
+        &self,
+        canister_id: Principal,
+        subnet_id: Principal,
+        request_type: RequestType,
+        now: Instant,
+    ) -> Result<(), RateLimited> {
+        let limits = self.limits.load();
+        let key = (canister_id, subnet_id, request_type);
+
+        // The request has to pass all the rules that match it.
+        // The rules are always locked in the same order, so this can't deadlock.
+        let mut matched = vec![];
+        for (idx, (rule, buckets)) in limits.rules.iter().zip(limits.buckets.iter()).enumerate() {
+            if !rule.matches(canister_id, subnet_id, request_type) {
+                continue;
+            }
+
+            let mut buckets = buckets.lock().unwrap();
+            if !buckets.contains_key(&key) && buckets.len() >= MAX_BUCKETS_PER_RULE {
+                buckets.retain(|_, b| {
+                    b.refill(now);
+                    !b.is_full()
+                });
+            }
+
+            buckets
+                .entry(key)
+                .or_insert_with(|| TokenBucket::new(rule.limit_per_second, rule.capacity(), now))
+                .check(now)
+                .map_err(|retry_after| RateLimited {
+                    rule: idx,
+                    retry_after,
+                })?;
+
+            matched.push(buckets);
+        }
+
+        // Tokens are only taken once all the rules have let the request through,
+        // so a rejected request doesn't eat into the budget of the other rules
+        for mut buckets in matched {
+            if let Some(bucket) = buckets.get_mut(&key) {
+                bucket.consume();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl RateLimit for RateLimiter {
+    fn acquire(
+        &self,
+        canister_id: Principal,
+        subnet_id: Principal,
+        request_type: RequestType,
+    ) -> Result<(), RateLimited> {
+        self.acquire_at(canister_id, subnet_id, request_type, Instant::now())
+    }
+}
+
+fn validate_rules(rules: &[Rule]) -> Result<(), Error> {
+    for (idx, rule) in rules.iter().enumerate() {
+        if !rule.limit_per_second.is_finite() || rule.limit_per_second < 0.0 {
+            return Err(anyhow!("rule {idx}: invalid limit_per_second"));
+        }
+    }
+
+    Ok(())
+}
+
+// Parses a JSON list of rules
+pub fn parse_rules(data: &[u8]) -> Result<Vec<Rule>, Error> {
+    let rules: Vec<Rule> = serde_json::from_slice(data).context("failed to parse rules")?;
+    validate_rules(&rules)?;
+
+    Ok(rules)
+}
+
+// Converts the rules stored in the registry
+pub fn rules_from_record(record: RateLimitingRulesRecord) -> Result<Vec<Rule>, Error> {
+    let rules = record
+        .rules
+        .into_iter()
+        .enumerate()
+        .map(|(idx, rule)| Rule::try_from(rule).with_context(|| format!("rule {idx}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    validate_rules(&rules)?;
+
+    Ok(rules)
+}
+
+#[async_trait]
+pub trait LoadRules: Send + Sync {
+    async fn load(&self) -> Result<Vec<Rule>, Error>;
+}
+
+#[async_trait]
+impl LoadRules for Box<dyn LoadRules> {
+    async fn load(&self) -> Result<Vec<Rule>, Error> {
+        self.as_ref().load().await
+    }
+}
+
+pub struct FileLoader {
+    path: PathBuf,
+}
+
+impl FileLoader {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl LoadRules for FileLoader {
+    async fn load(&self) -> Result<Vec<Rule>, Error> {
+        let data = fs::read(&self.path)
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+
+        parse_rules(&data)
+    }
+}
+
+// Loads the rules from the `RateLimitingRulesRecord` in the registry.
+// No record in the registry means no rate limiting.
+pub struct RegistryLoader {
+    registry_client: Arc<dyn RegistryClient>,
+}
+
+impl RegistryLoader {
+    pub fn new(registry_client: Arc<dyn RegistryClient>) -> Self {
+        Self { registry_client }
+    }
+}
+
+#[async_trait]
+impl LoadRules for RegistryLoader {
+    async fn load(&self) -> Result<Vec<Rule>, Error> {
+        let version = self.registry_client.get_latest_version();
+
+        let record = deserialize_registry_value::<RateLimitingRulesRecord>(
+            self.registry_client
+                .get_value(&make_boundary_node_rate_limiting_rules_key(), version),
+        )
+        .context("failed to get rate limiting rules from the registry")?;
+
+        match record {
+            Some(record) => rules_from_record(record),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+// Periodically reloads the rules and applies them if they've changed
+pub struct Runner<L: LoadRules> {
+    loader: L,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<L: LoadRules> Runner<L> {
+    pub fn new(loader: L, limiter: Arc<RateLimiter>) -> Self {
+        Self { loader, limiter }
+    }
+}
+
+#[async_trait]
+impl<L: LoadRules> Run for Runner<L> {
+    async fn run(&mut self) -> Result<(), Error> {
+        let rules = self
+            .loader
+            .load()
+            .await
+            .context("failed to load rate limiting rules")?;
+
+        // Don't reset the buckets if nothing has changed
+        if rules != self.limiter.rules() {
+            self.limiter.apply(rules);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test;
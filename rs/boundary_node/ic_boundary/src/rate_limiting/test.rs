@@ -0,0 +1,315 @@
+use super::*;
+
+use ic_protobuf::types::v1::CanisterId as CanisterIdProto;
+use ic_registry_client_fake::FakeRegistryClient;
+use ic_registry_proto_data_provider::ProtoRegistryDataProvider;
+use ic_types::RegistryVersion;
+
+const CANISTER_1: &str = "sxiki-5ygae-aq";
+const CANISTER_2: &str = "f7crg-kabae";
+const SUBNET_1: &str = "tdb26-jop6k-aogll-7ltgs-eruif-6kk7m-qpktf-gdiqx-mxtrf-vb5e6-eqe";
+
+fn principal(p: &str) -> Principal {
+    Principal::from_text(p).unwrap()
+}
+
+#[test]
+fn test_token_bucket() {
+    let now = Instant::now();
+    let mut b = TokenBucket::new(10.0, 2.0, now);
+
+    // Burst is served
+    assert!(b.try_acquire(now).is_ok());
+    assert!(b.try_acquire(now).is_ok());
+
+    // Bucket is empty, one token is refilled in 100ms
+    assert_eq!(b.try_acquire(now), Err(Some(Duration::from_millis(100))));
+
+    // Refilled
+    let now = now + Duration::from_millis(100);
+    assert!(b.try_acquire(now).is_ok());
+    assert!(b.try_acquire(now).is_err());
+
+    // Refill never exceeds the capacity
+    let now = now + Duration::from_secs(60);
+    assert!(b.try_acquire(now).is_ok());
+    assert!(b.try_acquire(now).is_ok());
+    assert!(b.try_acquire(now).is_err());
+}
+
+#[test]
+fn test_token_bucket_zero_rate() {
+    let now = Instant::now();
+    let mut b = TokenBucket::new(0.0, 1.0, now);
+
+    assert!(b.try_acquire(now).is_ok());
+    // Never refilled
+    assert_eq!(b.try_acquire(now), Err(None));
+}
+
+#[test]
+fn test_rule_matching() {
+    let rule = Rule {
+        canister_id: Some(principal(CANISTER_1)),
+        subnet_id: None,
+        request_type: Some(RequestType::Call),
+        limit_per_second: 1.0,
+        burst: None,
+    };
+
+    assert!(rule.matches(
+        principal(CANISTER_1),
+        principal(SUBNET_1),
+        RequestType::Call
+    ));
+    assert!(!rule.matches(
+        principal(CANISTER_1),
+        principal(SUBNET_1),
+        RequestType::Query
+    ));
+    assert!(!rule.matches(
+        principal(CANISTER_2),
+        principal(SUBNET_1),
+        RequestType::Call
+    ));
+}
+
+#[test]
+fn test_rate_limiter() {
+    let limiter = RateLimiter::new(vec![
+        // Subnet-wide limit
+        Rule {
+            canister_id: None,
+            subnet_id: Some(principal(SUBNET_1)),
+            request_type: None,
+            limit_per_second: 0.0,
+            burst: Some(3.0),
+        },
+        // Stricter limit for update calls to a single canister
+        Rule {
+            canister_id: Some(principal(CANISTER_1)),
+            subnet_id: None,
+            request_type: Some(RequestType::Call),
+            limit_per_second: 1.0,
+            burst: Some(1.0),
+        },
+    ]);
+
+    let (c1, c2, s1) = (
+        principal(CANISTER_1),
+        principal(CANISTER_2),
+        principal(SUBNET_1),
+    );
+
+    let now = Instant::now();
+    assert!(limiter.acquire_at(c1, s1, RequestType::Call, now).is_ok());
+
+    // Rejected by the per-canister rule
+    let err = limiter
+        .acquire_at(c1, s1, RequestType::Call, now)
+        .unwrap_err();
+    assert_eq!(err.rule, 1);
+    assert_eq!(err.retry_after, Some(Duration::from_secs(1)));
+    assert!(limiter.acquire_at(c1, s1, RequestType::Call, now).is_err());
+
+    // The requests rejected above didn't take tokens from the subnet bucket
+    let now = now + Duration::from_secs(1);
+    assert!(limiter.acquire_at(c1, s1, RequestType::Call, now).is_ok());
+    let now = now + Duration::from_secs(1);
+    assert!(limiter.acquire_at(c1, s1, RequestType::Call, now).is_ok());
+
+    // Subnet bucket of this canister is now exhausted and never refilled
+    let now = now + Duration::from_secs(1);
+    let err = limiter
+        .acquire_at(c1, s1, RequestType::Call, now)
+        .unwrap_err();
+    assert_eq!(err.rule, 0);
+    assert_eq!(err.retry_after, None);
+
+    // Other canisters and request types have their own buckets
+    assert!(limiter.acquire_at(c2, s1, RequestType::Call, now).is_ok());
+    assert!(limiter.acquire_at(c1, s1, RequestType::Query, now).is_ok());
+
+    // Applying rules resets the buckets
+    limiter.apply(limiter.rules());
+    assert!(limiter.acquire_at(c1, s1, RequestType::Call, now).is_ok());
+}
+
+#[test]
+fn test_rate_limiter_drops_full_buckets() {
+    let limiter = RateLimiter::new(vec![Rule {
+        canister_id: None,
+        subnet_id: None,
+        request_type: None,
+        limit_per_second: 1.0,
+        burst: Some(1.0),
+    }]);
+
+    let s1 = principal(SUBNET_1);
+    let now = Instant::now();
+    for i in 0..MAX_BUCKETS_PER_RULE as u64 {
+        let canister_id = Principal::from_slice(&i.to_be_bytes());
+        assert!(limiter
+            .acquire_at(canister_id, s1, RequestType::Query, now)
+            .is_ok());
+    }
+
+    // All the buckets are empty, so nothing can be dropped yet
+    let c1 = principal(CANISTER_1);
+    assert!(limiter.acquire_at(c1, s1, RequestType::Query, now).is_ok());
+    assert_eq!(
+        limiter.limits.load().buckets[0].lock().unwrap().len(),
+        MAX_BUCKETS_PER_RULE + 1
+    );
+
+    // Once refilled, the full buckets are dropped to make room for a new one
+    let now = now + Duration::from_secs(1);
+    let c2 = principal(CANISTER_2);
+    assert!(limiter.acquire_at(c2, s1, RequestType::Query, now).is_ok());
+    assert_eq!(limiter.limits.load().buckets[0].lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_parse_rules() -> Result<(), Error> {
+    let rules = parse_rules(
+        format!(
+            r#"[
+                {{"canister_id": "{CANISTER_1}", "request_type": "read_state", "limit_per_second": 5}},
+                {{"subnet_id": "{SUBNET_1}", "limit_per_second": 100, "burst": 200}}
+            ]"#
+        )
+        .as_bytes(),
+    )?;
+
+    assert_eq!(
+        rules,
+        vec![
+            Rule {
+                canister_id: Some(principal(CANISTER_1)),
+                subnet_id: None,
+                request_type: Some(RequestType::ReadState),
+                limit_per_second: 5.0,
+                burst: None,
+            },
+            Rule {
+                canister_id: None,
+                subnet_id: Some(principal(SUBNET_1)),
+                request_type: None,
+                limit_per_second: 100.0,
+                burst: Some(200.0),
+            },
+        ]
+    );
+
+    assert!(parse_rules(br#"[{"limit_per_second": -1}]"#).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_loader() -> Result<(), Error> {
+    let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+    let registry_client = Arc::new(FakeRegistryClient::new(data_provider.clone()));
+    let loader = RegistryLoader::new(registry_client.clone());
+
+    // No record means no rules
+    registry_client.update_to_latest_version();
+    assert_eq!(loader.load().await?, vec![]);
+
+    data_provider.add(
+        &make_boundary_node_rate_limiting_rules_key(),
+        RegistryVersion::from(1),
+        Some(RateLimitingRulesRecord {
+            rules: vec![
+                RateLimitingRule {
+                    canister_id: Some(CanisterIdProto {
+                        principal_id: Some(PrincipalIdProto {
+                            raw: principal(CANISTER_1).as_slice().to_vec(),
+                        }),
+                    }),
+                    subnet_id: None,
+                    request_type: Some(RateLimitingRequestType::Call as i32),
+                    limit_per_second: 5.0,
+                    burst: None,
+                },
+                RateLimitingRule {
+                    canister_id: None,
+                    subnet_id: None,
+                    request_type: None,
+                    limit_per_second: 10.0,
+                    burst: Some(20.0),
+                },
+            ],
+        }),
+    )?;
+    registry_client.update_to_latest_version();
+
+    assert_eq!(
+        loader.load().await?,
+        vec![
+            Rule {
+                canister_id: Some(principal(CANISTER_1)),
+                subnet_id: None,
+                request_type: Some(RequestType::Call),
+                limit_per_second: 5.0,
+                burst: None,
+            },
+            Rule {
+                canister_id: None,
+                subnet_id: None,
+                request_type: None,
+                limit_per_second: 10.0,
+                burst: Some(20.0),
+            }
+        ]
+    );
+
+    // Invalid records are refused
+    assert!(rules_from_record(RateLimitingRulesRecord {
+        rules: vec![RateLimitingRule {
+            canister_id: None,
+            subnet_id: None,
+            request_type: Some(RateLimitingRequestType::Unspecified as i32),
+            limit_per_second: 1.0,
+            burst: None,
+        }],
+    })
+    .is_err());
+
+    Ok(())
+}
+
+struct StaticLoader(Vec<Rule>);
+
+#[async_trait]
+impl LoadRules for StaticLoader {
+    async fn load(&self) -> Result<Vec<Rule>, Error> {
+        Ok(self.0.clone())
+    }
+}
+
+#[tokio::test]
+async fn test_runner() -> Result<(), Error> {
+    let rules = vec![Rule {
+        canister_id: None,
+        subnet_id: None,
+        request_type: None,
+        limit_per_second: 0.0,
+        burst: Some(1.0),
+    }];
+
+    let limiter = Arc::new(RateLimiter::new(vec![]));
+    let mut runner = Runner::new(StaticLoader(rules.clone()), Arc::clone(&limiter));
+
+    runner.run().await?;
+    assert_eq!(limiter.rules(), rules);
+
+    let (c1, s1) = (principal(CANISTER_1), principal(SUBNET_1));
+    assert!(limiter.acquire(c1, s1, RequestType::Query).is_ok());
+
+    // Reloading identical rules must not reset the buckets
+    runner.run().await?;
+    assert!(limiter.acquire(c1, s1, RequestType::Query).is_err());
+
+    Ok(())
+}
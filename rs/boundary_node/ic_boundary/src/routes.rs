@@ -1,4 +1,9 @@
-use std::{fmt, io::Read, sync::Arc, time::Instant};
+use std::{
+    fmt,
+    io::Read,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Context, Error};
 use arc_swap::ArcSwapOption;
@@ -15,7 +20,7 @@ use axum::{
 use bytes::Buf;
 use candid::Principal;
 use futures_util::{StreamExt, TryFutureExt};
-use http::{header, request::Parts, HeaderValue};
+use http::{header, request::Parts, HeaderMap, HeaderValue};
 use ic_types::{
     messages::{
         Blob, HttpQueryContent, HttpRequestEnvelope, HttpStatusResponse, HttpUserQuery,
@@ -23,7 +28,6 @@ use ic_types::{
     },
     CanisterId,
 };
-use rand::seq::SliceRandom;
 use reqwest::Response as ReqwestResponse;
 use serde::{Deserialize, Serialize};
 use serde_cbor::Value as CborValue;
@@ -31,10 +35,14 @@ use tokio::sync::RwLock;
 use tower_http::request_id::{MakeRequestId, RequestId};
 use tracing::{error, info};
 
-use crate::{metrics::HttpMetricParams, persist::Routes, snapshot::Node};
+use crate::{
+    cache::CacheStatus, load_balancing::LoadBalancer, metrics::HttpMetricParams, persist::Routes,
+    rate_limiting::RateLimit, snapshot::Node,
+};
 
 // Type of IC request
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RequestType {
     #[default]
     Status,
@@ -54,6 +62,18 @@ impl fmt::Display for RequestType {
     }
 }
 
+impl RequestType {
+    // Infers the request type from the last segment of the API path
+    pub fn from_path(path: &str) -> Self {
+        match path.rsplit('/').next() {
+            Some("query") => Self::Query,
+            Some("call") => Self::Call,
+            Some("read_state") => Self::ReadState,
+            _ => Self::Status,
+        }
+    }
+}
+
 // Categorized possible causes for request processing failures
 // Use String and not Error since it's not cloneable
 #[derive(Default, Clone)]
@@ -68,6 +88,9 @@ pub enum ErrorCause {
     NoHealthyNodes,
     ReplicaUnreachable(String),
     RouteNotFound,
+    RateLimited(Duration),
+    Forbidden,
+    Denylisted,
     Other(String),
 }
 
@@ -84,6 +107,9 @@ impl ErrorCause {
             Self::NoHealthyNodes => StatusCode::INTERNAL_SERVER_ERROR,
             Self::ReplicaUnreachable(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::RouteNotFound => StatusCode::NOT_FOUND,
+            Self::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::Denylisted => StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
         }
     }
 
@@ -111,6 +137,9 @@ impl fmt::Display for ErrorCause {
             Self::NoHealthyNodes => write!(f, "no_healthy_nodes"),
             Self::ReplicaUnreachable(_) => write!(f, "replica_unreachable"),
             Self::RouteNotFound => write!(f, "not_found"),
+            Self::RateLimited(_) => write!(f, "rate_limited"),
+            Self::Forbidden => write!(f, "forbidden"),
+            Self::Denylisted => write!(f, "denylisted"),
         }
     }
 }
@@ -123,15 +152,29 @@ pub struct RequestContext {
     pub node: Option<Node>,
     pub sender: Option<Principal>,
     pub method_name: Option<String>,
+    pub arg: Option<Vec<u8>>,
+    pub ingress_expiry: Option<u64>,
     pub request_type: RequestType,
     pub error_cause: ErrorCause,
     pub request_size: u32,
+    pub cache_status: CacheStatus,
 }
 
 impl RequestContext {
-    fn respond(&mut self, cause: ErrorCause) -> Response {
+    pub fn respond(&mut self, cause: ErrorCause) -> Response {
         self.error_cause = cause.clone();
-        (Extension(self.clone()), cause.status_code()).into_response()
+        let mut resp = (Extension(self.clone()), cause.status_code()).into_response();
+
+        // Tell the client when to retry, rounding up to whole seconds
+        if let ErrorCause::RateLimited(retry_after) = cause {
+            let secs = retry_after
+                .as_secs()
+                .saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+            resp.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+        }
+
+        resp
     }
 }
 
@@ -141,6 +184,8 @@ struct ICRequestContent {
     sender: Principal,
     canister_id: Option<Principal>,
     method_name: Option<String>,
+    arg: Option<Blob>,
+    ingress_expiry: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -173,6 +218,8 @@ pub struct ProxyRouter {
     http_client: Arc<reqwest::Client>,
     published_routes: Arc<ArcSwapOption<Routes>>,
     root_key: Vec<u8>,
+    load_balancer: Arc<LoadBalancer>,
+    max_retries: u32,
 }
 
 impl ProxyRouter {
@@ -180,22 +227,48 @@ impl ProxyRouter {
         http_client: Arc<reqwest::Client>,
         published_routes: Arc<ArcSwapOption<Routes>>,
         root_key: Vec<u8>,
+        load_balancer: Arc<LoadBalancer>,
+        max_retries: u32,
     ) -> Self {
         Self {
             http_client,
             published_routes,
             root_key,
+            load_balancer,
+            max_retries,
         }
     }
-}
 
-#[async_trait]
-impl Proxier for ProxyRouter {
-    async fn proxy(
+    // Looks up a node in the canister's subnet skipping the excluded ones
+    fn lookup_node_excluding(
+        &self,
+        canister_id: Principal,
+        exclude: &[Principal],
+    ) -> Result<Node, ErrorCause> {
+        let subnet = self
+            .published_routes
+            .load_full()
+            .ok_or(ErrorCause::NoRoutingTable)? // No routing table present
+            .lookup(canister_id)
+            .ok_or(ErrorCause::SubnetNotFound)?; // Requested canister route wasn't found
+
+        // Pick a node using the load balancer
+        let node = self
+            .load_balancer
+            .pick(&subnet.nodes, exclude, &mut rand::thread_rng())
+            .ok_or(ErrorCause::NoHealthyNodes)? // No healhy nodes in subnet
+            .clone();
+
+        Ok(node)
+    }
+
+    // Sends a single request to the given node and tracks its outcome
+    async fn send(
         &self,
         request_type: RequestType,
-        request: Request<Body>,
-        node: Node,
+        headers: &HeaderMap,
+        body: Bytes,
+        node: &Node,
         canister_id: Principal,
     ) -> Result<Response, ErrorCause> {
         // Prepare the request
@@ -204,22 +277,33 @@ impl Proxier for ProxyRouter {
             node.id, node.port,
         );
 
-        let (parts, body) = request.into_parts();
-
         let request = self
             .http_client
             .post(url)
-            .headers(parts.headers)
+            .headers(headers.clone())
             .body(body)
             .build()
             .map_err(|e| ErrorCause::Other(format!("Unable to build request: {e}")))?; // TODO can this even fail?
 
         // Send the request
-        let response = self
-            .http_client
-            .execute(request)
-            .await
-            .map_err(|e| ErrorCause::ReplicaUnreachable(format!("HTTP call failed: {e}")))?;
+        let in_flight = self.load_balancer.start(node.id);
+        let start_time = Instant::now();
+
+        let response = match self.http_client.execute(request).await {
+            Ok(v) => v,
+            Err(e) => {
+                in_flight.failure();
+                return Err(ErrorCause::ReplicaUnreachable(format!(
+                    "HTTP call failed: {e}"
+                )));
+            }
+        };
+
+        if response.status().is_server_error() {
+            in_flight.failure();
+        } else {
+            in_flight.success(start_time.elapsed());
+        }
 
         // Convert Reqwest response into Axum one with body streaming
         let status = response.status();
@@ -231,23 +315,74 @@ impl Proxier for ProxyRouter {
 
         Ok(response)
     }
+}
 
-    fn lookup_node(&self, canister_id: Principal) -> Result<Node, ErrorCause> {
-        let subnet = self
-            .published_routes
-            .load_full()
-            .ok_or(ErrorCause::NoRoutingTable)? // No routing table present
-            .lookup(canister_id)
-            .ok_or(ErrorCause::SubnetNotFound)?; // Requested canister route wasn't found
+#[async_trait]
+impl Proxier for ProxyRouter {
+    async fn proxy(
+        &self,
+        request_type: RequestType,
+        request: Request<Body>,
+        node: Node,
+        canister_id: Principal,
+    ) -> Result<Response, ErrorCause> {
+        let (parts, body) = request.into_parts();
 
-        // Pick random node
-        let node = subnet
-            .nodes
-            .choose(&mut rand::thread_rng())
-            .ok_or(ErrorCause::NoHealthyNodes)? // No healhy nodes in subnet
-            .clone();
+        // Buffer the body to be able to resend it, it's already in memory after preprocessing
+        let body = hyper::body::to_bytes(body)
+            .await
+            .map_err(|_| ErrorCause::UnableToReadBody)?;
+
+        // Only the requests that don't change the state are safe to retry
+        let max_retries = match request_type {
+            RequestType::Query | RequestType::ReadState => self.max_retries as usize,
+            _ => 0,
+        };
+
+        let mut node = node;
+        let mut tried = vec![];
+
+        loop {
+            tried.push(node.id);
+
+            let out = self
+                .send(
+                    request_type,
+                    &parts.headers,
+                    body.clone(),
+                    &node,
+                    canister_id,
+                )
+                .await;
+
+            let retry = match &out {
+                Ok(response) => response.status().is_server_error(),
+                Err(ErrorCause::ReplicaUnreachable(_)) => true,
+                Err(_) => false,
+            };
+
+            // Try another node in the same subnet if there's one left
+            let next_node = if retry && tried.len() <= max_retries {
+                self.lookup_node_excluding(canister_id, &tried).ok()
+            } else {
+                None
+            };
+
+            match next_node {
+                Some(n) => node = n,
+                None => {
+                    // Let the handler know which node has served the request
+                    return out.map(|mut response| {
+                        response.extensions_mut().insert(node);
+                        response
+                    });
+                }
+            }
+        }
+    }
 
-        Ok(node)
+    fn lookup_node(&self, canister_id: Principal) -> Result<Node, ErrorCause> {
+        self.lookup_node_excluding(canister_id, &[])
     }
 
     fn health(&self) -> ReplicaHealthStatus {
@@ -334,6 +469,8 @@ pub fn parse_body(ctx: &mut RequestContext, body: &[u8]) -> Result<(), Error> {
     ctx.sender = Some(content.sender);
     ctx.canister_id_cbor = content.canister_id;
     ctx.method_name = content.method_name;
+    ctx.arg = content.arg.map(|x| x.0);
+    ctx.ingress_expiry = content.ingress_expiry;
 
     Ok(())
 }
@@ -345,7 +482,10 @@ pub async fn preprocess_request(
     mut request: Request<Body>,
     next: Next<Body>,
 ) -> Result<Response, Response> {
-    let mut ctx = RequestContext::default();
+    let mut ctx = RequestContext {
+        request_type: RequestType::from_path(request.uri().path()),
+        ..Default::default()
+    };
 
     // Consume body
     let (mut parts, body) = read_body(request).await.map_err(|e| ctx.respond(e))?;
@@ -379,6 +519,31 @@ pub async fn preprocess_request(
     Ok(resp)
 }
 
+// Throttles requests according to the rate limiting rules.
+// Must run after `preprocess_request` since it relies on the request context.
+pub async fn rate_limit(
+    State(limiter): State<Arc<impl RateLimit>>,
+    Extension(mut ctx): Extension<RequestContext>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, Response> {
+    // These will be Some() if we got here, otherwise preprocessing would refuse request earlier
+    let canister_id = ctx.canister_id.unwrap();
+    let subnet_id = ctx.node.as_ref().unwrap().subnet_id;
+
+    // A rule with a zero limit never lets the request through, so there's no point in retrying
+    limiter
+        .acquire(canister_id, subnet_id, ctx.request_type)
+        .map_err(|e| {
+            ctx.respond(match e.retry_after {
+                Some(retry_after) => ErrorCause::RateLimited(retry_after),
+                None => ErrorCause::Forbidden,
+            })
+        })?;
+
+    Ok(next.run(request).await)
+}
+
 // Handles IC status call
 pub async fn status(State(state): State<Arc<impl Proxier>>) -> Response {
     let response = HttpStatusResponse {
@@ -412,6 +577,11 @@ pub async fn query(
         .await
         .map_err(|e| ctx.respond(e))?;
 
+    // The request might have been retried on another node
+    if let Some(node) = resp.extensions().get::<Node>() {
+        ctx.node = Some(node.clone());
+    }
+
     // Inject context into response
     resp.extensions_mut().insert(ctx);
 
@@ -458,6 +628,11 @@ pub async fn read_state(
         .await
         .map_err(|e| ctx.respond(e))?;
 
+    // The request might have been retried on another node
+    if let Some(node) = resp.extensions().get::<Node>() {
+        ctx.node = Some(node.clone());
+    }
+
     // Inject context into response
     resp.extensions_mut().insert(ctx);
 
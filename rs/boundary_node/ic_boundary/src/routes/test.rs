@@ -110,3 +110,29 @@ async fn test_query() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_rate_limited_response() {
+    let mut ctx = RequestContext::default();
+
+    let resp = ctx.respond(ErrorCause::RateLimited(Duration::from_millis(1500)));
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(
+        resp.headers().get(header::RETRY_AFTER),
+        Some(&HeaderValue::from_static("2"))
+    );
+
+    // Requests that will never be let through are refused without a Retry-After
+    let resp = ctx.respond(ErrorCause::Forbidden);
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(resp.headers().get(header::RETRY_AFTER), None);
+
+    assert_eq!(
+        RequestType::from_path("/api/v2/canister/sxiki-5ygae-aq/read_state"),
+        RequestType::ReadState
+    );
+    assert_eq!(
+        RequestType::from_path("/api/v2/status"),
+        RequestType::Status
+    );
+}
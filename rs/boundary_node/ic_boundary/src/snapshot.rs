@@ -29,7 +29,7 @@ use rustls::{
 use tracing::warn;
 use x509_parser::{certificate::X509Certificate, prelude::FromDer, time::ASN1Time};
 
-use crate::Run;
+use crate::{load_balancing::LoadBalancer, Run};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Node {
@@ -80,16 +80,19 @@ pub struct RoutingTable {
 pub struct Runner {
     published_routing_table: Arc<ArcSwapOption<RoutingTable>>,
     registry_client: Arc<dyn RegistryClient>,
+    load_balancer: Arc<LoadBalancer>,
 }
 
 impl Runner {
     pub fn new(
         published_routing_table: Arc<ArcSwapOption<RoutingTable>>,
         registry_client: Arc<dyn RegistryClient>,
+        load_balancer: Arc<LoadBalancer>,
     ) -> Self {
         Self {
             published_routing_table,
             registry_client,
+            load_balancer,
         }
     }
 
@@ -228,6 +231,11 @@ impl Run for Runner {
     async fn run(&mut self) -> Result<(), Error> {
         // Obtain routing table & publish it
         let rt = self.get_routing_table()?;
+
+        // Forget the nodes that have left the routing table
+        let node_ids = rt.nodes.values().map(|x| x.id).collect();
+        self.load_balancer.retain(&node_ids);
+
         self.published_routing_table.store(Some(Arc::new(rt)));
         Ok(())
     }
@@ -3,7 +3,7 @@ use super::*;
 use std::{
     collections::HashMap,
     net::{IpAddr, Ipv4Addr},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
 use ic_crypto_test_utils_keys::public_keys::valid_tls_certificate_and_validation_time;
@@ -24,6 +24,10 @@ use ic_test_utilities_registry::test_subnet_record;
 use ic_types::{CanisterId, RegistryVersion, ReplicaVersion, SubnetId};
 use rustls::{server::DnsName, Certificate, ServerName};
 
+fn load_balancer() -> Arc<LoadBalancer> {
+    Arc::new(LoadBalancer::new(0.5, Duration::from_secs(10)))
+}
+
 // CN = s52il-lowsg-eip4y-pt5lv-sbdpb-vg4gg-4iasu-egajp-yluji-znfz3-2qe
 const TEST_CERTIFICATE: &str = "3082015530820107a00302010202136abf05c1260364e09ad5f4ad0e9cb90a6e0edb300506032b6570304a3148304606035504030c3f733532696c2d6c6f7773672d\
                                 65697034792d7074356c762d73626470622d76673467672d34696173752d6567616a702d796c756a692d7a6e667a332d3271653020170d3232313131343135303230\
@@ -161,7 +165,7 @@ fn create_nodes() -> Vec<(&'static str, IpAddr, u16)> {
 async fn test_routing_table() -> Result<(), Error> {
     let rt = Arc::new(ArcSwapOption::empty());
     let reg = Arc::new(create_fake_registry_client(4));
-    let mut runner = Runner::new(Arc::clone(&rt), reg);
+    let mut runner = Runner::new(Arc::clone(&rt), reg, load_balancer());
     runner.run().await?;
     let rt = rt.load_full().unwrap();
 
@@ -241,7 +245,7 @@ fn check_certificate_verification(
 async fn test_verify_tls_certificate() -> Result<(), Error> {
     let rt = Arc::new(ArcSwapOption::empty());
     let reg = Arc::new(create_fake_registry_client(4));
-    let mut runner = Runner::new(Arc::clone(&rt), reg);
+    let mut runner = Runner::new(Arc::clone(&rt), reg, load_balancer());
     let helper = TlsVerifier::new(Arc::clone(&rt));
     runner.run().await?;
 
@@ -274,7 +278,7 @@ async fn test_resolve() -> Result<(), Error> {
     let reg = Arc::new(create_fake_registry_client(4));
     let rt = Arc::new(ArcSwapOption::empty());
     let helper = DnsResolver::new(Arc::clone(&rt));
-    let mut runner = Runner::new(Arc::clone(&rt), reg);
+    let mut runner = Runner::new(Arc::clone(&rt), reg, load_balancer());
     runner.run().await?;
 
     // Check that resolved node's IPs match expected ones
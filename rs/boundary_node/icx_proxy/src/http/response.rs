@@ -1,5 +1,8 @@
 use crate::http::headers::IC_CERTIFICATE_HEADER_NAME;
-use futures::StreamExt;
+use crate::validate::StreamVerifier;
+use anyhow::anyhow;
+use candid::Principal;
+use futures::{stream::BoxStream, Stream, StreamExt};
 use hyper::Body;
 use ic_agent::Agent;
 use ic_response_verification::types::Response;
@@ -10,6 +13,7 @@ use ic_utils::{
         StreamingCallbackHttpResponse, StreamingStrategy, Token,
     },
 };
+use std::borrow::Cow;
 use tracing::warn;
 
 // Limit the total number of calls to an HTTP Request loop to 1000 for now.
@@ -18,19 +22,23 @@ static MAX_HTTP_REQUEST_STREAM_CALLBACK_CALL_COUNT: usize = 1000;
 // Limit the number of Stream Callbacks buffered
 static STREAM_CALLBACK_BUFFER: usize = 2;
 
+// Limit the size of a streamed body that has to be buffered for verification because
+// its chunks are not certified individually
+static MAX_BUFFERED_BODY_SIZE: usize = 32 * 1024 * 1024;
+
 pub type AgentResponseAny = AgentResponse<Token, HttpRequestStreamingCallbackAny>;
 
+pub type StreamingStrategyAny = StreamingStrategy<Token, HttpRequestStreamingCallbackAny>;
+
 pub struct HttpResponse {
     pub status_code: u16,
     pub headers: Vec<(String, String)>,
     pub body: Vec<u8>,
-    /// Set if the body is being sent using a stream
-    pub streaming_body: Option<Body>,
     pub has_streaming_body: bool,
 }
 
-impl From<(&Agent, AgentResponseAny)> for HttpResponse {
-    fn from((agent, response): (&Agent, AgentResponseAny)) -> Self {
+impl From<&AgentResponseAny> for HttpResponse {
+    fn from(response: &AgentResponseAny) -> Self {
         let headers = response
             .headers
             .iter()
@@ -42,10 +50,6 @@ impl From<(&Agent, AgentResponseAny)> for HttpResponse {
             headers,
             body: response.body.clone(),
             has_streaming_body: response.streaming_strategy.is_some(),
-            streaming_body: match response.streaming_strategy.is_some() {
-                true => Some(HttpResponse::create_body_stream((agent, response))),
-                false => None,
-            },
         }
     }
 }
@@ -72,58 +76,269 @@ impl HttpResponse {
         false
     }
 
-    /// Resolves the correct body stream taking into account the streaming strategy.
-    fn create_body_stream((agent, response): (&Agent, AgentResponseAny)) -> Body {
-        let initial_body = response.body.clone();
-        match response.streaming_strategy {
-            Some(StreamingStrategy::Callback(callback)) => {
-                let body = futures::stream::once(async move { Ok(initial_body) });
-                Body::wrap_stream(
-                    body.chain(futures::stream::try_unfold(
-                        (agent.clone(), callback.callback.0, Some(callback.token)),
-                        move |(agent, callback, callback_token)| async move {
-                            let callback_token = match callback_token {
-                                Some(callback_token) => callback_token,
-                                None => return Ok(None),
-                            };
-
-                            let canister = HttpRequestCanister::create(&agent, callback.principal);
-                            match canister
-                                .http_request_stream_callback(&callback.method, callback_token)
-                                .call()
-                                .await
-                            {
-                                Ok((StreamingCallbackHttpResponse { body, token },)) => {
-                                    Ok(Some((body, (agent, callback, token))))
-                                }
-                                Err(e) => {
-                                    warn!("Error happened during streaming: {:?}", e);
-                                    Err(e)
-                                }
-                            }
-                        },
-                    ))
-                    .take(MAX_HTTP_REQUEST_STREAM_CALLBACK_CALL_COUNT)
-                    .map(|x| async move { x })
-                    .buffered(STREAM_CALLBACK_BUFFER),
-                )
+    /// Resolves the body stream of a streamed body, the body holds the first chunk.
+    /// If a verifier is given, no byte is sent before it passed verification,
+    /// see `verified_chunks`.
+    pub fn create_body_stream(
+        agent: &Agent,
+        canister_id: &Principal,
+        body: Vec<u8>,
+        streaming_strategy: StreamingStrategyAny,
+        verifier: Option<StreamVerifier>,
+    ) -> Result<Body, Cow<'static, str>> {
+        let chunks = callback_chunks(agent, canister_id, streaming_strategy)?;
+        let chunks = futures::stream::once(async move { Ok(body) }).chain(chunks);
+
+        Ok(match verifier {
+            Some(verifier) => Body::wrap_stream(verified_chunks(chunks, verifier)),
+            None => Body::wrap_stream(chunks),
+        })
+    }
+}
+
+// Passes the chunks through the verifier. If the canister certified the individual chunks,
+// every chunk is sent as soon as it passed verification and the stream is aborted on the first
+// chunk failing it. Otherwise only the complete body can be verified, so it's buffered and only
+// sent once it passed verification.
+// A truncated stream (e.g. by the callback call limit) fails the final verification.
+fn verified_chunks(
+    chunks: impl Stream<Item = Result<Vec<u8>, anyhow::Error>> + Send + 'static,
+    verifier: StreamVerifier,
+) -> BoxStream<'static, Result<Vec<u8>, anyhow::Error>> {
+    if !verifier.has_chunk_hashes() {
+        return buffered_chunks(chunks, verifier).boxed();
+    }
+
+    futures::stream::try_unfold(
+        (Box::pin(chunks), verifier),
+        |(mut chunks, mut verifier)| async move {
+            let chunk = match chunks.next().await {
+                Some(chunk) => chunk?,
+                None => return verifier.finish().map(|_| None).map_err(abort),
+            };
+
+            verifier.verify_chunk(&chunk).map_err(abort)?;
+            Ok(Some((chunk, (chunks, verifier))))
+        },
+    )
+    .boxed()
+}
+
+// Joins the chunks into a single one that is only sent once the complete body passed verification
+fn buffered_chunks(
+    chunks: impl Stream<Item = Result<Vec<u8>, anyhow::Error>> + Send + 'static,
+    mut verifier: StreamVerifier,
+) -> impl Stream<Item = Result<Vec<u8>, anyhow::Error>> + Send {
+    futures::stream::once(async move {
+        let mut chunks = Box::pin(chunks);
+        let mut body = Vec::new();
+
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            if body.len() + chunk.len() > MAX_BUFFERED_BODY_SIZE {
+                return Err(abort("Streamed body is too large to be verified".into()));
             }
-            None => Body::from(initial_body),
+
+            verifier.verify_chunk(&chunk).map_err(abort)?;
+            body.extend_from_slice(&chunk);
         }
+
+        verifier.finish().map_err(abort)?;
+        Ok(body)
+    })
+}
+
+fn abort(e: Cow<'static, str>) -> anyhow::Error {
+    warn!("Aborting streamed body: {}", e);
+    anyhow!(e)
+}
+
+// Pulls the remaining chunks of a streamed body from the streaming callback
+fn callback_chunks(
+    agent: &Agent,
+    canister_id: &Principal,
+    streaming_strategy: StreamingStrategyAny,
+) -> Result<impl Stream<Item = Result<Vec<u8>, anyhow::Error>>, Cow<'static, str>> {
+    let StreamingStrategy::Callback(callback) = streaming_strategy;
+
+    // The callback must not be able to pull the chunks from another canister
+    if callback.callback.0.principal != *canister_id {
+        return Err("Streaming callback points to a different canister".into());
     }
+
+    Ok(futures::stream::try_unfold(
+        (agent.clone(), callback.callback.0, Some(callback.token)),
+        move |(agent, callback, callback_token)| async move {
+            let callback_token = match callback_token {
+                Some(callback_token) => callback_token,
+                None => return Ok(None),
+            };
+
+            let canister = HttpRequestCanister::create(&agent, callback.principal);
+            let (StreamingCallbackHttpResponse { body, token },) = canister
+                .http_request_stream_callback(&callback.method, callback_token.clone())
+                .call()
+                .await
+                .map_err(|e| {
+                    warn!("Error happened during streaming: {:?}", e);
+                    anyhow::Error::from(e)
+                })?;
+
+            // Returning the same token again would make the stream loop
+            if matches!(&token, Some(token) if token.0 == callback_token.0) {
+                warn!("Streaming callback returned the same token");
+                return Err(anyhow::anyhow!(
+                    "Streaming callback returned the same token"
+                ));
+            }
+
+            Ok(Some((body, (agent, callback, token))))
+        },
+    )
+    .take(MAX_HTTP_REQUEST_STREAM_CALLBACK_CALL_COUNT)
+    .map(|x| async move { x })
+    .buffered(STREAM_CALLBACK_BUFFER))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::http::headers::IC_CERTIFICATE_HEADER_NAME;
-    use crate::http::response::HttpResponse;
+    use crate::http::response::{verified_chunks, HttpResponse, MAX_BUFFERED_BODY_SIZE};
+    use crate::validate::StreamVerifier;
+    use candid::{parser::value::IDLValue, Func, Principal};
+    use futures::{Stream, StreamExt};
+    use ic_agent::{
+        agent::http_transport::{
+            hyper::{Body, Uri},
+            HyperReplicaV2Transport,
+        },
+        Agent,
+    };
+    use ic_utils::interfaces::http_request::{
+        CallbackStrategy, HttpRequestStreamingCallbackAny, StreamingStrategy, Token,
+    };
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn chunk_stream(
+        chunks: Vec<Vec<u8>>,
+    ) -> impl Stream<Item = Result<Vec<u8>, anyhow::Error>> + Send + 'static {
+        futures::stream::iter(chunks.into_iter().map(Ok))
+    }
+
+    // Collects the chunks that were sent until the stream ended or was aborted
+    async fn collect_chunks(
+        stream: impl Stream<Item = Result<Vec<u8>, anyhow::Error>>,
+    ) -> (Vec<Vec<u8>>, bool) {
+        let mut stream = Box::pin(stream);
+        let mut sent = Vec::new();
+
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(chunk) => sent.push(chunk),
+                Err(_) => return (sent, false),
+            }
+        }
+
+        (sent, true)
+    }
+
+    #[test]
+    fn verified_chunks_pass() {
+        let chunks: &[&[u8]] = &[b"foo", b"bar", b"baz"];
+        let owned = chunks.iter().map(|x| x.to_vec()).collect::<Vec<_>>();
+
+        // Certified chunks are sent one by one
+        let verifier = StreamVerifier::for_chunks(chunks, true);
+        let out = aw!(collect_chunks(verified_chunks(
+            chunk_stream(owned.clone()),
+            verifier
+        )));
+        assert_eq!(out, (owned.clone(), true));
+
+        // Otherwise the verified body is sent at once
+        let verifier = StreamVerifier::for_chunks(chunks, false);
+        let out = aw!(collect_chunks(verified_chunks(
+            chunk_stream(owned.clone()),
+            verifier
+        )));
+        assert_eq!(out, (vec![owned.concat()], true));
+    }
+
+    #[test]
+    fn verified_chunks_tampered() {
+        let chunks: &[&[u8]] = &[b"foo", b"bar", b"baz"];
+        let tampered = vec![b"foo".to_vec(), b"bad".to_vec(), b"baz".to_vec()];
+
+        // With certified chunk hashes the tampered chunk is never sent
+        let verifier = StreamVerifier::for_chunks(chunks, true);
+        let out = aw!(collect_chunks(verified_chunks(
+            chunk_stream(tampered.clone()),
+            verifier
+        )));
+        assert_eq!(out, (vec![b"foo".to_vec()], false));
+
+        // Otherwise nothing is sent
+        let verifier = StreamVerifier::for_chunks(chunks, false);
+        let out = aw!(collect_chunks(verified_chunks(
+            chunk_stream(tampered),
+            verifier
+        )));
+        assert_eq!(out, (Vec::<Vec<u8>>::new(), false));
+    }
+
+    #[test]
+    fn verified_chunks_buffer_limit() {
+        let chunk = vec![0xab; 1024 * 1024];
+        let count = MAX_BUFFERED_BODY_SIZE / chunk.len() + 1;
+        let large = vec![chunk.as_slice(); count];
+
+        // Bodies without certified chunks that are too large to be buffered are not sent
+        let verifier = StreamVerifier::for_chunks(&large, false);
+        let stream = futures::stream::iter(std::iter::repeat(chunk.clone()).take(count).map(Ok));
+        let out = aw!(collect_chunks(verified_chunks(stream, verifier)));
+        assert_eq!(out, (Vec::<Vec<u8>>::new(), false));
+    }
+
+    #[test]
+    fn verified_chunks_oversize() {
+        let chunks: &[&[u8]] = &[b"foo", b"bar"];
+
+        // Chunks beyond the certified ones are not sent
+        let verifier = StreamVerifier::for_chunks(chunks, true);
+        let extended = vec![b"foo".to_vec(), b"bar".to_vec(), b"baz".to_vec()];
+        let out = aw!(collect_chunks(verified_chunks(
+            chunk_stream(extended),
+            verifier
+        )));
+        assert_eq!(out, (vec![b"foo".to_vec(), b"bar".to_vec()], false));
+
+        // Large bodies are streamed through without being buffered
+        let chunk = vec![0xab; 1024 * 1024];
+        let large = vec![chunk.as_slice(); 80];
+        let verifier = StreamVerifier::for_chunks(&large, true);
+        let stream = futures::stream::iter(std::iter::repeat(chunk).take(80).map(Ok));
+        let (sent, completed) = aw!(verified_chunks(stream, verifier).fold(
+            (0, true),
+            |(sent, completed), chunk| async move {
+                match chunk {
+                    Ok(chunk) => (sent + chunk.len(), completed),
+                    Err(_) => (sent, false),
+                }
+            }
+        ));
+        assert_eq!((sent, completed), (80 * 1024 * 1024, true));
+    }
 
     #[test]
     fn response_has_ic_certificate() {
         let response = HttpResponse {
             status_code: 200,
             headers: vec![(IC_CERTIFICATE_HEADER_NAME.to_string(), "".to_string())],
-            streaming_body: None,
             has_streaming_body: false,
             body: Vec::new(),
         };
@@ -136,11 +351,82 @@ mod tests {
         let response = HttpResponse {
             status_code: 200,
             headers: Vec::new(),
-            streaming_body: None,
             has_streaming_body: false,
             body: Vec::new(),
         };
 
         assert!(!response.has_ic_certificate());
     }
+
+    #[test]
+    fn body_stream_from_another_canister() {
+        let uri = Uri::from_static("http://www.example.com");
+        let transport = HyperReplicaV2Transport::<Body>::create(uri).unwrap();
+        let agent = Agent::builder().with_transport(transport).build().unwrap();
+
+        let canister_id = Principal::from_text("wwc2m-2qaaa-aaaac-qaaaa-cai").unwrap();
+        let streaming_strategy = |principal| {
+            StreamingStrategy::Callback(CallbackStrategy {
+                callback: HttpRequestStreamingCallbackAny(Func {
+                    principal,
+                    method: "http_request_streaming_callback".to_string(),
+                }),
+                token: Token(IDLValue::Null),
+            })
+        };
+
+        let out = HttpResponse::create_body_stream(
+            &agent,
+            &canister_id,
+            Vec::new(),
+            streaming_strategy(canister_id),
+            None,
+        );
+        assert!(out.is_ok());
+
+        // The chunks must not be pulled from another canister
+        let out = HttpResponse::create_body_stream(
+            &agent,
+            &canister_id,
+            Vec::new(),
+            streaming_strategy(Principal::management_canister()),
+            None,
+        );
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn body_stream_tampered() {
+        let uri = Uri::from_static("http://www.example.com");
+        let transport = HyperReplicaV2Transport::<Body>::create(uri).unwrap();
+        let agent = Agent::builder().with_transport(transport).build().unwrap();
+
+        let canister_id = Principal::from_text("wwc2m-2qaaa-aaaac-qaaaa-cai").unwrap();
+        let streaming_strategy = StreamingStrategy::Callback(CallbackStrategy {
+            callback: HttpRequestStreamingCallbackAny(Func {
+                principal: canister_id,
+                method: "http_request_streaming_callback".to_string(),
+            }),
+            token: Token(IDLValue::Null),
+        });
+
+        // The tampered first chunk is rejected before the remaining chunks are pulled
+        let chunks: &[&[u8]] = &[b"foo", b"bar"];
+        let verifier = StreamVerifier::for_chunks(chunks, true);
+        let body = HttpResponse::create_body_stream(
+            &agent,
+            &canister_id,
+            b"bad".to_vec(),
+            streaming_strategy,
+            Some(verifier),
+        )
+        .unwrap();
+
+        let out = aw!(collect_chunks(body.map(|chunk| {
+            chunk
+                .map(|chunk| chunk.to_vec())
+                .map_err(anyhow::Error::from)
+        })));
+        assert_eq!(out, (Vec::<Vec<u8>>::new(), false));
+    }
 }
@@ -17,7 +17,10 @@ use prometheus::{Encoder, TextEncoder};
 
 use crate::http::request::HttpRequest;
 use crate::http::response::HttpResponse;
-use crate::{logging::add_trace_layer, validate::Validate};
+use crate::{
+    logging::add_trace_layer,
+    validate::{StreamVerifier, Validate},
+};
 
 /// The options for metrics
 #[derive(Args)]
@@ -62,7 +65,38 @@ impl<T: Validate> Validate for WithMetrics<T> {
             status = "skip";
         }
 
-        let labels = &[KeyValue::new("status", status)];
+        let labels = &[
+            KeyValue::new("status", status),
+            KeyValue::new("streaming", "false"),
+        ];
+
+        let MetricParams { counter } = &self.1;
+        counter.add(1, labels);
+
+        out
+    }
+
+    fn validate_streaming(
+        &self,
+        agent: &Agent,
+        canister_id: &Principal,
+        request: &HttpRequest,
+        response: &HttpResponse,
+    ) -> Result<Option<StreamVerifier>, Cow<'static, str>> {
+        let out = self
+            .0
+            .validate_streaming(agent, canister_id, request, response);
+
+        let status = match &out {
+            Ok(Some(_)) => "ok",
+            Ok(None) => "skip",
+            Err(_) => "fail",
+        };
+
+        let labels = &[
+            KeyValue::new("status", status),
+            KeyValue::new("streaming", "true"),
+        ];
 
         let MetricParams { counter } = &self.1;
         counter.add(1, labels);
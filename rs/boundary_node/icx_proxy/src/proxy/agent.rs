@@ -329,29 +329,53 @@ async fn process_request_inner(
         agent_response
     };
 
-    let http_response = HttpResponse::from((agent, agent_response));
+    let http_response = HttpResponse::from(&agent_response);
     let mut response_builder =
         Response::builder().status(StatusCode::from_u16(http_response.status_code)?);
     for (name, value) in &http_response.headers {
         response_builder = response_builder.header(name, value);
     }
 
-    // At the moment verification is only performed if the response is not using a streaming
-    // strategy. Performing verification for those requests would required to join all the chunks
-    // and this could cause memory issues and possibly create DOS attack vectors.
-    let should_validate = !http_response.has_streaming_body && !is_update_call;
-    if should_validate {
-        let validation = validator.validate(agent, &canister_id, &http_request, &http_response);
+    // Streamed bodies are verified chunk by chunk as they're sent if the canister certified the
+    // individual chunks, otherwise the body is buffered (up to a limit) and verified before it's
+    // sent. Either way, the response is aborted as soon as verification fails.
+    let streaming_body = match agent_response.streaming_strategy {
+        Some(streaming_strategy) => {
+            let verifier = match is_update_call {
+                true => Ok(None),
+                false => {
+                    validator.validate_streaming(agent, &canister_id, &http_request, &http_response)
+                }
+            };
+
+            verifier.and_then(|verifier| {
+                HttpResponse::create_body_stream(
+                    agent,
+                    &canister_id,
+                    agent_response.body,
+                    streaming_strategy,
+                    verifier,
+                )
+                .map(Some)
+            })
+        }
+        None if !is_update_call => validator
+            .validate(agent, &canister_id, &http_request, &http_response)
+            .map(|_| None),
+        None => Ok(None),
+    };
 
-        if validation.is_err() {
+    let streaming_body = match streaming_body {
+        Ok(streaming_body) => streaming_body,
+        Err(e) => {
             return Ok(Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(validation.unwrap_err().into())
-                .unwrap());
+                .body(e.into())
+                .unwrap())
         }
-    }
+    };
 
-    let response = response_builder.body(match http_response.streaming_body {
+    let response = response_builder.body(match streaming_body {
         Some(body) => body,
         None => Body::from(http_response.body.clone()),
     })?;
@@ -1,13 +1,25 @@
+use crate::http::headers::IC_CERTIFICATE_HEADER_NAME;
 use crate::http::request::HttpRequest;
 use crate::http::response::HttpResponse;
 use candid::Principal;
-use ic_agent::Agent;
+use ic_agent::{
+    hash_tree::{HashTree, LookupResult},
+    lookup_value, Agent, Certificate,
+};
 use ic_response_verification::{verify_request_response_pair, MIN_VERIFICATION_VERSION};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
 use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
 
 const MAX_CERT_TIME_OFFSET_NS: u128 = 300_000_000_000;
 
+// Label of the subtree holding the certified hashes of the complete assets
+const LABEL_ASSETS: &[u8] = b"http_assets";
+// Label of the subtree holding the certified hashes of the individual chunks of streamed assets,
+// keyed by asset path and then by the chunk index
+const LABEL_ASSET_CHUNKS: &[u8] = b"http_asset_chunks";
+
 pub trait Validate: Sync + Send {
     fn validate(
         &self,
@@ -16,6 +28,18 @@ pub trait Validate: Sync + Send {
         request: &HttpRequest,
         response: &HttpResponse,
     ) -> Result<(), Cow<'static, str>>;
+
+    /// Validates the certificate of a response with a streaming body.
+    /// The response body only holds the first chunk, the returned verifier must be
+    /// used to verify all the chunks (including the first one) as they are streamed
+    /// and to verify the complete body after the last one.
+    fn validate_streaming(
+        &self,
+        agent: &Agent,
+        canister_id: &Principal,
+        request: &HttpRequest,
+        response: &HttpResponse,
+    ) -> Result<Option<StreamVerifier>, Cow<'static, str>>;
 }
 
 #[derive(Clone)]
@@ -63,6 +87,231 @@ impl Validate for Validator {
             }
         }
     }
+
+    fn validate_streaming(
+        &self,
+        agent: &Agent,
+        canister_id: &Principal,
+        request: &HttpRequest,
+        response: &HttpResponse,
+    ) -> Result<Option<StreamVerifier>, Cow<'static, str>> {
+        if cfg!(feature = "skip_body_verification") {
+            return Ok(None);
+        }
+
+        let certification_required = request.is_certification_required();
+        let header = response.headers.iter().find_map(|(name, value)| {
+            name.eq_ignore_ascii_case(IC_CERTIFICATE_HEADER_NAME)
+                .then_some(value)
+        });
+
+        let header = match (certification_required, header) {
+            // TODO: Remove this (FOLLOW-483)
+            (false, None) => return Ok(None),
+            (true, None) => return Err("Streamed body is not certified".into()),
+            (_, Some(header)) => parse_certificate_header(header)?,
+        };
+
+        // Only the v1 certification (hashes of complete assets) can be verified for streamed
+        // bodies, a certified body that can't be verified must not be served
+        if header.version.unwrap_or(1) > 1 {
+            warn!("Unable to verify streamed body: unsupported certification version");
+            return Err("Streamed body uses an unsupported certification version".into());
+        }
+
+        let cert = serde_cbor::from_slice::<Certificate>(&header.certificate)
+            .map_err(|_| "Failed to decode certificate")?;
+        let tree = serde_cbor::from_slice::<HashTree<Vec<u8>>>(&header.tree)
+            .map_err(|_| "Failed to decode certificate tree")?;
+
+        verify_certificate(agent, canister_id, &cert, &tree)?;
+
+        StreamVerifier::new(&tree, &decode_path(request.uri.path())).map(Some)
+    }
+}
+
+/// Verifies the chunks of a streamed body against the certified asset hash
+/// and, if the canister certified them, the hashes of the individual chunks.
+pub struct StreamVerifier {
+    asset_hash: Vec<u8>,
+    chunk_hashes: Vec<Vec<u8>>,
+    hasher: Sha256,
+    index: usize,
+}
+
+impl StreamVerifier {
+    fn new(tree: &HashTree<Vec<u8>>, path: &[u8]) -> Result<Self, Cow<'static, str>> {
+        // Same fallback as for non-streamed assets
+        let (path, asset_hash) = [path, b"/index.html".as_slice()]
+            .iter()
+            .find_map(|&path| match tree.lookup_path([LABEL_ASSETS, path]) {
+                LookupResult::Found(hash) => Some((path, hash.to_vec())),
+                _ => None,
+            })
+            .ok_or("Asset is not certified")?;
+
+        let mut chunk_hashes = Vec::new();
+        while let LookupResult::Found(hash) = tree.lookup_path([
+            LABEL_ASSET_CHUNKS,
+            path,
+            chunk_hashes.len().to_string().as_bytes(),
+        ]) {
+            chunk_hashes.push(hash.to_vec());
+        }
+
+        Ok(Self {
+            asset_hash,
+            chunk_hashes,
+            hasher: Sha256::new(),
+            index: 0,
+        })
+    }
+
+    /// Checks if the canister certified the individual chunks, i.e. if chunks can be
+    /// sent as soon as they're verified rather than only after the complete body.
+    pub fn has_chunk_hashes(&self) -> bool {
+        !self.chunk_hashes.is_empty()
+    }
+
+    /// Verifies the next chunk of the body.
+    /// The stream must be aborted as soon as a chunk fails verification.
+    pub fn verify_chunk(&mut self, chunk: &[u8]) -> Result<(), Cow<'static, str>> {
+        if !self.chunk_hashes.is_empty() {
+            let expected = self
+                .chunk_hashes
+                .get(self.index)
+                .ok_or("Streamed body has more chunks than certified")?;
+
+            if Sha256::digest(chunk).as_slice() != expected.as_slice() {
+                return Err("Streamed chunk does not pass verification".into());
+            }
+        }
+
+        self.hasher.update(chunk);
+        self.index += 1;
+
+        Ok(())
+    }
+
+    /// Verifies the complete body once the last chunk was streamed.
+    pub fn finish(self) -> Result<(), Cow<'static, str>> {
+        if !self.chunk_hashes.is_empty() && self.index != self.chunk_hashes.len() {
+            return Err("Streamed body has fewer chunks than certified".into());
+        }
+
+        if self.hasher.finalize().as_slice() != self.asset_hash.as_slice() {
+            return Err("Streamed body does not pass verification".into());
+        }
+
+        Ok(())
+    }
+
+    /// Creates a verifier for the given chunks, optionally certifying each chunk.
+    #[cfg(test)]
+    pub fn for_chunks(chunks: &[&[u8]], with_chunk_hashes: bool) -> Self {
+        Self {
+            asset_hash: Sha256::digest(chunks.concat()).to_vec(),
+            chunk_hashes: match with_chunk_hashes {
+                true => chunks.iter().map(|x| Sha256::digest(x).to_vec()).collect(),
+                false => Vec::new(),
+            },
+            hasher: Sha256::new(),
+            index: 0,
+        }
+    }
+}
+
+struct CertificateHeader {
+    certificate: Vec<u8>,
+    tree: Vec<u8>,
+    version: Option<u16>,
+}
+
+// Parses the `ic-certificate` header, e.g. `certificate=:<base64>:, tree=:<base64>:`
+fn parse_certificate_header(header: &str) -> Result<CertificateHeader, Cow<'static, str>> {
+    let (mut certificate, mut tree, mut version) = (None, None, None);
+
+    for field in header.split(',') {
+        let (name, value) = match field.trim().split_once('=') {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let value = value.trim_matches(':');
+        match name.trim() {
+            "certificate" => certificate = base64::decode(value).ok(),
+            "tree" => tree = base64::decode(value).ok(),
+            "version" => version = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    Ok(CertificateHeader {
+        certificate: certificate.ok_or("Certificate header is missing the certificate")?,
+        tree: tree.ok_or("Certificate header is missing the tree")?,
+        version,
+    })
+}
+
+// Checks the IC signature, the certificate time and that the tree matches the certified data
+fn verify_certificate(
+    agent: &Agent,
+    canister_id: &Principal,
+    cert: &Certificate,
+    tree: &HashTree<Vec<u8>>,
+) -> Result<(), Cow<'static, str>> {
+    agent
+        .verify(cert, *canister_id)
+        .map_err(|_| "Certificate does not pass verification")?;
+
+    let mut time = match cert.tree.lookup_path([b"time".as_slice()]) {
+        LookupResult::Found(time) => time,
+        _ => return Err("Certificate is missing the time".into()),
+    };
+    let time = leb128::read::unsigned(&mut time).map_err(|_| "Invalid certificate time")? as u128;
+
+    let now = get_current_time_in_ns();
+    if time > now + MAX_CERT_TIME_OFFSET_NS || time < now.saturating_sub(MAX_CERT_TIME_OFFSET_NS) {
+        return Err("Certificate time is out of range".into());
+    }
+
+    let witness = lookup_value(
+        cert,
+        vec![
+            "canister".as_bytes(),
+            canister_id.as_slice(),
+            "certified_data".as_bytes(),
+        ],
+    )
+    .map_err(|_| "Certificate is missing the certified data")?;
+
+    if tree.digest() != witness {
+        return Err("Certificate tree does not match the certified data".into());
+    }
+
+    Ok(())
+}
+
+// Percent-decodes the request path for the tree lookups
+fn decode_path(path: &str) -> Vec<u8> {
+    let path = path.as_bytes();
+    let mut out = Vec::with_capacity(path.len());
+
+    let mut i = 0;
+    while i < path.len() {
+        if path[i] == b'%' {
+            if let Some(Ok(x)) = path.get(i + 1..i + 3).map(hex::decode) {
+                out.push(x[0]);
+                i += 3;
+                continue;
+            }
+        }
+
+        out.push(path[i]);
+        i += 1;
+    }
+
+    out
 }
 
 fn get_current_time_in_ns() -> u128 {
@@ -87,7 +336,16 @@ mod tests {
         Agent,
     };
 
-    use crate::validate::{Validate, Validator};
+    use crate::http::headers::{IC_CERTIFICATE_HEADER_NAME, REQUIRE_CERTIFICATION_HEADER_NAME};
+    use crate::validate::{
+        decode_path, parse_certificate_header, StreamVerifier, Validate, Validator,
+    };
+
+    fn agent() -> Agent {
+        let uri = Uri::from_static("http://www.example.com");
+        let transport = HyperReplicaV2Transport::<Body>::create(uri).unwrap();
+        Agent::builder().with_transport(transport).build().unwrap()
+    }
 
     #[test]
     fn validate_nop() {
@@ -109,7 +367,6 @@ mod tests {
             &HttpResponse {
                 status_code: 200,
                 headers: Vec::new(),
-                streaming_body: None,
                 has_streaming_body: false,
                 body: Vec::new(),
             },
@@ -117,4 +374,129 @@ mod tests {
 
         assert_eq!(out, Ok(()));
     }
+
+    #[test]
+    fn validate_streaming_nop() {
+        let canister_id = Principal::from_text("wwc2m-2qaaa-aaaac-qaaaa-cai").unwrap();
+        let validator = Validator::new();
+
+        let mut request = HttpRequest {
+            uri: Uri::from_static("http://www.example.com"),
+            method: String::from("GET"),
+            body: Vec::new(),
+            headers: Vec::new(),
+        };
+
+        let response = HttpResponse {
+            status_code: 200,
+            headers: Vec::new(),
+            has_streaming_body: true,
+            body: Vec::new(),
+        };
+
+        let out = validator.validate_streaming(&agent(), &canister_id, &request, &response);
+        assert!(matches!(out, Ok(None)));
+
+        // Uncertified streamed bodies are rejected if certification is required
+        request.headers.push((
+            REQUIRE_CERTIFICATION_HEADER_NAME.to_string(),
+            "true".to_string(),
+        ));
+        let out = validator.validate_streaming(&agent(), &canister_id, &request, &response);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn validate_streaming_unsupported_version() {
+        let canister_id = Principal::from_text("wwc2m-2qaaa-aaaac-qaaaa-cai").unwrap();
+        let validator = Validator::new();
+
+        let request = HttpRequest {
+            uri: Uri::from_static("http://www.example.com"),
+            method: String::from("GET"),
+            body: Vec::new(),
+            headers: Vec::new(),
+        };
+
+        let response = HttpResponse {
+            status_code: 200,
+            headers: vec![(
+                IC_CERTIFICATE_HEADER_NAME.to_string(),
+                "certificate=:Zm9v:, tree=:YmFy:, version=2".to_string(),
+            )],
+            has_streaming_body: true,
+            body: Vec::new(),
+        };
+
+        // Certified bodies that can't be verified are rejected even if certification isn't required
+        let out = validator.validate_streaming(&agent(), &canister_id, &request, &response);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn verify_chunks() {
+        let chunks: &[&[u8]] = &[b"foo", b"bar", b"baz"];
+
+        for with_chunk_hashes in [false, true] {
+            let mut verifier = StreamVerifier::for_chunks(chunks, with_chunk_hashes);
+            for chunk in chunks {
+                assert_eq!(verifier.verify_chunk(chunk), Ok(()));
+            }
+            assert_eq!(verifier.finish(), Ok(()));
+
+            // Tampered last chunk
+            let mut verifier = StreamVerifier::for_chunks(chunks, with_chunk_hashes);
+            assert_eq!(verifier.verify_chunk(b"foo"), Ok(()));
+            assert_eq!(verifier.verify_chunk(b"bar"), Ok(()));
+            let out = verifier
+                .verify_chunk(b"bad")
+                .and_then(|_| verifier.finish());
+            assert!(out.is_err());
+        }
+    }
+
+    #[test]
+    fn verify_chunks_aborts_early() {
+        let chunks: &[&[u8]] = &[b"foo", b"bar", b"baz"];
+
+        // With certified chunk hashes the first tampered chunk is detected
+        let mut verifier = StreamVerifier::for_chunks(chunks, true);
+        assert_eq!(verifier.verify_chunk(b"foo"), Ok(()));
+        assert!(verifier.verify_chunk(b"bad").is_err());
+
+        // Missing chunks
+        let mut verifier = StreamVerifier::for_chunks(chunks, true);
+        assert_eq!(verifier.verify_chunk(b"foo"), Ok(()));
+        assert!(verifier.finish().is_err());
+
+        // Extra chunks
+        let mut verifier = StreamVerifier::for_chunks(chunks, true);
+        for chunk in chunks {
+            assert_eq!(verifier.verify_chunk(chunk), Ok(()));
+        }
+        assert!(verifier.verify_chunk(b"").is_err());
+    }
+
+    #[test]
+    fn certificate_header() {
+        let header = parse_certificate_header("certificate=:Zm9v:, tree=:YmFy:").unwrap();
+        assert_eq!(header.certificate, b"foo");
+        assert_eq!(header.tree, b"bar");
+        assert_eq!(header.version, None);
+
+        let header = parse_certificate_header(
+            "certificate=:Zm9v:, tree=:YmFy:, version=2, expr_path=:Zm9v:",
+        )
+        .unwrap();
+        assert_eq!(header.version, Some(2));
+
+        assert!(parse_certificate_header("tree=:YmFy:").is_err());
+    }
+
+    #[test]
+    fn path_decoding() {
+        assert_eq!(decode_path("/index.html"), b"/index.html");
+        assert_eq!(decode_path("/foo%20bar.txt"), b"/foo bar.txt");
+        assert_eq!(decode_path("/100%"), b"/100%");
+    }
 }
@@ -234,6 +234,18 @@ impl HttpClient {
         Self::wait_for_one_http_request(uri, response_future, deadline).await
     }
 
+    pub async fn send_get_request(
+        &self,
+        url: &str,
+        deadline: tokio::time::Instant,
+    ) -> Result<(Vec<u8>, hyper::StatusCode), String> {
+        let uri = url
+            .parse::<HyperUri>()
+            .map_err(|e| format!("HttpClient: Failed to parse URL {:?}: {:?}", url, e))?;
+        let response_future = self.hyper.get(uri.clone());
+        Self::wait_for_response_with_status(uri, response_future, deadline).await
+    }
+
     pub async fn send_post_request(
         &self,
         url: &str,
@@ -250,7 +262,14 @@ impl HttpClient {
             .body(hyper::Body::from(http_body))
             .map_err(|e| format!("HttpClient: Failed to fill body {:?}: {:?}", url, e))?;
         let response_future = self.hyper.request(req);
+        Self::wait_for_response_with_status(uri, response_future, deadline).await
+    }
 
+    async fn wait_for_response_with_status(
+        uri: HyperUri,
+        response_future: HyperFuture,
+        deadline: tokio::time::Instant,
+    ) -> Result<(Vec<u8>, hyper::StatusCode), String> {
         let response = tokio::time::timeout_at(deadline, response_future)
             .await
             .map_err(|e| format!("HttpClient: Request timed out for {:?}: {:?}", uri, e))?;
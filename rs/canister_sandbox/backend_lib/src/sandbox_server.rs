@@ -218,6 +218,8 @@ mod tests {
             CanisterTimer::Inactive,
             0,
             BTreeSet::from([controller]),
+            None,
+            None,
         )
     }
 
@@ -1,13 +1,15 @@
 //! A service provided by the controller to the launcher.
 
-use ic_types::CanisterId;
 use serde::{Deserialize, Serialize};
 
 use crate::fdenum::EnumerateInnerFileDescriptors;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SandboxExitedRequest {
-    pub canister_id: CanisterId,
+    /// The process id of the sandbox process that exited. Pre-forked sandbox
+    /// processes are launched before they are assigned to a canister, so the
+    /// controller identifies the process by its pid.
+    pub pid: u32,
 }
 
 impl EnumerateInnerFileDescriptors for SandboxExitedRequest {
@@ -13,7 +13,9 @@ use crate::fdenum::EnumerateInnerFileDescriptors;
 pub struct LaunchSandboxRequest {
     pub sandbox_exec_path: String,
     pub argv: Vec<String>,
-    pub canister_id: CanisterId,
+    /// The canister the sandbox process is launched for, or `None` for a
+    /// pre-forked process that is assigned to a canister later.
+    pub canister_id: Option<CanisterId>,
     pub socket: RawFd,
 }
 
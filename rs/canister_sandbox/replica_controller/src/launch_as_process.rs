@@ -76,7 +76,7 @@ pub fn spawn_launcher_process(
 pub fn spawn_canister_sandbox_process(
     exec_path: &str,
     argv: &[String],
-    canister_id: CanisterId,
+    canister_id: Option<CanisterId>,
     controller_service: Arc<dyn rpc::DemuxServer<ctlsvc::Request, ctlsvc::Reply> + Send + Sync>,
     launcher: &dyn LauncherService,
 ) -> std::io::Result<(Arc<dyn SandboxService>, u32, std::thread::JoinHandle<()>)> {
@@ -128,15 +128,18 @@ pub fn spawn_canister_sandbox_process(
     Ok((svc, pid, thread_handle))
 }
 
-/// Spawns a sandbox process for the given canister.
+/// Spawns a sandbox process for the given canister, or a pre-forked sandbox
+/// process that is not assigned to any canister yet if `canister_id` is `None`.
 pub fn create_sandbox_process(
     controller_service: Arc<dyn rpc::DemuxServer<ctlsvc::Request, ctlsvc::Reply> + Send + Sync>,
     launcher_service: &dyn LauncherService,
-    canister_id: CanisterId,
+    canister_id: Option<CanisterId>,
     mut argv: Vec<String>,
 ) -> std::io::Result<(Arc<dyn SandboxService>, u32)> {
     assert!(!argv.is_empty());
-    argv.push(canister_id.to_string());
+    if let Some(canister_id) = canister_id {
+        argv.push(canister_id.to_string());
+    }
 
     let (sandbox_handle, pid, _recv_thread_handle) = spawn_canister_sandbox_process(
         &argv[0],
@@ -1,28 +1,49 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use ic_types::CanisterId;
 
+/// The factor by which the warm-up cost of a candidate extends its `last_used`
+/// time for the purpose of eviction: a sandbox process that took 100ms to warm
+/// up is kept as if it had been used 100s later than it actually was.
+const WARMUP_COST_WEIGHT: u32 = 1_000;
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub(crate) struct EvictionCandidate {
     pub id: CanisterId,
     pub last_used: Instant,
+    /// The time it took to make the sandbox process ready to execute the
+    /// canister, i.e. what evicting it would cost on the next message.
+    pub warmup_cost: Duration,
+}
+
+impl EvictionCandidate {
+    /// The `last_used` time extended by the weighted warm-up cost.
+    fn retention_time(&self) -> Instant {
+        self.warmup_cost
+            .checked_mul(WARMUP_COST_WEIGHT)
+            .and_then(|bonus| self.last_used.checked_add(bonus))
+            .unwrap_or(self.last_used)
+    }
 }
 
 /// Evicts the least recently used candidates in order to bring the number of
-/// the remaining candidates down to `max_count_threshold`.
+/// the remaining candidates down to `max_count_threshold`. Candidates that are
+/// expensive to warm up again are considered more recently used than they
+/// were, proportionally to their warm-up cost.
 ///
 /// The function also tries to evict candidates that have been idle for a long
 /// time (`last_used_threshold`) while keeping the number of the remaining
 /// candidates at or above `min_count_threshold`.
 ///
 /// More formally:
-/// 1. Sort the candidates in the order of increasing `last_used` field.
+/// 1. Sort the candidates in the order of increasing retention time, which is
+///    `last_used + WARMUP_COST_WEIGHT * warmup_cost`.
 /// 2. Let `N` be the total number of candidates.
 /// 3. Evict the first `K` candidates such that the number of remaining
 ///    candidates `N-K` is between the given thresholds:
 ///    - `min_count_threshold <= N-K <= max_count_threshold`.
 ///    - if there multiple possible values for `K`, then choose the one that
-///      evicts the most candidates with `last_used < last_used_threshold`.
+///      evicts the most candidates with retention time `< last_used_threshold`.
 /// 4. Return the evicted candidates.
 pub(crate) fn evict(
     mut candidates: Vec<EvictionCandidate>,
@@ -30,7 +51,7 @@ pub(crate) fn evict(
     max_count_threshold: usize,
     last_used_threshold: Instant,
 ) -> Vec<EvictionCandidate> {
-    candidates.sort_by_key(|x| x.last_used);
+    candidates.sort_by_key(|x| x.retention_time());
 
     let evict_at_least = candidates.len().saturating_sub(max_count_threshold);
     let evict_at_most = candidates.len().saturating_sub(min_count_threshold);
@@ -43,7 +64,7 @@ pub(crate) fn evict(
             // should remain not evicted.
             break;
         }
-        if candidate.last_used >= last_used_threshold && evicted.len() >= evict_at_least {
+        if candidate.retention_time() >= last_used_threshold && evicted.len() >= evict_at_least {
             // We have already evicted the minimum required number of candidates
             // and all the remaining candidates were not idle the recent
             // `last_used_threshold` time window. No need to evict more.
@@ -76,6 +97,7 @@ mod tests {
             candidates.push(EvictionCandidate {
                 id: canister_test_id(i),
                 last_used: now,
+                warmup_cost: Duration::ZERO,
             });
         }
         assert_eq!(evict(candidates, 0, 10, now,), vec![],);
@@ -89,6 +111,7 @@ mod tests {
             candidates.push(EvictionCandidate {
                 id: canister_test_id(i),
                 last_used: now + Duration::from_secs(100 - i),
+                warmup_cost: Duration::ZERO,
             });
         }
         assert_eq!(
@@ -105,6 +128,7 @@ mod tests {
             candidates.push(EvictionCandidate {
                 id: canister_test_id(i),
                 last_used: now - Duration::from_secs(i),
+                warmup_cost: Duration::ZERO,
             });
         }
         assert_eq!(
@@ -121,6 +145,7 @@ mod tests {
             candidates.push(EvictionCandidate {
                 id: canister_test_id(i),
                 last_used: now - Duration::from_secs(i + 1),
+                warmup_cost: Duration::ZERO,
             });
         }
         assert_eq!(
@@ -129,6 +154,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn evict_prefers_candidates_that_are_cheap_to_warm_up() {
+        let now = Instant::now();
+        let candidate = |i, idle_secs, warmup_millis| EvictionCandidate {
+            id: canister_test_id(i),
+            last_used: now - Duration::from_secs(idle_secs),
+            warmup_cost: Duration::from_millis(warmup_millis),
+        };
+        // The expensive candidate is the least recently used one, but warming it
+        // up again costs as much as 200s of idle time.
+        let expensive = candidate(0, 150, 200);
+        let cheap = candidate(1, 100, 1);
+        let recent = candidate(2, 10, 1);
+
+        assert_eq!(
+            evict(
+                vec![expensive.clone(), cheap.clone(), recent.clone()],
+                0,
+                2,
+                now
+            ),
+            vec![cheap.clone()]
+        );
+        // Idle eviction also accounts for the warm-up cost.
+        assert_eq!(
+            evict(
+                vec![expensive, cheap.clone(), recent],
+                0,
+                3,
+                now - Duration::from_secs(30)
+            ),
+            vec![cheap]
+        );
+    }
+
     #[test]
     fn evict_all() {
         let mut candidates = vec![];
@@ -137,6 +197,7 @@ mod tests {
             candidates.push(EvictionCandidate {
                 id: canister_test_id(i),
                 last_used: now - Duration::from_secs(i + 1),
+                warmup_cost: Duration::ZERO,
             });
         }
         assert_eq!(evict(candidates.clone(), 0, 100, now).len(), 100);
@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The time window over which the launches of sandbox processes for canisters
+/// are counted in order to estimate the demand for pre-forked processes.
+const DEMAND_WINDOW: Duration = Duration::from_secs(60);
+
+/// A pool of idle, pre-forked sandbox processes that are not assigned to any
+/// canister yet.
+///
+/// Every time a canister needs a new sandbox process, one is taken from the
+/// pool if available. The pool is refilled periodically, aiming for as many
+/// processes as canisters needed in a refill interval during the recent
+/// `DEMAND_WINDOW`, but never more than `max_size`.
+pub(crate) struct SandboxProcessPool<P> {
+    idle: Vec<P>,
+    launches: VecDeque<Instant>,
+    max_size: usize,
+}
+
+impl<P> SandboxProcessPool<P> {
+    pub(crate) fn new(max_size: usize) -> Self {
+        Self {
+            idle: vec![],
+            launches: VecDeque::new(),
+            max_size,
+        }
+    }
+
+    /// Records that a canister needs a new sandbox process at time `now` and
+    /// returns a pre-forked one if the pool is not empty.
+    pub(crate) fn take(&mut self, now: Instant) -> Option<P> {
+        self.launches.push_back(now);
+        self.idle.pop()
+    }
+
+    /// Adds a freshly pre-forked process to the pool.
+    pub(crate) fn add(&mut self, process: P) {
+        self.idle.push(process);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.idle.len()
+    }
+
+    /// Returns the number of processes the pool should hold until the next
+    /// refill, which happens after `refill_interval`.
+    pub(crate) fn target_size(&mut self, now: Instant, refill_interval: Duration) -> usize {
+        while let Some(launch) = self.launches.front() {
+            if now.saturating_duration_since(*launch) <= DEMAND_WINDOW {
+                break;
+            }
+            self.launches.pop_front();
+        }
+        let demand = self.launches.len() as u128 * refill_interval.as_millis();
+        let window = DEMAND_WINDOW.as_millis();
+        let expected = (demand + window - 1) / window;
+        (expected as usize).min(self.max_size)
+    }
+
+    /// Removes and returns the processes that exceed the given size.
+    pub(crate) fn shrink_to(&mut self, size: usize) -> Vec<P> {
+        self.idle.split_off(size.min(self.idle.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFILL_INTERVAL: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn pool_is_empty_without_demand() {
+        let mut pool = SandboxProcessPool::<u32>::new(8);
+        assert_eq!(pool.target_size(Instant::now(), REFILL_INTERVAL), 0);
+        assert_eq!(pool.take(Instant::now()), None);
+    }
+
+    #[test]
+    fn pool_size_follows_recent_demand() {
+        let mut pool = SandboxProcessPool::<u32>::new(8);
+        let start = Instant::now();
+        for i in 0..12 {
+            assert_eq!(pool.take(start + Duration::from_secs(i)), None);
+        }
+        // 12 launches per minute are 2 launches per refill interval.
+        let now = start + Duration::from_secs(12);
+        assert_eq!(pool.target_size(now, REFILL_INTERVAL), 2);
+        pool.add(1);
+        pool.add(2);
+        assert_eq!(pool.take(now), Some(2));
+        assert_eq!(pool.len(), 1);
+
+        // Launches older than the demand window no longer count.
+        let later = start + DEMAND_WINDOW + Duration::from_secs(13);
+        assert_eq!(pool.target_size(later, REFILL_INTERVAL), 0);
+        assert_eq!(pool.shrink_to(0), vec![1]);
+    }
+
+    #[test]
+    fn pool_size_is_bounded() {
+        let mut pool = SandboxProcessPool::<u32>::new(3);
+        let now = Instant::now();
+        for _ in 0..100 {
+            pool.take(now);
+        }
+        assert_eq!(pool.target_size(now, REFILL_INTERVAL), 3);
+    }
+}
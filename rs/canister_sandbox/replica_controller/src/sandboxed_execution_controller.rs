@@ -28,9 +28,7 @@ use ic_types::ingress::WasmResult;
 use ic_types::methods::{FuncRef, WasmMethod};
 use ic_types::{CanisterId, NumInstructions};
 use ic_wasm_types::CanisterModule;
-#[cfg(target_os = "linux")]
-use prometheus::IntGauge;
-use prometheus::{Histogram, HistogramVec, IntCounter, IntCounterVec};
+use prometheus::{Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge};
 use std::collections::{HashMap, VecDeque};
 #[cfg(target_os = "linux")]
 use std::convert::TryInto;
@@ -48,6 +46,7 @@ use crate::process_exe_and_args::{create_launcher_argv, create_sandbox_argv};
 #[cfg(target_os = "linux")]
 use crate::process_os_metrics;
 use crate::sandbox_process_eviction::{self, EvictionCandidate};
+use crate::sandbox_process_pool::SandboxProcessPool;
 use ic_replicated_state::page_map::PageAllocatorFileDescriptor;
 
 const SANDBOX_PROCESS_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
@@ -76,6 +75,8 @@ struct SandboxedExecutionMetrics {
     sandboxed_execution_sandbox_execute_duration: HistogramVec,
     sandboxed_execution_sandbox_execute_run_duration: HistogramVec,
     sandboxed_execution_spawn_process: Histogram,
+    sandboxed_execution_sandbox_start_duration: HistogramVec,
+    sandboxed_execution_preforked_sandbox_processes: IntGauge,
     #[cfg(target_os = "linux")]
     sandboxed_execution_subprocess_anon_rss_total: IntGauge,
     #[cfg(target_os = "linux")]
@@ -153,6 +154,16 @@ impl SandboxedExecutionMetrics {
                 "The time to spawn a sandbox process",
                 decimal_buckets_with_zero(-4, 1),
             ),
+            sandboxed_execution_sandbox_start_duration: metrics_registry.histogram_vec(
+                "sandboxed_execution_sandbox_start_duration_seconds",
+                "The time until sending an execution request to the sandbox process by the way the process was obtained",
+                decimal_buckets_with_zero(-4, 1),
+                &["start_type"],
+            ),
+            sandboxed_execution_preforked_sandbox_processes: metrics_registry.int_gauge(
+                "sandboxed_execution_preforked_sandbox_processes",
+                "The number of idle pre-forked sandbox processes not assigned to any canister",
+            ),
             #[cfg(target_os = "linux")]
             sandboxed_execution_subprocess_anon_rss_total: metrics_registry.int_gauge(
                 "sandboxed_execution_subprocess_anon_rss_total_kib",
@@ -450,6 +461,31 @@ enum Backend {
 #[derive(Clone)]
 struct SandboxProcessStats {
     last_used: std::time::Instant,
+    /// The time it took to get the sandbox process ready for the first
+    /// execution of the canister. Used to avoid evicting processes that are
+    /// expensive to warm up again.
+    warmup_cost: Duration,
+}
+
+/// Describes how the sandbox process for an execution was obtained.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SandboxStart {
+    /// The canister already had a sandbox process.
+    Warm,
+    /// A pre-forked sandbox process was assigned to the canister.
+    Pooled,
+    /// A new sandbox process was spawned for the canister.
+    Cold,
+}
+
+impl SandboxStart {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SandboxStart::Warm => "warm",
+            SandboxStart::Pooled => "pooled",
+            SandboxStart::Cold => "cold",
+        }
+    }
 }
 
 enum SandboxProcessStatus {
@@ -627,6 +663,8 @@ pub struct SandboxedExecutionController {
     /// - An entry is removed from the registry only if it is in the `evicted`
     /// state and the strong reference count reaches zero.
     backends: Arc<Mutex<HashMap<CanisterId, Backend>>>,
+    /// Idle sandbox processes that are not assigned to any canister yet.
+    pool: Arc<Mutex<SandboxProcessPool<Arc<SandboxProcess>>>>,
     min_sandbox_count: usize,
     max_sandbox_count: usize,
     max_sandbox_idle_time: Duration,
@@ -636,7 +674,7 @@ pub struct SandboxedExecutionController {
     /// the same for all canisters.
     sandbox_exec_argv: Vec<String>,
     metrics: Arc<SandboxedExecutionMetrics>,
-    launcher_service: Box<dyn LauncherService>,
+    launcher_service: Arc<dyn LauncherService>,
     fd_factory: Arc<dyn PageAllocatorFileDescriptor>,
 }
 
@@ -645,6 +683,9 @@ impl Drop for SandboxedExecutionController {
         // Evict all the sandbox processes.
         let mut guard = self.backends.lock().unwrap();
         evict_sandbox_processes(&mut guard, 0, 0, Duration::default());
+        drop(guard);
+        // Terminate the idle pre-forked processes.
+        self.pool.lock().unwrap().shrink_to(0);
 
         // Terminate the Sandbox Launcher process.
         self.launcher_service
@@ -693,7 +734,8 @@ impl WasmExecutor for SandboxedExecutionController {
         };
 
         // Determine which process we want to run this on.
-        let sandbox_process = self.get_sandbox_process(sandbox_safe_system_state.canister_id());
+        let (sandbox_process, sandbox_start) =
+            self.get_sandbox_process(sandbox_safe_system_state.canister_id());
 
         // Ensure that Wasm is compiled.
         let (wasm_id, compilation_result) = match open_wasm(
@@ -768,6 +810,14 @@ impl WasmExecutor for SandboxedExecutionController {
             })
             .on_completion(|_| {});
         drop(prepare_timer);
+        let start_duration = execution_start.elapsed();
+        self.metrics
+            .sandboxed_execution_sandbox_start_duration
+            .with_label_values(&[sandbox_start.as_str()])
+            .observe(start_duration.as_secs_f64());
+        if sandbox_start != SandboxStart::Warm {
+            self.record_warmup_cost(canister_id, start_duration);
+        }
 
         let wait_timer = self
             .metrics
@@ -810,7 +860,8 @@ impl WasmExecutor for SandboxedExecutionController {
             .metrics
             .sandboxed_execution_replica_create_exe_state_duration
             .start_timer();
-        let sandbox_process = self.get_sandbox_process(canister_id);
+        let create_start = Instant::now();
+        let (sandbox_process, sandbox_start) = self.get_sandbox_process(canister_id);
         let wasm_binary = WasmBinary::new(canister_module);
 
         // The sandbox process prepares wasm memory, instantiates page maps
@@ -946,6 +997,9 @@ impl WasmExecutor for SandboxedExecutionController {
             stable_memory_page_map,
             ic_replicated_state::NumWasmPages::from(0),
         );
+        if sandbox_start != SandboxStart::Warm {
+            self.record_warmup_cost(canister_id, create_start.elapsed());
+        }
         let execution_state = ExecutionState::new(
             canister_root,
             wasm_binary,
@@ -1011,9 +1065,27 @@ impl SandboxedExecutionController {
         let sandbox_exec_argv =
             create_sandbox_argv(embedder_config).expect("No canister_sandbox binary found");
         let backends = Arc::new(Mutex::new(HashMap::new()));
+        let pool = Arc::new(Mutex::new(SandboxProcessPool::new(
+            embedder_config.max_preforked_sandbox_count,
+        )));
         let metrics = Arc::new(SandboxedExecutionMetrics::new(metrics_registry));
 
+        let exit_watcher = Arc::new(ExitWatcher {
+            logger: logger.clone(),
+            backends: Arc::clone(&backends),
+        });
+
+        let (launcher_service, mut child) = spawn_launcher_process(
+            &launcher_exec_argv[0],
+            &launcher_exec_argv[1..],
+            exit_watcher,
+        )?;
+        let launcher_service: Arc<dyn LauncherService> = Arc::from(launcher_service);
+
         let backends_copy = Arc::clone(&backends);
+        let pool_copy = Arc::clone(&pool);
+        let launcher_service_copy = Arc::clone(&launcher_service);
+        let sandbox_exec_argv_copy = sandbox_exec_argv.clone();
         let metrics_copy = Arc::clone(&metrics);
         let logger_copy = logger.clone();
 
@@ -1021,6 +1093,9 @@ impl SandboxedExecutionController {
             SandboxedExecutionController::monitor_and_evict_sandbox_processes(
                 logger_copy,
                 backends_copy,
+                pool_copy,
+                launcher_service_copy,
+                sandbox_exec_argv_copy,
                 metrics_copy,
                 min_sandbox_count,
                 max_sandbox_count,
@@ -1028,17 +1103,6 @@ impl SandboxedExecutionController {
             );
         });
 
-        let exit_watcher = Arc::new(ExitWatcher {
-            logger: logger.clone(),
-            backends: Arc::clone(&backends),
-        });
-
-        let (launcher_service, mut child) = spawn_launcher_process(
-            &launcher_exec_argv[0],
-            &launcher_exec_argv[1..],
-            exit_watcher,
-        )?;
-
         // We spawn a thread to wait for the exit notification of the launcher
         // process.
         thread::spawn(move || {
@@ -1050,6 +1114,7 @@ impl SandboxedExecutionController {
 
         Ok(Self {
             backends,
+            pool,
             min_sandbox_count,
             max_sandbox_count,
             max_sandbox_idle_time,
@@ -1064,11 +1129,15 @@ impl SandboxedExecutionController {
 
     // Periodically walk through all the backend processes and:
     // - evict inactive processes,
+    // - resize the pool of pre-forked processes to the recent demand,
     // - update memory usage metrics.
+    #[allow(clippy::too_many_arguments)]
     fn monitor_and_evict_sandbox_processes(
-        // `logger` isn't used on MacOS.
-        #[allow(unused_variables)] logger: ReplicaLogger,
+        logger: ReplicaLogger,
         backends: Arc<Mutex<HashMap<CanisterId, Backend>>>,
+        pool: Arc<Mutex<SandboxProcessPool<Arc<SandboxProcess>>>>,
+        launcher_service: Arc<dyn LauncherService>,
+        sandbox_exec_argv: Vec<String>,
         metrics: Arc<SandboxedExecutionMetrics>,
         min_sandbox_count: usize,
         max_sandbox_count: usize,
@@ -1172,6 +1241,11 @@ impl SandboxedExecutionController {
                 );
             }
 
+            refill_sandbox_process_pool(&pool, &*launcher_service, &sandbox_exec_argv, &logger);
+            metrics
+                .sandboxed_execution_preforked_sandbox_processes
+                .set(pool.lock().unwrap().len() as i64);
+
             // Collect metrics sufficiently infrequently that it does not use
             // excessive compute resources. It might be sensible to scale this
             // based on the time measured to perform the collection and e.g.
@@ -1181,7 +1255,7 @@ impl SandboxedExecutionController {
         }
     }
 
-    fn get_sandbox_process(&self, canister_id: CanisterId) -> Arc<SandboxProcess> {
+    fn get_sandbox_process(&self, canister_id: CanisterId) -> (Arc<SandboxProcess>, SandboxStart) {
         let mut guard = self.backends.lock().unwrap();
 
         if let Some(backend) = (*guard).get_mut(&canister_id) {
@@ -1197,24 +1271,26 @@ impl SandboxedExecutionController {
                 } => sandbox_process.upgrade().map(|p| (p, stats)),
                 Backend::Empty => None,
             };
-            if let Some((sandbox_process, _stats)) = sandbox_process_and_stats {
-                let now = std::time::Instant::now();
+            if let Some((sandbox_process, stats)) = sandbox_process_and_stats {
+                let stats = SandboxProcessStats {
+                    last_used: std::time::Instant::now(),
+                    warmup_cost: stats.warmup_cost,
+                };
                 if self.max_sandbox_count > 0 {
                     *backend = Backend::Active {
                         sandbox_process: Arc::clone(&sandbox_process),
-                        stats: SandboxProcessStats { last_used: now },
+                        stats,
                     };
                 } else {
                     *backend = Backend::Evicted {
                         sandbox_process: Arc::downgrade(&sandbox_process),
-                        stats: SandboxProcessStats { last_used: now },
+                        stats,
                     };
                 }
-                return sandbox_process;
+                return (sandbox_process, SandboxStart::Warm);
             }
         }
 
-        let _timer = self.metrics.sandboxed_execution_spawn_process.start_timer();
         if guard.len() > self.max_sandbox_count {
             let to_evict = self.max_sandbox_count * SANDBOX_PROCESS_EVICTION_PERCENT / 100;
             let max_active_sandboxes = self.max_sandbox_count.saturating_sub(to_evict);
@@ -1226,33 +1302,47 @@ impl SandboxedExecutionController {
             );
         }
 
-        // No sandbox process found for this canister. Start a new one and register it.
-        let reg = Arc::new(ActiveExecutionStateRegistry::new());
-        let controller_service = ControllerServiceImpl::new(Arc::clone(&reg), self.logger.clone());
-
-        let (sandbox_service, pid) = create_sandbox_process(
-            controller_service,
-            &*self.launcher_service,
-            canister_id,
-            self.sandbox_exec_argv.clone(),
-        )
-        .unwrap();
-
-        let sandbox_process = Arc::new(SandboxProcess {
-            execution_states: reg,
-            sandbox_service,
-            pid,
-            history: SandboxProcessRequestHistory::new(),
-        });
-
+        // No sandbox process found for this canister. Take a pre-forked one
+        // from the pool or start a new one and register it.
         let now = std::time::Instant::now();
+        let pooled = self.pool.lock().unwrap().take(now);
+        let (sandbox_process, sandbox_start) = match pooled {
+            Some(sandbox_process) => (sandbox_process, SandboxStart::Pooled),
+            None => {
+                let _timer = self.metrics.sandboxed_execution_spawn_process.start_timer();
+                let sandbox_process = spawn_sandbox_process(
+                    &*self.launcher_service,
+                    self.sandbox_exec_argv.clone(),
+                    Some(canister_id),
+                    &self.logger,
+                )
+                .unwrap();
+                (sandbox_process, SandboxStart::Cold)
+            }
+        };
+
         let backend = Backend::Active {
             sandbox_process: Arc::clone(&sandbox_process),
-            stats: SandboxProcessStats { last_used: now },
+            stats: SandboxProcessStats {
+                last_used: now,
+                warmup_cost: Duration::ZERO,
+            },
         };
         (*guard).insert(canister_id, backend);
 
-        sandbox_process
+        (sandbox_process, sandbox_start)
+    }
+
+    // Remembers how long it took to get a newly assigned sandbox process of
+    // the canister ready for execution.
+    fn record_warmup_cost(&self, canister_id: CanisterId, warmup_cost: Duration) {
+        let mut guard = self.backends.lock().unwrap();
+        match guard.get_mut(&canister_id) {
+            Some(Backend::Active { stats, .. }) | Some(Backend::Evicted { stats, .. }) => {
+                stats.warmup_cost = warmup_cost;
+            }
+            Some(Backend::Empty) | None => {}
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -1405,6 +1495,59 @@ impl SandboxedExecutionController {
     }
 }
 
+/// Spawns a new sandbox process. The process is not assigned to any canister
+/// if `canister_id` is `None`.
+fn spawn_sandbox_process(
+    launcher_service: &dyn LauncherService,
+    sandbox_exec_argv: Vec<String>,
+    canister_id: Option<CanisterId>,
+    logger: &ReplicaLogger,
+) -> std::io::Result<Arc<SandboxProcess>> {
+    let reg = Arc::new(ActiveExecutionStateRegistry::new());
+    let controller_service = ControllerServiceImpl::new(Arc::clone(&reg), logger.clone());
+
+    let (sandbox_service, pid) = create_sandbox_process(
+        controller_service,
+        launcher_service,
+        canister_id,
+        sandbox_exec_argv,
+    )?;
+
+    Ok(Arc::new(SandboxProcess {
+        execution_states: reg,
+        sandbox_service,
+        pid,
+        history: SandboxProcessRequestHistory::new(),
+    }))
+}
+
+// Brings the pool of pre-forked sandbox processes to the size expected by the
+// recent demand. New processes are spawned without holding the pool lock, so
+// that executions are not blocked by the refill.
+fn refill_sandbox_process_pool(
+    pool: &Mutex<SandboxProcessPool<Arc<SandboxProcess>>>,
+    launcher_service: &dyn LauncherService,
+    sandbox_exec_argv: &[String],
+    logger: &ReplicaLogger,
+) {
+    let missing = {
+        let mut guard = pool.lock().unwrap();
+        let target_size = guard.target_size(Instant::now(), SANDBOX_PROCESS_UPDATE_INTERVAL);
+        // Dropping the surplus processes terminates them.
+        guard.shrink_to(target_size);
+        target_size - guard.len()
+    };
+    for _ in 0..missing {
+        match spawn_sandbox_process(launcher_service, sandbox_exec_argv.to_vec(), None, logger) {
+            Ok(sandbox_process) => pool.lock().unwrap().add(sandbox_process),
+            Err(err) => {
+                error!(logger, "Failed to pre-fork a sandbox process: {}", err);
+                return;
+            }
+        }
+    }
+}
+
 /// Cache the sandbox process and wasm id of the opened wasm in the embedder
 /// cache.
 fn cache_opened_wasm(
@@ -1587,6 +1730,7 @@ fn evict_sandbox_processes(
             Backend::Active { stats, .. } => Some(EvictionCandidate {
                 id: *id,
                 last_used: stats.last_used,
+                warmup_cost: stats.warmup_cost,
             }),
             Backend::Evicted { .. } | Backend::Empty => None,
         })
@@ -1699,22 +1843,21 @@ impl ControllerLauncherService for ExitWatcher {
         req: protocol::ctllaunchersvc::SandboxExitedRequest,
     ) -> ic_canister_sandbox_common::rpc::Call<protocol::ctllaunchersvc::SandboxExitedReply> {
         let guard = self.backends.lock().unwrap();
-        let sandbox_process = match guard.get(&req.canister_id).unwrap_or_else(|| {
-            panic!(
-                "Sandbox exited for unrecognized canister id {}",
-                req.canister_id,
-            )
-        }) {
-            Backend::Active {
-                sandbox_process, ..
-            } => sandbox_process,
-            Backend::Evicted { .. } | Backend::Empty => {
-                return rpc::Call::new_resolved(Ok(protocol::ctllaunchersvc::SandboxExitedReply));
-            }
-        };
-        sandbox_process
-            .history
-            .replay(&self.logger, req.canister_id, sandbox_process.pid);
+        // Only active sandbox processes are expected to be alive. Evicted and
+        // pre-forked processes have no history worth replaying.
+        let exited = guard
+            .iter()
+            .find_map(|(canister_id, backend)| match backend {
+                Backend::Active {
+                    sandbox_process, ..
+                } if sandbox_process.pid == req.pid => Some((canister_id, sandbox_process)),
+                Backend::Active { .. } | Backend::Evicted { .. } | Backend::Empty => None,
+            });
+        if let Some((canister_id, sandbox_process)) = exited {
+            sandbox_process
+                .history
+                .replay(&self.logger, *canister_id, sandbox_process.pid);
+        }
         rpc::Call::new_resolved(Ok(protocol::ctllaunchersvc::SandboxExitedReply))
     }
 }
@@ -71,7 +71,7 @@ pub fn run_launcher(socket: std::os::unix::net::UnixStream) {
 }
 
 pub struct LauncherServer {
-    pid_to_canister_id: Arc<Mutex<HashMap<Pid, CanisterId>>>,
+    pid_to_canister_id: Arc<Mutex<HashMap<Pid, Option<CanisterId>>>>,
     has_children: Arc<Condvar>,
 }
 
@@ -108,17 +108,23 @@ impl LauncherServer {
                             .pid()
                             .expect("WaitStatus is not StillAlive so it should have a pid");
                         let mut canister_ids = watcher_canister_id_map.lock().unwrap();
-                        let canister_id = canister_ids.remove(&pid);
-                        eprintln!(
-                            "Sandbox pid {} for canister {:?} exited unexpectedly with status {:?}",
-                            pid, canister_id, status
-                        );
-                        // If we have a canister id, tell the replica process to print its history.
-                        if let Some(canister_id) = canister_id {
+                        // If we launched this process, tell the replica process to print its history.
+                        if let Some(canister_id) = canister_ids.remove(&pid) {
+                            eprintln!(
+                                "Sandbox pid {} for canister {:?} exited unexpectedly with status {:?}",
+                                pid, canister_id, status
+                            );
                             controller
-                                .sandbox_exited(SandboxExitedRequest { canister_id })
+                                .sandbox_exited(SandboxExitedRequest {
+                                    pid: pid.as_raw() as u32,
+                                })
                                 .sync()
                                 .unwrap();
+                        } else {
+                            eprintln!(
+                                "Sandbox pid {} exited unexpectedly with status {:?}",
+                                pid, status
+                            );
                         }
                         panic!("Launcher detected sandbox exit");
                     }
@@ -17,6 +17,7 @@ use crate::{
     nns_registry_replicator::Config as NnsRegistryReplicatorConfig,
     registration::Config as RegistrationConfig,
     registry_client::Config as RegistryClientConfig,
+    staged_upgrade::Config as StagedUpgradeConfig,
     state_manager::Config as StateManagerConfig,
     transport::TransportConfig,
 };
@@ -49,6 +50,7 @@ pub struct Config {
     pub registration: RegistrationConfig,
     pub nns_registry_replicator: NnsRegistryReplicatorConfig,
     pub adapters_config: AdaptersConfig,
+    pub staged_upgrade: StagedUpgradeConfig,
 }
 
 /// Mirrors the Config struct except that fields are made optional. This is
@@ -72,6 +74,7 @@ pub struct ConfigOptional {
     pub registration: Option<RegistrationConfig>,
     pub nns_registry_replicator: Option<NnsRegistryReplicatorConfig>,
     pub adapters_config: Option<AdaptersConfig>,
+    pub staged_upgrade: Option<StagedUpgradeConfig>,
 }
 
 impl Config {
@@ -100,6 +103,7 @@ impl Config {
             registration: RegistrationConfig::default(),
             nns_registry_replicator: NnsRegistryReplicatorConfig::default(),
             adapters_config: AdaptersConfig::default(),
+            staged_upgrade: StagedUpgradeConfig::default(),
         }
     }
 
@@ -152,6 +156,7 @@ impl Config {
                 .nns_registry_replicator
                 .unwrap_or(default.nns_registry_replicator),
             adapters_config: cfg.adapters_config.unwrap_or(default.adapters_config),
+            staged_upgrade: cfg.staged_upgrade.unwrap_or(default.staged_upgrade),
         })
     }
 
@@ -357,6 +357,24 @@ pub const SAMPLE_CONFIG: &str = r#"
         // The canister http adapter socket file is: /ic-os/guestos/rootfs/systemd/system/ic-https-outcalls-adapter.socket
         https_outcalls_uds_path: "/run/ic-node/https-outcalls-adapter/socket",
     },
+    // ====================================
+    // Staged replica upgrades
+    // ====================================
+    staged_upgrade: {
+        // Percentage of the subnet's nodes that upgrade first, at least a consensus quorum.
+        // 100 upgrades all nodes at once.
+        canary_percentage: 100,
+        // Seconds after observing a CUP of the canaries on the new version after which the
+        // remaining nodes upgrade.
+        canary_health_window_secs: 600,
+        // Seconds after the upgrade CUP after which the remaining nodes upgrade, even if the
+        // canaries did not produce a CUP on the new version.
+        canary_deadline_secs: 3600,
+        // Seconds a new version has to make progress before the node reverts to the previous image.
+        health_timeout_secs: 1800,
+        // Heights the finalized and certified heights have to advance by on a new version.
+        min_height_progress: 50,
+    },
 }
 "#;
 
@@ -51,6 +51,10 @@ pub(crate) const DEFAULT_MAX_SANDBOX_COUNT: usize = 2_000;
 /// duration and sandbox process eviction is activated.
 pub(crate) const DEFAULT_MAX_SANDBOX_IDLE_TIME: Duration = Duration::from_secs(30 * 60);
 
+/// The maximum number of idle sandbox processes that are started ahead of time
+/// so that a canister without a sandbox process does not have to wait for one.
+pub(crate) const DEFAULT_MAX_PREFORKED_SANDBOX_COUNT: usize = 8;
+
 #[allow(non_upper_case_globals)]
 const KiB: u64 = 1024;
 #[allow(non_upper_case_globals)]
@@ -156,6 +160,11 @@ pub struct Config {
     /// duration and sandbox process eviction is activated.
     pub max_sandbox_idle_time: Duration,
 
+    /// Upper bound on the number of idle, pre-forked sandbox processes that
+    /// are ready to take a canister. Within this bound, the pool is sized by
+    /// the recent rate of sandbox process launches. Zero disables pre-forking.
+    pub max_preforked_sandbox_count: usize,
+
     /// The type of the local subnet. The default value here should be replaced
     /// with the correct value at runtime when the hypervisor is created.
     pub subnet_type: SubnetType,
@@ -191,6 +200,7 @@ impl Config {
             min_sandbox_count: DEFAULT_MIN_SANDBOX_COUNT,
             max_sandbox_count: DEFAULT_MAX_SANDBOX_COUNT,
             max_sandbox_idle_time: DEFAULT_MAX_SANDBOX_IDLE_TIME,
+            max_preforked_sandbox_count: DEFAULT_MAX_PREFORKED_SANDBOX_COUNT,
             subnet_type: SubnetType::Application,
             dirty_page_overhead: NumInstructions::new(0),
             trace_execution: FlagStatus::Disabled,
@@ -5,7 +5,7 @@ use ic_types::{
     Cycles, NumBytes, NumInstructions, MAX_STABLE_MEMORY_IN_BYTES, MAX_WASM_MEMORY_IN_BYTES,
 };
 use serde::{Deserialize, Serialize};
-use std::{str::FromStr, time::Duration};
+use std::{path::PathBuf, str::FromStr, time::Duration};
 
 const MIB: u64 = 1024 * 1024;
 const GIB: u64 = MIB * 1024;
@@ -91,12 +91,27 @@ pub(crate) const QUERY_EXECUTION_THREADS_TOTAL: usize = 4;
 /// value increases the user-visible latency of the queries.
 const QUERY_SCHEDULING_TIME_SLICE_PER_CANISTER: Duration = Duration::from_millis(20);
 
+/// The maximum number of instructions a query or a query call graph may
+/// execute if query time slicing is enabled.
+const MAX_INSTRUCTIONS_PER_SLICED_QUERY: u64 = 20_000_000_000;
+
+/// The number of instructions a sliced query executes before it lets other
+/// waiting queries run on its thread. The value corresponds to roughly 50ms of
+/// execution and keeps the number of slices of the longest query well below
+/// the limit of the sandbox.
+const MAX_INSTRUCTIONS_PER_QUERY_SLICE: u64 = 100_000_000;
+
 /// The upper limit on how much memory query cache can occupy.
 ///
 /// The limit includes both cache keys and values, for successful query
 /// executions and user errors.
 const QUERY_CACHE_CAPACITY: NumBytes = NumBytes::new(100 * MIB);
 
+/// The upper limit on how long a query cache entry stays valid. Entries of
+/// queries that do not read the time stay valid across batches as long as the
+/// evaluated canisters do not change, but not longer than this limit.
+const QUERY_CACHE_MAX_AGE: Duration = Duration::from_secs(300);
+
 // The ID of the Bitcoin testnet canister.
 pub const BITCOIN_TESTNET_CANISTER_ID: &str = "g4xu7-jiaaa-aaaan-aaaaq-cai";
 
@@ -113,6 +128,13 @@ const BITCOIN_MAINNET_SOFT_LAUNCH_CANISTER_ID: &str = "gsvzx-syaaa-aaaan-aaabq-c
 /// The capacity of the Wasm compilation cache.
 pub const MAX_COMPILATION_CACHE_SIZE: NumBytes = NumBytes::new(10 * GIB);
 
+/// The capacity of the persisted Wasm compilation cache on disk.
+pub const MAX_COMPILATION_CACHE_DISK_SIZE: NumBytes = NumBytes::new(10 * GIB);
+
+/// The length of a query stats epoch in rounds. Nodes report the statistics
+/// of the queries they executed once per epoch.
+pub const QUERY_STATS_EPOCH_LENGTH: u64 = 2000;
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 #[serde(default)]
 pub struct Config {
@@ -173,6 +195,20 @@ pub struct Config {
     /// this amount of time.
     pub query_scheduling_time_slice_per_canister: Duration,
 
+    /// If this flag is enabled, then queries may execute up to
+    /// `max_instructions_per_sliced_query` instructions in slices of
+    /// `max_instructions_per_query_slice` instructions. Other waiting queries
+    /// are executed between the slices. It takes effect only if canister
+    /// sandboxing is enabled.
+    pub query_time_slicing: FlagStatus,
+
+    /// The instruction limit of a query and a query call graph if query time
+    /// slicing is enabled.
+    pub max_instructions_per_sliced_query: NumInstructions,
+
+    /// The instruction limit of a single slice of a query.
+    pub max_instructions_per_query_slice: NumInstructions,
+
     /// The maximum depth of a query call graph.
     pub max_query_call_graph_depth: usize,
 
@@ -218,11 +254,25 @@ pub struct Config {
     /// Query cache capacity in bytes
     pub query_cache_capacity: NumBytes,
 
+    /// The maximum age of a query cache entry in terms of the batch time.
+    pub query_cache_max_age: Duration,
+
     /// The capacity of the Wasm compilation cache.
     pub max_compilation_cache_size: NumBytes,
 
+    /// The directory in which compiled Wasm modules are persisted so that
+    /// they don't need to be recompiled after a restart. The compilation
+    /// cache is kept only in memory if not set.
+    pub compilation_cache_dir: Option<PathBuf>,
+
+    /// The capacity of the persisted Wasm compilation cache.
+    pub max_compilation_cache_disk_size: NumBytes,
+
     /// Indicate whether query stats should be collected or not.
     pub query_stats_aggregation: FlagStatus,
+
+    /// The length of a query stats epoch in rounds.
+    pub query_stats_epoch_length: u64,
 }
 
 impl Default for Config {
@@ -260,6 +310,13 @@ impl Default for Config {
             canister_sandboxing_flag: FlagStatus::Enabled,
             query_execution_threads_total: QUERY_EXECUTION_THREADS_TOTAL,
             query_scheduling_time_slice_per_canister: QUERY_SCHEDULING_TIME_SLICE_PER_CANISTER,
+            query_time_slicing: FlagStatus::Disabled,
+            max_instructions_per_sliced_query: NumInstructions::from(
+                MAX_INSTRUCTIONS_PER_SLICED_QUERY,
+            ),
+            max_instructions_per_query_slice: NumInstructions::from(
+                MAX_INSTRUCTIONS_PER_QUERY_SLICE,
+            ),
             max_query_call_graph_depth: MAX_QUERY_CALL_DEPTH,
             max_query_call_graph_instructions: NumInstructions::from(
                 MAX_INSTRUCTIONS_PER_COMPOSITE_QUERY_CALL,
@@ -286,8 +343,12 @@ impl Default for Config {
             composite_queries: FlagStatus::Enabled,
             query_caching: FlagStatus::Enabled,
             query_cache_capacity: QUERY_CACHE_CAPACITY,
+            query_cache_max_age: QUERY_CACHE_MAX_AGE,
             max_compilation_cache_size: MAX_COMPILATION_CACHE_SIZE,
+            compilation_cache_dir: None,
+            max_compilation_cache_disk_size: MAX_COMPILATION_CACHE_DISK_SIZE,
             query_stats_aggregation: FlagStatus::Disabled,
+            query_stats_epoch_length: QUERY_STATS_EPOCH_LENGTH,
         }
     }
 }
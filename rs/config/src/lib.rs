@@ -20,6 +20,7 @@ pub mod metrics;
 pub mod nns_registry_replicator;
 pub mod registration;
 pub mod registry_client;
+pub mod staged_upgrade;
 pub mod state_manager;
 pub mod transport;
 
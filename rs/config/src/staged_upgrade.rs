@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// Configuration of how the orchestrator rolls out replica version upgrades
+/// on an assigned node.
+///
+/// When `canary_percentage` is below 100, the nodes of a subnet upgrade in two
+/// stages once the upgrade CUP is reached: the canary nodes, always at least a
+/// consensus quorum, switch to the new version immediately. The remaining nodes
+/// follow once they observe a CUP produced by the canaries on the new version,
+/// and `canary_health_window_secs` after that. If the canaries do not produce
+/// such a CUP, e.g. because one of them is down, the remaining nodes upgrade
+/// `canary_deadline_secs` after the upgrade CUP. Independently of the stage, a
+/// node that just booted a new version stays on probation until the replica
+/// makes progress: if it does not within `health_timeout_secs`, and the subnet
+/// did not move past the upgrade CUP yet, the node reverts to the previous
+/// image and retries the version later, with a delay that doubles on every
+/// failure.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Percentage of the subnet's nodes (by position in the sorted membership)
+    /// that upgrade first. Lower values are raised to a consensus quorum. A
+    /// value of 100 upgrades all nodes at once.
+    pub canary_percentage: u8,
+
+    /// Time in seconds, counted from when a non-canary node first observes a
+    /// CUP produced by the canaries on the new version, after which it upgrades.
+    pub canary_health_window_secs: u64,
+
+    /// Time in seconds, counted from the time of the upgrade CUP, after which
+    /// non-canary nodes upgrade even if the canaries did not produce a CUP on
+    /// the new version yet. This bounds the time a single unresponsive canary
+    /// can keep the subnet from making progress.
+    pub canary_deadline_secs: u64,
+
+    /// Time in seconds a node on a new version has for the replica to start
+    /// and catch up before it is rolled back to the previous image. Also the
+    /// delay before the first retry of a reverted version.
+    pub health_timeout_secs: u64,
+
+    /// Number of heights both the finalized batch height and the certified
+    /// height have to advance by for a new version to be considered healthy.
+    pub min_height_progress: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            canary_percentage: 100,
+            canary_health_window_secs: 600,
+            canary_deadline_secs: 3600,
+            health_timeout_secs: 1800,
+            min_height_progress: 50,
+        }
+    }
+}
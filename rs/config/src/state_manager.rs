@@ -8,6 +8,15 @@ pub struct Config {
     /// A feature flag that enables/disables the file backed memory allocator.
     #[serde(default = "file_backed_memory_allocator_default")]
     pub file_backed_memory_allocator: FlagStatus,
+    /// A feature flag that enables/disables zstd compression of the protobuf
+    /// files written to checkpoints.
+    #[serde(default = "checkpoint_file_compression_default")]
+    pub checkpoint_file_compression: FlagStatus,
+    /// A feature flag that enables/disables serving compressed state sync
+    /// chunks. Receivers always accept compressed chunks, so this should only
+    /// be enabled once all replicas of the subnet can decode them.
+    #[serde(default = "state_sync_chunk_compression_default")]
+    pub state_sync_chunk_compression: FlagStatus,
 }
 
 impl Config {
@@ -15,6 +24,8 @@ impl Config {
         Self {
             state_root,
             file_backed_memory_allocator: file_backed_memory_allocator_default(),
+            checkpoint_file_compression: checkpoint_file_compression_default(),
+            state_sync_chunk_compression: state_sync_chunk_compression_default(),
         }
     }
 
@@ -32,3 +43,11 @@ impl Config {
 fn file_backed_memory_allocator_default() -> FlagStatus {
     FlagStatus::Enabled
 }
+
+fn checkpoint_file_compression_default() -> FlagStatus {
+    FlagStatus::Disabled
+}
+
+fn state_sync_chunk_compression_default() -> FlagStatus {
+    FlagStatus::Disabled
+}
@@ -366,6 +366,13 @@ pub struct CyclesAccountManagerConfig {
     /// Fee for storing a GiB of data per second.
     pub gib_storage_per_second_fee: Cycles,
 
+    /// The upper bound on the storage reservation period: the number of
+    /// seconds of storage fees that are reserved when memory grows on a
+    /// subnet whose memory usage is at full capacity. Below capacity the
+    /// period scales down linearly towards zero at the subnet memory
+    /// threshold.
+    pub max_storage_reservation_period: Duration,
+
     /// Fee for each percent of the reserved compute allocation. Note that
     /// reserved compute allocation is a scarce resource, and should be
     /// appropriately charged for.
@@ -414,6 +421,8 @@ impl CyclesAccountManagerConfig {
             ingress_byte_reception_fee: Cycles::new(2_000),
             // 4 SDR per GiB per year => 4e12 Cycles per year
             gib_storage_per_second_fee: Cycles::new(127_000),
+            // 10 years worth of storage fees.
+            max_storage_reservation_period: Duration::from_secs(300_000_000),
             duration_between_allocation_charges: Duration::from_secs(10),
             ecdsa_signature_fee: ECDSA_SIGNATURE_FEE,
             http_request_linear_baseline_fee: Cycles::new(3_000_000),
@@ -436,6 +445,7 @@ impl CyclesAccountManagerConfig {
             ingress_message_reception_fee: Cycles::new(0),
             ingress_byte_reception_fee: Cycles::new(0),
             gib_storage_per_second_fee: Cycles::new(0),
+            max_storage_reservation_period: Duration::from_secs(0),
             duration_between_allocation_charges: Duration::from_secs(10),
             /// The ECDSA signature fee is the fee charged when creating a
             /// signature on this subnet. The request likely came from a
@@ -22,6 +22,7 @@ use ic_ic00_types::IC_00;
 use ic_ingress_manager::IngressManager;
 use ic_interfaces::{
     artifact_pool::MutablePool,
+    batch_payload::ProposalContext,
     consensus::{PayloadBuilder, PayloadValidationError},
     consensus_pool::{ChangeAction, ChangeSet, ConsensusPool},
     time_source::TimeSource,
@@ -153,6 +154,7 @@ where
 
         let payload_builder = Arc::new(PayloadBuilderImpl::new(
             subnet_test_id(0),
+            node_test_id(0),
             registry_client,
             ingress_manager,
             Arc::new(FakeXNetPayloadBuilder::new()),
@@ -309,7 +311,10 @@ fn validate_payload(
         Height::from(CERTIFIED_HEIGHT + 1),
         payload,
         &past_payloads,
-        &validation_context,
+        &ProposalContext {
+            proposer: node_test_id(0),
+            validation_context: &validation_context,
+        },
     )
 }
 
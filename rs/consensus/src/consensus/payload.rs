@@ -3,7 +3,7 @@ use crate::consensus::metrics::{
 };
 use ic_consensus_utils::pool_reader::filter_past_payloads;
 use ic_interfaces::{
-    batch_payload::{BatchPayloadBuilder, PastPayload},
+    batch_payload::{BatchPayloadBuilder, PastPayload, ProposalContext},
     consensus::PayloadValidationError,
     ingress_manager::IngressSelector,
     messaging::XNetPayloadBuilder,
@@ -11,7 +11,7 @@ use ic_interfaces::{
 };
 use ic_logger::{error, ReplicaLogger};
 use ic_types::{
-    batch::{BatchPayload, IngressPayload, SelfValidatingPayload, ValidationContext, XNetPayload},
+    batch::{BatchPayload, IngressPayload, SelfValidatingPayload, XNetPayload},
     consensus::Payload,
     CountBytes, Height, NumBytes, Time,
 };
@@ -25,7 +25,7 @@ use std::sync::Arc;
 /// [`build_payload`](BatchPayloadSectionBuilder::build_payload)
 /// succeeds when passed into
 /// [`validate_payload`](BatchPayloadSectionBuilder::validate_payload),
-/// given the same arguments for [`ValidationContext`] and `past_payloads`,
+/// given the same arguments for [`ProposalContext`] and `past_payloads`,
 /// and that the following constraints are satisfied:
 ///
 /// - Payload size returned by [`build_payload`](BatchPayloadSectionBuilder::build_payload)
@@ -48,7 +48,7 @@ impl BatchPayloadSectionBuilder {
     /// Called to build the payload.
     ///
     /// # Arguments:
-    /// - `validation_context`: The [`ValidationContext`], under which the payload must be valid.
+    /// - `proposal_context`: The [`ProposalContext`], under which the payload must be valid.
     /// - `max_size`: The maximum size in [`NumBytes`], that the payload section has available in the current block.
     /// - `past_payloads`: All [`BatchPayload`]s from the certified height to the tip.
     /// - `logger`: Access to a [`ReplicaLogger`]
@@ -59,12 +59,13 @@ impl BatchPayloadSectionBuilder {
         &self,
         payload: &mut BatchPayload,
         height: Height,
-        validation_context: &ValidationContext,
+        proposal_context: &ProposalContext,
         max_size: NumBytes,
         past_payloads: &[(Height, Time, Payload)],
         metrics: &PayloadBuilderMetrics,
         logger: &ReplicaLogger,
     ) -> NumBytes {
+        let validation_context = proposal_context.validation_context;
         match self {
             Self::Ingress(builder) => {
                 let past_payloads = builder.filter_past_payloads(past_payloads, validation_context);
@@ -178,7 +179,7 @@ impl BatchPayloadSectionBuilder {
                     height,
                     &canister_http,
                     &past_payloads,
-                    validation_context,
+                    proposal_context,
                 ) {
                     Ok(()) => {
                         payload.canister_http = canister_http;
@@ -217,7 +218,7 @@ impl BatchPayloadSectionBuilder {
                     height,
                     &query_stats,
                     &past_payloads,
-                    validation_context,
+                    proposal_context,
                 ) {
                     Ok(()) => {
                         payload.query_stats = query_stats;
@@ -244,7 +245,7 @@ impl BatchPayloadSectionBuilder {
     ///
     /// # Argument:
     /// - `payload`: The payload to verify.
-    /// - `validation_context`: The [`ValidationContext`], under which to validate the payload.
+    /// - `proposal_context`: The [`ProposalContext`], under which to validate the payload.
     /// - `past_payloads`: All [`Payload`]s from the certified height to the tip.
     ///
     /// # Returns:
@@ -254,9 +255,10 @@ impl BatchPayloadSectionBuilder {
         &self,
         height: Height,
         payload: &BatchPayload,
-        validation_context: &ValidationContext,
+        proposal_context: &ProposalContext,
         past_payloads: &[(Height, Time, Payload)],
     ) -> Result<NumBytes, PayloadValidationError> {
+        let validation_context = proposal_context.validation_context;
         match self {
             Self::Ingress(builder) => {
                 let past_payloads = builder.filter_past_payloads(past_payloads, validation_context);
@@ -297,7 +299,7 @@ impl BatchPayloadSectionBuilder {
                     height,
                     &payload.canister_http,
                     &past_payloads,
-                    validation_context,
+                    proposal_context,
                 )?;
 
                 Ok(NumBytes::new(payload.canister_http.len() as u64))
@@ -316,7 +318,7 @@ impl BatchPayloadSectionBuilder {
                     height,
                     &payload.query_stats,
                     &past_payloads,
-                    validation_context,
+                    proposal_context,
                 )?;
 
                 Ok(NumBytes::new(payload.query_stats.len() as u64))
@@ -6,7 +6,7 @@ use crate::consensus::{
 };
 use ic_consensus_utils::get_subnet_record;
 use ic_interfaces::{
-    batch_payload::BatchPayloadBuilder,
+    batch_payload::{BatchPayloadBuilder, ProposalContext},
     consensus::{PayloadBuilder, PayloadPermanentError, PayloadValidationError},
     ingress_manager::IngressSelector,
     messaging::XNetPayloadBuilder,
@@ -21,13 +21,14 @@ use ic_types::{
     batch::{BatchPayload, ValidationContext, MAX_BITCOIN_PAYLOAD_IN_BYTES},
     consensus::{block_maker::SubnetRecords, Payload},
     messages::MAX_XNET_PAYLOAD_IN_BYTES,
-    Height, NumBytes, SubnetId, Time,
+    Height, NodeId, NumBytes, SubnetId, Time,
 };
 use std::sync::Arc;
 
 /// Implementation of PayloadBuilder.
 pub struct PayloadBuilderImpl {
     subnet_id: SubnetId,
+    node_id: NodeId,
     registry_client: Arc<dyn RegistryClient>,
     section_builder: Vec<BatchPayloadSectionBuilder>,
     metrics: PayloadBuilderMetrics,
@@ -38,6 +39,7 @@ impl PayloadBuilderImpl {
     /// Helper to create PayloadBuilder
     pub fn new(
         subnet_id: SubnetId,
+        node_id: NodeId,
         registry_client: Arc<dyn RegistryClient>,
         ingress_selector: Arc<dyn IngressSelector>,
         xnet_payload_builder: Arc<dyn XNetPayloadBuilder>,
@@ -57,6 +59,7 @@ impl PayloadBuilderImpl {
 
         Self {
             subnet_id,
+            node_id,
             registry_client,
             section_builder,
             metrics: PayloadBuilderMetrics::new(metrics),
@@ -93,13 +96,17 @@ impl PayloadBuilder for PayloadBuilderImpl {
 
         let mut batch_payload = BatchPayload::default();
         let mut accumulated_size = 0;
+        let proposal_context = ProposalContext {
+            proposer: self.node_id,
+            validation_context: context,
+        };
 
         for section_id in section_select {
             accumulated_size += self.section_builder[section_id]
                 .build_payload(
                     &mut batch_payload,
                     height,
-                    context,
+                    &proposal_context,
                     NumBytes::new(
                         max_block_payload_size
                             .get()
@@ -120,14 +127,14 @@ impl PayloadBuilder for PayloadBuilderImpl {
         height: Height,
         payload: &Payload,
         past_payloads: &[(Height, Time, Payload)],
-        context: &ValidationContext,
+        proposal_context: &ProposalContext,
     ) -> ValidationResult<PayloadValidationError> {
         let _timer = self.metrics.validate_payload_duration.start_timer();
         if payload.is_summary() {
             return Ok(());
         }
         let batch_payload = &payload.as_ref().as_data().batch;
-        let subnet_record = self.get_subnet_record(context)?;
+        let subnet_record = self.get_subnet_record(proposal_context.validation_context)?;
 
         // Retrieve max_block_payload_size from subnet
         let max_block_payload_size = self.get_max_block_payload_size_bytes(&subnet_record);
@@ -135,7 +142,7 @@ impl PayloadBuilder for PayloadBuilderImpl {
         let mut accumulated_size = NumBytes::new(0);
         for builder in &self.section_builder {
             accumulated_size +=
-                builder.validate_payload(height, batch_payload, context, past_payloads)?;
+                builder.validate_payload(height, batch_payload, proposal_context, past_payloads)?;
             if accumulated_size > max_block_payload_size {
                 return Err(ValidationError::Permanent(
                     PayloadPermanentError::PayloadTooBig {
@@ -253,6 +260,7 @@ pub(crate) mod test {
 
         PayloadBuilderImpl::new(
             subnet_test_id(0),
+            node_test_id(0),
             registry,
             Arc::new(ingress_selector),
             Arc::new(xnet_payload_builder),
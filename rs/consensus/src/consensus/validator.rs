@@ -20,6 +20,7 @@ use ic_consensus_utils::{
     RoundRobin,
 };
 use ic_interfaces::{
+    batch_payload::ProposalContext,
     consensus::{PayloadBuilder, PayloadPermanentError, PayloadTransientError},
     consensus_pool::*,
     dkg::DkgPool,
@@ -997,6 +998,7 @@ impl Validator {
         self.verify_artifact(pool_reader, proposal)?;
 
         // Ensure registry_version, certified_height and time are non-decreasing.
+        let proposer = proposal.signature.signer;
         let proposal = proposal.as_ref();
         if !proposal.context.greater_or_equal(&parent.context) {
             return Err(PermanentError::DecreasingValidationContext.into());
@@ -1035,7 +1037,10 @@ impl Validator {
                 proposal.height,
                 &proposal.payload,
                 &payloads,
-                &proposal.context,
+                &ProposalContext {
+                    proposer,
+                    validation_context: &proposal.context,
+                },
             )
             .map_err(|err| {
                 err.map(
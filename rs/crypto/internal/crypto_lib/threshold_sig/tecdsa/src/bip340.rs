@@ -0,0 +1,400 @@
+use crate::*;
+
+/// BIP-340 is only defined for secp256k1
+const BIP340_CURVE: EccCurveType = EccCurveType::K256;
+
+/// Compute a BIP-340 tagged hash
+///
+/// This is SHA-256(SHA-256(tag) || SHA-256(tag) || inputs...)
+fn bip340_tagged_hash(tag: &str, inputs: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = ic_crypto_sha2::Sha256::hash(tag.as_bytes());
+
+    let mut sha256 = ic_crypto_sha2::Sha256::new();
+    sha256.write(&tag_hash);
+    sha256.write(&tag_hash);
+    for input in inputs {
+        sha256.write(input);
+    }
+    sha256.finish()
+}
+
+/// Return true if the affine y coordinate of the point is even
+///
+/// BIP-340 represents public keys and the commitment R by their x
+/// coordinate alone, implicitly choosing the point with even y.
+fn has_even_y(pt: &EccPoint) -> ThresholdEcdsaResult<bool> {
+    if pt.is_infinity()? {
+        return Err(ThresholdEcdsaError::InvalidPoint);
+    }
+
+    // The compressed SEC1 encoding starts with 0x02 for even y and 0x03 for odd y
+    Ok(pt.serialize()[0] == 0x02)
+}
+
+/// Return the 32 byte x-only encoding used by BIP-340
+fn x_only_bytes(pt: &EccPoint) -> ThresholdEcdsaResult<Vec<u8>> {
+    if pt.is_infinity()? {
+        return Err(ThresholdEcdsaError::InvalidPoint);
+    }
+
+    Ok(pt.serialize()[1..].to_vec())
+}
+
+/// Negate the point unless it has an even y coordinate
+fn with_even_y(pt: &EccPoint) -> ThresholdEcdsaResult<EccPoint> {
+    if has_even_y(pt)? {
+        Ok(pt.clone())
+    } else {
+        Ok(pt.negate())
+    }
+}
+
+/// Compute the BIP-340 challenge e = H(R.x || P.x || msg) mod n
+fn bip340_challenge(
+    presig: &EccPoint,
+    public_key: &EccPoint,
+    message: &[u8],
+) -> ThresholdEcdsaResult<EccScalar> {
+    let e = bip340_tagged_hash(
+        "BIP0340/challenge",
+        &[&x_only_bytes(presig)?, &x_only_bytes(public_key)?, message],
+    );
+
+    EccScalar::from_bytes_wide(BIP340_CURVE, &e)
+}
+
+/// The values derived from the transcripts and the signing request
+/// which are shared by all steps of the signing protocol
+struct RerandomizedPresignature {
+    /// The public key derived from the master key and the derivation path
+    derived_key: EccPoint,
+    /// The additive tweak which derives `derived_key` from the master key
+    key_tweak: EccScalar,
+    /// The additive offset applied to the presignature nonce
+    presig_randomizer: EccScalar,
+    /// The presignature after rerandomization, ie R
+    randomized_presig: EccPoint,
+}
+
+impl RerandomizedPresignature {
+    fn compute(
+        message: &[u8],
+        randomness: &Randomness,
+        derivation_path: &DerivationPath,
+        key_transcript: &IDkgTranscriptInternal,
+        presig_transcript: &IDkgTranscriptInternal,
+    ) -> ThresholdEcdsaResult<Self> {
+        let pre_sig = match &presig_transcript.combined_commitment {
+            CombinedCommitment::ByInterpolation(PolynomialCommitment::Simple(c)) => {
+                c.constant_term()
+            }
+            _ => return Err(ThresholdEcdsaError::UnexpectedCommitmentType),
+        };
+
+        match &key_transcript.combined_commitment {
+            CombinedCommitment::ByInterpolation(PolynomialCommitment::Simple(_)) => {}
+            _ => return Err(ThresholdEcdsaError::UnexpectedCommitmentType),
+        }
+
+        if pre_sig.curve_type() != BIP340_CURVE
+            || key_transcript.constant_term().curve_type() != BIP340_CURVE
+        {
+            return Err(ThresholdEcdsaError::CurveMismatch);
+        }
+
+        let master_public_key = key_transcript.constant_term();
+        let (key_tweak, _chain_key) = derivation_path.derive_tweak(&master_public_key)?;
+        let derived_key = master_public_key.add_points(&EccPoint::mul_by_g(&key_tweak))?;
+
+        let mut ro = ro::RandomOracle::new("ic-crypto-schnorr-bip340-rerandomize-presig");
+        ro.add_bytestring("randomness", &randomness.get())?;
+        ro.add_bytestring("message", message)?;
+        ro.add_point("pre_sig", &pre_sig)?;
+        ro.add_scalar("key_tweak", &key_tweak)?;
+        let presig_randomizer = ro.output_scalar(BIP340_CURVE)?;
+
+        let randomized_presig = pre_sig.add_points(&EccPoint::mul_by_g(&presig_randomizer))?;
+
+        Ok(Self {
+            derived_key,
+            key_tweak,
+            presig_randomizer,
+            randomized_presig,
+        })
+    }
+
+    fn challenge(&self, message: &[u8]) -> ThresholdEcdsaResult<EccScalar> {
+        bip340_challenge(&self.randomized_presig, &self.derived_key, message)
+    }
+}
+
+/// A share of a threshold BIP-340 Schnorr signature
+///
+/// The key and the presignature are both unmasked IDKG transcripts, so
+/// that a share is a linear combination of the openings of the key and
+/// of the presignature nonce.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdBip340SignatureShareInternal {
+    s: EccScalar,
+}
+
+impl ThresholdBip340SignatureShareInternal {
+    /// Create a new BIP-340 signature share
+    ///
+    /// `key_opening` and `presig_opening` are our openings of the
+    /// commitments in `key_transcript` and `presig_transcript`.
+    pub fn new(
+        derivation_path: &DerivationPath,
+        message: &[u8],
+        randomness: Randomness,
+        key_transcript: &IDkgTranscriptInternal,
+        key_opening: &CommitmentOpening,
+        presig_transcript: &IDkgTranscriptInternal,
+        presig_opening: &CommitmentOpening,
+    ) -> ThresholdEcdsaResult<Self> {
+        let rerandomized = RerandomizedPresignature::compute(
+            message,
+            &randomness,
+            derivation_path,
+            key_transcript,
+            presig_transcript,
+        )?;
+
+        let key_share = match key_opening {
+            CommitmentOpening::Simple(s) => s,
+            _ => return Err(ThresholdEcdsaError::UnexpectedCommitmentType),
+        };
+
+        let presig_share = match presig_opening {
+            CommitmentOpening::Simple(s) => s,
+            _ => return Err(ThresholdEcdsaError::UnexpectedCommitmentType),
+        };
+
+        // Adding the same constant to every share adds it to the shared
+        // secret, since the Lagrange coefficients at zero sum to one.
+        let tweaked_key_share = key_share.add(&rerandomized.key_tweak)?;
+        let tweaked_presig_share = presig_share.add(&rerandomized.presig_randomizer)?;
+
+        // BIP-340 uses the secret key and nonce whose public points have
+        // even y coordinates, so negate the shares if required
+        let key_share = if has_even_y(&rerandomized.derived_key)? {
+            tweaked_key_share
+        } else {
+            tweaked_key_share.negate()
+        };
+
+        let presig_share = if has_even_y(&rerandomized.randomized_presig)? {
+            tweaked_presig_share
+        } else {
+            tweaked_presig_share.negate()
+        };
+
+        let e = rerandomized.challenge(message)?;
+
+        let s = presig_share.add(&e.mul(&key_share)?)?;
+
+        Ok(Self { s })
+    }
+
+    /// Verify a BIP-340 signature share
+    ///
+    /// The share is checked against the commitments of the key and
+    /// presignature transcripts evaluated at the index of the signer.
+    pub fn verify(
+        &self,
+        derivation_path: &DerivationPath,
+        message: &[u8],
+        randomness: Randomness,
+        signer_index: NodeIndex,
+        key_transcript: &IDkgTranscriptInternal,
+        presig_transcript: &IDkgTranscriptInternal,
+    ) -> ThresholdEcdsaResult<()> {
+        let rerandomized = RerandomizedPresignature::compute(
+            message,
+            &randomness,
+            derivation_path,
+            key_transcript,
+            presig_transcript,
+        )?;
+
+        let key_j = key_transcript
+            .evaluate_at(signer_index)?
+            .add_points(&EccPoint::mul_by_g(&rerandomized.key_tweak))?;
+        let presig_j = presig_transcript
+            .evaluate_at(signer_index)?
+            .add_points(&EccPoint::mul_by_g(&rerandomized.presig_randomizer))?;
+
+        let key_j = if has_even_y(&rerandomized.derived_key)? {
+            key_j
+        } else {
+            key_j.negate()
+        };
+
+        let presig_j = if has_even_y(&rerandomized.randomized_presig)? {
+            presig_j
+        } else {
+            presig_j.negate()
+        };
+
+        let e = rerandomized.challenge(message)?;
+
+        if EccPoint::mul_by_g(&self.s) != presig_j.add_points(&key_j.scalar_mul(&e)?)? {
+            return Err(ThresholdEcdsaError::InvalidSignatureShare);
+        }
+
+        Ok(())
+    }
+
+    pub fn serialize(&self) -> ThresholdEcdsaSerializationResult<Vec<u8>> {
+        serde_cbor::to_vec(self).map_err(|e| ThresholdEcdsaSerializationError(format!("{}", e)))
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> ThresholdEcdsaSerializationResult<Self> {
+        serde_cbor::from_slice::<Self>(bytes)
+            .map_err(|e| ThresholdEcdsaSerializationError(format!("{}", e)))
+    }
+}
+
+/// A threshold BIP-340 Schnorr signature
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdBip340CombinedSignatureInternal {
+    r: EccPoint,
+    s: EccScalar,
+}
+
+impl ThresholdBip340CombinedSignatureInternal {
+    /// Serialize in the 64 byte format used by BIP-340
+    pub fn serialize(&self) -> ThresholdEcdsaSerializationResult<Vec<u8>> {
+        let r_bytes = x_only_bytes(&self.r).map_err(|e| {
+            ThresholdEcdsaSerializationError(format!("Invalid presignature: {:?}", e))
+        })?;
+        let s_bytes = self.s.serialize();
+
+        let mut sig = Vec::with_capacity(r_bytes.len() + s_bytes.len());
+        sig.extend_from_slice(&r_bytes);
+        sig.extend_from_slice(&s_bytes);
+        Ok(sig)
+    }
+
+    /// Deserialize a signature in the 64 byte format used by BIP-340
+    pub fn deserialize(bytes: &[u8]) -> ThresholdEcdsaSerializationResult<Self> {
+        let curve_type = BIP340_CURVE;
+
+        if bytes.len() != curve_type.field_bytes() + curve_type.scalar_bytes() {
+            return Err(ThresholdEcdsaSerializationError(
+                "Bad signature length".to_string(),
+            ));
+        }
+
+        // The x-only encoding of R implies an even y coordinate
+        let mut r_bytes = Vec::with_capacity(curve_type.point_bytes());
+        r_bytes.push(0x02);
+        r_bytes.extend_from_slice(&bytes[..curve_type.field_bytes()]);
+
+        let r = EccPoint::deserialize(curve_type, &r_bytes)
+            .map_err(|e| ThresholdEcdsaSerializationError(format!("Invalid r: {:?}", e)))?;
+
+        let s = EccScalar::deserialize(curve_type, &bytes[curve_type.field_bytes()..])
+            .map_err(|e| ThresholdEcdsaSerializationError(format!("Invalid s: {:?}", e)))?;
+
+        Ok(Self { r, s })
+    }
+
+    /// Combine BIP-340 signature shares
+    ///
+    /// The signature shares must be verified prior to use, and there must
+    /// be at least reconstruction_threshold many of them.
+    pub fn new(
+        derivation_path: &DerivationPath,
+        message: &[u8],
+        randomness: Randomness,
+        key_transcript: &IDkgTranscriptInternal,
+        presig_transcript: &IDkgTranscriptInternal,
+        reconstruction_threshold: NumberOfNodes,
+        sig_shares: &BTreeMap<NodeIndex, ThresholdBip340SignatureShareInternal>,
+    ) -> ThresholdEcdsaResult<Self> {
+        let reconstruction_threshold = reconstruction_threshold.get() as usize;
+        if sig_shares.len() < reconstruction_threshold {
+            return Err(ThresholdEcdsaError::InsufficientDealings);
+        }
+
+        let rerandomized = RerandomizedPresignature::compute(
+            message,
+            &randomness,
+            derivation_path,
+            key_transcript,
+            presig_transcript,
+        )?;
+
+        let mut x_values = Vec::with_capacity(reconstruction_threshold);
+        let mut samples = Vec::with_capacity(reconstruction_threshold);
+
+        for (index, sig_share) in sig_shares.iter().take(reconstruction_threshold) {
+            x_values.push(*index);
+            samples.push(sig_share.s.clone());
+        }
+
+        let coefficients = LagrangeCoefficients::at_zero(BIP340_CURVE, &x_values)?;
+        let s = coefficients.interpolate_scalar(&samples)?;
+
+        Ok(Self {
+            r: with_even_y(&rerandomized.randomized_presig)?,
+            s,
+        })
+    }
+
+    /// Verify a threshold BIP-340 signature
+    ///
+    /// This not only verifies the BIP-340 signature equation but also that
+    /// the signature was generated with a particular presignature transcript.
+    pub fn verify(
+        &self,
+        derivation_path: &DerivationPath,
+        message: &[u8],
+        randomness: Randomness,
+        presig_transcript: &IDkgTranscriptInternal,
+        key_transcript: &IDkgTranscriptInternal,
+    ) -> ThresholdEcdsaResult<()> {
+        let rerandomized = RerandomizedPresignature::compute(
+            message,
+            &randomness,
+            derivation_path,
+            key_transcript,
+            presig_transcript,
+        )?;
+
+        if self.r != with_even_y(&rerandomized.randomized_presig)? {
+            return Err(ThresholdEcdsaError::InvalidSignature);
+        }
+
+        let e = rerandomized.challenge(message)?;
+        let public_key = with_even_y(&rerandomized.derived_key)?;
+
+        // s*G == R + e*P
+        if EccPoint::mul_by_g(&self.s) != self.r.add_points(&public_key.scalar_mul(&e)?)? {
+            return Err(ThresholdEcdsaError::InvalidSignature);
+        }
+
+        Ok(())
+    }
+}
+
+/// Derive the BIP-340 public key for a derivation path
+///
+/// Returns the 32 byte x-only encoding of the derived key, which is what
+/// BIP-340 signatures are verified against.
+pub fn derive_bip340_public_key(
+    key_transcript: &IDkgTranscriptInternal,
+    derivation_path: &DerivationPath,
+) -> ThresholdEcdsaResult<Vec<u8>> {
+    let master_public_key = key_transcript.constant_term();
+
+    if master_public_key.curve_type() != BIP340_CURVE {
+        return Err(ThresholdEcdsaError::CurveMismatch);
+    }
+
+    let (key_tweak, _chain_key) = derivation_path.derive_tweak(&master_public_key)?;
+    let derived_key = master_public_key.add_points(&EccPoint::mul_by_g(&key_tweak))?;
+
+    x_only_bytes(&derived_key)
+}
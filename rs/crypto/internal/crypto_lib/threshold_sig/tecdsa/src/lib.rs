@@ -69,6 +69,18 @@
 //! * Generation and verification of signature shares
 //! * Generation and verification of combined signatures
 //!
+//! ## Protocol: BIP-340 Schnorr Signature Generation and Verification
+//!
+//! File: `bip340.rs`
+//!
+//! Threshold BIP-340 Schnorr signatures over secp256k1. The key and the
+//! presignature nonce are both unmasked transcripts, so that a signature
+//! share is simply `k_i + e*x_i` (after rerandomization, key derivation,
+//! and the even-y normalization required by BIP-340).
+//!
+//! * Generation and verification of signature shares
+//! * Generation and verification of combined signatures
+//!
 //! ## Protocol: Multi-encryption gadget (MEGa)
 //!
 //! File: `mega.rs`
@@ -251,6 +263,7 @@ pub struct ThresholdEcdsaSerializationError(pub String);
 pub type ThresholdEcdsaSerializationResult<T> =
     std::result::Result<T, ThresholdEcdsaSerializationError>;
 
+pub mod bip340;
 mod complaints;
 mod dealings;
 mod fe;
@@ -265,6 +278,10 @@ pub mod test_utils;
 mod transcript;
 pub mod zk;
 
+pub use crate::bip340::{
+    derive_bip340_public_key, ThresholdBip340CombinedSignatureInternal,
+    ThresholdBip340SignatureShareInternal,
+};
 pub use crate::complaints::IDkgComplaintInternal;
 pub use crate::dealings::*;
 pub use crate::fe::*;
@@ -0,0 +1,192 @@
+use ic_crypto_internal_threshold_sig_ecdsa::*;
+use ic_crypto_test_utils_reproducible_rng::reproducible_rng;
+use ic_types::*;
+use rand::Rng;
+use std::collections::BTreeMap;
+
+mod test_utils;
+
+use crate::test_utils::*;
+
+fn random_subset(
+    shares: &BTreeMap<NodeIndex, ThresholdBip340SignatureShareInternal>,
+    include: usize,
+) -> BTreeMap<NodeIndex, ThresholdBip340SignatureShareInternal> {
+    assert!(include <= shares.len());
+
+    let mut rng = reproducible_rng();
+    let mut result = BTreeMap::new();
+
+    let keys = shares.keys().collect::<Vec<_>>();
+
+    while result.len() != include {
+        let key_to_add = keys[rng.gen::<usize>() % keys.len()];
+
+        if !result.contains_key(key_to_add) {
+            result.insert(*key_to_add, shares[key_to_add].clone());
+        }
+    }
+
+    result
+}
+
+#[test]
+fn should_basic_bip340_signing_protocol_work() -> Result<(), ThresholdEcdsaError> {
+    let nodes = 10;
+    let threshold = nodes / 3;
+    let number_of_dealings_corrupted = threshold;
+
+    let mut rng = reproducible_rng();
+    let random_seed = Seed::from_rng(&mut rng);
+
+    let setup = Bip340SignatureProtocolSetup::new(
+        nodes,
+        threshold,
+        number_of_dealings_corrupted,
+        random_seed,
+    )?;
+
+    let signed_message = rng.gen::<[u8; 32]>().to_vec();
+    let random_beacon = Randomness::from(rng.gen::<[u8; 32]>());
+
+    let derivation_path = DerivationPath::new_bip32(&[1, 2, 3]);
+    let proto = Bip340SignatureProtocolExecution::new(
+        setup.clone(),
+        signed_message.clone(),
+        random_beacon,
+        derivation_path.clone(),
+    );
+
+    let shares = proto.generate_shares()?;
+
+    for i in 0..=nodes {
+        let shares = random_subset(&shares, i);
+
+        if shares.len() < threshold {
+            assert!(proto.generate_signature(&shares).is_err());
+        } else {
+            let sig = proto.generate_signature(&shares)?;
+
+            let bytes = sig.serialize().expect("Serialization failed");
+            assert_eq!(bytes.len(), 64);
+            let sig2 = ThresholdBip340CombinedSignatureInternal::deserialize(&bytes)
+                .expect("Deserialization failed");
+            assert_eq!(sig, sig2);
+
+            assert_eq!(proto.verify_signature(&sig), Ok(()));
+        }
+    }
+
+    // Test that another run of the protocol generates signatures
+    // which are not verifiable in the earlier one (due to different R)
+    let random_beacon2 = Randomness::from(rng.gen::<[u8; 32]>());
+    let proto2 = Bip340SignatureProtocolExecution::new(
+        setup,
+        signed_message,
+        random_beacon2,
+        derivation_path,
+    );
+
+    let shares = proto2.generate_shares()?;
+    let sig = proto2.generate_signature(&shares)?;
+
+    assert!(proto.verify_signature(&sig).is_err());
+    assert_eq!(proto2.verify_signature(&sig), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn should_bip340_signatures_depend_on_message_and_derivation_path(
+) -> Result<(), ThresholdEcdsaError> {
+    let nodes = 4;
+    let threshold = 2;
+
+    let mut rng = reproducible_rng();
+    let setup = Bip340SignatureProtocolSetup::new(nodes, threshold, 0, Seed::from_rng(&mut rng))?;
+
+    let random_beacon = Randomness::from(rng.gen::<[u8; 32]>());
+
+    let proto = Bip340SignatureProtocolExecution::new(
+        setup.clone(),
+        b"message".to_vec(),
+        random_beacon,
+        DerivationPath::new_bip32(&[1]),
+    );
+    let sig = proto.generate_signature(&proto.generate_shares()?)?;
+    assert_eq!(proto.verify_signature(&sig), Ok(()));
+
+    let other_message = Bip340SignatureProtocolExecution::new(
+        setup.clone(),
+        b"other message".to_vec(),
+        random_beacon,
+        DerivationPath::new_bip32(&[1]),
+    );
+    assert_eq!(
+        other_message.verify_signature(&sig),
+        Err(ThresholdEcdsaError::InvalidSignature)
+    );
+
+    let other_path = Bip340SignatureProtocolExecution::new(
+        setup.clone(),
+        b"message".to_vec(),
+        random_beacon,
+        DerivationPath::new_bip32(&[2]),
+    );
+    assert_eq!(
+        other_path.verify_signature(&sig),
+        Err(ThresholdEcdsaError::InvalidSignature)
+    );
+
+    assert_ne!(
+        setup.public_key(&DerivationPath::new_bip32(&[1]))?,
+        setup.public_key(&DerivationPath::new_bip32(&[2]))?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn should_reject_bip340_share_of_another_signer() -> Result<(), ThresholdEcdsaError> {
+    let nodes = 4;
+    let threshold = 2;
+
+    let mut rng = reproducible_rng();
+    let setup = Bip340SignatureProtocolSetup::new(nodes, threshold, 0, Seed::from_rng(&mut rng))?;
+
+    let proto = Bip340SignatureProtocolExecution::new(
+        setup,
+        rng.gen::<[u8; 32]>().to_vec(),
+        Randomness::from(rng.gen::<[u8; 32]>()),
+        DerivationPath::new_bip32(&[]),
+    );
+
+    let shares = proto.generate_shares()?;
+
+    assert_eq!(
+        proto.verify_share(&shares[&0], 1),
+        Err(ThresholdEcdsaError::InvalidSignatureShare)
+    );
+
+    // A signature combined from a share that fails verification is invalid
+    let mut bad_shares = shares.clone();
+    bad_shares.insert(1, shares[&0].clone());
+    let sig = proto.generate_signature(&bad_shares)?;
+    assert_eq!(
+        proto.verify_signature(&sig),
+        Err(ThresholdEcdsaError::InvalidSignature)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn should_reject_malformed_bip340_signatures() {
+    assert!(ThresholdBip340CombinedSignatureInternal::deserialize(&[0u8; 63]).is_err());
+    assert!(ThresholdBip340CombinedSignatureInternal::deserialize(&[0u8; 65]).is_err());
+
+    // An x coordinate larger than the field modulus is not a valid point
+    let mut sig_with_invalid_r = [0u8; 64];
+    sig_with_invalid_r[..32].fill(0xFF);
+    assert!(ThresholdBip340CombinedSignatureInternal::deserialize(&sig_with_invalid_r).is_err());
+}
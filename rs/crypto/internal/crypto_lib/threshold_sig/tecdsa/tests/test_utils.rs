@@ -863,3 +863,187 @@ impl SignatureProtocolExecution {
         Ok(())
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct Bip340SignatureProtocolSetup {
+    setup: ProtocolSetup,
+    pub key: ProtocolRound,
+    pub presig: ProtocolRound,
+}
+
+impl Bip340SignatureProtocolSetup {
+    pub fn new(
+        number_of_dealers: usize,
+        threshold: usize,
+        number_of_dealings_corrupted: usize,
+        seed: Seed,
+    ) -> ThresholdEcdsaResult<Self> {
+        let setup = ProtocolSetup::new(EccCurveType::K256, number_of_dealers, threshold, seed)?;
+
+        // Both the key and the presignature are unmasked transcripts
+        let key = ProtocolRound::random(&setup, number_of_dealers, number_of_dealings_corrupted)?;
+        let presig =
+            ProtocolRound::random(&setup, number_of_dealers, number_of_dealings_corrupted)?;
+
+        let key = ProtocolRound::reshare_of_masked(
+            &setup,
+            &key,
+            number_of_dealers,
+            number_of_dealings_corrupted,
+        )?;
+        let presig = ProtocolRound::reshare_of_masked(
+            &setup,
+            &presig,
+            number_of_dealers,
+            number_of_dealings_corrupted,
+        )?;
+
+        Ok(Self { setup, key, presig })
+    }
+
+    pub fn public_key(&self, path: &DerivationPath) -> ThresholdEcdsaResult<Vec<u8>> {
+        derive_bip340_public_key(&self.key.transcript, path)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Bip340SignatureProtocolExecution {
+    setup: Bip340SignatureProtocolSetup,
+    signed_message: Vec<u8>,
+    random_beacon: Randomness,
+    derivation_path: DerivationPath,
+}
+
+impl Bip340SignatureProtocolExecution {
+    pub fn new(
+        setup: Bip340SignatureProtocolSetup,
+        signed_message: Vec<u8>,
+        random_beacon: Randomness,
+        derivation_path: DerivationPath,
+    ) -> Self {
+        Self {
+            setup,
+            signed_message,
+            random_beacon,
+            derivation_path,
+        }
+    }
+
+    pub fn generate_shares(
+        &self,
+    ) -> ThresholdEcdsaResult<BTreeMap<NodeIndex, ThresholdBip340SignatureShareInternal>> {
+        let mut shares = BTreeMap::new();
+
+        for node_index in 0..self.setup.setup.receivers {
+            let share = ThresholdBip340SignatureShareInternal::new(
+                &self.derivation_path,
+                &self.signed_message,
+                self.random_beacon,
+                &self.setup.key.transcript,
+                &self.setup.key.openings[node_index],
+                &self.setup.presig.transcript,
+                &self.setup.presig.openings[node_index],
+            )?;
+
+            self.verify_share(&share, node_index as NodeIndex)
+                .expect("Signature share verification failed");
+
+            shares.insert(node_index as NodeIndex, share);
+        }
+
+        Ok(shares)
+    }
+
+    pub fn verify_share(
+        &self,
+        share: &ThresholdBip340SignatureShareInternal,
+        signer_index: NodeIndex,
+    ) -> ThresholdEcdsaResult<()> {
+        share.verify(
+            &self.derivation_path,
+            &self.signed_message,
+            self.random_beacon,
+            signer_index,
+            &self.setup.key.transcript,
+            &self.setup.presig.transcript,
+        )
+    }
+
+    pub fn generate_signature(
+        &self,
+        shares: &BTreeMap<NodeIndex, ThresholdBip340SignatureShareInternal>,
+    ) -> ThresholdEcdsaResult<ThresholdBip340CombinedSignatureInternal> {
+        ThresholdBip340CombinedSignatureInternal::new(
+            &self.derivation_path,
+            &self.signed_message,
+            self.random_beacon,
+            &self.setup.key.transcript,
+            &self.setup.presig.transcript,
+            self.setup.setup.threshold,
+            shares,
+        )
+    }
+
+    pub fn verify_signature(
+        &self,
+        sig: &ThresholdBip340CombinedSignatureInternal,
+    ) -> ThresholdEcdsaResult<()> {
+        sig.verify(
+            &self.derivation_path,
+            &self.signed_message,
+            self.random_beacon,
+            &self.setup.presig.transcript,
+            &self.setup.key.transcript,
+        )?;
+
+        // If verification succeeded, also check the serialized signature
+        // with a plain BIP-340 verifier that knows nothing of the protocol
+        let pk = self.setup.public_key(&self.derivation_path)?;
+        let sig = sig
+            .serialize()
+            .map_err(|e| ThresholdEcdsaError::InvalidArguments(e.0))?;
+        assert!(verify_bip340_signature(&pk, &self.signed_message, &sig));
+
+        Ok(())
+    }
+}
+
+/// Verify a BIP-340 signature as specified in the BIP
+pub fn verify_bip340_signature(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    fn lift_x(x: &[u8]) -> Option<EccPoint> {
+        let mut sec1 = vec![0x02];
+        sec1.extend_from_slice(x);
+        EccPoint::deserialize(EccCurveType::K256, &sec1).ok()
+    }
+
+    if public_key.len() != 32 || signature.len() != 64 {
+        return false;
+    }
+
+    let (p, r) = match (lift_x(public_key), lift_x(&signature[..32])) {
+        (Some(p), Some(r)) => (p, r),
+        _ => return false,
+    };
+
+    let s = match EccScalar::deserialize(EccCurveType::K256, &signature[32..]) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+
+    let tag_hash = ic_crypto_sha2::Sha256::hash(b"BIP0340/challenge");
+    let mut challenge = ic_crypto_sha2::Sha256::new();
+    challenge.write(&tag_hash);
+    challenge.write(&tag_hash);
+    challenge.write(&signature[..32]);
+    challenge.write(public_key);
+    challenge.write(message);
+    let e = EccScalar::from_bytes_wide(EccCurveType::K256, &challenge.finish())
+        .expect("Failed to reduce challenge");
+
+    // R == s*G - e*P
+    let expected_r = EccPoint::mul_by_g(&s)
+        .sub_points(&p.scalar_mul(&e).expect("Failed to compute e*P"))
+        .expect("Failed to compute s*G - e*P");
+
+    expected_r == r
+}
@@ -0,0 +1,208 @@
+//! Explains the differences between two CatchUp packages.
+
+use ic_types::{
+    consensus::{ecdsa, CatchUpPackage, HasHeight},
+    crypto::threshold_sig::ni_dkg::{NiDkgTag, NiDkgTranscript},
+};
+use std::collections::BTreeMap;
+
+/// A property of a CUP that differs between two CUPs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    pub property: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+/// Describes the properties of `cup` that matter during a recovery: its
+/// height and versions, its state hash, and its DKG and ECDSA summaries.
+pub fn describe(cup: &CatchUpPackage) -> Vec<(&'static str, String)> {
+    let block = cup.content.block.as_ref();
+    let summary = block.payload.as_ref().as_summary();
+    let dkg = &summary.dkg;
+    let mut properties = vec![
+        ("height", cup.height().to_string()),
+        ("replica version", cup.content.version.to_string()),
+        (
+            "registry version",
+            block.context.registry_version.to_string(),
+        ),
+        (
+            "certified height",
+            block.context.certified_height.to_string(),
+        ),
+        ("time", block.context.time.to_string()),
+        (
+            "state hash",
+            hex::encode(&cup.content.state_hash.get_ref().0),
+        ),
+        ("dkg registry version", dkg.registry_version.to_string()),
+        ("dkg summary height", dkg.height.to_string()),
+        ("dkg interval length", dkg.interval_length.to_string()),
+        (
+            "dkg next interval length",
+            dkg.next_interval_length.to_string(),
+        ),
+        (
+            "dkg current transcripts",
+            transcript_ids(dkg.current_transcripts()),
+        ),
+        (
+            "dkg next transcripts",
+            transcript_ids(dkg.next_transcripts()),
+        ),
+        ("dkg configs", dkg.configs.len().to_string()),
+    ];
+    properties.extend(describe_ecdsa(&summary.ecdsa));
+    properties
+}
+
+fn transcript_ids(transcripts: &BTreeMap<NiDkgTag, NiDkgTranscript>) -> String {
+    transcripts
+        .values()
+        .map(|t| format!("{} (registry version {})", t.dkg_id, t.registry_version))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_ecdsa(summary: &ecdsa::Summary) -> Vec<(&'static str, String)> {
+    match summary {
+        None => vec![("ecdsa", "none".to_string())],
+        Some(payload) => vec![
+            ("ecdsa key id", payload.key_transcript.key_id.to_string()),
+            (
+                "ecdsa key transcript",
+                payload
+                    .key_transcript
+                    .current
+                    .as_ref()
+                    .map_or("none".to_string(), |t| format!("{:?}", t.transcript_id())),
+            ),
+            (
+                "ecdsa available quadruples",
+                payload.available_quadruples.len().to_string(),
+            ),
+            (
+                "ecdsa quadruples in creation",
+                payload.quadruples_in_creation.len().to_string(),
+            ),
+            (
+                "ecdsa ongoing signatures",
+                payload.ongoing_signatures.len().to_string(),
+            ),
+            (
+                "ecdsa signature agreements",
+                payload.signature_agreements.len().to_string(),
+            ),
+            (
+                "ecdsa ongoing xnet reshares",
+                payload.ongoing_xnet_reshares.len().to_string(),
+            ),
+        ],
+    }
+}
+
+/// Returns the properties, as listed by [`describe`], that differ between
+/// `left` and `right`. A property only one of the CUPs has, such as the
+/// ECDSA key id when only one of them has an ECDSA summary, is reported as
+/// `none` on the other side.
+pub fn diff(left: &CatchUpPackage, right: &CatchUpPackage) -> Vec<Difference> {
+    let left = describe(left);
+    let right = describe(right);
+    let value = |properties: &[(&'static str, String)], property: &str| {
+        properties
+            .iter()
+            .find(|(p, _)| *p == property)
+            .map_or_else(|| "none".to_string(), |(_, value)| value.clone())
+    };
+
+    let mut differences: Vec<Difference> = Vec::new();
+    for (property, _) in left.iter().chain(right.iter()) {
+        if differences.iter().any(|d| d.property == *property) {
+            continue;
+        }
+        let (left, right) = (value(&left, property), value(&right, property));
+        if left != right {
+            differences.push(Difference {
+                property,
+                left,
+                right,
+            });
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_test_utilities::consensus::make_genesis;
+    use ic_types::{
+        consensus::dkg::Summary,
+        crypto::{CryptoHash, CryptoHashOf},
+        Height, RegistryVersion,
+    };
+
+    fn cup(height: u64, registry_version: u64) -> CatchUpPackage {
+        let mut summary = Summary::fake();
+        summary.height = Height::from(height);
+        summary.registry_version = RegistryVersion::from(registry_version);
+        make_genesis(summary)
+    }
+
+    fn value(properties: &[(&'static str, String)], property: &str) -> String {
+        properties
+            .iter()
+            .find(|(p, _)| *p == property)
+            .map(|(_, value)| value.clone())
+            .unwrap_or_else(|| panic!("missing property {}", property))
+    }
+
+    #[test]
+    fn should_describe_cup() {
+        let mut cup = cup(100, 7);
+        cup.content.state_hash = CryptoHashOf::from(CryptoHash(vec![0xab, 0xcd]));
+
+        let properties = describe(&cup);
+
+        assert_eq!(value(&properties, "height"), "100");
+        assert_eq!(value(&properties, "registry version"), "7");
+        assert_eq!(value(&properties, "dkg registry version"), "7");
+        assert_eq!(value(&properties, "dkg summary height"), "100");
+        assert_eq!(value(&properties, "state hash"), "abcd");
+        assert_eq!(value(&properties, "ecdsa"), "none");
+    }
+
+    #[test]
+    fn should_find_no_differences_between_equal_cups() {
+        assert_eq!(diff(&cup(100, 7), &cup(100, 7)), vec![]);
+    }
+
+    #[test]
+    fn should_list_differing_properties() {
+        let left = cup(100, 7);
+        let mut right = cup(100, 8);
+        right.content.state_hash = CryptoHashOf::from(CryptoHash(vec![0xff]));
+
+        assert_eq!(
+            diff(&left, &right),
+            vec![
+                Difference {
+                    property: "registry version",
+                    left: "7".to_string(),
+                    right: "8".to_string(),
+                },
+                Difference {
+                    property: "state hash",
+                    left: String::new(),
+                    right: "ff".to_string(),
+                },
+                Difference {
+                    property: "dkg registry version",
+                    left: "7".to_string(),
+                    right: "8".to_string(),
+                },
+            ]
+        );
+    }
+}
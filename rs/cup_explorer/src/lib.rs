@@ -1,18 +1,17 @@
 use ic_canister_client::{Agent, Sender};
+use ic_protobuf::types::v1 as pb;
 use ic_protobuf::types::v1::CatchUpContent;
 use prost::Message;
 use reqwest::Url;
+use std::path::Path;
+
+pub mod diff;
+pub mod verify;
 
 /// Fetches the contents of a CatchUp package, if it's present.
 pub async fn get_catchup_content(url: &Url) -> Result<Option<CatchUpContent>, String> {
-    let agent = Agent::new(url.clone(), Sender::Anonymous);
-    let maybe_cup = agent
-        .query_cup_endpoint(None)
-        .await
-        .map_err(|e| format!("failed to get catch up package: {}", e))?;
-    match maybe_cup {
+    match get_catchup_package(url).await? {
         Some(cup) => {
-            // TODO(roman): verify signatures?
             let content = CatchUpContent::decode(&cup.content[..])
                 .map_err(|e| format!("failed to deserialize cup: {}", e))?;
             Ok(Some(content))
@@ -20,3 +19,22 @@ pub async fn get_catchup_content(url: &Url) -> Result<Option<CatchUpContent>, St
         None => Ok(None),
     }
 }
+
+/// Fetches a CatchUp package from a node, if it's present. The signature is
+/// not checked, see [`verify::verify_signature`].
+pub async fn get_catchup_package(url: &Url) -> Result<Option<pb::CatchUpPackage>, String> {
+    let agent = Agent::new(url.clone(), Sender::Anonymous);
+    agent
+        .query_cup_endpoint(None)
+        .await
+        .map_err(|e| format!("failed to get catch up package: {}", e))
+}
+
+/// Reads a CatchUp package from a file holding its binary protobuf encoding,
+/// as written by `ic-consensus-pool-util export-cup-proto`.
+pub fn read_catchup_package(path: &Path) -> Result<pb::CatchUpPackage, String> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| format!("failed to read cup from {}: {}", path.display(), e))?;
+    pb::CatchUpPackage::decode(&bytes[..])
+        .map_err(|e| format!("failed to deserialize cup from {}: {}", path.display(), e))
+}
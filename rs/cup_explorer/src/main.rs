@@ -1,12 +1,21 @@
-use ic_cup_explorer::get_catchup_content;
+use clap::{Parser, Subcommand};
+use ic_cup_explorer::{
+    get_catchup_content, get_catchup_package, read_catchup_package,
+    verify::{verify_signature, verify_state_hash},
+};
 use ic_protobuf::registry::{node::v1::NodeRecord, subnet::v1::SubnetRecord};
+use ic_protobuf::types::v1 as pb;
 use ic_registry_keys::{make_node_record_key, make_subnet_record_key};
 use ic_registry_nns_data_provider::registry::RegistryCanister;
-use ic_types::{NodeId, PrincipalId, SubnetId};
+use ic_types::{
+    consensus::{CatchUpPackage, HasHeight},
+    NodeId, PrincipalId, SubnetId,
+};
 use prost::Message;
 use reqwest::Url;
 use std::convert::TryFrom;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::task;
@@ -66,22 +75,178 @@ fn http_url(n: &NodeRecord) -> Url {
     .unwrap()
 }
 
+/// A CUP given on the command line, either as the URL of a node to fetch the
+/// latest CUP from, or as the path of a file holding a CUP protobuf.
+#[derive(Clone, Debug)]
+enum CupSource {
+    Node(Url),
+    File(PathBuf),
+}
+
+impl FromStr for CupSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            Url::parse(s)
+                .map(CupSource::Node)
+                .map_err(|e| format!("failed to parse node url {}: {}", s, e))
+        } else {
+            Ok(CupSource::File(PathBuf::from(s)))
+        }
+    }
+}
+
+impl CupSource {
+    async fn load(&self) -> pb::CatchUpPackage {
+        match self {
+            CupSource::Node(url) => get_catchup_package(url)
+                .await
+                .unwrap_or_else(|e| panic!("{}", e))
+                .unwrap_or_else(|| panic!("node {} has no cup yet", url)),
+            CupSource::File(path) => read_catchup_package(path).unwrap_or_else(|e| panic!("{}", e)),
+        }
+    }
+}
+
+fn parse_subnet_id(s: &str) -> Result<SubnetId, String> {
+    PrincipalId::from_str(s)
+        .map(SubnetId::from)
+        .map_err(|e| format!("failed to parse subnet id {}: {}", s, e))
+}
+
+#[derive(Parser)]
+#[clap(
+    name = "cup_explorer",
+    about = "Inspects and verifies catch up packages."
+)]
+struct CliArgs {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists the nodes of a subnet and the latest CUP each of them has.
+    Explore {
+        /// URL of the NNS registry.
+        registry_url: Url,
+        /// The subnet to explore.
+        #[clap(parse(try_from_str = parse_subnet_id))]
+        subnet_id: SubnetId,
+    },
+    /// Verifies the signature of a CUP against the subnet's threshold
+    /// signing key in the NNS registry and, optionally, its state hash
+    /// against a local checkpoint.
+    Verify {
+        /// URL of the NNS registry.
+        registry_url: Url,
+        /// The subnet the CUP belongs to.
+        #[clap(parse(try_from_str = parse_subnet_id))]
+        subnet_id: SubnetId,
+        /// The URL of a node to fetch the CUP from, or the path of a file
+        /// holding the CUP protobuf.
+        cup: CupSource,
+        /// The path of the checkpoint the CUP should correspond to.
+        #[clap(long)]
+        checkpoint: Option<PathBuf>,
+    },
+    /// Explains the differences between two CUPs.
+    Diff {
+        /// The URL of a node to fetch the first CUP from, or the path of a
+        /// file holding it.
+        left: CupSource,
+        /// The URL of a node to fetch the second CUP from, or the path of a
+        /// file holding it.
+        right: CupSource,
+    },
+}
+
 #[tokio::main]
 async fn main() {
-    let args: Vec<_> = std::env::args().collect();
-    if args.len() != 3 {
-        eprintln!("Usage: {} [REGISTRY_URL] [SUBNET_ID]", args[0]);
-        std::process::exit(1);
+    match CliArgs::parse().command {
+        Command::Explore {
+            registry_url,
+            subnet_id,
+        } => explore(registry_url, subnet_id).await,
+        Command::Verify {
+            registry_url,
+            subnet_id,
+            cup,
+            checkpoint,
+        } => verify(registry_url, subnet_id, cup, checkpoint).await,
+        Command::Diff { left, right } => diff(left, right).await,
     }
+}
 
-    let registry_url = Url::parse(&args[1][..])
-        .unwrap_or_else(|e| panic!("failed to parse registry url {}: {}", args[1], e));
+fn decode_cup(cup_proto: &pb::CatchUpPackage) -> CatchUpPackage {
+    CatchUpPackage::try_from(cup_proto)
+        .unwrap_or_else(|e| panic!("failed to deserialize cup: {:?}", e))
+}
 
-    let subnet_id = SubnetId::from(
-        PrincipalId::from_str(&args[2][..])
-            .unwrap_or_else(|e| panic!("failed to parse subnet id {}: {}", args[2], e)),
+async fn verify(
+    registry_url: Url,
+    subnet_id: SubnetId,
+    cup: CupSource,
+    checkpoint: Option<PathBuf>,
+) {
+    let registry_canister = RegistryCanister::new(vec![registry_url]);
+    let cup_proto = cup.load().await;
+    let cup = decode_cup(&cup_proto);
+    println!(
+        "CUP at height {} with state hash {}",
+        cup.height(),
+        hex::encode(&cup.content.state_hash.get_ref().0)
     );
 
+    let mut ok = true;
+    match verify_signature(&registry_canister, subnet_id, &cup_proto).await {
+        Ok(registry_version) => println!(
+            " ✔ signature: valid for the key of subnet {} at registry version {}",
+            subnet_id, registry_version
+        ),
+        Err(err) => {
+            ok = false;
+            println!(" ✘ signature: {}", err)
+        }
+    }
+    if let Some(checkpoint) = checkpoint {
+        match verify_state_hash(&cup, &checkpoint) {
+            Ok(()) => println!(
+                " ✔ state hash: matches the checkpoint at {}",
+                checkpoint.display()
+            ),
+            Err(err) => {
+                ok = false;
+                println!(" ✘ state hash: {}", err)
+            }
+        }
+    }
+    if !ok {
+        std::process::exit(1);
+    }
+}
+
+async fn diff(left: CupSource, right: CupSource) {
+    let left = decode_cup(&left.load().await);
+    let right = decode_cup(&right.load().await);
+    let differences = ic_cup_explorer::diff::diff(&left, &right);
+    if differences.is_empty() {
+        println!("The CUPs are identical in all compared properties.");
+        return;
+    }
+    let width = differences
+        .iter()
+        .map(|d| d.property.len())
+        .max()
+        .unwrap_or_default();
+    for d in differences {
+        println!("{:>width$}: {}", d.property, d.left, width = width);
+        println!("{:>width$}  {}", "", d.right, width = width);
+    }
+}
+
+async fn explore(registry_url: Url, subnet_id: SubnetId) {
     let registry_canister = Arc::new(RegistryCanister::new(vec![registry_url]));
 
     println!("Fetching the list of nodes on subnet {}...", subnet_id);
@@ -0,0 +1,270 @@
+//! Verification of a CatchUp package against the NNS registry and against
+//! a local checkpoint.
+
+use ic_crypto_utils_threshold_sig::verify_combined;
+use ic_interfaces_registry::RegistryClient;
+use ic_protobuf::registry::crypto::v1::PublicKey as PublicKeyProto;
+use ic_protobuf::types::v1 as pb;
+use ic_registry_keys::make_crypto_threshold_signing_pubkey_key;
+use ic_registry_nns_data_provider::registry::RegistryCanister;
+use ic_state_manager::manifest::{manifest_from_path, manifest_hash};
+use ic_types::{
+    consensus::{CatchUpContentProtobufBytes, CatchUpPackage},
+    crypto::{threshold_sig::ThresholdSigPublicKey, CombinedThresholdSig, CombinedThresholdSigOf},
+    RegistryVersion, SubnetId,
+};
+use prost::Message;
+use std::convert::TryFrom;
+use std::path::Path;
+
+/// Verifies the threshold signature on `cup_proto` against the threshold
+/// signing public key of `subnet_id`, as recorded in the registry at the
+/// registry version of the CUP's block. This is the same check the
+/// orchestrator performs on CUPs it fetches from peers.
+///
+/// Returns the registry version the key was read at.
+pub async fn verify_signature(
+    registry_canister: &RegistryCanister,
+    subnet_id: SubnetId,
+    cup_proto: &pb::CatchUpPackage,
+) -> Result<RegistryVersion, String> {
+    let registry_version = registry_version(cup_proto)?;
+    let (key_bytes, _) = registry_canister
+        .get_value(
+            make_crypto_threshold_signing_pubkey_key(subnet_id)
+                .as_bytes()
+                .to_vec(),
+            Some(registry_version.get()),
+        )
+        .await
+        .map_err(|e| {
+            format!(
+                "failed to get the threshold signing public key of subnet {} at registry version {}: {}",
+                subnet_id, registry_version, e
+            )
+        })?;
+    verify_signature_with_key(subnet_id, cup_proto, registry_version, &key_bytes)?;
+    Ok(registry_version)
+}
+
+/// Like [`verify_signature`], but reads the key from a snapshot of the
+/// registry, such as a local registry store, instead of the NNS.
+pub fn verify_signature_with_registry_client(
+    registry_client: &dyn RegistryClient,
+    subnet_id: SubnetId,
+    cup_proto: &pb::CatchUpPackage,
+) -> Result<RegistryVersion, String> {
+    let registry_version = registry_version(cup_proto)?;
+    let key_bytes = registry_client
+        .get_value(
+            &make_crypto_threshold_signing_pubkey_key(subnet_id),
+            registry_version,
+        )
+        .map_err(|e| {
+            format!(
+                "failed to get the threshold signing public key of subnet {} at registry version {}: {}",
+                subnet_id, registry_version, e
+            )
+        })?
+        .ok_or_else(|| {
+            format!(
+                "subnet {} has no threshold signing public key at registry version {}",
+                subnet_id, registry_version
+            )
+        })?;
+    verify_signature_with_key(subnet_id, cup_proto, registry_version, &key_bytes)?;
+    Ok(registry_version)
+}
+
+/// Returns the registry version of the block of `cup_proto`.
+fn registry_version(cup_proto: &pb::CatchUpPackage) -> Result<RegistryVersion, String> {
+    let cup = CatchUpPackage::try_from(cup_proto)
+        .map_err(|e| format!("failed to deserialize cup: {:?}", e))?;
+    Ok(cup.content.block.as_ref().context.registry_version)
+}
+
+/// Verifies the signature on `cup_proto` against the protobuf encoded
+/// threshold signing public key `key_bytes`.
+fn verify_signature_with_key(
+    subnet_id: SubnetId,
+    cup_proto: &pb::CatchUpPackage,
+    registry_version: RegistryVersion,
+    key_bytes: &[u8],
+) -> Result<(), String> {
+    let public_key = PublicKeyProto::decode(key_bytes)
+        .map_err(|e| format!("failed to decode threshold signing public key: {}", e))
+        .and_then(|key| {
+            ThresholdSigPublicKey::try_from(key)
+                .map_err(|e| format!("invalid threshold signing public key: {:?}", e))
+        })?;
+
+    verify_combined(
+        &CatchUpContentProtobufBytes(cup_proto.content.clone()),
+        &CombinedThresholdSigOf::new(CombinedThresholdSig(cup_proto.signature.clone())),
+        &public_key,
+    )
+    .map_err(|e| {
+        format!(
+            "invalid signature for the key of subnet {} at registry version {}: {}",
+            subnet_id, registry_version, e
+        )
+    })
+}
+
+/// Checks that the state hash of `cup` is the root hash of the manifest of
+/// the checkpoint rooted at `checkpoint_path`.
+pub fn verify_state_hash(cup: &CatchUpPackage, checkpoint_path: &Path) -> Result<(), String> {
+    let manifest = manifest_from_path(checkpoint_path).map_err(|e| {
+        format!(
+            "failed to compute the manifest of the checkpoint at {}: {}",
+            checkpoint_path.display(),
+            e
+        )
+    })?;
+    let root_hash = manifest_hash(&manifest);
+    let state_hash = &cup.content.state_hash.get_ref().0;
+    if &root_hash[..] != state_hash {
+        return Err(format!(
+            "state hash mismatch: the cup at height {} has {}, the checkpoint has {}",
+            cup.content.block.as_ref().height,
+            hex::encode(state_hash),
+            hex::encode(root_hash)
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_certification_test_utils::generate_root_of_trust;
+    use ic_crypto_internal_threshold_sig_bls12381::{
+        api::{combine_signatures, sign_message},
+        types::SecretKeyBytes,
+    };
+    use ic_protobuf::state::system_metadata::v1::SystemMetadata;
+    use ic_registry_client_fake::FakeRegistryClient;
+    use ic_registry_proto_data_provider::ProtoRegistryDataProvider;
+    use ic_test_utilities::{consensus::make_genesis, types::ids::subnet_test_id};
+    use ic_types::{
+        consensus::dkg::Summary,
+        crypto::{CryptoHash, CryptoHashOf, Signable},
+        state_sync::CURRENT_STATE_SYNC_VERSION,
+        NumberOfNodes,
+    };
+    use std::sync::Arc;
+
+    fn signed_cup_proto(secret_key: &SecretKeyBytes) -> pb::CatchUpPackage {
+        let mut cup_proto = pb::CatchUpPackage::from(&make_genesis(Summary::fake()));
+        let message = CatchUpContentProtobufBytes(cup_proto.content.clone()).as_signed_bytes();
+        let signature = sign_message(&message, secret_key).unwrap();
+        let signature = combine_signatures(&[Some(signature)], NumberOfNodes::new(1)).unwrap();
+        cup_proto.signature = signature.0.to_vec();
+        cup_proto
+    }
+
+    /// Returns a registry snapshot holding `public_key` as the threshold
+    /// signing public key of `subnet_id` at `version`.
+    fn registry_snapshot(
+        subnet_id: SubnetId,
+        version: RegistryVersion,
+        public_key: ThresholdSigPublicKey,
+    ) -> FakeRegistryClient {
+        let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+        data_provider
+            .add(
+                &make_crypto_threshold_signing_pubkey_key(subnet_id),
+                version,
+                Some(PublicKeyProto::from(public_key)),
+            )
+            .unwrap();
+        let registry_client = FakeRegistryClient::new(data_provider);
+        registry_client.update_to_latest_version();
+        registry_client
+    }
+
+    #[test]
+    fn should_verify_signature_against_registry_snapshot() {
+        let subnet_id = subnet_test_id(1);
+        let (public_key, secret_key) = generate_root_of_trust(&mut rand::thread_rng());
+        let cup_proto = signed_cup_proto(&secret_key);
+        let version = registry_version(&cup_proto).unwrap();
+        let registry_client = registry_snapshot(subnet_id, version, public_key);
+
+        assert_eq!(
+            verify_signature_with_registry_client(&registry_client, subnet_id, &cup_proto),
+            Ok(version)
+        );
+    }
+
+    #[test]
+    fn should_reject_signature_by_another_key() {
+        let subnet_id = subnet_test_id(1);
+        let (public_key, _) = generate_root_of_trust(&mut rand::thread_rng());
+        let (_, other_secret_key) = generate_root_of_trust(&mut rand::thread_rng());
+        let cup_proto = signed_cup_proto(&other_secret_key);
+        let version = registry_version(&cup_proto).unwrap();
+        let registry_client = registry_snapshot(subnet_id, version, public_key);
+
+        let err = verify_signature_with_registry_client(&registry_client, subnet_id, &cup_proto)
+            .unwrap_err();
+        assert!(err.starts_with("invalid signature"), "{}", err);
+    }
+
+    #[test]
+    fn should_reject_cup_of_subnet_without_key() {
+        let (public_key, secret_key) = generate_root_of_trust(&mut rand::thread_rng());
+        let cup_proto = signed_cup_proto(&secret_key);
+        let version = registry_version(&cup_proto).unwrap();
+        let registry_client = registry_snapshot(subnet_test_id(1), version, public_key);
+
+        let err =
+            verify_signature_with_registry_client(&registry_client, subnet_test_id(2), &cup_proto)
+                .unwrap_err();
+        assert!(
+            err.contains("has no threshold signing public key"),
+            "{}",
+            err
+        );
+    }
+
+    /// Writes a small checkpoint to `path` and returns its root hash.
+    fn write_checkpoint(path: &Path) -> [u8; 32] {
+        let metadata = SystemMetadata {
+            state_sync_version: CURRENT_STATE_SYNC_VERSION as u32,
+            ..Default::default()
+        };
+        std::fs::write(path.join("system_metadata.pbuf"), metadata.encode_to_vec()).unwrap();
+        std::fs::write(path.join("subnet_queues.pbuf"), b"queues").unwrap();
+        manifest_hash(&manifest_from_path(path).unwrap())
+    }
+
+    fn cup_with_state_hash(state_hash: &[u8]) -> CatchUpPackage {
+        let mut cup = make_genesis(Summary::fake());
+        cup.content.state_hash = CryptoHashOf::from(CryptoHash(state_hash.to_vec()));
+        cup
+    }
+
+    #[test]
+    fn should_accept_matching_checkpoint() {
+        let checkpoint = tempfile::tempdir().unwrap();
+        let root_hash = write_checkpoint(checkpoint.path());
+
+        assert_eq!(
+            verify_state_hash(&cup_with_state_hash(&root_hash), checkpoint.path()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn should_reject_mismatching_checkpoint() {
+        let checkpoint = tempfile::tempdir().unwrap();
+        let root_hash = write_checkpoint(checkpoint.path());
+        std::fs::write(checkpoint.path().join("subnet_queues.pbuf"), b"modified").unwrap();
+
+        let err =
+            verify_state_hash(&cup_with_state_hash(&root_hash), checkpoint.path()).unwrap_err();
+        assert!(err.starts_with("state hash mismatch"), "{}", err);
+        assert!(err.contains(&hex::encode(root_hash)), "{}", err);
+    }
+}
@@ -16,7 +16,7 @@
 use ic_base_types::NumSeconds;
 use ic_config::subnet_config::CyclesAccountManagerConfig;
 use ic_ic00_types::Method;
-use ic_interfaces::execution_environment::CanisterOutOfCyclesError;
+use ic_interfaces::execution_environment::{CanisterOutOfCyclesError, SubnetAvailableMemory};
 use ic_logger::{error, info, ReplicaLogger};
 use ic_nns_constants::CYCLES_MINTING_CANISTER_ID;
 use ic_registry_subnet_type::SubnetType;
@@ -61,6 +61,108 @@ impl std::fmt::Display for CyclesAccountManagerError {
     }
 }
 
+/// Describes how saturated a subnet resource, such as memory, is.
+///
+/// Once `usage` passes `threshold` the resource is considered scarce and
+/// growing it becomes more expensive; `capacity` is the total amount of the
+/// resource available on the subnet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceSaturation {
+    usage: u64,
+    threshold: u64,
+    capacity: u64,
+}
+
+impl ResourceSaturation {
+    pub fn new(usage: u64, threshold: u64, capacity: u64) -> Self {
+        let threshold = threshold.min(capacity);
+        Self {
+            usage,
+            threshold,
+            capacity,
+        }
+    }
+
+    /// Creates a saturation from the amount of the resource that is still
+    /// available rather than from the amount that is used.
+    pub fn new_from_available(available: u64, threshold: u64, capacity: u64) -> Self {
+        Self::new(capacity.saturating_sub(available), threshold, capacity)
+    }
+
+    /// Returns the approximate saturation of the subnet execution memory
+    /// given the currently available memory.
+    ///
+    /// The subnet available memory may be divided between execution threads,
+    /// so the global available memory is approximated using the scaling
+    /// factor.
+    pub fn from_subnet_available_memory(
+        subnet_available_memory: &SubnetAvailableMemory,
+        subnet_memory_threshold: NumBytes,
+        subnet_memory_capacity: NumBytes,
+    ) -> Self {
+        let available = subnet_available_memory
+            .get_execution_memory()
+            .max(0)
+            .saturating_mul(subnet_available_memory.get_scaling_factor().max(1));
+        Self::new_from_available(
+            available as u64,
+            subnet_memory_threshold.get(),
+            subnet_memory_capacity.get(),
+        )
+    }
+
+    pub fn usage(&self) -> u64 {
+        self.usage
+    }
+
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the saturation after `amount` more units of the resource are
+    /// used.
+    pub fn add(&self, amount: u64) -> Self {
+        Self {
+            usage: self.usage.saturating_add(amount),
+            threshold: self.threshold,
+            capacity: self.capacity,
+        }
+    }
+
+    /// Returns the number of units of a new `allocation` that are subject to
+    /// reservation.
+    ///
+    /// Every allocated unit is weighted by how saturated the resource is at
+    /// that point: the weight is zero up to the threshold, grows linearly to
+    /// one at capacity, and stays at one beyond capacity. The result is the
+    /// sum of these weights over `[usage, usage + allocation)`.
+    pub fn reservation_units(&self, allocation: u64) -> u64 {
+        let start = self.usage as u128;
+        let end = start + allocation as u128;
+        let threshold = self.threshold as u128;
+        let capacity = self.capacity as u128;
+
+        // The part of the allocation that lies on the linear segment.
+        let linear_start = start.max(threshold);
+        let linear_end = end.min(capacity);
+        let linear = if linear_start < linear_end {
+            let span = capacity - threshold;
+            ((linear_end - threshold).pow(2) - (linear_start - threshold).pow(2)) / (2 * span)
+        } else {
+            0
+        };
+
+        // The part of the allocation that lies beyond capacity.
+        let beyond = end.saturating_sub(start.max(capacity));
+
+        (linear + beyond).min(u64::MAX as u128) as u64
+    }
+}
+
 /// Handles any operation related to cycles accounting, such as charging (due to
 /// using system resources) or refunding unused cycles.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -552,6 +654,27 @@ impl CyclesAccountManager {
         self.scale_cost(cycles, subnet_size)
     }
 
+    /// Returns the number of cycles that need to be moved to the reserved
+    /// balance of a canister that allocates `allocated_bytes` of memory on a
+    /// subnet with the given memory saturation.
+    ///
+    /// The reserved cycles are used to pay for future storage fees of the
+    /// canister. Nothing is reserved as long as the subnet memory usage stays
+    /// below the threshold.
+    pub fn storage_reservation_cycles(
+        &self,
+        allocated_bytes: NumBytes,
+        subnet_memory_saturation: &ResourceSaturation,
+        subnet_size: usize,
+    ) -> Cycles {
+        let reserved_bytes = subnet_memory_saturation.reservation_units(allocated_bytes.get());
+        self.memory_cost(
+            NumBytes::from(reserved_bytes),
+            self.config.max_storage_reservation_period,
+            subnet_size,
+        )
+    }
+
     ////////////////////////////////////////////////////////////////////////////
     //
     // Request
@@ -1035,4 +1158,51 @@ mod tests {
             Cycles::from(1_605_046_800u64) * subnet_size
         );
     }
+
+    #[test]
+    fn test_resource_saturation_reservation_units() {
+        let saturation = ResourceSaturation::new(0, 100, 200);
+        // Below the threshold nothing is reserved.
+        assert_eq!(saturation.reservation_units(100), 0);
+        // Between threshold and capacity the weight grows linearly.
+        assert_eq!(saturation.reservation_units(200), 50);
+        assert_eq!(saturation.add(100).reservation_units(100), 50);
+        assert_eq!(saturation.add(150).reservation_units(50), 37);
+        // Beyond capacity every unit has the full weight.
+        assert_eq!(saturation.add(200).reservation_units(10), 10);
+        assert_eq!(saturation.add(100).reservation_units(110), 60);
+
+        // A threshold above capacity is clamped to capacity.
+        let saturation = ResourceSaturation::new(0, 300, 200);
+        assert_eq!(saturation.threshold(), 200);
+        assert_eq!(saturation.reservation_units(200), 0);
+        assert_eq!(saturation.reservation_units(210), 10);
+
+        let saturation = ResourceSaturation::new_from_available(50, 100, 200);
+        assert_eq!(saturation.usage(), 150);
+    }
+
+    #[test]
+    fn test_storage_reservation_cycles() {
+        let reference_subnet_size = 13;
+        let cam = create_cycles_account_manager(reference_subnet_size);
+        let gib = 1024 * 1024 * 1024;
+        let bytes = NumBytes::from(gib);
+
+        let below_threshold = ResourceSaturation::new(0, 2 * gib, 4 * gib);
+        assert_eq!(
+            cam.storage_reservation_cycles(bytes, &below_threshold, reference_subnet_size),
+            Cycles::zero()
+        );
+
+        let at_capacity = ResourceSaturation::new(4 * gib, 2 * gib, 4 * gib);
+        assert_eq!(
+            cam.storage_reservation_cycles(bytes, &at_capacity, reference_subnet_size),
+            cam.memory_cost(
+                bytes,
+                cam.config.max_storage_reservation_period,
+                reference_subnet_size
+            )
+        );
+    }
 }
@@ -133,6 +133,7 @@ pub(crate) fn setup() -> (
     let execution_services = ExecutionServices::setup_execution(
         log.clone().into(),
         &metrics_registry,
+        replica_config.node_id,
         replica_config.subnet_id,
         subnet_type,
         subnet_config.scheduler_config.clone(),
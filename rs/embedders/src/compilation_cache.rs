@@ -1,21 +1,77 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{sync_channel, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
 
 use crate::SerializedModule;
+use ic_config::embedders::Config as EmbeddersConfig;
+use ic_crypto_sha2::Sha256;
 use ic_interfaces::execution_environment::HypervisorResult;
-use ic_types::NumBytes;
+use ic_logger::{warn, ReplicaLogger};
+use ic_types::{NumBytes, ReplicaVersion};
 use ic_utils_lru_cache::LruCache;
 use ic_wasm_types::{CanisterModule, WasmHash};
 
 /// Stores the serialized modules of wasm code that has already been compiled so
 /// that it can be used again without recompiling.
+///
+/// Optionally, successfully compiled modules are also persisted on disk, so
+/// that they survive replica restarts. The modules are written by a background
+/// thread, so that inserting does not wait for the disk.
 pub struct CompilationCache {
     cache: Mutex<LruCache<WasmHash, HypervisorResult<Arc<SerializedModule>>>>,
+    disk: Option<(Arc<DiskCache>, DiskWriter)>,
 }
 
 impl CompilationCache {
     pub fn new(capacity: NumBytes) -> Self {
         Self {
             cache: Mutex::new(LruCache::new(capacity)),
+            disk: None,
+        }
+    }
+
+    /// Creates a cache that persists the serialized modules in a subdirectory
+    /// of `dir`. The subdirectory is specific to the replica version and the
+    /// given embedder config, because serialized modules produced with a
+    /// different version or config must not be used.
+    ///
+    /// Falls back to an in-memory cache if the directory cannot be set up.
+    pub fn new_persisted(
+        capacity: NumBytes,
+        dir: &Path,
+        disk_capacity: NumBytes,
+        embedder_config: &EmbeddersConfig,
+        log: ReplicaLogger,
+    ) -> Self {
+        let disk = DiskCache::open(dir, embedder_config, disk_capacity, log.clone())
+            .map(Arc::new)
+            .and_then(|disk| {
+                let writer = DiskWriter::start(Arc::clone(&disk), log.clone())?;
+                Ok((disk, writer))
+            });
+        let disk = match disk {
+            Ok(disk) => Some(disk),
+            Err(err) => {
+                warn!(
+                    log,
+                    "Failed to open the compilation cache directory {}: {}",
+                    dir.display(),
+                    err
+                );
+                None
+            }
+        };
+        Self {
+            cache: Mutex::new(LruCache::new(capacity)),
+            disk,
         }
     }
 
@@ -24,25 +80,564 @@ impl CompilationCache {
         canister_module: &CanisterModule,
         serialized_module: HypervisorResult<Arc<SerializedModule>>,
     ) {
+        let wasm_hash = WasmHash::from(canister_module);
+        if let (Some((_, writer)), Ok(serialized_module)) = (&self.disk, &serialized_module) {
+            writer.store(wasm_hash.clone(), Arc::clone(serialized_module));
+        }
         self.cache
             .lock()
             .unwrap()
-            .push(WasmHash::from(canister_module), serialized_module);
+            .push(wasm_hash, serialized_module);
     }
 
     pub fn get(
         &self,
         canister_module: &CanisterModule,
     ) -> Option<HypervisorResult<Arc<SerializedModule>>> {
+        let wasm_hash = WasmHash::from(canister_module);
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&wasm_hash)
+            .map(|o| o.as_ref().map(Arc::clone).map_err(|e| e.clone()));
+        if cached.is_some() {
+            return cached;
+        }
+        // Modules are loaded from disk lazily, on the first use after a restart.
+        let (disk, _) = self.disk.as_ref()?;
+        let serialized_module = Arc::new(disk.load(&wasm_hash)?);
         self.cache
             .lock()
             .unwrap()
-            .get(&WasmHash::from(canister_module))
-            .map(|o| o.as_ref().map(Arc::clone).map_err(|e| e.clone()))
+            .push(wasm_hash, Ok(Arc::clone(&serialized_module)));
+        Some(Ok(serialized_module))
+    }
+
+    /// Blocks until all modules inserted so far are persisted.
+    fn wait_for_writes(&self) {
+        if let Some((_, writer)) = &self.disk {
+            writer.wait();
+        }
     }
 
     #[doc(hidden)]
     pub fn clear_for_testing(&self) {
-        self.cache.lock().unwrap().clear()
+        self.cache.lock().unwrap().clear();
+        self.wait_for_writes();
+        if let Some((disk, _)) = &self.disk {
+            disk.clear();
+        }
+    }
+}
+
+// The number of modules that can wait to be persisted. Modules inserted while
+// the queue is full are kept in memory only.
+const WRITE_QUEUE_LENGTH: usize = 100;
+
+enum WriteRequest {
+    Store(WasmHash, Arc<SerializedModule>),
+    Await(SyncSender<()>),
+    Shutdown,
+}
+
+/// Persists modules in the `DiskCache` on a background thread, so that the
+/// execution thread that compiled a module does not wait for encoding, writing
+/// and syncing it.
+struct DiskWriter {
+    queue: Mutex<SyncSender<WriteRequest>>,
+    thread: Option<JoinHandle<()>>,
+    log: ReplicaLogger,
+}
+
+impl DiskWriter {
+    fn start(disk: Arc<DiskCache>, log: ReplicaLogger) -> std::io::Result<Self> {
+        let (tx, rx) = sync_channel(WRITE_QUEUE_LENGTH);
+        let thread = thread::Builder::new()
+            .name("CompilationCacheWriter".to_string())
+            .spawn(move || {
+                for request in rx {
+                    match request {
+                        WriteRequest::Store(wasm_hash, serialized_module) => {
+                            disk.store(&wasm_hash, &serialized_module)
+                        }
+                        WriteRequest::Await(tx) => {
+                            let _ = tx.send(());
+                        }
+                        WriteRequest::Shutdown => break,
+                    }
+                }
+            })?;
+        Ok(Self {
+            queue: Mutex::new(tx),
+            thread: Some(thread),
+            log,
+        })
+    }
+
+    fn store(&self, wasm_hash: WasmHash, serialized_module: Arc<SerializedModule>) {
+        let request = WriteRequest::Store(wasm_hash, serialized_module);
+        match self.queue.lock().unwrap().try_send(request) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!(
+                    self.log,
+                    "The compilation cache write queue is full, a module is not persisted"
+                );
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                warn!(
+                    self.log,
+                    "The compilation cache writer thread is not running"
+                );
+            }
+        }
+    }
+
+    fn wait(&self) {
+        let (tx, rx) = sync_channel(0);
+        if self
+            .queue
+            .lock()
+            .unwrap()
+            .send(WriteRequest::Await(tx))
+            .is_ok()
+        {
+            let _ = rx.recv();
+        }
+    }
+}
+
+impl Drop for DiskWriter {
+    // Finishes the pending writes before shutting down.
+    fn drop(&mut self) {
+        let _ = self.queue.lock().unwrap().send(WriteRequest::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!(self.log, "The compilation cache writer thread panicked");
+            }
+        }
+    }
+}
+
+/// The header of every file of the persisted compilation cache.
+const FILE_MAGIC: &[u8; 8] = b"ICCMOD01";
+const CHECKSUM_LENGTH: usize = 32;
+const FILE_EXTENSION: &str = "module";
+const TMP_FILE_EXTENSION: &str = "tmp";
+// Marks a directory as a cache namespace. It contains `FILE_MAGIC`.
+const MARKER_FILE_NAME: &str = "compilation_cache";
+
+struct DiskEntry {
+    size: u64,
+    // The value of `DiskIndex::clock` at the last access.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct DiskIndex {
+    entries: HashMap<WasmHash, DiskEntry>,
+    total_size: u64,
+    clock: u64,
+}
+
+impl DiskIndex {
+    fn touch(&mut self, wasm_hash: &WasmHash) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(wasm_hash) {
+            entry.last_used = self.clock;
+        }
+    }
+
+    fn insert(&mut self, wasm_hash: WasmHash, size: u64) {
+        self.remove(&wasm_hash);
+        self.clock += 1;
+        self.total_size += size;
+        let last_used = self.clock;
+        self.entries
+            .insert(wasm_hash, DiskEntry { size, last_used });
+    }
+
+    fn remove(&mut self, wasm_hash: &WasmHash) {
+        if let Some(entry) = self.entries.remove(wasm_hash) {
+            self.total_size -= entry.size;
+        }
+    }
+
+    // Removes the least recently used entries until the total size fits into
+    // the capacity and returns them.
+    fn evict(&mut self, capacity: u64) -> Vec<WasmHash> {
+        let mut evicted = vec![];
+        while self.total_size > capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(wasm_hash, _)| wasm_hash.clone());
+            match oldest {
+                Some(wasm_hash) => {
+                    self.remove(&wasm_hash);
+                    evicted.push(wasm_hash);
+                }
+                None => break,
+            }
+        }
+        evicted
+    }
+}
+
+/// The on-disk part of the compilation cache. Every serialized module is
+/// stored in a separate file named after the Wasm hash and consisting of:
+/// - `FILE_MAGIC`,
+/// - the SHA-256 checksum of the payload,
+/// - the payload: the Wasm hash followed by the bincode-encoded module.
+///
+/// Files that fail verification are deleted, which results in recompilation.
+/// The files live in a namespace directory per replica version and embedder
+/// config, marked by `MARKER_FILE_NAME`. Only such directories are removed
+/// when the namespace changes.
+struct DiskCache {
+    dir: PathBuf,
+    capacity: u64,
+    index: Mutex<DiskIndex>,
+    log: ReplicaLogger,
+}
+
+impl DiskCache {
+    fn open(
+        root: &Path,
+        embedder_config: &EmbeddersConfig,
+        capacity: NumBytes,
+        log: ReplicaLogger,
+    ) -> std::io::Result<Self> {
+        let namespace = cache_namespace(embedder_config)?;
+        let dir = root.join(&namespace);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(MARKER_FILE_NAME), FILE_MAGIC)?;
+
+        // Modules of other replica versions or embedder configs are useless.
+        // Anything in `root` that is not a cache namespace is left alone.
+        for entry in fs::read_dir(root)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_name() != namespace.as_str() && is_cache_namespace(&path) {
+                if let Err(err) = fs::remove_dir_all(&path) {
+                    warn!(log, "Failed to remove {}: {}", path.display(), err);
+                }
+            }
+        }
+
+        // Only the file names and sizes are read here; modules are loaded
+        // lazily. The modification time approximates the last usage.
+        let mut files = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let wasm_hash = path
+                .extension()
+                .filter(|extension| *extension == FILE_EXTENSION)
+                .and_then(|_| path.file_stem())
+                .and_then(|stem| parse_wasm_hash(&stem.to_string_lossy()));
+            match wasm_hash {
+                Some(wasm_hash) => {
+                    let metadata = fs::metadata(&path)?;
+                    files.push((metadata.modified().ok(), wasm_hash, metadata.len()));
+                }
+                None if path.file_name() == Some(MARKER_FILE_NAME.as_ref()) => {}
+                // Leftovers of interrupted writes and unknown files.
+                None => {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+        files.sort_by_key(|(modified, _, _)| *modified);
+        let mut index = DiskIndex::default();
+        for (_, wasm_hash, size) in files {
+            index.insert(wasm_hash, size);
+        }
+
+        let disk_cache = Self {
+            dir,
+            capacity: capacity.get(),
+            index: Mutex::new(index),
+            log,
+        };
+        let evicted = disk_cache.index.lock().unwrap().evict(disk_cache.capacity);
+        disk_cache.remove_files(&evicted);
+        Ok(disk_cache)
+    }
+
+    fn path(&self, wasm_hash: &WasmHash) -> PathBuf {
+        self.dir.join(format!(
+            "{}.{}",
+            hex::encode(wasm_hash.to_slice()),
+            FILE_EXTENSION
+        ))
+    }
+
+    fn load(&self, wasm_hash: &WasmHash) -> Option<SerializedModule> {
+        if !self.index.lock().unwrap().entries.contains_key(wasm_hash) {
+            return None;
+        }
+        let path = self.path(wasm_hash);
+        let result = fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| decode(&bytes, wasm_hash));
+        match result {
+            Ok(serialized_module) => {
+                self.index.lock().unwrap().touch(wasm_hash);
+                Some(serialized_module)
+            }
+            Err(err) => {
+                warn!(
+                    self.log,
+                    "Discarding the persisted compilation of {}: {}",
+                    path.display(),
+                    err
+                );
+                self.index.lock().unwrap().remove(wasm_hash);
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    fn store(&self, wasm_hash: &WasmHash, serialized_module: &SerializedModule) {
+        let bytes = match encode(wasm_hash, serialized_module) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!(self.log, "Failed to encode a compiled module: {}", err);
+                return;
+            }
+        };
+        let path = self.path(wasm_hash);
+        // Write to a temporary file first, so that a crash cannot leave a
+        // partially written module behind.
+        let tmp_path = path.with_extension(TMP_FILE_EXTENSION);
+        let result = fs::File::create(&tmp_path)
+            .and_then(|mut file| file.write_all(&bytes).and_then(|_| file.sync_data()))
+            .and_then(|_| fs::rename(&tmp_path, &path));
+        if let Err(err) = result {
+            warn!(
+                self.log,
+                "Failed to persist the compiled module {}: {}",
+                path.display(),
+                err
+            );
+            let _ = fs::remove_file(&tmp_path);
+            return;
+        }
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            index.insert(wasm_hash.clone(), bytes.len() as u64);
+            index.evict(self.capacity)
+        };
+        self.remove_files(&evicted);
+    }
+
+    fn remove_files(&self, wasm_hashes: &[WasmHash]) {
+        for wasm_hash in wasm_hashes {
+            let path = self.path(wasm_hash);
+            if let Err(err) = fs::remove_file(&path) {
+                warn!(self.log, "Failed to remove {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    fn clear(&self) {
+        let wasm_hashes: Vec<_> = {
+            let mut index = self.index.lock().unwrap();
+            let wasm_hashes = index.entries.keys().cloned().collect();
+            *index = DiskIndex::default();
+            wasm_hashes
+        };
+        self.remove_files(&wasm_hashes);
+    }
+}
+
+// Returns the name of the directory for modules compiled by this replica
+// version with the given embedder config.
+fn cache_namespace(embedder_config: &EmbeddersConfig) -> std::io::Result<String> {
+    let config = bincode::serialize(embedder_config)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    let mut hasher = Sha256::new();
+    hasher.write(ReplicaVersion::default().as_ref().as_bytes());
+    hasher.write(&config);
+    Ok(hex::encode(hasher.finish()))
+}
+
+// Returns true if `path` is a directory named like a namespace returned by
+// `cache_namespace()` and containing the marker file.
+fn is_cache_namespace(path: &Path) -> bool {
+    let is_namespace_name = path.file_name().map_or(false, |name| {
+        let name = name.to_string_lossy();
+        name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+    });
+    is_namespace_name
+        && fs::read(path.join(MARKER_FILE_NAME))
+            .map_or(false, |marker| marker.as_slice() == FILE_MAGIC.as_slice())
+}
+
+fn parse_wasm_hash(name: &str) -> Option<WasmHash> {
+    let bytes: [u8; 32] = hex::decode(name).ok()?.try_into().ok()?;
+    Some(WasmHash::from(bytes))
+}
+
+fn encode(wasm_hash: &WasmHash, serialized_module: &SerializedModule) -> Result<Vec<u8>, String> {
+    let mut payload = wasm_hash.to_vec();
+    bincode::serialize_into(&mut payload, serialized_module).map_err(|err| err.to_string())?;
+    let mut bytes = Vec::with_capacity(FILE_MAGIC.len() + CHECKSUM_LENGTH + payload.len());
+    bytes.extend_from_slice(FILE_MAGIC);
+    bytes.extend_from_slice(&Sha256::hash(&payload));
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+fn decode(bytes: &[u8], wasm_hash: &WasmHash) -> Result<SerializedModule, String> {
+    let rest = bytes
+        .strip_prefix(FILE_MAGIC.as_slice())
+        .ok_or("invalid header")?;
+    if rest.len() < CHECKSUM_LENGTH {
+        return Err("truncated file".to_string());
+    }
+    let (checksum, payload) = rest.split_at(CHECKSUM_LENGTH);
+    if checksum != Sha256::hash(payload).as_slice() {
+        return Err("checksum mismatch".to_string());
+    }
+    let module = payload
+        .strip_prefix(wasm_hash.to_vec().as_slice())
+        .ok_or("wasm hash mismatch")?;
+    bincode::deserialize(module).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{wasm_utils::compile, WasmtimeEmbedder};
+    use ic_logger::replica_logger::no_op_logger;
+    use ic_wasm_types::BinaryEncodedWasm;
+
+    fn canister_module(wat: &str) -> CanisterModule {
+        CanisterModule::new(wat::parse_str(wat).unwrap())
+    }
+
+    fn serialized_module(canister_module: &CanisterModule) -> Arc<SerializedModule> {
+        let embedder = WasmtimeEmbedder::new(EmbeddersConfig::default(), no_op_logger());
+        let wasm = BinaryEncodedWasm::new(canister_module.as_slice().to_vec());
+        let (_, result) = compile(&embedder, &wasm);
+        Arc::new(result.unwrap().1)
+    }
+
+    fn persisted_cache(dir: &Path, disk_capacity: u64) -> CompilationCache {
+        CompilationCache::new_persisted(
+            NumBytes::new(u64::MAX),
+            dir,
+            NumBytes::new(disk_capacity),
+            &EmbeddersConfig::default(),
+            no_op_logger(),
+        )
+    }
+
+    fn module_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for namespace in fs::read_dir(dir).unwrap() {
+            for file in fs::read_dir(namespace.unwrap().path()).unwrap() {
+                let path = file.unwrap().path();
+                if path.extension() == Some(FILE_EXTENSION.as_ref()) {
+                    files.push(path);
+                }
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn persisted_modules_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let module = canister_module("(module (func (export \"canister_query go\")))");
+        let serialized = serialized_module(&module);
+
+        persisted_cache(dir.path(), u64::MAX).insert(&module, Ok(Arc::clone(&serialized)));
+
+        let cache = persisted_cache(dir.path(), u64::MAX);
+        let loaded = cache.get(&module).unwrap().unwrap();
+        assert_eq!(loaded.bytes.as_slice(), serialized.bytes.as_slice());
+        assert_eq!(loaded.exported_functions, serialized.exported_functions);
+        assert_eq!(loaded.compilation_cost, serialized.compilation_cost);
+    }
+
+    #[test]
+    fn corrupted_module_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let module = canister_module("(module)");
+        persisted_cache(dir.path(), u64::MAX).insert(&module, Ok(serialized_module(&module)));
+
+        let files = module_files(dir.path());
+        assert_eq!(files.len(), 1);
+        let mut bytes = fs::read(&files[0]).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&files[0], bytes).unwrap();
+
+        let cache = persisted_cache(dir.path(), u64::MAX);
+        assert!(cache.get(&module).is_none());
+        assert!(module_files(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn disk_usage_is_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = canister_module("(module)");
+        let second = canister_module("(module (memory 1))");
+
+        let cache = persisted_cache(dir.path(), u64::MAX);
+        cache.insert(&first, Ok(serialized_module(&first)));
+        cache.wait_for_writes();
+        let size = fs::metadata(&module_files(dir.path())[0]).unwrap().len();
+
+        // Only one of the modules fits into the capacity.
+        let cache = persisted_cache(dir.path(), size + size / 2);
+        cache.insert(&second, Ok(serialized_module(&second)));
+        cache.wait_for_writes();
+        assert_eq!(module_files(dir.path()).len(), 1);
+
+        let cache = persisted_cache(dir.path(), size + size / 2);
+        assert!(cache.get(&first).is_none());
+        assert!(cache.get(&second).is_some());
+    }
+
+    #[test]
+    fn modules_of_other_namespaces_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let stale = dir.path().join("ab".repeat(32));
+        fs::create_dir_all(&stale).unwrap();
+        fs::write(stale.join(MARKER_FILE_NAME), FILE_MAGIC).unwrap();
+        fs::write(stale.join("module"), b"data").unwrap();
+
+        persisted_cache(dir.path(), u64::MAX);
+        assert!(!stale.exists());
+    }
+
+    #[test]
+    fn unrelated_files_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        // Named like a namespace, but without the marker.
+        let unmarked = dir.path().join("cd".repeat(32));
+        fs::create_dir_all(&unmarked).unwrap();
+        fs::write(unmarked.join("data"), b"data").unwrap();
+        // Contains a marker, but is not named like a namespace.
+        let other = dir.path().join("other");
+        fs::create_dir_all(&other).unwrap();
+        fs::write(other.join(MARKER_FILE_NAME), FILE_MAGIC).unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"data").unwrap();
+
+        let module = canister_module("(module)");
+        persisted_cache(dir.path(), u64::MAX).insert(&module, Ok(serialized_module(&module)));
+        let cache = persisted_cache(dir.path(), u64::MAX);
+        assert!(cache.get(&module).is_some());
+
+        assert!(unmarked.join("data").exists());
+        assert!(other.join(MARKER_FILE_NAME).exists());
+        assert!(file.exists());
     }
 }
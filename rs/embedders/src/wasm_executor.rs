@@ -20,7 +20,8 @@ use crate::{
 use ic_config::flag_status::FlagStatus;
 use ic_interfaces::execution_environment::{
     ExecutionComplexity, HypervisorError, HypervisorResult, InstanceStats,
-    OutOfInstructionsHandler, SubnetAvailableMemory, SystemApi, WasmExecutionOutput,
+    OutOfInstructionsHandler, SubnetAvailableMemory, SystemApi, SystemApiCallCounters,
+    WasmExecutionOutput,
 };
 use ic_logger::{warn, ReplicaLogger};
 use ic_metrics::MetricsRegistry;
@@ -456,6 +457,7 @@ pub fn wasm_execution_error(
             allocated_bytes: NumBytes::from(0),
             allocated_message_bytes: NumBytes::from(0),
             instance_stats: InstanceStats::default(),
+            system_api_call_counters: SystemApiCallCounters::default(),
         },
         None,
     )
@@ -618,6 +620,7 @@ pub fn process(
                     allocated_bytes: NumBytes::from(0),
                     allocated_message_bytes: NumBytes::from(0),
                     instance_stats: InstanceStats::default(),
+                    system_api_call_counters: SystemApiCallCounters::default(),
                 },
                 None,
                 Err(system_api),
@@ -649,6 +652,7 @@ pub fn process(
         .message_instructions_executed(instruction_counter)
         .min(message_instruction_limit);
     let message_instructions_left = message_instruction_limit - message_instructions_executed;
+    let system_api_call_counters = system_api.call_counters();
 
     let instance_stats = instance.get_stats();
 
@@ -739,6 +743,7 @@ pub fn process(
             allocated_bytes,
             allocated_message_bytes,
             instance_stats,
+            system_api_call_counters,
         },
         wasm_state_changes,
         Ok(instance),
@@ -10,7 +10,7 @@ use crate::{
 };
 use ic_base_types::NumSeconds;
 use ic_config::flag_status::FlagStatus;
-use ic_cycles_account_manager::CyclesAccountManager;
+use ic_cycles_account_manager::{CyclesAccountManager, ResourceSaturation};
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_ic00_types::{
     CanisterChangeDetails, CanisterChangeOrigin, CanisterInstallMode, CanisterStatusResultV2,
@@ -95,6 +95,7 @@ pub(crate) enum StopCanisterResult {
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 pub(crate) struct CanisterMgrConfig {
     pub(crate) subnet_memory_capacity: NumBytes,
+    pub(crate) subnet_memory_threshold: NumBytes,
     pub(crate) default_provisional_cycles_balance: Cycles,
     pub(crate) default_freeze_threshold: NumSeconds,
     pub(crate) compute_capacity: u64,
@@ -108,6 +109,7 @@ impl CanisterMgrConfig {
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         subnet_memory_capacity: NumBytes,
+        subnet_memory_threshold: NumBytes,
         default_provisional_cycles_balance: Cycles,
         default_freeze_threshold: NumSeconds,
         own_subnet_id: SubnetId,
@@ -119,6 +121,7 @@ impl CanisterMgrConfig {
     ) -> Self {
         Self {
             subnet_memory_capacity,
+            subnet_memory_threshold,
             default_provisional_cycles_balance,
             default_freeze_threshold,
             own_subnet_id,
@@ -394,6 +397,7 @@ impl CanisterManager {
         validate_canister_settings(
             settings,
             NumBytes::new(0),
+            NumBytes::new(0),
             MemoryAllocation::BestEffort,
             subnet_available_memory,
             ComputeAllocation::zero(),
@@ -405,6 +409,12 @@ impl CanisterManager {
             &self.cycles_account_manager,
             subnet_size,
             Cycles::zero(),
+            None,
+            &ResourceSaturation::from_subnet_available_memory(
+                subnet_available_memory,
+                self.config.subnet_memory_threshold,
+                self.config.subnet_memory_capacity,
+            ),
         )
     }
 
@@ -453,6 +463,35 @@ impl CanisterManager {
         if let Some(freezing_threshold) = settings.freezing_threshold() {
             canister.system_state.freeze_threshold = freezing_threshold;
         }
+        if let Some(wasm_memory_limit) = settings.wasm_memory_limit() {
+            // A limit of zero clears the limit.
+            canister.system_state.wasm_memory_limit =
+                Some(wasm_memory_limit).filter(|limit| limit.get() != 0);
+        }
+        if let Some(wasm_memory_threshold) = settings.wasm_memory_threshold() {
+            canister.system_state.wasm_memory_threshold = wasm_memory_threshold;
+        }
+        if let Some(reserved_cycles_limit) = settings.reserved_cycles_limit() {
+            canister
+                .system_state
+                .set_reserved_balance_limit(Some(reserved_cycles_limit));
+        }
+        // This should not fail normally because the settings validation has
+        // checked that the cycles balance covers the reservation and that the
+        // reserved cycles limit is not exceeded. However, log an error in case
+        // it happens for visibility.
+        if let Err(err) = canister
+            .system_state
+            .reserve_cycles(settings.reservation_cycles())
+        {
+            error!(
+                self.log,
+                "Failed to reserve {} cycles for canister {}: {:?}",
+                settings.reservation_cycles(),
+                canister.canister_id(),
+                err,
+            );
+        }
     }
 
     /// Tries to apply the requested settings on the canister identified by
@@ -473,6 +512,7 @@ impl CanisterManager {
         let validated_settings = validate_canister_settings(
             settings,
             canister.memory_usage(),
+            canister.wasm_memory_usage(),
             canister.memory_allocation(),
             &round_limits.subnet_available_memory,
             canister.compute_allocation(),
@@ -484,6 +524,12 @@ impl CanisterManager {
             &self.cycles_account_manager,
             subnet_size,
             canister.system_state.reserved_balance(),
+            canister.system_state.reserved_balance_limit(),
+            &ResourceSaturation::from_subnet_available_memory(
+                &round_limits.subnet_available_memory,
+                self.config.subnet_memory_threshold,
+                self.config.subnet_memory_capacity,
+            ),
         )?;
 
         let is_controllers_change =
@@ -957,6 +1003,7 @@ impl CanisterManager {
         let compute_allocation = canister.scheduler_state.compute_allocation;
         let memory_allocation = canister.memory_allocation();
         let freeze_threshold = canister.system_state.freeze_threshold;
+        let total_query_stats = &canister.system_state.total_query_stats;
 
         Ok(CanisterStatusResultV2::new(
             canister.status(),
@@ -971,6 +1018,16 @@ impl CanisterManager {
             compute_allocation.as_percent(),
             Some(memory_allocation.bytes().get()),
             freeze_threshold.get(),
+            canister
+                .system_state
+                .wasm_memory_limit
+                .map(|limit| limit.get()),
+            canister.system_state.wasm_memory_threshold.get(),
+            canister.system_state.reserved_balance().get(),
+            canister
+                .system_state
+                .reserved_balance_limit()
+                .map(|limit| limit.get()),
             self.cycles_account_manager
                 .idle_cycles_burned_rate(
                     memory_allocation,
@@ -979,6 +1036,10 @@ impl CanisterManager {
                     subnet_size,
                 )
                 .get(),
+            total_query_stats.num_calls_total,
+            total_query_stats.num_instructions_total,
+            total_query_stats.ingress_payload_size_total,
+            total_query_stats.egress_payload_size_total,
         ))
     }
 
@@ -1401,6 +1462,11 @@ pub(crate) enum CanisterManagerError {
         available: Cycles,
         threshold: Cycles,
     },
+    ReservedCyclesLimitExceededInMemoryAllocation {
+        memory_allocation: MemoryAllocation,
+        requested: Cycles,
+        limit: Cycles,
+    },
 }
 
 impl From<CanisterManagerError> for UserError {
@@ -1600,6 +1666,17 @@ impl From<CanisterManagerError> for UserError {
                          threshold - available)
                 )
             }
+            ReservedCyclesLimitExceededInMemoryAllocation { memory_allocation, requested, limit} =>
+            {
+                Self::new(
+                    ErrorCode::ReservedCyclesLimitExceededInMemoryAllocation,
+                    format!(
+                        "Cannot increase memory allocation to {} due to its reserved cycles limit. \
+                         The current limit ({}) would be exceeded by {}.",
+                        memory_allocation, limit, requested - limit,
+                    ),
+                )
+            }
         }
     }
 }
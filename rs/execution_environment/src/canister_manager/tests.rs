@@ -21,8 +21,8 @@ use ic_cycles_account_manager::CyclesAccountManager;
 use ic_error_types::{ErrorCode, UserError};
 use ic_ic00_types::{
     CanisterChange, CanisterChangeDetails, CanisterChangeOrigin, CanisterIdRecord,
-    CanisterInstallMode, CanisterSettingsArgsBuilder, CanisterStatusType, CreateCanisterArgs,
-    EmptyBlob, InstallCodeArgs, Method, Payload, UpdateSettingsArgs,
+    CanisterInstallMode, CanisterSettingsArgsBuilder, CanisterStatusResultV2, CanisterStatusType,
+    CreateCanisterArgs, EmptyBlob, InstallCodeArgs, Method, Payload, UpdateSettingsArgs,
 };
 use ic_interfaces::execution_environment::{
     ExecutionComplexity, ExecutionMode, HypervisorError, SubnetAvailableMemory,
@@ -262,6 +262,7 @@ fn canister_manager_config(
     rate_limiting_of_instructions: FlagStatus,
 ) -> CanisterMgrConfig {
     CanisterMgrConfig::new(
+        MEMORY_CAPACITY,
         MEMORY_CAPACITY,
         DEFAULT_PROVISIONAL_BALANCE,
         NumSeconds::from(100_000),
@@ -5569,3 +5570,200 @@ fn system_subnet_does_not_check_for_freezing_threshold_on_allocation_changes() {
     test.canister_update_allocations_settings(canister_id, Some(0), Some(0))
         .unwrap();
 }
+
+#[test]
+fn update_settings_reserves_cycles_for_memory_allocation_above_threshold() {
+    const GIB: i64 = 1024 * 1024 * 1024;
+    let mut test = ExecutionTestBuilder::new()
+        .with_subnet_execution_memory(10 * GIB)
+        .with_subnet_memory_threshold(0)
+        .with_subnet_memory_reservation(0)
+        .build();
+
+    let canister_id = test.create_canister(Cycles::new(1_000_000_000_000_000));
+    let balance_before = test.canister_state(canister_id).system_state.balance();
+    assert_eq!(
+        test.canister_state(canister_id)
+            .system_state
+            .reserved_balance(),
+        Cycles::zero()
+    );
+
+    test.canister_update_allocations_settings(canister_id, None, Some(GIB as u64))
+        .unwrap();
+
+    let reserved_balance = test
+        .canister_state(canister_id)
+        .system_state
+        .reserved_balance();
+    assert!(reserved_balance > Cycles::zero());
+    assert_eq!(
+        test.canister_state(canister_id).system_state.balance() + reserved_balance,
+        balance_before
+    );
+
+    let status = match test.canister_status(canister_id).unwrap() {
+        WasmResult::Reply(reply) => CanisterStatusResultV2::decode(&reply).unwrap(),
+        WasmResult::Reject(reject) => panic!("Unexpected reject: {}", reject),
+    };
+    assert_eq!(status.reserved_cycles(), reserved_balance.get());
+}
+
+#[test]
+fn update_settings_respects_reserved_cycles_limit() {
+    const GIB: i64 = 1024 * 1024 * 1024;
+    let mut test = ExecutionTestBuilder::new()
+        .with_subnet_execution_memory(10 * GIB)
+        .with_subnet_memory_threshold(0)
+        .with_subnet_memory_reservation(0)
+        .build();
+
+    let canister_id = test.create_canister(Cycles::new(1_000_000_000_000_000));
+    test.canister_update_reserved_cycles_limit(canister_id, Cycles::new(1))
+        .unwrap();
+    assert_eq!(
+        test.canister_state(canister_id)
+            .system_state
+            .reserved_balance_limit(),
+        Some(Cycles::new(1))
+    );
+
+    let err = test
+        .canister_update_allocations_settings(canister_id, None, Some(GIB as u64))
+        .unwrap_err();
+    assert_eq!(
+        err.code(),
+        ErrorCode::ReservedCyclesLimitExceededInMemoryAllocation
+    );
+    assert_eq!(
+        test.canister_state(canister_id)
+            .system_state
+            .reserved_balance(),
+        Cycles::zero()
+    );
+}
+
+#[test]
+fn update_settings_checks_wasm_memory_limit_against_usage() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (memory 3)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+
+    let err = test
+        .canister_update_wasm_memory_limit(canister_id, NumBytes::new(2 * WASM_PAGE_SIZE_IN_BYTES))
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterContractViolation);
+    assert!(err
+        .description()
+        .contains("'wasm_memory_limit' cannot be lower than the current Wasm memory usage"));
+    assert_eq!(
+        test.canister_state(canister_id)
+            .system_state
+            .wasm_memory_limit,
+        None
+    );
+
+    test.canister_update_wasm_memory_limit(canister_id, NumBytes::new(4 * WASM_PAGE_SIZE_IN_BYTES))
+        .unwrap();
+    assert_eq!(
+        test.canister_state(canister_id)
+            .system_state
+            .wasm_memory_limit,
+        Some(NumBytes::new(4 * WASM_PAGE_SIZE_IN_BYTES))
+    );
+}
+
+#[test]
+fn update_settings_with_zero_wasm_memory_limit_clears_the_limit() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.create_canister(Cycles::new(1_000_000_000_000));
+    test.canister_update_wasm_memory_limit(canister_id, NumBytes::new(1_000_000))
+        .unwrap();
+    assert_eq!(
+        test.canister_state(canister_id)
+            .system_state
+            .wasm_memory_limit,
+        Some(NumBytes::new(1_000_000))
+    );
+
+    test.canister_update_wasm_memory_limit(canister_id, NumBytes::new(0))
+        .unwrap();
+    assert_eq!(
+        test.canister_state(canister_id)
+            .system_state
+            .wasm_memory_limit,
+        None
+    );
+}
+
+#[test]
+fn install_code_checks_wasm_memory_limit_against_usage() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.create_canister(Cycles::new(1_000_000_000_000));
+    test.canister_update_wasm_memory_limit(canister_id, NumBytes::new(2 * WASM_PAGE_SIZE_IN_BYTES))
+        .unwrap();
+    let wasm = wat::parse_str(
+        r#"
+        (module
+            (memory 3)
+        )"#,
+    )
+    .unwrap();
+    let err = test.install_canister(canister_id, wasm).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterOutOfMemory);
+    assert!(err.description().contains("exceeded its Wasm memory limit"));
+}
+
+#[test]
+fn stable_grow_reserves_cycles_above_threshold() {
+    const GIB: i64 = 1024 * 1024 * 1024;
+    let mut test = ExecutionTestBuilder::new()
+        .with_subnet_execution_memory(10 * GIB)
+        .with_subnet_memory_threshold(0)
+        .with_subnet_memory_reservation(0)
+        .build();
+
+    let canister_id = test
+        .universal_canister_with_cycles(Cycles::new(1_000_000_000_000_000))
+        .unwrap();
+    let reserved_before = test
+        .canister_state(canister_id)
+        .system_state
+        .reserved_balance();
+
+    test.ingress(
+        canister_id,
+        "update",
+        wasm().stable64_grow(1_000).reply().build(),
+    )
+    .unwrap();
+    let reserved_after = test
+        .canister_state(canister_id)
+        .system_state
+        .reserved_balance();
+    assert!(reserved_after > reserved_before);
+
+    // Growing memory fails once the reservation would exceed the limit.
+    test.canister_update_reserved_cycles_limit(canister_id, reserved_after)
+        .unwrap();
+    let err = test
+        .ingress(
+            canister_id,
+            "update",
+            wasm().stable64_grow(1_000).reply().build(),
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.code(),
+        ErrorCode::ReservedCyclesLimitExceededInMemoryGrow
+    );
+    assert!(
+        test.canister_state(canister_id)
+            .system_state
+            .reserved_balance()
+            <= reserved_after
+    );
+}
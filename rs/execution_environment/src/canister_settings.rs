@@ -1,5 +1,5 @@
 use ic_base_types::{NumBytes, NumSeconds};
-use ic_cycles_account_manager::CyclesAccountManager;
+use ic_cycles_account_manager::{CyclesAccountManager, ResourceSaturation};
 use ic_error_types::{ErrorCode, UserError};
 use ic_ic00_types::CanisterSettingsArgs;
 use ic_interfaces::execution_environment::SubnetAvailableMemory;
@@ -20,6 +20,9 @@ pub(crate) struct CanisterSettings {
     pub(crate) compute_allocation: Option<ComputeAllocation>,
     pub(crate) memory_allocation: Option<MemoryAllocation>,
     pub(crate) freezing_threshold: Option<NumSeconds>,
+    pub(crate) wasm_memory_limit: Option<NumBytes>,
+    pub(crate) wasm_memory_threshold: Option<NumBytes>,
+    pub(crate) reserved_cycles_limit: Option<Cycles>,
 }
 
 impl CanisterSettings {
@@ -29,6 +32,9 @@ impl CanisterSettings {
         compute_allocation: Option<ComputeAllocation>,
         memory_allocation: Option<MemoryAllocation>,
         freezing_threshold: Option<NumSeconds>,
+        wasm_memory_limit: Option<NumBytes>,
+        wasm_memory_threshold: Option<NumBytes>,
+        reserved_cycles_limit: Option<Cycles>,
     ) -> Self {
         Self {
             controller,
@@ -36,6 +42,9 @@ impl CanisterSettings {
             compute_allocation,
             memory_allocation,
             freezing_threshold,
+            wasm_memory_limit,
+            wasm_memory_threshold,
+            reserved_cycles_limit,
         }
     }
 
@@ -58,6 +67,18 @@ impl CanisterSettings {
     pub fn freezing_threshold(&self) -> Option<NumSeconds> {
         self.freezing_threshold
     }
+
+    pub fn wasm_memory_limit(&self) -> Option<NumBytes> {
+        self.wasm_memory_limit
+    }
+
+    pub fn wasm_memory_threshold(&self) -> Option<NumBytes> {
+        self.wasm_memory_threshold
+    }
+
+    pub fn reserved_cycles_limit(&self) -> Option<Cycles> {
+        self.reserved_cycles_limit
+    }
 }
 
 impl TryFrom<CanisterSettingsArgs> for CanisterSettings {
@@ -88,12 +109,38 @@ impl TryFrom<CanisterSettingsArgs> for CanisterSettings {
             None => None,
         };
 
+        let wasm_memory_limit = match input.wasm_memory_limit {
+            Some(limit) => Some(NumBytes::from(limit.0.to_u64().ok_or(
+                UpdateSettingsError::WasmMemoryLimitOutOfRange { provided: limit },
+            )?)),
+            None => None,
+        };
+
+        let wasm_memory_threshold = match input.wasm_memory_threshold {
+            Some(threshold) => Some(NumBytes::from(threshold.0.to_u64().ok_or(
+                UpdateSettingsError::WasmMemoryThresholdOutOfRange {
+                    provided: threshold,
+                },
+            )?)),
+            None => None,
+        };
+
+        let reserved_cycles_limit = match input.reserved_cycles_limit {
+            Some(limit) => Some(Cycles::from(limit.0.to_u128().ok_or(
+                UpdateSettingsError::ReservedCyclesLimitOutOfRange { provided: limit },
+            )?)),
+            None => None,
+        };
+
         Ok(CanisterSettings::new(
             controller,
             input.controllers,
             compute_allocation,
             memory_allocation,
             freezing_threshold,
+            wasm_memory_limit,
+            wasm_memory_threshold,
+            reserved_cycles_limit,
         ))
     }
 }
@@ -115,6 +162,9 @@ pub(crate) struct CanisterSettingsBuilder {
     compute_allocation: Option<ComputeAllocation>,
     memory_allocation: Option<MemoryAllocation>,
     freezing_threshold: Option<NumSeconds>,
+    wasm_memory_limit: Option<NumBytes>,
+    wasm_memory_threshold: Option<NumBytes>,
+    reserved_cycles_limit: Option<Cycles>,
 }
 
 #[allow(dead_code)]
@@ -126,6 +176,9 @@ impl CanisterSettingsBuilder {
             compute_allocation: None,
             memory_allocation: None,
             freezing_threshold: None,
+            wasm_memory_limit: None,
+            wasm_memory_threshold: None,
+            reserved_cycles_limit: None,
         }
     }
 
@@ -136,6 +189,9 @@ impl CanisterSettingsBuilder {
             compute_allocation: self.compute_allocation,
             memory_allocation: self.memory_allocation,
             freezing_threshold: self.freezing_threshold,
+            wasm_memory_limit: self.wasm_memory_limit,
+            wasm_memory_threshold: self.wasm_memory_threshold,
+            reserved_cycles_limit: self.reserved_cycles_limit,
         }
     }
 
@@ -173,12 +229,36 @@ impl CanisterSettingsBuilder {
             ..self
         }
     }
+
+    pub fn with_wasm_memory_limit(self, wasm_memory_limit: NumBytes) -> Self {
+        Self {
+            wasm_memory_limit: Some(wasm_memory_limit),
+            ..self
+        }
+    }
+
+    pub fn with_wasm_memory_threshold(self, wasm_memory_threshold: NumBytes) -> Self {
+        Self {
+            wasm_memory_threshold: Some(wasm_memory_threshold),
+            ..self
+        }
+    }
+
+    pub fn with_reserved_cycles_limit(self, reserved_cycles_limit: Cycles) -> Self {
+        Self {
+            reserved_cycles_limit: Some(reserved_cycles_limit),
+            ..self
+        }
+    }
 }
 
 pub enum UpdateSettingsError {
     ComputeAllocation(InvalidComputeAllocationError),
     MemoryAllocation(InvalidMemoryAllocationError),
     FreezingThresholdOutOfRange { provided: candid::Nat },
+    WasmMemoryLimitOutOfRange { provided: candid::Nat },
+    WasmMemoryThresholdOutOfRange { provided: candid::Nat },
+    ReservedCyclesLimitOutOfRange { provided: candid::Nat },
 }
 
 impl From<UpdateSettingsError> for UserError {
@@ -207,6 +287,27 @@ impl From<UpdateSettingsError> for UserError {
                     provided
                 ),
             ),
+            UpdateSettingsError::WasmMemoryLimitOutOfRange { provided } => UserError::new(
+                ErrorCode::CanisterContractViolation,
+                format!(
+                    "Wasm memory limit expected to be in the range of [0..2^64-1], got {}",
+                    provided
+                ),
+            ),
+            UpdateSettingsError::WasmMemoryThresholdOutOfRange { provided } => UserError::new(
+                ErrorCode::CanisterContractViolation,
+                format!(
+                    "Wasm memory threshold expected to be in the range of [0..2^64-1], got {}",
+                    provided
+                ),
+            ),
+            UpdateSettingsError::ReservedCyclesLimitOutOfRange { provided } => UserError::new(
+                ErrorCode::CanisterContractViolation,
+                format!(
+                    "Reserved cycles limit expected to be in the range of [0..2^128-1], got {}",
+                    provided
+                ),
+            ),
         }
     }
 }
@@ -229,6 +330,10 @@ pub(crate) struct ValidatedCanisterSettings {
     compute_allocation: Option<ComputeAllocation>,
     memory_allocation: Option<MemoryAllocation>,
     freezing_threshold: Option<NumSeconds>,
+    wasm_memory_limit: Option<NumBytes>,
+    wasm_memory_threshold: Option<NumBytes>,
+    reserved_cycles_limit: Option<Cycles>,
+    reservation_cycles: Cycles,
 }
 
 impl ValidatedCanisterSettings {
@@ -251,6 +356,24 @@ impl ValidatedCanisterSettings {
     pub fn freezing_threshold(&self) -> Option<NumSeconds> {
         self.freezing_threshold
     }
+
+    pub fn wasm_memory_limit(&self) -> Option<NumBytes> {
+        self.wasm_memory_limit
+    }
+
+    pub fn wasm_memory_threshold(&self) -> Option<NumBytes> {
+        self.wasm_memory_threshold
+    }
+
+    pub fn reserved_cycles_limit(&self) -> Option<Cycles> {
+        self.reserved_cycles_limit
+    }
+
+    /// The cycles that need to be moved to the reserved balance when the
+    /// settings are applied.
+    pub fn reservation_cycles(&self) -> Cycles {
+        self.reservation_cycles
+    }
 }
 
 /// Validates the new canisters settings:
@@ -258,14 +381,22 @@ impl ValidatedCanisterSettings {
 ///     - it cannot be lower than the current canister memory usage.
 ///     - there must be enough available subnet capacity for the change.
 ///     - there must be enough cycles to avoid freezing the canister.
+///     - an increase reserves cycles if the subnet memory usage is above the
+///       threshold; the reservation must not exceed the reserved cycles limit.
 /// - compute allocation:
 ///     - there must be enough available compute capacity for the change.
 ///     - there must be enough cycles to avoid freezing the canister.
 /// - controllers:
 ///     - the number of controllers cannot exceed the given maximum.
+/// - reserved cycles limit:
+///     - it cannot be lower than the current reserved balance.
+/// - Wasm memory limit:
+///     - unless it is zero (no limit), it cannot be lower than the current
+///       Wasm memory usage.
 pub(crate) fn validate_canister_settings(
     settings: CanisterSettings,
     canister_memory_usage: NumBytes,
+    canister_wasm_memory_usage: NumBytes,
     canister_memory_allocation: MemoryAllocation,
     subnet_available_memory: &SubnetAvailableMemory,
     canister_compute_allocation: ComputeAllocation,
@@ -277,6 +408,8 @@ pub(crate) fn validate_canister_settings(
     cycles_account_manager: &CyclesAccountManager,
     subnet_size: usize,
     canister_reserved_balance: Cycles,
+    canister_reserved_balance_limit: Option<Cycles>,
+    subnet_memory_saturation: &ResourceSaturation,
 ) -> Result<ValidatedCanisterSettings, CanisterManagerError> {
     if let Some(new_memory_allocation) = settings.memory_allocation {
         // The new memory allocation cannot be lower than the current canister
@@ -352,6 +485,60 @@ pub(crate) fn validate_canister_settings(
         .memory_allocation
         .unwrap_or(canister_memory_allocation);
 
+    if let Some(limit) = settings.reserved_cycles_limit() {
+        if canister_reserved_balance > limit {
+            return Err(CanisterManagerError::InvalidSettings {
+                message: format!(
+                    "Invalid settings: 'reserved_cycles_limit' cannot be lower than the reserved cycles balance {}",
+                    canister_reserved_balance
+                ),
+            });
+        }
+    }
+
+    if let Some(limit) = settings.wasm_memory_limit() {
+        if limit.get() != 0 && limit < canister_wasm_memory_usage {
+            return Err(CanisterManagerError::InvalidSettings {
+                message: format!(
+                    "Invalid settings: 'wasm_memory_limit' cannot be lower than the current Wasm memory usage {}",
+                    canister_wasm_memory_usage
+                ),
+            });
+        }
+    }
+
+    let reserved_balance_limit = settings
+        .reserved_cycles_limit()
+        .or(canister_reserved_balance_limit);
+
+    // Only the part of the new memory allocation that exceeds the memory the
+    // canister already holds is newly allocated on the subnet.
+    let allocated_bytes = new_memory_allocation
+        .allocated_bytes(canister_memory_usage)
+        .get()
+        .saturating_sub(
+            canister_memory_allocation
+                .allocated_bytes(canister_memory_usage)
+                .get(),
+        );
+    let reservation_cycles = cycles_account_manager.storage_reservation_cycles(
+        NumBytes::from(allocated_bytes),
+        subnet_memory_saturation,
+        subnet_size,
+    );
+    if let Some(limit) = reserved_balance_limit {
+        let requested = canister_reserved_balance + reservation_cycles;
+        if requested > limit {
+            return Err(
+                CanisterManagerError::ReservedCyclesLimitExceededInMemoryAllocation {
+                    memory_allocation: new_memory_allocation,
+                    requested,
+                    limit,
+                },
+            );
+        }
+    }
+
     let new_compute_allocation = settings
         .compute_allocation()
         .unwrap_or(canister_compute_allocation);
@@ -366,8 +553,11 @@ pub(crate) fn validate_canister_settings(
         canister_memory_usage,
         new_compute_allocation,
         subnet_size,
-        canister_reserved_balance,
+        canister_reserved_balance + reservation_cycles,
     );
+    // The reserved cycles are taken from the main balance, so they need to be
+    // available on top of the freezing threshold.
+    let threshold = threshold + reservation_cycles;
 
     if canister_cycles_balance < threshold {
         if new_compute_allocation > canister_compute_allocation {
@@ -400,5 +590,9 @@ pub(crate) fn validate_canister_settings(
         compute_allocation: settings.compute_allocation(),
         memory_allocation: settings.memory_allocation(),
         freezing_threshold: settings.freezing_threshold(),
+        wasm_memory_limit: settings.wasm_memory_limit(),
+        wasm_memory_threshold: settings.wasm_memory_threshold(),
+        reserved_cycles_limit: settings.reserved_cycles_limit(),
+        reservation_cycles,
     })
 }
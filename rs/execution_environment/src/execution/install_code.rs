@@ -5,6 +5,7 @@ use std::path::{Path, PathBuf};
 
 use ic_base_types::{CanisterId, NumBytes, PrincipalId};
 use ic_config::flag_status::FlagStatus;
+use ic_cycles_account_manager::ResourceSaturation;
 use ic_embedders::wasm_executor::CanisterStateChanges;
 use ic_ic00_types::{CanisterChangeDetails, CanisterChangeOrigin, CanisterInstallMode};
 use ic_interfaces::execution_environment::{
@@ -12,7 +13,9 @@ use ic_interfaces::execution_environment::{
 };
 use ic_logger::{error, fatal, info, warn};
 use ic_replicated_state::metadata_state::subnet_call_context_manager::InstallCodeCallId;
-use ic_replicated_state::{CanisterState, ExecutionState};
+use ic_replicated_state::{
+    canister_state::system_state::ReservationError, CanisterState, ExecutionState,
+};
 use ic_state_layout::{CanisterLayout, CheckpointLayout, ReadOnly};
 use ic_sys::PAGE_SIZE;
 use ic_system_api::ExecutionParameters;
@@ -357,15 +360,19 @@ impl InstallCodeHelper {
 
         validate_controller(&self.canister, &original.sender)?;
 
-        validate_canister_settings(
+        let validated_settings = validate_canister_settings(
             CanisterSettings {
                 controller: None,
                 controllers: None,
                 compute_allocation: original.requested_compute_allocation,
                 memory_allocation: original.requested_memory_allocation,
                 freezing_threshold: None,
+                wasm_memory_limit: None,
+                wasm_memory_threshold: None,
+                reserved_cycles_limit: None,
             },
             self.canister.memory_usage(),
+            self.canister.wasm_memory_usage(),
             self.canister.memory_allocation(),
             &round_limits.subnet_available_memory,
             self.canister.compute_allocation(),
@@ -377,6 +384,12 @@ impl InstallCodeHelper {
             round.cycles_account_manager,
             original.subnet_size,
             self.canister.system_state.reserved_balance(),
+            self.canister.system_state.reserved_balance_limit(),
+            &ResourceSaturation::from_subnet_available_memory(
+                &round_limits.subnet_available_memory,
+                config.subnet_memory_threshold,
+                config.subnet_memory_capacity,
+            ),
         )?;
 
         match original.mode {
@@ -394,6 +407,31 @@ impl InstallCodeHelper {
             return Err(CanisterManagerError::InstallCodeRateLimited(id));
         }
 
+        // Reserve cycles for the increase of the memory allocation. This is
+        // non-zero only if the subnet memory usage is above the threshold.
+        let memory_allocation = original
+            .requested_memory_allocation
+            .unwrap_or_else(|| self.canister.memory_allocation());
+        self.canister
+            .system_state
+            .reserve_cycles(validated_settings.reservation_cycles())
+            .map_err(|err| match err {
+                ReservationError::InsufficientCycles(err) => {
+                    CanisterManagerError::InsufficientCyclesInMemoryAllocation {
+                        memory_allocation,
+                        available: err.available,
+                        threshold: err.requested,
+                    }
+                }
+                ReservationError::ReservedLimitExceeded { requested, limit } => {
+                    CanisterManagerError::ReservedCyclesLimitExceededInMemoryAllocation {
+                        memory_allocation,
+                        requested,
+                        limit,
+                    }
+                }
+            })?;
+
         Ok(())
     }
 
@@ -445,6 +483,22 @@ impl InstallCodeHelper {
             };
         self.canister.execution_state = Some(execution_state);
 
+        // The initial Wasm memory of the new module must fit the Wasm memory
+        // limit of the canister.
+        if let Some(limit) = self.canister.system_state.wasm_memory_limit {
+            let wasm_memory_usage = self.canister.wasm_memory_usage();
+            if wasm_memory_usage > limit {
+                return Err((
+                    self.canister.canister_id(),
+                    HypervisorError::WasmMemoryLimitExceeded {
+                        bytes: wasm_memory_usage,
+                        limit,
+                    },
+                )
+                    .into());
+            }
+        }
+
         // Update the compute allocation.
         let new_compute_allocation = original
             .requested_compute_allocation
@@ -9,6 +9,7 @@ use crate::execution::common::{validate_canister, validate_method};
 use crate::execution_environment::RoundLimits;
 use crate::{Hypervisor, NonReplicatedQueryKind};
 use ic_error_types::UserError;
+use ic_interfaces::execution_environment::SystemApiCallCounters;
 use ic_replicated_state::{CallOrigin, CanisterState, NetworkTopology};
 use ic_system_api::{ApiType, ExecutionParameters};
 use ic_types::ingress::WasmResult;
@@ -16,6 +17,10 @@ use ic_types::methods::{FuncRef, WasmMethod};
 use ic_types::{Cycles, NumInstructions, Time};
 
 // Execute non replicated query.
+// Returns the counters of environment-dependent system API calls in addition
+// to the new canister state, the remaining instructions, and the result.
+// If the instruction limits allow more than one slice, then `between_slices`
+// is invoked after every slice of the execution.
 #[allow(clippy::too_many_arguments)]
 pub fn execute_non_replicated_query(
     query_kind: NonReplicatedQueryKind,
@@ -28,10 +33,12 @@ pub fn execute_non_replicated_query(
     network_topology: &NetworkTopology,
     hypervisor: &Hypervisor,
     round_limits: &mut RoundLimits,
+    between_slices: &dyn Fn(),
 ) -> (
     CanisterState,
     NumInstructions,
     Result<Option<WasmResult>, UserError>,
+    SystemApiCallCounters,
 ) {
     // Validate that the canister is running.
     if let Err(err) = validate_canister(&canister) {
@@ -39,6 +46,7 @@ pub fn execute_non_replicated_query(
             canister,
             execution_parameters.instruction_limits.message(),
             Err(err),
+            SystemApiCallCounters::default(),
         );
     }
 
@@ -51,6 +59,7 @@ pub fn execute_non_replicated_query(
             canister,
             execution_parameters.instruction_limits.message(),
             Err(err.into_user_error(&canister_id)),
+            SystemApiCallCounters::default(),
         );
     }
 
@@ -92,7 +101,7 @@ pub fn execute_non_replicated_query(
     // As we are executing the query in non-replicated mode, we can
     // modify the canister as the caller is not going to be able to
     // commit modifications to the canister anyway.
-    let (output, output_execution_state, output_system_state) = hypervisor.execute(
+    let (output, output_execution_state, output_system_state) = hypervisor.execute_in_slices(
         api_type,
         time,
         canister.system_state,
@@ -102,6 +111,7 @@ pub fn execute_non_replicated_query(
         canister.execution_state.clone().unwrap(),
         network_topology,
         round_limits,
+        between_slices,
     );
     canister.system_state = output_system_state;
     if preserve_changes {
@@ -111,5 +121,10 @@ pub fn execute_non_replicated_query(
     let result = output
         .wasm_result
         .map_err(|err| err.into_user_error(&canister.canister_id()));
-    (canister, output.num_instructions_left, result)
+    (
+        canister,
+        output.num_instructions_left,
+        result,
+        output.system_api_call_counters,
+    )
 }
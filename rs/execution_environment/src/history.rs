@@ -301,5 +301,7 @@ fn dashboard_label_value_from(code: ErrorCode) -> &'static str {
         InsufficientCyclesInComputeAllocation => "Canister does not have enough cycles to increase its compute allocation",
         InsufficientCyclesInMemoryAllocation => "Canister does not have enough cycles to increase its memory allocation",
         InsufficientCyclesInMemoryGrow => "Canister does not have enough cycles to grow memory",
+        ReservedCyclesLimitExceededInMemoryAllocation => "Canister cannot increase memory allocation due to its reserved cycles limit",
+        ReservedCyclesLimitExceededInMemoryGrow => "Canister cannot grow memory due to its reserved cycles limit",
     }
 }
@@ -212,7 +212,7 @@ impl Hypervisor {
             }
             FlagStatus::Disabled => {
                 let executor = WasmExecutorImpl::new(
-                    WasmtimeEmbedder::new(embedder_config, log.clone()),
+                    WasmtimeEmbedder::new(embedder_config.clone(), log.clone()),
                     metrics_registry,
                     log.clone(),
                     Arc::clone(&fd_factory),
@@ -221,6 +221,17 @@ impl Hypervisor {
             }
         };
 
+        let compilation_cache = match &config.compilation_cache_dir {
+            Some(dir) => CompilationCache::new_persisted(
+                config.max_compilation_cache_size,
+                dir,
+                config.max_compilation_cache_disk_size,
+                &embedder_config,
+                log.clone(),
+            ),
+            None => CompilationCache::new(config.max_compilation_cache_size),
+        };
+
         Self {
             wasm_executor,
             metrics: Arc::new(HypervisorMetrics::new(metrics_registry)),
@@ -228,7 +239,7 @@ impl Hypervisor {
             own_subnet_type,
             log,
             cycles_account_manager,
-            compilation_cache: Arc::new(CompilationCache::new(config.max_compilation_cache_size)),
+            compilation_cache: Arc::new(compilation_cache),
             deterministic_time_slicing: config.deterministic_time_slicing,
             cost_to_compile_wasm_instruction: config
                 .embedders_config
@@ -276,11 +287,11 @@ impl Hypervisor {
         &self,
         api_type: ApiType,
         time: Time,
-        mut system_state: SystemState,
+        system_state: SystemState,
         canister_current_memory_usage: NumBytes,
         execution_parameters: ExecutionParameters,
         func_ref: FuncRef,
-        mut execution_state: ExecutionState,
+        execution_state: ExecutionState,
         network_topology: &NetworkTopology,
         round_limits: &mut RoundLimits,
     ) -> (WasmExecutionOutput, ExecutionState, SystemState) {
@@ -288,7 +299,43 @@ impl Hypervisor {
             execution_parameters.instruction_limits.message(),
             execution_parameters.instruction_limits.slice()
         );
-        let execution_result = self.execute_dts(
+        self.execute_in_slices(
+            api_type,
+            time,
+            system_state,
+            canister_current_memory_usage,
+            execution_parameters,
+            func_ref,
+            execution_state,
+            network_topology,
+            round_limits,
+            &|| {},
+        )
+    }
+
+    /// Executes the given WebAssembly function to completion, possibly in
+    /// multiple slices if the slice limit is lower than the message limit.
+    /// The `between_slices` hook is invoked on the executing thread after
+    /// every paused slice and before resuming the execution.
+    ///
+    /// Unlike `execute_dts()`, this does not depend on the deterministic time
+    /// slicing flag of the subnet, so it must only be used for non-replicated
+    /// executions that do not need to be persisted between rounds.
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_in_slices(
+        &self,
+        api_type: ApiType,
+        time: Time,
+        mut system_state: SystemState,
+        canister_current_memory_usage: NumBytes,
+        execution_parameters: ExecutionParameters,
+        func_ref: FuncRef,
+        mut execution_state: ExecutionState,
+        network_topology: &NetworkTopology,
+        round_limits: &mut RoundLimits,
+        between_slices: &dyn Fn(),
+    ) -> (WasmExecutionOutput, ExecutionState, SystemState) {
+        let mut execution_result = self.execute_wasm(
             api_type,
             &execution_state,
             &system_state,
@@ -298,12 +345,17 @@ impl Hypervisor {
             round_limits,
             network_topology,
         );
-        let (slice, mut output, canister_state_changes) = match execution_result {
-            WasmExecutionResult::Finished(slice, output, system_state_changes) => {
-                (slice, output, system_state_changes)
-            }
-            WasmExecutionResult::Paused(_, _) => {
-                unreachable!("DTS is not supported");
+        let (slice, mut output, canister_state_changes) = loop {
+            match execution_result {
+                WasmExecutionResult::Finished(slice, output, system_state_changes) => {
+                    break (slice, output, system_state_changes);
+                }
+                WasmExecutionResult::Paused(slice, paused) => {
+                    update_round_limits(round_limits, &slice);
+                    between_slices();
+                    execution_result = paused.resume(&execution_state);
+                    self.metrics.observe(&execution_result);
+                }
             }
         };
         update_round_limits(round_limits, &slice);
@@ -344,6 +396,30 @@ impl Hypervisor {
                 execution_parameters.instruction_limits.slice()
             ),
         }
+        self.execute_wasm(
+            api_type,
+            execution_state,
+            system_state,
+            canister_current_memory_usage,
+            execution_parameters,
+            func_ref,
+            round_limits,
+            network_topology,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute_wasm(
+        &self,
+        api_type: ApiType,
+        execution_state: &ExecutionState,
+        system_state: &SystemState,
+        canister_current_memory_usage: NumBytes,
+        execution_parameters: ExecutionParameters,
+        func_ref: FuncRef,
+        round_limits: &mut RoundLimits,
+        network_topology: &NetworkTopology,
+    ) -> WasmExecutionResult {
         let static_system_state = SandboxSafeSystemState::new(
             system_state,
             *self.cycles_account_manager,
@@ -1,6 +1,7 @@
 use assert_matches::assert_matches;
 use candid::{Decode, Encode};
 use ic_base_types::{NumSeconds, PrincipalId};
+use ic_config::flag_status::FlagStatus;
 use ic_error_types::{ErrorCode, RejectCode};
 use ic_ic00_types::{CanisterChange, CanisterHttpResponsePayload};
 use ic_interfaces::execution_environment::{HypervisorError, SubnetAvailableMemory};
@@ -13,6 +14,7 @@ use ic_replicated_state::{
 };
 use ic_replicated_state::{CanisterStatus, NumWasmPages, PageMap};
 use ic_sys::PAGE_SIZE;
+use ic_system_api::InstructionLimits;
 use ic_test_utilities::assert_utils::assert_balance_equals;
 use ic_test_utilities_execution_environment::{
     assert_empty_reply, check_ingress_status, get_reply, wasm_compilation_cost,
@@ -23,6 +25,7 @@ use ic_test_utilities_metrics::{fetch_histogram_stats, HistogramStats};
 use ic_types::{
     ingress::{IngressState, IngressStatus, WasmResult},
     messages::CanisterTask,
+    messages::UserQuery,
     messages::MAX_INTER_CANISTER_PAYLOAD_IN_BYTES,
     methods::WasmMethod,
     CanisterId, ComputeAllocation, Cycles, NumBytes, NumInstructions, MAX_STABLE_MEMORY_IN_BYTES,
@@ -30,8 +33,10 @@ use ic_types::{
 use ic_universal_canister::{call_args, wasm, UNIVERSAL_CANISTER_WASM};
 use proptest::prelude::*;
 use proptest::test_runner::{TestRng, TestRunner};
+use std::cell::{Cell, RefCell};
 use std::collections::BTreeSet;
 use std::mem::size_of;
+use std::sync::Arc;
 use std::time::Duration;
 
 const MAX_NUM_INSTRUCTIONS: NumInstructions = NumInstructions::new(1_000_000_000);
@@ -2348,6 +2353,59 @@ fn subnet_available_memory_is_respected_by_memory_grow() {
     assert_eq!(ErrorCode::CanisterOutOfMemory, err.code());
 }
 
+#[test]
+fn wasm_memory_limit_is_respected_by_memory_grow() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (func (export "canister_update grow")
+                (drop (memory.grow (i32.const 1)))
+            )
+            (func (export "canister_update grow_too_much")
+                (drop (memory.grow (i32.const 10)))
+            )
+            (memory 1 20)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+    test.canister_update_wasm_memory_limit(canister_id, NumBytes::new(5 * WASM_PAGE_SIZE as u64))
+        .unwrap();
+
+    let result = test.ingress(canister_id, "grow", vec![]);
+    assert_empty_reply(result);
+    assert_eq!(
+        test.execution_state(canister_id).wasm_memory.size,
+        NumWasmPages::new(2)
+    );
+
+    let err = test
+        .ingress(canister_id, "grow_too_much", vec![])
+        .unwrap_err();
+    assert_eq!(ErrorCode::CanisterOutOfMemory, err.code());
+    assert!(err.description().contains("exceeded its Wasm memory limit"));
+    assert_eq!(
+        test.execution_state(canister_id).wasm_memory.size,
+        NumWasmPages::new(2)
+    );
+}
+
+#[test]
+fn on_low_wasm_memory_hook_can_grow_memory() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (func (export "canister_on_low_wasm_memory")
+                (drop (memory.grow (i32.const 1)))
+            )
+            (memory 1 20)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+    test.canister_task(canister_id, CanisterTask::OnLowWasmMemory);
+    assert_eq!(
+        test.execution_state(canister_id).wasm_memory.size,
+        NumWasmPages::new(2)
+    );
+}
+
 #[test]
 fn subnet_available_memory_is_updated() {
     let mut test = ExecutionTestBuilder::new().build();
@@ -4876,6 +4934,72 @@ fn dts_concurrent_subnet_available_change() {
     assert_eq!(err.code(), ErrorCode::CanisterOutOfMemory);
 }
 
+#[test]
+fn sliced_query_can_exceed_the_query_instruction_limit() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_instruction_limit_without_dts(1_000_000)
+        .with_query_time_slicing(100_000_000, 1_000_000)
+        .build();
+    let canister_id = test.universal_canister().unwrap();
+    let work = wasm()
+        .instruction_counter_is_at_least(5_000_000)
+        .reply_data(&[1, 2, 3])
+        .build();
+    let result = test.non_replicated_query(canister_id, "query", work);
+    assert_eq!(result, Ok(WasmResult::Reply(vec![1, 2, 3])));
+}
+
+#[test]
+fn short_query_runs_between_slices_of_long_query() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_instruction_limit_without_dts(1_000_000)
+        .with_query_time_slicing(100_000_000, 1_000_000)
+        .build();
+    let long_canister_id = test.universal_canister().unwrap();
+    let short_canister_id = test.universal_canister().unwrap();
+    let state = Arc::new(test.state().clone());
+    let work = wasm()
+        .instruction_counter_is_at_least(5_000_000)
+        .reply_data(&[1, 2, 3])
+        .build();
+
+    let num_slices = Cell::new(1);
+    let short_query_results = RefCell::new(vec![]);
+    let result = test.non_replicated_query_in_slices(
+        long_canister_id,
+        "query",
+        work,
+        InstructionLimits::new(
+            FlagStatus::Enabled,
+            NumInstructions::from(100_000_000),
+            NumInstructions::from(1_000_000),
+        ),
+        &|| {
+            num_slices.set(num_slices.get() + 1);
+            let query = UserQuery {
+                source: test.user_id(),
+                receiver: short_canister_id,
+                method_name: "query".to_string(),
+                method_payload: wasm().reply_data(&[4]).build(),
+                ingress_expiry: 0,
+                nonce: None,
+            };
+            let result = test.query(query, Arc::clone(&state), vec![]);
+            short_query_results.borrow_mut().push(result);
+        },
+    );
+
+    // The long query executes well above the limit of unsliced queries.
+    assert_eq!(result, Ok(Some(WasmResult::Reply(vec![1, 2, 3]))));
+    assert!(num_slices.get() > 1);
+    // A short query ran and succeeded after every slice but the last one.
+    let short_query_results = short_query_results.into_inner();
+    assert_eq!(short_query_results.len() as u64, num_slices.get() - 1);
+    for result in short_query_results {
+        assert_eq!(result, Ok(WasmResult::Reply(vec![4])));
+    }
+}
+
 #[test]
 fn system_state_apply_change_fails() {
     let mut test = ExecutionTestBuilder::new()
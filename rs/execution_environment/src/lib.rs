@@ -26,7 +26,9 @@ pub use execution_environment::{
 pub use history::{IngressHistoryReaderImpl, IngressHistoryWriterImpl};
 pub use hypervisor::{Hypervisor, HypervisorMetrics};
 use ic_base_types::PrincipalId;
-use ic_config::{execution_environment::Config, subnet_config::SchedulerConfig};
+use ic_config::{
+    execution_environment::Config, flag_status::FlagStatus, subnet_config::SchedulerConfig,
+};
 use ic_cycles_account_manager::CyclesAccountManager;
 use ic_interfaces::batch_payload::BatchPayloadBuilder;
 use ic_interfaces::execution_environment::AnonymousQueryService;
@@ -40,7 +42,7 @@ use ic_metrics::MetricsRegistry;
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::page_map::PageAllocatorFileDescriptor;
 use ic_replicated_state::{CallOrigin, NetworkTopology, ReplicatedState};
-use ic_types::{messages::CallContextId, SubnetId};
+use ic_types::{messages::CallContextId, NodeId, SubnetId};
 use ingress_filter::IngressFilter;
 pub use query_handler::InternalHttpQueryHandler;
 use query_handler::{HttpQueryHandler, QueryScheduler, QuerySchedulerFlag};
@@ -98,6 +100,7 @@ impl ExecutionServices {
     pub fn setup_execution(
         logger: ReplicaLogger,
         metrics_registry: &MetricsRegistry,
+        own_node_id: NodeId,
         own_subnet_id: SubnetId,
         own_subnet_type: SubnetType,
         scheduler_config: SchedulerConfig,
@@ -125,7 +128,9 @@ impl ExecutionServices {
         let ingress_history_reader =
             Box::new(IngressHistoryReaderImpl::new(Arc::clone(&state_reader)));
 
-        let (query_stats_collector, query_stats_payload_builder) = init_query_stats(logger.clone());
+        let (query_stats_collector, query_stats_payload_builder) =
+            init_query_stats(own_node_id, config.query_stats_epoch_length, logger.clone());
+        let query_stats_collector = Arc::new(query_stats_collector);
 
         let exec_env = Arc::new(ExecutionEnvironment::new(
             logger.clone(),
@@ -146,7 +151,7 @@ impl ExecutionServices {
             metrics_registry,
             scheduler_config.max_instructions_per_message_without_dts,
             Arc::clone(&cycles_account_manager),
-            query_stats_collector,
+            Arc::clone(&query_stats_collector),
         ));
 
         let query_scheduler = QueryScheduler::new(
@@ -163,6 +168,8 @@ impl ExecutionServices {
             Arc::clone(&sync_query_handler) as Arc<_>,
             query_scheduler.clone(),
             Arc::clone(&state_reader),
+            (config.query_stats_aggregation == FlagStatus::Enabled)
+                .then_some(query_stats_collector),
         );
         let ingress_filter = IngressFilter::new_service(
             query_scheduler.clone(),
@@ -33,7 +33,7 @@ use ic_types::{
         Blob, Certificate, CertificateDelegation, HttpQueryResponse, HttpQueryResponseReply,
         UserQuery,
     },
-    CanisterId, NumInstructions,
+    CanisterId, Height, NumInstructions,
 };
 use serde::Serialize;
 use std::convert::Infallible;
@@ -61,7 +61,7 @@ fn get_latest_certified_state_and_data_certificate(
     state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
     certificate_delegation: Option<CertificateDelegation>,
     canister_id: CanisterId,
-) -> Option<(Arc<ReplicatedState>, Vec<u8>)> {
+) -> Option<(Arc<ReplicatedState>, Vec<u8>, Height)> {
     // The path to fetch the data certificate for the canister.
     let path = SubTree(flatmap! {
         label("canister") => SubTree(
@@ -86,6 +86,7 @@ fn get_latest_certified_state_and_data_certificate(
                     signature: Blob(cert.signed.signature.signature.get().0),
                     delegation: certificate_delegation,
                 }),
+                cert.height,
             )
         })
 }
@@ -102,7 +103,7 @@ pub struct InternalHttpQueryHandler {
     metrics: QueryHandlerMetrics,
     max_instructions_per_query: NumInstructions,
     cycles_account_manager: Arc<CyclesAccountManager>,
-    local_query_execution_stats: QueryStatsCollector,
+    local_query_execution_stats: Arc<QueryStatsCollector>,
     query_cache: query_cache::QueryCache,
 }
 
@@ -112,6 +113,9 @@ pub(crate) struct HttpQueryHandler {
     internal: Arc<dyn QueryHandler<State = ReplicatedState>>,
     state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
     query_scheduler: QueryScheduler,
+    /// Advanced to the epoch of the certified state each query executes on, if
+    /// query stats aggregation is enabled.
+    query_stats_collector: Option<Arc<QueryStatsCollector>>,
 }
 
 impl InternalHttpQueryHandler {
@@ -123,9 +127,10 @@ impl InternalHttpQueryHandler {
         metrics_registry: &MetricsRegistry,
         max_instructions_per_query: NumInstructions,
         cycles_account_manager: Arc<CyclesAccountManager>,
-        local_query_execution_stats: QueryStatsCollector,
+        local_query_execution_stats: Arc<QueryStatsCollector>,
     ) -> Self {
         let query_cache_capacity = config.query_cache_capacity;
+        let query_cache_max_age = config.query_cache_max_age;
         Self {
             log,
             hypervisor,
@@ -135,7 +140,11 @@ impl InternalHttpQueryHandler {
             max_instructions_per_query,
             cycles_account_manager,
             local_query_execution_stats,
-            query_cache: query_cache::QueryCache::new(metrics_registry, query_cache_capacity),
+            query_cache: query_cache::QueryCache::new(
+                metrics_registry,
+                query_cache_capacity,
+                query_cache_max_age,
+            ),
         }
     }
 }
@@ -153,18 +162,18 @@ impl QueryHandler for InternalHttpQueryHandler {
 
         // Check the query cache first (if the query caching is enabled).
         // If a valid cache entry found, the result will be immediately returned.
-        // Otherwise, the key and the env will be kept for the `insert` below.
-        let (cache_entry_key, cache_entry_env) = if self.config.query_caching == FlagStatus::Enabled
-        {
+        // Otherwise, the key will be kept for the `push` below.
+        let cache_entry_key = if self.config.query_caching == FlagStatus::Enabled {
             let key = query_cache::EntryKey::from(&query);
-            let env = query_cache::EntryEnv::try_from((&key, state.as_ref()))?;
-
-            if let Some(result) = self.query_cache.get_valid_result(&key, &env) {
+            if let Some(result) =
+                self.query_cache
+                    .get_valid_result(&key, state.as_ref(), &data_certificate)
+            {
                 return result;
             }
-            (Some(key), Some(env))
+            Some(key)
         } else {
-            (None, None)
+            None
         };
 
         // Letting the canister grow arbitrarily when executing the
@@ -172,18 +181,43 @@ impl QueryHandler for InternalHttpQueryHandler {
         let subnet_available_memory = subnet_memory_capacity(&self.config);
         let max_canister_memory_size = self.config.max_canister_memory_size;
 
+        // Query time slicing relies on pausing the execution, which is
+        // supported only by sandboxed execution.
+        let (
+            max_instructions_per_query,
+            max_instructions_per_query_slice,
+            max_call_graph_instructions,
+        ) = if self.config.query_time_slicing == FlagStatus::Enabled
+            && self.config.canister_sandboxing_flag == FlagStatus::Enabled
+        {
+            (
+                self.config.max_instructions_per_sliced_query,
+                self.config.max_instructions_per_query_slice,
+                self.config
+                    .max_query_call_graph_instructions
+                    .max(self.config.max_instructions_per_sliced_query),
+            )
+        } else {
+            (
+                self.max_instructions_per_query,
+                self.max_instructions_per_query,
+                self.config.max_query_call_graph_instructions,
+            )
+        };
+
         let mut context = query_context::QueryContext::new(
             &self.log,
             self.hypervisor.as_ref(),
             self.own_subnet_type,
-            state,
-            data_certificate,
+            Arc::clone(&state),
+            data_certificate.clone(),
             subnet_available_memory,
             self.config.subnet_memory_capacity,
             max_canister_memory_size,
-            self.max_instructions_per_query,
+            max_instructions_per_query,
+            max_instructions_per_query_slice,
             self.config.max_query_call_graph_depth,
-            self.config.max_query_call_graph_instructions,
+            max_call_graph_instructions,
             self.config.max_query_call_walltime,
             self.config.instruction_overhead_per_query_call,
             self.config.composite_queries,
@@ -218,10 +252,19 @@ impl QueryHandler for InternalHttpQueryHandler {
         }
 
         // Add the query execution result to the query cache  (if the query caching is enabled).
-        if self.config.query_caching == FlagStatus::Enabled {
-            if let (Some(key), Some(env)) = (cache_entry_key, cache_entry_env) {
-                self.query_cache
-                    .push(key, query_cache::EntryValue::new(env, result.clone()));
+        // The entry captures the environment of all the canisters evaluated by
+        // the query. If any of them is not active, then the result is not cached.
+        if let Some(key) = cache_entry_key {
+            if let Ok(env) =
+                query_cache::EntryEnv::try_new(state.as_ref(), &context.evaluated_canister_ids)
+            {
+                let value = query_cache::EntryValue::new(
+                    env,
+                    result.clone(),
+                    &context.system_api_call_counters,
+                    &data_certificate,
+                );
+                self.query_cache.push(key, value);
             }
         }
         result
@@ -233,10 +276,12 @@ impl HttpQueryHandler {
         internal: Arc<dyn QueryHandler<State = ReplicatedState>>,
         query_scheduler: QueryScheduler,
         state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
+        query_stats_collector: Option<Arc<QueryStatsCollector>>,
     ) -> QueryExecutionService {
         BoxCloneService::new(Self {
             internal,
             state_reader,
+            query_stats_collector,
             query_scheduler,
         })
     }
@@ -271,6 +316,7 @@ impl Service<(UserQuery, Option<CertificateDelegation>)> for HttpQueryHandler {
     ) -> Self::Future {
         let internal = Arc::clone(&self.internal);
         let state_reader = Arc::clone(&self.state_reader);
+        let query_stats_collector = self.query_stats_collector.clone();
         let (tx, rx) = oneshot::channel();
         let canister_id = query.receiver;
         self.query_scheduler.push(canister_id, move || {
@@ -288,7 +334,10 @@ impl Service<(UserQuery, Option<CertificateDelegation>)> for HttpQueryHandler {
                     certificate_delegation,
                     query.receiver,
                 ) {
-                    Some((state, cert)) => {
+                    Some((state, cert, height)) => {
+                        if let Some(query_stats_collector) = query_stats_collector {
+                            query_stats_collector.set_epoch_from_height(height);
+                        }
                         let result = internal.query(query, state, cert);
 
                         let http_query_response = match result {
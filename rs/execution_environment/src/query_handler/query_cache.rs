@@ -1,11 +1,13 @@
 use ic_base_types::{CanisterId, NumBytes};
 use ic_error_types::UserError;
+use ic_ic00_types::CanisterStatusType;
+use ic_interfaces::execution_environment::SystemApiCallCounters;
 use ic_metrics::MetricsRegistry;
 use ic_replicated_state::ReplicatedState;
 use ic_types::{ingress::WasmResult, messages::UserQuery, CountBytes, Cycles, Time, UserId};
 use ic_utils_lru_cache::LruCache;
 use prometheus::{Histogram, IntCounter, IntGauge};
-use std::{mem::size_of_val, sync::Mutex};
+use std::{mem::size_of_val, sync::Mutex, time::Duration};
 
 use crate::metrics::duration_histogram;
 
@@ -20,6 +22,8 @@ pub(crate) struct QueryCacheMetrics {
     pub invalidated_entries_by_time: IntCounter,
     pub invalidated_entries_by_canister_version: IntCounter,
     pub invalidated_entries_by_canister_balance: IntCounter,
+    pub invalidated_entries_by_max_age: IntCounter,
+    pub invalidated_entries_by_data_certificate: IntCounter,
     pub invalidated_entries_duration: Histogram,
     pub count_bytes: IntGauge,
     pub len: IntGauge,
@@ -61,6 +65,14 @@ impl QueryCacheMetrics {
                 "execution_query_cache_invalidated_entries_by_canister_balance_total",
                 "The total number of invalidated entries due to the changed canister balance",
             ),
+            invalidated_entries_by_max_age: metrics_registry.int_counter(
+                "execution_query_cache_invalidated_entries_by_max_age_total",
+                "The total number of invalidated entries due to exceeding the max age",
+            ),
+            invalidated_entries_by_data_certificate: metrics_registry.int_counter(
+                "execution_query_cache_invalidated_entries_by_data_certificate_total",
+                "The total number of invalidated entries due to a newer data certificate",
+            ),
             invalidated_entries_duration: duration_histogram(
                 "execution_query_cache_invalidated_entries_duration_seconds",
                 "The duration of invalidated cache entries in seconds",
@@ -121,27 +133,51 @@ impl From<&UserQuery> for EntryKey {
 pub(crate) struct EntryEnv {
     /// The Consensus-determined time when the cache entry was created.
     pub batch_time: Time,
-    /// Receiving canister version.
+    /// The environment of all the canisters evaluated by the query, i.e. the
+    /// receiving canister and, for composite queries, all the canisters in the
+    /// query call graph.
+    pub canisters: Vec<CanisterEnv>,
+}
+
+/// The part of the environment metadata that belongs to a single canister.
+#[derive(PartialEq)]
+pub(crate) struct CanisterEnv {
+    pub canister_id: CanisterId,
     pub canister_version: u64,
-    /// Receiving canister cycles balance.
     pub canister_balance: Cycles,
+    /// Starting and stopping a canister doesn't change its version, so the
+    /// status is tracked separately.
+    pub canister_status: CanisterStatusType,
 }
 
 impl CountBytes for EntryEnv {
     fn count_bytes(&self) -> usize {
-        size_of_val(self)
+        size_of_val(self) + size_of_val(self.canisters.as_slice())
     }
 }
 
-impl TryFrom<(&EntryKey, &ReplicatedState)> for EntryEnv {
-    type Error = UserError;
-
-    fn try_from((key, state): (&EntryKey, &ReplicatedState)) -> Result<Self, Self::Error> {
-        let canister = state.get_active_canister(&key.receiver)?;
+impl EntryEnv {
+    /// Captures the environment of the given canisters in the given state.
+    /// Returns an error if any of the canisters is not active.
+    pub(crate) fn try_new<'a>(
+        state: &ReplicatedState,
+        canister_ids: impl IntoIterator<Item = &'a CanisterId>,
+    ) -> Result<Self, UserError> {
+        let canisters = canister_ids
+            .into_iter()
+            .map(|canister_id| {
+                let canister = state.get_active_canister(canister_id)?;
+                Ok(CanisterEnv {
+                    canister_id: *canister_id,
+                    canister_version: canister.system_state.canister_version,
+                    canister_balance: canister.system_state.balance(),
+                    canister_status: canister.status(),
+                })
+            })
+            .collect::<Result<_, UserError>>()?;
         Ok(Self {
             batch_time: state.metadata.batch_time,
-            canister_version: canister.system_state.canister_version,
-            canister_balance: canister.system_state.balance(),
+            canisters,
         })
     }
 }
@@ -151,33 +187,84 @@ impl TryFrom<(&EntryKey, &ReplicatedState)> for EntryEnv {
 pub(crate) struct EntryValue {
     env: EntryEnv,
     result: Result<WasmResult, UserError>,
+    /// True if the query read the current time, so the entry is valid only
+    /// for the batch time it was created at.
+    includes_time: bool,
+    /// The data certificate the query read, if any. The entry is valid only
+    /// until a newer certification is available.
+    data_certificate: Option<Vec<u8>>,
 }
 
 impl CountBytes for EntryValue {
     fn count_bytes(&self) -> usize {
-        self.env.count_bytes() + self.result.count_bytes()
+        self.env.count_bytes()
+            + self.result.count_bytes()
+            + self.data_certificate.as_ref().map_or(0, |c| c.len())
     }
 }
 
 impl EntryValue {
-    pub(crate) fn new(env: EntryEnv, result: Result<WasmResult, UserError>) -> Self {
-        Self { env, result }
+    pub(crate) fn new(
+        env: EntryEnv,
+        result: Result<WasmResult, UserError>,
+        system_api_call_counters: &SystemApiCallCounters,
+        data_certificate: &[u8],
+    ) -> Self {
+        Self {
+            env,
+            result,
+            includes_time: system_api_call_counters.time > 0,
+            data_certificate: system_api_call_counters
+                .reads_data_certificate()
+                .then(|| data_certificate.to_vec()),
+        }
     }
 
-    fn is_valid(&self, env: &EntryEnv) -> bool {
-        self.env == *env
+    fn is_valid(
+        &self,
+        state: &ReplicatedState,
+        data_certificate: &[u8],
+        max_age: Duration,
+    ) -> bool {
+        let now = state.metadata.batch_time;
+        self.is_valid_time(now)
+            && !self.is_expired(now, max_age)
+            && self.is_valid_canister_versions(state)
+            && self.is_valid_canister_balances(state)
+            && self.is_valid_data_certificate(data_certificate)
     }
 
-    fn is_valid_time(&self, env: &EntryEnv) -> bool {
-        self.env.batch_time == env.batch_time
+    fn is_valid_time(&self, now: Time) -> bool {
+        !self.includes_time || self.env.batch_time == now
     }
 
-    fn is_valid_canister_version(&self, env: &EntryEnv) -> bool {
-        self.env.canister_version == env.canister_version
+    fn is_expired(&self, now: Time, max_age: Duration) -> bool {
+        now.saturating_sub(self.env.batch_time) > max_age
     }
 
-    fn is_valid_canister_balance(&self, env: &EntryEnv) -> bool {
-        self.env.canister_balance == env.canister_balance
+    fn is_valid_canister_versions(&self, state: &ReplicatedState) -> bool {
+        self.env.canisters.iter().all(|env| {
+            state
+                .get_active_canister(&env.canister_id)
+                .map_or(false, |c| {
+                    c.system_state.canister_version == env.canister_version
+                        && c.status() == env.canister_status
+                })
+        })
+    }
+
+    fn is_valid_canister_balances(&self, state: &ReplicatedState) -> bool {
+        self.env.canisters.iter().all(|env| {
+            state
+                .get_active_canister(&env.canister_id)
+                .map_or(false, |c| c.system_state.balance() == env.canister_balance)
+        })
+    }
+
+    fn is_valid_data_certificate(&self, data_certificate: &[u8]) -> bool {
+        self.data_certificate
+            .as_ref()
+            .map_or(true, |c| c == data_certificate)
     }
 
     fn result(&self) -> Result<WasmResult, UserError> {
@@ -195,6 +282,8 @@ pub(crate) struct QueryCache {
     // We can't use `RwLock`, as the `LruCache::get()` requires mutable reference
     // to update the LRU.
     cache: Mutex<LruCache<EntryKey, EntryValue>>,
+    // The maximum age of a cache entry in terms of the batch time.
+    max_age: Duration,
     // Query cache metrics (public for tests)
     pub(crate) metrics: QueryCacheMetrics,
 }
@@ -206,23 +295,31 @@ impl CountBytes for QueryCache {
 }
 
 impl QueryCache {
-    pub(crate) fn new(metrics_registry: &MetricsRegistry, capacity: NumBytes) -> Self {
+    pub(crate) fn new(
+        metrics_registry: &MetricsRegistry,
+        capacity: NumBytes,
+        max_age: Duration,
+    ) -> Self {
         QueryCache {
             cache: Mutex::new(LruCache::new(capacity)),
+            max_age,
             metrics: QueryCacheMetrics::new(metrics_registry),
         }
     }
 
+    /// Returns the cached result of the query if it is still valid for the
+    /// given state and data certificate.
     pub(crate) fn get_valid_result(
         &self,
         key: &EntryKey,
-        env: &EntryEnv,
+        state: &ReplicatedState,
+        data_certificate: &[u8],
     ) -> Option<Result<WasmResult, UserError>> {
         let mut cache = self.cache.lock().unwrap();
-        let now = env.batch_time;
+        let now = state.metadata.batch_time;
 
         if let Some(value) = cache.get(key) {
-            if value.is_valid(env) {
+            if value.is_valid(state, data_certificate, self.max_age) {
                 let res = value.result();
                 // Update the metrics.
                 self.metrics.hits.inc();
@@ -237,15 +334,21 @@ impl QueryCache {
                     .invalidated_entries_duration
                     .observe(value.elapsed_seconds(now));
                 // For the sake of correctness, we need a fall-through logic here.
-                if !value.is_valid_time(env) {
+                if !value.is_valid_time(now) {
                     self.metrics.invalidated_entries_by_time.inc();
                 }
-                if !value.is_valid_canister_version(env) {
+                if value.is_expired(now, self.max_age) {
+                    self.metrics.invalidated_entries_by_max_age.inc();
+                }
+                if !value.is_valid_canister_versions(state) {
                     self.metrics.invalidated_entries_by_canister_version.inc();
                 }
-                if !value.is_valid_canister_balance(env) {
+                if !value.is_valid_canister_balances(state) {
                     self.metrics.invalidated_entries_by_canister_balance.inc();
                 }
+                if !value.is_valid_data_certificate(data_certificate) {
+                    self.metrics.invalidated_entries_by_data_certificate.inc();
+                }
                 // The cache entry is no longer valid, remove it.
                 cache.pop(key);
             }
@@ -279,20 +382,32 @@ impl QueryCache {
 mod tests {
     use std::time::Duration;
 
+    use ic_ic00_types::CanisterStatusType;
+    use ic_interfaces::execution_environment::SystemApiCallCounters;
     use ic_state_machine_tests::WasmResult;
     use ic_types::{time, Cycles};
+    use ic_types_test_utils::ids::canister_test_id;
 
-    use super::{EntryEnv, EntryValue};
+    use super::{CanisterEnv, EntryEnv, EntryValue};
 
     #[test]
     fn query_cache_entry_value_elapsed_seconds() {
         let current_time = time::GENESIS;
         let entry_env = EntryEnv {
             batch_time: current_time,
-            canister_version: 1,
-            canister_balance: Cycles::new(0),
+            canisters: vec![CanisterEnv {
+                canister_id: canister_test_id(0),
+                canister_version: 1,
+                canister_balance: Cycles::new(0),
+                canister_status: CanisterStatusType::Running,
+            }],
         };
-        let entry_value = EntryValue::new(entry_env, Result::Ok(WasmResult::Reply(vec![])));
+        let entry_value = EntryValue::new(
+            entry_env,
+            Result::Ok(WasmResult::Reply(vec![])),
+            &SystemApiCallCounters::default(),
+            &[],
+        );
         let forward_time = current_time + Duration::from_secs(2);
         assert_eq!(2.0, entry_value.elapsed_seconds(forward_time));
 
@@ -13,6 +13,7 @@ use ic_cycles_account_manager::CyclesAccountManager;
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_interfaces::execution_environment::{
     ExecutionComplexity, ExecutionMode, HypervisorError, SubnetAvailableMemory,
+    SystemApiCallCounters,
 };
 use ic_logger::{error, ReplicaLogger};
 use ic_registry_subnet_type::SubnetType;
@@ -31,9 +32,16 @@ use ic_types::{
     NumSlices,
 };
 use prometheus::IntCounter;
-use std::{collections::VecDeque, sync::Arc, time::Duration, time::Instant};
+use std::{
+    cell::Cell,
+    collections::{BTreeSet, VecDeque},
+    sync::Arc,
+    time::Duration,
+    time::Instant,
+};
 
 use super::query_call_graph::evaluate_query_call_graph;
+use super::query_scheduler::yield_to_waiting_queries;
 
 /// The response of a query. If the query originated from a user, then it
 /// contains either `UserResponse` or `UserError`. If the query originated from
@@ -86,6 +94,8 @@ pub(super) struct QueryContext<'a> {
     data_certificate: (Vec<u8>, CanisterId),
     max_canister_memory_size: NumBytes,
     max_instructions_per_query: NumInstructions,
+    // Queries yield to other waiting queries after every slice.
+    max_instructions_per_query_slice: NumInstructions,
     max_query_call_graph_depth: usize,
     instruction_overhead_per_query_call: RoundInstructions,
     round_limits: RoundLimits,
@@ -93,9 +103,17 @@ pub(super) struct QueryContext<'a> {
     // Walltime at which the query has started to execute.
     query_context_time_start: Instant,
     query_context_time_limit: Duration,
+    // Walltime spent executing other queries between slices. It does not
+    // count towards `query_context_time_limit`.
+    query_context_time_yielded: Duration,
     query_critical_error: &'a IntCounter,
     // Number of instructions used in total
     pub total_instructions_used: NumInstructions,
+    // The IDs of all canisters that were called in the context, including the
+    // canisters that could not be executed.
+    pub evaluated_canister_ids: BTreeSet<CanisterId>,
+    // The environment-dependent system API calls of all executions.
+    pub system_api_call_counters: SystemApiCallCounters,
     subnet_memory_capacity: NumBytes,
 }
 
@@ -111,6 +129,7 @@ impl<'a> QueryContext<'a> {
         subnet_memory_capacity: NumBytes,
         max_canister_memory_size: NumBytes,
         max_instructions_per_query: NumInstructions,
+        max_instructions_per_query_slice: NumInstructions,
         max_query_call_graph_depth: usize,
         max_query_call_graph_instructions: NumInstructions,
         max_query_call_walltime: Duration,
@@ -136,6 +155,7 @@ impl<'a> QueryContext<'a> {
             data_certificate: (data_certificate, canister_id),
             max_canister_memory_size,
             max_instructions_per_query,
+            max_instructions_per_query_slice,
             max_query_call_graph_depth,
             instruction_overhead_per_query_call: as_round_instructions(
                 instruction_overhead_per_query_call,
@@ -144,8 +164,11 @@ impl<'a> QueryContext<'a> {
             composite_queries,
             query_context_time_start: Instant::now(),
             query_context_time_limit: max_query_call_walltime,
+            query_context_time_yielded: Duration::ZERO,
             query_critical_error,
             total_instructions_used: NumInstructions::from(0),
+            evaluated_canister_ids: BTreeSet::new(),
+            system_api_call_counters: SystemApiCallCounters::default(),
             subnet_memory_capacity,
         }
     }
@@ -168,6 +191,7 @@ impl<'a> QueryContext<'a> {
         measurement_scope: &MeasurementScope<'b>,
     ) -> Result<WasmResult, UserError> {
         let canister_id = query.receiver;
+        self.evaluated_canister_ids.insert(canister_id);
         let old_canister = self.state.get_active_canister(&canister_id)?;
 
         let subnet_size = self
@@ -380,28 +404,42 @@ impl<'a> QueryContext<'a> {
         let instruction_limit = self.max_instructions_per_query.min(NumInstructions::new(
             self.round_limits.instructions.get().max(0) as u64,
         ));
-        let instruction_limits =
-            InstructionLimits::new(FlagStatus::Disabled, instruction_limit, instruction_limit);
+        let instruction_limits = InstructionLimits::new(
+            FlagStatus::Enabled,
+            instruction_limit,
+            self.max_instructions_per_query_slice,
+        );
         let execution_parameters = self.execution_parameters(&canister, instruction_limits);
 
         let data_certificate = self.get_data_certificate(&canister.canister_id());
-        let (canister, instructions_left, result) = execute_non_replicated_query(
-            query_kind,
-            method_name,
-            method_payload,
-            canister,
-            data_certificate,
-            self.state.time(),
-            execution_parameters,
-            &self.network_topology,
-            self.hypervisor,
-            &mut self.round_limits,
-        );
+        let num_slices = Cell::new(1);
+        let yielded = Cell::new(Duration::ZERO);
+        self.evaluated_canister_ids.insert(canister.canister_id());
+        let (canister, instructions_left, result, system_api_call_counters) =
+            execute_non_replicated_query(
+                query_kind,
+                method_name,
+                method_payload,
+                canister,
+                data_certificate,
+                self.state.time(),
+                execution_parameters,
+                &self.network_topology,
+                self.hypervisor,
+                &mut self.round_limits,
+                &|| {
+                    num_slices.set(num_slices.get() + 1);
+                    yielded.set(yielded.get() + yield_to_waiting_queries());
+                },
+            );
+        self.query_context_time_yielded += yielded.get();
+        self.system_api_call_counters
+            .saturating_accumulate(&system_api_call_counters);
         let instructions_executed = instruction_limit - instructions_left;
         self.total_instructions_used += instructions_executed;
         measurement_scope.add(
             instructions_executed,
-            NumSlices::from(1),
+            NumSlices::from(num_slices.get()),
             NumMessages::from(1),
         );
         (canister, result)
@@ -470,8 +508,11 @@ impl<'a> QueryContext<'a> {
         let instruction_limit = self.max_instructions_per_query.min(NumInstructions::new(
             self.round_limits.instructions.get().max(0) as u64,
         ));
-        let instruction_limits =
-            InstructionLimits::new(FlagStatus::Disabled, instruction_limit, instruction_limit);
+        let instruction_limits = InstructionLimits::new(
+            FlagStatus::Enabled,
+            instruction_limit,
+            self.max_instructions_per_query_slice,
+        );
         let mut execution_parameters = self.execution_parameters(&canister, instruction_limits);
         let api_type = match response.response_payload {
             Payload::Data(payload) => ApiType::reply_callback(
@@ -494,18 +535,28 @@ impl<'a> QueryContext<'a> {
             ),
         };
 
-        let (output, output_execution_state, output_system_state) = self.hypervisor.execute(
-            api_type,
-            time,
-            canister.system_state.clone(),
-            canister.memory_usage(),
-            execution_parameters.clone(),
-            func_ref,
-            canister.execution_state.take().unwrap(),
-            &self.network_topology,
-            &mut self.round_limits,
-        );
+        let num_slices = Cell::new(1);
+        let yielded = Cell::new(Duration::ZERO);
+        let between_slices = || {
+            num_slices.set(num_slices.get() + 1);
+            yielded.set(yielded.get() + yield_to_waiting_queries());
+        };
+        let (output, output_execution_state, output_system_state) =
+            self.hypervisor.execute_in_slices(
+                api_type,
+                time,
+                canister.system_state.clone(),
+                canister.memory_usage(),
+                execution_parameters.clone(),
+                func_ref,
+                canister.execution_state.take().unwrap(),
+                &self.network_topology,
+                &mut self.round_limits,
+                &between_slices,
+            );
 
+        self.system_api_call_counters
+            .saturating_accumulate(&output.system_api_call_counters);
         let canister_current_memory_usage = canister.memory_usage();
         canister.execution_state = Some(output_execution_state);
         execution_parameters
@@ -534,11 +585,14 @@ impl<'a> QueryContext<'a> {
                         callback_err,
                         canister_current_memory_usage,
                         execution_parameters,
+                        &between_slices,
                     ),
                 }
             }
         };
 
+        self.query_context_time_yielded += yielded.get();
+
         let action = canister
             .system_state
             .call_context_manager_mut()
@@ -550,13 +604,14 @@ impl<'a> QueryContext<'a> {
         let instructions_executed = instruction_limit - instructions_left;
         measurement_scope.add(
             instructions_executed,
-            NumSlices::from(1),
+            NumSlices::from(num_slices.get()),
             NumMessages::from(1),
         );
         Ok((canister, call_origin, action))
     }
 
-    /// Execute cleanup.
+    /// Execute cleanup. Like the callback, the cleanup may execute in several
+    /// slices, invoking `between_slices` after each of them.
     ///
     /// Returns:
     ///     - Number of instructions left.
@@ -571,6 +626,7 @@ impl<'a> QueryContext<'a> {
         callback_err: HypervisorError,
         canister_current_memory_usage: NumBytes,
         execution_parameters: ExecutionParameters,
+        between_slices: &dyn Fn(),
     ) -> (NumInstructions, Result<Option<WasmResult>, HypervisorError>) {
         let func_ref = match call_origin {
             CallOrigin::Ingress(_, _)
@@ -581,7 +637,7 @@ impl<'a> QueryContext<'a> {
             }
         };
         let (cleanup_output, output_execution_state, output_system_state) =
-            self.hypervisor.execute(
+            self.hypervisor.execute_in_slices(
                 ApiType::Cleanup {
                     caller: call_origin.get_principal(),
                     time,
@@ -594,8 +650,11 @@ impl<'a> QueryContext<'a> {
                 canister.execution_state.take().unwrap(),
                 &self.network_topology,
                 &mut self.round_limits,
+                between_slices,
             );
 
+        self.system_api_call_counters
+            .saturating_accumulate(&cleanup_output.system_api_call_counters);
         canister.execution_state = Some(output_execution_state);
         match cleanup_output.wasm_result {
             Ok(_) => {
@@ -642,6 +701,7 @@ impl<'a> QueryContext<'a> {
         };
 
         let canister_id = request.receiver;
+        self.evaluated_canister_ids.insert(canister_id);
 
         let canister = match self.state.get_active_canister(&canister_id) {
             Ok(canister) => canister,
@@ -858,8 +918,12 @@ impl<'a> QueryContext<'a> {
     }
 
     /// Return whether the time limit for this query context has been reached.
+    /// The time spent executing other queries between slices is not counted.
     pub fn time_limit_reached(&self) -> bool {
-        self.query_context_time_start.elapsed() >= self.query_context_time_limit
+        self.query_context_time_start
+            .elapsed()
+            .saturating_sub(self.query_context_time_yielded)
+            >= self.query_context_time_limit
     }
 
     /// Returns a synthetic reject reponse for the case when a query call
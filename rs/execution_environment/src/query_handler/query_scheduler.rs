@@ -14,6 +14,8 @@ use self::{
 mod internal;
 mod thread_pool;
 
+pub(crate) use self::thread_pool::yield_to_waiting_queries;
+
 #[cfg(test)]
 mod tests;
 
@@ -6,7 +6,7 @@ use std::{
 
 use ic_base_types::CanisterId;
 use ic_metrics::{buckets::decimal_buckets_with_zero, MetricsRegistry};
-use prometheus::Histogram;
+use prometheus::{Histogram, IntCounter};
 
 /// An estimate of the average query execution duration. It is used at the
 /// start when there are no stats about the actual query execution duration.
@@ -16,6 +16,7 @@ pub(crate) const DEFAULT_QUERY_DURATION: Duration = Duration::from_millis(5);
 
 pub(crate) struct QuerySchedulerMetrics {
     pub queue_length: Histogram,
+    pub yields: IntCounter,
 }
 
 impl QuerySchedulerMetrics {
@@ -26,6 +27,11 @@ impl QuerySchedulerMetrics {
                 "The length of the query queue sampled for each arriving query",
                 decimal_buckets_with_zero(0, 4),
             ),
+            yields: metrics_registry.int_counter(
+                "execution_query_scheduler_yields_total",
+                "The number of queries executed between the slices of \
+                 long-running queries",
+            ),
         }
     }
 }
@@ -176,6 +182,30 @@ impl QuerySchedulerCore {
     /// Returns a batch of queries to execute if there are any.
     fn pop(&mut self) -> Option<(CanisterId, Vec<Query>)> {
         let canister_id = self.scheduled.pop_front()?;
+        let total =
+            self.canisters[&canister_id].queries_per_time_slice(self.time_slice_per_canister);
+        Some((canister_id, self.take_queries(canister_id, total)))
+    }
+
+    /// Returns a single query to execute between the slices of a long-running
+    /// query. Only canisters whose average query execution duration fits into
+    /// `time_slice_per_canister` are considered, so that a yield takes about as
+    /// long as a time slice at most.
+    fn pop_to_yield(&mut self) -> Option<(CanisterId, Query)> {
+        let index = self.scheduled.iter().position(|canister_id| {
+            self.canisters[canister_id].average_query_duration <= self.time_slice_per_canister
+        })?;
+        // It is safe to unwrap here because `index` is a valid position.
+        let canister_id = self.scheduled.remove(index).unwrap();
+        // Follows from the main invariant: a scheduled canister has queries.
+        let query = self.take_queries(canister_id, 1).pop().unwrap();
+        Some((canister_id, query))
+    }
+
+    // Takes up to `total` queries of the given canister, which was just
+    // removed from the round-robin queue, and accounts for the thread that
+    // executes them.
+    fn take_queries(&mut self, canister_id: CanisterId, total: usize) -> Vec<Query> {
         // It is safe to unwrap here because of the invariants in
         // `validate_invariants()`: each canister in the round-robin list must
         // be present in the canister table.
@@ -183,8 +213,6 @@ impl QuerySchedulerCore {
         debug_assert!(canister.has_been_scheduled);
         canister.has_been_scheduled = false;
 
-        let total = canister.queries_per_time_slice(self.time_slice_per_canister);
-
         // Collect queries from the `leftover` queue first.
         let from_leftover = total.min(canister.leftover.len());
         let mut result: Vec<_> = canister.leftover.drain(0..from_leftover).collect();
@@ -209,7 +237,7 @@ impl QuerySchedulerCore {
         #[cfg(debug_assertions)]
         self.verify_invariants();
 
-        Some((canister_id, result))
+        result
     }
 
     // This is called by the query execution thread after it finished executing
@@ -318,6 +346,18 @@ impl QuerySchedulerInternal {
         }
     }
 
+    /// Returns a single query if there is a suitable one without blocking.
+    /// This is used by threads that execute a long-running query to run
+    /// waiting queries between its slices.
+    pub fn try_pop_to_yield(&self) -> Option<(CanisterId, Query)> {
+        let mut core = self.core.lock().unwrap();
+        let result = core.pop_to_yield();
+        if result.is_some() {
+            core.metrics.yields.inc();
+        }
+        result
+    }
+
     #[cfg(test)]
     pub fn try_pop(&self) -> Option<(CanisterId, Vec<Query>)> {
         let mut core = self.core.lock().unwrap();
@@ -1,19 +1,20 @@
 use std::{
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
         Arc, Mutex,
     },
     time::Duration,
 };
 
 use ic_metrics::MetricsRegistry;
+use ic_test_utilities_metrics::fetch_int_counter;
 use ic_types_test_utils::ids::canister_test_id;
 
 use crate::query_handler::query_scheduler::internal::DEFAULT_QUERY_DURATION;
 
 use super::{
     internal::{Query, QuerySchedulerInternal},
-    QueryScheduler, QuerySchedulerFlag,
+    yield_to_waiting_queries, QueryScheduler, QuerySchedulerFlag,
 };
 
 #[test]
@@ -249,3 +250,156 @@ fn query_scheduler_properly_reads_leftover_queries() {
 
     assert_eq!(queries.len(), 1);
 }
+
+#[test]
+fn query_scheduler_runs_waiting_queries_when_long_query_yields() {
+    let metrics_registry = MetricsRegistry::new();
+    let scheduler = QueryScheduler::new(
+        1,
+        1,
+        Duration::from_millis(10),
+        &metrics_registry,
+        QuerySchedulerFlag::UseNewSchedulingAlgorithm,
+    );
+    let long_query_started = Arc::new(AtomicBool::new(false));
+    let short_query_done = Arc::new(AtomicBool::new(false));
+    let long_query_saw_short_query = Arc::new(AtomicBool::new(false));
+    let execution_count = Arc::new(AtomicU32::default());
+    {
+        let long_query_started = Arc::clone(&long_query_started);
+        let short_query_done = Arc::clone(&short_query_done);
+        let long_query_saw_short_query = Arc::clone(&long_query_saw_short_query);
+        let execution_count = Arc::clone(&execution_count);
+        scheduler.push(canister_test_id(0), move || {
+            long_query_started.store(true, Ordering::SeqCst);
+            // The only thread of the pool is busy with this query, so the
+            // short query can run only if this query yields between slices.
+            for _ in 0..10_000 {
+                if short_query_done.load(Ordering::SeqCst) {
+                    long_query_saw_short_query.store(true, Ordering::SeqCst);
+                    break;
+                }
+                yield_to_waiting_queries();
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            execution_count.fetch_add(1, Ordering::SeqCst);
+            std::time::Duration::from_millis(1000)
+        });
+    }
+    while !long_query_started.load(Ordering::SeqCst) {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    {
+        let short_query_done = Arc::clone(&short_query_done);
+        let execution_count = Arc::clone(&execution_count);
+        scheduler.push(canister_test_id(1), move || {
+            short_query_done.store(true, Ordering::SeqCst);
+            execution_count.fetch_add(1, Ordering::SeqCst);
+            std::time::Duration::from_millis(1)
+        });
+    }
+    while execution_count.load(Ordering::SeqCst) < 2 {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert!(long_query_saw_short_query.load(Ordering::SeqCst));
+}
+
+#[test]
+fn query_scheduler_records_yields() {
+    let metrics_registry = MetricsRegistry::new();
+    let scheduler = QuerySchedulerInternal::new(2, Duration::from_millis(10), &metrics_registry);
+    assert!(scheduler.try_pop_to_yield().is_none());
+    scheduler.push(
+        canister_test_id(0),
+        Query(Box::new(move || std::time::Duration::from_millis(1))),
+    );
+    let (canister_id, _query) = scheduler.try_pop_to_yield().unwrap();
+    assert_eq!(canister_id, canister_test_id(0));
+    assert_eq!(
+        fetch_int_counter(&metrics_registry, "execution_query_scheduler_yields_total"),
+        Some(1)
+    );
+}
+
+#[test]
+fn query_scheduler_yields_to_a_single_query() {
+    let metrics_registry = MetricsRegistry::new();
+    let scheduler = QuerySchedulerInternal::new(2, Duration::from_millis(10), &metrics_registry);
+    for _ in 0..3 {
+        scheduler.push(
+            canister_test_id(0),
+            Query(Box::new(move || std::time::Duration::from_millis(1))),
+        );
+    }
+
+    let (canister_id, query) = scheduler.try_pop_to_yield().unwrap();
+    assert_eq!(canister_id, canister_test_id(0));
+    scheduler.notify_finished_execution(canister_id, query.execute(), vec![]);
+
+    // The remaining queries are executed by the thread-pool.
+    let (_, queries) = scheduler.try_pop().unwrap();
+    assert_eq!(queries.len(), 2);
+}
+
+#[test]
+fn query_scheduler_does_not_yield_to_queries_exceeding_the_time_slice() {
+    let metrics_registry = MetricsRegistry::new();
+    let scheduler = QuerySchedulerInternal::new(2, Duration::from_millis(10), &metrics_registry);
+    scheduler.push(
+        canister_test_id(0),
+        Query(Box::new(move || std::time::Duration::from_millis(200))),
+    );
+    let (_, queries) = scheduler.try_pop().unwrap();
+    scheduler.notify_finished_execution(
+        canister_test_id(0),
+        std::time::Duration::from_millis(200),
+        queries,
+    );
+    scheduler.push(
+        canister_test_id(1),
+        Query(Box::new(move || std::time::Duration::from_millis(1))),
+    );
+
+    let (canister_id, _query) = scheduler.try_pop_to_yield().unwrap();
+    assert_eq!(canister_id, canister_test_id(1));
+    assert!(scheduler.try_pop_to_yield().is_none());
+    let (canister_id, _queries) = scheduler.try_pop().unwrap();
+    assert_eq!(canister_id, canister_test_id(0));
+}
+
+#[test]
+fn query_scheduler_reports_the_time_spent_in_yields() {
+    assert_eq!(yield_to_waiting_queries(), Duration::ZERO);
+
+    let metrics_registry = MetricsRegistry::new();
+    let scheduler = QueryScheduler::new(
+        1,
+        1,
+        Duration::from_millis(10),
+        &metrics_registry,
+        QuerySchedulerFlag::UseNewSchedulingAlgorithm,
+    );
+    let short_query_pushed = Arc::new(AtomicBool::new(false));
+    let yielded = Arc::new(Mutex::new(None));
+    {
+        let short_query_pushed = Arc::clone(&short_query_pushed);
+        let yielded = Arc::clone(&yielded);
+        scheduler.push(canister_test_id(0), move || {
+            while !short_query_pushed.load(Ordering::SeqCst) {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            *yielded.lock().unwrap() = Some(yield_to_waiting_queries());
+            std::time::Duration::from_millis(1)
+        });
+    }
+    scheduler.push(canister_test_id(1), move || {
+        let duration = std::time::Duration::from_millis(5);
+        std::thread::sleep(duration);
+        duration
+    });
+    short_query_pushed.store(true, Ordering::SeqCst);
+    while yielded.lock().unwrap().is_none() {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert!(yielded.lock().unwrap().unwrap() >= Duration::from_millis(5));
+}
@@ -1,6 +1,24 @@
-use std::time::Duration;
+use std::{
+    cell::{Cell, RefCell},
+    time::{Duration, Instant},
+};
 
-use super::internal::QuerySchedulerInternal;
+use ic_base_types::CanisterId;
+
+use super::internal::{Query, QuerySchedulerInternal};
+
+thread_local! {
+    // The scheduler of the thread-pool that owns the current thread. It is
+    // `None` for threads outside of the thread-pool and while the thread is
+    // executing a query in `yield_to_waiting_queries()`, which prevents nested
+    // yields.
+    static CURRENT_SCHEDULER: RefCell<Option<QuerySchedulerInternal>> = RefCell::new(None);
+
+    // The time the current thread spent in `yield_to_waiting_queries()` since
+    // it started executing its current query. It is not attributed to that
+    // query.
+    static YIELDED_DURATION: Cell<Duration> = Cell::new(Duration::ZERO);
+}
 
 /// Manages a thread-pool where each thread polls queries from `scheduler` and
 /// executes them. The threads stop when the thread-pool object is dropped.
@@ -46,25 +64,72 @@ impl Drop for QueryThreadPool {
 
 // The body of each thread in the thread-pool.
 fn query_execution_thread(time_slice_per_canister: Duration, scheduler: QuerySchedulerInternal) {
+    CURRENT_SCHEDULER.with(|current| {
+        *current.borrow_mut() = Some(scheduler.clone());
+    });
     loop {
         match scheduler.pop() {
             None => break,
             Some((canister_id, queries)) => {
-                let mut iter = queries.into_iter();
-                let mut query_duration_sum = Duration::ZERO;
-                let mut query_duration_cnt = 0;
-                for query in iter.by_ref() {
-                    let query_duration = query.execute();
-                    query_duration_sum += query_duration;
-                    query_duration_cnt += 1;
-                    if query_duration_sum >= time_slice_per_canister {
-                        break;
-                    }
-                }
-                let average_query_duration = query_duration_sum / query_duration_cnt.max(1);
-                let leftover = iter.collect();
-                scheduler.notify_finished_execution(canister_id, average_query_duration, leftover)
+                execute_batch(&scheduler, time_slice_per_canister, canister_id, queries);
             }
         }
     }
 }
+
+// Executes the given batch of queries one by one until the total execution
+// duration exceeds `time_slice_per_canister` and returns the remaining queries
+// to the scheduler.
+fn execute_batch(
+    scheduler: &QuerySchedulerInternal,
+    time_slice_per_canister: Duration,
+    canister_id: CanisterId,
+    queries: Vec<Query>,
+) {
+    let mut iter = queries.into_iter();
+    let mut query_duration_sum = Duration::ZERO;
+    let mut query_duration_cnt = 0;
+    for query in iter.by_ref() {
+        YIELDED_DURATION.with(|yielded| yielded.set(Duration::ZERO));
+        let query_duration = query
+            .execute()
+            .saturating_sub(YIELDED_DURATION.with(Cell::get));
+        query_duration_sum += query_duration;
+        query_duration_cnt += 1;
+        if query_duration_sum >= time_slice_per_canister {
+            break;
+        }
+    }
+    let average_query_duration = query_duration_sum / query_duration_cnt.max(1);
+    let leftover = iter.collect();
+    scheduler.notify_finished_execution(canister_id, average_query_duration, leftover)
+}
+
+/// Executes one waiting query, if there is one whose average execution
+/// duration fits into the time slice of its canister, on the current thread.
+/// This is called between the slices of a long-running query so that it does
+/// not monopolize its thread while queries of other canisters are waiting.
+///
+/// Returns the time spent executing the waiting query, which the caller should
+/// not attribute to the long-running query. The same time is also excluded
+/// from the execution duration the thread-pool reports for it.
+///
+/// It does nothing if the current thread does not belong to the query
+/// thread-pool or if it is already executing a query on behalf of a yield.
+pub(crate) fn yield_to_waiting_queries() -> Duration {
+    let Some(scheduler) = CURRENT_SCHEDULER.with(|current| current.borrow_mut().take()) else {
+        return Duration::ZERO;
+    };
+    let mut yielded = Duration::ZERO;
+    if let Some((canister_id, query)) = scheduler.try_pop_to_yield() {
+        let start = Instant::now();
+        let query_duration = query.execute();
+        scheduler.notify_finished_execution(canister_id, query_duration, vec![]);
+        yielded = start.elapsed();
+        YIELDED_DURATION.with(|total| total.set(total.get() + yielded));
+    }
+    CURRENT_SCHEDULER.with(|current| {
+        *current.borrow_mut() = Some(scheduler);
+    });
+    yielded
+}
@@ -1,26 +1,36 @@
 use crossbeam_channel::{Receiver, Sender, TryRecvError, TrySendError};
 use ic_interfaces::{
-    batch_payload::{BatchPayloadBuilder, PastPayload},
-    consensus::PayloadValidationError,
+    batch_payload::{BatchPayloadBuilder, PastPayload, ProposalContext},
+    consensus::{InvalidQueryStatsPayloadReason, PayloadPermanentError, PayloadValidationError},
+    validation::ValidationError,
 };
 use ic_logger::{info, warn, ReplicaLogger};
 use ic_types::{
-    batch::{EpochStats, QueryStatsPayload, ValidationContext},
-    CanisterId, Height, NumBytes, NumInstructions, QueryStatsEpoch,
+    batch::{CanisterQueryStats, EpochStats, QueryStatsPayload, ValidationContext},
+    CanisterId, Height, NodeId, NumBytes, NumInstructions, QueryStatsEpoch,
 };
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::{Mutex, RwLock};
 
-pub fn init_query_stats(log: ReplicaLogger) -> (QueryStatsCollector, QueryStatsPayloadBuilderImpl) {
+pub fn init_query_stats(
+    node_id: NodeId,
+    epoch_length: u64,
+    log: ReplicaLogger,
+) -> (QueryStatsCollector, QueryStatsPayloadBuilderImpl) {
     let (tx, rx) = crossbeam_channel::bounded(1);
+    let epoch_length = epoch_length.max(1);
     (
         QueryStatsCollector {
             log: log.clone(),
-            current_query_stats: Mutex::new(QueryStatsPayload::default()),
-            current_epoch: None,
+            current_query_stats: Mutex::new(BTreeMap::new()),
+            current_epoch: Mutex::new(None),
+            epoch_length,
             sender: tx,
         },
         QueryStatsPayloadBuilderImpl {
             log,
+            node_id,
+            epoch_length,
             current_epoch: RwLock::new(None),
             receiver: rx,
         },
@@ -33,19 +43,26 @@ pub fn init_query_stats(log: ReplicaLogger) -> (QueryStatsCollector, QueryStatsP
 /// the payload builder interface.
 pub struct QueryStatsCollector {
     log: ReplicaLogger,
-    current_query_stats: Mutex<QueryStatsPayload>,
-    current_epoch: Option<QueryStatsEpoch>,
+    current_query_stats: Mutex<BTreeMap<CanisterId, CanisterQueryStats>>,
+    current_epoch: Mutex<Option<QueryStatsEpoch>>,
+    epoch_length: u64,
     sender: Sender<EpochStats>,
 }
 
 impl QueryStatsCollector {
-    pub fn set_epoch(&mut self, new_epoch: QueryStatsEpoch) {
-        let Some(previous_epoch) = self.current_epoch else {
-            self.current_epoch = Some(new_epoch);
+    /// Sets the epoch containing the given certified height.
+    pub fn set_epoch_from_height(&self, height: Height) {
+        self.set_epoch(QueryStatsEpoch::from(height.get() / self.epoch_length));
+    }
+
+    pub fn set_epoch(&self, new_epoch: QueryStatsEpoch) {
+        let mut current_epoch = self.current_epoch.lock().unwrap();
+        let Some(previous_epoch) = *current_epoch else {
+            *current_epoch = Some(new_epoch);
             return;
         };
 
-        if previous_epoch == new_epoch {
+        if previous_epoch >= new_epoch {
             return;
         }
 
@@ -56,7 +73,7 @@ impl QueryStatsCollector {
         // Epoch changed, send stats from previous epoch to block maker
         match self.sender.try_send(EpochStats {
             epoch: previous_epoch,
-            stats: previous_stats.canister_stats.into_iter().collect(),
+            stats: previous_stats.into_iter().collect(),
         }) {
             Ok(()) => (),
             Err(TrySendError::Full(_)) => {
@@ -75,7 +92,7 @@ impl QueryStatsCollector {
                 );
             }
         }
-        self.current_epoch = Some(new_epoch);
+        *current_epoch = Some(new_epoch);
     }
 
     pub fn register_query_statistics(
@@ -85,7 +102,8 @@ impl QueryStatsCollector {
         ingress_payload_size: u64,
         egress_payload_size: u64,
     ) {
-        if self.current_epoch.is_none() {
+        let current_epoch = self.current_epoch.lock().unwrap();
+        if current_epoch.is_none() {
             info!(
                 every_n_seconds => 30,
                 self.log,
@@ -96,7 +114,7 @@ impl QueryStatsCollector {
         }
 
         let mut state = self.current_query_stats.lock().unwrap();
-        let stats_for_canister = state.canister_stats.entry(canister_id).or_default();
+        let stats_for_canister = state.entry(canister_id).or_default();
 
         stats_for_canister.num_calls = stats_for_canister.num_calls.saturating_add(1);
         stats_for_canister.num_instructions = stats_for_canister
@@ -113,17 +131,42 @@ impl QueryStatsCollector {
 
 pub struct QueryStatsPayloadBuilderImpl {
     log: ReplicaLogger,
+    node_id: NodeId,
+    epoch_length: u64,
     current_epoch: RwLock<Option<EpochStats>>,
     receiver: Receiver<EpochStats>,
 }
 
+impl QueryStatsPayloadBuilderImpl {
+    /// Returns the epoch containing the certified height of `context`. Only
+    /// stats of earlier epochs are complete and may be included in a block.
+    fn current_epoch(&self, context: &ValidationContext) -> QueryStatsEpoch {
+        QueryStatsEpoch::from(context.certified_height.get() / self.epoch_length)
+    }
+
+    /// Returns the canisters whose stats for `epoch` have already been included
+    /// in `past_payloads` by `proposer`.
+    fn included_canisters(
+        past_payloads: &[PastPayload],
+        epoch: QueryStatsEpoch,
+        proposer: NodeId,
+    ) -> BTreeSet<CanisterId> {
+        past_payloads
+            .iter()
+            .filter_map(|past| QueryStatsPayload::deserialize(past.payload).ok().flatten())
+            .filter(|past| past.epoch == epoch && past.proposer == proposer)
+            .flat_map(|past| past.canister_stats.into_keys())
+            .collect()
+    }
+}
+
 impl BatchPayloadBuilder for QueryStatsPayloadBuilderImpl {
     fn build_payload(
         &self,
         _height: Height,
-        _max_size: NumBytes,
-        _past_payloads: &[PastPayload],
-        _context: &ValidationContext,
+        max_size: NumBytes,
+        past_payloads: &[PastPayload],
+        context: &ValidationContext,
     ) -> Vec<u8> {
         match self.receiver.try_recv() {
             Ok(new_epoch) => {
@@ -141,19 +184,317 @@ impl BatchPayloadBuilder for QueryStatsPayloadBuilderImpl {
             }
         }
 
-        let _epoch = self.current_epoch.read().unwrap();
+        let mut epoch = self.current_epoch.write().unwrap();
+        let Some(epoch_stats) = epoch.as_mut() else {
+            return vec![];
+        };
+        if epoch_stats.epoch >= self.current_epoch(context) {
+            return vec![];
+        }
 
-        // TODO: Implement the actual streaming of the epoch stats
+        // Stream the stats of the epoch over as many blocks as needed. Stats
+        // are proposed at most once: those included in a block that does not
+        // get finalized are lost, which the aggregation across nodes tolerates.
+        let included = Self::included_canisters(past_payloads, epoch_stats.epoch, self.node_id);
+        epoch_stats
+            .stats
+            .retain(|(canister_id, _)| !included.contains(canister_id));
+        let mut num_entries = epoch_stats.stats.len();
+        while num_entries > 0 {
+            let payload = QueryStatsPayload {
+                epoch: epoch_stats.epoch,
+                proposer: self.node_id,
+                canister_stats: epoch_stats.stats[..num_entries].iter().cloned().collect(),
+            }
+            .serialize();
+            if payload.len() as u64 <= max_size.get() {
+                epoch_stats.stats.drain(..num_entries);
+                return payload;
+            }
+            num_entries /= 2;
+        }
         vec![]
     }
 
     fn validate_payload(
         &self,
         _height: Height,
-        _payload: &[u8],
-        _past_payloads: &[PastPayload],
-        _context: &ValidationContext,
+        payload: &[u8],
+        past_payloads: &[PastPayload],
+        proposal_context: &ProposalContext,
     ) -> Result<(), PayloadValidationError> {
-        Ok(())
+        let invalid = |reason| {
+            ValidationError::Permanent(PayloadPermanentError::QueryStatsPayloadValidationError(
+                reason,
+            ))
+        };
+
+        let payload = match QueryStatsPayload::deserialize(payload) {
+            Ok(Some(payload)) => payload,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                return Err(invalid(
+                    InvalidQueryStatsPayloadReason::DeserializationFailed(err.to_string()),
+                ))
+            }
+        };
+
+        if payload.proposer != proposal_context.proposer {
+            return Err(invalid(InvalidQueryStatsPayloadReason::InvalidProposer {
+                payload_proposer: payload.proposer,
+                block_proposer: proposal_context.proposer,
+            }));
+        }
+
+        let current_epoch = self.current_epoch(proposal_context.validation_context);
+        if payload.epoch >= current_epoch {
+            return Err(invalid(InvalidQueryStatsPayloadReason::EpochTooHigh {
+                epoch: payload.epoch,
+                current_epoch,
+            }));
+        }
+
+        let included = Self::included_canisters(past_payloads, payload.epoch, payload.proposer);
+        match payload
+            .canister_stats
+            .keys()
+            .find(|canister_id| included.contains(canister_id))
+        {
+            Some(canister_id) => Err(invalid(
+                InvalidQueryStatsPayloadReason::DuplicateCanisterId(*canister_id),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use ic_test_utilities::{
+        mock_time,
+        types::ids::{canister_test_id, node_test_id},
+    };
+    use ic_test_utilities_logger::with_test_replica_logger;
+    use ic_types::{
+        crypto::{CryptoHash, CryptoHashOf},
+        RegistryVersion,
+    };
+
+    /// A validation context in epoch 1, with an epoch length of 2000.
+    fn validation_context() -> ValidationContext {
+        ValidationContext {
+            registry_version: RegistryVersion::from(1),
+            certified_height: Height::from(2000),
+            time: mock_time(),
+        }
+    }
+
+    fn proposal_context(proposer: NodeId, context: &ValidationContext) -> ProposalContext {
+        ProposalContext {
+            proposer,
+            validation_context: context,
+        }
+    }
+
+    fn past_payload(payload: &[u8]) -> PastPayload {
+        PastPayload {
+            height: Height::from(1),
+            time: mock_time(),
+            block_hash: CryptoHashOf::from(CryptoHash(vec![])),
+            payload,
+        }
+    }
+
+    fn collect_epoch(collector: &QueryStatsCollector, num_canisters: u64) {
+        collector.set_epoch_from_height(Height::from(0));
+        for i in 0..num_canisters {
+            collector.register_query_statistics(
+                canister_test_id(i),
+                NumInstructions::from(1000),
+                10,
+                20,
+            );
+        }
+        collector.set_epoch_from_height(Height::from(2000));
+    }
+
+    #[test]
+    fn payloads_are_streamed_across_blocks() {
+        with_test_replica_logger(|log| {
+            let (collector, builder) = init_query_stats(node_test_id(1), 2000, log);
+            collect_epoch(&collector, 100);
+            let context = validation_context();
+
+            let mut past = vec![];
+            let mut seen = BTreeSet::new();
+            loop {
+                let past_payloads: Vec<_> =
+                    past.iter().map(|p: &Vec<u8>| past_payload(p)).collect();
+                let payload = builder.build_payload(
+                    Height::from(1),
+                    NumBytes::from(500),
+                    &past_payloads,
+                    &context,
+                );
+                if payload.is_empty() {
+                    break;
+                }
+                assert!(payload.len() <= 500);
+                assert!(builder
+                    .validate_payload(
+                        Height::from(1),
+                        &payload,
+                        &past_payloads,
+                        &proposal_context(node_test_id(1), &context)
+                    )
+                    .is_ok());
+
+                let decoded = QueryStatsPayload::deserialize(&payload).unwrap().unwrap();
+                assert_eq!(decoded.epoch, QueryStatsEpoch::from(0));
+                assert_eq!(decoded.proposer, node_test_id(1));
+                for canister_id in decoded.canister_stats.keys() {
+                    assert!(seen.insert(*canister_id));
+                }
+                past.push(payload);
+            }
+            assert!(past.len() > 1);
+            assert_eq!(seen.len(), 100);
+        });
+    }
+
+    #[test]
+    fn duplicate_canisters_are_rejected() {
+        with_test_replica_logger(|log| {
+            let (collector, builder) = init_query_stats(node_test_id(1), 2000, log);
+            collect_epoch(&collector, 3);
+            let context = validation_context();
+
+            let payload =
+                builder.build_payload(Height::from(1), NumBytes::from(1 << 20), &[], &context);
+            assert_matches!(
+                builder.validate_payload(
+                    Height::from(2),
+                    &payload,
+                    &[past_payload(&payload)],
+                    &proposal_context(node_test_id(1), &context)
+                ),
+                Err(ValidationError::Permanent(
+                    PayloadPermanentError::QueryStatsPayloadValidationError(
+                        InvalidQueryStatsPayloadReason::DuplicateCanisterId(canister_id)
+                    )
+                )) if canister_id == canister_test_id(0)
+            );
+        });
+    }
+
+    #[test]
+    fn payloads_of_other_nodes_are_rejected() {
+        with_test_replica_logger(|log| {
+            let (collector, builder) = init_query_stats(node_test_id(1), 2000, log);
+            collect_epoch(&collector, 3);
+            let context = validation_context();
+
+            let payload =
+                builder.build_payload(Height::from(1), NumBytes::from(1 << 20), &[], &context);
+            assert_matches!(
+                builder.validate_payload(
+                    Height::from(1),
+                    &payload,
+                    &[],
+                    &proposal_context(node_test_id(2), &context)
+                ),
+                Err(ValidationError::Permanent(
+                    PayloadPermanentError::QueryStatsPayloadValidationError(
+                        InvalidQueryStatsPayloadReason::InvalidProposer {
+                            payload_proposer,
+                            block_proposer,
+                        }
+                    )
+                )) if payload_proposer == node_test_id(1) && block_proposer == node_test_id(2)
+            );
+        });
+    }
+
+    #[test]
+    fn epochs_that_have_not_ended_are_rejected() {
+        with_test_replica_logger(|log| {
+            let (collector, builder) = init_query_stats(node_test_id(1), 2000, log);
+            collect_epoch(&collector, 3);
+
+            // At certified height 1999, epoch 0 has not ended yet.
+            let early_context = ValidationContext {
+                certified_height: Height::from(1999),
+                ..validation_context()
+            };
+            assert!(builder
+                .build_payload(
+                    Height::from(1),
+                    NumBytes::from(1 << 20),
+                    &[],
+                    &early_context
+                )
+                .is_empty());
+
+            let context = validation_context();
+            let payload =
+                builder.build_payload(Height::from(1), NumBytes::from(1 << 20), &[], &context);
+            assert!(!payload.is_empty());
+            assert_matches!(
+                builder.validate_payload(
+                    Height::from(1),
+                    &payload,
+                    &[],
+                    &proposal_context(node_test_id(1), &early_context)
+                ),
+                Err(ValidationError::Permanent(
+                    PayloadPermanentError::QueryStatsPayloadValidationError(
+                        InvalidQueryStatsPayloadReason::EpochTooHigh {
+                            epoch,
+                            current_epoch,
+                        }
+                    )
+                )) if epoch == QueryStatsEpoch::from(0) && current_epoch == QueryStatsEpoch::from(0)
+            );
+        });
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        with_test_replica_logger(|log| {
+            let (_collector, builder) = init_query_stats(node_test_id(1), 2000, log);
+            let context = validation_context();
+            let proposal_context = proposal_context(node_test_id(1), &context);
+            assert!(builder
+                .validate_payload(Height::from(1), &[0xff; 8], &[], &proposal_context)
+                .is_err());
+            assert!(builder
+                .validate_payload(Height::from(1), &[], &[], &proposal_context)
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn stats_are_dropped_without_epoch() {
+        with_test_replica_logger(|log| {
+            let (collector, builder) = init_query_stats(node_test_id(1), 2000, log);
+            collector.register_query_statistics(
+                canister_test_id(0),
+                NumInstructions::from(1),
+                1,
+                1,
+            );
+            collector.set_epoch(QueryStatsEpoch::from(0));
+            collector.set_epoch(QueryStatsEpoch::from(1));
+
+            let payload = builder.build_payload(
+                Height::from(1),
+                NumBytes::from(1 << 20),
+                &[],
+                &validation_context(),
+            );
+            assert!(payload.is_empty());
+        });
     }
 }
@@ -491,6 +491,12 @@ const QUERY_CACHE_WAT: &str = r#"
     (import "ic0" "msg_reply_data_append"
         (func $msg_reply_data_append (param i32 i32)))
     (import "ic0" "canister_cycle_balance" (func $canister_cycle_balance (result i64)))
+    (import "ic0" "time" (func $time (result i64)))
+    (import "ic0" "data_certificate_present"
+        (func $data_certificate_present (result i32)))
+    (import "ic0" "data_certificate_size" (func $data_certificate_size (result i32)))
+    (import "ic0" "data_certificate_copy"
+        (func $data_certificate_copy (param i32 i32 i32)))
 
     (memory 100)
     (data (i32.const 0) "42")
@@ -509,6 +515,36 @@ const QUERY_CACHE_WAT: &str = r#"
         (call $msg_reply)
     )
 
+    (func (export "canister_query time")
+        ;; The reply doesn't depend on the time, but the query reads it.
+        (drop (call $time))
+        (call $f)
+    )
+
+    (func (export "canister_query data_certificate")
+        ;; Reply with the data certificate
+        (call $data_certificate_copy
+            (i32.const 100)
+            (i32.const 0)
+            (call $data_certificate_size)
+        )
+        (call $msg_reply_data_append (i32.const 100) (call $data_certificate_size))
+        (call $msg_reply)
+    )
+
+    (func (export "canister_query data_certificate_size")
+        ;; Reply with a prefix of "42" as long as the data certificate
+        (call $msg_reply_data_append (i32.const 0) (call $data_certificate_size))
+        (call $msg_reply)
+    )
+
+    (func (export "canister_query data_certificate_present")
+        ;; The reply doesn't depend on the data certificate, but the query
+        ;; checks whether it is present.
+        (drop (call $data_certificate_present))
+        (call $f)
+    )
+
     (export "canister_query f1" (func $f))
     (export "canister_query f2" (func $f))
 )"#;
@@ -591,14 +627,17 @@ fn query_cache_key_different_method_payload_returns_different_results() {
 
 #[test]
 fn query_cache_env_different_batch_time_returns_different_results() {
-    let mut test = ExecutionTestBuilder::new().with_query_caching().build();
-    let canister_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let mut test = ExecutionTestBuilder::new()
+        .with_query_caching()
+        .with_initial_canister_cycles(CYCLES_BALANCE.get())
+        .build();
+    let canister_id = test.canister_from_wat(QUERY_CACHE_WAT).unwrap();
     let output_1 = test.query(
         UserQuery {
             source: user_test_id(1),
             receiver: canister_id,
-            method_name: "query".into(),
-            method_payload: wasm().reply_data(&[42]).build(),
+            method_name: "time".into(),
+            method_payload: vec![],
             ingress_expiry: 0,
             nonce: None,
         },
@@ -608,15 +647,15 @@ fn query_cache_env_different_batch_time_returns_different_results() {
     {
         let query_handler = downcast_query_handler(test.query_handler());
         assert_eq!(query_handler.query_cache.metrics.misses.get(), 1);
-        assert_eq!(output_1, Ok(WasmResult::Reply([42].into())));
+        assert_eq!(output_1, Ok(WasmResult::Reply(b"42".to_vec())));
     }
     test.state_mut().metadata.batch_time += Duration::from_secs(1);
     let output_2 = test.query(
         UserQuery {
             source: user_test_id(1),
             receiver: canister_id,
-            method_name: "query".into(),
-            method_payload: wasm().reply_data(&[42]).build(),
+            method_name: "time".into(),
+            method_payload: vec![],
             ingress_expiry: 0,
             nonce: None,
         },
@@ -646,18 +685,21 @@ fn query_cache_env_different_batch_time_returns_different_results() {
 
 #[test]
 fn query_cache_env_invalidated_entries_negative_duration_works() {
-    let mut test = ExecutionTestBuilder::new().with_query_caching().build();
+    let mut test = ExecutionTestBuilder::new()
+        .with_query_caching()
+        .with_initial_canister_cycles(CYCLES_BALANCE.get())
+        .build();
 
     // As there are no updates, the default system time is unix epoch, so we explicitly set it here.
     test.state_mut().metadata.batch_time = time::GENESIS;
 
-    let canister_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let canister_id = test.canister_from_wat(QUERY_CACHE_WAT).unwrap();
     let output_1 = test.query(
         UserQuery {
             source: user_test_id(1),
             receiver: canister_id,
-            method_name: "query".into(),
-            method_payload: wasm().reply_data(&[42]).build(),
+            method_name: "time".into(),
+            method_payload: vec![],
             ingress_expiry: 0,
             nonce: None,
         },
@@ -674,8 +716,8 @@ fn query_cache_env_invalidated_entries_negative_duration_works() {
         UserQuery {
             source: user_test_id(1),
             receiver: canister_id,
-            method_name: "query".into(),
-            method_payload: wasm().reply_data(&[42]).build(),
+            method_name: "time".into(),
+            method_payload: vec![],
             ingress_expiry: 0,
             nonce: None,
         },
@@ -816,14 +858,17 @@ fn query_cache_env_different_canister_balance_returns_different_results() {
 
 #[test]
 fn query_cache_env_combined_invalidation() {
-    let mut test = ExecutionTestBuilder::new().with_query_caching().build();
-    let canister_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let mut test = ExecutionTestBuilder::new()
+        .with_query_caching()
+        .with_initial_canister_cycles(CYCLES_BALANCE.get())
+        .build();
+    let canister_id = test.canister_from_wat(QUERY_CACHE_WAT).unwrap();
     let output_1 = test.query(
         UserQuery {
             source: user_test_id(1),
             receiver: canister_id,
-            method_name: "query".into(),
-            method_payload: wasm().reply_data(&[42]).build(),
+            method_name: "time".into(),
+            method_payload: vec![],
             ingress_expiry: 0,
             nonce: None,
         },
@@ -841,8 +886,8 @@ fn query_cache_env_combined_invalidation() {
         UserQuery {
             source: user_test_id(1),
             receiver: canister_id,
-            method_name: "query".into(),
-            method_payload: wasm().reply_data(&[42]).build(),
+            method_name: "time".into(),
+            method_payload: vec![],
             ingress_expiry: 0,
             nonce: None,
         },
@@ -862,6 +907,254 @@ fn query_cache_env_combined_invalidation() {
     }
 }
 
+#[test]
+fn query_cache_env_different_batch_time_without_time_read_returns_cached_result() {
+    let mut test = ExecutionTestBuilder::new().with_query_caching().build();
+    let canister_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let output_1 = test.query(
+        UserQuery {
+            source: user_test_id(1),
+            receiver: canister_id,
+            method_name: "query".into(),
+            method_payload: wasm().reply_data(&[42]).build(),
+            ingress_expiry: 0,
+            nonce: None,
+        },
+        Arc::new(test.state().clone()),
+        vec![],
+    );
+    // The query doesn't read the time, so the entry survives the new batch time.
+    test.state_mut().metadata.batch_time += Duration::from_secs(1);
+    let output_2 = test.query(
+        UserQuery {
+            source: user_test_id(1),
+            receiver: canister_id,
+            method_name: "query".into(),
+            method_payload: wasm().reply_data(&[42]).build(),
+            ingress_expiry: 0,
+            nonce: None,
+        },
+        Arc::new(test.state().clone()),
+        vec![],
+    );
+    {
+        let metrics = &downcast_query_handler(test.query_handler())
+            .query_cache
+            .metrics;
+        assert_eq!(1, metrics.misses.get());
+        assert_eq!(1, metrics.hits.get());
+        assert_eq!(output_1, output_2);
+        assert_eq!(0, metrics.invalidated_entries.get());
+    }
+}
+
+#[test]
+fn query_cache_env_expired_entry_returns_different_results() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_query_caching()
+        .with_query_cache_max_age(Duration::from_secs(10))
+        .build();
+    let canister_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let output_1 = test.query(
+        UserQuery {
+            source: user_test_id(1),
+            receiver: canister_id,
+            method_name: "query".into(),
+            method_payload: wasm().reply_data(&[42]).build(),
+            ingress_expiry: 0,
+            nonce: None,
+        },
+        Arc::new(test.state().clone()),
+        vec![],
+    );
+    test.state_mut().metadata.batch_time += Duration::from_secs(11);
+    let output_2 = test.query(
+        UserQuery {
+            source: user_test_id(1),
+            receiver: canister_id,
+            method_name: "query".into(),
+            method_payload: wasm().reply_data(&[42]).build(),
+            ingress_expiry: 0,
+            nonce: None,
+        },
+        Arc::new(test.state().clone()),
+        vec![],
+    );
+    {
+        let metrics = &downcast_query_handler(test.query_handler())
+            .query_cache
+            .metrics;
+        assert_eq!(2, metrics.misses.get());
+        assert_eq!(output_1, output_2);
+        assert_eq!(1, metrics.invalidated_entries.get());
+        assert_eq!(0, metrics.invalidated_entries_by_time.get());
+        assert_eq!(1, metrics.invalidated_entries_by_max_age.get());
+        assert_eq!(
+            11,
+            metrics.invalidated_entries_duration.get_sample_sum() as usize
+        );
+    }
+}
+
+#[test]
+fn query_cache_env_different_data_certificate_returns_different_results() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_query_caching()
+        .with_initial_canister_cycles(CYCLES_BALANCE.get())
+        .build();
+    let canister_id = test.canister_from_wat(QUERY_CACHE_WAT).unwrap();
+    let query = UserQuery {
+        source: user_test_id(1),
+        receiver: canister_id,
+        method_name: "data_certificate".into(),
+        method_payload: vec![],
+        ingress_expiry: 0,
+        nonce: None,
+    };
+    let output_1 = test.query(query.clone(), Arc::new(test.state().clone()), vec![1]);
+    assert_eq!(output_1, Ok(WasmResult::Reply(vec![1])));
+    let output_2 = test.query(query.clone(), Arc::new(test.state().clone()), vec![1]);
+    assert_eq!(output_1, output_2);
+    let output_3 = test.query(query, Arc::new(test.state().clone()), vec![2]);
+    assert_eq!(output_3, Ok(WasmResult::Reply(vec![2])));
+    {
+        let metrics = &downcast_query_handler(test.query_handler())
+            .query_cache
+            .metrics;
+        assert_eq!(1, metrics.hits.get());
+        assert_eq!(2, metrics.misses.get());
+        assert_eq!(1, metrics.invalidated_entries.get());
+        assert_eq!(1, metrics.invalidated_entries_by_data_certificate.get());
+    }
+}
+
+#[test]
+fn query_cache_env_different_data_certificate_size_returns_different_results() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_query_caching()
+        .with_initial_canister_cycles(CYCLES_BALANCE.get())
+        .build();
+    let canister_id = test.canister_from_wat(QUERY_CACHE_WAT).unwrap();
+    let query = UserQuery {
+        source: user_test_id(1),
+        receiver: canister_id,
+        method_name: "data_certificate_size".into(),
+        method_payload: vec![],
+        ingress_expiry: 0,
+        nonce: None,
+    };
+    let output_1 = test.query(query.clone(), Arc::new(test.state().clone()), vec![1]);
+    assert_eq!(output_1, Ok(WasmResult::Reply(b"4".to_vec())));
+    let output_2 = test.query(query, Arc::new(test.state().clone()), vec![1, 2]);
+    assert_eq!(output_2, Ok(WasmResult::Reply(b"42".to_vec())));
+    {
+        let metrics = &downcast_query_handler(test.query_handler())
+            .query_cache
+            .metrics;
+        assert_eq!(0, metrics.hits.get());
+        assert_eq!(2, metrics.misses.get());
+        assert_eq!(1, metrics.invalidated_entries_by_data_certificate.get());
+    }
+}
+
+#[test]
+fn query_cache_env_different_data_certificate_after_present_check_is_a_miss() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_query_caching()
+        .with_initial_canister_cycles(CYCLES_BALANCE.get())
+        .build();
+    let canister_id = test.canister_from_wat(QUERY_CACHE_WAT).unwrap();
+    let query = UserQuery {
+        source: user_test_id(1),
+        receiver: canister_id,
+        method_name: "data_certificate_present".into(),
+        method_payload: vec![],
+        ingress_expiry: 0,
+        nonce: None,
+    };
+    let output_1 = test.query(query.clone(), Arc::new(test.state().clone()), vec![1]);
+    let output_2 = test.query(query, Arc::new(test.state().clone()), vec![2]);
+    assert_eq!(output_1, output_2);
+    {
+        let metrics = &downcast_query_handler(test.query_handler())
+            .query_cache
+            .metrics;
+        assert_eq!(0, metrics.hits.get());
+        assert_eq!(2, metrics.misses.get());
+        assert_eq!(1, metrics.invalidated_entries_by_data_certificate.get());
+    }
+}
+
+#[test]
+fn query_cache_env_different_data_certificate_without_read_returns_cached_result() {
+    let mut test = ExecutionTestBuilder::new().with_query_caching().build();
+    let canister_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let query = UserQuery {
+        source: user_test_id(1),
+        receiver: canister_id,
+        method_name: "query".into(),
+        method_payload: wasm().reply_data(&[42]).build(),
+        ingress_expiry: 0,
+        nonce: None,
+    };
+    let output_1 = test.query(query.clone(), Arc::new(test.state().clone()), vec![1]);
+    let output_2 = test.query(query, Arc::new(test.state().clone()), vec![2]);
+    {
+        let metrics = &downcast_query_handler(test.query_handler())
+            .query_cache
+            .metrics;
+        assert_eq!(1, metrics.hits.get());
+        assert_eq!(1, metrics.misses.get());
+        assert_eq!(output_1, output_2);
+        assert_eq!(0, metrics.invalidated_entries_by_data_certificate.get());
+    }
+}
+
+#[test]
+fn query_cache_composite_query_different_callee_version_returns_different_results() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_query_caching()
+        .with_composite_queries()
+        .build();
+    let caller_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let callee_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let query = UserQuery {
+        source: user_test_id(1),
+        receiver: caller_id,
+        method_name: "composite_query".into(),
+        method_payload: wasm()
+            .composite_query(
+                callee_id,
+                call_args()
+                    .other_side(wasm().reply_data(&[42]))
+                    .on_reply(wasm().message_payload().append_and_reply()),
+            )
+            .build(),
+        ingress_expiry: 0,
+        nonce: None,
+    };
+    let output_1 = test.query(query.clone(), Arc::new(test.state().clone()), vec![]);
+    assert_eq!(output_1, Ok(WasmResult::Reply(vec![42])));
+    let output_2 = test.query(query.clone(), Arc::new(test.state().clone()), vec![]);
+    assert_eq!(output_1, output_2);
+    // Changing the callee must invalidate the cached result of the caller.
+    test.canister_state_mut(callee_id)
+        .system_state
+        .canister_version += 1;
+    let output_3 = test.query(query, Arc::new(test.state().clone()), vec![]);
+    assert_eq!(output_1, output_3);
+    {
+        let metrics = &downcast_query_handler(test.query_handler())
+            .query_cache
+            .metrics;
+        assert_eq!(1, metrics.hits.get());
+        assert_eq!(2, metrics.misses.get());
+        assert_eq!(1, metrics.invalidated_entries.get());
+        assert_eq!(1, metrics.invalidated_entries_by_canister_version.get());
+        assert_eq!(0, metrics.invalidated_entries_by_canister_balance.get());
+    }
+}
+
 #[test]
 fn query_cache_env_old_invalid_entry_frees_memory() {
     static BIG_RESPONSE_SIZE: usize = 1_000_000;
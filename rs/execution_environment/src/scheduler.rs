@@ -576,6 +576,19 @@ impl SchedulerImpl {
                                 break;
                             }
                         }
+
+                        // The low Wasm memory hook runs ahead of all other
+                        // tasks and messages.
+                        canister.update_on_low_wasm_memory_hook_condition();
+                        if canister.system_state.is_low_wasm_memory_hook_ready()
+                            && canister.exports_on_low_wasm_memory_method()
+                        {
+                            canister
+                                .system_state
+                                .task_queue
+                                .push_front(ExecutionTask::OnLowWasmMemory);
+                            heartbeat_and_timer_canister_ids.insert(canister.canister_id());
+                        }
                     }
                 }
             }
@@ -679,12 +692,15 @@ impl SchedulerImpl {
                 .metrics
                 .round_inner_heartbeat_overhead_duration
                 .start_timer();
-            // Remove all remaining `Heartbeat` and `GlobalTimer` tasks
-            // because they will be added again in the next round.
+            // Remove all remaining `Heartbeat`, `GlobalTimer` and
+            // `OnLowWasmMemory` tasks because they will be added again in
+            // the next round.
             for canister_id in &heartbeat_and_timer_canister_ids {
                 let canister = state.canister_state_mut(canister_id).unwrap();
                 canister.system_state.task_queue.retain(|task| match task {
-                    ExecutionTask::Heartbeat | ExecutionTask::GlobalTimer => false,
+                    ExecutionTask::Heartbeat
+                    | ExecutionTask::GlobalTimer
+                    | ExecutionTask::OnLowWasmMemory => false,
                     ExecutionTask::PausedExecution(..)
                     | ExecutionTask::PausedInstallCode(..)
                     | ExecutionTask::AbortedExecution { .. }
@@ -1238,7 +1254,7 @@ impl SchedulerImpl {
             .iter()
             .filter(|(_, canister)| !canister.system_state.task_queue.is_empty());
 
-        // 1. Heartbeat and GlobalTimer tasks exist only during the round
+        // 1. Heartbeat, GlobalTimer and OnLowWasmMemory tasks exist only during the round
         //    and must not exist after the round.
         // 2. Paused executions can exist only in ordinary rounds (not checkpoint rounds).
         // 3. If deterministic time slicing is disabled, then there are no paused tasks.
@@ -1260,6 +1276,12 @@ impl SchedulerImpl {
                             id
                         );
                     }
+                    ExecutionTask::OnLowWasmMemory => {
+                        panic!(
+                            "Unexpected on low Wasm memory task after a round in canister {:?}",
+                            id
+                        );
+                    }
                     ExecutionTask::PausedExecution(_) | ExecutionTask::PausedInstallCode(_) => {
                         assert_eq!(
                             self.deterministic_time_slicing,
@@ -1920,7 +1942,10 @@ fn observe_replicated_state_metrics(
             Some(&ExecutionTask::AbortedInstallCode { .. }) => {
                 num_aborted_install += 1;
             }
-            Some(&ExecutionTask::Heartbeat) | Some(&ExecutionTask::GlobalTimer) | None => {}
+            Some(&ExecutionTask::Heartbeat)
+            | Some(&ExecutionTask::GlobalTimer)
+            | Some(&ExecutionTask::OnLowWasmMemory)
+            | None => {}
         }
         consumed_cycles_total += canister
             .system_state
@@ -2227,7 +2252,8 @@ fn should_add_task(
         ExecutionTask::GlobalTimer => {
             global_timer_has_reached_deadline && canister.exports_global_timer_method()
         }
-        ExecutionTask::AbortedExecution { .. }
+        ExecutionTask::OnLowWasmMemory
+        | ExecutionTask::AbortedExecution { .. }
         | ExecutionTask::AbortedInstallCode { .. }
         | ExecutionTask::PausedExecution(..)
         | ExecutionTask::PausedInstallCode(..) => unreachable!("Unexpected ExecutionTask variant."),
@@ -2238,7 +2264,8 @@ fn get_other_task(task: ExecutionTask) -> ExecutionTask {
     match task {
         ExecutionTask::Heartbeat => ExecutionTask::GlobalTimer,
         ExecutionTask::GlobalTimer => ExecutionTask::Heartbeat,
-        ExecutionTask::AbortedExecution { .. }
+        ExecutionTask::OnLowWasmMemory
+        | ExecutionTask::AbortedExecution { .. }
         | ExecutionTask::AbortedInstallCode { .. }
         | ExecutionTask::PausedExecution(..)
         | ExecutionTask::PausedInstallCode(..) => unreachable!("Unexpected ExecutionTask variant."),
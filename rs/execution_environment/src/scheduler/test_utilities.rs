@@ -24,7 +24,8 @@ use ic_ic00_types::{
 };
 use ic_interfaces::execution_environment::{
     ExecutionComplexity, ExecutionRoundType, HypervisorError, HypervisorResult,
-    IngressHistoryWriter, InstanceStats, RegistryExecutionSettings, Scheduler, WasmExecutionOutput,
+    IngressHistoryWriter, InstanceStats, RegistryExecutionSettings, Scheduler,
+    SystemApiCallCounters, WasmExecutionOutput,
 };
 use ic_logger::{replica_logger::no_op_logger, ReplicaLogger};
 use ic_metrics::MetricsRegistry;
@@ -463,6 +464,20 @@ impl SchedulerTest {
         wasm_executor.push_system_task(canister_id, system_task);
     }
 
+    pub fn expect_on_low_wasm_memory(&mut self, canister_id: CanisterId, system_task: TestMessage) {
+        assert!(
+            self.canister_state(canister_id)
+                .execution_state
+                .as_ref()
+                .unwrap()
+                .exports_method(&WasmMethod::System(SystemMethod::CanisterOnLowWasmMemory)),
+            "The canister should be created with \
+             `create_canister_with(.., Some(SystemMethod::CanisterOnLowWasmMemory))`"
+        );
+        let mut wasm_executor = self.wasm_executor.core.lock().unwrap();
+        wasm_executor.push_system_task(canister_id, system_task);
+    }
+
     pub fn execute_round(&mut self, round_type: ExecutionRoundType) {
         let state = self.state.take().unwrap();
         let state = self.scheduler.execute_round(
@@ -1082,6 +1097,7 @@ impl TestWasmExecutorCore {
                 allocated_bytes: NumBytes::from(0),
                 allocated_message_bytes: NumBytes::from(0),
                 instance_stats: InstanceStats::default(),
+                system_api_call_counters: SystemApiCallCounters::default(),
             };
             self.schedule
                 .push((self.round, canister_id, instructions_to_execute));
@@ -1122,6 +1138,7 @@ impl TestWasmExecutorCore {
             allocated_message_bytes: NumBytes::from(0),
             num_instructions_left: instructions_left,
             instance_stats,
+            system_api_call_counters: SystemApiCallCounters::default(),
         };
         self.schedule
             .push((self.round, canister_id, instructions_to_execute));
@@ -25,7 +25,9 @@ use ic_interfaces::execution_environment::SubnetAvailableMemory;
 use ic_logger::replica_logger::no_op_logger;
 use ic_registry_routing_table::CanisterIdRange;
 use ic_registry_subnet_type::SubnetType;
-use ic_replicated_state::canister_state::system_state::PausedExecutionId;
+use ic_replicated_state::canister_state::system_state::{
+    OnLowWasmMemoryHookStatus, PausedExecutionId,
+};
 use ic_replicated_state::testing::CanisterQueuesTesting;
 use ic_replicated_state::testing::SystemStateTesting;
 use ic_replicated_state::ExportedFunctions;
@@ -1837,6 +1839,52 @@ fn execute_global_timer_before_messages() {
     assert_eq!(test.ingress_queue_size(canister), 3);
 }
 
+#[test]
+fn execute_on_low_wasm_memory_hook_before_messages() {
+    let mut test = SchedulerTestBuilder::new()
+        .with_scheduler_config(SchedulerConfig {
+            scheduler_cores: 2,
+            max_instructions_per_round: NumInstructions::new(1),
+            max_instructions_per_message: NumInstructions::new(1),
+            max_instructions_per_message_without_dts: NumInstructions::new(1),
+            max_instructions_per_slice: NumInstructions::new(1),
+            instruction_overhead_per_message: NumInstructions::from(0),
+            instruction_overhead_per_canister: NumInstructions::from(0),
+            ..SchedulerConfig::system_subnet()
+        })
+        .build();
+    let canister = test.create_canister_with(
+        Cycles::new(1_000_000_000_000),
+        ComputeAllocation::zero(),
+        MemoryAllocation::BestEffort,
+        Some(SystemMethod::CanisterOnLowWasmMemory),
+        None,
+        None,
+    );
+    // The free Wasm heap is below the threshold.
+    let system_state = &mut test.canister_state_mut(canister).system_state;
+    system_state.wasm_memory_limit = Some(NumBytes::new(1_000));
+    system_state.wasm_memory_threshold = NumBytes::new(2_000);
+
+    test.send_ingress(canister, ingress(1));
+    test.send_ingress(canister, ingress(1));
+    test.expect_on_low_wasm_memory(canister, instructions(1));
+    test.execute_round(ExecutionRoundType::OrdinaryRound);
+    let metrics = &test.scheduler().metrics;
+    assert_eq!(metrics.round_inner.messages.get_sample_sum(), 1.0);
+    assert_eq!(test.ingress_queue_size(canister), 2);
+    assert_eq!(
+        test.canister_state(canister)
+            .system_state
+            .on_low_wasm_memory_hook_status(),
+        OnLowWasmMemoryHookStatus::Executed
+    );
+
+    // The hook is not scheduled again while the condition still holds.
+    test.execute_round(ExecutionRoundType::OrdinaryRound);
+    assert_eq!(test.ingress_queue_size(canister), 1);
+}
+
 #[test]
 fn test_drain_subnet_messages_with_some_long_running_canisters() {
     let mut test = SchedulerTestBuilder::new()
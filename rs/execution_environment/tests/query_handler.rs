@@ -10,7 +10,7 @@ use ic_replicated_state::ReplicatedState;
 use ic_test_utilities::{
     cycles_account_manager::CyclesAccountManagerBuilder,
     state_manager::FakeStateManager,
-    types::ids::{subnet_test_id, user_test_id},
+    types::ids::{node_test_id, subnet_test_id, user_test_id},
 };
 use ic_test_utilities_logger::with_test_replica_logger;
 use ic_types::{messages::UserQuery, CanisterId, SubnetId};
@@ -43,6 +43,7 @@ async fn query_non_existent() {
         let execution_services = ExecutionServices::setup_execution(
             log,
             &metrics_registry,
+            node_test_id(1),
             subnet_id,
             subnet_type,
             subnet_config.scheduler_config,
@@ -698,6 +698,7 @@ fn get_cycles_account_manager_config(subnet_type: SubnetType) -> CyclesAccountMa
             ingress_message_reception_fee: Cycles::new(0),
             ingress_byte_reception_fee: Cycles::new(0),
             gib_storage_per_second_fee: Cycles::new(0),
+            max_storage_reservation_period: Duration::from_secs(0),
             duration_between_allocation_charges: Duration::from_secs(10),
             /// The ECDSA signature fee is the fee charged when creating a
             /// signature on this subnet. The request likely came from a
@@ -726,6 +727,7 @@ fn get_cycles_account_manager_config(subnet_type: SubnetType) -> CyclesAccountMa
             ingress_byte_reception_fee: Cycles::new(2_000),
             // 4 SDR per GiB per year => 4e12 Cycles per year
             gib_storage_per_second_fee: Cycles::new(127_000),
+            max_storage_reservation_period: Duration::from_secs(300_000_000),
             duration_between_allocation_charges: Duration::from_secs(10),
             ecdsa_signature_fee: ECDSA_SIGNATURE_FEE,
             http_request_linear_baseline_fee: Cycles::new(3_000_000),
@@ -12,7 +12,7 @@ use ic_consensus_utils::{
 };
 use ic_error_types::RejectCode;
 use ic_interfaces::{
-    batch_payload::{BatchPayloadBuilder, IntoMessages, PastPayload},
+    batch_payload::{BatchPayloadBuilder, IntoMessages, PastPayload, ProposalContext},
     canister_http::{
         CanisterHttpPayloadValidationError, CanisterHttpPermanentValidationError, CanisterHttpPool,
         CanisterHttpTransientValidationError,
@@ -617,7 +617,7 @@ impl BatchPayloadBuilder for CanisterHttpPayloadBuilderImpl {
         height: Height,
         payload: &[u8],
         past_payloads: &[PastPayload],
-        context: &ValidationContext,
+        proposal_context: &ProposalContext,
     ) -> Result<(), PayloadValidationError> {
         let _time = self
             .metrics
@@ -647,16 +647,21 @@ impl BatchPayloadBuilder for CanisterHttpPayloadBuilderImpl {
                 CanisterHttpPermanentValidationError::DecodeError(e),
             ))
         })?;
-        self.validate_canister_http_payload_impl(height, &payload, context, delivered_ids)
-            .map(|_| ())
-            .map_err(|err| match err {
-                ValidationError::Permanent(err) => ValidationError::Permanent(
-                    PayloadPermanentError::CanisterHttpPayloadValidationError(err),
-                ),
-                ValidationError::Transient(err) => ValidationError::Transient(
-                    PayloadTransientError::CanisterHttpPayloadValidationError(err),
-                ),
-            })
+        self.validate_canister_http_payload_impl(
+            height,
+            &payload,
+            proposal_context.validation_context,
+            delivered_ids,
+        )
+        .map(|_| ())
+        .map_err(|err| match err {
+            ValidationError::Permanent(err) => ValidationError::Permanent(
+                PayloadPermanentError::CanisterHttpPayloadValidationError(err),
+            ),
+            ValidationError::Transient(err) => ValidationError::Transient(
+                PayloadTransientError::CanisterHttpPayloadValidationError(err),
+            ),
+        })
     }
 }
 
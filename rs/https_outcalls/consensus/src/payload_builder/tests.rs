@@ -10,7 +10,7 @@ use ic_artifact_pool::canister_http_pool::CanisterHttpPoolImpl;
 use ic_consensus_mocks::{dependencies_with_subnet_params, Dependencies};
 use ic_interfaces::{
     artifact_pool::{MutablePool, UnvalidatedArtifact},
-    batch_payload::{BatchPayloadBuilder, PastPayload},
+    batch_payload::{BatchPayloadBuilder, PastPayload, ProposalContext},
     canister_http::{
         CanisterHttpChangeAction, CanisterHttpChangeSet, CanisterHttpPermanentValidationError,
         CanisterHttpTransientValidationError,
@@ -93,7 +93,7 @@ fn single_request_test() {
             assert_eq!(parsed_payload.responses[0].content, response);
 
             assert!(payload_builder
-                .validate_payload(Height::new(1), &payload, &[], &context)
+                .validate_payload(Height::new(1), &payload, &[], &proposal_context(&context))
                 .is_ok());
         });
 
@@ -229,7 +229,7 @@ fn multiple_payload_test() {
                     Height::new(1),
                     &payload,
                     &past_payloads,
-                    &validation_context,
+                    &proposal_context(&validation_context),
                 )
                 .unwrap();
 
@@ -476,7 +476,12 @@ fn max_responses() {
 
         //  Make sure the response is not contained in the payload
         payload_builder
-            .validate_payload(Height::new(1), &payload, &[], &validation_context)
+            .validate_payload(
+                Height::new(1),
+                &payload,
+                &[],
+                &proposal_context(&validation_context),
+            )
             .unwrap();
     })
 }
@@ -489,7 +494,7 @@ fn oversized_validation() {
             // Give response oversized content
             response.content = CanisterHttpResponseContent::Success(vec![123; 2 * 1024 * 1024]);
         },
-        &default_validation_context(),
+        &proposal_context(&default_validation_context()),
     );
     match validation_result {
         Err(ValidationError::Permanent(
@@ -531,7 +536,7 @@ fn hash_validation() {
             // Change response content to have a different hash
             response.content = CanisterHttpResponseContent::Success(b"cba".to_vec());
         },
-        &default_validation_context(),
+        &proposal_context(&default_validation_context()),
     );
     match validation_result {
         Err(ValidationError::Permanent(
@@ -640,7 +645,7 @@ fn duplicate_validation() {
             Height::from(1),
             &payload,
             &past_payloads,
-            &default_validation_context(),
+            &proposal_context(&default_validation_context()),
         );
 
         match validation_result {
@@ -687,7 +692,7 @@ fn divergence_response_validation_test() {
                 Height::from(1),
                 &payload,
                 &[],
-                &default_validation_context(),
+                &proposal_context(&default_validation_context()),
             );
 
             assert!(validation_result.is_ok());
@@ -707,7 +712,7 @@ fn divergence_response_validation_test() {
                 Height::from(1),
                 &payload,
                 &[],
-                &default_validation_context(),
+                &proposal_context(&default_validation_context()),
             );
 
             match validation_result {
@@ -744,7 +749,7 @@ fn divergence_response_validation_test() {
                 Height::from(1),
                 &payload,
                 &[],
-                &default_validation_context(),
+                &proposal_context(&default_validation_context()),
             );
 
             match validation_result {
@@ -951,6 +956,15 @@ pub(crate) fn default_validation_context() -> ValidationContext {
     }
 }
 
+/// Wraps `validation_context` into the [`ProposalContext`] of a block proposed
+/// by node 0.
+pub(crate) fn proposal_context(validation_context: &ValidationContext) -> ProposalContext {
+    ProposalContext {
+        proposer: node_test_id(0),
+        validation_context,
+    }
+}
+
 /// Mocks up a test environment and test response and metadata. Lets the caller modify them and
 /// then runs validation on it and returns the validation result.
 ///
@@ -974,6 +988,11 @@ where
         };
 
         let payload = payload_to_bytes(&payload, NumBytes::new(4 * 1024 * 1024));
-        payload_builder.validate_payload(Height::from(1), &payload, &[], validation_context)
+        payload_builder.validate_payload(
+            Height::from(1),
+            &payload,
+            &[],
+            &proposal_context(validation_context),
+        )
     })
 }
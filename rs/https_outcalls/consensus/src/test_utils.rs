@@ -1,5 +1,5 @@
 use ic_interfaces::{
-    batch_payload::{BatchPayloadBuilder, PastPayload},
+    batch_payload::{BatchPayloadBuilder, PastPayload, ProposalContext},
     consensus::PayloadValidationError,
 };
 use ic_types::{
@@ -45,7 +45,7 @@ impl BatchPayloadBuilder for FakeCanisterHttpPayloadBuilder {
         _height: Height,
         _payload: &[u8],
         _past_payloads: &[PastPayload],
-        _context: &ValidationContext,
+        _proposal_context: &ProposalContext,
     ) -> Result<(), PayloadValidationError> {
         Ok(())
     }
@@ -1,5 +1,5 @@
 use crate::{consensus::PayloadValidationError, validation::ValidationResult};
-use ic_base_types::NumBytes;
+use ic_base_types::{NodeId, NumBytes};
 use ic_types::{
     batch::ValidationContext, consensus::BlockPayload, crypto::CryptoHashOf, Height, Time,
 };
@@ -28,6 +28,14 @@ pub struct PastPayload<'a> {
     pub payload: &'a [u8],
 }
 
+/// The block proposal a payload is validated as part of.
+pub struct ProposalContext<'a> {
+    /// The node that proposed the block.
+    pub proposer: NodeId,
+    /// The [`ValidationContext`] of the block.
+    pub validation_context: &'a ValidationContext,
+}
+
 /// Indicates that this component can build batch payloads.
 ///
 /// A batch payload has the following properties:
@@ -65,7 +73,8 @@ pub trait BatchPayloadBuilder: Send + Sync {
     /// - `payload`: The payload to validate
     /// - `past_payloads`: A collection of past payloads. Allows the payload builder
     ///     to deduplicate messages
-    /// - `context`: [`ValidationContext`] under which to validate the payload
+    /// - `proposal_context`: The [`ProposalContext`] of the block the payload
+    ///     is part of
     ///
     /// # Returns
     ///
@@ -76,7 +85,7 @@ pub trait BatchPayloadBuilder: Send + Sync {
         height: Height,
         payload: &[u8],
         past_payloads: &[PastPayload],
-        context: &ValidationContext,
+        proposal_context: &ProposalContext,
     ) -> ValidationResult<PayloadValidationError>;
 }
 
@@ -1,5 +1,6 @@
 //! The consensus public interface.
 use crate::{
+    batch_payload::ProposalContext,
     canister_http::{
         CanisterHttpPayloadValidationError, CanisterHttpPermanentValidationError,
         CanisterHttpTransientValidationError,
@@ -14,12 +15,12 @@ use crate::{
     },
     validation::{ValidationError, ValidationResult},
 };
-use ic_base_types::{NumBytes, SubnetId};
+use ic_base_types::{CanisterId, NodeId, NumBytes, SubnetId};
 use ic_types::{
     batch::{BatchPayload, ValidationContext},
     consensus::{block_maker::SubnetRecords, Payload},
     registry::RegistryClientError,
-    Height, Time,
+    Height, QueryStatsEpoch, Time,
 };
 
 /// The [`PayloadBuilder`] is responsible for creating and validating payload that
@@ -39,17 +40,17 @@ pub trait PayloadBuilder: Send + Sync {
     ) -> BatchPayload;
 
     /// Checks whether the provided `payload` is valid given `past_payloads` and
-    /// `context`.
+    /// the `proposal_context` of the block it is part of.
     ///
     /// `past_payloads` contains the `Payloads` from all blocks above the
-    /// certified height provided in `context`, in descending block height
-    /// order.
+    /// certified height provided in the validation context, in descending
+    /// block height order.
     fn validate_payload(
         &self,
         height: Height,
         payload: &Payload,
         past_payloads: &[(Height, Time, Payload)],
-        context: &ValidationContext,
+        proposal_context: &ProposalContext,
     ) -> ValidationResult<PayloadValidationError>;
 }
 
@@ -63,6 +64,29 @@ pub enum PayloadPermanentError {
     },
     SelfValidatingPayloadValidationError(InvalidSelfValidatingPayload),
     CanisterHttpPayloadValidationError(CanisterHttpPermanentValidationError),
+    QueryStatsPayloadValidationError(InvalidQueryStatsPayloadReason),
+}
+
+/// Reasons why a query stats payload might be invalid.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InvalidQueryStatsPayloadReason {
+    /// The payload could not be decoded.
+    DeserializationFailed(String),
+    /// The payload contains stats for a canister that were already included in
+    /// a past payload of the same proposer and epoch.
+    DuplicateCanisterId(CanisterId),
+    /// The payload claims to hold the stats of a node other than the one that
+    /// proposed the block.
+    InvalidProposer {
+        payload_proposer: NodeId,
+        block_proposer: NodeId,
+    },
+    /// The payload holds stats for an epoch that has not ended yet at the
+    /// certified height of the block.
+    EpochTooHigh {
+        epoch: QueryStatsEpoch,
+        current_epoch: QueryStatsEpoch,
+    },
 }
 
 #[derive(Debug)]
@@ -47,6 +47,43 @@ pub struct InstanceStats {
     pub direct_write_count: usize,
 }
 
+/// Counters of the system API calls whose results depend on the environment of
+/// the execution rather than on the canister state. They are used to decide
+/// whether the result of a non-replicated query can be reused.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SystemApiCallCounters {
+    /// Number of calls to `ic0.data_certificate_copy()`.
+    pub data_certificate_copy: usize,
+    /// Number of calls to `ic0.data_certificate_present()`.
+    pub data_certificate_present: usize,
+    /// Number of calls to `ic0.data_certificate_size()`.
+    pub data_certificate_size: usize,
+    /// Number of calls to `ic0.time()`.
+    pub time: usize,
+}
+
+impl SystemApiCallCounters {
+    pub fn saturating_accumulate(&mut self, rhs: &Self) {
+        self.data_certificate_copy = self
+            .data_certificate_copy
+            .saturating_add(rhs.data_certificate_copy);
+        self.data_certificate_present = self
+            .data_certificate_present
+            .saturating_add(rhs.data_certificate_present);
+        self.data_certificate_size = self
+            .data_certificate_size
+            .saturating_add(rhs.data_certificate_size);
+        self.time = self.time.saturating_add(rhs.time);
+    }
+
+    /// Returns true if the execution read anything about the data certificate.
+    pub fn reads_data_certificate(&self) -> bool {
+        self.data_certificate_copy > 0
+            || self.data_certificate_present > 0
+            || self.data_certificate_size > 0
+    }
+}
+
 /// Errors that can be returned when fetching the available memory on a subnet.
 #[derive(Debug)]
 pub enum SubnetAvailableMemoryError {
@@ -775,7 +812,7 @@ pub trait SystemApi {
     ) -> HypervisorResult<(NumPages, NumInstructions)>;
 
     /// The canister can query the IC for the current time.
-    fn ic0_time(&self) -> HypervisorResult<Time>;
+    fn ic0_time(&mut self) -> HypervisorResult<Time>;
 
     /// The canister can set a global one-off timer at the specific time.
     fn ic0_global_timer_set(&mut self, time: Time) -> HypervisorResult<Time>;
@@ -936,18 +973,18 @@ pub trait SystemApi {
     /// returns 1 if the data certificate is present, 0 otherwise.
     /// If run in replicated execution (i.e. an update call or a certified
     /// query), returns 0.
-    fn ic0_data_certificate_present(&self) -> HypervisorResult<i32>;
+    fn ic0_data_certificate_present(&mut self) -> HypervisorResult<i32>;
 
     /// Returns the size of the data certificate if it is present
     /// (i.e. data_certificate_present returns 1).
     /// Traps if data_certificate_present returns 0.
-    fn ic0_data_certificate_size(&self) -> HypervisorResult<i32>;
+    fn ic0_data_certificate_size(&mut self) -> HypervisorResult<i32>;
 
     /// Copies the data certificate into the heap if it is present
     /// (i.e. data_certificate_present returns 1).
     /// Traps if data_certificate_present returns 0.
     fn ic0_data_certificate_copy(
-        &self,
+        &mut self,
         dst: u32,
         offset: u32,
         size: u32,
@@ -1063,6 +1100,7 @@ pub struct WasmExecutionOutput {
     pub allocated_bytes: NumBytes,
     pub allocated_message_bytes: NumBytes,
     pub instance_stats: InstanceStats,
+    pub system_api_call_counters: SystemApiCallCounters,
 }
 
 impl fmt::Display for WasmExecutionOutput {
@@ -80,6 +80,10 @@ impl<'a> Demux for DemuxImpl<'a> {
             });
         }
 
+        if let Some(query_stats) = batch_messages.query_stats {
+            state.deliver_query_stats(query_stats);
+        }
+
         state
     }
 }
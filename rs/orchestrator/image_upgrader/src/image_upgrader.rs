@@ -126,6 +126,27 @@ pub trait ImageUpgrader<V: Clone + Debug + PartialEq + Eq + Send + Sync, R: Send
         }
     }
 
+    /// Calls a corresponding script to reboot into the previous image. This only
+    /// succeeds if the boot of the current image has not been confirmed yet.
+    async fn revert_boot(&self) -> UpgradeResult<()> {
+        info!(self.log(), "Attempting to revert to the previous image");
+        let script = self.binary_dir().join("manageboot.sh");
+        let mut cmd = Command::new(script.into_os_string());
+        let out = cmd
+            .arg("revert")
+            .output()
+            .await
+            .map_err(|e| UpgradeError::file_command_error(e, &cmd))?;
+
+        if !out.status.success() {
+            warn!(self.log(), "revert has failed: {:?}", out.status);
+            Err(UpgradeError::GenericError("revert failed".to_string()))
+        } else {
+            info!(self.log(), "Rebooting {:?}", out);
+            exit(42);
+        }
+    }
+
     /// Return a value that would differentiate the nodes (but not necessarily unique) in order
     /// to allow them to download the new release package from different URLs.
     fn get_load_balance_number(&self) -> usize;
@@ -54,6 +54,9 @@ pub enum OrchestratorError {
 
     /// Generic error while monitoring key changes
     ThresholdKeyMonitoringError(String),
+
+    /// Generic error while persisting or loading the state of a staged upgrade
+    StagedUpgradeError(String),
 }
 
 impl OrchestratorError {
@@ -68,6 +71,10 @@ impl OrchestratorError {
     pub(crate) fn key_monitoring_error(msg: impl ToString) -> Self {
         OrchestratorError::ThresholdKeyMonitoringError(msg.to_string())
     }
+
+    pub(crate) fn staged_upgrade_error(msg: impl ToString) -> Self {
+        OrchestratorError::StagedUpgradeError(msg.to_string())
+    }
 }
 
 impl fmt::Display for OrchestratorError {
@@ -108,6 +115,9 @@ impl fmt::Display for OrchestratorError {
                     msg
                 )
             }
+            OrchestratorError::StagedUpgradeError(msg) => {
+                write!(f, "Failed to read or write staged upgrade state: {}", msg)
+            }
             OrchestratorError::SubnetMissingError(subnet_id, registry_version) => write!(
                 f,
                 "Subnet ID {:?} does not exist in the Registry at registry version {:?}",
@@ -26,6 +26,14 @@
 //! 5. If the version is different from what we are currently running, apply
 //! upgrade and restart replica with that CUP.
 //!
+//! If the `staged_upgrade` configuration selects only a fraction of the nodes
+//! as canaries, the remaining nodes apply the upgrade only once the canaries
+//! produced a CUP on the new version and a health window has passed since.
+//! After booting a new version, the image is confirmed only once the replica
+//! makes progress (according to its finalized and certified heights);
+//! otherwise the node reverts to the previous image and retries the version
+//! after a growing delay.
+//!
 //! # Registry
 //!
 //! The orchestrator also fetches configuration updates from the
@@ -44,4 +52,5 @@ mod registry_helper;
 mod replica_process;
 mod signer;
 mod ssh_access_manager;
+mod staged_upgrade;
 mod upgrade;
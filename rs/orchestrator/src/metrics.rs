@@ -17,6 +17,9 @@ pub struct OrchestratorMetrics {
     pub key_rotation_status: IntGaugeVec,
     pub ecdsa_key_changed_errors: IntCounterVec,
     pub failed_consecutive_upgrade_checks: IntCounter,
+    pub upgrade_probation: IntGauge,
+    pub upgrade_deferred: IntGauge,
+    pub failed_upgrade_version: IntGaugeVec,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, Eq, IntoStaticStr, PartialOrd, Ord, PartialEq)]
@@ -88,6 +91,19 @@ impl OrchestratorMetrics {
                 "orchestrator_failed_consecutive_upgrade_checks_total",
                 "Number of times the upgrade check failed consecutively",
             ),
+            upgrade_probation: metrics_registry.int_gauge(
+                "orchestrator_upgrade_probation",
+                "1 while the replica version booted by the last upgrade has not been confirmed healthy",
+            ),
+            upgrade_deferred: metrics_registry.int_gauge(
+                "orchestrator_upgrade_deferred",
+                "1 while an upgrade required by the latest CUP is deferred by the staged rollout",
+            ),
+            failed_upgrade_version: metrics_registry.int_gauge_vec(
+                "orchestrator_failed_upgrade_version",
+                "Replica version this node reverted from after it failed to start or catch up",
+                &["replica_version"],
+            ),
         }
     }
 
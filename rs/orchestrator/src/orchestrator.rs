@@ -7,6 +7,7 @@ use crate::registration::NodeRegistration;
 use crate::registry_helper::RegistryHelper;
 use crate::replica_process::ReplicaProcess;
 use crate::ssh_access_manager::SshAccessManager;
+use crate::staged_upgrade::replica_metrics_addr;
 use crate::upgrade::Upgrade;
 use ic_config::metrics::{Config as MetricsConfig, Exporter};
 use ic_crypto::{CryptoComponent, CryptoComponentForNonReplicaProcess};
@@ -201,6 +202,8 @@ impl Orchestrator {
                 args.replica_binary_dir.clone(),
                 logger.clone(),
                 args.orchestrator_data_directory.clone(),
+                config.staged_upgrade.clone(),
+                replica_metrics_addr(&config.metrics),
             )
             .await,
         );
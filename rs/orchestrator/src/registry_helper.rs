@@ -89,6 +89,21 @@ impl RegistryHelper {
         }
     }
 
+    /// Return the ids of the nodes that are members of the given subnet
+    pub(crate) fn get_node_ids_on_subnet(
+        &self,
+        subnet_id: SubnetId,
+        version: RegistryVersion,
+    ) -> OrchestratorResult<Vec<NodeId>> {
+        match self
+            .registry_client
+            .get_node_ids_on_subnet(subnet_id, version)
+        {
+            Ok(Some(node_ids)) => Ok(node_ids),
+            _ => Err(OrchestratorError::SubnetMissingError(subnet_id, version)),
+        }
+    }
+
     /// Return the `ReplicaVersionRecord` for the given replica version
     pub(crate) fn get_replica_version_record(
         &self,
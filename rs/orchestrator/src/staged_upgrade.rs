@@ -0,0 +1,512 @@
+//! Helpers for staged replica upgrades: the selection of canary nodes, the
+//! bookkeeping of upgrades across reboots and the replica health checks that
+//! decide whether a freshly booted version is kept or reverted.
+
+use crate::error::{OrchestratorError, OrchestratorResult};
+use ic_canister_client::HttpClient;
+use ic_config::metrics::{Config as MetricsConfig, Exporter};
+use ic_config::staged_upgrade::Config as StagedUpgradeConfig;
+use ic_types::consensus::get_faults_tolerated;
+use ic_types::{Height, NodeId, ReplicaVersion, Time};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const PENDING_UPGRADE_FILENAME: &str = "pending_upgrade.cbor";
+const FAILED_UPGRADE_FILENAME: &str = "failed_upgrade.cbor";
+
+const FINALIZED_HEIGHT_METRIC: &str = "consensus_batch_height";
+const CERTIFIED_HEIGHT_METRIC: &str = "state_manager_latest_certified_height";
+const METRICS_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bound on the delay before a failed upgrade is retried.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// An upgrade that was committed, but whose new version has not been confirmed
+/// to be healthy yet. It is persisted before rebooting into the new image.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PendingUpgrade {
+    pub(crate) from_version: ReplicaVersion,
+    pub(crate) to_version: ReplicaVersion,
+    /// The height of the CUP at which the upgrade was executed.
+    pub(crate) cup_height: Height,
+}
+
+impl PendingUpgrade {
+    /// Returns true if the node may still revert to `from_version`, given the
+    /// height of the latest CUP of the subnet. Once the subnet produced a CUP
+    /// beyond the upgrade CUP, its state was computed by `to_version` and the
+    /// previous version cannot continue from it.
+    pub(crate) fn may_revert(&self, latest_cup_height: Height) -> bool {
+        latest_cup_height <= self.cup_height
+    }
+}
+
+/// A version this node reverted from because it failed to start or catch up.
+/// The upgrade is retried after a delay that grows with every failed attempt.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FailedUpgrade {
+    pub(crate) version: ReplicaVersion,
+    /// The number of consecutive failed attempts to upgrade to `version`.
+    pub(crate) attempts: u32,
+    /// The time at which the last attempt failed.
+    pub(crate) failed_at: Time,
+}
+
+impl FailedUpgrade {
+    /// Returns the time after which the upgrade to `version` may be retried:
+    /// `health_timeout_secs` after the first failure, doubling with every
+    /// further failure, and at most `MAX_RETRY_DELAY`.
+    pub(crate) fn retry_time(&self, config: &StagedUpgradeConfig) -> Time {
+        let doublings = self.attempts.saturating_sub(1).min(16);
+        let delay = Duration::from_secs(config.health_timeout_secs)
+            .saturating_mul(1 << doublings)
+            .min(MAX_RETRY_DELAY);
+        self.failed_at + delay
+    }
+}
+
+/// Returns true if the given node is among the canaries of the subnet: the
+/// first `canary_percentage` percent of the sorted membership, but at least
+/// as many nodes as a consensus quorum, so that the canaries can make progress
+/// on the new version without the remaining nodes.
+pub(crate) fn is_canary(node_id: NodeId, members: &[NodeId], canary_percentage: u8) -> bool {
+    let mut members = members.to_vec();
+    members.sort();
+    let quorum = members.len() - get_faults_tolerated(members.len());
+    let canaries = ((members.len() * canary_percentage.min(100) as usize + 99) / 100).max(quorum);
+    members
+        .iter()
+        .position(|id| *id == node_id)
+        .map_or(false, |index| index < canaries)
+}
+
+/// Returns the reason why a node that is not a canary has to wait before
+/// upgrading to `version`, or `None` if it can upgrade now.
+///
+/// `cup_version` and `cup_time` are the version and time of the latest CUP, and
+/// `healthy_since` is when the canaries were first observed to have produced a
+/// CUP on `version`. As long as they did not, the node waits until
+/// `canary_deadline_secs` after the upgrade CUP, which is the latest CUP in
+/// that case.
+pub(crate) fn non_canary_deferral_reason(
+    version: &ReplicaVersion,
+    cup_version: &ReplicaVersion,
+    cup_time: Time,
+    healthy_since: Option<Time>,
+    now: Time,
+    config: &StagedUpgradeConfig,
+) -> Option<String> {
+    if cup_version != version {
+        let deadline = cup_time + Duration::from_secs(config.canary_deadline_secs);
+        return (now < deadline).then(|| {
+            format!(
+                "this node is not a canary and waits for the canaries to produce a CUP on version {} until {}",
+                version, deadline
+            )
+        });
+    }
+    let window = Duration::from_secs(config.canary_health_window_secs);
+    let healthy_since = healthy_since.unwrap_or(now);
+    (now < healthy_since + window).then(|| {
+        format!(
+            "the canaries are healthy on version {}, this node follows {:?} after observing it",
+            version, window
+        )
+    })
+}
+
+pub(crate) fn persist_pending_upgrade(
+    data_dir: &Path,
+    pending: &PendingUpgrade,
+) -> OrchestratorResult<()> {
+    persist(&data_dir.join(PENDING_UPGRADE_FILENAME), pending)
+}
+
+pub(crate) fn load_pending_upgrade(data_dir: &Path) -> OrchestratorResult<Option<PendingUpgrade>> {
+    load(&data_dir.join(PENDING_UPGRADE_FILENAME))
+}
+
+pub(crate) fn clear_pending_upgrade(data_dir: &Path) -> OrchestratorResult<()> {
+    remove(&data_dir.join(PENDING_UPGRADE_FILENAME))
+}
+
+pub(crate) fn persist_failed_upgrade(
+    data_dir: &Path,
+    failed: &FailedUpgrade,
+) -> OrchestratorResult<()> {
+    persist(&data_dir.join(FAILED_UPGRADE_FILENAME), failed)
+}
+
+pub(crate) fn load_failed_upgrade(data_dir: &Path) -> OrchestratorResult<Option<FailedUpgrade>> {
+    load(&data_dir.join(FAILED_UPGRADE_FILENAME))
+}
+
+pub(crate) fn clear_failed_upgrade(data_dir: &Path) -> OrchestratorResult<()> {
+    remove(&data_dir.join(FAILED_UPGRADE_FILENAME))
+}
+
+fn persist<T: Serialize>(path: &Path, value: &T) -> OrchestratorResult<()> {
+    let file =
+        std::fs::File::create(path).map_err(|e| OrchestratorError::file_write_error(path, e))?;
+    serde_cbor::to_writer(file, value).map_err(OrchestratorError::staged_upgrade_error)
+}
+
+fn remove(path: &Path) -> OrchestratorResult<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(OrchestratorError::IoError(
+            format!("Couldn't delete {:?}", path),
+            e,
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn load<T: for<'de> Deserialize<'de>>(path: &Path) -> OrchestratorResult<Option<T>> {
+    match std::fs::File::open(path) {
+        Ok(file) => serde_cbor::from_reader(file)
+            .map(Some)
+            .map_err(OrchestratorError::staged_upgrade_error),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(OrchestratorError::IoError(
+            format!("Couldn't read {:?}", path),
+            e,
+        )),
+    }
+}
+
+/// Returns the local address at which the replica exports its metrics, if it
+/// is configured to export them over HTTP.
+pub(crate) fn replica_metrics_addr(config: &MetricsConfig) -> Option<SocketAddr> {
+    match &config.exporter {
+        Exporter::Http(addr) => {
+            let ip = match addr.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                ip => ip,
+            };
+            Some(SocketAddr::new(ip, addr.port()))
+        }
+        Exporter::Log | Exporter::File(_) => None,
+    }
+}
+
+/// The progress of the replica as reported by its metrics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ReplicaHealth {
+    pub(crate) finalized_height: u64,
+    pub(crate) certified_height: u64,
+}
+
+/// Scrapes the metrics endpoint of the local replica.
+pub(crate) async fn fetch_replica_health(
+    client: &HttpClient,
+    addr: SocketAddr,
+) -> Result<ReplicaHealth, String> {
+    let deadline = tokio::time::Instant::now() + METRICS_REQUEST_TIMEOUT;
+    let (body, status) = client
+        .send_get_request(&format!("http://{}/", addr), deadline)
+        .await?;
+    if !status.is_success() {
+        return Err(format!(
+            "Fetching replica metrics from {} failed with status {}",
+            addr, status
+        ));
+    }
+    let body = String::from_utf8(body)
+        .map_err(|e| format!("Malformed metrics response from {}: {}", addr, e))?;
+    parse_replica_health(&body)
+}
+
+/// Extracts the finalized and certified heights from Prometheus text output.
+pub(crate) fn parse_replica_health(metrics: &str) -> Result<ReplicaHealth, String> {
+    let gauge = |name: &str| {
+        metrics
+            .lines()
+            .filter_map(|line| line.strip_prefix(name))
+            .filter(|rest| rest.starts_with([' ', '{']))
+            .find_map(|rest| rest.rsplit(' ').next()?.parse::<f64>().ok())
+            .map(|value| value as u64)
+            .ok_or_else(|| format!("Metric {} not found", name))
+    };
+    Ok(ReplicaHealth {
+        finalized_height: gauge(FINALIZED_HEIGHT_METRIC)?,
+        certified_height: gauge(CERTIFIED_HEIGHT_METRIC)?,
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ProbationOutcome {
+    /// The new version made enough progress and the boot can be confirmed.
+    Healthy,
+    /// Not enough progress yet, but the timeout has not expired.
+    Pending,
+    /// The new version failed to start or catch up within the timeout.
+    Failed(String),
+}
+
+/// Tracks the replica health of a freshly booted version until it either made
+/// enough progress or the health timeout expired.
+pub(crate) struct Probation {
+    pub(crate) pending: PendingUpgrade,
+    started: Instant,
+    baseline: Option<ReplicaHealth>,
+    latest: Option<ReplicaHealth>,
+}
+
+impl Probation {
+    pub(crate) fn new(pending: PendingUpgrade) -> Self {
+        Self {
+            pending,
+            started: Instant::now(),
+            baseline: None,
+            latest: None,
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Records the given health sample, which is `None` if the replica is not
+    /// running or its metrics could not be read, and evaluates the probation
+    /// after `elapsed` time.
+    pub(crate) fn evaluate(
+        &mut self,
+        sample: Option<ReplicaHealth>,
+        elapsed: Duration,
+        config: &StagedUpgradeConfig,
+    ) -> ProbationOutcome {
+        if let Some(sample) = sample {
+            let baseline = *self.baseline.get_or_insert(sample);
+            self.latest = Some(sample);
+            let finalized = sample
+                .finalized_height
+                .saturating_sub(baseline.finalized_height);
+            let certified = sample
+                .certified_height
+                .saturating_sub(baseline.certified_height);
+            if finalized >= config.min_height_progress && certified >= config.min_height_progress {
+                return ProbationOutcome::Healthy;
+            }
+        }
+        if elapsed < Duration::from_secs(config.health_timeout_secs) {
+            return ProbationOutcome::Pending;
+        }
+        ProbationOutcome::Failed(match (self.baseline, self.latest) {
+            (Some(baseline), Some(latest)) => format!(
+                "the replica did not catch up within {:?}: finalized height {} -> {}, certified height {} -> {}",
+                elapsed,
+                baseline.finalized_height,
+                latest.finalized_height,
+                baseline.certified_height,
+                latest.certified_height
+            ),
+            _ => format!("the replica did not start within {:?}", elapsed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_test_utilities::types::ids::node_test_id;
+
+    fn health(finalized_height: u64, certified_height: u64) -> Option<ReplicaHealth> {
+        Some(ReplicaHealth {
+            finalized_height,
+            certified_height,
+        })
+    }
+
+    #[test]
+    fn canaries_are_a_prefix_of_the_sorted_membership() {
+        let members: Vec<_> = [7, 3, 5, 1, 9, 2, 8]
+            .into_iter()
+            .map(node_test_id)
+            .collect();
+        let canaries = |percentage| -> Vec<u64> {
+            (0..10)
+                .filter(|id| is_canary(node_test_id(*id), &members, percentage))
+                .collect()
+        };
+
+        // Seven nodes tolerate two faults, so at least five nodes are canaries.
+        assert_eq!(canaries(0), vec![1, 2, 3, 5, 7]);
+        assert_eq!(canaries(30), vec![1, 2, 3, 5, 7]);
+        assert_eq!(canaries(80), vec![1, 2, 3, 5, 7, 8]);
+        assert_eq!(canaries(100), vec![1, 2, 3, 5, 7, 8, 9]);
+    }
+
+    #[test]
+    fn one_unresponsive_canary_does_not_halt_the_upgrade() {
+        let config = StagedUpgradeConfig {
+            canary_percentage: 0,
+            canary_health_window_secs: 600,
+            canary_deadline_secs: 3600,
+            ..Default::default()
+        };
+        let members: Vec<_> = (1..=7).map(node_test_id).collect();
+        // Five of the seven nodes are canaries, exactly a quorum. With one of
+        // them unresponsive, the new version cannot produce a CUP on its own.
+        let canaries: Vec<u64> = (1..=7)
+            .filter(|id| is_canary(node_test_id(*id), &members, config.canary_percentage))
+            .collect();
+        assert_eq!(canaries, vec![1, 2, 3, 4, 5]);
+        let old = ReplicaVersion::try_from("old").unwrap();
+        let new = ReplicaVersion::try_from("new").unwrap();
+        let cup_time = Time::from_nanos_since_unix_epoch(1_000_000_000_000);
+        let secs = Duration::from_secs;
+
+        // The latest CUP is the upgrade CUP, still produced by the old version.
+        let reason = |now| non_canary_deferral_reason(&new, &old, cup_time, None, now, &config);
+        assert!(reason(cup_time).is_some());
+        assert!(reason(cup_time + secs(3599)).is_some());
+        assert_eq!(reason(cup_time + secs(3600)), None);
+    }
+
+    #[test]
+    fn non_canaries_follow_healthy_canaries_after_the_window() {
+        let config = StagedUpgradeConfig {
+            canary_health_window_secs: 600,
+            canary_deadline_secs: 3600,
+            ..Default::default()
+        };
+        let new = ReplicaVersion::try_from("new").unwrap();
+        let cup_time = Time::from_nanos_since_unix_epoch(1_000_000_000_000);
+        let observed_at = cup_time + Duration::from_secs(100);
+        let reason = |healthy_since, now| {
+            non_canary_deferral_reason(&new, &new, cup_time, healthy_since, now, &config)
+        };
+
+        assert!(reason(None, observed_at).is_some());
+        assert!(reason(Some(observed_at), observed_at + Duration::from_secs(599)).is_some());
+        assert_eq!(
+            reason(Some(observed_at), observed_at + Duration::from_secs(600)),
+            None
+        );
+    }
+
+    #[test]
+    fn reverts_are_only_allowed_up_to_the_upgrade_cup() {
+        let pending = PendingUpgrade {
+            from_version: ReplicaVersion::try_from("old").unwrap(),
+            to_version: ReplicaVersion::try_from("new").unwrap(),
+            cup_height: Height::from(500),
+        };
+
+        assert!(pending.may_revert(Height::from(500)));
+        assert!(!pending.may_revert(Height::from(1000)));
+    }
+
+    #[test]
+    fn failed_upgrades_are_retried_with_growing_delay() {
+        let config = StagedUpgradeConfig {
+            health_timeout_secs: 60,
+            ..Default::default()
+        };
+        let failed_at = Time::from_nanos_since_unix_epoch(1_000_000_000_000);
+        let failed = |attempts| FailedUpgrade {
+            version: ReplicaVersion::try_from("new").unwrap(),
+            attempts,
+            failed_at,
+        };
+        let secs = Duration::from_secs;
+
+        assert_eq!(failed(1).retry_time(&config), failed_at + secs(60));
+        assert_eq!(failed(2).retry_time(&config), failed_at + secs(120));
+        assert_eq!(failed(4).retry_time(&config), failed_at + secs(480));
+        assert_eq!(failed(100).retry_time(&config), failed_at + MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn replica_health_is_parsed_from_prometheus_text() {
+        let metrics = "\
+# HELP consensus_batch_height The height of batches sent to Message Routing
+# TYPE consensus_batch_height gauge
+consensus_batch_height 1234
+consensus_batch_height_total 1
+state_manager_latest_certified_height 1230
+";
+        assert_eq!(
+            parse_replica_health(metrics),
+            Ok(health(1234, 1230).unwrap())
+        );
+        assert!(parse_replica_health("consensus_batch_height 1").is_err());
+    }
+
+    #[test]
+    fn probation_requires_progress_within_timeout() {
+        let config = StagedUpgradeConfig {
+            health_timeout_secs: 60,
+            min_height_progress: 10,
+            ..Default::default()
+        };
+        let pending = PendingUpgrade {
+            from_version: ReplicaVersion::try_from("old").unwrap(),
+            to_version: ReplicaVersion::try_from("new").unwrap(),
+            cup_height: Height::from(500),
+        };
+        let secs = Duration::from_secs;
+
+        let mut probation = Probation::new(pending.clone());
+        assert_eq!(
+            probation.evaluate(None, secs(10), &config),
+            ProbationOutcome::Pending
+        );
+        assert_eq!(
+            probation.evaluate(health(100, 95), secs(20), &config),
+            ProbationOutcome::Pending
+        );
+        assert_eq!(
+            probation.evaluate(health(110, 100), secs(30), &config),
+            ProbationOutcome::Pending
+        );
+        assert_eq!(
+            probation.evaluate(health(115, 105), secs(40), &config),
+            ProbationOutcome::Healthy
+        );
+
+        let mut probation = Probation::new(pending.clone());
+        probation.evaluate(health(100, 95), secs(20), &config);
+        assert!(matches!(
+            probation.evaluate(health(101, 95), secs(60), &config),
+            ProbationOutcome::Failed(reason) if reason.contains("did not catch up")
+        ));
+
+        let mut probation = Probation::new(pending);
+        assert!(matches!(
+            probation.evaluate(None, secs(60), &config),
+            ProbationOutcome::Failed(reason) if reason.contains("did not start")
+        ));
+    }
+
+    #[test]
+    fn pending_upgrade_survives_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let pending = PendingUpgrade {
+            from_version: ReplicaVersion::try_from("old").unwrap(),
+            to_version: ReplicaVersion::try_from("new").unwrap(),
+            cup_height: Height::from(500),
+        };
+
+        assert_eq!(load_pending_upgrade(dir.path()).unwrap(), None);
+        persist_pending_upgrade(dir.path(), &pending).unwrap();
+        assert_eq!(load_pending_upgrade(dir.path()).unwrap(), Some(pending));
+        clear_pending_upgrade(dir.path()).unwrap();
+        clear_pending_upgrade(dir.path()).unwrap();
+        assert_eq!(load_pending_upgrade(dir.path()).unwrap(), None);
+
+        let failed = FailedUpgrade {
+            version: ReplicaVersion::try_from("new").unwrap(),
+            attempts: 2,
+            failed_at: Time::from_nanos_since_unix_epoch(42),
+        };
+        assert_eq!(load_failed_upgrade(dir.path()).unwrap(), None);
+        persist_failed_upgrade(dir.path(), &failed).unwrap();
+        assert_eq!(load_failed_upgrade(dir.path()).unwrap(), Some(failed));
+        clear_failed_upgrade(dir.path()).unwrap();
+        assert_eq!(load_failed_upgrade(dir.path()).unwrap(), None);
+    }
+}
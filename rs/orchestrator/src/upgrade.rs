@@ -3,7 +3,14 @@ use crate::error::{OrchestratorError, OrchestratorResult};
 use crate::metrics::OrchestratorMetrics;
 use crate::registry_helper::RegistryHelper;
 use crate::replica_process::ReplicaProcess;
+use crate::staged_upgrade::{
+    clear_failed_upgrade, clear_pending_upgrade, fetch_replica_health, is_canary,
+    load_failed_upgrade, load_pending_upgrade, non_canary_deferral_reason, persist_failed_upgrade,
+    persist_pending_upgrade, FailedUpgrade, PendingUpgrade, Probation, ProbationOutcome,
+};
 use async_trait::async_trait;
+use ic_canister_client::{HttpClient, HttpClientConfig};
+use ic_config::staged_upgrade::Config as StagedUpgradeConfig;
 use ic_crypto::get_tecdsa_master_public_key;
 use ic_http_utils::file_downloader::FileDownloader;
 use ic_ic00_types::EcdsaKeyId;
@@ -17,8 +24,9 @@ use ic_registry_local_store::LocalStoreImpl;
 use ic_registry_replicator::RegistryReplicator;
 use ic_types::consensus::{CatchUpPackage, HasHeight};
 use ic_types::crypto::canister_threshold_sig::MasterEcdsaPublicKey;
-use ic_types::{Height, NodeId, RegistryVersion, ReplicaVersion, SubnetId};
+use ic_types::{Height, NodeId, RegistryVersion, ReplicaVersion, SubnetId, Time};
 use std::collections::BTreeMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
@@ -43,6 +51,21 @@ pub(crate) struct Upgrade {
     /// The replica version that is prepared by 'prepare_upgrade' to upgrade to.
     pub prepared_upgrade_version: Option<ReplicaVersion>,
     pub orchestrator_data_directory: PathBuf,
+    staged_upgrade_config: StagedUpgradeConfig,
+    /// The address of the replica's metrics endpoint, used to check the health
+    /// of a new version. If `None`, new versions are confirmed without checks.
+    replica_metrics_addr: Option<SocketAddr>,
+    /// The client used to scrape the replica's metrics endpoint.
+    http_client: HttpClient,
+    /// Set while the currently running version was booted by an upgrade and has
+    /// not been confirmed healthy yet.
+    probation: Option<Probation>,
+    /// The last version this node had to revert from. It is retried with a
+    /// growing delay.
+    failed_upgrade: Option<FailedUpgrade>,
+    /// The version the canaries of the subnet were first observed to be healthy
+    /// on, i.e. to have produced a CUP with, and when that was observed.
+    healthy_canaries: Option<(ReplicaVersion, Time)>,
 }
 
 impl Upgrade {
@@ -60,8 +83,10 @@ impl Upgrade {
         release_content_dir: PathBuf,
         logger: ReplicaLogger,
         orchestrator_data_directory: PathBuf,
+        staged_upgrade_config: StagedUpgradeConfig,
+        replica_metrics_addr: Option<SocketAddr>,
     ) -> Self {
-        let value = Self {
+        let mut value = Self {
             registry,
             metrics,
             replica_process,
@@ -75,6 +100,15 @@ impl Upgrade {
             logger: logger.clone(),
             prepared_upgrade_version: None,
             orchestrator_data_directory,
+            staged_upgrade_config,
+            replica_metrics_addr,
+            http_client: HttpClient::new_with_config(HttpClientConfig {
+                http2_only: false,
+                ..Default::default()
+            }),
+            probation: None,
+            failed_upgrade: None,
+            healthy_canaries: None,
         };
         if let Err(e) = value.report_reboot_time() {
             warn!(logger, "Cannot report the reboot time: {}", e);
@@ -85,10 +119,255 @@ impl Upgrade {
         ) {
             warn!(logger, "Cannot report ECDSA key changed metric: {}", e);
         }
-        value.confirm_boot().await;
+        value.confirm_boot_or_start_probation().await;
         value
     }
 
+    /// Confirms the boot of the current image, unless it was booted by an upgrade
+    /// whose version still has to prove healthy. In that case, the boot is only
+    /// confirmed once the replica makes progress, cf. `check_upgrade_health()`.
+    async fn confirm_boot_or_start_probation(&mut self) {
+        let data_dir = self.orchestrator_data_directory.clone();
+        match load_failed_upgrade(&data_dir) {
+            Ok(failed) => self.set_failed_upgrade(failed),
+            Err(e) => warn!(self.logger, "Cannot load the failed upgrade: {}", e),
+        }
+        let pending = load_pending_upgrade(&data_dir).unwrap_or_else(|e| {
+            warn!(self.logger, "Cannot load the pending upgrade: {}", e);
+            None
+        });
+        match pending {
+            Some(pending)
+                if pending.to_version == self.replica_version
+                    && self.replica_metrics_addr.is_some() =>
+            {
+                info!(
+                    self.logger,
+                    "Replica version {} is on probation until the replica makes progress",
+                    self.replica_version
+                );
+                self.metrics.upgrade_probation.set(1);
+                self.probation = Some(Probation::new(pending));
+                return;
+            }
+            // The upgrade was committed, but we are still running the previous
+            // image: the bootloader fell back to it or we reverted.
+            Some(pending) if pending.from_version == self.replica_version => {
+                error!(
+                    self.logger,
+                    "The upgrade to replica version {} was reverted", pending.to_version
+                );
+                self.record_failed_upgrade(pending.to_version);
+            }
+            _ => {}
+        }
+        if let Err(e) = clear_pending_upgrade(&data_dir) {
+            warn!(self.logger, "Cannot clear the pending upgrade: {}", e);
+        }
+        self.confirm_boot().await;
+    }
+
+    fn set_failed_upgrade(&mut self, failed: Option<FailedUpgrade>) {
+        self.metrics.failed_upgrade_version.reset();
+        if let Some(failed) = &failed {
+            self.metrics
+                .failed_upgrade_version
+                .with_label_values(&[failed.version.as_ref()])
+                .set(1);
+        }
+        self.failed_upgrade = failed;
+    }
+
+    /// Records another failed attempt to upgrade to `version`, which delays the
+    /// next attempt, cf. `FailedUpgrade::retry_time()`.
+    fn record_failed_upgrade(&mut self, version: ReplicaVersion) {
+        let attempts = match &self.failed_upgrade {
+            Some(failed) if failed.version == version => failed.attempts.saturating_add(1),
+            _ => 1,
+        };
+        let failed = FailedUpgrade {
+            version,
+            attempts,
+            failed_at: ic_types::time::current_time(),
+        };
+        info!(
+            self.logger,
+            "The upgrade to replica version {} failed {} time(s), retrying at {}",
+            failed.version,
+            failed.attempts,
+            failed.retry_time(&self.staged_upgrade_config)
+        );
+        if let Err(e) = persist_failed_upgrade(&self.orchestrator_data_directory, &failed) {
+            warn!(self.logger, "Cannot persist the failed upgrade: {}", e);
+        }
+        self.set_failed_upgrade(Some(failed));
+    }
+
+    /// Confirms the boot of the version on probation and ends the probation.
+    async fn end_probation(&mut self) {
+        if self.probation.take().is_none() {
+            return;
+        }
+        if let Err(e) = clear_pending_upgrade(&self.orchestrator_data_directory) {
+            warn!(self.logger, "Cannot clear the pending upgrade: {}", e);
+        }
+        // Earlier failures are superseded by a version that proved healthy.
+        if let Err(e) = clear_failed_upgrade(&self.orchestrator_data_directory) {
+            warn!(self.logger, "Cannot clear the failed upgrade: {}", e);
+        }
+        self.set_failed_upgrade(None);
+        self.confirm_boot().await;
+        self.metrics.upgrade_probation.set(0);
+    }
+
+    /// Checks whether the replica of the version on probation made enough progress
+    /// to confirm the boot, or reverts to the previous image if it failed to start
+    /// or catch up within the configured timeout. A failed version is kept if the
+    /// subnet already moved past the upgrade CUP, cf. `PendingUpgrade::may_revert()`.
+    async fn check_upgrade_health(&mut self, latest_cup_height: Height) -> OrchestratorResult<()> {
+        let (Some(probation), Some(addr)) = (self.probation.as_mut(), self.replica_metrics_addr)
+        else {
+            return Ok(());
+        };
+        let sample = if self.replica_process.lock().unwrap().is_running() {
+            fetch_replica_health(&self.http_client, addr)
+                .await
+                .map_err(|e| info!(self.logger, "Replica health unavailable: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        let elapsed = probation.elapsed();
+        match probation.evaluate(sample, elapsed, &self.staged_upgrade_config) {
+            ProbationOutcome::Pending => Ok(()),
+            ProbationOutcome::Healthy => {
+                info!(
+                    self.logger,
+                    "Replica version {} is healthy, confirming the boot", self.replica_version
+                );
+                self.end_probation().await;
+                Ok(())
+            }
+            ProbationOutcome::Failed(reason) => {
+                let pending = probation.pending.clone();
+                if !pending.may_revert(latest_cup_height) {
+                    error!(
+                        self.logger,
+                        "Replica version {} failed: {}. Not reverting, because the subnet produced a CUP at height {} past the upgrade CUP at height {}",
+                        pending.to_version,
+                        reason,
+                        latest_cup_height,
+                        pending.cup_height
+                    );
+                    self.end_probation().await;
+                    return Ok(());
+                }
+                error!(
+                    self.logger,
+                    "Replica version {} failed: {}. Reverting to version {}",
+                    pending.to_version,
+                    reason,
+                    pending.from_version
+                );
+                self.record_failed_upgrade(pending.to_version);
+                if let Err(e) = self.stop_replica() {
+                    warn!(self.logger, "Failed to stop replica with error {:?}", e);
+                }
+                self.revert_boot().await.map_err(OrchestratorError::from)
+            }
+        }
+    }
+
+    /// Returns the reason why the upgrade to `version`, required by the given CUP,
+    /// has to wait, or `None` if it can be executed now.
+    ///
+    /// Non-canary nodes only upgrade once the canaries proved healthy on the new
+    /// version: the latest CUP must have been produced by the new version, which
+    /// requires the canaries to finalize and certify a whole DKG interval, and
+    /// `canary_health_window_secs` must have passed since this was observed. If
+    /// the canaries do not get there, e.g. because one of them is down, the node
+    /// upgrades `canary_deadline_secs` after the upgrade CUP.
+    fn get_upgrade_deferral_reason(
+        &mut self,
+        version: &ReplicaVersion,
+        subnet_id: SubnetId,
+        cup: &CatchUpPackage,
+    ) -> OrchestratorResult<Option<String>> {
+        if self.probation.is_some() {
+            return Ok(Some(format!(
+                "the current version {} is still on probation",
+                self.replica_version
+            )));
+        }
+        if let Some(failed) = self
+            .failed_upgrade
+            .as_ref()
+            .filter(|failed| failed.version == *version)
+        {
+            let retry_time = failed.retry_time(&self.staged_upgrade_config);
+            if ic_types::time::current_time() < retry_time {
+                return Ok(Some(format!(
+                    "version {} failed on this node {} time(s), retrying at {}",
+                    version, failed.attempts, retry_time
+                )));
+            }
+        }
+        let config = &self.staged_upgrade_config;
+        if config.canary_percentage >= 100 {
+            return Ok(None);
+        }
+        let members = self
+            .registry
+            .get_node_ids_on_subnet(subnet_id, cup.content.registry_version())?;
+        if is_canary(self.node_id, &members, config.canary_percentage) {
+            return Ok(None);
+        }
+        let block = cup.content.block.get_value();
+        let now = ic_types::time::current_time();
+        let healthy_since = if block.version == *version {
+            match &self.healthy_canaries {
+                Some((healthy_version, observed_at)) if healthy_version == version => {
+                    Some(*observed_at)
+                }
+                _ => {
+                    self.healthy_canaries = Some((version.clone(), now));
+                    Some(now)
+                }
+            }
+        } else {
+            None
+        };
+        Ok(non_canary_deferral_reason(
+            version,
+            &block.version,
+            block.context.time,
+            healthy_since,
+            now,
+            config,
+        ))
+    }
+
+    /// Executes the upgrade to the given version, recording it as pending such
+    /// that the new version is put on probation after the reboot.
+    async fn execute_upgrade_with_probation(
+        &mut self,
+        version: &ReplicaVersion,
+        cup_height: Height,
+    ) -> OrchestratorResult<()> {
+        let pending = PendingUpgrade {
+            from_version: self.replica_version.clone(),
+            to_version: version.clone(),
+            cup_height,
+        };
+        persist_pending_upgrade(&self.orchestrator_data_directory, &pending)?;
+        // This only returns if the upgrade failed before rebooting.
+        let result = self.execute_upgrade(version).await;
+        if let Err(e) = clear_pending_upgrade(&self.orchestrator_data_directory) {
+            warn!(self.logger, "Cannot clear the pending upgrade: {}", e);
+        }
+        result.map_err(OrchestratorError::from)
+    }
+
     fn report_reboot_time(&self) -> OrchestratorResult<()> {
         let elapsed_time = self.get_time_since_last_reboot_trigger()?;
         self.metrics
@@ -122,6 +401,8 @@ impl Upgrade {
                     }
                     // If no subnet is assigned to the node id, we're unassigned.
                     _ => {
+                        // There is no replica to judge the health of a new version by.
+                        self.end_probation().await;
                         self.check_for_upgrade_as_unassigned().await?;
                         return Ok(None);
                     }
@@ -197,21 +478,39 @@ impl Upgrade {
             .registry
             .get_replica_version(subnet_id, cup_registry_version)?;
         if new_replica_version != self.replica_version {
-            info!(
-                self.logger,
-                "Starting version upgrade at CUP registry version {}: {} -> {}",
-                cup_registry_version,
-                self.replica_version,
-                new_replica_version
-            );
-            // Only downloads the new image if it doesn't already exists locally, i.e. it
-            // was previously downloaded by `prepare_upgrade_if_scheduled()`, see
-            // below.
-            self.execute_upgrade(&new_replica_version).await?;
-            return Ok(Some(subnet_id));
+            match self.get_upgrade_deferral_reason(&new_replica_version, subnet_id, &latest_cup)? {
+                None => {
+                    info!(
+                        self.logger,
+                        "Starting version upgrade at CUP registry version {}: {} -> {}",
+                        cup_registry_version,
+                        self.replica_version,
+                        new_replica_version
+                    );
+                    // Only downloads the new image if it doesn't already exists locally, i.e. it
+                    // was previously downloaded by `prepare_upgrade_if_scheduled()`, see
+                    // below.
+                    self.execute_upgrade_with_probation(&new_replica_version, latest_cup.height())
+                        .await?;
+                    return Ok(Some(subnet_id));
+                }
+                Some(reason) => {
+                    info!(
+                        self.logger,
+                        "Deferring version upgrade at CUP registry version {} to {}: {}",
+                        cup_registry_version,
+                        new_replica_version,
+                        reason
+                    );
+                    self.metrics.upgrade_deferred.set(1);
+                }
+            }
+        } else {
+            self.metrics.upgrade_deferred.set(0);
         }
 
-        // If we arrive here, we are on the newest replica version.
+        // If we arrive here, we are on the newest replica version, or the upgrade
+        // to it is deferred.
         // Now we check if a subnet recovery is in progress.
         // If it is, we restart to pass the unsigned CUP to consensus.
         self.stop_replica_if_new_recovery_cup(&latest_cup, old_cup_height);
@@ -219,6 +518,9 @@ impl Upgrade {
         // This will start a new replica process if none is running.
         self.ensure_replica_is_running(&self.replica_version, subnet_id)?;
 
+        // This will confirm or revert a freshly upgraded version on probation.
+        self.check_upgrade_health(latest_cup.height()).await?;
+
         // This will trigger an image download if one is already scheduled but we did
         // not arrive at the corresponding CUP yet.
         self.prepare_upgrade_if_scheduled(subnet_id).await?;
@@ -283,6 +585,10 @@ impl Upgrade {
         &mut self,
         subnet_id: SubnetId,
     ) -> OrchestratorResult<()> {
+        // An image cannot be installed before the current one is confirmed.
+        if self.probation.is_some() {
+            return Ok(());
+        }
         let (expected_replica_version, registry_version) =
             self.registry.get_expected_replica_version(subnet_id)?;
         if expected_replica_version != self.replica_version {
@@ -345,6 +345,7 @@ fn state_sync_artifact(id: StateSyncArtifactId) -> Artifact {
         manifest,
         meta_manifest: Arc::new(meta_manifest),
         state_sync_file_group: Default::default(),
+        chunk_reader: None,
     })
 }
 
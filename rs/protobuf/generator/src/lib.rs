@@ -270,6 +270,10 @@ fn build_registry_proto(def: &Path, out: &Path) {
         ".registry.unassigned_nodes_config",
         "#[derive(serde::Serialize, serde::Deserialize)]",
     );
+    config.type_attribute(
+        ".registry.boundary_node",
+        "#[derive(serde::Serialize, serde::Deserialize)]",
+    );
     config.type_attribute(
         ".registry.node.v1.ConnectionEndpoint",
         "#[derive(Eq, PartialOrd, Ord)]",
@@ -290,6 +294,7 @@ fn build_registry_proto(def: &Path, out: &Path) {
         def.join("registry/node_rewards/v2/node_rewards.proto"),
         def.join("registry/dc/v1/dc.proto"),
         def.join("registry/unassigned_nodes_config/v1/unassigned_nodes_config.proto"),
+        def.join("registry/boundary_node/v1/boundary_node.proto"),
     ];
 
     compile_protos(config, def, &registry_files);
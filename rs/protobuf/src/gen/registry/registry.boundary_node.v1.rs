@@ -0,0 +1,52 @@
+/// A rule limiting the rate of the requests that match all of its filters.
+/// Unset filters match any request.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RateLimitingRule {
+    #[prost(message, optional, tag = "1")]
+    pub canister_id: ::core::option::Option<super::super::super::types::v1::CanisterId>,
+    #[prost(message, optional, tag = "2")]
+    pub subnet_id: ::core::option::Option<super::super::super::types::v1::SubnetId>,
+    #[prost(enumeration = "RateLimitingRequestType", optional, tag = "3")]
+    pub request_type: ::core::option::Option<i32>,
+    /// Sustained number of requests per second.
+    #[prost(double, tag = "4")]
+    pub limit_per_second: f64,
+    /// Maximum number of requests served in a burst, defaults to `limit_per_second`.
+    #[prost(double, optional, tag = "5")]
+    pub burst: ::core::option::Option<f64>,
+}
+/// The rate limiting rules applied by the boundary nodes.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RateLimitingRulesRecord {
+    #[prost(message, repeated, tag = "1")]
+    pub rules: ::prost::alloc::vec::Vec<RateLimitingRule>,
+}
+/// Type of request a rate limiting rule applies to.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum RateLimitingRequestType {
+    Unspecified = 0,
+    Query = 1,
+    Call = 2,
+    ReadState = 3,
+}
+impl RateLimitingRequestType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            RateLimitingRequestType::Unspecified => "RATE_LIMITING_REQUEST_TYPE_UNSPECIFIED",
+            RateLimitingRequestType::Query => "RATE_LIMITING_REQUEST_TYPE_QUERY",
+            RateLimitingRequestType::Call => "RATE_LIMITING_REQUEST_TYPE_CALL",
+            RateLimitingRequestType::ReadState => "RATE_LIMITING_REQUEST_TYPE_READ_STATE",
+        }
+    }
+}
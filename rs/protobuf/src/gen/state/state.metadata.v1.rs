@@ -357,6 +357,40 @@ pub struct NodePublicKeyEntry {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterQueryStats {
+    #[prost(message, optional, tag = "1")]
+    pub canister_id: ::core::option::Option<super::super::super::types::v1::CanisterId>,
+    #[prost(uint32, tag = "2")]
+    pub num_calls: u32,
+    #[prost(uint64, tag = "3")]
+    pub num_instructions: u64,
+    #[prost(uint64, tag = "4")]
+    pub ingress_payload_size: u64,
+    #[prost(uint64, tag = "5")]
+    pub egress_payload_size: u64,
+}
+/// Query stats reported by one node for one epoch.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NodeQueryStats {
+    #[prost(uint64, tag = "1")]
+    pub epoch: u64,
+    #[prost(message, optional, tag = "2")]
+    pub node_id: ::core::option::Option<super::super::super::types::v1::NodeId>,
+    #[prost(message, repeated, tag = "3")]
+    pub stats: ::prost::alloc::vec::Vec<CanisterQueryStats>,
+}
+/// Query stats delivered in blocks but not yet aggregated into canister states.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RawQueryStats {
+    #[prost(uint64, optional, tag = "1")]
+    pub highest_aggregated_epoch: ::core::option::Option<u64>,
+    #[prost(message, repeated, tag = "2")]
+    pub stats: ::prost::alloc::vec::Vec<NodeQueryStats>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SystemMetadata {
     #[prost(message, optional, tag = "2")]
     pub prev_state_hash: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
@@ -403,6 +437,8 @@ pub struct SystemMetadata {
         ::prost::alloc::vec::Vec<BitcoinGetSuccessorsFollowUpResponses>,
     #[prost(message, repeated, tag = "19")]
     pub node_public_keys: ::prost::alloc::vec::Vec<NodePublicKeyEntry>,
+    #[prost(message, optional, tag = "20")]
+    pub raw_query_stats: ::core::option::Option<RawQueryStats>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -1011,6 +1011,12 @@ pub struct CanisterHttpPayload {
 pub struct QueryStatsPayload {
     #[prost(message, repeated, tag = "1")]
     pub canister_stats: ::prost::alloc::vec::Vec<QueryStatsPayloadInner>,
+    /// The epoch in which the stats were collected.
+    #[prost(uint64, tag = "2")]
+    pub epoch: u64,
+    /// The node that collected the stats.
+    #[prost(message, optional, tag = "3")]
+    pub proposer: ::core::option::Option<NodeId>,
 }
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
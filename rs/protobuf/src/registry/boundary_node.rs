@@ -0,0 +1,3 @@
+#[allow(clippy::all)]
+#[path = "../gen/registry/registry.boundary_node.v1.rs"]
+pub mod v1;
@@ -2,7 +2,8 @@
 use crate::{
     app_subnet_recovery::{AppSubnetRecovery, AppSubnetRecoveryArgs},
     args_merger::merge,
-    get_node_heights_from_metrics,
+    dry_run::DryRun,
+    get_member_ips, get_node_heights_from_metrics,
     nns_recovery_failover_nodes::{NNSRecoveryFailoverNodes, NNSRecoveryFailoverNodesArgs},
     nns_recovery_same_nodes::{NNSRecoverySameNodes, NNSRecoverySameNodesArgs},
     recovery_iterator::RecoveryIterator,
@@ -189,7 +190,26 @@ fn print_summary(logger: &Logger, args: &RecoveryArgs, subnet_id: SubnetId) {
     info!(logger, "Creating recovery directory in {:?}", args.dir);
 }
 
-pub fn print_height_info(logger: &Logger, registry_helper: &RegistryHelper, subnet_id: SubnetId) {
+/// Print the heights of all nodes of the given subnet. In dry-run mode, only
+/// the metrics queries are printed.
+pub fn print_height_info(
+    logger: &Logger,
+    registry_helper: &RegistryHelper,
+    subnet_id: SubnetId,
+    dry_run: Option<&DryRun>,
+) {
+    if let Some(dry_run) = dry_run {
+        match get_member_ips(registry_helper, subnet_id) {
+            Ok(ips) => {
+                for ip in ips {
+                    let query = format!("GET http://[{}]:9090", ip);
+                    dry_run.skip(logger, "metrics query", &query);
+                }
+            }
+            Err(err) => warn!(logger, "Failed to query member IPs: {:?}", err),
+        }
+        return;
+    }
     info!(logger, "Collecting node heights from metrics...");
     info!(logger, "Select a node with highest finalization height:");
     match get_node_heights_from_metrics(logger, registry_helper, subnet_id) {
@@ -50,6 +50,11 @@ pub struct RecoveryToolArgs {
     #[clap(long)]
     pub test: bool,
 
+    /// Rehearse the recovery against a local backup tree of the subnet instead
+    /// of live nodes. Commands and proposals are printed but not executed.
+    #[clap(long, parse(from_os_str))]
+    pub dry_run: Option<PathBuf>,
+
     #[clap(subcommand)]
     pub subcmd: Option<SubCommand>,
 }
@@ -0,0 +1,401 @@
+//! Dry-run backend of the recovery tool. When enabled, no step touches a live
+//! node or the NNS: SSH commands and `ic-admin` proposals are only printed,
+//! and `rsync` transfers are redirected to local directories.
+//!
+//! The backup tree passed with `--dry-run` stands in for the subnet:
+//!
+//! ```text
+//! <backup>/ic_registry_local_store/     registry local store used instead of the NNS
+//! <backup>/<node ip>/var/lib/ic/data/   copy of the node's data directory
+//! <backup>/<node ip>/run/ic-node/...    copy of the node's config (ic.json5)
+//! ```
+//!
+//! Downloads from `account@[ip]:path` read from `<backup>/<ip>/path`. Uploads
+//! to `account@[ip]:path` are written to `<recovery dir>/dry_run/<ip>/path`, so
+//! the backup tree is never modified and can be reused for the next rehearsal.
+use crate::{
+    error::{RecoveryError, RecoveryResult},
+    file_sync_helper::{create_dir, path_exists},
+    replay_helper, Recovery, CHECKPOINTS, IC_CHECKPOINTS_PATH, IC_DATA_PATH,
+    IC_REGISTRY_LOCAL_STORE, NEW_IC_STATE,
+};
+use ic_protobuf::types::v1 as pb;
+use ic_types::Height;
+use prost::Message;
+use slog::{info, Logger};
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+/// Name of the directory below the recovery directory receiving uploads.
+pub const DRY_RUN_UPLOAD_DIR: &str = "dry_run";
+/// Name of the recovery CUP written by [crate::steps::GetRecoveryCUPStep].
+const RECOVERY_CUP_FILE_NAME: &str = "cup.proto";
+
+/// The dry-run configuration of a [Recovery], created from
+/// [crate::RecoveryArgs::dry_run] and handed to every step and helper that
+/// would otherwise reach a live node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DryRun {
+    backup_dir: PathBuf,
+    upload_dir: PathBuf,
+}
+
+impl DryRun {
+    /// Rehearse against `backup_dir`, writing uploads to `upload_dir`.
+    pub fn new(backup_dir: PathBuf, upload_dir: PathBuf) -> RecoveryResult<Self> {
+        if !path_exists(&backup_dir)? {
+            return Err(RecoveryError::invalid_output_error(format!(
+                "Dry-run backup directory {} does not exist",
+                backup_dir.display()
+            )));
+        }
+        create_dir(&upload_dir)?;
+        Ok(Self {
+            backup_dir,
+            upload_dir,
+        })
+    }
+
+    pub fn backup_dir(&self) -> &Path {
+        &self.backup_dir
+    }
+
+    pub fn upload_dir(&self) -> &Path {
+        &self.upload_dir
+    }
+
+    /// The registry local store of the backup tree.
+    pub fn registry_local_store(&self) -> PathBuf {
+        self.backup_dir.join(IC_REGISTRY_LOCAL_STORE)
+    }
+
+    /// The local directory standing in for the root file system of the given
+    /// node, when reading from or writing to it.
+    pub fn node_root(&self, ip: IpAddr, is_upload: bool) -> PathBuf {
+        let base = if is_upload {
+            &self.upload_dir
+        } else {
+            &self.backup_dir
+        };
+        base.join(ip.to_string())
+    }
+
+    /// Map an `rsync` location to the local directory standing in for it.
+    /// Remote locations (`account@[ip]:path`) are resolved below the backup
+    /// tree, or below the upload directory if `is_upload` is set. Local
+    /// locations are returned unchanged.
+    pub fn map_location(&self, location: &str, is_upload: bool) -> RecoveryResult<String> {
+        let Some((ip, path)) = parse_remote_location(location) else {
+            return Ok(location.to_string());
+        };
+        let ip = ip.parse::<IpAddr>().map_err(|e| {
+            RecoveryError::invalid_output_error(format!(
+                "Invalid IP in location {}: {}",
+                location, e
+            ))
+        })?;
+        let local = self
+            .node_root(ip, is_upload)
+            .join(path.trim_start_matches('/'));
+        if is_upload {
+            match local.parent() {
+                Some(parent) if !path.ends_with('/') => create_dir(parent)?,
+                _ => create_dir(&local)?,
+            }
+        }
+        let mut local = local.display().to_string();
+        if path.ends_with('/') {
+            local.push('/');
+        }
+        Ok(local)
+    }
+
+    /// Log a command that is not executed because of the dry run.
+    pub fn skip(&self, logger: &Logger, what: &str, command: &str) {
+        info!(logger, "");
+        info!(logger, "Dry run, not executing {}:", what);
+        info!(logger, "{}", command);
+    }
+}
+
+// Split `account@[ip]:path` into `ip` and `path`.
+fn parse_remote_location(location: &str) -> Option<(&str, &str)> {
+    let (_account, rest) = location.split_once("@[")?;
+    rest.split_once("]:")
+}
+
+/// Check that the artifacts produced so far agree with each other: the
+/// highest local checkpoint and the state uploaded to `node_ip` (if any) are
+/// at the replay height, and the recovery CUP (if any) points to the
+/// recovery height and the state hash reported by the replay.
+pub fn validate_recovery_artifacts(
+    logger: &Logger,
+    dry_run: &DryRun,
+    work_dir: &Path,
+    node_ip: Option<IpAddr>,
+) -> RecoveryResult<()> {
+    let state_params = replay_helper::read_output(work_dir.join(replay_helper::OUTPUT_FILE_NAME))?;
+    info!(
+        logger,
+        "Replay output: height {}, state hash {}", state_params.height, state_params.hash
+    );
+
+    let checkpoints = work_dir.join("data").join(IC_CHECKPOINTS_PATH);
+    if path_exists(&checkpoints)? {
+        check_checkpoint_height(&checkpoints, state_params.height)?;
+        info!(logger, "Local checkpoint matches replay height");
+    }
+
+    if let Some(ip) = node_ip {
+        let uploaded = dry_run
+            .node_root(ip, true)
+            .join(IC_DATA_PATH.trim_start_matches('/'))
+            .join(NEW_IC_STATE)
+            .join(CHECKPOINTS);
+        if path_exists(&uploaded)? {
+            check_checkpoint_height(&uploaded, state_params.height)?;
+            info!(logger, "State uploaded to {} matches replay height", ip);
+        }
+    }
+
+    let cup_file = work_dir.join(RECOVERY_CUP_FILE_NAME);
+    if path_exists(&cup_file)? {
+        let (cup_height, cup_hash) = read_recovery_cup(&cup_file)?;
+        let recovery_height = Recovery::get_recovery_height(state_params.height);
+        if cup_height != recovery_height {
+            return Err(RecoveryError::invalid_output_error(format!(
+                "Recovery CUP height ({}) doesn't match recovery height ({})",
+                cup_height, recovery_height
+            )));
+        }
+        if cup_hash != state_params.hash {
+            return Err(RecoveryError::invalid_output_error(format!(
+                "Recovery CUP state hash ({}) doesn't match replay output ({})",
+                cup_hash, state_params.hash
+            )));
+        }
+        info!(logger, "Recovery CUP matches replay output");
+    }
+
+    Ok(())
+}
+
+fn check_checkpoint_height(checkpoints: &Path, height: Height) -> RecoveryResult<()> {
+    let (name, checkpoint_height) = Recovery::get_latest_checkpoint_name_and_height(checkpoints)?;
+    if checkpoint_height != height {
+        return Err(RecoveryError::invalid_output_error(format!(
+            "Latest checkpoint {} in {} doesn't match replay height ({})",
+            name,
+            checkpoints.display(),
+            height
+        )));
+    }
+    Ok(())
+}
+
+// Return the height and hex-encoded state hash of the CUP stored in `file`.
+fn read_recovery_cup(file: &Path) -> RecoveryResult<(Height, String)> {
+    let cup =
+        ic_cup_explorer::read_catchup_package(file).map_err(RecoveryError::invalid_output_error)?;
+    let content = pb::CatchUpContent::decode(&cup.content[..]).map_err(|e| {
+        RecoveryError::invalid_output_error(format!("Failed to decode CUP content: {}", e))
+    })?;
+    let height = content
+        .random_beacon
+        .map(|beacon| Height::from(beacon.height))
+        .ok_or_else(|| RecoveryError::invalid_output_error("Recovery CUP has no random beacon"))?;
+    Ok((height, hex::encode(content.state_hash)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use ic_replay::player::StateParams;
+    use tempfile::tempdir;
+
+    const NODE_IP: &str = "1.2.3.4";
+    const STATE_HASH: &str = "fedcba98";
+
+    fn dry_run(root: &Path) -> DryRun {
+        DryRun {
+            backup_dir: root.join("backup"),
+            upload_dir: root.join("upload"),
+        }
+    }
+
+    fn checkpoint_name(height: u64) -> String {
+        format!("{:016x}", height)
+    }
+
+    fn write_replay_output(work_dir: &Path, height: u64) {
+        let state_params = StateParams {
+            height: Height::from(height),
+            hash: STATE_HASH.to_string(),
+            ..Default::default()
+        };
+        replay_helper::store_replay_output(
+            state_params,
+            work_dir.join(replay_helper::OUTPUT_FILE_NAME),
+        )
+        .unwrap();
+    }
+
+    fn write_recovery_cup(work_dir: &Path, height: u64, state_hash: &str) {
+        let content = pb::CatchUpContent {
+            random_beacon: Some(pb::RandomBeacon {
+                height,
+                ..Default::default()
+            }),
+            state_hash: hex::decode(state_hash).unwrap(),
+            ..Default::default()
+        };
+        let cup = pb::CatchUpPackage {
+            content: content.encode_to_vec(),
+            ..Default::default()
+        };
+        std::fs::write(work_dir.join(RECOVERY_CUP_FILE_NAME), cup.encode_to_vec()).unwrap();
+    }
+
+    /// Create a work dir with a replay output at `height` and a local
+    /// checkpoint at `checkpoint_height`.
+    fn setup_work_dir(root: &Path, height: u64, checkpoint_height: u64) -> PathBuf {
+        let work_dir = root.join("work_dir");
+        let checkpoints = work_dir.join("data").join(IC_CHECKPOINTS_PATH);
+        create_dir(&checkpoints.join(checkpoint_name(checkpoint_height))).unwrap();
+        write_replay_output(&work_dir, height);
+        work_dir
+    }
+
+    fn upload_checkpoint(dry_run: &DryRun, ip: IpAddr, height: u64) {
+        let checkpoints = dry_run
+            .node_root(ip, true)
+            .join(IC_DATA_PATH.trim_start_matches('/'))
+            .join(NEW_IC_STATE)
+            .join(CHECKPOINTS);
+        create_dir(&checkpoints.join(checkpoint_name(height))).unwrap();
+    }
+
+    fn validate(dry_run: &DryRun, work_dir: &Path, node_ip: Option<IpAddr>) -> RecoveryResult<()> {
+        validate_recovery_artifacts(&util::make_logger(), dry_run, work_dir, node_ip)
+    }
+
+    #[test]
+    fn local_locations_are_not_mapped() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+
+        assert_eq!(
+            dry_run.map_location("/tmp/recovery/", false).unwrap(),
+            "/tmp/recovery/"
+        );
+    }
+
+    #[test]
+    fn downloads_are_mapped_to_backup_tree() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+
+        assert_eq!(
+            dry_run
+                .map_location("readonly@[::1]:/var/lib/ic/data", false)
+                .unwrap(),
+            format!("{}/backup/::1/var/lib/ic/data", tmp.path().display())
+        );
+        assert!(!tmp.path().join("backup").exists());
+    }
+
+    #[test]
+    fn uploads_are_mapped_to_upload_dir() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+
+        let target = dry_run
+            .map_location("admin@[1.2.3.4]:/var/lib/ic/data/new_ic_state/", true)
+            .unwrap();
+
+        assert_eq!(
+            target,
+            format!(
+                "{}/upload/1.2.3.4/var/lib/ic/data/new_ic_state/",
+                tmp.path().display()
+            )
+        );
+        assert!(PathBuf::from(target).exists());
+    }
+
+    #[test]
+    fn invalid_ip_is_rejected() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+
+        assert!(dry_run
+            .map_location("admin@[not-an-ip]:/var/lib/ic/data", false)
+            .is_err());
+    }
+
+    #[test]
+    fn matching_artifacts_are_accepted() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+        let work_dir = setup_work_dir(tmp.path(), 1234, 1234);
+        write_recovery_cup(&work_dir, 2000, STATE_HASH);
+        let ip: IpAddr = NODE_IP.parse().unwrap();
+        upload_checkpoint(&dry_run, ip, 1234);
+
+        assert!(validate(&dry_run, &work_dir, Some(ip)).is_ok());
+    }
+
+    #[test]
+    fn missing_replay_output_is_rejected() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+        let work_dir = tmp.path().join("work_dir");
+        create_dir(&work_dir).unwrap();
+
+        assert!(validate(&dry_run, &work_dir, None).is_err());
+    }
+
+    #[test]
+    fn local_checkpoint_at_other_height_is_rejected() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+        let work_dir = setup_work_dir(tmp.path(), 1234, 1200);
+
+        assert!(validate(&dry_run, &work_dir, None).is_err());
+    }
+
+    #[test]
+    fn uploaded_checkpoint_at_other_height_is_rejected() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+        let work_dir = setup_work_dir(tmp.path(), 1234, 1234);
+        let ip: IpAddr = NODE_IP.parse().unwrap();
+        upload_checkpoint(&dry_run, ip, 1200);
+
+        assert!(validate(&dry_run, &work_dir, None).is_ok());
+        assert!(validate(&dry_run, &work_dir, Some(ip)).is_err());
+    }
+
+    #[test]
+    fn cup_at_other_height_is_rejected() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+        let work_dir = setup_work_dir(tmp.path(), 1234, 1234);
+        write_recovery_cup(&work_dir, 1234, STATE_HASH);
+
+        assert!(validate(&dry_run, &work_dir, None).is_err());
+    }
+
+    #[test]
+    fn cup_with_other_state_hash_is_rejected() {
+        let tmp = tempdir().expect("Couldn't create a temp test directory");
+        let dry_run = dry_run(tmp.path());
+        let work_dir = setup_work_dir(tmp.path(), 1234, 1234);
+        write_recovery_cup(&work_dir, 2000, "01234567");
+
+        assert!(validate(&dry_run, &work_dir, None).is_err());
+    }
+}
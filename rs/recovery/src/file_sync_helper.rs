@@ -1,6 +1,7 @@
 use crate::{
     cli::wait_for_confirmation,
     command_helper::exec_cmd,
+    dry_run::DryRun,
     error::{RecoveryError, RecoveryResult},
     ssh_helper,
 };
@@ -67,6 +68,7 @@ pub fn rsync_with_retries(
     target: &str,
     require_confirmation: bool,
     key_file: Option<&PathBuf>,
+    dry_run: Option<&DryRun>,
     retries: usize,
 ) -> RecoveryResult<Option<String>> {
     for _ in 0..retries {
@@ -77,6 +79,7 @@ pub fn rsync_with_retries(
             target,
             require_confirmation,
             key_file,
+            dry_run,
         ) {
             Err(e) => {
                 warn!(logger, "Rsync failed: {:?}, retrying...", e);
@@ -90,6 +93,8 @@ pub fn rsync_with_retries(
 
 /// Copy the files from src to target using [rsync](https://linux.die.net/man/1/rsync) and options `--delete`, `-acP`.
 /// File and directory names part of the `excludes` vector are discarded.
+/// In dry-run mode, remote locations are replaced by their local stand-ins,
+/// see [DryRun::map_location].
 pub fn rsync<I>(
     logger: &Logger,
     excludes: I,
@@ -97,12 +102,26 @@ pub fn rsync<I>(
     target: &str,
     require_confirmation: bool,
     key_file: Option<&PathBuf>,
+    dry_run: Option<&DryRun>,
 ) -> RecoveryResult<Option<String>>
 where
     I: IntoIterator,
     I::Item: std::fmt::Display,
 {
-    let mut rsync = get_rsync_command(excludes, src, target, key_file);
+    let excludes: Vec<String> = excludes.into_iter().map(|e| e.to_string()).collect();
+    let mut rsync = match dry_run {
+        Some(dry_run) => {
+            let remote = get_rsync_command(&excludes, src, target, key_file);
+            dry_run.skip(logger, "remote transfer", &format!("{:?}", remote));
+            let is_upload = !src.contains("@[");
+            get_local_rsync_command(
+                &excludes,
+                &dry_run.map_location(src, false)?,
+                &dry_run.map_location(target, is_upload)?,
+            )
+        }
+        None => get_rsync_command(&excludes, src, target, key_file),
+    };
 
     info!(logger, "");
     info!(logger, "About to execute:");
@@ -126,6 +145,17 @@ where
 }
 
 fn get_rsync_command<I>(excludes: I, src: &str, target: &str, key_file: Option<&PathBuf>) -> Command
+where
+    I: IntoIterator,
+    I::Item: std::fmt::Display,
+{
+    let mut rsync = get_local_rsync_command(excludes, src, target);
+    rsync.arg("-e").arg(ssh_helper::get_rsync_ssh_arg(key_file));
+
+    rsync
+}
+
+fn get_local_rsync_command<I>(excludes: I, src: &str, target: &str) -> Command
 where
     I: IntoIterator,
     I::Item: std::fmt::Display,
@@ -134,7 +164,6 @@ where
     rsync.arg("--delete").arg("-acP").arg("--no-g");
     rsync.args(excludes.into_iter().map(|e| format!("--exclude={}", e)));
     rsync.arg(src).arg(target);
-    rsync.arg("-e").arg(ssh_helper::get_rsync_ssh_arg(key_file));
 
     rsync
 }
@@ -218,4 +247,22 @@ mod tests {
                 "-e",
                 "ssh -o StrictHostKeyChecking=no -o NumberOfPasswordPrompts=0 -o ConnectionAttempts=30 -o ConnectTimeout=60 -A -i /tmp/key_file"]);
     }
+
+    #[test]
+    fn get_local_rsync_command_test() {
+        let rsync = get_local_rsync_command(["exclude1"], "/tmp/src", "/tmp/target");
+
+        assert_eq!(rsync.get_program(), "rsync");
+        assert_eq!(
+            rsync.get_args().collect::<Vec<_>>(),
+            vec![
+                "--delete",
+                "-acP",
+                "--no-g",
+                "--exclude=exclude1",
+                "/tmp/src",
+                "/tmp/target",
+            ]
+        );
+    }
 }
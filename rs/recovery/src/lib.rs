@@ -6,12 +6,13 @@
 //! reproducible) description of the step, as well as its potential automatic
 //! execution.
 use crate::{
-    cli::wait_for_confirmation, file_sync_helper::remove_dir, registry_helper::RegistryHelper,
+    cli::wait_for_confirmation, dry_run::DryRun, file_sync_helper::remove_dir,
+    registry_helper::RegistryHelper,
 };
 use admin_helper::{AdminHelper, IcAdmin, RegistryParams};
 use command_helper::exec_cmd;
 use error::{RecoveryError, RecoveryResult};
-use file_sync_helper::{create_dir, download_binary, read_dir};
+use file_sync_helper::{create_dir, download_binary, read_dir, rsync};
 use futures::future::join_all;
 use ic_base_types::{CanisterId, NodeId};
 use ic_cup_explorer::get_catchup_content;
@@ -43,6 +44,7 @@ pub mod args_merger;
 pub mod cli;
 pub mod cmd;
 pub mod command_helper;
+pub mod dry_run;
 pub mod error;
 pub mod file_sync_helper;
 pub mod nns_recovery_failover_nodes;
@@ -103,6 +105,9 @@ pub struct RecoveryArgs {
     pub replica_version: Option<ReplicaVersion>,
     pub key_file: Option<PathBuf>,
     pub test_mode: bool,
+    /// Backup tree to rehearse the recovery against, see [dry_run].
+    #[serde(default)]
+    pub dry_run: Option<PathBuf>,
 }
 
 /// The recovery struct comprises working directories for the recovery of a
@@ -125,6 +130,7 @@ pub struct Recovery {
 
     pub key_file: Option<PathBuf>,
     ssh_confirmation: bool,
+    pub dry_run: Option<DryRun>,
 
     pub logger: Logger,
 }
@@ -149,12 +155,43 @@ impl Recovery {
 
         Recovery::create_dirs(&[&binary_dir, &data_dir, &work_dir, &local_store_path])?;
 
+        let dry_run = match args.dry_run {
+            Some(backup_dir) => Some(DryRun::new(
+                backup_dir,
+                recovery_dir.join(dry_run::DRY_RUN_UPLOAD_DIR),
+            )?),
+            None => None,
+        };
+
+        let registry_polling_strategy = match &dry_run {
+            Some(dry_run) => {
+                info!(
+                    logger,
+                    "Dry run against {}, uploads go to {}",
+                    dry_run.backup_dir().display(),
+                    dry_run.upload_dir().display()
+                );
+                rsync(
+                    &logger,
+                    Vec::<String>::default(),
+                    &format!("{}/", dry_run.registry_local_store().display()),
+                    &local_store_path.display().to_string(),
+                    false,
+                    None,
+                    None,
+                )?;
+                RegistryPollingStrategy::OnlyOnInit
+            }
+            None => registry_polling_strategy,
+        };
+
         let registry_helper = RegistryHelper::new(
             logger.clone(),
             registry_nns_url,
             local_store_path.clone(),
             nns_pem.as_path(),
             registry_polling_strategy,
+            dry_run.as_ref(),
         );
 
         if ssh_confirmation {
@@ -188,6 +225,7 @@ impl Recovery {
             registry_helper,
             key_file: args.key_file,
             ssh_confirmation,
+            dry_run,
             logger,
         })
     }
@@ -242,6 +280,7 @@ impl Recovery {
             ic_admin_cmd: self
                 .admin_helper
                 .get_halt_subnet_command(subnet_id, is_halted, keys),
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -258,6 +297,7 @@ impl Recovery {
             node_ip,
             self.ssh_confirmation,
             self.key_file.clone(),
+            self.dry_run.clone(),
         );
         ssh_helper.ssh(commands.to_string())
     }
@@ -270,12 +310,22 @@ impl Recovery {
             node_ip,
             self.ssh_confirmation,
             self.key_file.clone(),
+            self.dry_run.clone(),
         );
         ssh_helper.can_connect()
     }
 
-    // Execute an `ic-admin` command, log the output.
-    fn exec_admin_cmd(logger: &Logger, ic_admin_cmd: &IcAdmin) -> RecoveryResult<()> {
+    // Execute an `ic-admin` command, log the output. In dry-run mode, only log
+    // the command.
+    fn exec_admin_cmd(
+        logger: &Logger,
+        ic_admin_cmd: &IcAdmin,
+        dry_run: Option<&DryRun>,
+    ) -> RecoveryResult<()> {
+        if let Some(dry_run) = dry_run {
+            dry_run.skip(logger, "ic-admin command", &ic_admin_cmd.join(" "));
+            return Ok(());
+        }
         let mut cmd = AdminHelper::to_system_command(ic_admin_cmd);
         if let Some(res) = exec_cmd(&mut cmd)? {
             info!(logger, "{}", res);
@@ -294,6 +344,7 @@ impl Recovery {
             require_confirmation: self.ssh_confirmation,
             key_file: self.key_file.clone(),
             admin,
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -328,6 +379,7 @@ impl Recovery {
                 .iter()
                 .map(std::string::ToString::to_string)
                 .collect(),
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -455,6 +507,7 @@ impl Recovery {
             registry_helper: self.registry_helper.clone(),
             work_dir: self.work_dir.clone(),
             extra_batches,
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -479,6 +532,7 @@ impl Recovery {
             require_confirmation: self.ssh_confirmation,
             key_file: self.key_file.clone(),
             check_ic_replay_height: true,
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -553,6 +607,7 @@ impl Recovery {
                     &upgrade_url,
                     sha256,
                 ),
+            dry_run: self.dry_run.clone(),
         })
     }
 
@@ -568,6 +623,7 @@ impl Recovery {
             ic_admin_cmd: self
                 .admin_helper
                 .get_propose_to_update_subnet_replica_version_command(subnet_id, upgrade_version),
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -613,6 +669,7 @@ impl Recovery {
                     ecdsa_subnet_id,
                     SystemTime::now(),
                 ),
+            dry_run: self.dry_run.clone(),
         })
     }
 
@@ -623,6 +680,7 @@ impl Recovery {
             logger: self.logger.clone(),
             node_ip,
             work_dir: self.work_dir.clone(),
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -751,6 +809,7 @@ impl Recovery {
             node_ip,
             require_confirmation: self.ssh_confirmation,
             key_file: self.key_file.clone(),
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -811,6 +870,7 @@ impl Recovery {
             work_dir: self.work_dir.clone(),
             require_confirmation: self.ssh_confirmation,
             key_file: self.key_file.clone(),
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -828,6 +888,7 @@ impl Recovery {
                 replica_version,
                 node_ids,
             ),
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -845,6 +906,7 @@ impl Recovery {
             work_dir: self.work_dir.clone(),
             require_confirmation: self.ssh_confirmation,
             key_file: self.key_file.clone(),
+            dry_run: self.dry_run.clone(),
         }
     }
 
@@ -862,6 +924,7 @@ impl Recovery {
             tar,
             require_confirmation: self.ssh_confirmation,
             key_file: self.key_file.clone(),
+            dry_run: self.dry_run.clone(),
         }
     }
 }
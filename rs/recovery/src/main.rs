@@ -27,6 +27,7 @@ fn main() {
         replica_version: args.replica_version,
         key_file: args.key_file,
         test_mode: args.test,
+        dry_run: args.dry_run,
     };
 
     let recovery_state = cli::read_and_maybe_update_state(&logger, recovery_args, args.subcmd);
@@ -165,6 +165,7 @@ impl RecoveryIterator<StepType, StepTypeIter> for NNSRecoveryFailoverNodes {
                     &self.logger,
                     &self.recovery.registry_helper,
                     self.params.subnet_id,
+                    self.recovery.dry_run.as_ref(),
                 );
 
                 if self.params.download_node.is_none() {
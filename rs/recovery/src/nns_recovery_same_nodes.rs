@@ -129,6 +129,7 @@ impl RecoveryIterator<StepType, StepTypeIter> for NNSRecoverySameNodes {
                     &self.logger,
                     &self.recovery.registry_helper,
                     self.params.subnet_id,
+                    self.recovery.dry_run.as_ref(),
                 );
 
                 if self.params.download_node.is_none() {
@@ -1,4 +1,5 @@
 use crate::{
+    dry_run::DryRun,
     error::{RecoveryError, RecoveryResult},
     file_sync_helper::read_file,
     util::{block_on, write_public_key_to_file},
@@ -62,6 +63,7 @@ impl RegistryHelper {
         local_store_path: PathBuf,
         nns_pem_path: &Path,
         polling_strategy: RegistryPollingStrategy,
+        dry_run: Option<&DryRun>,
     ) -> Self {
         let local_store = Arc::new(LocalStoreImpl::new(local_store_path));
         let registry_client = Arc::new(RegistryClientImpl::new(
@@ -75,14 +77,21 @@ impl RegistryHelper {
             Duration::from_secs(10),
         ));
 
-        match get_nns_public_key(&nns_url, nns_pem_path, &logger) {
-            Ok(nns_public_key) => {
-                block_on(
-                    registry_replicator
-                        .initialize_local_store(vec![nns_url.clone()], Some(nns_public_key)),
-                );
+        if dry_run.is_some() {
+            info!(
+                logger,
+                "Dry run, using the registry local store without syncing it with the NNS"
+            );
+        } else {
+            match get_nns_public_key(&nns_url, nns_pem_path, &logger) {
+                Ok(nns_public_key) => {
+                    block_on(
+                        registry_replicator
+                            .initialize_local_store(vec![nns_url.clone()], Some(nns_public_key)),
+                    );
+                }
+                Err(err) => error!(logger, "Failed getting the nns public key: {}", err),
             }
-            Err(err) => error!(logger, "Failed getting the nns public key: {}", err),
         }
 
         Self {
@@ -1,5 +1,6 @@
 use crate::cli::wait_for_confirmation;
 use crate::command_helper::exec_cmd;
+use crate::dry_run::DryRun;
 use crate::error::RecoveryError;
 use crate::RecoveryResult;
 use slog::{info, warn, Logger};
@@ -29,6 +30,7 @@ pub struct SshHelper {
     pub ip: IpAddr,
     pub require_confirmation: bool,
     pub key_file: Option<PathBuf>,
+    pub dry_run: Option<DryRun>,
 }
 
 impl SshHelper {
@@ -38,6 +40,7 @@ impl SshHelper {
         ip: IpAddr,
         require_confirmation: bool,
         key_file: Option<PathBuf>,
+        dry_run: Option<DryRun>,
     ) -> Self {
         Self {
             logger,
@@ -45,12 +48,18 @@ impl SshHelper {
             ip,
             require_confirmation,
             key_file,
+            dry_run,
         }
     }
 
     /// Execute the given command string on a remote machine using SSH.
+    /// In dry-run mode, the command is only printed.
     pub fn ssh(&self, commands: String) -> RecoveryResult<Option<String>> {
         let mut ssh = self.get_command(commands);
+        if let Some(dry_run) = &self.dry_run {
+            dry_run.skip(&self.logger, "ssh command", &format!("{:?}", ssh));
+            return Ok(None);
+        }
         info!(self.logger, "");
         info!(self.logger, "About to execute:");
         info!(self.logger, "{:?}", ssh);
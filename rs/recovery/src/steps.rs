@@ -1,6 +1,7 @@
 use crate::{
     admin_helper::IcAdmin,
     command_helper::exec_cmd,
+    dry_run::{self, DryRun},
     error::{RecoveryError, RecoveryResult},
     file_sync_helper::{create_dir, read_dir, remove_dir, rsync, rsync_with_retries},
     get_member_ips, get_node_heights_from_metrics,
@@ -44,6 +45,7 @@ impl<T: Step + 'static> From<T> for Box<dyn Step> {
 pub struct AdminStep {
     pub logger: Logger,
     pub ic_admin_cmd: IcAdmin,
+    pub dry_run: Option<DryRun>,
 }
 
 impl Step for AdminStep {
@@ -55,7 +57,7 @@ impl Step for AdminStep {
 
     // Execute the ic-admin CLI string as a system command
     fn exec(&self) -> RecoveryResult<()> {
-        Recovery::exec_admin_cmd(&self.logger, &self.ic_admin_cmd)
+        Recovery::exec_admin_cmd(&self.logger, &self.ic_admin_cmd, self.dry_run.as_ref())
     }
 }
 
@@ -67,6 +69,7 @@ pub struct DownloadCertificationsStep {
     pub require_confirmation: bool,
     pub key_file: Option<PathBuf>,
     pub admin: bool,
+    pub dry_run: Option<DryRun>,
 }
 
 impl Step for DownloadCertificationsStep {
@@ -97,6 +100,7 @@ impl Step for DownloadCertificationsStep {
                 &target.display().to_string(),
                 self.require_confirmation,
                 self.key_file.as_ref(),
+                self.dry_run.as_ref(),
                 5,
             )
             .map_err(|e| warn!(self.logger, "Failed to download certifications: {:?}", e));
@@ -264,6 +268,7 @@ pub struct DownloadIcStateStep {
     pub require_confirmation: bool,
     pub key_file: Option<PathBuf>,
     pub additional_excludes: Vec<String>,
+    pub dry_run: Option<DryRun>,
 }
 
 impl Step for DownloadIcStateStep {
@@ -295,6 +300,7 @@ impl Step for DownloadIcStateStep {
             self.node_ip,
             self.require_confirmation,
             self.key_file.clone(),
+            self.dry_run.clone(),
         );
 
         if ssh_helper.wait_for_access().is_err() {
@@ -354,6 +360,7 @@ impl Step for DownloadIcStateStep {
             target,
             self.require_confirmation,
             self.key_file.as_ref(),
+            self.dry_run.as_ref(),
         )?;
 
         rsync(
@@ -363,6 +370,7 @@ impl Step for DownloadIcStateStep {
             target,
             self.require_confirmation,
             self.key_file.as_ref(),
+            self.dry_run.as_ref(),
         )?;
 
         if self.keep_downloaded_state {
@@ -373,6 +381,7 @@ impl Step for DownloadIcStateStep {
                 &self.working_dir,
                 false,
                 None,
+                None,
             )?;
         }
 
@@ -452,6 +461,7 @@ pub struct ValidateReplayStep {
     pub registry_helper: RegistryHelper,
     pub work_dir: PathBuf,
     pub extra_batches: u64,
+    pub dry_run: Option<DryRun>,
 }
 
 impl Step for ValidateReplayStep {
@@ -460,6 +470,19 @@ impl Step for ValidateReplayStep {
     }
 
     fn exec(&self) -> RecoveryResult<()> {
+        if let Some(dry_run) = &self.dry_run {
+            info!(
+                self.logger,
+                "Dry run, validating replay output against local artifacts instead of node metrics"
+            );
+            return dry_run::validate_recovery_artifacts(
+                &self.logger,
+                dry_run,
+                &self.work_dir,
+                None,
+            );
+        }
+
         let latest_height =
             replay_helper::read_output(self.work_dir.join(replay_helper::OUTPUT_FILE_NAME))?.height;
 
@@ -513,6 +536,7 @@ pub struct UploadAndRestartStep {
     pub require_confirmation: bool,
     pub key_file: Option<PathBuf>,
     pub check_ic_replay_height: bool,
+    pub dry_run: Option<DryRun>,
 }
 
 impl Step for UploadAndRestartStep {
@@ -533,6 +557,7 @@ impl Step for UploadAndRestartStep {
             self.node_ip,
             self.require_confirmation,
             self.key_file.clone(),
+            self.dry_run.clone(),
         );
 
         let checkpoint_path = self.data_src.join(CHECKPOINTS);
@@ -590,6 +615,7 @@ impl Step for UploadAndRestartStep {
             &target,
             self.require_confirmation,
             self.key_file.as_ref(),
+            self.dry_run.as_ref(),
         )?;
 
         let ic_state_path = format!("{}/{}", IC_DATA_PATH, IC_STATE);
@@ -630,6 +656,7 @@ pub struct WaitForCUPStep {
     pub logger: Logger,
     pub node_ip: IpAddr,
     pub work_dir: PathBuf,
+    pub dry_run: Option<DryRun>,
 }
 
 impl Step for WaitForCUPStep {
@@ -641,6 +668,20 @@ impl Step for WaitForCUPStep {
     }
 
     fn exec(&self) -> RecoveryResult<()> {
+        if let Some(dry_run) = &self.dry_run {
+            info!(
+                self.logger,
+                "Dry run, validating recovery artifacts of node {} instead of waiting for its CUP",
+                self.node_ip
+            );
+            return dry_run::validate_recovery_artifacts(
+                &self.logger,
+                dry_run,
+                &self.work_dir,
+                Some(self.node_ip),
+            );
+        }
+
         let state_params =
             replay_helper::read_output(self.work_dir.join(replay_helper::OUTPUT_FILE_NAME))?;
         let recovery_height = Recovery::get_recovery_height(state_params.height);
@@ -673,6 +714,7 @@ pub struct StopReplicaStep {
     pub node_ip: IpAddr,
     pub require_confirmation: bool,
     pub key_file: Option<PathBuf>,
+    pub dry_run: Option<DryRun>,
 }
 
 impl Step for StopReplicaStep {
@@ -687,6 +729,7 @@ impl Step for StopReplicaStep {
             self.node_ip,
             self.require_confirmation,
             self.key_file.clone(),
+            self.dry_run.clone(),
         );
         ssh_helper.ssh("sudo systemctl stop ic-replica".to_string())?;
         Ok(())
@@ -800,6 +843,7 @@ impl Step for CopyIcStateStep {
             &format!("{}/", self.new_state_dir.display()),
             false,
             None,
+            None,
         )?;
         Ok(())
     }
@@ -812,6 +856,7 @@ pub struct UploadCUPAndTar {
     pub require_confirmation: bool,
     pub key_file: Option<PathBuf>,
     pub work_dir: PathBuf,
+    pub dry_run: Option<DryRun>,
 }
 
 impl UploadCUPAndTar {
@@ -859,6 +904,7 @@ impl Step for UploadCUPAndTar {
                     ip,
                     self.require_confirmation,
                     self.key_file.clone(),
+                    self.dry_run.clone(),
                 );
 
                 if !ssh_helper.can_connect() {
@@ -885,6 +931,7 @@ impl Step for UploadCUPAndTar {
                     &target,
                     self.require_confirmation,
                     self.key_file.as_ref(),
+                    self.dry_run.as_ref(),
                 )?;
 
                 rsync(
@@ -894,6 +941,7 @@ impl Step for UploadCUPAndTar {
                     &target,
                     self.require_confirmation,
                     self.key_file.as_ref(),
+                    self.dry_run.as_ref(),
                 )?;
 
                 ssh_helper.ssh(self.get_restart_commands())
@@ -911,6 +959,7 @@ pub struct DownloadRegistryStoreStep {
     pub work_dir: PathBuf,
     pub require_confirmation: bool,
     pub key_file: Option<PathBuf>,
+    pub dry_run: Option<DryRun>,
 }
 
 impl Step for DownloadRegistryStoreStep {
@@ -931,6 +980,7 @@ impl Step for DownloadRegistryStoreStep {
             self.node_ip,
             self.require_confirmation,
             self.key_file.clone(),
+            self.dry_run.clone(),
         );
 
         info!(
@@ -971,6 +1021,7 @@ impl Step for DownloadRegistryStoreStep {
             &format!("{}/", self.work_dir.display()),
             self.require_confirmation,
             self.key_file.as_ref(),
+            self.dry_run.as_ref(),
         )?;
 
         Ok(())
@@ -984,6 +1035,7 @@ pub struct UploadAndHostTarStep {
     pub tar: PathBuf,
     pub require_confirmation: bool,
     pub key_file: Option<PathBuf>,
+    pub dry_run: Option<DryRun>,
 }
 
 impl Step for UploadAndHostTarStep {
@@ -1003,6 +1055,7 @@ impl Step for UploadAndHostTarStep {
             self.aux_ip,
             self.require_confirmation,
             self.key_file.clone(),
+            self.dry_run.clone(),
         );
 
         let upload_dir = "/tmp/recovery_registry";
@@ -1019,6 +1072,7 @@ impl Step for UploadAndHostTarStep {
             &target,
             self.require_confirmation,
             self.key_file.as_ref(),
+            self.dry_run.as_ref(),
         )?;
 
         ssh_helper.ssh("daemonize $(which python3) -m http.server --bind :: 8081".to_string())?;
@@ -73,6 +73,7 @@ fn main() {
         replica_version: args.replica_version,
         key_file: args.key_file,
         test_mode: args.test,
+        dry_run: None,
     };
 
     let subnet_splitting_state =
@@ -55,6 +55,7 @@ impl Step for CopyWorkDirStep {
                 .to_string(),
             /*require_confirmation=*/ false,
             /*key_file=*/ None,
+            /*dry_run=*/ None,
         )
         .map(|_| ())
     }
@@ -323,6 +323,7 @@ impl SubnetSplitting {
                 require_confirmation: self.interactive,
                 key_file: self.recovery.key_file.clone(),
                 check_ic_replay_height: false,
+                dry_run: self.recovery.dry_run.clone(),
             }),
             None => Err(RecoveryError::StepSkipped),
         }
@@ -480,6 +481,7 @@ impl RecoveryIterator<StepType, StepTypeIter> for SubnetSplitting {
                     self.params.source_subnet_id,
                     self.params.destination_subnet_id,
                 ),
+                dry_run: self.recovery.dry_run.clone(),
             }
             .into(),
 
@@ -503,6 +505,7 @@ impl RecoveryIterator<StepType, StepTypeIter> for SubnetSplitting {
                     self.params.source_subnet_id,
                     &self.params.pub_key,
                 ),
+                dry_run: self.recovery.dry_run.clone(),
             }
             .into(),
 
@@ -514,6 +517,7 @@ impl RecoveryIterator<StepType, StepTypeIter> for SubnetSplitting {
                     self.params.source_subnet_id,
                     self.params.destination_subnet_id,
                 ),
+                dry_run: self.recovery.dry_run.clone(),
             }
             .into(),
 
@@ -597,6 +601,7 @@ impl RecoveryIterator<StepType, StepTypeIter> for SubnetSplitting {
                     self.params.source_subnet_id,
                     self.params.destination_subnet_id,
                 ),
+                dry_run: self.recovery.dry_run.clone(),
             }
             .into(),
 
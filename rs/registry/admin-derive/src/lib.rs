@@ -27,12 +27,16 @@ fn impl_proposal_metadata(ast: &syn::DeriveInput) -> TokenStream {
             }
 
             fn proposer_and_sender(&self, sender: Sender) -> (NeuronId, Sender) {
-                let use_test_neuron = self.test_neuron_proposer || (self.dry_run && matches!(sender, Sender::Anonymous));
+                let use_test_neuron = self.test_neuron_proposer || (self.is_dry_run() && matches!(sender, Sender::Anonymous));
                 get_proposer_and_sender(self.proposer.clone(), sender, use_test_neuron)
             }
 
             fn is_dry_run(&self) -> bool {
-                self.dry_run
+                self.dry_run || self.simulate.is_some()
+            }
+
+            fn simulate(&self) -> Option<PathBuf> {
+                self.simulate.clone()
             }
 
             fn is_json(&self) -> bool {
@@ -94,6 +98,15 @@ pub fn derive_common_proposal_fields(_: TokenStream, item: TokenStream) -> Token
                             /// If set, JSON output will be printed for --dry-run
                             #[clap(long)]
                             pub json: bool,
+
+                            /// If set to the path of a registry local store, the proposal is not
+                            /// submitted. Instead, the registry mutation it would execute is applied
+                            /// to the latest version of the local store, checked against all registry
+                            /// invariants, and the resulting registry diff is printed. The local
+                            /// store itself is left unchanged. Proposals that are not executed by
+                            /// the registry canister fail with "simulation unsupported".
+                            #[clap(long, value_name = "LOCAL_STORE_PATH")]
+                            pub simulate: Option<PathBuf>,
                     });
                     stream.extend(gen);
                     stream.extend(group.stream());
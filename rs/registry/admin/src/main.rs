@@ -158,7 +158,10 @@ use std::{
     sync::Arc,
     time::SystemTime,
 };
-use types::{ProvisionalWhitelistRecord, Registry, RegistryRecord, RegistryValue, SubnetRecord};
+use types::{
+    MonthlyNodeProviderRewards, NodeOperatorRecord as NodeOperator, ProvisionalWhitelistRecord,
+    Registry, RegistryRecord, RegistryValue, RootProposal, SubnetRecord,
+};
 use url::Url;
 
 #[macro_use]
@@ -166,6 +169,7 @@ extern crate ic_admin_derive;
 
 extern crate chrono;
 
+mod simulate;
 mod types;
 
 #[cfg(test)]
@@ -231,7 +235,9 @@ struct Opts {
     )]
     nns_public_key_pem_file: Option<PathBuf>,
 
-    /// Return the output in JSON format.
+    /// Return the output in JSON format. Read commands print a single JSON
+    /// document, with principals in their textual representation and other
+    /// binary data hex encoded.
     #[clap(long = "json", global = true)]
     json: bool,
 }
@@ -526,6 +532,20 @@ pub trait ProposalMetadata {
     fn proposer_and_sender(&self, sender: Sender) -> (NeuronId, Sender);
     fn is_dry_run(&self) -> bool;
     fn is_json(&self) -> bool;
+    fn simulate(&self) -> Option<PathBuf>;
+}
+
+/// Exits with an error if `--simulate` was given for a proposal that is not
+/// submitted through `propose_external_proposal_from_command`, as only the
+/// registry mutations of those proposals can be simulated.
+fn exit_if_simulated(cmd: &impl ProposalMetadata) {
+    if cmd.simulate().is_some() {
+        eprintln!(
+            "{}",
+            simulate::simulation_unsupported("the proposal is not a registry proposal")
+        );
+        std::process::exit(1);
+    }
 }
 
 /// Trait to extract the title for proposal type.
@@ -2476,6 +2496,7 @@ impl From<&ProposeToOpenSnsTokenSwap> for OpenSnsTokenSwap {
             summary_file: _,
             dry_run: _,
             json: _,
+            simulate: _,
         } = (*cli_proposal).clone();
         OpenSnsTokenSwap {
             target_swap_canister_id: Some(target_swap_canister_id),
@@ -3776,6 +3797,7 @@ impl TryFrom<ProposeToCreateServiceNervousSystemCmd> for CreateServiceNervousSys
             summary_file: _,
             dry_run: _,
             json: _,
+            simulate: _,
         } = cmd;
 
         let name = Some(name);
@@ -3982,6 +4004,7 @@ async fn propose_to_create_service_nervous_system(
     agent: Agent,
     proposer: NeuronId,
 ) {
+    exit_if_simulated(&cmd);
     let is_dry_run = cmd.is_dry_run();
 
     let action = Some(Action::CreateServiceNervousSystem(
@@ -4277,6 +4300,49 @@ async fn main() {
             println!("{}", res);
         }
 
+        SubCommand::GetTopology if opts.json => {
+            // Each subnet record is printed as a `RegistryRecord`, as by
+            // `get-subnet --json`.
+            let mut subnets = BTreeMap::new();
+            let mut seen: HashSet<NodeId> = HashSet::new();
+            for subnet_id in get_subnet_ids(&registry_canister).await {
+                let key = make_subnet_record_key(subnet_id);
+                let (bytes, version) = registry_canister
+                    .get_value(key.as_bytes().to_vec(), None)
+                    .await
+                    .unwrap();
+                let record = SubnetRecordProto::decode(&bytes[..])
+                    .expect("Error decoding value from registry.");
+                seen.extend(
+                    record
+                        .membership
+                        .iter()
+                        .map(|n| NodeId::from(PrincipalId::try_from(&n[..]).unwrap())),
+                );
+                subnets.insert(
+                    subnet_id.to_string(),
+                    RegistryRecord {
+                        key,
+                        version,
+                        value: RegistryValue::SubnetRecord(SubnetRecord::from(&record)),
+                    },
+                );
+            }
+            let unassigned_nodes = get_node_list_since(0, registry_canister)
+                .await
+                .into_iter()
+                .filter(|record| {
+                    let node_id = NodeId::from(PrincipalId::from_str(&record.node_id).unwrap());
+                    !seen.contains(&node_id)
+                })
+                .collect::<Vec<_>>();
+            print_json(&serde_json::json!({
+                "topology": {
+                    "subnets": subnets,
+                    "unassigned_nodes": unassigned_nodes,
+                }
+            }));
+        }
         SubCommand::GetTopology => {
             // Because ic-admin codebase is riddled with bad patterns -- most notably, all
             // get/fetch methods also print out the representation of the
@@ -4295,7 +4361,7 @@ async fn main() {
                 let record = print_and_get_last_value::<SubnetRecordProto>(
                     make_subnet_record_key(*subnet_id).as_bytes().to_vec(),
                     &registry_canister,
-                    true,
+                    false,
                 )
                 .await;
                 if i + 1 != subnet_count {
@@ -4342,6 +4408,14 @@ async fn main() {
             )
             .await;
         }
+        SubCommand::GetSubnetList if opts.json => {
+            print_and_get_last_value::<SubnetListRecord>(
+                make_subnet_list_record_key().as_bytes().to_vec(),
+                &registry_canister,
+                true,
+            )
+            .await;
+        }
         SubCommand::GetSubnetList => {
             let value: Vec<_> = registry_canister
                 .get_value(make_subnet_list_record_key().as_bytes().to_vec(), None)
@@ -4365,6 +4439,16 @@ async fn main() {
             )
             .await;
 
+            // With `--json`, stdout only carries the record, so the results of
+            // the checks below are reported on stderr.
+            let as_json = opts.json;
+            let report = |line: &str| {
+                if as_json {
+                    eprintln!("{}", line);
+                } else {
+                    println!("{}", line);
+                }
+            };
             let mut success = true;
 
             eprintln!("Download IC-OS .. ");
@@ -4383,14 +4467,14 @@ async fn main() {
                 panic!("Download of release package failed.");
             }
 
-            println!("OK   Download success");
+            report("OK   Download success");
 
             // Explicitly check sha256 sum again, just to make sure and make the output a
             // bit nicer
             match check_file_hash(&tmp_file, &version.release_package_sha256_hex) {
-                Ok(()) => println!("OK   sha256 hash of IC-OS upgrade tar"),
+                Ok(()) => report("OK   sha256 hash of IC-OS upgrade tar"),
                 Err(e) => {
-                    println!("FAIL sha256 incorrect: {:?}", e);
+                    report(&format!("FAIL sha256 incorrect: {:?}", e));
                     success = false;
                 }
             };
@@ -4399,7 +4483,7 @@ async fn main() {
             eprintln!("Extracting .. ");
             match extract_tar_gz_into_dir(&tmp_file, &tmp_dir) {
                 Ok(()) => {
-                    println!("OK   extracting tar gz archive");
+                    report("OK   extracting tar gz archive");
                     let mut version_file = tmp_dir.clone();
                     version_file.push("VERSION.TXT");
 
@@ -4414,17 +4498,17 @@ async fn main() {
                     let archive_version = archive_version.trim();
 
                     if archive_version == get_replica_version_cmd.replica_version_id {
-                        println!("OK   correct version number in archived version file");
+                        report("OK   correct version number in archived version file");
                     } else {
-                        println!(
+                        report(&format!(
                             "FAIL incorrect version number in archived version file ({} vs {})",
                             archive_version, get_replica_version_cmd.replica_version_id
-                        );
+                        ));
                         success = false;
                     }
                 }
                 Err(e) => {
-                    println!("FAIL extracting tar gz archive: {:?}", e);
+                    report(&format!("FAIL extracting tar gz archive: {:?}", e));
                     success = false;
                 }
             }
@@ -4482,8 +4566,21 @@ async fn main() {
                 .get_ecdsa_signing_subnets(registry_client.get_latest_version())
                 .unwrap()
                 .unwrap();
-            for (key_id, subnets) in signing_subnets.iter() {
-                println!("KeyId {:?}: {:?}", key_id, subnets);
+            if opts.json {
+                let signing_subnets: BTreeMap<String, Vec<String>> = signing_subnets
+                    .iter()
+                    .map(|(key_id, subnets)| {
+                        (
+                            key_id.to_string(),
+                            subnets.iter().map(ToString::to_string).collect(),
+                        )
+                    })
+                    .collect();
+                print_json(&signing_subnets);
+            } else {
+                for (key_id, subnets) in signing_subnets.iter() {
+                    println!("KeyId {:?}: {:?}", key_id, subnets);
+                }
             }
         }
         SubCommand::ProposeToUpdateElectedReplicaVersions(cmd) => {
@@ -4878,6 +4975,14 @@ async fn main() {
             )
             .await;
         }
+        SubCommand::GetFirewallConfig if opts.json => {
+            print_and_get_last_value::<FirewallConfig>(
+                make_firewall_config_record_key().into_bytes(),
+                &registry_canister,
+                true,
+            )
+            .await;
+        }
         SubCommand::GetFirewallConfig => {
             let key = make_firewall_config_record_key();
             let (bytes, _) = registry_canister.get_value(key.into(), None).await.unwrap();
@@ -4964,7 +5069,7 @@ async fn main() {
             get_firewall_rules_for_node(cmd, &registry_canister, opts.nns_url).await;
         }
         SubCommand::GetFirewallRulesetHash(cmd) => {
-            get_firewall_ruleset_hash(cmd);
+            get_firewall_ruleset_hash(cmd, opts.json);
         }
         SubCommand::ProposeToAddOrRemoveNodeProvider(cmd) => {
             let (proposer, sender) =
@@ -4983,7 +5088,11 @@ async fn main() {
         }
         SubCommand::GetRegistryVersion => {
             let latest_version = registry_canister.get_latest_version().await.unwrap();
-            println!("{}", latest_version)
+            if opts.json {
+                print_json(&serde_json::json!({ "version": latest_version }));
+            } else {
+                println!("{}", latest_version)
+            }
         }
         SubCommand::SubmitRootProposalToUpgradeGovernanceCanister(cmd) => {
             let sender = get_test_sender_if_set(sender, cmd.test_user_proposer);
@@ -4999,12 +5108,15 @@ async fn main() {
             .await
         }
         SubCommand::GetPendingRootProposalsToUpgradeGovernanceCanister => {
-            get_pending_root_proposals_to_upgrade_governance_canister(make_canister_client(
-                opts.nns_url,
-                opts.verify_nns_responses,
-                opts.nns_public_key_pem_file,
-                sender,
-            ))
+            get_pending_root_proposals_to_upgrade_governance_canister(
+                make_canister_client(
+                    opts.nns_url,
+                    opts.verify_nns_responses,
+                    opts.nns_public_key_pem_file,
+                    sender,
+                ),
+                opts.json,
+            )
             .await
         }
         SubCommand::VoteOnRootProposalToUpgradeGovernanceCanister(cmd) => {
@@ -5140,7 +5252,17 @@ async fn main() {
             ));
 
             let response = canister_client.get_monthly_node_provider_rewards().await;
-            println!("{:?}", response);
+            if opts.json {
+                match response {
+                    Ok(rewards) => print_json(&MonthlyNodeProviderRewards::from(rewards)),
+                    Err(e) => {
+                        eprintln!("get_monthly_node_provider_rewards error: {:?}", e);
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                println!("{:?}", response);
+            }
         }
         SubCommand::ProposeToRemoveNodeOperators(cmd) => {
             let (proposer, sender) = cmd.proposer_and_sender(sender);
@@ -5280,6 +5402,7 @@ async fn main() {
                 opts.nns_public_key_pem_file,
                 sender,
             );
+            exit_if_simulated(&cmd);
             // Custom rendering to make it easier to debug your command
             if cmd.is_dry_run() {
                 let payload = cmd.payload(agent.url.clone()).await;
@@ -5365,14 +5488,24 @@ async fn main() {
                 )
                 .unwrap();
 
-            for key in keys {
-                let bytes = registry_client
-                    .get_value(&key, registry_client.get_latest_version())
-                    .unwrap()
-                    .unwrap();
-                let hostos_version_record = HostOsVersionRecord::decode(&bytes[..])
-                    .expect("Error decoding HostOsVersionRecord from registry");
-                println!("{}", hostos_version_record.hostos_version_id);
+            let hostos_versions: Vec<_> = keys
+                .iter()
+                .map(|key| {
+                    let bytes = registry_client
+                        .get_value(key, registry_client.get_latest_version())
+                        .unwrap()
+                        .unwrap();
+                    HostOsVersionRecord::decode(&bytes[..])
+                        .expect("Error decoding HostOsVersionRecord from registry")
+                        .hostos_version_id
+                })
+                .collect();
+            if opts.json {
+                print_json(&hostos_versions);
+            } else {
+                for hostos_version_id in hostos_versions {
+                    println!("{}", hostos_version_id);
+                }
             }
         }
     }
@@ -5410,7 +5543,18 @@ fn print_value<T: Debug + serde::Serialize>(key: &String, version: u64, value: T
     }
 }
 
+/// Prints `value` as pretty JSON on stdout.
+fn print_json<T: Serialize>(value: &T) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("Failed to serialize value to JSON")
+    );
+}
+
 /// Fetches the last value stored under `key` in the registry and prints it.
+///
+/// With `as_json`, the record is printed as a [`RegistryRecord`], following
+/// the encoding described in [`types`].
 async fn print_and_get_last_value<T: Message + Default + serde::Serialize>(
     key: Vec<u8>,
     registry: &RegistryCanister,
@@ -5419,7 +5563,22 @@ async fn print_and_get_last_value<T: Message + Default + serde::Serialize>(
     let value = registry.get_value(key.clone(), None).await;
     match value.clone() {
         Ok((bytes, version)) => {
-            if key.starts_with(b"subnet_record_") {
+            if as_json {
+                let key = std::str::from_utf8(&key)
+                    .expect("key is not a str")
+                    .to_string();
+                let value = RegistryValue::from_registry_bytes(&key, &bytes).unwrap_or_else(|| {
+                    let value = T::decode(&bytes[..]).expect("Error decoding value from registry.");
+                    RegistryValue::Other(
+                        serde_json::to_value(value).expect("Failed to serialize value to JSON"),
+                    )
+                });
+                print_json(&RegistryRecord {
+                    key,
+                    version,
+                    value,
+                });
+            } else if key.starts_with(b"subnet_record_") {
                 // subnet records are emitted as JSON
                 let value = SubnetRecordProto::decode(&bytes[..])
                     .expect("Error decoding value from registry.");
@@ -5511,31 +5670,10 @@ async fn print_and_get_last_value<T: Message + Default + serde::Serialize>(
                     );
                 }
             } else if key.starts_with(NODE_OPERATOR_RECORD_KEY_PREFIX.as_bytes()) {
-                #[derive(Debug, Serialize)]
-                pub struct NodeOperator {
-                    pub node_operator_principal_id: PrincipalId,
-                    pub node_allowance: u64,
-                    pub node_provider_principal_id: PrincipalId,
-                    pub dc_id: String,
-                    pub rewardable_nodes: std::collections::BTreeMap<String, u32>,
-                    pub ipv6: Option<String>,
-                }
-                let record = NodeOperatorRecord::decode(&bytes[..])
-                    .expect("Error decoding value from registry.");
-                let record = NodeOperator {
-                    node_operator_principal_id: PrincipalId::try_from(
-                        record.node_operator_principal_id,
-                    )
-                    .expect("Error decoding principal"),
-                    node_allowance: record.node_allowance,
-                    node_provider_principal_id: PrincipalId::try_from(
-                        record.node_provider_principal_id,
-                    )
-                    .expect("Error decoding principal"),
-                    dc_id: record.dc_id,
-                    rewardable_nodes: record.rewardable_nodes,
-                    ipv6: record.ipv6,
-                };
+                let record = NodeOperator::from(
+                    NodeOperatorRecord::decode(&bytes[..])
+                        .expect("Error decoding value from registry."),
+                );
                 print_value(
                     &std::str::from_utf8(&key)
                         .expect("key is not a str")
@@ -5593,6 +5731,17 @@ async fn propose_external_proposal_from_command<
 
     print_proposal(&payload, &cmd);
 
+    if let Some(local_store_path) = cmd.simulate() {
+        match simulate::simulate_registry_proposal(&local_store_path, nns_function, &payload) {
+            Ok(diff) => println!("{}", diff),
+            Err(e) => {
+                eprintln!("Simulation of {} failed: {}", cmd.title(), e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     if cmd.is_dry_run() {
         return;
     }
@@ -5817,12 +5966,17 @@ async fn get_firewall_rules_for_node(
     );
 }
 
-fn get_firewall_ruleset_hash(cmd: GetFirewallRulesetHashCmd) {
+fn get_firewall_ruleset_hash(cmd: GetFirewallRulesetHashCmd, as_json: bool) {
     let rule_file = String::from_utf8(read_file_fully(&cmd.rules_file)).unwrap();
     let rules: Vec<FirewallRule> = serde_json::from_str(&rule_file)
         .unwrap_or_else(|_| panic!("Failed to parse firewall rules"));
 
-    println!("{}", compute_firewall_ruleset_hash(&rules));
+    let hash = compute_firewall_ruleset_hash(&rules);
+    if as_json {
+        print_json(&serde_json::json!({ "hash": hash }));
+    } else {
+        println!("{}", hash);
+    }
 }
 
 /// Encapsulates a node/node operator id pair.
@@ -6079,6 +6233,7 @@ async fn propose_to_open_sns_token_swap(
             GOVERNANCE_CANISTER_ID,
             Some(proposer),
         ));
+        exit_if_simulated(&cmd);
         let payload = OpenSnsTokenSwap::from(&cmd);
         print_proposal(&payload, &cmd);
 
@@ -6156,6 +6311,7 @@ async fn propose_to_add_or_remove_node_provider(
             (Some(Change::ToRemove(node_provider)), msg.clone(), msg)
         }
     };
+    exit_if_simulated(&cmd);
     let payload = AddOrRemoveNodeProvider { change };
     print_proposal(&payload, &cmd);
 
@@ -6307,13 +6463,16 @@ async fn submit_root_proposal_to_upgrade_governance_canister(
 
 /// Returns the current list of pending root proposals to upgrade the governance
 /// canister.
-async fn get_pending_root_proposals_to_upgrade_governance_canister(agent: Agent) {
+async fn get_pending_root_proposals_to_upgrade_governance_canister(agent: Agent, as_json: bool) {
     let canister_client = RootCanisterClient(NnsCanisterClient::new(agent, ROOT_CANISTER_ID, None));
     let proposals = canister_client
         .get_pending_root_proposals_to_upgrade_governance_canister()
         .await;
 
-    if proposals.is_empty() {
+    if as_json {
+        let proposals: Vec<_> = proposals.into_iter().map(RootProposal::from).collect();
+        print_json(&proposals);
+    } else if proposals.is_empty() {
         println!("No currently pending root proposals.")
     } else {
         println!("Currently pending root proposals: ");
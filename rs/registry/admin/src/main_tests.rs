@@ -379,3 +379,101 @@ fn convert_from_flags_to_create_service_nervous_system_without_start_time() {
 
     assert_eq!(result.swap_parameters.unwrap().start_time, None);
 }
+
+#[test]
+fn registry_values_encode_principals_and_bytes_consistently() {
+    let node_operator_id = PrincipalId::new_user_test_id(1);
+    let node_record = NodeRecord {
+        node_operator_id: node_operator_id.to_vec(),
+        chip_id: vec![0xab, 0xcd],
+        ..Default::default()
+    };
+    let node_key = make_node_record_key(NodeId::from(PrincipalId::new_node_test_id(1)));
+
+    let value = RegistryValue::from_registry_bytes(&node_key, &node_record.encode_to_vec())
+        .expect("node records have a dedicated representation");
+    let json = serde_json::to_value(value).unwrap();
+
+    assert_eq!(json["node_operator_id"], node_operator_id.to_string());
+    assert_eq!(json["chip_id"], "abcd");
+
+    let public_key = PublicKey {
+        version: 0,
+        algorithm: ic_protobuf::registry::crypto::v1::AlgorithmId::Ed25519 as i32,
+        key_value: vec![1, 2, 3],
+        proof_data: None,
+        timestamp: Some(42),
+    };
+    let public_key_key = make_crypto_node_key(
+        NodeId::from(PrincipalId::new_node_test_id(1)),
+        KeyPurpose::NodeSigning,
+    );
+
+    let value = RegistryValue::from_registry_bytes(&public_key_key, &public_key.encode_to_vec())
+        .expect("public keys have a dedicated representation");
+    let json = serde_json::to_value(value).unwrap();
+
+    assert_eq!(json["algorithm"], "ALGORITHM_ID_ED25519");
+    assert_eq!(json["key_value"], "010203");
+    assert_eq!(json["timestamp"], 42);
+}
+
+#[tokio::test]
+async fn simulate_update_subnet_prints_registry_diff() {
+    use ic_nns_test_utils::registry::{invariant_compliant_mutation, TEST_ID};
+    use ic_registry_local_store::LocalStoreReader;
+
+    let local_store_dir = tempfile::tempdir().unwrap();
+    LocalStoreImpl::new(local_store_dir.path())
+        .store(
+            RegistryVersion::from(1),
+            invariant_compliant_mutation(0)
+                .into_iter()
+                .map(|mutation| KeyMutation {
+                    key: String::from_utf8(mutation.key).unwrap(),
+                    value: Some(mutation.value),
+                })
+                .collect(),
+        )
+        .unwrap();
+    let subnet_id = SubnetId::from(PrincipalId::new_subnet_test_id(TEST_ID));
+
+    let cmd = ProposeToUpdateSubnetCmd::parse_from([
+        "propose-to-update-subnet",
+        "--subnet",
+        &subnet_id.to_string(),
+        "--max-ingress-messages-per-block",
+        "5000",
+        "--simulate",
+        local_store_dir.path().to_str().unwrap(),
+    ]);
+    assert!(cmd.is_dry_run());
+    let payload = cmd
+        .payload(Url::parse("http://localhost:8080").unwrap())
+        .await;
+    let output = simulate::simulate_registry_proposal(
+        &cmd.simulate().unwrap(),
+        NnsFunction::UpdateConfigOfSubnet,
+        &payload,
+    )
+    .unwrap();
+
+    let diff: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let subnet_key = make_subnet_record_key(subnet_id);
+    assert_eq!(
+        diff.as_object().unwrap().keys().collect::<Vec<_>>(),
+        vec!["__version", &subnet_key]
+    );
+    assert_eq!(diff["__version"], 2);
+    assert_eq!(diff[&subnet_key]["max_ingress_messages_per_block"], 5000);
+    assert_eq!(diff[&subnet_key]["unit_delay_millis"], 600);
+
+    // The local store itself is left unchanged.
+    assert_eq!(
+        LocalStoreImpl::new(local_store_dir.path())
+            .get_changelog_since_version(RegistryVersion::from(0))
+            .unwrap()
+            .len(),
+        1
+    );
+}
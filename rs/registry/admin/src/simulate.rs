@@ -0,0 +1,245 @@
+//! Simulation of registry proposals against a local store snapshot.
+
+use candid::{CandidType, Decode, Encode};
+use ic_nns_governance::pb::v1::NnsFunction;
+use ic_registry_local_store::{KeyMutation, LocalStoreImpl, LocalStoreReader};
+use ic_registry_transport::pb::v1::{
+    registry_mutation::Type, RegistryAtomicMutateRequest, RegistryMutation,
+};
+use ic_types::RegistryVersion;
+use prost::Message;
+use registry_canister::{
+    pb::v1::{
+        registry_stable_storage::Version as ReprVersion, ChangelogEntry, RegistryStableStorage,
+    },
+    registry::Registry,
+};
+use serde::de::DeserializeOwned;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+/// Applies the proposal for `nns_function` with the given `payload` on top of
+/// the latest version of the registry local store at `local_store_path`, the
+/// same way the registry canister would when executing the proposal.
+///
+/// The mutation is computed and checked against all registry invariants by the
+/// registry canister code itself. On success, returns the resulting registry
+/// diff, pretty-printed in the same format as `ic-regedit show-diff`. The local
+/// store is not modified.
+pub(crate) fn simulate_registry_proposal<C: CandidType>(
+    local_store_path: &Path,
+    nns_function: NnsFunction,
+    payload: &C,
+) -> Result<String, String> {
+    let mut registry = registry_from_local_store(local_store_path)?;
+    let base_version = registry.latest_version();
+    let payload = Encode!(payload).map_err(|e| format!("Cannot encode payload: {}", e))?;
+
+    // The registry canister reports invalid proposals (including invariant
+    // violations) by panicking, so catch those and surface them as errors.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        apply_proposal(&mut registry, nns_function, &payload)
+    }));
+    panic::set_hook(default_hook);
+    result.map_err(|panic| {
+        let message = panic
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| panic.downcast_ref::<&str>().copied())
+            .unwrap_or("unknown error");
+        format!("The registry canister rejected the proposal: {}", message)
+    })??;
+
+    let mutations = registry
+        .get_changes_since(base_version, None)
+        .into_iter()
+        .map(|delta| {
+            let value = delta
+                .values
+                .into_iter()
+                .next()
+                .expect("Empty registry delta");
+            Ok(KeyMutation {
+                key: String::from_utf8(delta.key)
+                    .map_err(|e| format!("Invalid registry key: {}", e))?,
+                value: (!value.deletion_marker).then_some(value.value),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let diff =
+        ic_regedit::diff_local_store_with_mutations(local_store_path.to_path_buf(), mutations)
+            .map_err(|e| format!("Cannot compute the registry diff: {}", e))?;
+    Ok(serde_json::to_string_pretty(&diff).expect("Could not pretty print value."))
+}
+
+/// Loads the full changelog of the local store into a registry canister
+/// instance, without re-checking invariants on the historic versions.
+fn registry_from_local_store(local_store_path: &Path) -> Result<Registry, String> {
+    let changelog = LocalStoreImpl::new(local_store_path)
+        .get_changelog_since_version(RegistryVersion::from(0))
+        .map_err(|e| format!("Cannot read the registry local store: {}", e))?;
+
+    let changelog = changelog
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let mut encoded_mutation = vec![];
+            RegistryAtomicMutateRequest {
+                mutations: entry
+                    .into_iter()
+                    .map(|m| RegistryMutation {
+                        mutation_type: if m.value.is_some() {
+                            Type::Upsert
+                        } else {
+                            Type::Delete
+                        } as i32,
+                        key: m.key.into_bytes(),
+                        value: m.value.unwrap_or_default(),
+                    })
+                    .collect(),
+                preconditions: vec![],
+            }
+            .encode(&mut encoded_mutation)
+            .expect("Cannot encode registry mutation");
+            ChangelogEntry {
+                version: i as u64 + 1,
+                encoded_mutation,
+            }
+        })
+        .collect();
+
+    let mut registry = Registry::new();
+    registry.from_serializable_form(RegistryStableStorage {
+        version: ReprVersion::Version1 as i32,
+        deltas: vec![],
+        changelog,
+    });
+    Ok(registry)
+}
+
+/// Returns the error reported when `--simulate` is given for a proposal that
+/// cannot be simulated.
+pub(crate) fn simulation_unsupported(reason: &str) -> String {
+    format!("simulation unsupported: {}", reason)
+}
+
+fn decode<T: CandidType + DeserializeOwned>(payload: &[u8]) -> Result<T, String> {
+    Decode!(payload, T).map_err(|e| format!("Cannot decode payload: {}", e))
+}
+
+/// Runs the registry canister method that `nns_function` proposals execute.
+fn apply_proposal(
+    registry: &mut Registry,
+    nns_function: NnsFunction,
+    payload: &[u8],
+) -> Result<(), String> {
+    match nns_function {
+        NnsFunction::AssignNoid => registry.do_add_node_operator(decode(payload)?),
+        NnsFunction::AddNodeToSubnet => registry.do_add_nodes_to_subnet(decode(payload)?),
+        NnsFunction::RemoveNodesFromSubnet => {
+            registry.do_remove_nodes_from_subnet(decode(payload)?)
+        }
+        NnsFunction::ChangeSubnetMembership => {
+            registry.do_change_subnet_membership(decode(payload)?)
+        }
+        NnsFunction::UpdateElectedReplicaVersions => {
+            registry.do_update_elected_replica_versions(decode(payload)?)
+        }
+        NnsFunction::UpdateNodeOperatorConfig => {
+            registry.do_update_node_operator_config(decode(payload)?)
+        }
+        NnsFunction::UpdateSubnetReplicaVersion => {
+            registry.do_update_subnet_replica_version(decode(payload)?)
+        }
+        NnsFunction::AddHostOsVersion => registry.do_add_hostos_version(decode(payload)?),
+        NnsFunction::UpdateNodesHostOsVersion => {
+            registry.do_update_nodes_hostos_version(decode(payload)?)
+        }
+        NnsFunction::UpdateConfigOfSubnet => registry.do_update_subnet(decode(payload)?),
+        NnsFunction::ClearProvisionalWhitelist => registry.do_clear_provisional_whitelist(),
+        NnsFunction::SetFirewallConfig => registry.do_set_firewall_config(decode(payload)?),
+        NnsFunction::AddFirewallRules => registry.do_add_firewall_rules(decode(payload)?),
+        NnsFunction::RemoveFirewallRules => registry.do_remove_firewall_rules(decode(payload)?),
+        NnsFunction::UpdateFirewallRules => registry.do_update_firewall_rules(decode(payload)?),
+        NnsFunction::RemoveNodes => registry.do_remove_nodes(decode(payload)?),
+        NnsFunction::UpdateNodeRewardsTable => {
+            registry.do_update_node_rewards_table(decode(payload)?)
+        }
+        NnsFunction::AddOrRemoveDataCenters => {
+            registry.do_add_or_remove_data_centers(decode(payload)?)
+        }
+        NnsFunction::UpdateUnassignedNodesConfig => {
+            registry.do_update_unassigned_nodes_config(decode(payload)?)
+        }
+        NnsFunction::RemoveNodeOperators => registry.do_remove_node_operators(decode(payload)?),
+        NnsFunction::RerouteCanisterRanges => {
+            return registry.reroute_canister_ranges(decode(payload)?)
+        }
+        NnsFunction::PrepareCanisterMigration => {
+            return registry.prepare_canister_migration(decode(payload)?)
+        }
+        NnsFunction::CompleteCanisterMigration => {
+            return registry.complete_canister_migration(decode(payload)?)
+        }
+        NnsFunction::CreateSubnet | NnsFunction::RecoverSubnet => {
+            return Err(simulation_unsupported(&format!(
+                "{:?} proposals call other canisters",
+                nns_function
+            )))
+        }
+        _ => {
+            return Err(simulation_unsupported(&format!(
+                "{:?} proposals do not mutate the registry",
+                nns_function
+            )))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_registry_local_store::LocalStoreWriter;
+
+    #[test]
+    fn registry_from_local_store_replays_changelog() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let local_store = LocalStoreImpl::new(tmp_dir.path());
+        let mutation = |key: &str, value: Option<&[u8]>| KeyMutation {
+            key: key.to_string(),
+            value: value.map(|v| v.to_vec()),
+        };
+        local_store
+            .store(
+                RegistryVersion::from(1),
+                vec![mutation("a", Some(b"1")), mutation("b", Some(b"2"))],
+            )
+            .unwrap();
+        local_store
+            .store(RegistryVersion::from(2), vec![mutation("a", None)])
+            .unwrap();
+
+        let registry = registry_from_local_store(tmp_dir.path()).unwrap();
+
+        assert_eq!(registry.latest_version(), 2);
+        assert_eq!(registry.get(b"a", 1).unwrap().value, b"1".to_vec());
+        assert!(registry.get(b"a", 2).is_none());
+        assert_eq!(registry.get(b"b", 2).unwrap().value, b"2".to_vec());
+    }
+
+    #[test]
+    fn non_registry_proposals_cannot_be_simulated() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+
+        let err = simulate_registry_proposal(tmp_dir.path(), NnsFunction::NnsCanisterUpgrade, &())
+            .unwrap_err();
+
+        assert!(err.contains("simulation unsupported"), "{}", err);
+    }
+}
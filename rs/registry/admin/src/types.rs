@@ -1,15 +1,51 @@
 //! Helper types used by `ic-admin`.
+//!
+//! With `--json`, every read command prints exactly one JSON document on
+//! stdout. Commands that read a single registry record print a
+//! [`RegistryRecord`], i.e. `{"key": <string>, "version": <u64>, "value":
+//! <object>}`. Across all commands, values are encoded consistently:
+//!
+//! * principals (node, subnet, canister, node operator and node provider ids)
+//!   are strings in their textual representation;
+//! * all other binary data (keys, certificates, hashes, chip ids) are
+//!   lowercase hex strings;
+//! * protobuf enums are the names of their variants.
+//!
+//! Firewall rules and the node rewards table are printed in the format
+//! accepted by the corresponding proposal commands, with or without `--json`.
 
+use ic_nns_governance::pb::v1::{reward_node_provider::RewardMode, RewardNodeProviders};
+use ic_nns_handler_root::root_proposals::GovernanceUpgradeRootProposal;
 use ic_protobuf::registry::{
+    crypto::v1::{AlgorithmId, PublicKey as PublicKeyProto, X509PublicKeyCert},
+    node::v1::{ConnectionEndpoint, NodeRecord as NodeRecordProto},
+    node_operator::v1::NodeOperatorRecord as NodeOperatorRecordProto,
     provisional_whitelist::v1::ProvisionalWhitelist as ProvisionalWhitelistProto,
-    subnet::v1::{GossipConfig as GossipConfigProto, SubnetRecord as SubnetRecordProto},
+    routing_table::v1::{
+        CanisterMigrations as CanisterMigrationsProto, RoutingTable as RoutingTableProto,
+    },
+    subnet::v1::{
+        GossipConfig as GossipConfigProto, SubnetListRecord as SubnetListRecordProto,
+        SubnetRecord as SubnetRecordProto,
+    },
+};
+use ic_registry_keys::{
+    make_canister_migrations_record_key, make_provisional_whitelist_record_key,
+    make_routing_table_record_key, make_subnet_list_record_key, CRYPTO_RECORD_KEY_PREFIX,
+    CRYPTO_THRESHOLD_SIGNING_KEY_PREFIX, CRYPTO_TLS_CERT_KEY_PREFIX,
+    NODE_OPERATOR_RECORD_KEY_PREFIX, NODE_RECORD_KEY_PREFIX, SUBNET_RECORD_KEY_PREFIX,
 };
 use ic_registry_provisional_whitelist::ProvisionalWhitelist;
+use ic_registry_routing_table::{CanisterMigrations, RoutingTable};
 use ic_registry_subnet_features::{EcdsaConfig, SubnetFeatures};
 use ic_registry_subnet_type::SubnetType;
 use ic_types::{NodeId, PrincipalId};
+use prost::Message;
 use serde::Serialize;
-use std::convert::{From, TryFrom, TryInto};
+use std::{
+    collections::BTreeMap,
+    convert::{From, TryFrom, TryInto},
+};
 
 /// All or part of the registry
 #[derive(Default, Serialize)]
@@ -38,6 +74,86 @@ pub(crate) enum RegistryValue {
     Unknown,
     SubnetRecord(SubnetRecord),
     ProvisionalWhitelistRecord(ProvisionalWhitelistRecord),
+    SubnetListRecord(Vec<String>),
+    NodeRecord(NodeRecord),
+    NodeOperatorRecord(NodeOperatorRecord),
+    PublicKey(PublicKeyRecord),
+    TlsCertificate(TlsCertificateRecord),
+    RoutingTable(Vec<RoutingTableEntry>),
+    CanisterMigrations(Vec<CanisterMigrationsEntry>),
+    /// Records without principals or binary data, serialized as is.
+    Other(serde_json::Value),
+}
+
+impl RegistryValue {
+    /// Decodes the protobuf-encoded value stored under `key` into its user
+    /// friendly representation. Returns `None` for keys whose records have no
+    /// dedicated representation.
+    pub(crate) fn from_registry_bytes(key: &str, bytes: &[u8]) -> Option<Self> {
+        fn decode<T: Message + Default>(bytes: &[u8]) -> T {
+            T::decode(bytes).expect("Error decoding value from registry.")
+        }
+
+        let value = if key.starts_with(SUBNET_RECORD_KEY_PREFIX) {
+            Self::SubnetRecord(SubnetRecord::from(&decode::<SubnetRecordProto>(bytes)))
+        } else if key == make_provisional_whitelist_record_key() {
+            Self::ProvisionalWhitelistRecord(decode::<ProvisionalWhitelistProto>(bytes).into())
+        } else if key == make_subnet_list_record_key() {
+            Self::SubnetListRecord(
+                decode::<SubnetListRecordProto>(bytes)
+                    .subnets
+                    .iter()
+                    .map(|id| principal_to_string(id))
+                    .collect(),
+            )
+        } else if key.starts_with(NODE_OPERATOR_RECORD_KEY_PREFIX) {
+            Self::NodeOperatorRecord(decode::<NodeOperatorRecordProto>(bytes).into())
+        } else if key.starts_with(NODE_RECORD_KEY_PREFIX) {
+            Self::NodeRecord(decode::<NodeRecordProto>(bytes).into())
+        } else if key.starts_with(CRYPTO_RECORD_KEY_PREFIX)
+            || key.starts_with(CRYPTO_THRESHOLD_SIGNING_KEY_PREFIX)
+        {
+            Self::PublicKey(decode::<PublicKeyProto>(bytes).into())
+        } else if key.starts_with(CRYPTO_TLS_CERT_KEY_PREFIX) {
+            Self::TlsCertificate(decode::<X509PublicKeyCert>(bytes).into())
+        } else if key == make_routing_table_record_key() {
+            let routing_table = RoutingTable::try_from(decode::<RoutingTableProto>(bytes))
+                .expect("Error decoding routing table.");
+            Self::RoutingTable(
+                routing_table
+                    .iter()
+                    .map(|(range, subnet_id)| RoutingTableEntry {
+                        start_canister_id: range.start.to_string(),
+                        end_canister_id: range.end.to_string(),
+                        subnet_id: subnet_id.to_string(),
+                    })
+                    .collect(),
+            )
+        } else if key == make_canister_migrations_record_key() {
+            let migrations = CanisterMigrations::try_from(decode::<CanisterMigrationsProto>(bytes))
+                .expect("Error decoding canister migrations.");
+            Self::CanisterMigrations(
+                migrations
+                    .iter()
+                    .map(|(range, trace)| CanisterMigrationsEntry {
+                        start_canister_id: range.start.to_string(),
+                        end_canister_id: range.end.to_string(),
+                        trace: trace.iter().map(ToString::to_string).collect(),
+                    })
+                    .collect(),
+            )
+        } else {
+            return None;
+        };
+        Some(value)
+    }
+}
+
+/// Returns the textual representation of the principal encoded in `bytes`.
+pub(crate) fn principal_to_string(bytes: &[u8]) -> String {
+    PrincipalId::try_from(bytes)
+        .expect("Error decoding principal")
+        .to_string()
 }
 
 /// User-friendly representation of a v1::SubnetRecord. Only difference is that
@@ -127,3 +243,204 @@ impl From<ProvisionalWhitelistProto> for ProvisionalWhitelistRecord {
         }
     }
 }
+
+/// User-friendly representation of a v1::NodeRecord.
+#[derive(Serialize)]
+pub(crate) struct NodeRecord {
+    pub xnet: Option<ConnectionEndpoint>,
+    pub http: Option<ConnectionEndpoint>,
+    pub p2p_flow_endpoints: Vec<ConnectionEndpoint>,
+    pub node_operator_id: String,
+    pub chip_id: Option<String>,
+    pub hostos_version_id: Option<String>,
+}
+
+impl From<NodeRecordProto> for NodeRecord {
+    fn from(value: NodeRecordProto) -> Self {
+        Self {
+            xnet: value.xnet,
+            http: value.http,
+            p2p_flow_endpoints: value
+                .p2p_flow_endpoints
+                .into_iter()
+                .filter_map(|flow| flow.endpoint)
+                .collect(),
+            node_operator_id: principal_to_string(&value.node_operator_id),
+            chip_id: (!value.chip_id.is_empty()).then(|| hex::encode(value.chip_id)),
+            hostos_version_id: value.hostos_version_id,
+        }
+    }
+}
+
+/// User-friendly representation of a v1::NodeOperatorRecord.
+#[derive(Debug, Serialize)]
+pub(crate) struct NodeOperatorRecord {
+    pub node_operator_principal_id: String,
+    pub node_allowance: u64,
+    pub node_provider_principal_id: String,
+    pub dc_id: String,
+    pub rewardable_nodes: BTreeMap<String, u32>,
+    pub ipv6: Option<String>,
+}
+
+impl From<NodeOperatorRecordProto> for NodeOperatorRecord {
+    fn from(value: NodeOperatorRecordProto) -> Self {
+        Self {
+            node_operator_principal_id: principal_to_string(&value.node_operator_principal_id),
+            node_allowance: value.node_allowance,
+            node_provider_principal_id: principal_to_string(&value.node_provider_principal_id),
+            dc_id: value.dc_id,
+            rewardable_nodes: value.rewardable_nodes,
+            ipv6: value.ipv6,
+        }
+    }
+}
+
+/// User-friendly representation of a v1::PublicKey. The key and the proof
+/// of possession are hex encoded.
+#[derive(Serialize)]
+pub(crate) struct PublicKeyRecord {
+    pub version: u32,
+    pub algorithm: String,
+    pub key_value: String,
+    pub proof_data: Option<String>,
+    pub timestamp: Option<u64>,
+}
+
+impl From<PublicKeyProto> for PublicKeyRecord {
+    fn from(value: PublicKeyProto) -> Self {
+        Self {
+            version: value.version,
+            algorithm: AlgorithmId::from_i32(value.algorithm)
+                .map(|algorithm| algorithm.as_str_name().to_string())
+                .unwrap_or_else(|| value.algorithm.to_string()),
+            key_value: hex::encode(value.key_value),
+            proof_data: value.proof_data.map(hex::encode),
+            timestamp: value.timestamp,
+        }
+    }
+}
+
+/// User-friendly representation of a v1::X509PublicKeyCert. The DER encoded
+/// certificate is hex encoded.
+#[derive(Serialize)]
+pub(crate) struct TlsCertificateRecord {
+    pub certificate_der: String,
+}
+
+impl From<X509PublicKeyCert> for TlsCertificateRecord {
+    fn from(value: X509PublicKeyCert) -> Self {
+        Self {
+            certificate_der: hex::encode(value.certificate_der),
+        }
+    }
+}
+
+/// A range of canister ids (both ends inclusive) and the subnet it is
+/// assigned to.
+#[derive(Serialize)]
+pub(crate) struct RoutingTableEntry {
+    pub start_canister_id: String,
+    pub end_canister_id: String,
+    pub subnet_id: String,
+}
+
+/// A range of canister ids (both ends inclusive) being migrated, and the
+/// subnets it is migrated through.
+#[derive(Serialize)]
+pub(crate) struct CanisterMigrationsEntry {
+    pub start_canister_id: String,
+    pub end_canister_id: String,
+    pub trace: Vec<String>,
+}
+
+/// The rewards to be paid to a single node provider.
+#[derive(Serialize)]
+pub(crate) struct NodeProviderReward {
+    pub node_provider_id: Option<String>,
+    pub amount_e8s: u64,
+    /// The hex encoded account the reward is minted to, if paid to an account.
+    pub reward_to_account: Option<String>,
+    /// The dissolve delay of the neuron the reward is paid to, if paid to a
+    /// neuron.
+    pub reward_to_neuron_dissolve_delay_seconds: Option<u64>,
+}
+
+/// User-friendly representation of the monthly node provider rewards.
+#[derive(Serialize)]
+pub(crate) struct MonthlyNodeProviderRewards {
+    pub rewards: Vec<NodeProviderReward>,
+}
+
+impl From<RewardNodeProviders> for MonthlyNodeProviderRewards {
+    fn from(value: RewardNodeProviders) -> Self {
+        Self {
+            rewards: value
+                .rewards
+                .into_iter()
+                .map(|reward| {
+                    let (reward_to_account, reward_to_neuron_dissolve_delay_seconds) =
+                        match reward.reward_mode {
+                            Some(RewardMode::RewardToAccount(r)) => {
+                                (r.to_account.map(|a| hex::encode(a.hash)), None)
+                            }
+                            Some(RewardMode::RewardToNeuron(r)) => {
+                                (None, Some(r.dissolve_delay_seconds))
+                            }
+                            None => (None, None),
+                        };
+                    NodeProviderReward {
+                        node_provider_id: reward
+                            .node_provider
+                            .and_then(|p| p.id)
+                            .map(|id| id.to_string()),
+                        amount_e8s: reward.amount_e8s,
+                        reward_to_account,
+                        reward_to_neuron_dissolve_delay_seconds,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The ballot of a single node operator on a root proposal.
+#[derive(Serialize)]
+pub(crate) struct RootProposalBallotEntry {
+    pub node_operator_id: String,
+    pub ballot: String,
+}
+
+/// User-friendly representation of a pending root proposal to upgrade the
+/// governance canister. The wasm hashes are hex encoded.
+#[derive(Serialize)]
+pub(crate) struct RootProposal {
+    pub nns_subnet_id: String,
+    pub proposer: String,
+    pub current_wasm_sha256: String,
+    pub proposed_wasm_sha256: String,
+    pub subnet_membership_registry_version: u64,
+    pub node_operator_ballots: Vec<RootProposalBallotEntry>,
+    pub submission_timestamp_seconds: u64,
+}
+
+impl From<GovernanceUpgradeRootProposal> for RootProposal {
+    fn from(value: GovernanceUpgradeRootProposal) -> Self {
+        Self {
+            nns_subnet_id: value.nns_subnet_id.to_string(),
+            proposer: value.proposer.to_string(),
+            current_wasm_sha256: hex::encode(value.current_wasm_sha),
+            proposed_wasm_sha256: hex::encode(value.proposed_wasm_sha),
+            subnet_membership_registry_version: value.subnet_membership_registry_version,
+            node_operator_ballots: value
+                .node_operator_ballots
+                .into_iter()
+                .map(|(node_operator_id, ballot)| RootProposalBallotEntry {
+                    node_operator_id: node_operator_id.to_string(),
+                    ballot: format!("{:?}", ballot),
+                })
+                .collect(),
+            submission_timestamp_seconds: value.submission_timestamp_seconds,
+        }
+    }
+}
@@ -98,6 +98,12 @@ pub fn make_canister_migrations_record_key() -> String {
     "canister_migrations".to_string()
 }
 
+/// Returns the only key whose payload is the `RateLimitingRulesRecord` applied
+/// by the boundary nodes.
+pub fn make_boundary_node_rate_limiting_rules_key() -> String {
+    "boundary_node_rate_limiting_rules".to_string()
+}
+
 // TODO: Remove when all subnets are upgraded with IC-1026
 pub fn make_firewall_config_record_key() -> String {
     "firewall_config".to_string()
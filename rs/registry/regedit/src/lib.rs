@@ -11,8 +11,9 @@ mod tests;
 use anyhow::{anyhow, Result};
 use args::{universal_projection, Command, RegistrySpec, SourceSpec, VersionSpec};
 use ic_base_types::RegistryVersion;
+use ic_registry_client::client::RegistryTransportRecord;
 use ic_registry_local_store::{
-    changelog_to_compact_delta, KeyMutation, LocalStoreImpl, LocalStoreWriter,
+    changelog_to_compact_delta, ChangelogEntry, KeyMutation, LocalStoreImpl, LocalStoreWriter,
 };
 use normalization::NormalizedSnapshot;
 use serde_json::Value;
@@ -145,3 +146,31 @@ pub fn load_registry_local_store(local_store_path: PathBuf) -> Result<Value> {
         projection: universal_projection(),
     })
 }
+
+/// Returns the diff, in the same format as the `show-diff` command, that
+/// results from applying `mutations` on top of the latest version of the
+/// local store at `local_store_path`. The local store is not modified.
+pub fn diff_local_store_with_mutations(
+    local_store_path: PathBuf,
+    mutations: ChangelogEntry,
+) -> Result<Value> {
+    let (mut records, latest_version) =
+        source::get_changelog(SourceSpec::LocalStore(local_store_path))?;
+    let base_snapshot = snapshot::changelog_to_snapshot(
+        (records.clone(), latest_version),
+        VersionSpec::RelativeToLatest(0),
+    )?;
+
+    let new_version = latest_version + RegistryVersion::from(1);
+    records.extend(mutations.into_iter().map(|m| RegistryTransportRecord {
+        key: m.key,
+        version: new_version,
+        value: m.value,
+    }));
+    let new_snapshot =
+        snapshot::changelog_to_snapshot((records, new_version), VersionSpec::RelativeToLatest(0))?;
+
+    let diff = diff::make_diff(base_snapshot, new_snapshot)?;
+    let (normalized_diff, _) = normalization::normalize(diff.0);
+    Ok(normalized_diff.0)
+}
@@ -2,7 +2,7 @@
 use crate::{
     args::{universal_projection, Command, RegistrySpec, SourceSpec, VersionSpec},
     diff::DELETED_MARKER,
-    execute_command, normalization,
+    diff_local_store_with_mutations, execute_command, normalization,
     snapshot::SPECIAL_FIELD_PREFIX,
 };
 use ic_prep_lib::{
@@ -11,6 +11,7 @@ use ic_prep_lib::{
     prep_state_directory::IcPrepStateDir,
     subnet_configuration::{SubnetConfig, SubnetRunningState},
 };
+use ic_registry_local_store::KeyMutation;
 use ic_registry_provisional_whitelist::ProvisionalWhitelist;
 use ic_registry_subnet_type::SubnetType;
 use std::{
@@ -110,6 +111,54 @@ fn adding_deleting_values_shows_up_in_diff() {
     assert_eq!(expected_snapshot.0, final_snapshot);
 }
 
+#[test]
+fn diff_with_mutations_matches_show_diff() {
+    let (_guard, ic_prep_dir) = run_ic_prep();
+    let registry_spec = local_store_latest_snapshot(ic_prep_dir.registry_local_store_path());
+    let snapshot = execute_command(Command::Snapshot {
+        registry_spec: registry_spec.clone(),
+        projection: universal_projection(),
+    })
+    .unwrap();
+
+    let removed_key = filter_special_keys(snapshot.as_object().unwrap().keys().cloned().collect())
+        .pop()
+        .unwrap();
+    let new_key = "a_key_that_does_not_exist".to_string();
+    let arbitrary_bytes: Vec<u8> = (b'A'..b'z').collect();
+
+    let diff = diff_local_store_with_mutations(
+        ic_prep_dir.registry_local_store_path(),
+        vec![
+            KeyMutation {
+                key: removed_key.clone(),
+                value: None,
+            },
+            KeyMutation {
+                key: new_key.clone(),
+                value: Some(arbitrary_bytes.clone()),
+            },
+        ],
+    )
+    .unwrap();
+
+    let mut expected_snapshot = snapshot;
+    let obj = expected_snapshot.as_object_mut().unwrap();
+    obj.remove(&removed_key);
+    let mut arbitrary_obj = "(binary-data)".to_string();
+    arbitrary_bytes
+        .iter()
+        .for_each(|x| arbitrary_obj.push_str(&format!("{:02x}", x)));
+    obj.insert(new_key, serde_json::to_value(&arbitrary_obj).unwrap());
+    let expected_diff = execute_command(Command::ShowDiff {
+        registry_spec,
+        snapshot: expected_snapshot,
+    })
+    .unwrap();
+
+    assert_eq!(diff, expected_diff);
+}
+
 pub fn local_store_latest_snapshot(path: PathBuf) -> RegistrySpec {
     let source = SourceSpec::LocalStore(path);
     let version = VersionSpec::RelativeToLatest(0);
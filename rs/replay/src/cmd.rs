@@ -39,6 +39,13 @@ pub struct ReplayToolArgs {
     #[clap(long)]
     /// The replay will stop at this height and make a checkpoint.
     pub replay_until_height: Option<u64>,
+
+    /// Comma-separated heights at which the replay pauses to inspect the state
+    /// interactively: run queries, diff it against the previous state and list
+    /// the messages of the executed batch. Only applies when replaying the
+    /// consensus pool.
+    #[clap(long, use_value_delimiter = true)]
+    pub inspect_heights: Vec<u64>,
 }
 
 #[derive(Clone, Parser)]
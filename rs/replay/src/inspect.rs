@@ -0,0 +1,162 @@
+//! Interactive inspection of the states computed during a replay.
+//!
+//! When the replay is started with `--inspect-heights`, it pauses after
+//! executing the batch at each of the given heights and reads commands from
+//! stdin until told to continue.
+
+use ic_state_manager::tree_diff::Changes;
+use ic_types::{CanisterId, PrincipalId};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// The help text listing the commands available at a paused height.
+pub(crate) const HELP: &str = "\
+Commands:
+  query <canister_id> <method> [<hex_payload>]  Run a query against the state at this height.
+  diff                                          Show per-canister changes since the previous height.
+  messages                                      Show the messages executed in this height's batch.
+  continue                                      Continue to the next inspected height.
+  finish                                        Replay to the end without pausing again.
+  help                                          Show this help.";
+
+/// A command entered at a paused height.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Command {
+    Query {
+        canister_id: CanisterId,
+        method_name: String,
+        payload: Vec<u8>,
+    },
+    Diff,
+    Messages,
+    Continue,
+    Finish,
+    Help,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.as_slice() {
+            ["query", canister_id, method_name, rest @ ..] => {
+                let canister_id = CanisterId::from_str(canister_id)
+                    .map_err(|err| format!("Invalid canister id {}: {}", canister_id, err))?;
+                let payload = match rest {
+                    [] => Vec::new(),
+                    [hex_payload] => hex::decode(hex_payload)
+                        .map_err(|err| format!("Invalid hex payload: {}", err))?,
+                    _ => return Err("Too many arguments to `query`".to_string()),
+                };
+                Ok(Command::Query {
+                    canister_id,
+                    method_name: method_name.to_string(),
+                    payload,
+                })
+            }
+            ["diff"] => Ok(Command::Diff),
+            ["messages"] => Ok(Command::Messages),
+            [] | ["continue"] => Ok(Command::Continue),
+            ["finish"] => Ok(Command::Finish),
+            ["help"] => Ok(Command::Help),
+            _ => Err(format!("Unknown command: {}", s.trim())),
+        }
+    }
+}
+
+/// The group under which changes that don't belong to a canister are listed
+/// by [`changes_by_canister`].
+pub(crate) const SUBNET_CHANGES: &str = "subnet";
+
+/// Groups the changes between two state trees by the canister they belong to.
+/// Changes outside of the `/canister` subtree are grouped under
+/// [`SUBNET_CHANGES`].
+pub(crate) fn changes_by_canister(changes: &Changes) -> BTreeMap<String, Changes> {
+    let mut result: BTreeMap<String, Changes> = BTreeMap::new();
+    for (path, change) in changes.iter() {
+        let group = match path.as_slice() {
+            [root, canister_id, ..] if root.as_bytes() == b"canister" => {
+                match PrincipalId::try_from(canister_id.as_bytes()) {
+                    Ok(principal_id) => principal_id.to_string(),
+                    Err(_) => hex::encode(canister_id.as_bytes()),
+                }
+            }
+            _ => SUBNET_CHANGES.to_string(),
+        };
+        result
+            .entry(group)
+            .or_default()
+            .insert(path.clone(), change.clone());
+    }
+    result
+}
+
+/// Renders a query reply: as Candid if it decodes as such, as hex otherwise.
+pub(crate) fn format_reply(bytes: &[u8]) -> String {
+    match candid::IDLArgs::from_bytes(bytes) {
+        Ok(args) => args.to_string(),
+        Err(_) => hex::encode(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_crypto_tree_hash::{Label, Path};
+    use ic_state_manager::tree_diff::Change;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            Command::from_str("query rwlgt-iiaaa-aaaaa-aaaaa-cai get_value 4449444c0000"),
+            Ok(Command::Query {
+                canister_id: CanisterId::from_str("rwlgt-iiaaa-aaaaa-aaaaa-cai").unwrap(),
+                method_name: "get_value".to_string(),
+                payload: vec![0x44, 0x49, 0x44, 0x4c, 0x00, 0x00],
+            })
+        );
+        assert_eq!(
+            Command::from_str("query rwlgt-iiaaa-aaaaa-aaaaa-cai get_latest_version"),
+            Ok(Command::Query {
+                canister_id: CanisterId::from_str("rwlgt-iiaaa-aaaaa-aaaaa-cai").unwrap(),
+                method_name: "get_latest_version".to_string(),
+                payload: vec![],
+            })
+        );
+        assert_eq!(Command::from_str(" diff \n"), Ok(Command::Diff));
+        assert_eq!(Command::from_str("messages"), Ok(Command::Messages));
+        assert_eq!(Command::from_str("\n"), Ok(Command::Continue));
+        assert_eq!(Command::from_str("finish"), Ok(Command::Finish));
+        assert!(Command::from_str("query rwlgt-iiaaa-aaaaa-aaaaa-cai").is_err());
+        assert!(Command::from_str("query not-a-principal method").is_err());
+        assert!(Command::from_str("query rwlgt-iiaaa-aaaaa-aaaaa-cai method xyz").is_err());
+        assert!(Command::from_str("rewind").is_err());
+    }
+
+    #[test]
+    fn changes_are_grouped_by_canister() {
+        let canister_id = CanisterId::from_u64(7);
+        let canister_path = |leaf: &str| -> Path {
+            vec![
+                Label::from("canister"),
+                Label::from(canister_id.get_ref().as_slice()),
+                Label::from(leaf),
+            ]
+            .into_iter()
+            .collect()
+        };
+        let subnet_path: Path = vec![Label::from("time")].into_iter().collect();
+
+        let mut changes = Changes::new();
+        changes.insert(canister_path("certified_data"), Change::DeleteSubtree);
+        changes.insert(canister_path("module_hash"), Change::InsertEmptyFork);
+        changes.insert(subnet_path, Change::DeleteSubtree);
+
+        let grouped = changes_by_canister(&changes);
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[&canister_id.to_string()].len(), 2);
+        assert_eq!(grouped[SUBNET_CHANGES].len(), 1);
+    }
+}
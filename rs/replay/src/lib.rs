@@ -36,6 +36,7 @@ use std::rc::Rc;
 mod backup;
 pub mod cmd;
 pub mod ingress;
+mod inspect;
 mod mocks;
 pub mod player;
 mod validator;
@@ -58,6 +59,7 @@ mod validator;
 ///     config: Some(PathBuf::from("/path/to/ic.json5")),
 ///     canister_caller_id: None,
 ///     replay_until_height: None,
+///     inspect_heights: vec![],
 ///     data_root: None,
 ///     subcmd: Some(SubCommand::RestoreFromBackup(RestoreFromBackupCmd {
 ///         registry_local_store_path: PathBuf::from("/path/to/ic_registry_local_store"),
@@ -147,9 +149,9 @@ pub fn replay(args: ReplayToolArgs) -> ReplayResult {
                     "Target height cannot be used with any sub-command in subnet-recovery mode."
                 );
                 }
-                (_, target_height) => {
-                    Player::new(cfg, subnet_id).with_replay_target_height(target_height)
-                }
+                (_, target_height) => Player::new(cfg, subnet_id)
+                    .with_replay_target_height(target_height)
+                    .with_inspect_heights(args.inspect_heights),
             };
 
             if let Some(SubCommand::GetRecoveryCup(cmd)) = subcmd {
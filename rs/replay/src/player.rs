@@ -1,5 +1,6 @@
 use crate::backup::{cup_file_name, rename_file};
 use crate::ingress::IngressWithPrinter;
+use crate::inspect;
 use crate::{
     backup,
     validator::{InvalidArtifact, ReplayValidator},
@@ -47,7 +48,11 @@ use ic_registry_transport::{
     serialize_get_value_request,
 };
 use ic_replicated_state::ReplicatedState;
-use ic_state_manager::StateManagerImpl;
+use ic_state_manager::{
+    tree_diff::{diff, PrettyPrintedChanges},
+    tree_hash::hash_state,
+    StateManagerImpl,
+};
 use ic_types::batch::BatchMessages;
 use ic_types::consensus::certification::CertificationShare;
 use ic_types::malicious_flags::MaliciousFlags;
@@ -57,8 +62,8 @@ use ic_types::{
     ingress::{IngressState, IngressStatus, WasmResult},
     messages::UserQuery,
     time::current_time,
-    CryptoHashOfState, Height, PrincipalId, Randomness, RegistryVersion, ReplicaVersion, SubnetId,
-    Time, UserId,
+    CanisterId, CryptoHashOfState, Height, PrincipalId, Randomness, RegistryVersion,
+    ReplicaVersion, SubnetId, Time, UserId,
 };
 use ic_types::{
     consensus::CatchUpContentProtobufBytes,
@@ -127,6 +132,8 @@ pub struct Player {
     // The target height until which the state will be replayed.
     // None means finalized height.
     replay_target_height: Option<u64>,
+    // The heights at which the replay pauses for interactive inspection.
+    inspect_heights: Vec<Height>,
 }
 
 impl Player {
@@ -285,6 +292,8 @@ impl Player {
         let execution_service = ExecutionServices::setup_execution(
             log.clone(),
             &metrics_registry,
+            // The replay never proposes blocks, so the node ID is irrelevant.
+            NodeId::from(PrincipalId::new_anonymous()),
             subnet_id,
             subnet_type,
             subnet_config.scheduler_config,
@@ -346,6 +355,7 @@ impl Player {
             _async_log_guard,
             tmp_dir: None,
             replay_target_height: None,
+            inspect_heights: Vec::new(),
         }
     }
 
@@ -355,6 +365,17 @@ impl Player {
         self
     }
 
+    /// Set the heights at which the replay of the consensus pool pauses for
+    /// interactive inspection of the state.
+    pub fn with_inspect_heights(mut self, inspect_heights: Vec<u64>) -> Self {
+        let mut inspect_heights: Vec<Height> =
+            inspect_heights.into_iter().map(Height::from).collect();
+        inspect_heights.sort();
+        inspect_heights.dedup();
+        self.inspect_heights = inspect_heights;
+        self
+    }
+
     /// In case a consensus pool was supplied, replay past finalized but
     /// un-executed blocks by delivering ingress messages for execution,
     /// and make a full checkpoint of the latest state when they all finish.
@@ -455,6 +476,21 @@ impl Player {
             invalid_artifacts.iter().for_each(|a| println!("{:?}", a));
         }
 
+        // Pause at each of the inspected heights that is yet to be executed.
+        let expected_batch_height = self.message_routing.expected_batch_height();
+        for &height in self
+            .inspect_heights
+            .iter()
+            .filter(|h| expected_batch_height <= **h && Some(**h) <= target_height)
+        {
+            let last_batch_height =
+                self.deliver_batches(self.message_routing.as_ref(), pool_reader, Some(height));
+            self.wait_for_state(last_batch_height);
+            if last_batch_height != height || !self.inspect(pool_reader, height) {
+                break;
+            }
+        }
+
         let last_batch_height =
             self.deliver_batches(self.message_routing.as_ref(), pool_reader, target_height);
         self.wait_for_state(last_batch_height);
@@ -557,6 +593,95 @@ impl Player {
             })
     }
 
+    // Reads and runs inspection commands for the state at the given height
+    // from stdin. Returns `false` if the replay should not pause again.
+    fn inspect(&self, pool: &PoolReader<'_>, height: Height) -> bool {
+        use std::io::{stdin, stdout, Write};
+
+        println!("Paused at height {}. Type `help` for commands.", height);
+        loop {
+            print!("[{}]> ", height);
+            let _ = stdout().flush();
+            let mut line = String::new();
+            match stdin().read_line(&mut line) {
+                // Stop pausing on end of input.
+                Ok(0) => return false,
+                Ok(_) => {}
+                Err(err) => {
+                    println!("Failed to read command: {}", err);
+                    return false;
+                }
+            }
+            match line.parse::<inspect::Command>() {
+                Ok(inspect::Command::Query {
+                    canister_id,
+                    method_name,
+                    payload,
+                }) => self.inspect_query(height, canister_id, method_name, payload),
+                Ok(inspect::Command::Diff) => self.inspect_diff(height),
+                Ok(inspect::Command::Messages) => inspect_messages(pool, height),
+                Ok(inspect::Command::Continue) => return true,
+                Ok(inspect::Command::Finish) => return false,
+                Ok(inspect::Command::Help) => println!("{}", inspect::HELP),
+                Err(err) => println!("{}\n{}", err, inspect::HELP),
+            }
+        }
+    }
+
+    // Runs a query against the state at the given height and prints the result.
+    fn inspect_query(
+        &self,
+        height: Height,
+        canister_id: CanisterId,
+        method_name: String,
+        method_payload: Vec<u8>,
+    ) {
+        let state = match self.state_manager.get_state_at(height) {
+            Ok(state) => state.take(),
+            Err(err) => {
+                println!("State at height {} is not available: {:?}", height, err);
+                return;
+            }
+        };
+        let query = UserQuery {
+            source: UserId::from(PrincipalId::new_anonymous()),
+            receiver: canister_id,
+            method_name,
+            method_payload,
+            ingress_expiry: (state.time() + Duration::from_secs(60)).as_nanos_since_unix_epoch(),
+            nonce: None,
+        };
+        match self.http_query_handler.query(query, state, Vec::new()) {
+            Ok(WasmResult::Reply(bytes)) => println!("Reply: {}", inspect::format_reply(&bytes)),
+            Ok(WasmResult::Reject(msg)) => println!("Rejected: {}", msg),
+            Err(err) => println!("Query failed: {:?}", err),
+        }
+    }
+
+    // Prints the per-canister changes between the states at the previous and
+    // the given height.
+    fn inspect_diff(&self, height: Height) {
+        let states = (
+            self.state_manager.get_state_at(height.decrement()),
+            self.state_manager.get_state_at(height),
+        );
+        let (previous, current) = match states {
+            (Ok(previous), Ok(current)) => (previous.take(), current.take()),
+            (Err(err), _) | (_, Err(err)) => {
+                println!("States to diff are not available: {:?}", err);
+                return;
+            }
+        };
+        let changes = diff(&hash_state(&previous), &hash_state(&current));
+        if changes.is_empty() {
+            println!("No changes since height {}", height.decrement());
+        }
+        for (group, changes) in inspect::changes_by_canister(&changes) {
+            println!("{}:", group);
+            print!("{}", PrettyPrintedChanges(&changes));
+        }
+    }
+
     // Blocks until the state at the given height is committed.
     fn wait_for_state(&self, height: Height) {
         loop {
@@ -1138,6 +1263,53 @@ fn find_malicious_nodes(
     malicious
 }
 
+// Prints the messages in the batch of the finalized block at the given height.
+fn inspect_messages(pool: &PoolReader<'_>, height: Height) {
+    let block = match pool.get_finalized_block(height) {
+        Some(block) => block,
+        None => {
+            println!("No finalized block at height {}", height);
+            return;
+        }
+    };
+    if block.payload.is_summary() {
+        println!(
+            "The block at height {} is a summary block without messages",
+            height
+        );
+        return;
+    }
+    let batch = &block.payload.as_ref().as_data().batch;
+    let messages = match batch.clone().into_messages() {
+        Ok(messages) => messages,
+        Err(err) => {
+            println!("Failed to decode the batch payload: {:?}", err);
+            return;
+        }
+    };
+    println!("Ingress messages: {}", messages.signed_ingress_msgs.len());
+    for ingress in &messages.signed_ingress_msgs {
+        println!(
+            "  {} {} -> {}.{}",
+            ingress.id(),
+            ingress.sender(),
+            ingress.canister_id(),
+            ingress.method_name()
+        );
+    }
+    println!(
+        "Certified stream slices: {}",
+        messages.certified_stream_slices.len()
+    );
+    for (subnet_id, slice) in &messages.certified_stream_slices {
+        println!("  from {}: {} bytes", subnet_id, slice.payload.len());
+    }
+    println!(
+        "Bitcoin adapter responses: {}",
+        messages.bitcoin_adapter_responses.len()
+    );
+}
+
 // Find all certification shares at the given heights and count which hashes occurred how many times. Shares created
 // by malicious nodes (those creating more than one share for the same height) are ignored while counting.
 //
@@ -158,6 +158,7 @@ pub fn construct_ic_stack(
     let execution_services = ExecutionServices::setup_execution(
         log.clone(),
         metrics_registry,
+        node_id,
         subnet_id,
         subnet_type,
         subnet_config.scheduler_config,
@@ -710,6 +710,14 @@ fn can_get_canister_information() {
                 ComputeAllocation::default().as_percent(),
                 None,
                 2592000,
+                None,
+                0,
+                0u128,
+                None,
+                0u128,
+                0u128,
+                0u128,
+                0u128,
                 0u128,
             )
         );
@@ -761,6 +769,14 @@ fn can_get_canister_information() {
                     ComputeAllocation::default().as_percent(),
                     None,
                     259200,
+                    None,
+                    0,
+                    0u128,
+                    None,
+                    0u128,
+                    0u128,
+                    0u128,
+                    0u128,
                     0u128,
                 ),
                 CanisterStatusResultV2::decode(&res).unwrap(),
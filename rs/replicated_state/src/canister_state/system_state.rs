@@ -301,6 +301,11 @@ pub struct SystemState {
     /// Resource reservation cycles.
     reserved_balance: Cycles,
 
+    /// The user-specified upper limit on `reserved_balance`. A resource
+    /// allocation that would push `reserved_balance` above it fails.
+    /// `None` means there is no limit.
+    reserved_balance_limit: Option<Cycles>,
+
     /// Tasks to execute before processing input messages.
     /// Currently the task queue is empty outside of execution rounds.
     pub task_queue: VecDeque<ExecutionTask>,
@@ -313,6 +318,57 @@ pub struct SystemState {
 
     /// Canister history.
     canister_history: CanisterHistory,
+
+    /// Query statistics aggregated across the subnet's nodes since the
+    /// canister was created.
+    pub total_query_stats: TotalQueryStats,
+
+    /// Upper limit on the size of the Wasm heap. Growing the heap beyond it
+    /// traps. `None` means the heap is only bounded by the Wasm memory cap.
+    pub wasm_memory_limit: Option<NumBytes>,
+
+    /// The `canister_on_low_wasm_memory` hook is scheduled once the free Wasm
+    /// heap, relative to `wasm_memory_limit`, drops below this threshold.
+    pub wasm_memory_threshold: NumBytes,
+
+    /// Whether the `canister_on_low_wasm_memory` hook should run.
+    on_low_wasm_memory_hook_status: OnLowWasmMemoryHookStatus,
+}
+
+/// The state of the `canister_on_low_wasm_memory` hook. The hook runs at most
+/// once each time the low memory condition becomes satisfied.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnLowWasmMemoryHookStatus {
+    #[default]
+    ConditionNotSatisfied,
+    Ready,
+    Executed,
+}
+
+impl From<OnLowWasmMemoryHookStatus> for pb::OnLowWasmMemoryHookStatus {
+    fn from(item: OnLowWasmMemoryHookStatus) -> Self {
+        match item {
+            OnLowWasmMemoryHookStatus::ConditionNotSatisfied => Self::ConditionNotSatisfied,
+            OnLowWasmMemoryHookStatus::Ready => Self::Ready,
+            OnLowWasmMemoryHookStatus::Executed => Self::Executed,
+        }
+    }
+}
+
+impl TryFrom<pb::OnLowWasmMemoryHookStatus> for OnLowWasmMemoryHookStatus {
+    type Error = ProxyDecodeError;
+
+    fn try_from(value: pb::OnLowWasmMemoryHookStatus) -> Result<Self, Self::Error> {
+        match value {
+            // Checkpoints written before the hook existed.
+            pb::OnLowWasmMemoryHookStatus::Unspecified
+            | pb::OnLowWasmMemoryHookStatus::ConditionNotSatisfied => {
+                Ok(OnLowWasmMemoryHookStatus::ConditionNotSatisfied)
+            }
+            pb::OnLowWasmMemoryHookStatus::Ready => Ok(OnLowWasmMemoryHookStatus::Ready),
+            pb::OnLowWasmMemoryHookStatus::Executed => Ok(OnLowWasmMemoryHookStatus::Executed),
+        }
+    }
 }
 
 /// A wrapper around the different canister statuses.
@@ -412,6 +468,10 @@ pub enum ExecutionTask {
     /// The task exists only within an execution round, it never gets serialized.
     GlobalTimer,
 
+    /// Canister low Wasm memory hook task.
+    /// The task exists only within an execution round, it never gets serialized.
+    OnLowWasmMemory,
+
     // A paused execution task exists only within an epoch (between
     // checkpoints). It is never serialized, and it turns into `AbortedExecution`
     // before the checkpoint or when there are too many long-running executions.
@@ -454,6 +514,7 @@ impl From<&ExecutionTask> for pb::ExecutionTask {
         match item {
             ExecutionTask::Heartbeat
             | ExecutionTask::GlobalTimer
+            | ExecutionTask::OnLowWasmMemory
             | ExecutionTask::PausedExecution(_)
             | ExecutionTask::PausedInstallCode(_) => {
                 panic!("Attempt to serialize ephemeral task: {:?}.", item);
@@ -481,6 +542,9 @@ impl From<&ExecutionTask> for pb::ExecutionTask {
                     CanisterMessageOrTask::Task(CanisterTask::GlobalTimer) => {
                         PbInput::Task(PbCanisterTask::Timer as i32)
                     }
+                    CanisterMessageOrTask::Task(CanisterTask::OnLowWasmMemory) => {
+                        PbInput::Task(PbCanisterTask::OnLowWasmMemory as i32)
+                    }
                 };
                 Self {
                     task: Some(pb::execution_task::Task::AbortedExecution(
@@ -556,6 +620,7 @@ impl TryFrom<pb::ExecutionTask> for ExecutionTask {
                             }
                             PbCanisterTask::Heartbeat => CanisterTask::Heartbeat,
                             PbCanisterTask::Timer => CanisterTask::GlobalTimer,
+                            PbCanisterTask::OnLowWasmMemory => CanisterTask::OnLowWasmMemory,
                         };
                         CanisterMessageOrTask::Task(task)
                     }
@@ -628,12 +693,82 @@ impl TryFrom<pb::CanisterHistory> for CanisterHistory {
     }
 }
 
+/// Running totals of the query statistics of a canister, as aggregated from
+/// the per-epoch statistics reported by the nodes of the subnet.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TotalQueryStats {
+    pub num_calls_total: u128,
+    pub num_instructions_total: u128,
+    pub ingress_payload_size_total: u128,
+    pub egress_payload_size_total: u128,
+}
+
+impl From<&TotalQueryStats> for pb::TotalQueryStats {
+    fn from(item: &TotalQueryStats) -> Self {
+        Self {
+            num_calls_total: item.num_calls_total.to_le_bytes().to_vec(),
+            num_instructions_total: item.num_instructions_total.to_le_bytes().to_vec(),
+            ingress_payload_size_total: item.ingress_payload_size_total.to_le_bytes().to_vec(),
+            egress_payload_size_total: item.egress_payload_size_total.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<pb::TotalQueryStats> for TotalQueryStats {
+    type Error = ProxyDecodeError;
+
+    fn try_from(value: pb::TotalQueryStats) -> Result<Self, Self::Error> {
+        // Missing totals are encoded as empty byte strings and decode to zero.
+        fn decode_u128(bytes: Vec<u8>, field: &'static str) -> Result<u128, ProxyDecodeError> {
+            if bytes.is_empty() {
+                return Ok(0);
+            }
+            let bytes: [u8; 16] =
+                bytes
+                    .try_into()
+                    .map_err(|bytes: Vec<u8>| ProxyDecodeError::ValueOutOfRange {
+                        typ: field,
+                        err: format!("expected 16 bytes, got {}", bytes.len()),
+                    })?;
+            Ok(u128::from_le_bytes(bytes))
+        }
+
+        Ok(Self {
+            num_calls_total: decode_u128(
+                value.num_calls_total,
+                "TotalQueryStats::num_calls_total",
+            )?,
+            num_instructions_total: decode_u128(
+                value.num_instructions_total,
+                "TotalQueryStats::num_instructions_total",
+            )?,
+            ingress_payload_size_total: decode_u128(
+                value.ingress_payload_size_total,
+                "TotalQueryStats::ingress_payload_size_total",
+            )?,
+            egress_payload_size_total: decode_u128(
+                value.egress_payload_size_total,
+                "TotalQueryStats::egress_payload_size_total",
+            )?,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct InsufficientCyclesError {
     pub requested: Cycles,
     pub available: Cycles,
 }
 
+/// Errors returned when moving cycles to the reserved balance.
+#[derive(Debug)]
+pub enum ReservationError {
+    /// The main balance is lower than the requested amount.
+    InsufficientCycles(InsufficientCyclesError),
+    /// The reserved balance would exceed `reserved_balance_limit`.
+    ReservedLimitExceeded { requested: Cycles, limit: Cycles },
+}
+
 impl SystemState {
     pub fn new_running(
         canister_id: CanisterId,
@@ -697,6 +832,7 @@ impl SystemState {
             cycles_balance: initial_cycles,
             ingress_induction_cycles_debit: Cycles::zero(),
             reserved_balance: Cycles::zero(),
+            reserved_balance_limit: None,
             memory_allocation: MemoryAllocation::BestEffort,
             freeze_threshold,
             status,
@@ -706,6 +842,10 @@ impl SystemState {
             global_timer: CanisterTimer::Inactive,
             canister_version: 0,
             canister_history: CanisterHistory::default(),
+            total_query_stats: TotalQueryStats::default(),
+            wasm_memory_limit: None,
+            wasm_memory_threshold: NumBytes::new(0),
+            on_low_wasm_memory_hook_status: OnLowWasmMemoryHookStatus::default(),
         }
     }
 
@@ -742,6 +882,11 @@ impl SystemState {
         global_timer: CanisterTimer,
         canister_version: u64,
         canister_history: CanisterHistory,
+        total_query_stats: TotalQueryStats,
+        wasm_memory_limit: Option<NumBytes>,
+        wasm_memory_threshold: NumBytes,
+        on_low_wasm_memory_hook_status: OnLowWasmMemoryHookStatus,
+        reserved_balance_limit: Option<Cycles>,
     ) -> Self {
         Self {
             controllers,
@@ -759,6 +904,11 @@ impl SystemState {
             global_timer,
             canister_version,
             canister_history,
+            total_query_stats,
+            wasm_memory_limit,
+            wasm_memory_threshold,
+            on_low_wasm_memory_hook_status,
+            reserved_balance_limit,
         }
     }
 
@@ -788,6 +938,58 @@ impl SystemState {
         self.reserved_balance
     }
 
+    /// Returns the user-specified upper limit on the reserved balance.
+    pub fn reserved_balance_limit(&self) -> Option<Cycles> {
+        self.reserved_balance_limit
+    }
+
+    /// Sets the user-specified upper limit on the reserved balance.
+    pub fn set_reserved_balance_limit(&mut self, limit: Option<Cycles>) {
+        self.reserved_balance_limit = limit;
+    }
+
+    pub fn on_low_wasm_memory_hook_status(&self) -> OnLowWasmMemoryHookStatus {
+        self.on_low_wasm_memory_hook_status
+    }
+
+    /// Returns true if the free Wasm heap, relative to `wasm_memory_limit`, is
+    /// below `wasm_memory_threshold` for a heap of the given size.
+    pub fn is_low_wasm_memory_hook_condition_satisfied(&self, wasm_memory_usage: NumBytes) -> bool {
+        match self.wasm_memory_limit {
+            Some(limit) => {
+                limit.get().saturating_sub(wasm_memory_usage.get())
+                    < self.wasm_memory_threshold.get()
+            }
+            None => false,
+        }
+    }
+
+    /// Updates the status of the `canister_on_low_wasm_memory` hook for a heap
+    /// of the given size: the hook becomes ready when the low memory condition
+    /// becomes satisfied and is reset when the condition no longer holds.
+    pub fn update_on_low_wasm_memory_hook_condition(&mut self, wasm_memory_usage: NumBytes) {
+        if !self.is_low_wasm_memory_hook_condition_satisfied(wasm_memory_usage) {
+            self.on_low_wasm_memory_hook_status = OnLowWasmMemoryHookStatus::ConditionNotSatisfied;
+        } else if self.on_low_wasm_memory_hook_status
+            == OnLowWasmMemoryHookStatus::ConditionNotSatisfied
+        {
+            self.on_low_wasm_memory_hook_status = OnLowWasmMemoryHookStatus::Ready;
+        }
+    }
+
+    /// Returns true if the `canister_on_low_wasm_memory` hook should be
+    /// scheduled.
+    pub fn is_low_wasm_memory_hook_ready(&self) -> bool {
+        self.on_low_wasm_memory_hook_status == OnLowWasmMemoryHookStatus::Ready
+    }
+
+    /// Records that the `canister_on_low_wasm_memory` hook has started
+    /// executing, so that it is not scheduled again until the condition is
+    /// reset.
+    pub fn on_low_wasm_memory_hook_executed(&mut self) {
+        self.on_low_wasm_memory_hook_status = OnLowWasmMemoryHookStatus::Executed;
+    }
+
     /// Records the given amount as debit that will be charged from the balance
     /// at some point in the future.
     ///
@@ -1256,17 +1458,29 @@ impl SystemState {
     }
 
     /// Moves the given amount of cycles from the main balance to the reserved balance.
-    /// Returns an error if the main balance is lower than the requested amount.
-    pub fn reserve_cycles(&mut self, amount: Cycles) -> Result<(), InsufficientCyclesError> {
+    /// Returns an error if the main balance is lower than the requested amount
+    /// or if the reserved balance would exceed `reserved_balance_limit`.
+    pub fn reserve_cycles(&mut self, amount: Cycles) -> Result<(), ReservationError> {
         if amount > self.cycles_balance {
-            Err(InsufficientCyclesError {
-                requested: amount,
-                available: self.cycles_balance,
-            })
-        } else {
-            self.cycles_balance -= amount;
-            self.reserved_balance += amount;
-            Ok(())
+            return Err(ReservationError::InsufficientCycles(
+                InsufficientCyclesError {
+                    requested: amount,
+                    available: self.cycles_balance,
+                },
+            ));
+        }
+        match self.reserved_balance_limit {
+            Some(limit) if self.reserved_balance + amount > limit => {
+                Err(ReservationError::ReservedLimitExceeded {
+                    requested: self.reserved_balance + amount,
+                    limit,
+                })
+            }
+            _ => {
+                self.cycles_balance -= amount;
+                self.reserved_balance += amount;
+                Ok(())
+            }
         }
     }
 
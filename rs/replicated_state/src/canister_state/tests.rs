@@ -6,7 +6,7 @@ use crate::canister_state::execution_state::CustomSection;
 use crate::canister_state::execution_state::CustomSectionType;
 use crate::canister_state::execution_state::WasmMetadata;
 use crate::canister_state::system_state::{
-    CanisterHistory, CyclesUseCase, MAX_CANISTER_HISTORY_CHANGES,
+    CanisterHistory, CyclesUseCase, OnLowWasmMemoryHookStatus, MAX_CANISTER_HISTORY_CHANGES,
 };
 use crate::CallOrigin;
 use crate::Memory;
@@ -764,3 +764,41 @@ fn canister_history_operations() {
         assert_eq!(canister_history.get_total_num_changes(), total_num_changes);
     }
 }
+
+#[test]
+fn on_low_wasm_memory_hook_runs_once_per_low_memory_episode() {
+    let mut system_state = CanisterStateFixture::new().canister_state.system_state;
+    let mib = |n: u64| NumBytes::from(n << 20);
+
+    // Without a Wasm memory limit the condition is never satisfied.
+    system_state.wasm_memory_threshold = mib(10);
+    system_state.update_on_low_wasm_memory_hook_condition(mib(1000));
+    assert!(!system_state.is_low_wasm_memory_hook_ready());
+
+    system_state.wasm_memory_limit = Some(mib(100));
+    system_state.update_on_low_wasm_memory_hook_condition(mib(50));
+    assert_eq!(
+        system_state.on_low_wasm_memory_hook_status(),
+        OnLowWasmMemoryHookStatus::ConditionNotSatisfied
+    );
+
+    system_state.update_on_low_wasm_memory_hook_condition(mib(95));
+    assert!(system_state.is_low_wasm_memory_hook_ready());
+
+    // Once executed, the hook is not scheduled again while memory stays low.
+    system_state.on_low_wasm_memory_hook_executed();
+    system_state.update_on_low_wasm_memory_hook_condition(mib(99));
+    assert_eq!(
+        system_state.on_low_wasm_memory_hook_status(),
+        OnLowWasmMemoryHookStatus::Executed
+    );
+
+    // Freeing memory resets the hook.
+    system_state.update_on_low_wasm_memory_hook_condition(mib(50));
+    assert_eq!(
+        system_state.on_low_wasm_memory_hook_status(),
+        OnLowWasmMemoryHookStatus::ConditionNotSatisfied
+    );
+    system_state.update_on_low_wasm_memory_hook_condition(mib(91));
+    assert!(system_state.is_low_wasm_memory_hook_ready());
+}
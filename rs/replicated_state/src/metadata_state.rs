@@ -1,10 +1,13 @@
+pub mod query_stats;
 pub mod subnet_call_context_manager;
 #[cfg(test)]
 mod tests;
 
 use crate::{
     canister_state::system_state::CyclesUseCase,
-    metadata_state::subnet_call_context_manager::SubnetCallContextManager,
+    metadata_state::{
+        query_stats::RawQueryStats, subnet_call_context_manager::SubnetCallContextManager,
+    },
 };
 use ic_base_types::CanisterId;
 use ic_btc_types_internal::BlockBlob;
@@ -156,6 +159,10 @@ pub struct SystemMetadata {
     /// response limit. To work around this limitation, large responses are paginated
     /// and are stored here temporarily until they're fetched by the calling canister.
     pub bitcoin_get_successors_follow_up_responses: BTreeMap<CanisterId, Vec<BlockBlob>>,
+
+    /// Query statistics delivered in blocks and not yet aggregated into the
+    /// canister states.
+    pub raw_query_stats: RawQueryStats,
 }
 
 /// Full description of the IC network toplogy.
@@ -509,6 +516,7 @@ impl From<&SystemMetadata> for pb_metadata::SystemMetadata {
                     public_key: public_key.clone(),
                 })
                 .collect(),
+            raw_query_stats: Some((&item.raw_query_stats).into()),
         }
     }
 }
@@ -616,6 +624,10 @@ impl TryFrom<pb_metadata::SystemMetadata> for SystemMetadata {
             },
             expected_compiled_wasms: BTreeSet::new(),
             bitcoin_get_successors_follow_up_responses,
+            raw_query_stats: match item.raw_query_stats {
+                Some(raw_query_stats) => raw_query_stats.try_into()?,
+                None => RawQueryStats::default(),
+            },
         })
     }
 }
@@ -651,6 +663,7 @@ impl SystemMetadata {
             subnet_metrics: Default::default(),
             expected_compiled_wasms: BTreeSet::new(),
             bitcoin_get_successors_follow_up_responses: BTreeMap::default(),
+            raw_query_stats: RawQueryStats::default(),
         }
     }
 
@@ -910,6 +923,7 @@ impl SystemMetadata {
             subnet_metrics,
             expected_compiled_wasms,
             bitcoin_get_successors_follow_up_responses,
+            raw_query_stats,
         } = self;
 
         let split_from = split_from.expect("Not a state resulting from a subnet split");
@@ -946,6 +960,7 @@ impl SystemMetadata {
             subnet_metrics,
             expected_compiled_wasms,
             bitcoin_get_successors_follow_up_responses,
+            raw_query_stats,
         }
     }
 }
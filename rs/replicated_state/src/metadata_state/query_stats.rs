@@ -0,0 +1,371 @@
+//! Query statistics delivered in blocks, before they are aggregated into the
+//! canister states.
+//!
+//! Every node collects statistics about the queries it executes and, once per
+//! epoch, includes them in the blocks it proposes. Since the statistics of
+//! individual nodes are not trustworthy, an epoch is only aggregated once all
+//! nodes had the chance to report it, by taking, per canister and metric, the
+//! median across all reporting nodes.
+//!
+//! Nodes stream the statistics of an epoch over several blocks before moving
+//! on to the next epoch, so a node has fully reported an epoch once it delivers
+//! statistics for a later one. An epoch is aggregated as soon as all nodes of
+//! the subnet have fully reported it or, since idle or faulty nodes may never
+//! report anything, once statistics for an epoch
+//! [`QUERY_STATS_AGGREGATION_GRACE_PERIOD`] epochs later are delivered.
+
+use crate::canister_state::system_state::TotalQueryStats;
+use ic_base_types::CanisterId;
+use ic_protobuf::{
+    proxy::{try_from_option_field, ProxyDecodeError},
+    state::system_metadata::v1 as pb_metadata,
+};
+use ic_types::{
+    batch::{CanisterQueryStats, QueryStatsPayload},
+    node_id_into_protobuf, node_id_try_from_option, NodeId, QueryStatsEpoch,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Bound::{Excluded, Unbounded},
+};
+
+/// The number of epochs after which an epoch is aggregated even if not all
+/// nodes have reported it.
+pub const QUERY_STATS_AGGREGATION_GRACE_PERIOD: u64 = 2;
+
+/// The statistics reported by the nodes of the subnet, by node.
+type NodeStats = BTreeMap<NodeId, BTreeMap<CanisterId, CanisterQueryStats>>;
+
+/// Query statistics delivered in blocks but not yet aggregated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RawQueryStats {
+    /// The highest epoch whose statistics have been aggregated. Statistics for
+    /// this or earlier epochs that are delivered late are dropped.
+    pub highest_aggregated_epoch: Option<QueryStatsEpoch>,
+
+    /// Statistics reported for epochs that have not been aggregated yet.
+    pub stats: BTreeMap<QueryStatsEpoch, NodeStats>,
+}
+
+impl RawQueryStats {
+    /// Records the statistics in `payload` and aggregates the statistics of all
+    /// epochs that are complete, given the nodes of the subnet `subnet_nodes`.
+    ///
+    /// Returns the aggregated statistics by canister, to be added to the
+    /// canisters' totals.
+    pub fn deliver(
+        &mut self,
+        payload: QueryStatsPayload,
+        subnet_nodes: &BTreeSet<NodeId>,
+    ) -> BTreeMap<CanisterId, TotalQueryStats> {
+        let QueryStatsPayload {
+            epoch,
+            proposer,
+            canister_stats,
+        } = payload;
+        if self
+            .highest_aggregated_epoch
+            .map_or(false, |highest| epoch <= highest)
+        {
+            return BTreeMap::new();
+        }
+
+        let node_stats = self
+            .stats
+            .entry(epoch)
+            .or_default()
+            .entry(proposer)
+            .or_default();
+        for (canister_id, stats) in canister_stats {
+            // A node reports every canister at most once per epoch, anything
+            // else is ignored.
+            node_stats.entry(canister_id).or_insert(stats);
+        }
+
+        let mut totals = BTreeMap::new();
+        while let Some(oldest) = self.stats.keys().next().copied() {
+            if !self.is_complete(oldest, subnet_nodes) {
+                break;
+            }
+            let node_stats = self.stats.remove(&oldest).unwrap_or_default();
+            for (canister_id, stats) in aggregate(&node_stats) {
+                add_to_total(totals.entry(canister_id).or_default(), &stats);
+            }
+            self.highest_aggregated_epoch = Some(oldest);
+        }
+        totals
+    }
+
+    /// Returns whether `epoch` can be aggregated: either all of `subnet_nodes`
+    /// delivered statistics for a later epoch, or the grace period is over.
+    fn is_complete(&self, epoch: QueryStatsEpoch, subnet_nodes: &BTreeSet<NodeId>) -> bool {
+        let grace_period_end = epoch
+            .get()
+            .saturating_add(QUERY_STATS_AGGREGATION_GRACE_PERIOD);
+        if let Some(latest) = self.stats.keys().next_back() {
+            if latest.get() >= grace_period_end {
+                return true;
+            }
+        }
+
+        let reported: BTreeSet<&NodeId> = self
+            .stats
+            .range((Excluded(epoch), Unbounded))
+            .flat_map(|(_, node_stats)| node_stats.keys())
+            .collect();
+        !subnet_nodes.is_empty() && subnet_nodes.iter().all(|node| reported.contains(node))
+    }
+}
+
+/// Aggregates the statistics of one epoch: for each canister and metric, the
+/// median of the values reported by all nodes (counting a missing report as
+/// zero), scaled by the number of reporting nodes.
+fn aggregate(node_stats: &NodeStats) -> BTreeMap<CanisterId, TotalQueryStats> {
+    let num_nodes = node_stats.len() as u128;
+    let mut by_canister: BTreeMap<CanisterId, Vec<&CanisterQueryStats>> = BTreeMap::new();
+    for stats in node_stats.values() {
+        for (canister_id, stats) in stats {
+            by_canister.entry(*canister_id).or_default().push(stats);
+        }
+    }
+
+    by_canister
+        .into_iter()
+        .map(|(canister_id, stats)| {
+            let median = |value: fn(&CanisterQueryStats) -> u128| {
+                let mut values: Vec<u128> = stats.iter().map(|s| value(s)).collect();
+                values.resize(num_nodes as usize, 0);
+                values.sort_unstable();
+                values[values.len() / 2] * num_nodes
+            };
+            (
+                canister_id,
+                TotalQueryStats {
+                    num_calls_total: median(|s| s.num_calls as u128),
+                    num_instructions_total: median(|s| s.num_instructions as u128),
+                    ingress_payload_size_total: median(|s| s.ingress_payload_size as u128),
+                    egress_payload_size_total: median(|s| s.egress_payload_size as u128),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Adds `stats` to `total`, saturating at `u128::MAX`.
+pub(crate) fn add_to_total(total: &mut TotalQueryStats, stats: &TotalQueryStats) {
+    total.num_calls_total = total.num_calls_total.saturating_add(stats.num_calls_total);
+    total.num_instructions_total = total
+        .num_instructions_total
+        .saturating_add(stats.num_instructions_total);
+    total.ingress_payload_size_total = total
+        .ingress_payload_size_total
+        .saturating_add(stats.ingress_payload_size_total);
+    total.egress_payload_size_total = total
+        .egress_payload_size_total
+        .saturating_add(stats.egress_payload_size_total);
+}
+
+impl From<&RawQueryStats> for pb_metadata::RawQueryStats {
+    fn from(item: &RawQueryStats) -> Self {
+        let mut stats = vec![];
+        for (epoch, node_stats) in &item.stats {
+            for (node_id, canister_stats) in node_stats {
+                stats.push(pb_metadata::NodeQueryStats {
+                    epoch: epoch.get(),
+                    node_id: Some(node_id_into_protobuf(*node_id)),
+                    stats: canister_stats
+                        .iter()
+                        .map(|(canister_id, stats)| pb_metadata::CanisterQueryStats {
+                            canister_id: Some((*canister_id).into()),
+                            num_calls: stats.num_calls,
+                            num_instructions: stats.num_instructions,
+                            ingress_payload_size: stats.ingress_payload_size,
+                            egress_payload_size: stats.egress_payload_size,
+                        })
+                        .collect(),
+                });
+            }
+        }
+        Self {
+            highest_aggregated_epoch: item.highest_aggregated_epoch.map(|epoch| epoch.get()),
+            stats,
+        }
+    }
+}
+
+impl TryFrom<pb_metadata::RawQueryStats> for RawQueryStats {
+    type Error = ProxyDecodeError;
+
+    fn try_from(item: pb_metadata::RawQueryStats) -> Result<Self, Self::Error> {
+        let mut stats: BTreeMap<QueryStatsEpoch, NodeStats> = BTreeMap::new();
+        for entry in item.stats {
+            let node_stats = stats
+                .entry(QueryStatsEpoch::from(entry.epoch))
+                .or_default()
+                .entry(node_id_try_from_option(entry.node_id)?)
+                .or_default();
+            for canister_stats in entry.stats {
+                let canister_id: CanisterId = try_from_option_field(
+                    canister_stats.canister_id,
+                    "CanisterQueryStats::canister_id",
+                )?;
+                node_stats.insert(
+                    canister_id,
+                    CanisterQueryStats {
+                        num_calls: canister_stats.num_calls,
+                        num_instructions: canister_stats.num_instructions,
+                        ingress_payload_size: canister_stats.ingress_payload_size,
+                        egress_payload_size: canister_stats.egress_payload_size,
+                    },
+                );
+            }
+        }
+        Ok(Self {
+            highest_aggregated_epoch: item.highest_aggregated_epoch.map(QueryStatsEpoch::from),
+            stats,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_test_utilities::types::ids::{canister_test_id, node_test_id};
+
+    fn stats(num_calls: u32) -> CanisterQueryStats {
+        CanisterQueryStats {
+            num_calls,
+            num_instructions: num_calls as u64 * 1000,
+            ingress_payload_size: num_calls as u64 * 10,
+            egress_payload_size: num_calls as u64 * 20,
+        }
+    }
+
+    fn payload(epoch: u64, node: u64, canister_stats: &[(u64, u32)]) -> QueryStatsPayload {
+        QueryStatsPayload {
+            epoch: QueryStatsEpoch::from(epoch),
+            proposer: node_test_id(node),
+            canister_stats: canister_stats
+                .iter()
+                .map(|(canister, num_calls)| (canister_test_id(*canister), stats(*num_calls)))
+                .collect(),
+        }
+    }
+
+    fn nodes(ids: &[u64]) -> BTreeSet<NodeId> {
+        ids.iter().map(|id| node_test_id(*id)).collect()
+    }
+
+    #[test]
+    fn epochs_are_aggregated_once_all_nodes_reported() {
+        let subnet_nodes = nodes(&[1, 2, 3]);
+        let mut raw = RawQueryStats::default();
+
+        assert!(raw
+            .deliver(payload(1, 1, &[(0, 10)]), &subnet_nodes)
+            .is_empty());
+        assert!(raw
+            .deliver(payload(1, 2, &[(0, 30)]), &subnet_nodes)
+            .is_empty());
+        assert!(raw
+            .deliver(payload(1, 3, &[(0, 20), (1, 5)]), &subnet_nodes)
+            .is_empty());
+        // Nodes 1 and 2 moved on to epoch 2, node 3 may still be reporting.
+        assert!(raw
+            .deliver(payload(2, 1, &[(0, 1)]), &subnet_nodes)
+            .is_empty());
+        assert!(raw
+            .deliver(payload(2, 2, &[(0, 1)]), &subnet_nodes)
+            .is_empty());
+        assert_eq!(raw.highest_aggregated_epoch, None);
+
+        let totals = raw.deliver(payload(2, 3, &[(0, 1)]), &subnet_nodes);
+
+        // Median of [10, 20, 30] times 3 nodes.
+        assert_eq!(
+            totals.get(&canister_test_id(0)),
+            Some(&TotalQueryStats {
+                num_calls_total: 60,
+                num_instructions_total: 60_000,
+                ingress_payload_size_total: 600,
+                egress_payload_size_total: 1200,
+            })
+        );
+        // Median of [0, 0, 5] is zero.
+        assert_eq!(
+            totals.get(&canister_test_id(1)),
+            Some(&TotalQueryStats::default())
+        );
+        assert_eq!(raw.highest_aggregated_epoch, Some(QueryStatsEpoch::from(1)));
+        assert_eq!(
+            raw.stats.keys().collect::<Vec<_>>(),
+            vec![&QueryStatsEpoch::from(2)]
+        );
+    }
+
+    #[test]
+    fn epochs_are_aggregated_after_the_grace_period() {
+        let subnet_nodes = nodes(&[1, 2, 3]);
+        let mut raw = RawQueryStats::default();
+
+        raw.deliver(payload(1, 1, &[(0, 10)]), &subnet_nodes);
+        raw.deliver(payload(1, 2, &[(0, 10)]), &subnet_nodes);
+        assert!(raw
+            .deliver(payload(2, 1, &[(0, 1)]), &subnet_nodes)
+            .is_empty());
+
+        // Node 3 never reports, epoch 1 is aggregated once the grace period is
+        // over.
+        let totals = raw.deliver(
+            payload(1 + QUERY_STATS_AGGREGATION_GRACE_PERIOD, 1, &[]),
+            &subnet_nodes,
+        );
+        assert_eq!(
+            totals.get(&canister_test_id(0)).unwrap().num_calls_total,
+            20
+        );
+        assert_eq!(raw.highest_aggregated_epoch, Some(QueryStatsEpoch::from(1)));
+    }
+
+    #[test]
+    fn late_payloads_are_dropped() {
+        let subnet_nodes = nodes(&[1, 2]);
+        let mut raw = RawQueryStats::default();
+        raw.deliver(payload(1, 1, &[(0, 10)]), &subnet_nodes);
+        raw.deliver(payload(2, 1, &[(0, 10)]), &subnet_nodes);
+        raw.deliver(payload(2, 2, &[(0, 10)]), &subnet_nodes);
+        assert_eq!(raw.highest_aggregated_epoch, Some(QueryStatsEpoch::from(1)));
+
+        assert!(raw
+            .deliver(payload(1, 2, &[(0, 10)]), &subnet_nodes)
+            .is_empty());
+        assert!(!raw.stats.contains_key(&QueryStatsEpoch::from(1)));
+    }
+
+    #[test]
+    fn duplicate_reports_are_ignored() {
+        let subnet_nodes = nodes(&[1]);
+        let mut raw = RawQueryStats::default();
+        raw.deliver(payload(1, 1, &[(0, 10)]), &subnet_nodes);
+        raw.deliver(payload(1, 1, &[(0, 1000), (1, 7)]), &subnet_nodes);
+
+        let totals = raw.deliver(payload(2, 1, &[]), &subnet_nodes);
+        assert_eq!(
+            totals.get(&canister_test_id(0)).unwrap().num_calls_total,
+            10
+        );
+        assert_eq!(totals.get(&canister_test_id(1)).unwrap().num_calls_total, 7);
+    }
+
+    #[test]
+    fn protobuf_round_trip() {
+        let subnet_nodes = nodes(&[1, 2]);
+        let mut raw = RawQueryStats::default();
+        raw.deliver(payload(1, 1, &[(0, 10)]), &subnet_nodes);
+        raw.deliver(payload(2, 1, &[(0, 10), (1, 3)]), &subnet_nodes);
+        raw.deliver(payload(2, 2, &[(1, 4)]), &subnet_nodes);
+
+        let pb = pb_metadata::RawQueryStats::from(&raw);
+        assert_eq!(RawQueryStats::try_from(pb).unwrap(), raw);
+    }
+}
@@ -27,8 +27,8 @@ use std::os::unix::io::RawFd;
 use std::path::Path;
 use std::sync::Arc;
 
-// When persisting data we expand dirty pages to an aligned bucket of given size.
-const WRITE_BUCKET_PAGES: u64 = 16;
+/// When persisting data we expand dirty pages to an aligned bucket of given size.
+pub const WRITE_BUCKET_PAGES: u64 = 16;
 
 struct WriteBuffer<'a> {
     content: Vec<&'a [u8]>,
@@ -5,7 +5,7 @@ use super::{
 use crate::{
     canister_state::queues::CanisterQueuesLoopDetector,
     canister_state::system_state::{push_input, CanisterOutputQueuesIterator},
-    metadata_state::StreamMap,
+    metadata_state::{query_stats::add_to_total, StreamMap},
     CanisterQueues,
 };
 use ic_base_types::PrincipalId;
@@ -15,6 +15,7 @@ use ic_interfaces::execution_environment::CanisterOutOfCyclesError;
 use ic_registry_routing_table::RoutingTable;
 use ic_registry_subnet_type::SubnetType;
 use ic_types::{
+    batch::QueryStatsPayload,
     ingress::IngressStatus,
     messages::{CallbackId, CanisterMessage, Ingress, MessageId, RequestOrResponse, Response},
     xnet::QueueId,
@@ -412,6 +413,28 @@ impl ReplicatedState {
         std::mem::take(&mut self.canister_states)
     }
 
+    /// Records the query statistics included in a block and adds the
+    /// statistics of all complete epochs to the canisters' totals.
+    /// Statistics of canisters that no longer exist are dropped.
+    pub fn deliver_query_stats(&mut self, payload: QueryStatsPayload) {
+        let subnet_nodes = self
+            .metadata
+            .network_topology
+            .subnets
+            .get(&self.metadata.own_subnet_id)
+            .map(|subnet| subnet.nodes.clone())
+            .unwrap_or_default();
+        let totals = self
+            .metadata
+            .raw_query_stats
+            .deliver(payload, &subnet_nodes);
+        for (canister_id, stats) in totals {
+            if let Some(canister) = self.canister_states.get_mut(&canister_id) {
+                add_to_total(&mut canister.system_state.total_query_stats, &stats);
+            }
+        }
+    }
+
     pub fn routing_table(&self) -> Arc<RoutingTable> {
         Arc::clone(&self.metadata.network_topology.routing_table)
     }
@@ -0,0 +1,477 @@
+//! Optional compression of checkpoint files and state sync chunks.
+//!
+//! A protobuf checkpoint file can be stored zstd-compressed at `<path>.zst`
+//! instead of `<path>`. The manifest still refers to such a file as `<path>`
+//! and is computed over its uncompressed content, so compression never
+//! affects the state hash.
+//!
+//! Compressed files use the zstd seekable format: every `DEFAULT_CHUNK_SIZE`
+//! bytes of content are compressed into a separate frame, and a seek table in
+//! a trailing skippable frame lists the frame sizes. Reading a state sync
+//! chunk therefore only decompresses the frames it overlaps, and the
+//! uncompressed size of a file is known without decompressing it.
+//!
+//! PageMap files are mmapped and are never compressed. Instead, their all-zero
+//! pages can be deallocated, which keeps mostly-empty heaps small on disk
+//! without changing their content, see [`deallocate_zero_pages`].
+//!
+//! File chunks and file group chunks sent during state sync may be compressed
+//! as well. A chunk is only ever sent compressed if that makes it strictly
+//! shorter, so the receiver, which knows the uncompressed size of each chunk
+//! from the manifest, can tell the two encodings apart without any extra
+//! framing: a payload of the expected size is raw, a shorter one is
+//! compressed.
+use ic_sys::{PageBytes, PageIndex, PAGE_SIZE};
+use ic_types::state_sync::DEFAULT_CHUNK_SIZE;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+/// The extension appended to the path of a compressed checkpoint file.
+pub const COMPRESSED_FILE_EXTENSION: &str = "zst";
+
+/// The zstd compression level. Level 3 is the zstd default and offers a good
+/// trade-off between speed and ratio for mostly-empty pages.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// The magic number of the skippable frame holding the seek table.
+const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D_2A5E;
+
+/// The magic number at the very end of a file in the zstd seekable format.
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+
+/// The size of the seek table footer: the number of frames, the seek table
+/// descriptor and the seekable magic number.
+const SEEK_TABLE_FOOTER_SIZE: usize = 9;
+
+/// The size of a seek table entry without checksum: the compressed and the
+/// decompressed size of a frame.
+const SEEK_TABLE_ENTRY_SIZE: usize = 8;
+
+/// Returns the path of the compressed variant of the file at `path`.
+pub fn compressed_file_path(path: &Path) -> PathBuf {
+    let mut file_name = path.as_os_str().to_os_string();
+    file_name.push(".");
+    file_name.push(COMPRESSED_FILE_EXTENSION);
+    PathBuf::from(file_name)
+}
+
+/// If `path` is the path of a compressed checkpoint file, returns the path
+/// the file is known by in the manifest, i.e. `path` without the extension.
+pub fn uncompressed_file_path(path: &Path) -> Option<PathBuf> {
+    if path.extension()? == COMPRESSED_FILE_EXTENSION {
+        Some(path.with_extension(""))
+    } else {
+        None
+    }
+}
+
+/// Compresses `data` into the contents of a compressed checkpoint file.
+pub fn compress_file_contents(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    let mut seek_table = Vec::new();
+    for piece in data.chunks(DEFAULT_CHUNK_SIZE as usize) {
+        let frame = zstd::bulk::compress(piece, COMPRESSION_LEVEL)?;
+        seek_table.extend((frame.len() as u32).to_le_bytes());
+        seek_table.extend((piece.len() as u32).to_le_bytes());
+        contents.extend(frame);
+    }
+    let num_frames = (seek_table.len() / SEEK_TABLE_ENTRY_SIZE) as u32;
+
+    contents.extend(SKIPPABLE_FRAME_MAGIC.to_le_bytes());
+    contents.extend(((seek_table.len() + SEEK_TABLE_FOOTER_SIZE) as u32).to_le_bytes());
+    contents.extend(seek_table);
+    contents.extend(num_frames.to_le_bytes());
+    // Seek table descriptor: no checksums.
+    contents.push(0);
+    contents.extend(SEEKABLE_MAGIC.to_le_bytes());
+    Ok(contents)
+}
+
+/// A frame of a compressed checkpoint file.
+struct Frame {
+    /// Offset of the frame in the compressed file.
+    compressed_offset: u64,
+    compressed_size: usize,
+    /// Offset of the frame's content in the uncompressed file.
+    offset: u64,
+    size: usize,
+}
+
+/// An open compressed checkpoint file.
+pub struct CompressedFile {
+    file: File,
+    frames: Vec<Frame>,
+}
+
+impl CompressedFile {
+    /// Opens the compressed file at `path` and reads its seek table.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let invalid = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        };
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        if len < SEEK_TABLE_FOOTER_SIZE as u64 {
+            return Err(invalid("file too short for a seek table"));
+        }
+
+        let mut footer = [0; SEEK_TABLE_FOOTER_SIZE];
+        file.read_exact_at(&mut footer, len - SEEK_TABLE_FOOTER_SIZE as u64)?;
+        let num_frames = u32::from_le_bytes(footer[0..4].try_into().unwrap()) as u64;
+        if u32::from_le_bytes(footer[5..9].try_into().unwrap()) != SEEKABLE_MAGIC {
+            return Err(invalid("missing seek table"));
+        }
+        if footer[4] != 0 {
+            return Err(invalid("unsupported seek table descriptor"));
+        }
+
+        let seek_table_size = num_frames * SEEK_TABLE_ENTRY_SIZE as u64;
+        let Some(seek_table_offset) = len.checked_sub(seek_table_size + SEEK_TABLE_FOOTER_SIZE as u64) else {
+            return Err(invalid("seek table larger than the file"));
+        };
+        let mut seek_table = vec![0; seek_table_size as usize];
+        file.read_exact_at(&mut seek_table, seek_table_offset)?;
+
+        let mut frames = Vec::with_capacity(num_frames as usize);
+        let (mut compressed_offset, mut offset) = (0, 0);
+        for entry in seek_table.chunks_exact(SEEK_TABLE_ENTRY_SIZE) {
+            let compressed_size = u32::from_le_bytes(entry[0..4].try_into().unwrap()) as usize;
+            let size = u32::from_le_bytes(entry[4..8].try_into().unwrap()) as usize;
+            frames.push(Frame {
+                compressed_offset,
+                compressed_size,
+                offset,
+                size,
+            });
+            compressed_offset += compressed_size as u64;
+            offset += size as u64;
+        }
+        // The seek table is preceded by the 8 byte skippable frame header.
+        if compressed_offset + 8 != seek_table_offset {
+            return Err(invalid("seek table does not match the frames"));
+        }
+
+        Ok(Self { file, frames })
+    }
+
+    /// Returns the size of the uncompressed content.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.frames
+            .last()
+            .map_or(0, |frame| frame.offset + frame.size as u64)
+    }
+
+    /// Fills `buf` with the uncompressed bytes at `offset`, decompressing
+    /// only the frames that overlap with them.
+    pub fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let end = offset + buf.len() as u64;
+        if end > self.uncompressed_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "read of {} bytes at offset {} beyond the end of the file",
+                    buf.len(),
+                    offset
+                ),
+            ));
+        }
+
+        let first = self
+            .frames
+            .partition_point(|frame| frame.offset + frame.size as u64 <= offset);
+        for frame in self.frames[first..]
+            .iter()
+            .take_while(|frame| frame.offset < end)
+        {
+            let data = self.decompress_frame(frame)?;
+            let start = offset.max(frame.offset);
+            let stop = end.min(frame.offset + frame.size as u64);
+            buf[(start - offset) as usize..(stop - offset) as usize].copy_from_slice(
+                &data[(start - frame.offset) as usize..(stop - frame.offset) as usize],
+            );
+        }
+        Ok(())
+    }
+
+    /// Reads and decompresses the whole content.
+    pub fn read_all(&self) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; self.uncompressed_size() as usize];
+        self.read_exact_at(&mut data, 0)?;
+        Ok(data)
+    }
+
+    fn decompress_frame(&self, frame: &Frame) -> io::Result<Vec<u8>> {
+        let mut compressed = vec![0; frame.compressed_size];
+        self.file
+            .read_exact_at(&mut compressed, frame.compressed_offset)?;
+        let data = zstd::bulk::decompress(&compressed, frame.size)?;
+        if data.len() != frame.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "frame decompressed to {} bytes, expected {} bytes",
+                    data.len(),
+                    frame.size
+                ),
+            ));
+        }
+        Ok(data)
+    }
+}
+
+/// Reads and decompresses the whole compressed file at `path`.
+pub fn read_compressed_file(path: &Path) -> io::Result<Vec<u8>> {
+    CompressedFile::open(path)?.read_all()
+}
+
+/// Returns the uncompressed size of the compressed file at `path`.
+pub fn uncompressed_file_size(path: &Path) -> io::Result<u64> {
+    Ok(CompressedFile::open(path)?.uncompressed_size())
+}
+
+/// Fills `buf` with the bytes at `offset` of the checkpoint file at `path`.
+///
+/// If there is no file at `path` but there is a compressed one, reads from
+/// the uncompressed content of the latter.
+pub fn read_exact_at(path: &Path, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    match File::open(path) {
+        Ok(f) => f.read_exact_at(buf, offset),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let compressed_path = compressed_file_path(path);
+            if !compressed_path.exists() {
+                return Err(err);
+            }
+            CompressedFile::open(&compressed_path)?.read_exact_at(buf, offset)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Deallocates the all-zero pages among `pages`, given in ascending order, of
+/// the PageMap file at `path`, turning them into holes. The content of the
+/// file doesn't change.
+pub fn deallocate_zero_pages<'a>(
+    path: &Path,
+    pages: impl IntoIterator<Item = (PageIndex, &'a PageBytes)>,
+) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let file = std::fs::OpenOptions::new().write(true).open(path)?;
+        let len = file.metadata()?.len();
+        let punch_hole = |start: u64, end: u64| {
+            let end = end.min(len);
+            if start >= end {
+                return Ok(());
+            }
+            let result = unsafe {
+                libc::fallocate(
+                    file.as_raw_fd(),
+                    libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                    start as libc::off_t,
+                    (end - start) as libc::off_t,
+                )
+            };
+            if result == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        };
+
+        // Coalesce consecutive zero pages into a single hole.
+        let mut hole: Option<(u64, u64)> = None;
+        for (index, page) in pages {
+            let is_zero = page.iter().all(|byte| *byte == 0);
+            let start = index.get() * PAGE_SIZE as u64;
+            let end = start + PAGE_SIZE as u64;
+            hole = match (hole, is_zero) {
+                (Some((hole_start, hole_end)), true) if hole_end == start => {
+                    Some((hole_start, end))
+                }
+                (hole, is_zero) => {
+                    if let Some((hole_start, hole_end)) = hole {
+                        punch_hole(hole_start, hole_end)?;
+                    }
+                    is_zero.then_some((start, end))
+                }
+            };
+        }
+        if let Some((hole_start, hole_end)) = hole {
+            punch_hole(hole_start, hole_end)?;
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (path, pages.into_iter());
+    }
+
+    Ok(())
+}
+
+/// Encodes a file (group) chunk payload for transmission, compressing it
+/// only if that makes it shorter.
+pub fn compress_chunk(payload: Vec<u8>) -> Vec<u8> {
+    match zstd::bulk::compress(&payload, COMPRESSION_LEVEL) {
+        Ok(compressed) if compressed.len() < payload.len() => compressed,
+        _ => payload,
+    }
+}
+
+/// Decodes a file (group) chunk payload whose uncompressed size is
+/// `expected_len`. See the module documentation for the encoding.
+pub fn decompress_chunk(payload: &[u8], expected_len: usize) -> Result<Vec<u8>, String> {
+    if payload.len() == expected_len {
+        return Ok(payload.to_vec());
+    }
+    if payload.len() > expected_len {
+        return Err(format!(
+            "chunk payload of {} bytes is longer than the expected {} bytes",
+            payload.len(),
+            expected_len
+        ));
+    }
+    // Bounding the output by the expected size protects against payloads
+    // that decompress to arbitrarily large buffers.
+    let decompressed = zstd::bulk::decompress(payload, expected_len)
+        .map_err(|err| format!("failed to decompress chunk: {}", err))?;
+    if decompressed.len() != expected_len {
+        return Err(format!(
+            "chunk decompressed to {} bytes, expected {} bytes",
+            decompressed.len(),
+            expected_len
+        ));
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressible_chunk_round_trips() {
+        let payload = vec![0; 1 << 20];
+        let encoded = compress_chunk(payload.clone());
+        assert!(encoded.len() < payload.len());
+        assert_eq!(decompress_chunk(&encoded, payload.len()).unwrap(), payload);
+    }
+
+    #[test]
+    fn incompressible_chunk_is_sent_raw() {
+        // Bytes from a xorshift generator don't compress.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let payload: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let encoded = compress_chunk(payload.clone());
+        assert_eq!(encoded, payload);
+        assert_eq!(decompress_chunk(&encoded, payload.len()).unwrap(), payload);
+    }
+
+    #[test]
+    fn decompress_chunk_rejects_wrong_sizes() {
+        let payload = vec![1; 1000];
+        let encoded = compress_chunk(payload.clone());
+        assert!(decompress_chunk(&encoded, payload.len() - 1).is_err());
+        assert!(decompress_chunk(&encoded, payload.len() + 1).is_err());
+        assert!(decompress_chunk(&payload, payload.len() - 1).is_err());
+    }
+
+    #[test]
+    fn compressed_file_paths() {
+        let path = Path::new("canister_states/00000000000000010101/canister.pbuf");
+        let compressed = compressed_file_path(path);
+        assert_eq!(
+            compressed,
+            Path::new("canister_states/00000000000000010101/canister.pbuf.zst")
+        );
+        assert_eq!(uncompressed_file_path(&compressed).unwrap(), path);
+        assert_eq!(uncompressed_file_path(path), None);
+    }
+
+    #[test]
+    fn read_exact_at_reads_compressed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("system_metadata.pbuf");
+        // Three frames, the last one partial.
+        let len = 2 * DEFAULT_CHUNK_SIZE as usize + 10_000;
+        let data: Vec<u8> = (0..len).map(|i| (i % 13) as u8).collect();
+        std::fs::write(
+            compressed_file_path(&path),
+            compress_file_contents(&data).unwrap(),
+        )
+        .unwrap();
+
+        // Within a frame, across two frames and at the very end.
+        for offset in [5_000, DEFAULT_CHUNK_SIZE as usize - 50, len - 100] {
+            let mut buf = vec![0; 100];
+            read_exact_at(&path, &mut buf, offset as u64).unwrap();
+            assert_eq!(buf, data[offset..offset + 100]);
+        }
+        let mut buf = vec![0; 100];
+        assert!(read_exact_at(&path, &mut buf, len as u64 - 50).is_err());
+
+        assert_eq!(
+            uncompressed_file_size(&compressed_file_path(&path)).unwrap(),
+            len as u64
+        );
+        assert_eq!(
+            read_compressed_file(&compressed_file_path(&path)).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn empty_compressed_file_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.pbuf.zst");
+        std::fs::write(&path, compress_file_contents(&[]).unwrap()).unwrap();
+        assert_eq!(uncompressed_file_size(&path).unwrap(), 0);
+        assert!(read_compressed_file(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn compressed_files_are_valid_zstd() {
+        let data: Vec<u8> = (0..3 * DEFAULT_CHUNK_SIZE as usize)
+            .map(|i| (i % 7) as u8)
+            .collect();
+        let contents = compress_file_contents(&data).unwrap();
+        // Standard decoders skip the seek table.
+        assert_eq!(zstd::stream::decode_all(contents.as_slice()).unwrap(), data);
+    }
+
+    #[test]
+    fn deallocate_zero_pages_keeps_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vmemory_0.bin");
+        let mut pages = vec![[0; PAGE_SIZE]; 8];
+        pages[3][0] = 1;
+        pages[7][PAGE_SIZE - 1] = 2;
+        let data = pages.concat();
+        std::fs::write(&path, &data).unwrap();
+
+        deallocate_zero_pages(
+            &path,
+            pages
+                .iter()
+                .enumerate()
+                .map(|(i, page)| (PageIndex::new(i as u64), page)),
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+    }
+}
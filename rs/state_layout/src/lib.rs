@@ -1,3 +1,4 @@
+pub mod compression;
 pub mod error;
 pub mod state_layout;
 pub mod utils;
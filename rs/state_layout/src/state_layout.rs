@@ -1,3 +1,4 @@
+use crate::compression;
 use crate::error::LayoutError;
 use crate::utils::do_copy;
 
@@ -14,7 +15,9 @@ use ic_protobuf::{
 use ic_replicated_state::{
     canister_state::{
         execution_state::{NextScheduledMethod, WasmMetadata},
-        system_state::{CanisterHistory, CyclesUseCase},
+        system_state::{
+            CanisterHistory, CyclesUseCase, OnLowWasmMemoryHookStatus, TotalQueryStats,
+        },
     },
     CallContextManager, CanisterStatus, ExecutionTask, ExportedFunctions, Global, NumWasmPages,
 };
@@ -155,6 +158,11 @@ pub struct CanisterStateBits {
     pub canister_version: u64,
     pub consumed_cycles_since_replica_started_by_use_cases: BTreeMap<CyclesUseCase, NominalCycles>,
     pub canister_history: CanisterHistory,
+    pub total_query_stats: TotalQueryStats,
+    pub wasm_memory_limit: Option<NumBytes>,
+    pub wasm_memory_threshold: NumBytes,
+    pub on_low_wasm_memory_hook_status: OnLowWasmMemoryHookStatus,
+    pub reserved_balance_limit: Option<Cycles>,
 }
 
 #[derive(Clone)]
@@ -1414,9 +1422,17 @@ impl<T, Permission> ProtoFileWith<T, Permission> {
         &self.path
     }
 
-    /// Removes the file if it exists, else does nothing.
+    /// Returns the path of the compressed variant of this file, see
+    /// [`crate::compression`].
+    pub fn compressed_path(&self) -> PathBuf {
+        compression::compressed_file_path(&self.path)
+    }
+
+    /// Removes the file and its compressed variant if they exist, else does
+    /// nothing.
     pub fn try_remove_file(&self) -> Result<(), LayoutError> {
-        try_remove_file(&self.path)
+        try_remove_file(&self.path)?;
+        try_remove_file(&self.compressed_path())
     }
 }
 
@@ -1433,18 +1449,48 @@ where
             return Ok(());
         }
 
-        let file = open_for_write(&self.path)?;
+        // The compressed variant may be left over from a checkpoint written
+        // with compression enabled.
+        try_remove_file(&self.compressed_path())?;
+        self.write(&self.path, &serialized)
+    }
+
+    /// Like `serialize`, but stores the file zstd-compressed at
+    /// `compressed_path()`. Readers of the file, including the manifest
+    /// computation, decompress it transparently.
+    pub fn serialize_compressed(&self, value: T) -> Result<(), LayoutError> {
+        let serialized = value.encode_to_vec();
+
+        if serialized.is_empty() {
+            self.try_remove_file()?;
+            return Ok(());
+        }
+
+        let path = self.compressed_path();
+        let compressed = compression::compress_file_contents(&serialized).map_err(|io_err| {
+            LayoutError::IoError {
+                path: path.clone(),
+                message: "failed to compress serialized protobuf".to_string(),
+                io_err,
+            }
+        })?;
+        try_remove_file(&self.path)?;
+        self.write(&path, &compressed)
+    }
+
+    fn write(&self, path: &Path, bytes: &[u8]) -> Result<(), LayoutError> {
+        let file = open_for_write(path)?;
         let mut writer = std::io::BufWriter::new(file);
         writer
-            .write_all(&serialized)
+            .write_all(bytes)
             .map_err(|io_err| LayoutError::IoError {
-                path: self.path.clone(),
+                path: path.to_path_buf(),
                 message: "failed to write serialized protobuf to disk".to_string(),
                 io_err,
             })?;
 
         writer.into_inner().map_err(|err| LayoutError::IoError {
-            path: self.path.clone(),
+            path: path.to_path_buf(),
             message: "failed to flush buffers to file".to_string(),
             io_err: std::io::Error::new(err.error().kind(), err.to_string()),
         })?;
@@ -1458,8 +1504,9 @@ where
     T: prost::Message + std::default::Default,
     P: ReadPolicy,
 {
-    /// Deserializes the value from the underlying file.
-    /// If the file does not exist, deserialize as an empty buffer.
+    /// Deserializes the value from the underlying file, or from its
+    /// compressed variant if only that one exists.
+    /// If neither file exists, deserialize as an empty buffer.
     /// Returns an error for all other I/O errors.
     pub fn deserialize(&self) -> Result<T, LayoutError> {
         match open_for_read(&self.path) {
@@ -1467,7 +1514,19 @@ where
             Err(LayoutError::IoError { io_err, .. })
                 if io_err.kind() == std::io::ErrorKind::NotFound =>
             {
-                self.deserialize_buffer(&[])
+                let compressed_path = self.compressed_path();
+                if !compressed_path.exists() {
+                    return self.deserialize_buffer(&[]);
+                }
+                let buf =
+                    compression::read_compressed_file(&compressed_path).map_err(|io_err| {
+                        LayoutError::IoError {
+                            path: compressed_path,
+                            message: "failed to read compressed file".to_string(),
+                            io_err,
+                        }
+                    })?;
+                self.deserialize_buffer(&buf)
             }
             Err(err) => Err(err),
         }
@@ -1623,6 +1682,13 @@ impl From<CanisterStateBits> for pb_canister_state_bits::CanisterStateBits {
                 })
                 .collect(),
             canister_history: Some((&item.canister_history).into()),
+            total_query_stats: Some((&item.total_query_stats).into()),
+            wasm_memory_limit: item.wasm_memory_limit.map(|limit| limit.get()),
+            wasm_memory_threshold: item.wasm_memory_threshold.get(),
+            on_low_wasm_memory_hook_status: pb_canister_state_bits::OnLowWasmMemoryHookStatus::from(
+                item.on_low_wasm_memory_hook_status,
+            ) as i32,
+            reserved_balance_limit: item.reserved_balance_limit.map(|limit| limit.into()),
         }
     }
 }
@@ -1668,12 +1734,30 @@ impl TryFrom<pb_canister_state_bits::CanisterStateBits> for CanisterStateBits {
             .transpose()?
             .unwrap_or_else(Cycles::zero);
 
+        let reserved_balance_limit = value
+            .reserved_balance_limit
+            .map(|c| c.try_into())
+            .transpose()?;
+
         let task_queue = value
             .task_queue
             .into_iter()
             .map(|v| v.try_into())
             .collect::<Result<_, _>>()?;
 
+        let on_low_wasm_memory_hook_status =
+            pb_canister_state_bits::OnLowWasmMemoryHookStatus::from_i32(
+                value.on_low_wasm_memory_hook_status,
+            )
+            .ok_or(ProxyDecodeError::ValueOutOfRange {
+                typ: "OnLowWasmMemoryHookStatus",
+                err: format!(
+                    "Unexpected value for the on low Wasm memory hook status: {}",
+                    value.on_low_wasm_memory_hook_status
+                ),
+            })?
+            .try_into()?;
+
         Ok(Self {
             controllers,
             last_full_execution_round: value.last_full_execution_round.into(),
@@ -1733,6 +1817,16 @@ impl TryFrom<pb_canister_state_bits::CanisterStateBits> for CanisterStateBits {
                 "CanisterStateBits::canister_history",
             )
             .unwrap_or_default(),
+            // Checkpoints written before query stats were aggregated have no totals.
+            total_query_stats: value
+                .total_query_stats
+                .map(TotalQueryStats::try_from)
+                .transpose()?
+                .unwrap_or_default(),
+            wasm_memory_limit: value.wasm_memory_limit.map(NumBytes::from),
+            wasm_memory_threshold: NumBytes::from(value.wasm_memory_threshold),
+            on_low_wasm_memory_hook_status,
+            reserved_balance_limit,
         })
     }
 }
@@ -3,7 +3,7 @@ use super::*;
 use ic_ic00_types::{
     CanisterChange, CanisterChangeDetails, CanisterChangeOrigin, CanisterInstallMode, IC_00,
 };
-use ic_replicated_state::canister_state::system_state::CanisterHistory;
+use ic_replicated_state::canister_state::system_state::{CanisterHistory, TotalQueryStats};
 use ic_replicated_state::metadata_state::subnet_call_context_manager::InstallCodeCallId;
 use ic_test_utilities::types::ids::user_test_id;
 use ic_test_utilities::{
@@ -47,6 +47,11 @@ fn default_canister_state_bits() -> CanisterStateBits {
         canister_version: 0,
         consumed_cycles_since_replica_started_by_use_cases: BTreeMap::new(),
         canister_history: CanisterHistory::default(),
+        total_query_stats: TotalQueryStats::default(),
+        wasm_memory_limit: None,
+        wasm_memory_threshold: NumBytes::from(0),
+        on_low_wasm_memory_hook_status: OnLowWasmMemoryHookStatus::default(),
+        reserved_balance_limit: None,
     }
 }
 
@@ -187,6 +192,68 @@ fn test_encode_decode_non_empty_history() {
     assert_eq!(canister_state_bits.canister_history, canister_history);
 }
 
+#[test]
+fn test_encode_decode_total_query_stats() {
+    let total_query_stats = TotalQueryStats {
+        num_calls_total: 42,
+        num_instructions_total: u128::MAX,
+        ingress_payload_size_total: 1 << 70,
+        egress_payload_size_total: 0,
+    };
+    let canister_state_bits = CanisterStateBits {
+        total_query_stats: total_query_stats.clone(),
+        ..default_canister_state_bits()
+    };
+
+    let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+    let canister_state_bits = CanisterStateBits::try_from(pb_bits).unwrap();
+    assert_eq!(canister_state_bits.total_query_stats, total_query_stats);
+}
+
+#[test]
+fn test_encode_decode_wasm_memory_limit_settings() {
+    let canister_state_bits = CanisterStateBits {
+        wasm_memory_limit: Some(NumBytes::from(1 << 30)),
+        wasm_memory_threshold: NumBytes::from(1 << 20),
+        on_low_wasm_memory_hook_status: OnLowWasmMemoryHookStatus::Executed,
+        ..default_canister_state_bits()
+    };
+
+    let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+    let canister_state_bits = CanisterStateBits::try_from(pb_bits).unwrap();
+    assert_eq!(
+        canister_state_bits.wasm_memory_limit,
+        Some(NumBytes::from(1 << 30))
+    );
+    assert_eq!(
+        canister_state_bits.wasm_memory_threshold,
+        NumBytes::from(1 << 20)
+    );
+    assert_eq!(
+        canister_state_bits.on_low_wasm_memory_hook_status,
+        OnLowWasmMemoryHookStatus::Executed
+    );
+}
+
+#[test]
+fn test_encode_decode_reserved_balance_limit() {
+    for reserved_balance_limit in [None, Some(Cycles::new(0)), Some(Cycles::new(1 << 100))] {
+        let canister_state_bits = CanisterStateBits {
+            reserved_balance: Cycles::new(42),
+            reserved_balance_limit,
+            ..default_canister_state_bits()
+        };
+
+        let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+        let canister_state_bits = CanisterStateBits::try_from(pb_bits).unwrap();
+        assert_eq!(canister_state_bits.reserved_balance, Cycles::new(42));
+        assert_eq!(
+            canister_state_bits.reserved_balance_limit,
+            reserved_balance_limit
+        );
+    }
+}
+
 #[test]
 fn test_encode_decode_task_queue() {
     let ingress = Arc::new(IngressBuilder::new().method_name("test_ingress").build());
@@ -346,3 +413,27 @@ fn test_canister_id_from_path() {
         canister_id_from_path(Path::new("canister_states/not-a-canister-ID/queues.pbuf"))
     );
 }
+
+#[test]
+fn test_compressed_proto_file_round_trip() {
+    let tempdir = tmpdir("state_layout");
+    let file: ProtoFileWith<pb_canister_state_bits::CanisterStateBits, RwPolicy<()>> =
+        tempdir.path().join("canister.pbuf").into();
+    let bits: pb_canister_state_bits::CanisterStateBits = default_canister_state_bits().into();
+
+    file.serialize_compressed(bits.clone()).unwrap();
+    assert!(!file.raw_path().exists());
+    assert!(file.compressed_path().exists());
+    assert_eq!(file.deserialize().unwrap(), bits);
+
+    // Switching back to uncompressed storage removes the compressed variant.
+    file.serialize(bits.clone()).unwrap();
+    assert!(file.raw_path().exists());
+    assert!(!file.compressed_path().exists());
+    assert_eq!(file.deserialize().unwrap(), bits);
+
+    file.serialize_compressed(bits).unwrap();
+    assert!(!file.raw_path().exists());
+    file.try_remove_file().unwrap();
+    assert!(!file.compressed_path().exists());
+}
@@ -592,6 +592,7 @@ impl StateMachine {
             ExecutionServices::setup_execution(
                 replica_logger.clone(),
                 &metrics_registry,
+                node_ids[0],
                 subnet_id,
                 subnet_type,
                 subnet_config.scheduler_config,
@@ -853,6 +854,7 @@ impl StateMachine {
                 signed_ingress_msgs: payload.ingress_messages,
                 certified_stream_slices: payload.xnet_payload.stream_slices,
                 bitcoin_adapter_responses: vec![],
+                query_stats: None,
             },
             randomness: Randomness::from(seed),
             ecdsa_subnet_public_keys: self.ecdsa_subnet_public_keys.clone(),
@@ -354,6 +354,11 @@ pub fn load_canister_state<P: ReadPolicy>(
         CanisterTimer::from_nanos_since_unix_epoch(canister_state_bits.global_timer_nanos),
         canister_state_bits.canister_version,
         canister_state_bits.canister_history,
+        canister_state_bits.total_query_stats,
+        canister_state_bits.wasm_memory_limit,
+        canister_state_bits.wasm_memory_threshold,
+        canister_state_bits.on_low_wasm_memory_hook_status,
+        canister_state_bits.reserved_balance_limit,
     );
 
     let canister_state = CanisterState {
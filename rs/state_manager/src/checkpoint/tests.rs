@@ -1,6 +1,7 @@
 use super::*;
 use crate::{spawn_tip_thread, StateManagerMetrics, NUMBER_OF_CHECKPOINT_THREADS};
 use ic_base_types::NumSeconds;
+use ic_config::flag_status::FlagStatus;
 use ic_ic00_types::CanisterStatusType;
 use ic_metrics::MetricsRegistry;
 use ic_registry_subnet_type::SubnetType;
@@ -101,6 +102,7 @@ fn can_make_a_checkpoint() {
             tip_handler,
             layout.clone(),
             state_manager_metrics(),
+            FlagStatus::Disabled,
             MaliciousFlags::default(),
         );
 
@@ -165,6 +167,7 @@ fn scratchpad_dir_is_deleted_if_checkpointing_failed() {
             tip_handler,
             layout,
             state_manager_metrics.clone(),
+            FlagStatus::Disabled,
             MaliciousFlags::default(),
         );
 
@@ -215,6 +218,7 @@ fn can_recover_from_a_checkpoint() {
             tip_handler,
             layout.clone(),
             state_manager_metrics.clone(),
+            FlagStatus::Disabled,
             MaliciousFlags::default(),
         );
 
@@ -317,6 +321,7 @@ fn can_recover_an_empty_state() {
             tip_handler,
             layout.clone(),
             state_manager_metrics.clone(),
+            FlagStatus::Disabled,
             MaliciousFlags::default(),
         );
 
@@ -401,6 +406,7 @@ fn can_recover_a_stopping_canister() {
             tip_handler,
             layout.clone(),
             state_manager_metrics.clone(),
+            FlagStatus::Disabled,
             MaliciousFlags::default(),
         );
 
@@ -468,6 +474,7 @@ fn can_recover_a_stopped_canister() {
             tip_handler,
             layout.clone(),
             state_manager_metrics.clone(),
+            FlagStatus::Disabled,
             MaliciousFlags::default(),
         );
 
@@ -520,6 +527,7 @@ fn can_recover_a_running_canister() {
             tip_handler,
             layout.clone(),
             state_manager_metrics.clone(),
+            FlagStatus::Disabled,
             MaliciousFlags::default(),
         );
 
@@ -572,6 +580,7 @@ fn can_recover_subnet_queues() {
             tip_handler,
             layout.clone(),
             state_manager_metrics.clone(),
+            FlagStatus::Disabled,
             MaliciousFlags::default(),
         );
 
@@ -622,6 +631,7 @@ fn empty_protobufs_are_loaded_correctly() {
             tip_handler,
             layout.clone(),
             state_manager_metrics.clone(),
+            FlagStatus::Disabled,
             MaliciousFlags::default(),
         );
 
@@ -105,6 +105,7 @@ const LABEL_FETCH: &str = "fetch";
 const LABEL_COPY_FILES: &str = "copy_files";
 const LABEL_COPY_CHUNKS: &str = "copy_chunks";
 const LABEL_PREALLOCATE: &str = "preallocate";
+const LABEL_COPY_OTHER_CHECKPOINTS: &str = "copy_other_checkpoints";
 const LABEL_STATE_SYNC_MAKE_CHECKPOINT: &str = "state_sync_make_checkpoint";
 
 /// Labels for slice validation metrics
@@ -156,6 +157,7 @@ pub struct StateSyncMetrics {
     remaining: IntGauge,
     corrupted_chunks_critical: IntCounter,
     corrupted_chunks: IntCounterVec,
+    reused_bytes: HistogramVec,
 }
 
 #[derive(Clone)]
@@ -450,7 +452,7 @@ impl StateSyncMetrics {
     pub fn new(metrics_registry: &MetricsRegistry) -> Self {
         let size = metrics_registry.int_counter_vec(
             "state_sync_size_bytes_total",
-            "Size of chunks synchronized by different operations ('fetch', 'copy_files', 'copy_chunks', 'preallocate') during all the state sync in bytes.",
+            "Size of chunks synchronized by different operations ('fetch', 'copy_files', 'copy_chunks', 'copy_other_checkpoints', 'preallocate') during all the state sync in bytes.",
             &["op"],
         );
 
@@ -459,6 +461,7 @@ impl StateSyncMetrics {
             LABEL_FETCH,
             LABEL_COPY_FILES,
             LABEL_COPY_CHUNKS,
+            LABEL_COPY_OTHER_CHECKPOINTS,
             LABEL_PREALLOCATE,
         ] {
             size.with_label_values(&[*op]);
@@ -521,6 +524,23 @@ impl StateSyncMetrics {
             corrupted_chunks.with_label_values(&[*source]);
         }
 
+        let reused_bytes = metrics_registry.histogram_vec(
+            "state_sync_reused_bytes",
+            "Bytes copied from local data instead of being fetched, per state sync, by source ('copy_files', 'copy_chunks', 'copy_other_checkpoints').",
+            // 1KB, 2KB, 5KB, 10KB, 20KB, 50KB, …, 100GB, 200GB, 500GB
+            decimal_buckets(3, 11),
+            &["source"],
+        );
+
+        // Note [Metrics preallocation]
+        for source in &[
+            LABEL_COPY_FILES,
+            LABEL_COPY_CHUNKS,
+            LABEL_COPY_OTHER_CHECKPOINTS,
+        ] {
+            reused_bytes.with_label_values(&[*source]);
+        }
+
         Self {
             size,
             duration,
@@ -528,6 +548,7 @@ impl StateSyncMetrics {
             remaining,
             corrupted_chunks_critical,
             corrupted_chunks,
+            reused_bytes,
         }
     }
 }
@@ -746,6 +767,7 @@ pub struct StateManagerImpl {
     fd_factory: Arc<dyn PageAllocatorFileDescriptor>,
     malicious_flags: MaliciousFlags,
     latest_height_update_time: Arc<Mutex<Instant>>,
+    state_sync_chunk_compression: FlagStatus,
 }
 
 fn load_checkpoint(
@@ -1347,6 +1369,7 @@ impl StateManagerImpl {
             state_layout.capture_tip_handler(),
             state_layout.clone(),
             metrics.clone(),
+            config.checkpoint_file_compression,
             malicious_flags.clone(),
         );
 
@@ -1569,6 +1592,7 @@ impl StateManagerImpl {
             fd_factory,
             malicious_flags,
             latest_height_update_time: Arc::new(Mutex::new(Instant::now())),
+            state_sync_chunk_compression: config.state_sync_chunk_compression,
         }
     }
     /// Returns the Page Allocator file descriptor factory. This will then be
@@ -1699,17 +1723,20 @@ impl StateManagerImpl {
 
     /// Returns the manifest of the latest checkpoint on disk with its
     /// checkpoint layout.
-    fn latest_manifest(&self) -> Option<(Manifest, CheckpointLayout<ReadOnly>)> {
+    /// Returns the manifests and layouts of all the checkpoints whose manifest
+    /// has been computed, latest first.
+    fn checkpoint_manifests(&self) -> Vec<(Manifest, CheckpointLayout<ReadOnly>)> {
         self.checkpoint_heights()
             .iter()
             .rev()
-            .find_map(|checkpointed_height| {
+            .filter_map(|checkpointed_height| {
                 let states = self.states.read();
                 let metadata = states.states_metadata.get(checkpointed_height)?;
                 let manifest = metadata.manifest()?.clone();
                 let checkpoint_layout = metadata.checkpoint_layout.clone()?;
                 Some((manifest, checkpoint_layout))
             })
+            .collect()
     }
 
     fn compute_certification_metadata(
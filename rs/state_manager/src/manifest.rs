@@ -21,7 +21,7 @@ use ic_crypto_sha2::Sha256;
 use ic_logger::{error, fatal, replica_logger::no_op_logger, ReplicaLogger};
 use ic_metrics::MetricsRegistry;
 use ic_replicated_state::PageIndex;
-use ic_state_layout::{CheckpointLayout, ReadOnly, CANISTER_FILE};
+use ic_state_layout::{compression, CheckpointLayout, ReadOnly, CANISTER_FILE};
 use ic_sys::{mmap::ScopedMmap, PAGE_SIZE};
 use ic_types::{
     crypto::CryptoHash,
@@ -348,7 +348,7 @@ fn build_chunk_table_parallel(
     // and close the corresponding file.
     // This way we keep the number of files opened at the same time
     // low (it doesn't exceed the number of the threads).
-    let file_cache: Arc<Mutex<HashMap<u32, Weak<ScopedMmap>>>> =
+    let file_cache: Arc<Mutex<HashMap<u32, Weak<FileContents>>>> =
         Arc::new(Mutex::new(HashMap::new()));
 
     // Compute real chunk hashes in parallel.
@@ -362,7 +362,7 @@ fn build_chunk_table_parallel(
             let file_cache = Arc::clone(&file_cache);
             scope.execute(move || {
                 let recompute_chunk_hash = || {
-                    let mmap: Arc<ScopedMmap> = if file_size > max_chunk_size as u64 {
+                    let mmap: Arc<FileContents> = if file_size > max_chunk_size as u64 {
                         // We only use the file cache if there is more than one chunk in the file,
                         // otherwise the synchronization cost is unnecessary.
                         let mut cache = file_cache.lock().unwrap();
@@ -370,7 +370,7 @@ fn build_chunk_table_parallel(
                             Some(mmap) => mmap,
                             None => {
                                 let mmap = Arc::new(
-                                    ScopedMmap::from_path(&file_path)
+                                    FileContents::open(&file_path)
                                         .unwrap_or_else(|e| fatal!(log, "failed to mmap file {}: {}", file_path.display(), e)),
                                 );
                                 cache.insert(chunk_info.file_index, Arc::downgrade(&mmap));
@@ -379,7 +379,7 @@ fn build_chunk_table_parallel(
                         }
                     } else {
                         Arc::new(
-                            ScopedMmap::from_path(&file_path)
+                            FileContents::open(&file_path)
                                 .unwrap_or_else(|e| fatal!(log, "failed to mmap file {}: {}", file_path.display(), e))
                         )
                     };
@@ -541,7 +541,7 @@ fn build_chunk_table_sequential(
             });
         };
 
-        let mmap = ScopedMmap::from_path(root.join(&relative_path)).expect("failed to open file");
+        let mmap = FileContents::open(&root.join(&relative_path)).expect("failed to open file");
         let data = mmap.as_slice();
         compute_file_chunk_hashes(data);
     }
@@ -551,8 +551,40 @@ fn build_chunk_table_sequential(
     (file_table, chunk_table)
 }
 
+/// The contents of a checkpoint file: memory-mapped if the file is stored
+/// uncompressed, decompressed into memory otherwise.
+enum FileContents {
+    Mapped(ScopedMmap),
+    Decompressed(Vec<u8>),
+}
+
+impl FileContents {
+    /// Opens the checkpoint file that the manifest refers to as `path`.
+    fn open(path: &Path) -> std::io::Result<Self> {
+        match ScopedMmap::from_path(path) {
+            Ok(mmap) => Ok(Self::Mapped(mmap)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let compressed_path = compression::compressed_file_path(path);
+                if !compressed_path.exists() {
+                    return Err(err);
+                }
+                compression::read_compressed_file(&compressed_path).map(Self::Decompressed)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::Mapped(mmap) => mmap.as_slice(),
+            Self::Decompressed(data) => data.as_slice(),
+        }
+    }
+}
+
 /// Traverses root recursively and populates the `files` vector with entries of
-/// the form `(relative_file_name, file_len)`.
+/// the form `(relative_file_name, file_len)`. Compressed files are listed
+/// with the path and length of their uncompressed content.
 fn files_with_sizes(
     root: &Path,
     relative_path: PathBuf,
@@ -568,7 +600,22 @@ fn files_with_sizes(
         })?;
 
     if metadata.is_file() {
-        files.push(FileWithSize(relative_path, metadata.len()))
+        match compression::uncompressed_file_path(&relative_path) {
+            // Compressed files are listed under their uncompressed path and
+            // size, so that compression does not affect the manifest.
+            Some(uncompressed_path) => {
+                let size_bytes =
+                    compression::uncompressed_file_size(&absolute_path).map_err(|io_err| {
+                        CheckpointError::IoError {
+                            path: absolute_path.clone(),
+                            message: "failed to read compressed file size".to_string(),
+                            io_err: io_err.to_string(),
+                        }
+                    })?;
+                files.push(FileWithSize(uncompressed_path, size_bytes))
+            }
+            None => files.push(FileWithSize(relative_path, metadata.len())),
+        }
     } else {
         if relative_path.ends_with("slot_db") {
             return Ok(());
@@ -1328,6 +1375,75 @@ pub fn diff_manifest(
     }
 }
 
+/// Finds the chunks of `manifest_new` listed in `fetch_chunks` in any of
+/// `manifests_old` and returns one DiffScript per old manifest that copies the
+/// chunks found there. The chunks found are removed from `fetch_chunks`.
+///
+/// The file and chunk tables of all old manifests are indexed by hash. If the
+/// same content is available in several old manifests, the earlier one is
+/// used. A file is copied as a whole only if all of its chunks need to be
+/// fetched. The returned DiffScripts never contain chunks to fetch.
+pub fn diff_manifests(
+    manifests_old: &[&Manifest],
+    fetch_chunks: &mut HashSet<NewIndex>,
+    manifest_new: &Manifest,
+) -> Vec<DiffScript> {
+    let mut file_hash_to_index: HashMap<[u8; 32], (usize, OldIndex)> = Default::default();
+    let mut chunk_hash_to_index: HashMap<[u8; 32], (usize, OldIndex)> = Default::default();
+    for (manifest_index, manifest_old) in manifests_old.iter().enumerate() {
+        for (file_index, file_info) in manifest_old.file_table.iter().enumerate() {
+            file_hash_to_index
+                .entry(file_info.hash)
+                .or_insert((manifest_index, file_index));
+        }
+        for (chunk_index, chunk_info) in manifest_old.chunk_table.iter().enumerate() {
+            chunk_hash_to_index
+                .entry(chunk_info.hash)
+                .or_insert((manifest_index, chunk_index));
+        }
+    }
+
+    let mut diff_scripts: Vec<DiffScript> = manifests_old
+        .iter()
+        .map(|_| DiffScript {
+            copy_files: Default::default(),
+            copy_chunks: Default::default(),
+            fetch_chunks: Default::default(),
+            zeros_chunks: 0,
+        })
+        .collect();
+
+    for (file_index, file_info) in manifest_new.file_table.iter().enumerate() {
+        let chunk_range = file_chunk_range(&manifest_new.chunk_table, file_index);
+        if chunk_range.is_empty() || !chunk_range.clone().all(|i| fetch_chunks.contains(&i)) {
+            continue;
+        }
+        if let Some((manifest_index, old_index)) = file_hash_to_index.get(&file_info.hash) {
+            diff_scripts[*manifest_index]
+                .copy_files
+                .insert(file_index, *old_index);
+            for chunk_index in chunk_range {
+                fetch_chunks.remove(&chunk_index);
+            }
+        }
+    }
+
+    fetch_chunks.retain(|chunk_index| {
+        let chunk_info = &manifest_new.chunk_table[*chunk_index];
+        match chunk_hash_to_index.get(&chunk_info.hash) {
+            Some((manifest_index, old_index)) => {
+                diff_scripts[*manifest_index]
+                    .copy_chunks
+                    .insert(*chunk_index, *old_index);
+                false
+            }
+            None => true,
+        }
+    });
+
+    diff_scripts
+}
+
 /// Filters out all-zero chunks in the manifest chunk table and returns the set
 /// of remaining chunks indices.
 pub fn filter_out_zero_chunks(manifest: &Manifest) -> HashSet<usize> {
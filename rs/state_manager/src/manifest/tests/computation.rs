@@ -1,6 +1,6 @@
 use crate::manifest::validate_manifest_internal_consistency;
 use crate::manifest::{
-    build_file_group_chunks, build_meta_manifest, compute_manifest, diff_manifest,
+    build_file_group_chunks, build_meta_manifest, compute_manifest, diff_manifest, diff_manifests,
     file_chunk_range, filter_out_zero_chunks, hash::ManifestHash, manifest_hash, manifest_hash_v1,
     manifest_hash_v2, meta_manifest_hash, validate_chunk, validate_manifest,
     validate_meta_manifest, validate_sub_manifest, ChunkValidationError, DiffScript,
@@ -840,6 +840,64 @@ fn test_missing_simple_manifest() {
     );
 }
 
+#[test]
+fn test_diff_manifests_finds_chunks_in_any_manifest() {
+    let (_, manifest_new) = simple_manifest(CURRENT_STATE_SYNC_VERSION);
+    let (file_table, chunk_table) = simple_file_table_and_chunk_table(CURRENT_STATE_SYNC_VERSION);
+
+    // An old manifest containing only file_2 ('subdir/metadata').
+    let manifest_metadata_only = Manifest::new(
+        CURRENT_STATE_SYNC_VERSION,
+        vec![file_table[2].clone()],
+        chunk_table[3..5]
+            .iter()
+            .map(|chunk_info| ChunkInfo {
+                file_index: 0,
+                ..chunk_info.clone()
+            })
+            .collect(),
+    );
+    let (_, manifest_full) = simple_manifest(CURRENT_STATE_SYNC_VERSION);
+
+    // chunk_2 is not listed, so file_1 has to be copied chunk by chunk.
+    let mut fetch_chunks = maplit::hashset! {0, 1, 3, 4};
+    assert_eq!(
+        diff_manifests(
+            &[&manifest_metadata_only, &manifest_full],
+            &mut fetch_chunks,
+            &manifest_new
+        ),
+        vec![
+            DiffScript {
+                copy_files: maplit::hashmap! {2 => 0},
+                copy_chunks: Default::default(),
+                fetch_chunks: Default::default(),
+                zeros_chunks: 0,
+            },
+            DiffScript {
+                copy_files: maplit::hashmap! {0 => 0},
+                copy_chunks: maplit::hashmap! {1 => 1},
+                fetch_chunks: Default::default(),
+                zeros_chunks: 0,
+            },
+        ]
+    );
+    assert!(fetch_chunks.is_empty());
+
+    // Chunks that are not found anywhere still need to be fetched.
+    let mut fetch_chunks = maplit::hashset! {0, 1, 3, 4};
+    assert_eq!(
+        diff_manifests(&[&manifest_metadata_only], &mut fetch_chunks, &manifest_new),
+        vec![DiffScript {
+            copy_files: maplit::hashmap! {2 => 0},
+            copy_chunks: Default::default(),
+            fetch_chunks: Default::default(),
+            zeros_chunks: 0,
+        }]
+    );
+    assert_eq!(fetch_chunks, maplit::hashset! {0, 1});
+}
+
 #[test]
 fn test_simple_manifest_encoding_roundtrip() {
     let (_hash, manifest) = simple_manifest_v1();
@@ -6,7 +6,7 @@ use crate::{
 };
 
 use ic_base_types::CanisterId;
-use ic_config::state_manager::Config;
+use ic_config::{flag_status::FlagStatus, state_manager::Config};
 use ic_logger::ReplicaLogger;
 use ic_metrics::MetricsRegistry;
 use ic_registry_routing_table::{
@@ -188,6 +188,7 @@ fn write_checkpoint(
         tip_handler,
         state_layout,
         metrics.clone(),
+        FlagStatus::Disabled,
         MaliciousFlags::default(),
     );
 
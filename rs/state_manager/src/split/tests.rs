@@ -322,6 +322,7 @@ fn new_state_layout(log: ReplicaLogger) -> (TempDir, Time) {
         tip_handler,
         layout.clone(),
         state_manager_metrics.clone(),
+        FlagStatus::Disabled,
         MaliciousFlags::default(),
     );
 
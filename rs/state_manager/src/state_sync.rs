@@ -6,6 +6,7 @@ use crate::{
     NUMBER_OF_CHECKPOINT_THREADS,
 };
 use ic_base_types::NodeId;
+use ic_config::flag_status::FlagStatus;
 use ic_interfaces::{
     artifact_manager::{ArtifactClient, ArtifactProcessor},
     artifact_pool::UnvalidatedArtifact,
@@ -14,10 +15,11 @@ use ic_interfaces::{
 };
 use ic_interfaces_state_manager::{StateManager, CERT_CERTIFIED};
 use ic_logger::{info, warn, ReplicaLogger};
+use ic_state_layout::compression;
 use ic_types::{
     artifact::{
-        Advert, ArtifactKind, ArtifactTag, Priority, StateSyncArtifactId, StateSyncFilter,
-        StateSyncMessage,
+        Advert, ArtifactKind, ArtifactTag, Priority, StateSyncArtifactId, StateSyncChunkReader,
+        StateSyncFilter, StateSyncMessage,
     },
     chunkable::{ArtifactChunk, ChunkId, Chunkable, ChunkableArtifact},
     crypto::crypto_hash,
@@ -54,7 +56,7 @@ impl StateSync {
             id.height,
             id.hash.clone(),
             self.state_manager.state_layout.clone(),
-            self.state_manager.latest_manifest(),
+            self.state_manager.checkpoint_manifests(),
             self.state_manager.metrics.clone(),
             self.state_manager.own_subnet_type,
             Arc::new(Mutex::new(scoped_threadpool::Pool::new(
@@ -96,6 +98,32 @@ impl ArtifactKind for StateSyncArtifact {
     }
 }
 
+/// Reads the chunks of a checkpoint, decompressing the files stored compressed
+/// and, if `compress_chunks` is set, compressing the chunks for transmission.
+#[derive(Debug)]
+struct CheckpointChunkReader {
+    compress_chunks: bool,
+}
+
+impl StateSyncChunkReader for CheckpointChunkReader {
+    fn read_chunk(&self, msg: &StateSyncMessage, chunk_table_indices: &[u32]) -> Option<Vec<u8>> {
+        let mut payload = Vec::new();
+        for chunk_table_index in chunk_table_indices {
+            let chunk = msg.manifest.chunk_table.get(*chunk_table_index as usize)?;
+            let path = msg
+                .checkpoint_root
+                .join(&msg.manifest.file_table[chunk.file_index as usize].relative_path);
+            let start = payload.len();
+            payload.resize(start + chunk.size_bytes as usize, 0);
+            compression::read_exact_at(&path, &mut payload[start..], chunk.offset).ok()?;
+        }
+        if self.compress_chunks {
+            payload = compression::compress_chunk(payload);
+        }
+        Some(payload)
+    }
+}
+
 impl ArtifactClient<StateSyncArtifact> for StateSync {
     fn get_validated_by_identifier(
         &self,
@@ -133,6 +161,10 @@ impl ArtifactClient<StateSyncArtifact> for StateSync {
                         meta_manifest,
                         manifest: manifest.clone(),
                         state_sync_file_group,
+                        chunk_reader: Some(Arc::new(CheckpointChunkReader {
+                            compress_chunks: self.state_manager.state_sync_chunk_compression
+                                == FlagStatus::Enabled,
+                        })),
                     })
                 } else {
                     None
@@ -197,6 +229,7 @@ impl ArtifactClient<StateSyncArtifact> for StateSync {
                         manifest: manifest.clone(),
                         meta_manifest,
                         state_sync_file_group: Default::default(),
+                        chunk_reader: None,
                     };
                     Some(StateSyncArtifact::message_to_advert(&msg))
                 } else {
@@ -1,15 +1,17 @@
 use crate::{
     manifest::{build_file_group_chunks, filter_out_zero_chunks, DiffScript},
     StateManagerMetrics, StateSyncMetrics, StateSyncRefs,
-    CRITICAL_ERROR_STATE_SYNC_CORRUPTED_CHUNKS, LABEL_COPY_CHUNKS, LABEL_COPY_FILES, LABEL_FETCH,
-    LABEL_PREALLOCATE, LABEL_STATE_SYNC_MAKE_CHECKPOINT,
+    CRITICAL_ERROR_STATE_SYNC_CORRUPTED_CHUNKS, LABEL_COPY_CHUNKS, LABEL_COPY_FILES,
+    LABEL_COPY_OTHER_CHECKPOINTS, LABEL_FETCH, LABEL_PREALLOCATE, LABEL_STATE_SYNC_MAKE_CHECKPOINT,
 };
 use ic_logger::{debug, error, fatal, info, trace, warn, ReplicaLogger};
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::page_map::PageAllocatorFileDescriptor;
 use ic_state_layout::utils::do_copy_overwrite;
-use ic_state_layout::{error::LayoutError, CheckpointLayout, ReadOnly, RwPolicy, StateLayout};
-use ic_sys::mmap::ScopedMmap;
+use ic_state_layout::{
+    compression, error::LayoutError, CheckpointLayout, ReadOnly, RwPolicy, StateLayout,
+};
+use ic_sys::{mmap::ScopedMmap, PAGE_SIZE};
 use ic_types::{
     artifact::{Artifact, StateSyncMessage},
     chunkable::{
@@ -89,7 +91,8 @@ pub struct IncompleteState {
     height: Height,
     root_hash: CryptoHashOfState,
     state: DownloadState,
-    manifest_with_checkpoint_layout: Option<(Manifest, CheckpointLayout<ReadOnly>)>,
+    /// Manifests and layouts of the local checkpoints, latest first.
+    manifests_with_checkpoint_layouts: Vec<(Manifest, CheckpointLayout<ReadOnly>)>,
     metrics: StateManagerMetrics,
     started_at: Instant,
     fetch_started_at: Option<Instant>,
@@ -187,7 +190,7 @@ impl IncompleteState {
         height: Height,
         root_hash: CryptoHashOfState,
         state_layout: StateLayout,
-        manifest_with_checkpoint_layout: Option<(Manifest, CheckpointLayout<ReadOnly>)>,
+        manifests_with_checkpoint_layouts: Vec<(Manifest, CheckpointLayout<ReadOnly>)>,
         metrics: StateManagerMetrics,
         own_subnet_type: SubnetType,
         thread_pool: Arc<Mutex<scoped_threadpool::Pool>>,
@@ -212,7 +215,7 @@ impl IncompleteState {
             height,
             root_hash,
             state: DownloadState::Blank,
-            manifest_with_checkpoint_layout,
+            manifests_with_checkpoint_layouts,
             metrics,
             started_at: Instant::now(),
             fetch_started_at: None,
@@ -537,6 +540,44 @@ impl IncompleteState {
         }
     }
 
+    /// Turns the copy operations of the diff script whose source file is stored
+    /// compressed in the old checkpoint into fetch operations.
+    ///
+    /// The copy phase works on the raw bytes of the old files, which compressed
+    /// files don't provide. Only protobuf files are ever stored compressed, and
+    /// those are small and mostly change between checkpoints anyway.
+    pub(crate) fn fetch_compressed_files(
+        root_old: &Path,
+        manifest_old: &Manifest,
+        manifest_new: &Manifest,
+        diff_script: &mut DiffScript,
+    ) {
+        let is_compressed = |old_file_index: usize| {
+            let path = root_old.join(&manifest_old.file_table[old_file_index].relative_path);
+            !path.exists() && compression::compressed_file_path(&path).exists()
+        };
+
+        let mut fetch_chunks = Vec::new();
+        diff_script.copy_files.retain(|new_index, old_index| {
+            if !is_compressed(*old_index) {
+                return true;
+            }
+            fetch_chunks.extend(crate::manifest::file_chunk_range(
+                &manifest_new.chunk_table,
+                *new_index,
+            ));
+            false
+        });
+        diff_script.copy_chunks.retain(|new_index, old_index| {
+            if !is_compressed(manifest_old.chunk_table[*old_index].file_index as usize) {
+                return true;
+            }
+            fetch_chunks.push(*new_index);
+            false
+        });
+        diff_script.fetch_chunks.extend(fetch_chunks);
+    }
+
     /// Copy reusable chunks from previous checkpoint according to diff script.
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn copy_chunks(
@@ -757,16 +798,37 @@ impl IncompleteState {
             .create(false)
             .open(&path)
             .unwrap_or_else(|err| fatal!(log, "Failed to open file {}: {}", path.display(), err));
-        f.write_all_at(bytes, chunk.offset).unwrap_or_else(|err| {
-            fatal!(
-                log,
-                "Failed to write chunk (offset = {}, size = {}) to file {}: {}",
-                chunk.offset,
-                chunk.size_bytes,
-                path.display(),
-                err
-            )
-        });
+        // The file was preallocated without writing to it, so we can skip the
+        // all-zero pages of the chunk and keep them as holes. This keeps
+        // mostly-empty heaps small on disk.
+        let write = |start: usize, end: usize| {
+            f.write_all_at(&bytes[start..end], chunk.offset + start as u64)
+                .unwrap_or_else(|err| {
+                    fatal!(
+                        log,
+                        "Failed to write chunk (offset = {}, size = {}) to file {}: {}",
+                        chunk.offset,
+                        chunk.size_bytes,
+                        path.display(),
+                        err
+                    )
+                });
+        };
+        let mut run_start = None;
+        for (i, page) in bytes.chunks(PAGE_SIZE).enumerate() {
+            let is_zero = page.iter().all(|byte| *byte == 0);
+            match (run_start, is_zero) {
+                (None, false) => run_start = Some(i * PAGE_SIZE),
+                (Some(start), true) => {
+                    write(start, i * PAGE_SIZE);
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = run_start {
+            write(start, bytes.len());
+        }
         metrics.remaining.sub(1);
     }
 
@@ -787,9 +849,10 @@ impl IncompleteState {
                 .to_path_buf(),
             meta_manifest: Arc::new(meta_manifest.clone()),
             manifest: manifest.clone(),
-            // `state_sync_file_group` and `checkpoint_root` are not included in the integrity hash of this artifact.
+            // `state_sync_file_group`, `checkpoint_root` and `chunk_reader` are not included in the integrity hash of this artifact.
             // Therefore it is OK to pass a default value here as it is only used when fetching chunks.
             state_sync_file_group: Default::default(),
+            chunk_reader: None,
         })
     }
 
@@ -902,7 +965,8 @@ impl IncompleteState {
     }
 
     /// Preallocates the files listed in the manifest and copies the chunks
-    /// that we have locally.
+    /// that we have locally, either in the state sync cache or in any of the
+    /// local checkpoints.
     /// Returns a set of chunks that still need to be fetched
     fn initialize_state_on_disk(&mut self, manifest_new: &Manifest) -> HashSet<usize> {
         Self::preallocate_layout(&self.log, &self.root, manifest_new);
@@ -927,6 +991,11 @@ impl IncompleteState {
             .state_sync_metrics
             .size
             .with_label_values(&[LABEL_PREALLOCATE]);
+        let state_sync_size_copy_other_checkpoints = self
+            .metrics
+            .state_sync_metrics
+            .size
+            .with_label_values(&[LABEL_COPY_OTHER_CHECKPOINTS]);
         let total_bytes: u64 = manifest_new.file_table.iter().map(|f| f.size_bytes).sum();
 
         self.metrics
@@ -950,7 +1019,7 @@ impl IncompleteState {
         // Get a DiffData from the cache or checkpoint_layout, or neither
         let diff_data: Option<DiffData> = match (
             cache.as_ref(),
-            self.manifest_with_checkpoint_layout.as_ref(),
+            self.manifests_with_checkpoint_layouts.first(),
         ) {
             (Some(cache_entry), Some((checkpoint_manifest, checkpoint_layout))) => {
                 let cache_height = cache_entry.height;
@@ -1026,8 +1095,39 @@ impl IncompleteState {
                 },
                 height_old
             );
-            let diff_script =
+            let mut diff_script =
                 crate::manifest::diff_manifest(manifest_old, &missing_chunks, manifest_new);
+            Self::fetch_compressed_files(&root_old, manifest_old, manifest_new, &mut diff_script);
+
+            // Chunks that are not available at `root_old` may still be found in
+            // one of the other local checkpoints.
+            let other_checkpoints: Vec<_> = self
+                .manifests_with_checkpoint_layouts
+                .iter()
+                .filter(|(_, checkpoint_layout)| checkpoint_layout.raw_path() != root_old)
+                .collect();
+            let mut other_diff_scripts = crate::manifest::diff_manifests(
+                &other_checkpoints
+                    .iter()
+                    .map(|(manifest, _)| manifest)
+                    .collect::<Vec<_>>(),
+                &mut diff_script.fetch_chunks,
+                manifest_new,
+            );
+            for ((manifest, checkpoint_layout), other_diff_script) in
+                other_checkpoints.iter().zip(other_diff_scripts.iter_mut())
+            {
+                Self::fetch_compressed_files(
+                    checkpoint_layout.raw_path(),
+                    manifest,
+                    manifest_new,
+                    other_diff_script,
+                );
+                diff_script
+                    .fetch_chunks
+                    .extend(other_diff_script.fetch_chunks.drain());
+            }
+
             debug!(
                 self.log,
                 "State sync diff script (@{} -> @{}): {:?}", height_old, self.height, diff_script
@@ -1057,13 +1157,45 @@ impl IncompleteState {
                 .map(|i| manifest_new.file_table[*i].size_bytes)
                 .sum();
 
-            let copy_chunks_bytes: u64 =
-                total_bytes - diff_bytes - preallocate_bytes - copy_files_bytes;
+            let copy_other_checkpoints_bytes: u64 = other_diff_scripts
+                .iter()
+                .map(|other_diff_script| {
+                    let files_bytes: u64 = other_diff_script
+                        .copy_files
+                        .keys()
+                        .map(|i| manifest_new.file_table[*i].size_bytes)
+                        .sum();
+                    let chunks_bytes: u64 = other_diff_script
+                        .copy_chunks
+                        .keys()
+                        .map(|i| manifest_new.chunk_table[*i].size_bytes as u64)
+                        .sum();
+                    files_bytes + chunks_bytes
+                })
+                .sum();
+
+            let copy_chunks_bytes: u64 = total_bytes
+                - diff_bytes
+                - preallocate_bytes
+                - copy_files_bytes
+                - copy_other_checkpoints_bytes;
 
             state_sync_size_fetch.inc_by(diff_bytes);
             state_sync_size_preallocate.inc_by(preallocate_bytes);
             state_sync_size_copy_files.inc_by(copy_files_bytes);
             state_sync_size_copy_chunks.inc_by(copy_chunks_bytes);
+            state_sync_size_copy_other_checkpoints.inc_by(copy_other_checkpoints_bytes);
+
+            let reused_bytes = &self.metrics.state_sync_metrics.reused_bytes;
+            reused_bytes
+                .with_label_values(&[LABEL_COPY_FILES])
+                .observe(copy_files_bytes as f64);
+            reused_bytes
+                .with_label_values(&[LABEL_COPY_CHUNKS])
+                .observe(copy_chunks_bytes as f64);
+            reused_bytes
+                .with_label_values(&[LABEL_COPY_OTHER_CHECKPOINTS])
+                .observe(copy_other_checkpoints_bytes as f64);
 
             self.metrics
                 .state_sync_metrics
@@ -1097,6 +1229,53 @@ impl IncompleteState {
                 &mut fetch_chunks,
             );
 
+            for ((manifest, checkpoint_layout), other_diff_script) in
+                other_checkpoints.iter().zip(other_diff_scripts.iter())
+            {
+                if other_diff_script.copy_files.is_empty()
+                    && other_diff_script.copy_chunks.is_empty()
+                {
+                    continue;
+                }
+                info!(
+                    self.log,
+                    "Copying {} files and {} chunks for state sync @{} from checkpoint @{}",
+                    other_diff_script.copy_files.len(),
+                    other_diff_script.copy_chunks.len(),
+                    self.height,
+                    checkpoint_layout.height()
+                );
+                let validate_data = !self
+                    .state_sync_refs
+                    .cache
+                    .read()
+                    .state_is_fetched(checkpoint_layout.height());
+                Self::copy_files(
+                    &self.log,
+                    &self.metrics.state_sync_metrics,
+                    &mut thread_pool,
+                    checkpoint_layout.raw_path(),
+                    &self.root,
+                    manifest,
+                    manifest_new,
+                    other_diff_script,
+                    validate_data,
+                    &mut fetch_chunks,
+                );
+                Self::copy_chunks(
+                    &self.log,
+                    &self.metrics.state_sync_metrics,
+                    &mut thread_pool,
+                    checkpoint_layout.raw_path(),
+                    &self.root,
+                    manifest,
+                    manifest_new,
+                    other_diff_script,
+                    validate_data,
+                    &mut fetch_chunks,
+                );
+            }
+
             fetch_chunks
         } else {
             info!(
@@ -1398,41 +1577,39 @@ impl Chunkable for IncompleteState {
                     return Err(ChunksMoreNeeded);
                 }
 
-                // Each index in `chunk_table_indices` is mapped to a piece of payload bytes
-                // with its corresponding start and end position.
-                let (chunk_table_indices, payload_pieces) = match state_sync_chunk_type(ix) {
-                    StateSyncChunk::FileChunk(index) => {
-                        // If it is a normal chunk, there is only one index mapped to the whole payload.
-                        (vec![index], vec![(0, payload.len())])
-                    }
-                    StateSyncChunk::FileGroupChunk(index) => {
-                        // If it is a file group chunk, divide it into pieces according to the `FileGroupChunks`.
-                        let chunk_table_indices = state_sync_file_group
-                            .get(&index)
-                            .ok_or(ChunkVerificationFailed)?
-                            .clone();
-
-                        let mut cur_offset = 0;
-                        let mut payload_pieces: Vec<(usize, usize)> = Vec::new();
-                        for chunk_table_index in &chunk_table_indices {
-                            let chunk_size = manifest.chunk_table[*chunk_table_index as usize]
-                                .size_bytes as usize;
-                            payload_pieces.push((cur_offset, cur_offset + chunk_size));
-                            cur_offset += chunk_size;
-                        }
-
-                        if cur_offset != payload.len() {
-                            warn!(self.log, "Received invalid file group chunk {}", ix);
-                            return Err(ChunkVerificationFailed);
-                        }
-                        (chunk_table_indices, payload_pieces)
-                    }
+                let chunk_table_indices = match state_sync_chunk_type(ix) {
+                    // If it is a normal chunk, there is only one index mapped to the whole payload.
+                    StateSyncChunk::FileChunk(index) => vec![index],
+                    // If it is a file group chunk, the payload is the concatenation of the chunks
+                    // listed in the `FileGroupChunks`.
+                    StateSyncChunk::FileGroupChunk(index) => state_sync_file_group
+                        .get(&index)
+                        .ok_or(ChunkVerificationFailed)?
+                        .clone(),
                     _ => {
                         // meta-manifest/manifest chunks are not expected in the `Loading` phase.
                         return Err(ChunksMoreNeeded);
                     }
                 };
 
+                // Each index in `chunk_table_indices` is mapped to a piece of payload bytes
+                // with its corresponding start and end position.
+                let mut cur_offset = 0;
+                let mut payload_pieces: Vec<(usize, usize)> = Vec::new();
+                for chunk_table_index in &chunk_table_indices {
+                    let chunk_size =
+                        manifest.chunk_table[*chunk_table_index as usize].size_bytes as usize;
+                    payload_pieces.push((cur_offset, cur_offset + chunk_size));
+                    cur_offset += chunk_size;
+                }
+
+                // The sender may have compressed the payload, see `compression`.
+                let payload =
+                    compression::decompress_chunk(payload, cur_offset).map_err(|err| {
+                        warn!(self.log, "Received invalid chunk {}: {}", ix, err);
+                        ChunkVerificationFailed
+                    })?;
+
                 let log = &self.log;
                 let metrics = &self.metrics;
 
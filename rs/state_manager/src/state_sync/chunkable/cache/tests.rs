@@ -81,6 +81,7 @@ fn fake_complete() -> DownloadState {
         manifest,
         meta_manifest: Arc::new(meta_manifest),
         state_sync_file_group: Default::default(),
+        chunk_reader: None,
     });
     DownloadState::Complete(Box::new(artifact))
 }
@@ -119,7 +120,7 @@ fn incomplete_state_for_tests(
         height,
         hash,
         env.state_layout.clone(),
-        None,
+        Vec::new(),
         env.metrics.clone(),
         SubnetType::Application,
         Arc::new(Mutex::new(scoped_threadpool::Pool::new(NUM_THREADS))),
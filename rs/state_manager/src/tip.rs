@@ -5,16 +5,17 @@ use crate::{
 };
 use crossbeam_channel::{unbounded, Sender};
 use ic_base_types::subnet_id_into_protobuf;
+use ic_config::flag_status::FlagStatus;
 use ic_logger::{error, fatal, info, ReplicaLogger};
 use ic_protobuf::state::system_metadata::v1::{SplitFrom, SystemMetadata};
 #[allow(unused)]
 use ic_replicated_state::{
-    canister_state::execution_state::SandboxMemory, CanisterState, NumWasmPages, PageMap,
-    ReplicatedState,
+    canister_state::execution_state::SandboxMemory, page_map::WRITE_BUCKET_PAGES, CanisterState,
+    NumWasmPages, PageIndex, PageMap, ReplicatedState,
 };
 use ic_state_layout::{
-    error::LayoutError, CanisterStateBits, CheckpointLayout, ExecutionStateBits, ReadOnly,
-    RwPolicy, StateLayout, TipHandler,
+    compression, error::LayoutError, CanisterStateBits, CheckpointLayout, ExecutionStateBits,
+    ProtoFileWith, ReadOnly, RwPolicy, StateLayout, TipHandler, WritePolicy,
 };
 use ic_types::state_sync::{
     FILE_GROUP_CHUNK_ID_OFFSET, MANIFEST_CHUNK_ID_OFFSET, MAX_SUPPORTED_STATE_SYNC_VERSION,
@@ -137,8 +138,10 @@ pub(crate) fn spawn_tip_thread(
     mut tip_handler: TipHandler,
     state_layout: StateLayout,
     metrics: StateManagerMetrics,
+    checkpoint_file_compression: FlagStatus,
     malicious_flags: MaliciousFlags,
 ) -> (JoinOnDrop<()>, Sender<TipRequest>) {
+    let compress_files = checkpoint_file_compression == FlagStatus::Enabled;
     let (tip_sender, tip_receiver) = unbounded();
     let mut thread_pool = scoped_threadpool::Pool::new(NUMBER_OF_CHECKPOINT_THREADS);
     let mut tip_state = TipState::ReadyForPageDeltas(Height::from(0));
@@ -291,6 +294,7 @@ pub(crate) fn spawn_tip_thread(
                                     );
                                 }),
                                 &mut thread_pool,
+                                compress_files,
                             )
                             .unwrap_or_else(|err| {
                                 fatal!(log, "Failed to serialize to tip @{}: {}", height, err);
@@ -373,6 +377,7 @@ fn serialize_to_tip(
     state: &ReplicatedState,
     tip: &CheckpointLayout<RwPolicy<TipHandler>>,
     thread_pool: &mut scoped_threadpool::Pool,
+    compress_files: bool,
 ) -> Result<(), CheckpointError> {
     // Serialize ingress history separately. The `SystemMetadata` proto does not
     // encode it.
@@ -381,30 +386,37 @@ fn serialize_to_tip(
     // manifest file hashes (the ingress history is initially preserved unmodified
     // on both sides of the split, while the system metadata is not).
     let ingress_history = (&state.system_metadata().ingress_history).into();
-    tip.ingress_history().serialize(ingress_history)?;
+    serialize_proto(tip.ingress_history(), ingress_history, compress_files)?;
 
     let system_metadata: SystemMetadata = state.system_metadata().into();
-    tip.system_metadata().serialize(system_metadata)?;
+    serialize_proto(tip.system_metadata(), system_metadata, compress_files)?;
 
     // The split marker is also serialized separately from `SystemMetadata` because
     // preserving the latter unmodified during a split makes verification a matter
     // of comparing manifest file hashes.
     match state.system_metadata().split_from {
         Some(subnet_id) => {
-            tip.split_marker().serialize(SplitFrom {
-                subnet_id: Some(subnet_id_into_protobuf(subnet_id)),
-            })?;
+            serialize_proto(
+                tip.split_marker(),
+                SplitFrom {
+                    subnet_id: Some(subnet_id_into_protobuf(subnet_id)),
+                },
+                compress_files,
+            )?;
         }
         None => {
             tip.split_marker().try_remove_file()?;
         }
     }
 
-    tip.subnet_queues()
-        .serialize((state.subnet_queues()).into())?;
+    serialize_proto(
+        tip.subnet_queues(),
+        (state.subnet_queues()).into(),
+        compress_files,
+    )?;
 
     let results = parallel_map(thread_pool, state.canisters_iter(), |canister_state| {
-        serialize_canister_to_tip(log, canister_state, tip)
+        serialize_canister_to_tip(log, canister_state, tip, compress_files)
     });
 
     for result in results.into_iter() {
@@ -418,11 +430,14 @@ fn serialize_canister_to_tip(
     log: &ReplicaLogger,
     canister_state: &CanisterState,
     tip: &CheckpointLayout<RwPolicy<TipHandler>>,
+    compress_files: bool,
 ) -> Result<(), CheckpointError> {
     let canister_layout = tip.canister(&canister_state.canister_id())?;
-    canister_layout
-        .queues()
-        .serialize(canister_state.system_state.queues().into())?;
+    serialize_proto(
+        canister_layout.queues(),
+        canister_state.system_state.queues().into(),
+        compress_files,
+    )?;
 
     let execution_state_bits = match &canister_state.execution_state {
         Some(execution_state) => {
@@ -457,6 +472,16 @@ fn serialize_canister_to_tip(
                 .stable_memory
                 .page_map
                 .persist_delta(&canister_layout.stable_memory_blob())?;
+            if compress_files {
+                deallocate_zero_pages(
+                    &canister_layout.vmemory_0(),
+                    &execution_state.wasm_memory.page_map,
+                )?;
+                deallocate_zero_pages(
+                    &canister_layout.stable_memory_blob(),
+                    &execution_state.stable_memory.page_map,
+                )?;
+            }
 
             Some(ExecutionStateBits {
                 exported_globals: execution_state.exported_globals.clone(),
@@ -477,7 +502,8 @@ fn serialize_canister_to_tip(
     };
     // Priority credit must be zero at this point
     assert_eq!(canister_state.scheduler_state.priority_credit.get(), 0);
-    canister_layout.canister().serialize(
+    serialize_proto(
+        canister_layout.canister(),
         CanisterStateBits {
             controllers: canister_state.system_state.controllers.clone(),
             last_full_execution_round: canister_state.scheduler_state.last_full_execution_round,
@@ -537,12 +563,63 @@ fn serialize_canister_to_tip(
                 .get_consumed_cycles_since_replica_started_by_use_cases()
                 .clone(),
             canister_history: canister_state.system_state.get_canister_history().clone(),
+            total_query_stats: canister_state.system_state.total_query_stats.clone(),
+            wasm_memory_limit: canister_state.system_state.wasm_memory_limit,
+            wasm_memory_threshold: canister_state.system_state.wasm_memory_threshold,
+            on_low_wasm_memory_hook_status: canister_state
+                .system_state
+                .on_low_wasm_memory_hook_status(),
+            reserved_balance_limit: canister_state.system_state.reserved_balance_limit(),
         }
         .into(),
+        compress_files,
     )?;
     Ok(())
 }
 
+/// Serializes `value` into `file`, zstd-compressed if `compress_files` is set.
+fn serialize_proto<T: prost::Message, P: WritePolicy>(
+    file: ProtoFileWith<T, P>,
+    value: T,
+    compress_files: bool,
+) -> Result<(), LayoutError> {
+    if compress_files {
+        file.serialize_compressed(value)
+    } else {
+        file.serialize(value)
+    }
+}
+
+/// Deallocates the all-zero pages that persisting the delta of `page_map`
+/// wrote to the PageMap file at `path`. PageMap files are mmapped and can't be
+/// compressed, but this keeps mostly-empty heaps small on disk.
+fn deallocate_zero_pages(path: &Path, page_map: &PageMap) -> Result<(), CheckpointError> {
+    // Persisting a delta writes the whole bucket of every dirty page.
+    let num_pages = page_map.num_host_pages() as u64;
+    let buckets: BTreeSet<u64> = page_map
+        .get_page_delta_indices()
+        .iter()
+        .map(|index| index.get() / WRITE_BUCKET_PAGES)
+        .collect();
+    if buckets.is_empty() {
+        return Ok(());
+    }
+    let pages = buckets
+        .into_iter()
+        .flat_map(|bucket| {
+            bucket * WRITE_BUCKET_PAGES..((bucket + 1) * WRITE_BUCKET_PAGES).min(num_pages)
+        })
+        .map(|index| {
+            let index = PageIndex::new(index);
+            (index, page_map.get_page(index))
+        });
+    compression::deallocate_zero_pages(path, pages).map_err(|io_err| CheckpointError::IoError {
+        path: path.to_path_buf(),
+        message: "failed to deallocate zero pages".to_string(),
+        io_err: io_err.to_string(),
+    })
+}
+
 /// Defragments part of the tip directory.
 ///
 /// The way we use PageMap files in the tip, namely by having a
@@ -794,8 +871,14 @@ mod test {
             let metrics_registry = ic_metrics::MetricsRegistry::new();
             let metrics = StateManagerMetrics::new(&metrics_registry);
             let tip_handler = layout.capture_tip_handler();
-            let (_h, _s) =
-                spawn_tip_thread(log, tip_handler, layout, metrics, MaliciousFlags::default());
+            let (_h, _s) = spawn_tip_thread(
+                log,
+                tip_handler,
+                layout,
+                metrics,
+                FlagStatus::Disabled,
+                MaliciousFlags::default(),
+            );
         });
     }
 
@@ -575,10 +575,12 @@ fn state_manager_test_with_state_sync_and_verifier_result<
     F: FnOnce(&MetricsRegistry, Arc<StateManagerImpl>, StateSync),
 >(
     should_pass_verification: bool,
+    modify_config: impl FnOnce(&mut Config),
     f: F,
 ) {
     let tmp = tmpdir("sm");
-    let config = Config::new(tmp.path().into());
+    let mut config = Config::new(tmp.path().into());
+    modify_config(&mut config);
     let metrics_registry = MetricsRegistry::new();
     let own_subnet = subnet_test_id(42);
     let verifier: Arc<dyn Verifier> = if should_pass_verification {
@@ -611,7 +613,16 @@ pub fn state_manager_test_with_state_sync<
 >(
     f: F,
 ) {
-    state_manager_test_with_state_sync_and_verifier_result(true, f)
+    state_manager_test_with_state_sync_and_verifier_result(true, |_| {}, f)
+}
+
+pub fn state_manager_test_with_state_sync_and_config<
+    F: FnOnce(&MetricsRegistry, Arc<StateManagerImpl>, StateSync),
+>(
+    modify_config: impl FnOnce(&mut Config),
+    f: F,
+) {
+    state_manager_test_with_state_sync_and_verifier_result(true, modify_config, f)
 }
 
 pub fn state_manager_restart_test_deleting_metadata<Test>(test: Test)
@@ -1,5 +1,5 @@
 use ic_certification_version::{CertificationVersion::V11, CURRENT_CERTIFICATION_VERSION};
-use ic_config::state_manager::Config;
+use ic_config::{flag_status::FlagStatus, state_manager::Config};
 use ic_crypto_tree_hash::{
     flatmap, sparse_labeled_tree_from_paths, Label, LabeledTree, MixedHashTree, Path as LabelPath,
 };
@@ -1891,6 +1891,87 @@ fn can_do_simple_state_sync_transfer() {
     })
 }
 
+#[test]
+fn can_do_state_sync_transfer_with_compression() {
+    let enable_compression = |config: &mut Config| {
+        config.checkpoint_file_compression = FlagStatus::Enabled;
+        config.state_sync_chunk_compression = FlagStatus::Enabled;
+    };
+
+    // Compression must not affect the state hash.
+    let uncompressed_hash = {
+        let mut hash = None;
+        state_manager_test(|_metrics, state_manager| {
+            let (_height, mut state) = state_manager.take_tip();
+            insert_dummy_canister(&mut state, canister_test_id(100));
+            state_manager.commit_and_certify(state, height(1), CertificationScope::Full);
+            hash = Some(wait_for_checkpoint(&state_manager, height(1)));
+        });
+        hash.unwrap()
+    };
+
+    state_manager_test_with_state_sync_and_config(
+        enable_compression,
+        |src_metrics, src_state_manager, src_state_sync| {
+            let (_height, mut state) = src_state_manager.take_tip();
+            insert_dummy_canister(&mut state, canister_test_id(100));
+            let time_source = ic_test_utilities::FastForwardTimeSource::new();
+
+            src_state_manager.commit_and_certify(state, height(1), CertificationScope::Full);
+            let hash = wait_for_checkpoint(&*src_state_manager, height(1));
+            assert_eq!(hash, uncompressed_hash);
+
+            let checkpoint_root = src_state_manager
+                .state_layout()
+                .checkpoint(height(1))
+                .unwrap()
+                .raw_path()
+                .to_path_buf();
+            assert!(!checkpoint_root.join(SYSTEM_METADATA_FILE).exists());
+            assert!(checkpoint_root
+                .join(format!("{}.zst", SYSTEM_METADATA_FILE))
+                .exists());
+
+            let id = StateSyncArtifactId {
+                height: height(1),
+                hash,
+            };
+            let state = src_state_manager.get_latest_state().take();
+            let msg = src_state_sync
+                .get_validated_by_identifier(&id)
+                .expect("failed to get state sync messages");
+            assert!(msg.chunk_reader.is_some());
+
+            assert_error_counters(src_metrics);
+
+            state_manager_test_with_state_sync(|dst_metrics, dst_state_manager, dst_state_sync| {
+                let chunkable = dst_state_sync.create_chunkable_state(&id);
+
+                let dst_msg = pipe_state_sync(msg, chunkable);
+                dst_state_sync.process_changes(
+                    time_source.as_ref(),
+                    vec![UnvalidatedArtifact {
+                        message: dst_msg,
+                        peer_id: node_test_id(0),
+                        timestamp: mock_time(),
+                    }],
+                );
+
+                let recovered_state = dst_state_manager
+                    .get_state_at(height(1))
+                    .expect("Destination state manager didn't receive the state")
+                    .take();
+
+                assert_eq!(height(1), dst_state_manager.latest_state_height());
+                assert_eq!(state, recovered_state);
+
+                assert_error_counters(dst_metrics);
+                assert_no_remaining_chunks(dst_metrics);
+            })
+        },
+    )
+}
+
 #[test]
 fn state_sync_message_returns_none_for_invalid_chunk_requests() {
     state_manager_test_with_state_sync(|_, src_state_manager, src_state_sync| {
@@ -2667,6 +2748,131 @@ fn can_state_sync_based_on_old_checkpoint() {
     });
 }
 
+/// Syncs to a state whose canister 100 only exists in an older local
+/// checkpoint of the destination, not in its latest one. If `corrupt` is
+/// set, the canister's memory in that older checkpoint is corrupted before
+/// the sync.
+fn state_sync_reusing_older_checkpoint(corrupt: bool) {
+    let populate_canister = |state: &mut ReplicatedState| {
+        insert_dummy_canister(state, canister_test_id(100));
+        let canister_state = state.canister_state_mut(&canister_test_id(100)).unwrap();
+        let execution_state = canister_state.execution_state.as_mut().unwrap();
+        execution_state.wasm_memory.page_map.update(&[
+            (PageIndex::new(0), &[100u8; PAGE_SIZE]),
+            (PageIndex::new(1), &[101u8; PAGE_SIZE]),
+        ]);
+    };
+
+    state_manager_test_with_state_sync(|src_metrics, src_state_manager, src_state_sync| {
+        let (_height, state) = src_state_manager.take_tip();
+        src_state_manager.commit_and_certify(state, height(1), CertificationScope::Metadata);
+        let (_height, state) = src_state_manager.take_tip();
+        src_state_manager.commit_and_certify(state, height(2), CertificationScope::Metadata);
+
+        let (_height, mut state) = src_state_manager.take_tip();
+        populate_canister(&mut state);
+        src_state_manager.commit_and_certify(state, height(3), CertificationScope::Full);
+        let time_source = ic_test_utilities::FastForwardTimeSource::new();
+
+        let hash = wait_for_checkpoint(&*src_state_manager, height(3));
+        let id = StateSyncArtifactId {
+            height: height(3),
+            hash,
+        };
+        let msg = src_state_sync
+            .get_validated_by_identifier(&id)
+            .expect("failed to get state sync message");
+
+        assert_error_counters(src_metrics);
+
+        state_manager_test_with_state_sync(|dst_metrics, dst_state_manager, dst_state_sync| {
+            let (_height, mut state) = dst_state_manager.take_tip();
+            populate_canister(&mut state);
+            dst_state_manager.commit_and_certify(state, height(1), CertificationScope::Full);
+            wait_for_checkpoint(&*dst_state_manager, height(1));
+
+            // The latest checkpoint does not contain canister 100.
+            let (_height, mut state) = dst_state_manager.take_tip();
+            state.take_canister_state(&canister_test_id(100));
+            insert_dummy_canister(&mut state, canister_test_id(300));
+            dst_state_manager.commit_and_certify(state, height(2), CertificationScope::Full);
+            wait_for_checkpoint(&*dst_state_manager, height(2));
+
+            if corrupt {
+                use ic_state_layout::{CheckpointLayout, RwPolicy};
+
+                let state_layout = dst_state_manager.state_layout();
+                let mutable_cp_layout = CheckpointLayout::<RwPolicy<()>>::new_untracked(
+                    state_layout
+                        .checkpoint(height(1))
+                        .unwrap()
+                        .raw_path()
+                        .to_path_buf(),
+                    height(1),
+                )
+                .unwrap();
+                let canister_memory = mutable_cp_layout
+                    .canister(&canister_test_id(100))
+                    .unwrap()
+                    .vmemory_0();
+                make_mutable(&canister_memory).unwrap();
+                write_all_at(&canister_memory, &[3u8; PAGE_SIZE], PAGE_SIZE as u64).unwrap();
+            }
+
+            let chunkable = dst_state_sync.create_chunkable_state(&id);
+            let dst_msg = pipe_state_sync(msg, chunkable);
+            dst_state_sync.process_changes(
+                time_source.as_ref(),
+                vec![UnvalidatedArtifact {
+                    message: dst_msg,
+                    peer_id: node_test_id(0),
+                    timestamp: mock_time(),
+                }],
+            );
+
+            let expected_state = src_state_manager.get_latest_state();
+
+            assert_eq!(dst_state_manager.get_latest_state(), expected_state);
+
+            let mut tip = dst_state_manager.take_tip().1;
+            let state = expected_state.take();
+            // Because `take_tip()` modifies the `prev_state_hash`, we change it back to compare the rest of state.
+            tip.metadata.prev_state_hash = state.metadata.prev_state_hash.clone();
+            assert_eq!(tip, *state.as_ref());
+
+            let copy_other_checkpoints_label =
+                maplit::btreemap! {"op".to_string() => "copy_other_checkpoints".to_string()};
+            let copy_other_checkpoints_bytes =
+                fetch_int_counter_vec(dst_metrics, "state_sync_size_bytes_total")
+                    [&copy_other_checkpoints_label];
+            let corrupted_chunks =
+                fetch_int_counter_vec(dst_metrics, "state_sync_corrupted_chunks")
+                    .values()
+                    .sum::<u64>();
+            if corrupt {
+                // The corrupted chunk was detected and fetched instead.
+                assert!(corrupted_chunks > 0);
+            } else {
+                assert!(copy_other_checkpoints_bytes > 0);
+                assert_eq!(corrupted_chunks, 0);
+            }
+
+            assert_no_remaining_chunks(dst_metrics);
+            assert_error_counters(dst_metrics);
+        })
+    });
+}
+
+#[test]
+fn can_state_sync_reusing_chunks_from_older_checkpoint() {
+    state_sync_reusing_older_checkpoint(false);
+}
+
+#[test]
+fn can_recover_from_corruption_in_older_checkpoint_on_state_sync() {
+    state_sync_reusing_older_checkpoint(true);
+}
+
 #[test]
 fn can_recover_from_corruption_on_state_sync() {
     use ic_state_layout::{CheckpointLayout, RwPolicy};
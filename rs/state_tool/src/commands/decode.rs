@@ -8,14 +8,16 @@ use ic_replicated_state::{
     canister_state::CanisterQueues, metadata_state::IngressHistoryState, SystemMetadata,
 };
 use ic_state_layout::{
-    CanisterStateBits, ProtoFileWith, ReadOnly, CANISTER_FILE, INGRESS_HISTORY_FILE, QUEUES_FILE,
-    SUBNET_QUEUES_FILE, SYSTEM_METADATA_FILE,
+    compression, CanisterStateBits, ProtoFileWith, ReadOnly, CANISTER_FILE, INGRESS_HISTORY_FILE,
+    QUEUES_FILE, SUBNET_QUEUES_FILE, SYSTEM_METADATA_FILE,
 };
 use std::convert::TryFrom;
 use std::path::PathBuf;
 
-/// Decodes the `.pbuf` state file located at `path`.
+/// Decodes the `.pbuf` state file located at `path`. Compressed state files
+/// (`.pbuf.zst`) are decompressed transparently.
 pub fn do_decode(path: PathBuf) -> Result<(), String> {
+    let path = compression::uncompressed_file_path(&path).unwrap_or(path);
     let fname = path
         .file_name()
         .ok_or_else(|| format!("failed to get file name of path {}", path.display()))?
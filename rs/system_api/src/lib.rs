@@ -7,12 +7,13 @@ pub mod system_api_empty;
 
 use ic_base_types::PrincipalIdBlobParseError;
 use ic_config::flag_status::FlagStatus;
+use ic_cycles_account_manager::ResourceSaturation;
 use ic_error_types::RejectCode;
 use ic_interfaces::execution_environment::{
     ExecutionComplexity, ExecutionMode,
     HypervisorError::{self, *},
     HypervisorResult, OutOfInstructionsHandler, PerformanceCounterType, StableGrowOutcome,
-    StableMemoryApi, SubnetAvailableMemory, SystemApi,
+    StableMemoryApi, SubnetAvailableMemory, SystemApi, SystemApiCallCounters,
     TrapCode::{self, CyclesAmountTooBigFor64Bit},
 };
 use ic_logger::{error, ReplicaLogger};
@@ -319,11 +320,13 @@ pub enum ApiType {
         message_accepted: bool,
     },
 
-    // For executing the `canister_heartbeat` or `canister_global_timer` methods
+    // For executing the `canister_heartbeat`, `canister_global_timer` or
+    // `canister_on_low_wasm_memory` methods
     SystemTask {
         caller: PrincipalId,
         /// System task to execute.
-        /// Only `canister_heartbeat` and `canister_global_timer` are allowed.
+        /// Only `canister_heartbeat`, `canister_global_timer` and
+        /// `canister_on_low_wasm_memory` are allowed.
         system_task: SystemMethod,
         time: Time,
         call_context_id: CallContextId,
@@ -541,7 +544,11 @@ impl ApiType {
             ApiType::SystemTask { system_task, .. } => match system_task {
                 SystemMethod::CanisterHeartbeat => "heartbeat",
                 SystemMethod::CanisterGlobalTimer => "global timer",
-                _ => panic!("Only `canister_heartbeat` and `canister_global_timer` are allowed."),
+                SystemMethod::CanisterOnLowWasmMemory => "on low wasm memory",
+                _ => panic!(
+                    "Only `canister_heartbeat`, `canister_global_timer` and \
+                    `canister_on_low_wasm_memory` are allowed."
+                ),
             },
             ApiType::Update { .. } => "update",
             ApiType::ReplicatedQuery { .. } => "replicated query",
@@ -591,9 +598,16 @@ struct MemoryUsage {
 
     /// The memory allocation of the canister.
     memory_allocation: MemoryAllocation,
+
+    /// The subnet memory usage above which memory growth reserves cycles.
+    subnet_memory_threshold: NumBytes,
+
+    /// The total execution memory of the subnet.
+    subnet_memory_capacity: NumBytes,
 }
 
 impl MemoryUsage {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         log: ReplicaLogger,
         canister_id: CanisterId,
@@ -601,6 +615,8 @@ impl MemoryUsage {
         current_usage: NumBytes,
         subnet_available_memory: SubnetAvailableMemory,
         memory_allocation: MemoryAllocation,
+        subnet_memory_threshold: NumBytes,
+        subnet_memory_capacity: NumBytes,
     ) -> Self {
         // A canister's current usage should never exceed its limit. This is
         // most probably a bug. Panicking here due to this inconsistency has the
@@ -622,6 +638,8 @@ impl MemoryUsage {
             allocated_execution_memory: NumBytes::from(0),
             allocated_message_memory: NumBytes::from(0),
             memory_allocation,
+            subnet_memory_threshold,
+            subnet_memory_capacity,
         }
     }
 
@@ -633,12 +651,17 @@ impl MemoryUsage {
     ///
     /// Returns `Err(HypervisorError::InsufficientCyclesInMemoryGrow)` and
     /// leaves `self` unchanged if freezing threshold check is needed for the
-    /// given API type and canister would be frozen after the allocation.
+    /// given API type and canister would be frozen after the allocation, or
+    /// if the canister cannot cover the storage reservation.
+    ///
+    /// Returns `Err(HypervisorError::ReservedCyclesLimitExceededInMemoryGrow)`
+    /// and leaves `self` unchanged if the storage reservation would exceed
+    /// the reserved cycles limit of the canister.
     fn allocate_pages(
         &mut self,
         pages: usize,
         api_type: &ApiType,
-        sandbox_safe_system_state: &SandboxSafeSystemState,
+        sandbox_safe_system_state: &mut SandboxSafeSystemState,
     ) -> HypervisorResult<()> {
         let bytes = ic_replicated_state::num_bytes_try_from(NumWasmPages::from(pages))
             .map_err(|_| HypervisorError::OutOfMemory)?;
@@ -656,12 +679,17 @@ impl MemoryUsage {
     ///
     /// Returns `Err(HypervisorError::InsufficientCyclesInMemoryGrow)` and
     /// leaves `self` unchanged if freezing threshold check is needed for the
-    /// given API type and canister would be frozen after the allocation.
+    /// given API type and canister would be frozen after the allocation, or
+    /// if the canister cannot cover the storage reservation.
+    ///
+    /// Returns `Err(HypervisorError::ReservedCyclesLimitExceededInMemoryGrow)`
+    /// and leaves `self` unchanged if the storage reservation would exceed
+    /// the reserved cycles limit of the canister.
     fn allocate_execution_memory(
         &mut self,
         execution_bytes: NumBytes,
         api_type: &ApiType,
-        sandbox_safe_system_state: &SandboxSafeSystemState,
+        sandbox_safe_system_state: &mut SandboxSafeSystemState,
     ) -> HypervisorResult<()> {
         let (new_usage, overflow) = self
             .current_usage
@@ -682,12 +710,29 @@ impl MemoryUsage {
         // at the time of reservation.
         match self.memory_allocation {
             MemoryAllocation::BestEffort => {
+                let subnet_memory_saturation = ResourceSaturation::from_subnet_available_memory(
+                    &self.subnet_available_memory,
+                    self.subnet_memory_threshold,
+                    self.subnet_memory_capacity,
+                );
                 match self.subnet_available_memory.try_decrement(
                     execution_bytes,
                     NumBytes::from(0),
                     NumBytes::from(0),
                 ) {
                     Ok(()) => {
+                        if let Err(err) = sandbox_safe_system_state.reserve_storage_cycles(
+                            execution_bytes,
+                            &subnet_memory_saturation,
+                            api_type,
+                        ) {
+                            self.subnet_available_memory.increment(
+                                execution_bytes,
+                                NumBytes::from(0),
+                                NumBytes::from(0),
+                            );
+                            return Err(err);
+                        }
                         self.current_usage = NumBytes::from(new_usage);
                         self.allocated_execution_memory += execution_bytes;
                         Ok(())
@@ -793,6 +838,10 @@ pub struct SystemApiImpl {
 
     /// Tracks the complexity accumulated during the message execution.
     execution_complexity: ExecutionComplexity,
+
+    /// Counts the calls to system APIs that depend on the environment of the
+    /// execution, such as the time or the data certificate.
+    call_counters: SystemApiCallCounters,
 }
 
 impl SystemApiImpl {
@@ -816,6 +865,8 @@ impl SystemApiImpl {
             canister_current_memory_usage,
             subnet_available_memory,
             execution_parameters.memory_allocation,
+            execution_parameters.subnet_memory_threshold,
+            execution_parameters.subnet_memory_capacity,
         );
         let stable_memory = StableMemory::new(stable_memory);
         let slice_limit = execution_parameters.instruction_limits.slice().get();
@@ -833,6 +884,7 @@ impl SystemApiImpl {
             current_slice_instruction_limit: i64::try_from(slice_limit).unwrap_or(i64::MAX),
             instructions_executed_before_current_slice: 0,
             execution_complexity: ExecutionComplexity::default(),
+            call_counters: SystemApiCallCounters::default(),
         }
     }
 
@@ -918,6 +970,11 @@ impl SystemApiImpl {
         self.memory_usage.current_usage
     }
 
+    /// Returns the counters of environment-dependent system API calls.
+    pub fn call_counters(&self) -> SystemApiCallCounters {
+        self.call_counters
+    }
+
     /// Bytes allocated in the Wasm/stable memory.
     pub fn get_allocated_bytes(&self) -> NumBytes {
         self.memory_usage.allocated_execution_memory
@@ -2190,7 +2247,8 @@ impl SystemApi for SystemApiImpl {
         Ok((dirty_pages, cost))
     }
 
-    fn ic0_time(&self) -> HypervisorResult<Time> {
+    fn ic0_time(&mut self) -> HypervisorResult<Time> {
+        self.call_counters.time += 1;
         let result = match &self.api_type {
             ApiType::Start { .. } => Err(self.error_for("ic0_time")),
             ApiType::Init { time, .. }
@@ -2303,13 +2361,30 @@ impl SystemApi for SystemApiImpl {
                 .map(NumBytes::new)
                 .ok_or(HypervisorError::OutOfMemory)?;
 
+            // `memory.grow` reports Wasm pages; table growth is not subject to
+            // the Wasm memory limit.
+            if element_size == WASM_PAGE_SIZE_IN_BYTES as u64 {
+                if let Some(limit) = self.sandbox_safe_system_state.wasm_memory_limit() {
+                    let new_size = (native_memory_grow_res as u64)
+                        .saturating_add(additional_elements)
+                        .saturating_mul(element_size);
+                    if new_size > limit.get() {
+                        return Err(HypervisorError::WasmMemoryLimitExceeded {
+                            bytes: NumBytes::new(new_size),
+                            limit,
+                        });
+                    }
+                }
+            }
+
             match self.memory_usage.allocate_execution_memory(
                 bytes,
                 &self.api_type,
-                &self.sandbox_safe_system_state,
+                &mut self.sandbox_safe_system_state,
             ) {
                 Ok(()) => Ok(()),
-                Err(err @ HypervisorError::InsufficientCyclesInMemoryGrow { .. }) => {
+                Err(err @ HypervisorError::InsufficientCyclesInMemoryGrow { .. })
+                | Err(err @ HypervisorError::ReservedCyclesLimitExceededInMemoryGrow { .. }) => {
                     // Return an out-of-cycles error instead of out-of-memory.
                     Err(err)
                 }
@@ -2350,10 +2425,11 @@ impl SystemApi for SystemApiImpl {
         match self.memory_usage.allocate_pages(
             additional_pages as usize,
             &self.api_type,
-            &self.sandbox_safe_system_state,
+            &mut self.sandbox_safe_system_state,
         ) {
             Ok(()) => Ok(StableGrowOutcome::Success),
-            Err(err @ HypervisorError::InsufficientCyclesInMemoryGrow { .. }) => {
+            Err(err @ HypervisorError::InsufficientCyclesInMemoryGrow { .. })
+            | Err(err @ HypervisorError::ReservedCyclesLimitExceededInMemoryGrow { .. }) => {
                 // Trap instead of returning -1 in order to give the developer
                 // more actionable error message. Otherwise, they cannot
                 // distinguish between out-of-memory and out-of-cycles.
@@ -2484,7 +2560,8 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
-    fn ic0_data_certificate_present(&self) -> HypervisorResult<i32> {
+    fn ic0_data_certificate_present(&mut self) -> HypervisorResult<i32> {
+        self.call_counters.data_certificate_present += 1;
         let result = match &self.api_type {
             ApiType::Start { .. } => Err(self.error_for("ic0_data_certificate_present")),
             ApiType::Init { .. }
@@ -2509,7 +2586,8 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
-    fn ic0_data_certificate_size(&self) -> HypervisorResult<i32> {
+    fn ic0_data_certificate_size(&mut self) -> HypervisorResult<i32> {
+        self.call_counters.data_certificate_size += 1;
         let result = match &self.api_type {
             ApiType::Start { .. }
             | ApiType::Init { .. }
@@ -2535,12 +2613,13 @@ impl SystemApi for SystemApiImpl {
     }
 
     fn ic0_data_certificate_copy(
-        &self,
+        &mut self,
         dst: u32,
         offset: u32,
         size: u32,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
+        self.call_counters.data_certificate_copy += 1;
         let result = match &self.api_type {
             ApiType::Start { .. }
             | ApiType::Init { .. }
@@ -3,7 +3,9 @@ use std::collections::{BTreeMap, BTreeSet};
 use crate::{routing::ResolveDestinationError, ApiType};
 use ic_base_types::{CanisterId, NumBytes, NumSeconds, PrincipalId, SubnetId};
 use ic_constants::{LOG_CANISTER_OPERATION_CYCLES_THRESHOLD, SMALL_APP_SUBNET_MAX_SIZE};
-use ic_cycles_account_manager::{CyclesAccountManager, CyclesAccountManagerError};
+use ic_cycles_account_manager::{
+    CyclesAccountManager, CyclesAccountManagerError, ResourceSaturation,
+};
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_ic00_types::{
     CreateCanisterArgs, InstallCodeArgs, Method as Ic00Method, Payload,
@@ -64,6 +66,9 @@ pub struct SystemStateChanges {
     request_slots_used: BTreeMap<CanisterId, usize>,
     requests: Vec<Request>,
     pub(super) new_global_timer: Option<CanisterTimer>,
+    /// Cycles moved from the main balance to the reserved balance. They are
+    /// included in `cycles_balance_change` as removed cycles.
+    reserved_cycles: Cycles,
 }
 
 impl Default for SystemStateChanges {
@@ -77,6 +82,7 @@ impl Default for SystemStateChanges {
             request_slots_used: BTreeMap::new(),
             requests: vec![],
             new_global_timer: None,
+            reserved_cycles: Cycles::zero(),
         }
     }
 }
@@ -429,12 +435,13 @@ impl SystemStateChanges {
         // - CyclesBalanceChange::added(cycles_accepted_from_the_call_context)
         // - CyclesBalanceChange::remove(cycles_sent_via_outgoing_calls)
         // - CyclesBalanceChange::remove(cycles_consumed_by_various_fees)
-        // This loop removes the last part from `self.cycles_balance_change`.
+        // - CyclesBalanceChange::remove(cycles_moved_to_reserved_balance)
+        // The code below removes the last two parts from `self.cycles_balance_change`.
         let mut result = self.cycles_balance_change;
         for (_use_case, amount) in self.consumed_cycles_by_use_case.iter() {
             result = result + CyclesBalanceChange::added(*amount)
         }
-        result
+        result + CyclesBalanceChange::added(self.reserved_cycles)
     }
 
     /// Applies the balance change to the given state.
@@ -453,6 +460,13 @@ impl SystemStateChanges {
         for (use_case, amount) in self.consumed_cycles_by_use_case.iter() {
             state.remove_cycles(*amount, *use_case);
         }
+        if self.reserved_cycles > Cycles::zero() {
+            // The sandbox has already checked the main balance and the
+            // reserved balance limit against the same state.
+            state
+                .reserve_cycles(self.reserved_cycles)
+                .expect("Reserving cycles should succeed after the sandbox check");
+        }
         // All changes applied above should be equivalent to simply applying
         // `self.cycles_balance_change` to the initial balance.
         let expected_balance = match self.cycles_balance_change {
@@ -514,6 +528,8 @@ pub struct SandboxSafeSystemState {
     global_timer: CanisterTimer,
     canister_version: u64,
     controllers: BTreeSet<PrincipalId>,
+    wasm_memory_limit: Option<NumBytes>,
+    reserved_balance_limit: Option<Cycles>,
 }
 
 impl SandboxSafeSystemState {
@@ -539,6 +555,8 @@ impl SandboxSafeSystemState {
         global_timer: CanisterTimer,
         canister_version: u64,
         controllers: BTreeSet<PrincipalId>,
+        wasm_memory_limit: Option<NumBytes>,
+        reserved_balance_limit: Option<Cycles>,
     ) -> Self {
         Self {
             canister_id,
@@ -561,6 +579,8 @@ impl SandboxSafeSystemState {
             global_timer,
             canister_version,
             controllers,
+            wasm_memory_limit,
+            reserved_balance_limit,
         }
     }
 
@@ -624,6 +644,8 @@ impl SandboxSafeSystemState {
             system_state.global_timer,
             system_state.canister_version,
             system_state.controllers.clone(),
+            system_state.wasm_memory_limit,
+            system_state.reserved_balance_limit(),
         )
     }
 
@@ -639,6 +661,10 @@ impl SandboxSafeSystemState {
         self.canister_version
     }
 
+    pub fn wasm_memory_limit(&self) -> Option<NumBytes> {
+        self.wasm_memory_limit
+    }
+
     pub fn set_global_timer(&mut self, timer: CanisterTimer) {
         // Update both sandbox global timer and the changes.
         self.system_state_changes.new_global_timer = Some(timer);
@@ -929,6 +955,79 @@ impl SandboxSafeSystemState {
         }
     }
 
+    /// Moves cycles from the main balance to the reserved balance to pay for
+    /// future storage fees of `allocated_bytes` if that's needed for the
+    /// given API type. The amount depends on how saturated the subnet memory
+    /// is before the allocation.
+    ///
+    /// Returns `Err(HypervisorError::ReservedCyclesLimitExceededInMemoryGrow)`
+    /// if the reserved balance would exceed its limit and
+    /// `Err(HypervisorError::InsufficientCyclesInMemoryGrow)` if the main
+    /// balance cannot cover the reservation.
+    pub(super) fn reserve_storage_cycles(
+        &mut self,
+        allocated_bytes: NumBytes,
+        subnet_memory_saturation: &ResourceSaturation,
+        api_type: &ApiType,
+    ) -> HypervisorResult<()> {
+        if !self.should_reserve_storage_cycles(api_type) {
+            return Ok(());
+        }
+        let cycles_to_reserve = self.cycles_account_manager.storage_reservation_cycles(
+            allocated_bytes,
+            subnet_memory_saturation,
+            self.subnet_size,
+        );
+        if cycles_to_reserve == Cycles::zero() {
+            return Ok(());
+        }
+        if let Some(limit) = self.reserved_balance_limit {
+            let requested = self.reserved_balance + cycles_to_reserve;
+            if requested > limit {
+                return Err(HypervisorError::ReservedCyclesLimitExceededInMemoryGrow {
+                    bytes: allocated_bytes,
+                    requested,
+                    limit,
+                });
+            }
+        }
+        let old_balance = self.cycles_balance();
+        if old_balance < cycles_to_reserve {
+            return Err(HypervisorError::InsufficientCyclesInMemoryGrow {
+                bytes: allocated_bytes,
+                available: old_balance,
+                threshold: cycles_to_reserve,
+            });
+        }
+        self.update_balance_change(old_balance - cycles_to_reserve);
+        self.reserved_balance += cycles_to_reserve;
+        self.system_state_changes.reserved_cycles += cycles_to_reserve;
+        Ok(())
+    }
+
+    // Returns `true` if storage cycles need to be reserved for the given API
+    // type when growing memory.
+    fn should_reserve_storage_cycles(&self, api_type: &ApiType) -> bool {
+        match api_type {
+            ApiType::Update { .. }
+            | ApiType::SystemTask { .. }
+            | ApiType::Start { .. }
+            | ApiType::Init { .. }
+            | ApiType::PreUpgrade { .. }
+            | ApiType::ReplyCallback { .. }
+            | ApiType::RejectCallback { .. }
+            | ApiType::Cleanup { .. } => true,
+
+            ApiType::InspectMessage { .. }
+            | ApiType::ReplicatedQuery { .. }
+            | ApiType::NonReplicatedQuery { .. } => {
+                // Queries do not reserve cycles because the state changes are
+                // discarded anyways.
+                false
+            }
+        }
+    }
+
     // Returns `true` if the freezing threshold needs to be checked for the given
     // API type when growing memory.
     fn should_check_freezing_threshold_for_memory_grow(&self, api_type: &ApiType) -> bool {
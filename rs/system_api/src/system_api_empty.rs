@@ -174,7 +174,7 @@ impl SystemApi for SystemApiEmpty {
     fn ic0_stable64_write(&mut self, _: u64, _: u64, _: u64, _: &[u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_time(&self) -> HypervisorResult<Time> {
+    fn ic0_time(&mut self) -> HypervisorResult<Time> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_global_timer_set(&mut self, _time: Time) -> HypervisorResult<Time> {
@@ -235,14 +235,14 @@ impl SystemApi for SystemApiEmpty {
     fn ic0_certified_data_set(&mut self, _: u32, _: u32, _: &[u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_data_certificate_present(&self) -> HypervisorResult<i32> {
+    fn ic0_data_certificate_present(&mut self) -> HypervisorResult<i32> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_data_certificate_size(&self) -> HypervisorResult<i32> {
+    fn ic0_data_certificate_size(&mut self) -> HypervisorResult<i32> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_data_certificate_copy(
-        &self,
+        &mut self,
         _: u32,
         _: u32,
         _: u32,
@@ -1285,7 +1285,7 @@ fn certified_data_set() {
 fn data_certificate_copy() {
     let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
     let system_state = SystemStateBuilder::default().build();
-    let api = get_system_api(
+    let mut api = get_system_api(
         ApiType::replicated_query(
             mock_time(),
             vec![],
@@ -1314,6 +1314,32 @@ fn data_certificate_copy() {
     assert_eq!(heap, vec![1, 2, 3, 4, 5, 6, 3, 4, 5, 6]);
 }
 
+#[test]
+fn data_certificate_reads_are_counted() {
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    let system_state = SystemStateBuilder::default().build();
+    let mut api = get_system_api(
+        ApiType::replicated_query(
+            mock_time(),
+            vec![],
+            user_test_id(1).get(),
+            Some(vec![1, 2, 3]),
+        ),
+        &system_state,
+        cycles_account_manager,
+    );
+    assert!(!api.call_counters().reads_data_certificate());
+
+    assert_eq!(api.ic0_data_certificate_present().unwrap(), 1);
+    assert_eq!(api.ic0_data_certificate_size().unwrap(), 3);
+    assert_eq!(api.ic0_data_certificate_size().unwrap(), 3);
+    let counters = api.call_counters();
+    assert_eq!(counters.data_certificate_present, 1);
+    assert_eq!(counters.data_certificate_size, 2);
+    assert_eq!(counters.data_certificate_copy, 0);
+    assert!(counters.reads_data_certificate());
+}
+
 #[test]
 fn canister_status() {
     let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
@@ -15,9 +15,10 @@ use ic_embedders::{wasm_utils::compile, WasmtimeEmbedder};
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 pub use ic_execution_environment::ExecutionResponse;
 use ic_execution_environment::{
-    execute_canister, init_query_stats, util::process_stopping_canisters, CompilationCostHandling,
+    execute_canister, execution::nonreplicated_query::execute_non_replicated_query,
+    init_query_stats, util::process_stopping_canisters, CompilationCostHandling,
     ExecuteMessageResult, ExecutionEnvironment, Hypervisor, IngressHistoryWriterImpl,
-    InternalHttpQueryHandler, RoundInstructions, RoundLimits,
+    InternalHttpQueryHandler, NonReplicatedQueryKind, RoundInstructions, RoundLimits,
 };
 use ic_ic00_types::{
     CanisterIdRecord, CanisterInstallMode, CanisterSettingsArgs, CanisterSettingsArgsBuilder,
@@ -44,7 +45,7 @@ use ic_replicated_state::{
     PageIndex, ReplicatedState, SubnetTopology,
 };
 use ic_replicated_state::{page_map::TestPageAllocatorFileDescriptorImpl, PageMap};
-use ic_system_api::InstructionLimits;
+use ic_system_api::{ExecutionParameters, InstructionLimits};
 use ic_types::{
     crypto::{canister_threshold_sig::MasterEcdsaPublicKey, AlgorithmId},
     ingress::{IngressState, IngressStatus, WasmResult},
@@ -52,6 +53,7 @@ use ic_types::{
         AnonymousQuery, CallbackId, CanisterCall, CanisterMessage, CanisterTask, MessageId,
         RequestOrResponse, Response, UserQuery, MAX_INTER_CANISTER_PAYLOAD_IN_BYTES,
     },
+    methods::WasmMethod,
     CanisterId, Cycles, Height, NumInstructions, NumPages, Time, UserId,
 };
 use ic_types_test_utils::ids::{node_test_id, subnet_test_id, user_test_id};
@@ -504,6 +506,40 @@ impl ExecutionTest {
         self.subnet_message(Method::UpdateSettings, payload)
     }
 
+    /// Updates the reserved cycles limit of the given canister.
+    pub fn canister_update_reserved_cycles_limit(
+        &mut self,
+        canister_id: CanisterId,
+        reserved_cycles_limit: Cycles,
+    ) -> Result<WasmResult, UserError> {
+        let payload = UpdateSettingsArgs {
+            canister_id: canister_id.into(),
+            settings: CanisterSettingsArgsBuilder::new()
+                .with_reserved_cycles_limit(reserved_cycles_limit.get())
+                .build(),
+            sender_canister_version: None,
+        }
+        .encode();
+        self.subnet_message(Method::UpdateSettings, payload)
+    }
+
+    /// Updates the Wasm memory limit of the given canister.
+    pub fn canister_update_wasm_memory_limit(
+        &mut self,
+        canister_id: CanisterId,
+        wasm_memory_limit: NumBytes,
+    ) -> Result<WasmResult, UserError> {
+        let payload = UpdateSettingsArgs {
+            canister_id: canister_id.into(),
+            settings: CanisterSettingsArgsBuilder::new()
+                .with_wasm_memory_limit(wasm_memory_limit.get())
+                .build(),
+            sender_canister_version: None,
+        }
+        .encode();
+        self.subnet_message(Method::UpdateSettings, payload)
+    }
+
     /// Updates the controller of the given canister.
     pub fn canister_update_controller(
         &mut self,
@@ -858,6 +894,12 @@ impl ExecutionTest {
                     .task_queue
                     .push_front(ExecutionTask::GlobalTimer);
             }
+            CanisterTask::OnLowWasmMemory => {
+                canister
+                    .system_state
+                    .task_queue
+                    .push_front(ExecutionTask::OnLowWasmMemory);
+            }
         }
         let result = execute_canister(
             &self.exec_env,
@@ -927,6 +969,54 @@ impl ExecutionTest {
         result
     }
 
+    /// Executes a non-replicated query on the latest state directly in the
+    /// hypervisor, bypassing the query handler. The query executes in slices
+    /// according to the given instruction limits and `between_slices` is
+    /// invoked after every slice but the last one.
+    pub fn non_replicated_query_in_slices<S: ToString>(
+        &self,
+        canister_id: CanisterId,
+        method_name: S,
+        method_payload: Vec<u8>,
+        instruction_limits: InstructionLimits,
+        between_slices: &dyn Fn(),
+    ) -> Result<Option<WasmResult>, UserError> {
+        let state = self.state();
+        let canister = state.canister_state(&canister_id).unwrap().clone();
+        let execution_parameters = ExecutionParameters {
+            instruction_limits,
+            canister_memory_limit: canister.memory_limit(NumBytes::new(u64::MAX / 2)),
+            memory_allocation: canister.memory_allocation(),
+            compute_allocation: canister.compute_allocation(),
+            subnet_type: state.metadata.own_subnet_type,
+            execution_mode: ExecutionMode::NonReplicated,
+            subnet_memory_capacity: NumBytes::new(u64::MAX / 2),
+            subnet_memory_threshold: NumBytes::new(u64::MAX / 2),
+        };
+        let mut round_limits = RoundLimits {
+            instructions: RoundInstructions::from(i64::MAX),
+            execution_complexity: ExecutionComplexity::MAX,
+            subnet_available_memory: self.subnet_available_memory,
+            compute_allocation_used: state.total_compute_allocation(),
+        };
+        let (_, _, result, _) = execute_non_replicated_query(
+            NonReplicatedQueryKind::Pure {
+                caller: self.user_id.get(),
+            },
+            WasmMethod::Query(method_name.to_string()),
+            &method_payload,
+            canister,
+            None,
+            self.time,
+            execution_parameters,
+            &state.metadata.network_topology,
+            self.hypervisor_deprecated(),
+            &mut round_limits,
+            between_slices,
+        );
+        result
+    }
+
     pub fn execute_response(
         &mut self,
         canister_id: CanisterId,
@@ -1595,6 +1685,12 @@ impl ExecutionTestBuilder {
         self
     }
 
+    pub fn with_subnet_memory_threshold(mut self, subnet_memory_threshold: i64) -> Self {
+        self.execution_config.subnet_memory_threshold =
+            NumBytes::from(subnet_memory_threshold as u64);
+        self
+    }
+
     pub fn with_subnet_memory_reservation(mut self, subnet_memory_reservation: i64) -> Self {
         self.execution_config.subnet_memory_reservation =
             NumBytes::from(subnet_memory_reservation as u64);
@@ -1656,6 +1752,19 @@ impl ExecutionTestBuilder {
         self
     }
 
+    pub fn with_query_time_slicing(
+        mut self,
+        max_instructions_per_sliced_query: u64,
+        max_instructions_per_query_slice: u64,
+    ) -> Self {
+        self.execution_config.query_time_slicing = FlagStatus::Enabled;
+        self.execution_config.max_instructions_per_sliced_query =
+            NumInstructions::from(max_instructions_per_sliced_query);
+        self.execution_config.max_instructions_per_query_slice =
+            NumInstructions::from(max_instructions_per_query_slice);
+        self
+    }
+
     pub fn with_composite_queries(mut self) -> Self {
         self.execution_config.composite_queries = FlagStatus::Enabled;
         self
@@ -1671,6 +1780,11 @@ impl ExecutionTestBuilder {
         self
     }
 
+    pub fn with_query_cache_max_age(mut self, max_age: std::time::Duration) -> Self {
+        self.execution_config.query_cache_max_age = max_age;
+        self
+    }
+
     pub fn with_allocatable_compute_capacity_in_percent(
         mut self,
         allocatable_compute_capacity_in_percent: usize,
@@ -1862,7 +1976,11 @@ impl ExecutionTestBuilder {
             config.clone(),
             Arc::clone(&cycles_account_manager),
         );
-        let (query_stats_collector, _) = init_query_stats(self.log.clone());
+        let (query_stats_collector, _) = init_query_stats(
+            node_test_id(0),
+            config.query_stats_epoch_length,
+            self.log.clone(),
+        );
 
         let query_handler = InternalHttpQueryHandler::new(
             self.log.clone(),
@@ -1872,7 +1990,7 @@ impl ExecutionTestBuilder {
             &metrics_registry,
             self.instruction_limit_without_dts,
             Arc::clone(&cycles_account_manager),
-            query_stats_collector,
+            Arc::new(query_stats_collector),
         );
         ExecutionTest {
             state: Some(state),
@@ -2,6 +2,7 @@ use ic_base_types::NumBytes;
 use ic_interfaces::{
     batch_payload::{
         iterator_to_bytes, BatchPayloadBuilder as BatchPayloadBuilderTrait, PastPayload,
+        ProposalContext,
     },
     consensus::PayloadValidationError,
     validation::ValidationResult,
@@ -26,7 +27,7 @@ mock! {
             height: Height,
             payload: &[u8],
             past_payloads: &[PastPayload<'a>],
-            context: &ValidationContext,
+            proposal_context: &ProposalContext<'a>,
         ) -> ValidationResult<PayloadValidationError>;
     }
 }
@@ -216,6 +216,7 @@ pub fn app_subnet_recovery_test(env: TestEnv, upgrade: bool, ecdsa: bool) {
         replica_version: Some(master_version.clone()),
         key_file: Some(ssh_authorized_priv_keys_dir.join(SSH_USERNAME)),
         test_mode: true,
+        dry_run: None,
     };
 
     let mut unassigned_nodes = env.topology_snapshot().unassigned_nodes();
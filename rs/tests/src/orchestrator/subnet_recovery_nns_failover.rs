@@ -158,6 +158,7 @@ pub fn test(env: TestEnv) {
         replica_version: Some(ic_version.clone()),
         key_file: Some(ssh_authorized_priv_keys_dir.join(SSH_USERNAME)),
         test_mode: true,
+        dry_run: None,
     };
     let subnet_args = NNSRecoveryFailoverNodesArgs {
         subnet_id: topo_broken_ic.root_subnet_id(),
@@ -110,6 +110,7 @@ pub fn test(env: TestEnv) {
         replica_version: Some(ic_version),
         key_file: Some(ssh_authorized_priv_keys_dir.join(SSH_USERNAME)),
         test_mode: true,
+        dry_run: None,
     };
 
     // unlike during a production recovery using the CLI, here we already know all of parameters
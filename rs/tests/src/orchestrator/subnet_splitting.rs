@@ -136,6 +136,7 @@ pub fn subnet_splitting_test(env: TestEnv) {
                 .join(SSH_USERNAME),
         ),
         test_mode: true,
+        dry_run: None,
     };
 
     let subnet_splitting_args = SubnetSplittingArgs {
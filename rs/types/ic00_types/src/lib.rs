@@ -629,6 +629,9 @@ impl Payload<'_> for UninstallCodeArgs {}
 ///     controller : principal;
 ///     compute_allocation: nat;
 ///     memory_allocation: opt nat;
+///     wasm_memory_limit: opt nat;
+///     wasm_memory_threshold: nat;
+///     reserved_cycles_limit: opt nat;
 /// })`
 #[derive(CandidType, Deserialize, Debug, Eq, PartialEq)]
 pub struct DefiniteCanisterSettingsArgs {
@@ -637,15 +640,22 @@ pub struct DefiniteCanisterSettingsArgs {
     compute_allocation: candid::Nat,
     memory_allocation: candid::Nat,
     freezing_threshold: candid::Nat,
+    wasm_memory_limit: Option<candid::Nat>,
+    wasm_memory_threshold: candid::Nat,
+    reserved_cycles_limit: Option<candid::Nat>,
 }
 
 impl DefiniteCanisterSettingsArgs {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         controller: PrincipalId,
         controllers: Vec<PrincipalId>,
         compute_allocation: u64,
         memory_allocation: Option<u64>,
         freezing_threshold: u64,
+        wasm_memory_limit: Option<u64>,
+        wasm_memory_threshold: u64,
+        reserved_cycles_limit: Option<u128>,
     ) -> Self {
         let memory_allocation = match memory_allocation {
             None => candid::Nat::from(0),
@@ -657,12 +667,31 @@ impl DefiniteCanisterSettingsArgs {
             compute_allocation: candid::Nat::from(compute_allocation),
             memory_allocation,
             freezing_threshold: candid::Nat::from(freezing_threshold),
+            wasm_memory_limit: wasm_memory_limit.map(candid::Nat::from),
+            wasm_memory_threshold: candid::Nat::from(wasm_memory_threshold),
+            reserved_cycles_limit: reserved_cycles_limit.map(candid::Nat::from),
         }
     }
 
     pub fn controllers(&self) -> Vec<PrincipalId> {
         self.controllers.clone()
     }
+
+    pub fn wasm_memory_limit(&self) -> Option<u64> {
+        self.wasm_memory_limit
+            .as_ref()
+            .map(|limit| limit.0.to_u64().unwrap())
+    }
+
+    pub fn wasm_memory_threshold(&self) -> u64 {
+        self.wasm_memory_threshold.0.to_u64().unwrap()
+    }
+
+    pub fn reserved_cycles_limit(&self) -> Option<u128> {
+        self.reserved_cycles_limit
+            .as_ref()
+            .map(|limit| limit.0.to_u128().unwrap())
+    }
 }
 
 impl Payload<'_> for DefiniteCanisterSettingsArgs {}
@@ -731,7 +760,14 @@ impl Payload<'_> for CanisterStatusResult {}
 ///     controller: principal;
 ///     memory_size: nat;
 ///     cycles: nat;
+///     reserved_cycles: nat;
 ///     idle_cycles_burned_per_day: nat;
+///     query_stats: record {
+///         num_calls_total: nat;
+///         num_instructions_total: nat;
+///         request_payload_bytes_total: nat;
+///         response_payload_bytes_total: nat;
+///     };
 /// })`
 #[derive(CandidType, Debug, Deserialize, Eq, PartialEq)]
 pub struct CanisterStatusResultV2 {
@@ -744,7 +780,19 @@ pub struct CanisterStatusResultV2 {
     // this is for compat with Spec 0.12/0.13
     balance: Vec<(Vec<u8>, candid::Nat)>,
     freezing_threshold: candid::Nat,
+    reserved_cycles: candid::Nat,
     idle_cycles_burned_per_day: candid::Nat,
+    query_stats: QueryStats,
+}
+
+/// Query statistics of a canister, aggregated across the nodes of its subnet
+/// since the canister was created.
+#[derive(CandidType, Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct QueryStats {
+    pub num_calls_total: candid::Nat,
+    pub num_instructions_total: candid::Nat,
+    pub request_payload_bytes_total: candid::Nat,
+    pub response_payload_bytes_total: candid::Nat,
 }
 
 impl CanisterStatusResultV2 {
@@ -759,7 +807,15 @@ impl CanisterStatusResultV2 {
         compute_allocation: u64,
         memory_allocation: Option<u64>,
         freezing_threshold: u64,
+        wasm_memory_limit: Option<u64>,
+        wasm_memory_threshold: u64,
+        reserved_cycles: u128,
+        reserved_cycles_limit: Option<u128>,
         idle_cycles_burned_per_day: u128,
+        query_num_calls_total: u128,
+        query_num_instructions_total: u128,
+        query_request_payload_bytes_total: u128,
+        query_response_payload_bytes_total: u128,
     ) -> Self {
         Self {
             status,
@@ -776,9 +832,19 @@ impl CanisterStatusResultV2 {
                 compute_allocation,
                 memory_allocation,
                 freezing_threshold,
+                wasm_memory_limit,
+                wasm_memory_threshold,
+                reserved_cycles_limit,
             ),
             freezing_threshold: candid::Nat::from(freezing_threshold),
+            reserved_cycles: candid::Nat::from(reserved_cycles),
             idle_cycles_burned_per_day: candid::Nat::from(idle_cycles_burned_per_day),
+            query_stats: QueryStats {
+                num_calls_total: candid::Nat::from(query_num_calls_total),
+                num_instructions_total: candid::Nat::from(query_num_instructions_total),
+                request_payload_bytes_total: candid::Nat::from(query_request_payload_bytes_total),
+                response_payload_bytes_total: candid::Nat::from(query_response_payload_bytes_total),
+            },
         }
     }
 
@@ -798,6 +864,10 @@ impl CanisterStatusResultV2 {
         self.settings.controllers()
     }
 
+    pub fn settings(&self) -> &DefiniteCanisterSettingsArgs {
+        &self.settings
+    }
+
     pub fn memory_size(&self) -> NumBytes {
         NumBytes::from(self.memory_size.0.to_u64().unwrap())
     }
@@ -810,9 +880,17 @@ impl CanisterStatusResultV2 {
         self.freezing_threshold.0.to_u64().unwrap()
     }
 
+    pub fn reserved_cycles(&self) -> u128 {
+        self.reserved_cycles.0.to_u128().unwrap()
+    }
+
     pub fn idle_cycles_burned_per_day(&self) -> u128 {
         self.idle_cycles_burned_per_day.0.to_u128().unwrap()
     }
+
+    pub fn query_stats(&self) -> &QueryStats {
+        &self.query_stats
+    }
 }
 
 /// Indicates whether the canister is running, stopping, or stopped.
@@ -1118,6 +1196,10 @@ impl Payload<'_> for UpdateSettingsArgs {}
 ///     controllers: opt vec principal;
 ///     compute_allocation: opt nat;
 ///     memory_allocation: opt nat;
+///     freezing_threshold: opt nat;
+///     wasm_memory_limit: opt nat;
+///     wasm_memory_threshold: opt nat;
+///     reserved_cycles_limit: opt nat;
 /// })`
 #[derive(Default, Clone, CandidType, Deserialize, Debug)]
 pub struct CanisterSettingsArgs {
@@ -1127,6 +1209,10 @@ pub struct CanisterSettingsArgs {
     pub compute_allocation: Option<candid::Nat>,
     pub memory_allocation: Option<candid::Nat>,
     pub freezing_threshold: Option<candid::Nat>,
+    /// A limit of zero removes the Wasm memory limit.
+    pub wasm_memory_limit: Option<candid::Nat>,
+    pub wasm_memory_threshold: Option<candid::Nat>,
+    pub reserved_cycles_limit: Option<candid::Nat>,
 }
 
 impl Payload<'_> for CanisterSettingsArgs {}
@@ -1144,6 +1230,9 @@ impl CanisterSettingsArgs {
             compute_allocation: compute_allocation.map(candid::Nat::from),
             memory_allocation: memory_allocation.map(candid::Nat::from),
             freezing_threshold: freezing_threshold.map(candid::Nat::from),
+            wasm_memory_limit: None,
+            wasm_memory_threshold: None,
+            reserved_cycles_limit: None,
         }
     }
 
@@ -1159,6 +1248,9 @@ pub struct CanisterSettingsArgsBuilder {
     compute_allocation: Option<candid::Nat>,
     memory_allocation: Option<candid::Nat>,
     freezing_threshold: Option<candid::Nat>,
+    wasm_memory_limit: Option<candid::Nat>,
+    wasm_memory_threshold: Option<candid::Nat>,
+    reserved_cycles_limit: Option<candid::Nat>,
 }
 
 #[allow(dead_code)]
@@ -1174,6 +1266,9 @@ impl CanisterSettingsArgsBuilder {
             compute_allocation: self.compute_allocation,
             memory_allocation: self.memory_allocation,
             freezing_threshold: self.freezing_threshold,
+            wasm_memory_limit: self.wasm_memory_limit,
+            wasm_memory_threshold: self.wasm_memory_threshold,
+            reserved_cycles_limit: self.reserved_cycles_limit,
         }
     }
 
@@ -1217,6 +1312,33 @@ impl CanisterSettingsArgsBuilder {
             ..self
         }
     }
+
+    /// Sets the upper limit on the canister's Wasm heap in bytes. Growing
+    /// the heap beyond it traps.
+    pub fn with_wasm_memory_limit(self, wasm_memory_limit: u64) -> Self {
+        Self {
+            wasm_memory_limit: Some(candid::Nat::from(wasm_memory_limit)),
+            ..self
+        }
+    }
+
+    /// Sets the threshold in bytes of free Wasm heap, relative to the Wasm
+    /// memory limit, below which `canister_on_low_wasm_memory` is scheduled.
+    pub fn with_wasm_memory_threshold(self, wasm_memory_threshold: u64) -> Self {
+        Self {
+            wasm_memory_threshold: Some(candid::Nat::from(wasm_memory_threshold)),
+            ..self
+        }
+    }
+
+    /// Sets the upper limit in cycles on the canister's reserved balance.
+    /// Memory growth that would reserve cycles above it fails.
+    pub fn with_reserved_cycles_limit(self, reserved_cycles_limit: u128) -> Self {
+        Self {
+            reserved_cycles_limit: Some(candid::Nat::from(reserved_cycles_limit)),
+            ..self
+        }
+    }
 }
 
 /// Struct used for encoding/decoding
@@ -426,7 +426,7 @@ pub struct StateSyncArtifactId {
 //
 // P2P will call get_chunk() on it to get a byte array to send to a peer, and
 // this byte array will be read from the FS.
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StateSyncMessage {
     pub height: Height,
     pub root_hash: CryptoHashOfState,
@@ -440,6 +440,21 @@ pub struct StateSyncMessage {
     #[serde(serialize_with = "ic_utils::serde_arc::serialize_arc")]
     #[serde(deserialize_with = "ic_utils::serde_arc::deserialize_arc")]
     pub state_sync_file_group: Arc<crate::state_sync::FileGroupChunks>,
+    /// Reads the file chunks served by `get_chunk`. If not set, the chunks
+    /// are read from the uncompressed checkpoint files.
+    #[serde(skip)]
+    pub chunk_reader: Option<Arc<dyn StateSyncChunkReader>>,
+}
+
+/// Reads the payload of the file chunks and file group chunks of a
+/// [`StateSyncMessage`].
+///
+/// Implemented by the state manager, which knows how checkpoint files are
+/// stored on disk and how chunks are encoded for transmission.
+pub trait StateSyncChunkReader: std::fmt::Debug + Send + Sync {
+    /// Returns the payload of the chunk that consists of the chunks at
+    /// `chunk_table_indices` in the chunk table of `msg.manifest`.
+    fn read_chunk(&self, msg: &StateSyncMessage, chunk_table_indices: &[u32]) -> Option<Vec<u8>>;
 }
 
 impl ChunkableArtifact for StateSyncMessage {
@@ -469,8 +484,18 @@ impl ChunkableArtifact for StateSyncMessage {
                 f.read_exact_at(&mut buf[..], chunk.offset).ok()?;
                 Some(buf)
             };
+            let get_chunk = |chunk_table_indices: &[u32]| -> Option<Vec<u8>> {
+                if let Some(chunk_reader) = &self.chunk_reader {
+                    return chunk_reader.read_chunk(self, chunk_table_indices);
+                }
+                let mut payload = Vec::new();
+                for chunk_table_index in chunk_table_indices {
+                    payload.extend(get_single_chunk(*chunk_table_index as usize)?);
+                }
+                Some(payload)
+            };
 
-            let mut payload: Vec<u8> = Vec::new();
+            let payload: Vec<u8>;
             match state_sync_chunk_type(chunk_id.get()) {
                 StateSyncChunk::MetaManifestChunk => {
                     payload = encode_meta_manifest(&self.meta_manifest);
@@ -496,15 +521,13 @@ impl ChunkableArtifact for StateSyncMessage {
                 }
                 StateSyncChunk::FileGroupChunk(index) => {
                     if let Some(chunk_table_indices) = self.state_sync_file_group.get(&index) {
-                        for chunk_table_index in chunk_table_indices {
-                            payload.extend(get_single_chunk(*chunk_table_index as usize)?);
-                        }
+                        payload = get_chunk(chunk_table_indices)?;
                     } else {
                         return None;
                     }
                 }
                 StateSyncChunk::FileChunk(index) => {
-                    payload = get_single_chunk(index as usize)?;
+                    payload = get_chunk(&[index])?;
                 }
             }
 
@@ -517,18 +540,26 @@ impl ChunkableArtifact for StateSyncMessage {
     }
 }
 
+// We need a custom PartialEq instance to skip chunk_reader, which only
+// determines how chunks are read, not what they contain.
+impl PartialEq for StateSyncMessage {
+    fn eq(&self, other: &Self) -> bool {
+        self.height == other.height
+            && self.root_hash == other.root_hash
+            && self.checkpoint_root == other.checkpoint_root
+            && self.meta_manifest == other.meta_manifest
+            && self.manifest == other.manifest
+            && self.state_sync_file_group == other.state_sync_file_group
+    }
+}
+
+impl Eq for StateSyncMessage {}
+
 // We need a custom Hash instance to skip checkpoint_root in order
 // for integrity_hash to produce the same result on different nodes.
 //
-// Clippy gives a warning about having a derived PartialEq but a
-// hand-rolled Hash instance. In our case this is acceptable because:
-//
-// 1. We only use use Hash for integrity check.
-//
-// 2. Even if we use it for other purposes (e.g. in a HashSet), this
-//    is still safe because identical (height, root_hash) should
-//    lead to identical checkpoint_root.
-#[allow(clippy::derived_hash_with_manual_eq)]
+// This is consistent with PartialEq because identical (height, root_hash)
+// should lead to identical checkpoint_root.
 impl std::hash::Hash for StateSyncMessage {
     fn hash<Hasher: std::hash::Hasher>(&self, state: &mut Hasher) {
         self.height.hash(state);
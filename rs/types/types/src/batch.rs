@@ -8,8 +8,7 @@ mod self_validating;
 mod xnet;
 
 pub use self::canister_http::{CanisterHttpPayload, MAX_CANISTER_HTTP_PAYLOAD_SIZE};
-pub use self::execution_environment::EpochStats;
-pub use self::execution_environment::QueryStatsPayload;
+pub use self::execution_environment::{CanisterQueryStats, EpochStats, QueryStatsPayload};
 pub use self::ingress::{IngressPayload, IngressPayloadError};
 pub use self::self_validating::{SelfValidatingPayload, MAX_BITCOIN_PAYLOAD_IN_BYTES};
 pub use self::xnet::XNetPayload;
@@ -93,6 +92,7 @@ pub struct BatchMessages {
     pub signed_ingress_msgs: Vec<SignedIngress>,
     pub certified_stream_slices: BTreeMap<SubnetId, CertifiedStreamSlice>,
     pub bitcoin_adapter_responses: Vec<BitcoinAdapterResponse>,
+    pub query_stats: Option<QueryStatsPayload>,
 }
 
 impl BatchPayload {
@@ -105,6 +105,10 @@ impl BatchPayload {
             signed_ingress_msgs: self.ingress.try_into()?,
             certified_stream_slices: self.xnet.stream_slices,
             bitcoin_adapter_responses: self.self_validating.0,
+            // The payload has been validated, so it can be decoded.
+            query_stats: QueryStatsPayload::deserialize(&self.query_stats)
+                .ok()
+                .flatten(),
         })
     }
 
@@ -113,12 +117,13 @@ impl BatchPayload {
             && self.xnet.stream_slices.is_empty()
             && self.self_validating.is_empty()
             && self.canister_http.is_empty()
+            && self.query_stats.is_empty()
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::CountBytes;
+    use crate::{CanisterId, CountBytes, NodeId, PrincipalId, QueryStatsEpoch};
 
     /// This is a quick test to check the invariant, that the [`Default`] implementation
     /// of a payload section actually produces the empty payload,
@@ -129,6 +134,29 @@ mod tests {
         assert_eq!(CanisterHttpPayload::default().count_bytes(), 0);
     }
 
+    #[test]
+    fn query_stats_payload_round_trip() {
+        let payload = QueryStatsPayload {
+            epoch: QueryStatsEpoch::from(3),
+            proposer: NodeId::from(PrincipalId::new_node_test_id(1)),
+            canister_stats: BTreeMap::from([(
+                CanisterId::from_u64(7),
+                CanisterQueryStats {
+                    num_calls: 2,
+                    num_instructions: 1000,
+                    ingress_payload_size: 10,
+                    egress_payload_size: 20,
+                },
+            )]),
+        };
+
+        assert_eq!(
+            QueryStatsPayload::deserialize(&payload.serialize()).unwrap(),
+            Some(payload)
+        );
+        assert_eq!(QueryStatsPayload::deserialize(&[]).unwrap(), None);
+    }
+
     #[test]
     fn test_validation_context_ordering() {
         let context1 = ValidationContext {
@@ -1,7 +1,8 @@
-use crate::QueryStatsEpoch;
+use crate::{node_id_into_protobuf, node_id_try_from_option, NodeId, QueryStatsEpoch};
 use ic_base_types::CanisterId;
 use ic_protobuf::proxy::{try_from_option_field, ProxyDecodeError};
 use ic_protobuf::types::v1::{self as pb};
+use prost::Message;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::hash::Hash;
@@ -19,15 +20,42 @@ pub struct EpochStats {
     pub stats: Vec<(CanisterId, CanisterQueryStats)>,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Query stats collected by `proposer` during `epoch`, as included in a block.
+///
+/// The stats of one epoch may be spread over several payloads of the same
+/// proposer, but each canister appears in at most one of them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct QueryStatsPayload {
+    pub epoch: QueryStatsEpoch,
+    pub proposer: NodeId,
     pub canister_stats: BTreeMap<CanisterId, CanisterQueryStats>,
 }
 
+impl QueryStatsPayload {
+    /// Encodes the payload for inclusion in a block.
+    pub fn serialize(&self) -> Vec<u8> {
+        pb::QueryStatsPayload::from(self).encode_to_vec()
+    }
+
+    /// Decodes a payload included in a block. An empty slice decodes to `None`.
+    pub fn deserialize(data: &[u8]) -> Result<Option<Self>, ProxyDecodeError> {
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let payload = pb::QueryStatsPayload::decode(data)
+            .map_err(|err| ProxyDecodeError::Other(err.to_string()))?;
+        Self::try_from(payload).map(Some)
+    }
+}
+
 impl From<&QueryStatsPayload> for pb::QueryStatsPayload {
     // Encode protobuf representation of query stats
     fn from(payload: &QueryStatsPayload) -> Self {
-        let mut container = pb::QueryStatsPayload::default();
+        let mut container = pb::QueryStatsPayload {
+            epoch: payload.epoch.get(),
+            proposer: Some(node_id_into_protobuf(payload.proposer)),
+            ..Default::default()
+        };
         for (key, value) in &payload.canister_stats {
             let inner = pb::QueryStatsPayloadInner {
                 canister_id: Some(pb::CanisterId::from(*key)),
@@ -59,6 +87,10 @@ impl TryFrom<pb::QueryStatsPayload> for QueryStatsPayload {
                 },
             );
         }
-        Ok(Self { canister_stats })
+        Ok(Self {
+            epoch: QueryStatsEpoch::from(payload.epoch),
+            proposer: node_id_try_from_option(payload.proposer)?,
+            canister_stats,
+        })
     }
 }